@@ -39,7 +39,7 @@ use rg3d::{
     scene::{
         base::BaseBuilder, camera::CameraBuilder, node::Node, transform::TransformBuilder, Scene,
     },
-    utils::translate_event,
+    utils::translate_event_scaled,
     window::Fullscreen,
 };
 use rg3d_ui::message::MessageDirection;
@@ -516,8 +516,8 @@ fn main() {
 
                 // It is very important to "feed" user interface (UI) with events coming
                 // from main window, otherwise UI won't respond to mouse, keyboard, or any
-                // other event.
-                if let Some(os_event) = translate_event(&event) {
+                // other event. Cursor position must be converted to UI units.
+                if let Some(os_event) = translate_event_scaled(&event, engine.ui_scale()) {
                     engine.user_interface.process_os_event(&os_event);
                 }
             }
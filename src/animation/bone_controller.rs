@@ -0,0 +1,183 @@
+//! Common part of procedural bone controllers - foot IK, hit reactions and ragdoll chains.
+//!
+//! Bone controllers adjust bones on top of current animation using post transforms of bones
+//! (see `Base::set_post_transform`). Every frame post transforms of controlled bones are
+//! reset before global transforms are calculated, so every frame starts from animated pose.
+//! Then controllers are updated in fixed order: foot IK, hit reactions, ragdoll chains, and
+//! every controller composes its adjustments with adjustments made by previous ones, so
+//! different controllers can drive same bones.
+
+use crate::{
+    core::{
+        math::{mat4::Mat4, quat::Quat, vec3::Vec3},
+        pool::{Handle, Pool, PoolIterator, PoolIteratorMut},
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::{graph::Graph, node::Node},
+};
+use std::collections::HashMap;
+
+/// Procedural controller of bones, see module docs.
+pub trait BoneController: Visit + Default + Clone {
+    /// Returns every bone whose post transform is set by controller.
+    fn controlled_bones(&self) -> Vec<Handle<Node>>;
+
+    /// Returns copy of controller for a copied graph, None if some bones weren't copied.
+    fn remap(&self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) -> Option<Self>;
+}
+
+/// Container of bone controllers of a scene.
+#[derive(Clone, Debug)]
+pub struct BoneControllerContainer<T> {
+    pool: Pool<T>,
+}
+
+impl<T> Default for BoneControllerContainer<T> {
+    fn default() -> Self {
+        Self { pool: Pool::new() }
+    }
+}
+
+impl<T: BoneController + 'static> Visit for BoneControllerContainer<T> {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl<T: BoneController> BoneControllerContainer<T> {
+    /// Returns shared reference to a controller.
+    pub fn get(&self, handle: Handle<T>) -> &T {
+        self.pool.borrow(handle)
+    }
+
+    /// Returns mutable reference to a controller.
+    pub fn get_mut(&mut self, handle: Handle<T>) -> &mut T {
+        self.pool.borrow_mut(handle)
+    }
+
+    /// Returns iterator over controllers.
+    pub fn iter(&self) -> PoolIterator<T> {
+        self.pool.iter()
+    }
+
+    /// Returns iterator over controllers.
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<T> {
+        self.pool.iter_mut()
+    }
+
+    pub(in crate) fn spawn(&mut self, controller: T) -> Handle<T> {
+        self.pool.spawn(controller)
+    }
+
+    /// Removes controller, its bones return to animated pose immediately.
+    pub(in crate) fn free(&mut self, handle: Handle<T>, graph: &mut Graph) -> T {
+        let controller = self.pool.free(handle);
+        for bone in controller.controlled_bones() {
+            if graph.is_valid_handle(bone) {
+                graph[bone].set_post_transform(None);
+            }
+        }
+        controller
+    }
+
+    /// Resets post transforms of every controlled bone. Must be called before global
+    /// transforms of a frame are calculated.
+    pub(in crate) fn reset_post_transforms(&self, graph: &mut Graph) {
+        for controller in self.pool.iter() {
+            for bone in controller.controlled_bones() {
+                if graph.is_valid_handle(bone) {
+                    graph[bone].set_post_transform(None);
+                }
+            }
+        }
+    }
+
+    /// Remaps bones of controllers of a copied graph, controllers with bones that weren't
+    /// copied are dropped.
+    pub(in crate) fn remap(&self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) -> Self {
+        let mut pool = Pool::new();
+        for controller in self.pool.iter() {
+            if let Some(controller) = controller.remap(old_new_map) {
+                pool.spawn(controller);
+            }
+        }
+        Self { pool }
+    }
+}
+
+/// Composes post transform of a bone with given world space transform, so global transform
+/// of the bone becomes `world * global`, and updates global transforms of its descendants.
+pub(in crate) fn transform_bone(graph: &mut Graph, bone: Handle<Node>, world: Mat4) {
+    let global = graph[bone].global_transform();
+    let post = global.inverse().unwrap_or_default() * world * global;
+    let post = match graph[bone].post_transform() {
+        Some(existing) => existing * post,
+        None => post,
+    };
+    graph[bone].set_post_transform(Some(post));
+    graph.update_hierarchical_data_for_descendants(bone);
+}
+
+/// Rotates bone around its origin by given world space rotation, see `transform_bone`.
+pub(in crate) fn rotate_bone(graph: &mut Graph, bone: Handle<Node>, rotation: Quat) {
+    let pivot = graph[bone].global_position();
+    transform_bone(
+        graph,
+        bone,
+        Mat4::translate(pivot) * Mat4::from_quat(rotation) * Mat4::translate(-pivot),
+    );
+}
+
+/// Returns shortest rotation which turns direction `from` into direction `to`. Opposite
+/// directions give half-turn around an axis perpendicular to `from`. Returns None if any
+/// of vectors is degenerate or if they have same direction (no rotation is needed).
+pub(in crate) fn rotation_between(from: Vec3, to: Vec3) -> Option<Quat> {
+    let from = from.normalized()?;
+    let to = to.normalized()?;
+    let cos = from.dot(&to).max(-1.0).min(1.0);
+    match from.cross(&to).normalized() {
+        Some(axis) => Some(Quat::from_axis_angle(axis, cos.acos())),
+        None if cos < 0.0 => {
+            // Any axis perpendicular to `from` will do, take the one which is farthest
+            // from being collinear with it.
+            let helper = if from.x.abs() < 0.9 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            let axis = from.cross(&helper).normalized()?;
+            Some(Quat::from_axis_angle(axis, std::f32::consts::PI))
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::bone_controller::rotation_between,
+        core::math::{mat4::Mat4, vec3::Vec3},
+    };
+
+    #[test]
+    fn test_rotation_between() {
+        let from = Vec3::new(1.0, 0.0, 0.0);
+        let to = Vec3::new(0.0, 2.0, 0.0);
+        let rotation = rotation_between(from, to).unwrap();
+        let rotated = Mat4::from_quat(rotation).transform_vector(from);
+        assert!(rotated.distance(&Vec3::new(0.0, 1.0, 0.0)) < 0.001);
+        assert!(rotation_between(from, from.scale(3.0)).is_none());
+        assert!(rotation_between(from, Vec3::ZERO).is_none());
+
+        // Opposite directions.
+        for &from in [from, Vec3::new(0.0, 0.0, 2.0)].iter() {
+            let rotation = rotation_between(from, from.scale(-1.0)).unwrap();
+            let rotated = Mat4::from_quat(rotation).transform_vector(from);
+            assert!(rotated.distance(&from.scale(-1.0)) < 0.001);
+        }
+    }
+}
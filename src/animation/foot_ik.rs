@@ -0,0 +1,589 @@
+//! Foot placement on uneven ground.
+//!
+//! Animations are usually authored on flat ground, so on stairs and slopes one foot of a
+//! character floats in the air while the other one sinks into the ground. Foot IK fixes
+//! this on top of current animation:
+//!
+//! - Every foot casts a ray down from its animated position to find ground under it.
+//! - Pelvis is lowered by the height of the lowest ground under feet, so the foot on lower
+//! ground can reach it.
+//! - Every leg is bent by two-bone IK so the foot stands on the ground found by its ray.
+//! - Every foot is pitched to match slope of the ground.
+//!
+//! Ground heights, slopes and pelvis offset are smoothed over time, so feet do not snap
+//! when a character steps onto a stair. Ground level of animation is the position of the
+//! root node of a character, which must stand on the ground, so usually it is root of the
+//! character model whose position is synced with a character controller.
+//!
+//! Ground is static geometry of physics world, rays are cast by `Physics::ray_cast` which
+//! uses acceleration structures of static geometries, so cost of foot IK does not depend on
+//! amount of meshes in a scene. Rigid bodies (including capsule of the character itself and
+//! bodies of ragdoll chains) are not considered as ground.
+//!
+//! Foot IK is a bone controller, it is updated first and hit reactions and ragdoll chains
+//! are composed with its adjustments, see
+//! [bone_controller](../bone_controller/index.html) module docs.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::foot_ik::{FootIk, FootIkLeg},
+//!     core::pool::Handle,
+//!     scene::{node::Node, Scene},
+//! };
+//!
+//! fn setup_foot_ik(scene: &mut Scene, model: Handle<Node>) -> Handle<FootIk> {
+//!     let graph = &scene.graph;
+//!     let leg = |thigh, calf, foot| {
+//!         FootIkLeg::new(
+//!             graph.find_by_name(model, thigh),
+//!             graph.find_by_name(model, calf),
+//!             graph.find_by_name(model, foot),
+//!         )
+//!     };
+//!     let foot_ik = FootIk::new(model, graph.find_by_name(model, "Hips"))
+//!         .with_leg(leg("LeftUpLeg", "LeftLeg", "LeftFoot"))
+//!         .with_leg(leg("RightUpLeg", "RightLeg", "RightFoot"));
+//!     scene.foot_ik.add(foot_ik)
+//! }
+//! ```
+
+use crate::{
+    animation::bone_controller::{
+        rotate_bone, rotation_between, transform_bone, BoneController, BoneControllerContainer,
+    },
+    core::{
+        math::{mat4::Mat4, quat::Quat, ray::Ray, vec3::Vec3},
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    physics::{Physics, RayCastOptions, RayCastResult},
+    scene::{graph::Graph, node::Node},
+};
+use std::collections::HashMap;
+
+/// Finds new positions of middle and end joints of two-bone chain (for example knee and
+/// ankle) so that end joint reaches target while lengths of bones are preserved. Chain
+/// keeps bending to the same side as before, `pole` defines the side when chain is fully
+/// straight. Unreachable target is approached as close as possible.
+pub fn solve_two_bone(
+    root: Vec3,
+    joint: Vec3,
+    end: Vec3,
+    target: Vec3,
+    pole: Vec3,
+) -> (Vec3, Vec3) {
+    let upper = root.distance(&joint);
+    let lower = joint.distance(&end);
+    let to_target = target - root;
+    let dir = match to_target.normalized() {
+        Some(dir) => dir,
+        None => return (joint, end),
+    };
+    let distance = to_target
+        .len()
+        .max((upper - lower).abs())
+        .min(upper + lower);
+    if distance <= std::f32::EPSILON {
+        return (joint, end);
+    }
+
+    let perpendicular = |v: Vec3| (v - dir.scale(v.dot(&dir))).normalized();
+    let bend = match perpendicular(joint - root).or_else(|| perpendicular(pole)) {
+        Some(bend) => bend,
+        None => return (joint, end),
+    };
+
+    // Law of cosines gives projection of middle joint on root-target line.
+    let along = (upper * upper - lower * lower + distance * distance) / (2.0 * distance);
+    let height = (upper * upper - along * along).max(0.0).sqrt();
+
+    (
+        root + dir.scale(along) + bend.scale(height),
+        root + dir.scale(distance),
+    )
+}
+
+/// Parameters of foot placement.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FootIkSettings {
+    /// Maximum height of ground above ground level of animation that feet can step on.
+    pub max_step_up: f32,
+    /// Maximum depth of ground below ground level of animation that feet can reach.
+    pub max_step_down: f32,
+    /// Maximum distance pelvis can be lowered by.
+    pub max_pelvis_offset: f32,
+    /// Maximum angle of foot pitch to match slope, in radians.
+    pub max_foot_angle: f32,
+    /// How fast feet and pelvis follow changes of ground, higher values give faster
+    /// adaptation, zero disables smoothing.
+    pub smoothing_speed: f32,
+}
+
+impl Default for FootIkSettings {
+    fn default() -> Self {
+        Self {
+            max_step_up: 0.5,
+            max_step_down: 0.5,
+            max_pelvis_offset: 0.4,
+            max_foot_angle: 30.0f32.to_radians(),
+            smoothing_speed: 12.0,
+        }
+    }
+}
+
+impl Visit for FootIkSettings {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.max_step_up.visit("MaxStepUp", visitor)?;
+        self.max_step_down.visit("MaxStepDown", visitor)?;
+        self.max_pelvis_offset.visit("MaxPelvisOffset", visitor)?;
+        self.max_foot_angle.visit("MaxFootAngle", visitor)?;
+        self.smoothing_speed.visit("SmoothingSpeed", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Single leg of foot IK.
+#[derive(Copy, Clone, Debug)]
+pub struct FootIkLeg {
+    thigh: Handle<Node>,
+    calf: Handle<Node>,
+    foot: Handle<Node>,
+    // Smoothed height of ground under the foot relative to ground level of animation.
+    ground_offset: f32,
+    // Smoothed normal of ground under the foot.
+    ground_normal: Vec3,
+}
+
+impl Default for FootIkLeg {
+    fn default() -> Self {
+        Self::new(Handle::NONE, Handle::NONE, Handle::NONE)
+    }
+}
+
+impl Visit for FootIkLeg {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.thigh.visit("Thigh", visitor)?;
+        self.calf.visit("Calf", visitor)?;
+        self.foot.visit("Foot", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl FootIkLeg {
+    /// Creates new leg from its bones, foot bone is the one placed on the ground, its
+    /// origin is ankle joint.
+    pub fn new(thigh: Handle<Node>, calf: Handle<Node>, foot: Handle<Node>) -> Self {
+        Self {
+            thigh,
+            calf,
+            foot,
+            ground_offset: 0.0,
+            ground_normal: Vec3::UP,
+        }
+    }
+
+    /// Returns thigh bone.
+    pub fn thigh(&self) -> Handle<Node> {
+        self.thigh
+    }
+
+    /// Returns calf bone.
+    pub fn calf(&self) -> Handle<Node> {
+        self.calf
+    }
+
+    /// Returns foot bone.
+    pub fn foot(&self) -> Handle<Node> {
+        self.foot
+    }
+
+    /// Returns smoothed height of ground under the foot relative to ground level of
+    /// animation.
+    pub fn ground_offset(&self) -> f32 {
+        self.ground_offset
+    }
+
+    fn bones(&self) -> [Handle<Node>; 3] {
+        [self.thigh, self.calf, self.foot]
+    }
+}
+
+/// Foot placement of a character, see module docs.
+#[derive(Clone, Debug)]
+pub struct FootIk {
+    root: Handle<Node>,
+    pelvis: Handle<Node>,
+    legs: Vec<FootIkLeg>,
+    settings: FootIkSettings,
+    weight: f32,
+    // Smoothed offset of pelvis, always zero or negative.
+    pelvis_offset: f32,
+}
+
+impl Default for FootIk {
+    fn default() -> Self {
+        Self::new(Handle::NONE, Handle::NONE)
+    }
+}
+
+impl Visit for FootIk {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.root.visit("Root", visitor)?;
+        self.pelvis.visit("Pelvis", visitor)?;
+        self.legs.visit("Legs", visitor)?;
+        self.settings.visit("Settings", visitor)?;
+        self.weight.visit("Weight", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Smoothing factor of exponential smoothing for given speed and time step.
+fn smoothing_factor(speed: f32, dt: f32) -> f32 {
+    if speed <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-speed * dt).exp()
+    }
+}
+
+/// Finds closest ground between two points, returns position and upward normal.
+fn cast_ground(
+    physics: &Physics,
+    hits: &mut Vec<RayCastResult>,
+    from: Vec3,
+    to: Vec3,
+) -> Option<(Vec3, Vec3)> {
+    let ray = Ray::from_two_points(&from, &to)?;
+    let direction = ray.dir.normalized()?;
+    hits.clear();
+    physics.ray_cast(
+        &ray,
+        RayCastOptions {
+            ignore_bodies: true,
+            ignore_static_geometries: false,
+            sort_results: true,
+        },
+        hits,
+    );
+    hits.first().map(|hit| {
+        // Winding of triangles is not reliable, ground always faces up.
+        let normal = if hit.normal.y < 0.0 {
+            -hit.normal
+        } else {
+            hit.normal
+        };
+        (from + direction.scale(hit.sqr_distance.sqrt()), normal)
+    })
+}
+
+impl FootIk {
+    /// Creates new foot IK for a character with given root node, whose position is ground
+    /// level of animation, and pelvis bone, which is parent of all legs.
+    pub fn new(root: Handle<Node>, pelvis: Handle<Node>) -> Self {
+        Self {
+            root,
+            pelvis,
+            legs: Default::default(),
+            settings: Default::default(),
+            weight: 1.0,
+            pelvis_offset: 0.0,
+        }
+    }
+
+    /// Adds new leg.
+    pub fn with_leg(mut self, leg: FootIkLeg) -> Self {
+        self.legs.push(leg);
+        self
+    }
+
+    /// Sets parameters of foot placement.
+    pub fn with_settings(mut self, settings: FootIkSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Returns root node of character.
+    pub fn root(&self) -> Handle<Node> {
+        self.root
+    }
+
+    /// Returns pelvis bone.
+    pub fn pelvis(&self) -> Handle<Node> {
+        self.pelvis
+    }
+
+    /// Returns legs.
+    pub fn legs(&self) -> &[FootIkLeg] {
+        &self.legs
+    }
+
+    /// Returns parameters of foot placement.
+    pub fn settings(&self) -> &FootIkSettings {
+        &self.settings
+    }
+
+    /// Sets parameters of foot placement.
+    pub fn set_settings(&mut self, settings: FootIkSettings) {
+        self.settings = settings;
+    }
+
+    /// Sets weight of foot placement in range [0; 1], 0 - animation is left as is. Weight
+    /// should be lowered when character is in the air, adjustments fade out smoothly.
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight.max(0.0).min(1.0);
+    }
+
+    /// Returns weight of foot placement.
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Returns smoothed offset of pelvis, zero or negative.
+    pub fn pelvis_offset(&self) -> f32 {
+        self.pelvis_offset
+    }
+
+    fn bones(&self) -> impl Iterator<Item = Handle<Node>> + '_ {
+        std::iter::once(self.pelvis).chain(self.legs.iter().flat_map(|leg| leg.bones().to_vec()))
+    }
+
+    fn update(
+        &mut self,
+        graph: &mut Graph,
+        physics: &Physics,
+        hits: &mut Vec<RayCastResult>,
+        dt: f32,
+    ) {
+        let settings = self.settings;
+        let factor = smoothing_factor(settings.smoothing_speed, dt);
+        let ground_level = graph[self.root].global_position().y;
+
+        for leg in self.legs.iter_mut() {
+            let ankle = graph[leg.foot].global_position();
+            let from = Vec3::new(ankle.x, ground_level + settings.max_step_up, ankle.z);
+            let to = Vec3::new(ankle.x, ground_level - settings.max_step_down, ankle.z);
+            // Foot over a hole stays at animated height.
+            let (offset, normal) = cast_ground(physics, hits, from, to)
+                .map(|(point, normal)| (point.y - ground_level, normal))
+                .unwrap_or((0.0, Vec3::UP));
+
+            leg.ground_offset += (offset * self.weight - leg.ground_offset) * factor;
+            leg.ground_normal = leg
+                .ground_normal
+                .lerp(&Vec3::UP.lerp(&normal, self.weight), factor)
+                .normalized()
+                .unwrap_or(Vec3::UP);
+        }
+
+        // Lower pelvis so the foot on lowest ground can reach it, feet on higher ground
+        // are reached by bending legs.
+        let lowest = self
+            .legs
+            .iter()
+            .map(|leg| leg.ground_offset)
+            .fold(0.0f32, f32::min);
+        let pelvis_target = lowest.max(-settings.max_pelvis_offset);
+        self.pelvis_offset += (pelvis_target - self.pelvis_offset) * factor;
+        if self.pelvis_offset < 0.0 {
+            transform_bone(
+                graph,
+                self.pelvis,
+                Mat4::translate(Vec3::new(0.0, self.pelvis_offset, 0.0)),
+            );
+        }
+
+        for leg in self.legs.iter() {
+            let hip = graph[leg.thigh].global_position();
+            let knee = graph[leg.calf].global_position();
+            let ankle = graph[leg.foot].global_position();
+            // Animated ankle moved by height of ground under it, pelvis offset is already
+            // applied to the ankle, so it must be excluded.
+            let target = ankle + Vec3::new(0.0, leg.ground_offset - self.pelvis_offset, 0.0);
+            // Knees bend forward, so a straight leg bends in the direction character faces.
+            let pole = graph[self.root].look_vector();
+            let (new_knee, new_ankle) = solve_two_bone(hip, knee, ankle, target, pole);
+
+            if let Some(rotation) = rotation_between(knee - hip, new_knee - hip) {
+                rotate_bone(graph, leg.thigh, rotation);
+            }
+            let knee = graph[leg.calf].global_position();
+            let ankle = graph[leg.foot].global_position();
+            if let Some(rotation) = rotation_between(ankle - knee, new_ankle - knee) {
+                rotate_bone(graph, leg.calf, rotation);
+            }
+
+            if let Some(axis) = Vec3::UP.cross(&leg.ground_normal).normalized() {
+                let angle = Vec3::UP
+                    .dot(&leg.ground_normal)
+                    .max(-1.0)
+                    .min(1.0)
+                    .acos()
+                    .min(settings.max_foot_angle);
+                rotate_bone(graph, leg.foot, Quat::from_axis_angle(axis, angle));
+            }
+        }
+    }
+
+    fn is_valid(&self, graph: &Graph) -> bool {
+        graph.is_valid_handle(self.root) && self.bones().all(|bone| graph.is_valid_handle(bone))
+    }
+}
+
+impl BoneController for FootIk {
+    fn controlled_bones(&self) -> Vec<Handle<Node>> {
+        self.bones().collect()
+    }
+
+    fn remap(&self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) -> Option<Self> {
+        let map = |handle: Handle<Node>| old_new_map.get(&handle).copied();
+        let legs = self
+            .legs
+            .iter()
+            .map(|leg| {
+                Some(FootIkLeg {
+                    thigh: map(leg.thigh)?,
+                    calf: map(leg.calf)?,
+                    foot: map(leg.foot)?,
+                    ..*leg
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            root: map(self.root)?,
+            pelvis: map(self.pelvis)?,
+            legs,
+            ..self.clone()
+        })
+    }
+}
+
+/// Container of foot IK of a scene.
+pub type FootIkContainer = BoneControllerContainer<FootIk>;
+
+impl FootIkContainer {
+    /// Adds new foot IK to container.
+    pub fn add(&mut self, foot_ik: FootIk) -> Handle<FootIk> {
+        self.spawn(foot_ik)
+    }
+
+    /// Removes foot IK, its bones return to animated pose immediately.
+    pub fn remove(&mut self, handle: Handle<FootIk>, graph: &mut Graph) -> FootIk {
+        self.free(handle, graph)
+    }
+
+    /// Updates every foot IK, see module docs.
+    pub(in crate) fn update(&mut self, graph: &mut Graph, physics: &Physics, dt: f32) {
+        let mut hits = Vec::new();
+        for foot_ik in self.iter_mut() {
+            if foot_ik.is_valid(graph) {
+                foot_ik.update(graph, physics, &mut hits, dt);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::foot_ik::{solve_two_bone, FootIk, FootIkContainer, FootIkLeg, FootIkSettings},
+        core::{math::vec3::Vec3, pool::Handle},
+        physics::{
+            static_geometry::{StaticGeometry, StaticTriangle},
+            Physics,
+        },
+        scene::{base::BaseBuilder, graph::Graph, node::Node, transform::TransformBuilder},
+    };
+
+    #[test]
+    fn test_solve_two_bone() {
+        let root = Vec3::ZERO;
+        let knee = Vec3::new(0.0, -1.0, 0.0);
+        let ankle = Vec3::new(0.0, -2.0, 0.0);
+        let pole = Vec3::new(0.0, 0.0, 1.0);
+
+        let target = Vec3::new(0.0, -1.5, 0.0);
+        let (new_knee, new_ankle) = solve_two_bone(root, knee, ankle, target, pole);
+        assert!(new_ankle.distance(&target) < 0.0001);
+        assert!((new_knee.distance(&root) - 1.0).abs() < 0.0001);
+        assert!((new_ankle.distance(&new_knee) - 1.0).abs() < 0.0001);
+        // Straight leg bends towards pole.
+        assert!(new_knee.z > 0.0);
+
+        // Unreachable target is approached by straight chain.
+        let (_, new_ankle) = solve_two_bone(root, knee, ankle, Vec3::new(0.0, -5.0, 0.0), pole);
+        assert!(new_ankle.distance(&ankle) < 0.0001);
+    }
+
+    fn add_bone(graph: &mut Graph, parent: Handle<Node>, position: Vec3) -> Handle<Node> {
+        let bone = graph.add_node(Node::Base(
+            BaseBuilder::new()
+                .with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_position(position)
+                        .build(),
+                )
+                .build(),
+        ));
+        if parent.is_some() {
+            graph.link_nodes(bone, parent);
+        }
+        bone
+    }
+
+    #[test]
+    fn test_foot_is_placed_on_static_geometry() {
+        let mut graph = Graph::new();
+        let root = add_bone(&mut graph, Handle::NONE, Vec3::ZERO);
+        let pelvis = add_bone(&mut graph, root, Vec3::new(0.0, 2.0, 0.0));
+        let thigh = add_bone(&mut graph, pelvis, Vec3::new(0.2, 0.0, 0.0));
+        let calf = add_bone(&mut graph, thigh, Vec3::new(0.0, -1.0, 0.0));
+        let foot = add_bone(&mut graph, calf, Vec3::new(0.0, -1.0, 0.0));
+        graph.update_hierachical_data();
+
+        // Step which is higher than ground level of animation.
+        let mut physics = Physics::default();
+        let corners = [
+            Vec3::new(-5.0, 0.3, -5.0),
+            Vec3::new(5.0, 0.3, -5.0),
+            Vec3::new(5.0, 0.3, 5.0),
+            Vec3::new(-5.0, 0.3, 5.0),
+        ];
+        let triangles = [(0, 1, 2), (0, 2, 3)]
+            .iter()
+            .filter_map(|&(a, b, c)| {
+                StaticTriangle::from_points(&corners[a], &corners[b], &corners[c])
+            })
+            .collect();
+        physics.add_static_geometry(StaticGeometry::new(triangles));
+
+        let mut container = FootIkContainer::default();
+        container.add(
+            FootIk::new(root, pelvis)
+                .with_leg(FootIkLeg::new(thigh, calf, foot))
+                .with_settings(FootIkSettings {
+                    smoothing_speed: 0.0,
+                    ..Default::default()
+                }),
+        );
+        container.update(&mut graph, &physics, 1.0 / 60.0);
+
+        let ankle = graph[foot].global_position();
+        assert!((ankle.y - 0.3).abs() < 0.001);
+        // Leg is bent, so length of bones is preserved.
+        let hip = graph[thigh].global_position();
+        let knee = graph[calf].global_position();
+        assert!((hip.distance(&knee) - 1.0).abs() < 0.001);
+        assert!((knee.distance(&ankle) - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,417 @@
+//! Procedural hit reactions.
+//!
+//! Hit reaction turns a hit (point, direction and strength of impact) into short additive
+//! rotations of bones which are layered over current animation, so a character visibly
+//! flinches when shot without any authored reaction clips. Every bone of reaction has an
+//! angular spring: hit gives the spring angular velocity, the spring bends the bone and
+//! brings it back to animated pose in a fraction of second.
+//!
+//! Hit affects the bone that was hit and its ancestors which are part of reaction, every
+//! next ancestor reacts weaker (see `HitReactionSettings::falloff`), so a hit into a hand
+//! mostly moves the arm while a hit into chest moves spine and head.
+//!
+//! Hit reaction is a bone controller, its rotations are composed with adjustments of foot
+//! IK, see [bone_controller](../bone_controller/index.html) module docs.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::hit_reaction::HitReaction,
+//!     core::{math::vec3::Vec3, pool::Handle},
+//!     scene::{node::Node, Scene},
+//! };
+//!
+//! fn on_bullet_hit(scene: &mut Scene, reaction: Handle<HitReaction>, bone: Handle<Node>) {
+//!     let point = scene.graph[bone].global_position();
+//!     scene.hit_reactions.get_mut(reaction).hit(
+//!         &scene.graph,
+//!         bone,
+//!         point,
+//!         Vec3::new(0.0, 0.0, 1.0),
+//!         1.0,
+//!     );
+//! }
+//! ```
+
+use crate::{
+    animation::bone_controller::{rotate_bone, BoneController, BoneControllerContainer},
+    core::{
+        math::{quat::Quat, vec3::Vec3},
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::{graph::Graph, node::Node},
+    utils::log::Log,
+};
+use std::collections::HashMap;
+
+/// Parameters of springs of hit reaction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HitReactionSettings {
+    /// Stiffness of springs, higher values give faster and shorter reactions.
+    pub stiffness: f32,
+    /// Damping of springs, critical damping is `2 * sqrt(stiffness)`.
+    pub damping: f32,
+    /// Maximum angle of bone deviation from animated pose in radians.
+    pub max_angle: f32,
+    /// Multiplier of reaction for every next ancestor of the bone which was hit.
+    pub falloff: f32,
+}
+
+impl Default for HitReactionSettings {
+    fn default() -> Self {
+        Self {
+            stiffness: 150.0,
+            damping: 14.0,
+            max_angle: 35.0f32.to_radians(),
+            falloff: 0.6,
+        }
+    }
+}
+
+impl Visit for HitReactionSettings {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.stiffness.visit("Stiffness", visitor)?;
+        self.damping.visit("Damping", visitor)?;
+        self.max_angle.visit("MaxAngle", visitor)?;
+        self.falloff.visit("Falloff", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Angular spring of a bone, rotation is stored as axis multiplied by angle in world
+/// coordinates.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Spring {
+    angle: Vec3,
+    velocity: Vec3,
+}
+
+impl Spring {
+    /// Threshold below which spring is considered to be at rest.
+    const REST_EPSILON: f32 = 0.0001;
+
+    fn update(&mut self, settings: &HitReactionSettings, dt: f32) {
+        let acceleration =
+            self.angle.scale(-settings.stiffness) - self.velocity.scale(settings.damping);
+        // Semi-implicit Euler is stable enough for stiff springs at usual frame rates.
+        self.velocity += acceleration.scale(dt);
+        self.angle += self.velocity.scale(dt);
+
+        let angle = self.angle.len();
+        if angle > settings.max_angle {
+            self.angle = self.angle.scale(settings.max_angle / angle);
+        }
+    }
+
+    fn is_at_rest(&self) -> bool {
+        self.angle.sqr_len() < Self::REST_EPSILON && self.velocity.sqr_len() < Self::REST_EPSILON
+    }
+
+    fn rotation(&self) -> Option<Quat> {
+        let angle = self.angle.len();
+        let axis = self.angle.normalized()?;
+        Some(Quat::from_axis_angle(axis, angle))
+    }
+}
+
+/// Set of bones reacting to hits, see module docs.
+#[derive(Clone, Debug, Default)]
+pub struct HitReaction {
+    bones: Vec<Handle<Node>>,
+    weights: Vec<f32>,
+    springs: Vec<Spring>,
+    settings: HitReactionSettings,
+    enabled: bool,
+}
+
+impl Visit for HitReaction {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.bones.visit("Bones", visitor)?;
+        self.weights.visit("Weights", visitor)?;
+        self.settings.visit("Settings", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+
+        visitor.leave_region()?;
+
+        // Springs are not serialized, loaded reaction starts at rest.
+        if visitor.is_reading() {
+            if self.weights.len() != self.bones.len() {
+                Log::writeln(format!(
+                    "Hit reaction has {} bones, but {} weights, missing weights are set \
+                     to 1.0 and extra ones are dropped.",
+                    self.bones.len(),
+                    self.weights.len()
+                ));
+                self.weights.resize(self.bones.len(), 1.0);
+            }
+            self.springs = vec![Spring::default(); self.bones.len()];
+        }
+
+        Ok(())
+    }
+}
+
+impl HitReaction {
+    /// Creates new reaction for given bones, every bone reacts with full weight.
+    pub fn new(bones: Vec<Handle<Node>>) -> Self {
+        Self {
+            weights: vec![1.0; bones.len()],
+            springs: vec![Spring::default(); bones.len()],
+            bones,
+            settings: Default::default(),
+            enabled: true,
+        }
+    }
+
+    /// Sets how strong given bone reacts to hits, 0 - bone does not react at all. Heavy
+    /// bones like pelvis should have lower weights than light ones like head.
+    pub fn with_bone_weight(mut self, bone: Handle<Node>, weight: f32) -> Self {
+        if let Some(index) = self.bones.iter().position(|&b| b == bone) {
+            self.weights[index] = weight.max(0.0);
+        }
+        self
+    }
+
+    /// Sets parameters of springs.
+    pub fn with_settings(mut self, settings: HitReactionSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Returns bones of reaction.
+    pub fn bones(&self) -> &[Handle<Node>] {
+        &self.bones
+    }
+
+    /// Returns parameters of springs.
+    pub fn settings(&self) -> &HitReactionSettings {
+        &self.settings
+    }
+
+    /// Sets parameters of springs.
+    pub fn set_settings(&mut self, settings: HitReactionSettings) {
+        self.settings = settings;
+    }
+
+    /// Enables or disables reaction, disabled reaction ignores hits and its bones return
+    /// to animated pose immediately.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.reset();
+        }
+    }
+
+    /// Returns true if reaction is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Stops every spring, bones return to animated pose on next update.
+    pub fn reset(&mut self) {
+        for spring in self.springs.iter_mut() {
+            *spring = Spring::default();
+        }
+    }
+
+    /// Returns true if any bone is still moving because of a hit.
+    pub fn is_active(&self) -> bool {
+        self.springs.iter().any(|spring| !spring.is_at_rest())
+    }
+
+    /// Applies hit to given bone. `point` is world position of the hit, `direction` is
+    /// world direction of impact (i.e. direction of bullet), `strength` is angular speed
+    /// given to bone that was hit in radians per second per unit of lever arm.
+    pub fn hit(
+        &mut self,
+        graph: &Graph,
+        bone: Handle<Node>,
+        point: Vec3,
+        direction: Vec3,
+        strength: f32,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let direction = match direction.normalized() {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        // Walk from the bone that was hit up to the root, every ancestor which is a part of
+        // reaction gets weaker impulse.
+        let mut influence = 1.0;
+        let mut current = bone;
+        while current.is_some() && graph.is_valid_handle(current) {
+            if let Some(index) = self.bones.iter().position(|&b| b == current) {
+                let lever = point - graph[current].global_position();
+                let impulse = lever
+                    .cross(&direction)
+                    .scale(strength * influence * self.weights[index]);
+                self.springs[index].velocity += impulse;
+                influence *= self.settings.falloff;
+            }
+            current = graph[current].parent();
+        }
+    }
+
+    /// Integrates springs and applies their rotations to bones.
+    fn update(&mut self, graph: &mut Graph, dt: f32) {
+        // Bones at rest are left in pose made by animation and previous controllers.
+        if !self.is_active() {
+            return;
+        }
+
+        let settings = self.settings;
+        for spring in self.springs.iter_mut() {
+            spring.update(&settings, dt);
+        }
+
+        // Ancestors must be processed first, because their rotations move descendants.
+        let depth = |graph: &Graph, mut node: Handle<Node>| {
+            let mut depth = 0;
+            while node.is_some() {
+                node = graph[node].parent();
+                depth += 1;
+            }
+            depth
+        };
+        let mut order = (0..self.bones.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| depth(graph, self.bones[i]));
+
+        for i in order {
+            if let Some(rotation) = self.springs[i].rotation() {
+                rotate_bone(graph, self.bones[i], rotation);
+            }
+        }
+    }
+}
+
+impl BoneController for HitReaction {
+    fn controlled_bones(&self) -> Vec<Handle<Node>> {
+        self.bones.clone()
+    }
+
+    fn remap(&self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) -> Option<Self> {
+        let bones = self
+            .bones
+            .iter()
+            .map(|bone| old_new_map.get(bone).copied())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            bones,
+            ..self.clone()
+        })
+    }
+}
+
+/// Container of hit reactions of a scene.
+pub type HitReactionContainer = BoneControllerContainer<HitReaction>;
+
+impl HitReactionContainer {
+    /// Adds new reaction to container.
+    pub fn add(&mut self, reaction: HitReaction) -> Handle<HitReaction> {
+        self.spawn(reaction)
+    }
+
+    /// Removes reaction, its bones return to animated pose immediately.
+    pub fn remove(&mut self, handle: Handle<HitReaction>, graph: &mut Graph) -> HitReaction {
+        self.free(handle, graph)
+    }
+
+    /// Updates every reaction, see module docs.
+    pub(in crate) fn update(&mut self, graph: &mut Graph, dt: f32) {
+        for reaction in self.iter_mut() {
+            if reaction
+                .bones
+                .iter()
+                .all(|&bone| graph.is_valid_handle(bone))
+            {
+                reaction.update(graph, dt);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::hit_reaction::{HitReaction, HitReactionContainer, HitReactionSettings, Spring},
+        core::math::vec3::Vec3,
+        scene::{base::BaseBuilder, graph::Graph, node::Node, transform::TransformBuilder},
+    };
+
+    #[test]
+    fn test_spring_returns_to_rest() {
+        let settings = HitReactionSettings::default();
+        let mut spring = Spring {
+            angle: Vec3::ZERO,
+            velocity: Vec3::new(0.0, 20.0, 0.0),
+        };
+        let mut max_angle = 0.0f32;
+        for _ in 0..120 {
+            spring.update(&settings, 1.0 / 60.0);
+            max_angle = max_angle.max(spring.angle.len());
+        }
+        assert!(max_angle > 0.1);
+        assert!(max_angle <= settings.max_angle + 0.0001);
+        assert!(spring.is_at_rest());
+    }
+
+    #[test]
+    fn test_hit() {
+        let mut graph = Graph::new();
+        let parent = graph.add_node(Node::Base(BaseBuilder::new().build()));
+        let child = graph.add_node(Node::Base(
+            BaseBuilder::new()
+                .with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_position(Vec3::new(0.0, 1.0, 0.0))
+                        .build(),
+                )
+                .build(),
+        ));
+        graph.link_nodes(child, parent);
+        graph.update_hierachical_data();
+
+        let mut reactions = HitReactionContainer::default();
+        let reaction = reactions.add(HitReaction::new(vec![parent, child]));
+        assert!(!reactions.get(reaction).is_active());
+
+        // Hit into the tip of child bone pushes it along Z.
+        reactions.get_mut(reaction).hit(
+            &graph,
+            child,
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+        );
+        let springs = &reactions.get(reaction).springs;
+        assert_eq!(springs[1].velocity, Vec3::new(1.0, 0.0, 0.0));
+        // Parent reacts weaker with longer lever.
+        let falloff = HitReactionSettings::default().falloff;
+        assert!((springs[0].velocity.x - 2.0 * falloff).abs() < 0.0001);
+        assert!(reactions.get(reaction).is_active());
+
+        reactions.update(&mut graph, 1.0 / 60.0);
+        assert!(graph[child].global_position().z > 0.0);
+
+        // Disabled reaction stops and ignores hits.
+        reactions.get_mut(reaction).set_enabled(false);
+        reactions.get_mut(reaction).hit(
+            &graph,
+            child,
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+        );
+        assert!(!reactions.get(reaction).is_active());
+    }
+}
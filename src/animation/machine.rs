@@ -210,8 +210,8 @@ impl Default for PoseWeight {
 impl PoseWeight {
     fn from_id(id: i32) -> Result<Self, String> {
         match id {
-            0 => Ok(PoseWeight::Parameter(Default::default())),
-            1 => Ok(PoseWeight::Constant(0.0)),
+            0 => Ok(PoseWeight::Constant(0.0)),
+            1 => Ok(PoseWeight::Parameter(Default::default())),
             _ => Err(format!("Invalid pose weight id {}", id)),
         }
     }
@@ -820,3 +820,71 @@ impl Visit for Machine {
         visitor.leave_region()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::machine::{
+            BlendAnimation, BlendPose, Machine, Parameter, PlayAnimation, PoseNode, PoseWeight,
+            State, Transition,
+        },
+        core::{
+            pool::Handle,
+            visitor::{Visit, Visitor},
+        },
+    };
+
+    #[test]
+    fn machine_weights_round_trip() {
+        let mut machine = Machine::new();
+        let aim = machine.add_node(PoseNode::PlayAnimation(PlayAnimation::new(Handle::NONE)));
+        let walk = machine.add_node(PoseNode::PlayAnimation(PlayAnimation::new(Handle::NONE)));
+        machine.add_node(PoseNode::BlendAnimations(BlendAnimation::new(vec![
+            BlendPose::with_constant_weight(0.75, aim),
+            BlendPose::with_param_weight("Walk", walk),
+        ])));
+        let walk_state = machine.add_state(State::new("Walk", walk));
+        let aim_state = machine.add_state(State::new("Aim", aim));
+        machine.add_transition(Transition::new(
+            "Walk->Aim",
+            walk_state,
+            aim_state,
+            1.0,
+            "WalkToAim",
+        ));
+        machine.set_parameter("Walk", Parameter::Weight(0.25));
+
+        let path = std::env::temp_dir().join(format!(
+            "rg3d_machine_weights_round_trip_{}.bin",
+            std::process::id()
+        ));
+        let mut visitor = Visitor::new();
+        machine.visit("Machine", &mut visitor).unwrap();
+        visitor.save_binary(&path).unwrap();
+
+        let mut visitor = Visitor::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut loaded = Machine::default();
+        loaded.visit("Machine", &mut visitor).unwrap();
+
+        match loaded.parameters.get("Walk") {
+            Some(Parameter::Weight(weight)) => assert_eq!(*weight, 0.25),
+            _ => panic!("weight parameter must be restored"),
+        }
+        let blend = loaded
+            .nodes()
+            .find_map(|node| match node {
+                PoseNode::BlendAnimations(blend) => Some(blend),
+                _ => None,
+            })
+            .unwrap();
+        let sources = blend.pose_sources.borrow();
+        match (&sources[0].weight, &sources[1].weight) {
+            (PoseWeight::Constant(constant), PoseWeight::Parameter(param_id)) => {
+                assert_eq!(*constant, 0.75);
+                assert_eq!(param_id, "Walk");
+            }
+            _ => panic!("pose weights must be restored"),
+        }
+    }
+}
@@ -1,3 +1,6 @@
+pub mod bone_controller;
+pub mod foot_ik;
+pub mod hit_reaction;
 pub mod machine;
 
 use crate::core::pool::Ticket;
@@ -569,6 +572,10 @@ impl Animation {
                 }
             }
         }
+
+        // Restore pose at saved time position, otherwise pose will be empty until next
+        // update and disabled animations will have no pose at all.
+        self.update_pose();
     }
 
     fn update_pose(&mut self) {
@@ -608,6 +615,8 @@ impl Visit for Animation {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
 
+        // Playback state (time position, speed, enabled, signals) is serialized, so
+        // animation will continue from where it was saved. Pose is restored on resolve.
         self.tracks.visit("Tracks", visitor)?;
         self.speed.visit("Speed", visitor)?;
         self.length.visit("Length", visitor)?;
@@ -702,6 +711,12 @@ impl AnimationContainer {
         self.pool.borrow_mut(handle)
     }
 
+    /// Checks whether given animation handle is valid or not.
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Animation>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
     #[inline]
     pub fn retain<P>(&mut self, pred: P)
     where
@@ -738,3 +753,62 @@ impl Visit for AnimationContainer {
         visitor.leave_region()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::{Animation, KeyFrame, Track},
+        core::{
+            math::{quat::Quat, vec3::Vec3},
+            visitor::{Visit, Visitor},
+        },
+        scene::{base::Base, graph::Graph, node::Node},
+    };
+
+    #[test]
+    fn animation_pose_round_trip() {
+        let mut graph = Graph::new();
+        let node = graph.add_node(Node::Base(Base::default()));
+
+        let mut track = Track::new();
+        track.set_node(node);
+        track.add_key_frame(KeyFrame::new(0.0, Vec3::ZERO, Vec3::UNIT, Quat::IDENTITY));
+        track.add_key_frame(KeyFrame::new(
+            1.0,
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::UNIT,
+            Quat::IDENTITY,
+        ));
+        let key_frames = track.get_key_frames().to_vec();
+
+        let mut animation = Animation::default();
+        animation.add_track(track);
+        animation.set_time_position(0.5).set_enabled(false);
+
+        let path = std::env::temp_dir().join(format!(
+            "rg3d_animation_pose_round_trip_{}.bin",
+            std::process::id()
+        ));
+        let mut visitor = Visitor::new();
+        animation.visit("Animation", &mut visitor).unwrap();
+        visitor.save_binary(&path).unwrap();
+
+        let mut visitor = Visitor::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut loaded = Animation::default();
+        loaded.visit("Animation", &mut visitor).unwrap();
+
+        assert_eq!(loaded.get_time_position(), 0.5);
+        assert!(!loaded.is_enabled());
+
+        // Key frames are not saved, they are taken from resource on resolve.
+        loaded.get_tracks_mut()[0].set_key_frames(&key_frames);
+        loaded.resolve(&graph);
+        loaded.get_pose().apply(&mut graph);
+
+        assert_eq!(
+            graph[node].local_transform().position(),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Game event sink - generic way to report named gameplay events with payloads to
+//! pluggable backends.
+//!
+//! Analytics, achievements, telemetry and platform integrations all need same thing: a
+//! stream of "something happened" events from gameplay code. Instead of calling every
+//! integration directly, game emits [`GameEvent`]s to [`GameEventSink`] of the engine and
+//! every registered [`GameEventBackend`] receives them. Events are queued and dispatched
+//! once per frame during engine update, so emitting is cheap and backends never run in
+//! the middle of gameplay code. Events can be emitted from any thread using
+//! [`GameEventSender`].
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::engine::game_events::{EventCounter, GameEvent, GameEventSink};
+//!
+//! let mut sink = GameEventSink::new();
+//! let counter = EventCounter::new();
+//! sink.add_backend(Box::new(counter.clone()));
+//!
+//! sink.emit(
+//!     GameEvent::new("enemy_killed")
+//!         .with("weapon", "shotgun")
+//!         .with("distance", 12.5),
+//! );
+//! sink.dispatch();
+//!
+//! // "Kill 100 enemies" achievement can be checked using counter.
+//! assert_eq!(counter.count("enemy_killed"), 1);
+//! ```
+
+use crate::utils::log::Log;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+/// Value of a single field of event payload.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventValue {
+    /// Boolean value.
+    Bool(bool),
+    /// Integer value.
+    Integer(i64),
+    /// Floating point value.
+    Number(f64),
+    /// String value.
+    String(String),
+}
+
+impl Display for EventValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventValue::Bool(v) => write!(f, "{}", v),
+            EventValue::Integer(v) => write!(f, "{}", v),
+            EventValue::Number(v) => write!(f, "{}", v),
+            EventValue::String(v) => write!(f, "{:?}", v),
+        }
+    }
+}
+
+impl From<bool> for EventValue {
+    fn from(v: bool) -> Self {
+        EventValue::Bool(v)
+    }
+}
+
+impl From<i32> for EventValue {
+    fn from(v: i32) -> Self {
+        EventValue::Integer(v as i64)
+    }
+}
+
+impl From<i64> for EventValue {
+    fn from(v: i64) -> Self {
+        EventValue::Integer(v)
+    }
+}
+
+impl From<u32> for EventValue {
+    fn from(v: u32) -> Self {
+        EventValue::Integer(v as i64)
+    }
+}
+
+impl From<f32> for EventValue {
+    fn from(v: f32) -> Self {
+        EventValue::Number(v as f64)
+    }
+}
+
+impl From<f64> for EventValue {
+    fn from(v: f64) -> Self {
+        EventValue::Number(v)
+    }
+}
+
+impl From<&str> for EventValue {
+    fn from(v: &str) -> Self {
+        EventValue::String(v.to_owned())
+    }
+}
+
+impl From<String> for EventValue {
+    fn from(v: String) -> Self {
+        EventValue::String(v)
+    }
+}
+
+/// Named event with payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameEvent {
+    name: String,
+    payload: Vec<(String, EventValue)>,
+    timestamp: SystemTime,
+}
+
+impl GameEvent {
+    /// Creates new event with given name, empty payload and current time as timestamp.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        Self {
+            name: name.into(),
+            payload: Default::default(),
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// Adds field to payload.
+    pub fn with<K: Into<String>, V: Into<EventValue>>(mut self, key: K, value: V) -> Self {
+        self.payload.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns name of event.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns payload of event as a list of key-value pairs in order of insertion.
+    pub fn payload(&self) -> &[(String, EventValue)] {
+        &self.payload
+    }
+
+    /// Returns value of payload field with given key.
+    pub fn get(&self, key: &str) -> Option<&EventValue> {
+        self.payload.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Returns time at which event was created.
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+}
+
+impl Display for GameEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (i, (key, value)) in self.payload.iter().enumerate() {
+            write!(f, "{}{}={}", if i == 0 { " " } else { ", " }, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Receiver of game events, for example analytics service client or achievement system
+/// of a platform.
+pub trait GameEventBackend: Send {
+    /// Called for every dispatched event.
+    fn on_event(&mut self, event: &GameEvent);
+
+    /// Called after every batch of events, backends that send events over network should
+    /// send accumulated data here.
+    fn flush(&mut self) {}
+}
+
+/// Handle of registered backend.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BackendId(u32);
+
+/// Cloneable sender of events which can be used from any thread.
+#[derive(Clone)]
+pub struct GameEventSender(Sender<GameEvent>);
+
+impl GameEventSender {
+    /// Queues event, it will be dispatched on next engine update.
+    pub fn emit(&self, event: GameEvent) {
+        // Sink lives as long as engine, it is fine to drop events after that.
+        let _ = self.0.send(event);
+    }
+}
+
+/// See module docs.
+pub struct GameEventSink {
+    backends: Vec<(BackendId, Box<dyn GameEventBackend>)>,
+    next_id: u32,
+    sender: Sender<GameEvent>,
+    receiver: Receiver<GameEvent>,
+    enabled: bool,
+}
+
+impl Default for GameEventSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameEventSink {
+    /// Creates new sink without backends.
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            backends: Default::default(),
+            next_id: 0,
+            sender,
+            receiver,
+            enabled: true,
+        }
+    }
+
+    /// Registers new backend, it will receive every event dispatched after this call.
+    pub fn add_backend(&mut self, backend: Box<dyn GameEventBackend>) -> BackendId {
+        let id = BackendId(self.next_id);
+        self.next_id += 1;
+        self.backends.push((id, backend));
+        id
+    }
+
+    /// Removes backend, returns it if it was registered.
+    pub fn remove_backend(&mut self, id: BackendId) -> Option<Box<dyn GameEventBackend>> {
+        let index = self.backends.iter().position(|(i, _)| *i == id)?;
+        Some(self.backends.remove(index).1)
+    }
+
+    /// Returns amount of registered backends.
+    pub fn backend_count(&self) -> usize {
+        self.backends.len()
+    }
+
+    /// Enables or disables sink, events emitted while sink is disabled are discarded. Can
+    /// be used to respect user opt-out of telemetry.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Returns true if sink is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Queues event, it will be dispatched on next engine update.
+    pub fn emit(&self, event: GameEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Returns new sender which can be moved to other threads.
+    pub fn sender(&self) -> GameEventSender {
+        GameEventSender(self.sender.clone())
+    }
+
+    /// Sends every queued event to every backend and flushes backends. Called by engine
+    /// on each update.
+    pub fn dispatch(&mut self) {
+        let mut any = false;
+        while let Ok(event) = self.receiver.try_recv() {
+            if self.enabled {
+                for (_, backend) in self.backends.iter_mut() {
+                    backend.on_event(&event);
+                }
+                any = true;
+            }
+        }
+        if any {
+            for (_, backend) in self.backends.iter_mut() {
+                backend.flush();
+            }
+        }
+    }
+}
+
+/// Backend that writes every event to the log. Useful during development.
+#[derive(Default)]
+pub struct LogBackend;
+
+impl GameEventBackend for LogBackend {
+    fn on_event(&mut self, event: &GameEvent) {
+        Log::writeln(format!("Game event: {}", event));
+    }
+}
+
+/// Backend that counts events by name. Counter is shared between clones, so one clone
+/// can be registered in sink while other is used to query counts, for example to unlock
+/// achievements like "kill 100 enemies".
+#[derive(Clone, Default)]
+pub struct EventCounter {
+    counts: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl EventCounter {
+    /// Creates new counter with zero counts.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns amount of events with given name received so far.
+    pub fn count(&self, name: &str) -> u64 {
+        self.counts.lock().unwrap().get(name).cloned().unwrap_or(0)
+    }
+
+    /// Sets amount of events with given name, useful to restore progress from save file.
+    pub fn set_count(&self, name: &str, count: u64) {
+        self.counts.lock().unwrap().insert(name.to_owned(), count);
+    }
+
+    /// Resets every count to zero.
+    pub fn reset(&self) {
+        self.counts.lock().unwrap().clear();
+    }
+}
+
+impl GameEventBackend for EventCounter {
+    fn on_event(&mut self, event: &GameEvent) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry(event.name().to_owned())
+            .or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::game_events::{EventCounter, EventValue, GameEvent, GameEventSink};
+
+    #[test]
+    fn test_dispatch_from_other_thread() {
+        let mut sink = GameEventSink::new();
+        let counter = EventCounter::new();
+        let id = sink.add_backend(Box::new(counter.clone()));
+
+        let sender = sink.sender();
+        std::thread::spawn(move || sender.emit(GameEvent::new("loaded")))
+            .join()
+            .unwrap();
+        sink.emit(GameEvent::new("loaded"));
+        // Nothing is dispatched until explicit dispatch.
+        assert_eq!(counter.count("loaded"), 0);
+        sink.dispatch();
+        assert_eq!(counter.count("loaded"), 2);
+
+        sink.set_enabled(false);
+        sink.emit(GameEvent::new("loaded"));
+        sink.dispatch();
+        assert_eq!(counter.count("loaded"), 2);
+
+        assert!(sink.remove_backend(id).is_some());
+        assert_eq!(sink.backend_count(), 0);
+    }
+
+    #[test]
+    fn test_payload() {
+        let event = GameEvent::new("level_complete")
+            .with("level", 3)
+            .with("secret", true)
+            .with("time", 95.5);
+        assert_eq!(event.get("level"), Some(&EventValue::Integer(3)));
+        assert_eq!(event.get("missing"), None);
+        assert_eq!(
+            event.to_string(),
+            "level_complete level=3, secret=true, time=95.5"
+        );
+    }
+}
@@ -4,16 +4,30 @@
 #![warn(missing_docs)]
 
 pub mod error;
+pub mod game_events;
+pub mod photo_mode;
 pub mod resource_manager;
+pub mod safe_area;
+pub mod sound_virtualization;
+pub mod ui_scaling;
+pub mod vfs;
 
 use crate::{
     core::{
         math::vec2::Vec2,
+        pool::Handle,
         visitor::{Visit, VisitResult, Visitor},
     },
-    engine::{error::EngineError, resource_manager::ResourceManager},
+    engine::{
+        error::EngineError, game_events::GameEventSink, resource_manager::ResourceManager,
+        safe_area::SafeArea, sound_virtualization::SoundVirtualizer, ui_scaling::UiScaling,
+    },
     event_loop::EventLoop,
-    gui::{Control, UserInterface},
+    gui::{
+        message::{MessageDirection, WidgetMessage},
+        node::UINode,
+        Control, Thickness, UserInterface,
+    },
     renderer::{error::RendererError, Renderer},
     scene::SceneContainer,
     sound::context::Context,
@@ -21,11 +35,72 @@ use crate::{
     Api, GlProfile, GlRequest, NotCurrent, PossiblyCurrent, WindowedContext,
 };
 use std::{
+    ops::{BitOr, BitOrAssign},
     sync::{Arc, Mutex},
     time::{self, Duration},
 };
 use rg3d_ui::message::MessageData;
 
+/// Set of subsystems which are halted by engine pause. User interface and rendering
+/// are never paused, so pause menu can be drawn on top of frozen scenes.
+///
+/// # Example
+///
+/// ```
+/// use rg3d::engine::PauseFlags;
+///
+/// // Typical pause menu - everything in the game stops.
+/// let flags = PauseFlags::ALL;
+/// // Only physics is paused, characters can still be animated.
+/// let flags = PauseFlags::PHYSICS;
+/// // Several subsystems.
+/// let flags = PauseFlags::PHYSICS | PauseFlags::PARTICLES;
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct PauseFlags(u32);
+
+impl PauseFlags {
+    /// Nothing is paused.
+    pub const NONE: Self = Self(0);
+    /// Physics of every scene is paused, including sleeping of bodies.
+    pub const PHYSICS: Self = Self(1);
+    /// Animations of every scene are paused, including light animations, fading of light
+    /// rules and lifetimes of nodes.
+    pub const ANIMATION: Self = Self(1 << 1);
+    /// Particle systems of every scene are paused, existing particles are still drawn.
+    /// Lifetimes of particle systems are paused too.
+    pub const PARTICLES: Self = Self(1 << 2);
+    /// Sound context is paused, sources will continue from same position on resume.
+    pub const SOUND: Self = Self(1 << 3);
+    /// Every subsystem listed above is paused.
+    pub const ALL: Self =
+        Self(Self::PHYSICS.0 | Self::ANIMATION.0 | Self::PARTICLES.0 | Self::SOUND.0);
+
+    /// Returns true if every flag of `other` is set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if no flags are set.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for PauseFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PauseFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0
+    }
+}
+
 /// See module docs.
 pub struct Engine<M: MessageData, C: Control<M, C>> {
     context: glutin::WindowedContext<PossiblyCurrent>,
@@ -50,6 +125,20 @@ pub struct Engine<M: MessageData, C: Control<M, C>> {
     /// for such statistics, probably it is best to make separate structure to hold all
     /// such data.
     pub ui_time: Duration,
+    /// Sink of gameplay events, backends for analytics or achievements should be registered
+    /// here. See [game_events](game_events/index.html) module docs for more info.
+    pub game_events: GameEventSink,
+    /// Virtualizer of far sound sources, it is disabled by default. See
+    /// [sound_virtualization](sound_virtualization/index.html) module docs for more info.
+    pub sound_virtualizer: SoundVirtualizer,
+    paused: PauseFlags,
+    frame_stepping: bool,
+    pending_frame_steps: u32,
+    frame_step_dt: f32,
+    ui_scaling: UiScaling,
+    ui_scale: f32,
+    ui_visible: bool,
+    safe_area: SafeArea,
 }
 
 impl<M: MessageData, C: Control<M, C>> Engine<M, C> {
@@ -99,6 +188,16 @@ impl<M: MessageData, C: Control<M, C>> Engine<M, C> {
                 client_size.height as f32,
             )),
             ui_time: Default::default(),
+            game_events: GameEventSink::new(),
+            sound_virtualizer: Default::default(),
+            paused: PauseFlags::NONE,
+            frame_stepping: false,
+            pending_frame_steps: 0,
+            frame_step_dt: 1.0 / 60.0,
+            ui_scaling: Default::default(),
+            ui_scale: 1.0,
+            ui_visible: true,
+            safe_area: Default::default(),
             context,
         })
     }
@@ -124,23 +223,181 @@ impl<M: MessageData, C: Control<M, C>> Engine<M, C> {
             resource_manager.update(dt);
         }
 
-        for scene in self.scenes.iter_mut() {
-            scene.update(frame_size, dt);
+        if self.frame_stepping {
+            if self.pending_frame_steps > 0 {
+                self.pending_frame_steps -= 1;
+                for scene in self.scenes.iter_mut() {
+                    scene.update_with_pause(frame_size, self.frame_step_dt, self.paused);
+                }
+            } else {
+                // Simulation is frozen, but nodes still must be updated to be rendered
+                // correctly (i.e. when camera was moved manually).
+                for scene in self.scenes.iter_mut() {
+                    scene.update_with_pause(frame_size, 0.0, PauseFlags::ALL);
+                }
+            }
+        } else {
+            for scene in self.scenes.iter_mut() {
+                scene.update_with_pause(frame_size, dt, self.paused);
+            }
+        }
+
+        if !self.paused.contains(PauseFlags::SOUND) {
+            self.sound_virtualizer
+                .update(&mut self.sound_context.lock().unwrap(), dt);
         }
 
+        self.game_events.dispatch();
+
+        self.ui_scale = self
+            .ui_scaling
+            .scale(frame_size, self.context.window().scale_factor() as f32);
+
         let time = time::Instant::now();
-        self.user_interface.update(frame_size, dt);
+        self.user_interface
+            .update(frame_size.scale(1.0 / self.ui_scale), dt);
         self.ui_time = time::Instant::now() - time;
     }
 
+    /// Sets which subsystems should be halted, pass `PauseFlags::NONE` to resume everything.
+    /// User interface and rendering continue to work while paused.
+    pub fn set_paused(&mut self, flags: PauseFlags) {
+        self.sound_context
+            .lock()
+            .unwrap()
+            .pause(flags.contains(PauseFlags::SOUND));
+        self.paused = flags;
+    }
+
+    /// Returns flags of currently paused subsystems.
+    pub fn paused(&self) -> PauseFlags {
+        self.paused
+    }
+
+    /// Enables or disables frame stepping debug mode. In this mode simulation of scenes is
+    /// frozen and advances exactly one fixed update with `frame_step_dt` time delta per
+    /// each `request_frame_step` call, while user interface and rendering continue to
+    /// work. This is very useful to debug physics and animation glitches.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rg3d::{engine::Engine, gui::node::StubNode, event::VirtualKeyCode};
+    /// # fn on_key(engine: &mut Engine<(), StubNode>, key: VirtualKeyCode) {
+    /// match key {
+    ///     VirtualKeyCode::F10 => {
+    ///         let enabled = engine.is_frame_stepping();
+    ///         engine.set_frame_stepping(!enabled)
+    ///     }
+    ///     VirtualKeyCode::F11 => engine.request_frame_step(),
+    ///     _ => (),
+    /// }
+    /// # }
+    /// ```
+    pub fn set_frame_stepping(&mut self, enabled: bool) {
+        self.frame_stepping = enabled;
+        self.pending_frame_steps = 0;
+    }
+
+    /// Returns true if frame stepping debug mode is enabled.
+    pub fn is_frame_stepping(&self) -> bool {
+        self.frame_stepping
+    }
+
+    /// Requests a single fixed update of scenes in frame stepping mode, it will be done
+    /// on next `update` call. Requests are accumulated, so fast key presses won't be
+    /// lost. Does nothing if frame stepping is disabled.
+    pub fn request_frame_step(&mut self) {
+        if self.frame_stepping {
+            self.pending_frame_steps += 1;
+        }
+    }
+
+    /// Sets time delta of a single step in frame stepping mode. Default is 1/60 s.
+    pub fn set_frame_step_dt(&mut self, dt: f32) {
+        self.frame_step_dt = dt.max(0.0);
+    }
+
+    /// Returns time delta of a single step in frame stepping mode.
+    pub fn frame_step_dt(&self) -> f32 {
+        self.frame_step_dt
+    }
+
+    /// Sets new scaling of user interface, it will be applied on next `update` call. See
+    /// [ui_scaling](ui_scaling/index.html) module docs for more info.
+    pub fn set_ui_scaling(&mut self, scaling: UiScaling) {
+        self.ui_scaling = scaling;
+    }
+
+    /// Returns current scaling of user interface.
+    pub fn ui_scaling(&self) -> UiScaling {
+        self.ui_scaling
+    }
+
+    /// Returns amount of physical pixels in a single UI unit which was calculated on last
+    /// `update` call. Positions of cursor must be divided by this value before passing them
+    /// to user interface, [translate_event_scaled](../utils/fn.translate_event_scaled.html)
+    /// does this.
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    /// Shows or hides user interface. Hidden interface is still updated and receives
+    /// messages, but it is not drawn. Useful for photo mode and clean screenshots.
+    pub fn set_ui_visible(&mut self, visible: bool) {
+        self.ui_visible = visible;
+    }
+
+    /// Returns true if user interface is drawn.
+    pub fn is_ui_visible(&self) -> bool {
+        self.ui_visible
+    }
+
+    /// Sets safe area of the screen, it is usually taken from game settings where user
+    /// adjusts it to fit TV. See [safe_area](safe_area/index.html) module docs for more
+    /// info.
+    pub fn set_safe_area(&mut self, safe_area: SafeArea) {
+        self.safe_area = safe_area;
+    }
+
+    /// Returns current safe area of the screen.
+    pub fn safe_area(&self) -> SafeArea {
+        self.safe_area
+    }
+
+    /// Returns insets of safe area in logical UI units, UI scaling is taken into account.
+    pub fn safe_area_insets(&self) -> Thickness {
+        let (width, height) = self.renderer.get_frame_size();
+        let frame_size = Vec2::new(width as f32, height as f32);
+        self.safe_area.insets(frame_size.scale(1.0 / self.ui_scale))
+    }
+
+    /// Sets margin of given widget to insets of safe area, so its content stays inside
+    /// safe area. Usually widget is root of HUD which is stretched over the whole screen.
+    /// Must be called again when safe area, frame size or UI scaling changes.
+    pub fn apply_safe_area(&mut self, widget: Handle<UINode<M, C>>) {
+        let insets = self.safe_area_insets();
+        self.user_interface.send_message(WidgetMessage::margin(
+            widget,
+            MessageDirection::ToWidget,
+            insets,
+        ));
+    }
+
     /// Performs rendering of single frame, must be called from your game loop, otherwise you won't
     /// see anything.
     #[inline]
     pub fn render(&mut self, dt: f32) -> Result<(), RendererError> {
-        self.user_interface.draw();
+        let drawing_context = if self.ui_visible {
+            self.user_interface.draw();
+            Some(self.user_interface.get_drawing_context())
+        } else {
+            None
+        };
         self.renderer.render_and_swap_buffers(
             &self.scenes,
-            &self.user_interface.get_drawing_context(),
+            drawing_context,
+            self.ui_scale,
             &self.context,
             dt,
         )
@@ -0,0 +1,295 @@
+//! Photo mode - free camera for taking in-game pictures.
+//!
+//! When photo mode is entered, every subsystem is paused (see [`PauseFlags`]), user
+//! interface is hidden and a free camera is created at position of current camera of
+//! the scene, every other camera is disabled. Free camera can optionally collide with
+//! scene geometry and be limited by distance from point where photo mode was entered, so
+//! players cannot look behind level boundaries. Exposure and depth of field can be
+//! overridden for the photo camera only. Pictures can be captured at any resolution, see
+//! [`Renderer::render_screenshot`](../../renderer/struct.Renderer.html#method.render_screenshot).
+//! Everything is restored on exit.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::{math::vec3::Vec3, pool::Handle},
+//!     engine::{photo_mode::{PhotoMode, PhotoModeSettings}, Engine},
+//!     gui::node::StubNode,
+//!     scene::Scene,
+//! };
+//!
+//! fn take_photo(engine: &mut Engine<(), StubNode>, scene: Handle<Scene>) {
+//!     let mut photo_mode = PhotoMode::enter(engine, scene, PhotoModeSettings::default());
+//!     // Move forward and turn a bit, usually this is driven by input.
+//!     photo_mode.move_camera(engine, Vec3::new(0.0, 0.0, 1.0), 0.1, 0.0, 1.0 / 60.0);
+//!     photo_mode.set_exposure(engine, 1.2);
+//!     let mut photo = photo_mode.capture(engine, 3840, 2160).unwrap();
+//!     photo.set_path(&"photo.png");
+//!     photo.save().unwrap();
+//!     photo_mode.exit(engine);
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::{quat::Quat, ray::Ray, vec3::Vec3},
+        pool::Handle,
+    },
+    engine::{Engine, PauseFlags},
+    gui::{message::MessageData, Control},
+    renderer::{error::RendererError, screenshot::ScreenshotSettings},
+    resource::texture::Texture,
+    scene::{
+        base::BaseBuilder,
+        camera::{CameraBuilder, DepthOfField},
+        node::Node,
+        Scene,
+    },
+};
+
+/// Settings of photo mode camera.
+#[derive(Copy, Clone, Debug)]
+pub struct PhotoModeSettings {
+    /// Speed of camera in units per second. Default is 3.0.
+    pub move_speed: f32,
+    /// If set, camera collides with visible meshes of the scene and keeps given distance
+    /// from them. Default is Some(0.2).
+    pub collision_radius: Option<f32>,
+    /// If set, camera cannot go further than given distance from point where photo mode
+    /// was entered. Default is Some(10.0).
+    pub max_distance: Option<f32>,
+}
+
+impl Default for PhotoModeSettings {
+    fn default() -> Self {
+        Self {
+            move_speed: 3.0,
+            collision_radius: Some(0.2),
+            max_distance: Some(10.0),
+        }
+    }
+}
+
+/// See module docs.
+pub struct PhotoMode {
+    scene: Handle<Scene>,
+    camera: Handle<Node>,
+    disabled_cameras: Vec<Handle<Node>>,
+    previous_pause: PauseFlags,
+    previous_ui_visible: bool,
+    origin: Vec3,
+    yaw: f32,
+    pitch: f32,
+    settings: PhotoModeSettings,
+}
+
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+fn rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), yaw)
+        * Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), pitch)
+}
+
+impl PhotoMode {
+    /// Enters photo mode in given scene. Photo camera starts at position and orientation
+    /// of first enabled camera of the scene and inherits its field of view and effects.
+    pub fn enter<M: MessageData, C: Control<M, C>>(
+        engine: &mut Engine<M, C>,
+        scene: Handle<Scene>,
+        settings: PhotoModeSettings,
+    ) -> Self {
+        let previous_pause = engine.paused();
+        let previous_ui_visible = engine.is_ui_visible();
+        engine.set_paused(PauseFlags::ALL);
+        engine.set_ui_visible(false);
+
+        let graph = &mut engine.scenes[scene].graph;
+
+        let disabled_cameras = graph
+            .pair_iter()
+            .filter_map(|(handle, node)| match node {
+                Node::Camera(camera) if camera.is_enabled() => Some(handle),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let camera = graph.add_node(CameraBuilder::new(BaseBuilder::new()).build_node());
+
+        let (mut origin, mut look) = (Vec3::ZERO, Vec3::LOOK);
+        if let Some(&first) = disabled_cameras.first() {
+            let (source, photo_camera) = graph.get_two_mut((first, camera));
+            if let (Node::Camera(source), Node::Camera(photo_camera)) = (source, photo_camera) {
+                origin = source.global_position();
+                look = source.look_vector();
+                photo_camera
+                    .set_fov(source.fov())
+                    .set_z_near(source.z_near())
+                    .set_z_far(source.z_far())
+                    .set_layer_mask(source.layer_mask())
+                    .set_exposure(source.exposure())
+                    .set_depth_of_field(source.depth_of_field());
+            }
+        }
+        for &handle in disabled_cameras.iter() {
+            if let Node::Camera(camera) = &mut graph[handle] {
+                camera.set_enabled(false);
+            }
+        }
+
+        let yaw = look.x.atan2(look.z);
+        let pitch = (-look.y)
+            .max(-1.0)
+            .min(1.0)
+            .asin()
+            .max(-MAX_PITCH)
+            .min(MAX_PITCH);
+        graph[camera]
+            .local_transform_mut()
+            .set_position(origin)
+            .set_rotation(rotation(yaw, pitch));
+
+        Self {
+            scene,
+            camera,
+            disabled_cameras,
+            previous_pause,
+            previous_ui_visible,
+            origin,
+            yaw,
+            pitch,
+            settings,
+        }
+    }
+
+    /// Leaves photo mode: removes photo camera, enables cameras that were disabled and
+    /// restores pause flags and visibility of user interface.
+    pub fn exit<M: MessageData, C: Control<M, C>>(self, engine: &mut Engine<M, C>) {
+        let graph = &mut engine.scenes[self.scene].graph;
+        graph.remove_node(self.camera);
+        for handle in self.disabled_cameras {
+            if graph.is_valid_handle(handle) {
+                if let Node::Camera(camera) = &mut graph[handle] {
+                    camera.set_enabled(true);
+                }
+            }
+        }
+        engine.set_paused(self.previous_pause);
+        engine.set_ui_visible(self.previous_ui_visible);
+    }
+
+    /// Returns handle of photo camera.
+    pub fn camera(&self) -> Handle<Node> {
+        self.camera
+    }
+
+    /// Returns settings of photo mode.
+    pub fn settings(&self) -> &PhotoModeSettings {
+        &self.settings
+    }
+
+    /// Sets new settings of photo mode.
+    pub fn set_settings(&mut self, settings: PhotoModeSettings) {
+        self.settings = settings;
+    }
+
+    /// Moves and rotates photo camera. Direction is given in local space of camera
+    /// (X - right, Y - up, Z - forward) and is multiplied by move speed and time delta,
+    /// rotation is given in radians.
+    pub fn move_camera<M: MessageData, C: Control<M, C>>(
+        &mut self,
+        engine: &mut Engine<M, C>,
+        direction: Vec3,
+        yaw_delta: f32,
+        pitch_delta: f32,
+        dt: f32,
+    ) {
+        self.yaw += yaw_delta;
+        self.pitch = (self.pitch + pitch_delta).max(-MAX_PITCH).min(MAX_PITCH);
+
+        let scene = &mut engine.scenes[self.scene];
+        let node = &scene.graph[self.camera];
+        let from = node.local_transform().position();
+        let offset = (node.side_vector().scale(direction.x)
+            + node.up_vector().scale(direction.y)
+            + node.look_vector().scale(direction.z))
+        .normalized()
+        .unwrap_or(Vec3::ZERO)
+        .scale(self.settings.move_speed * dt);
+        let mut to = from + offset;
+
+        if let Some(radius) = self.settings.collision_radius {
+            if let Some(ray) = Ray::from_two_points(&from, &to) {
+                let length = offset.len();
+                if let Some(hit) = scene.query_surface(&ray) {
+                    if hit.distance < length + radius {
+                        let allowed = (hit.distance - radius).max(0.0);
+                        to = from + offset.scale(allowed / length);
+                    }
+                }
+            }
+        }
+
+        if let Some(max_distance) = self.settings.max_distance {
+            let delta = to - self.origin;
+            if delta.len() > max_distance {
+                to = self.origin + delta.normalized().unwrap_or(Vec3::ZERO).scale(max_distance);
+            }
+        }
+
+        scene.graph[self.camera]
+            .local_transform_mut()
+            .set_position(to)
+            .set_rotation(rotation(self.yaw, self.pitch));
+    }
+
+    /// Overrides exposure of photo camera.
+    pub fn set_exposure<M: MessageData, C: Control<M, C>>(
+        &mut self,
+        engine: &mut Engine<M, C>,
+        exposure: f32,
+    ) {
+        if let Node::Camera(camera) = &mut engine.scenes[self.scene].graph[self.camera] {
+            camera.set_exposure(exposure);
+        }
+    }
+
+    /// Overrides depth of field of photo camera.
+    pub fn set_depth_of_field<M: MessageData, C: Control<M, C>>(
+        &mut self,
+        engine: &mut Engine<M, C>,
+        depth_of_field: Option<DepthOfField>,
+    ) {
+        if let Node::Camera(camera) = &mut engine.scenes[self.scene].graph[self.camera] {
+            camera.set_depth_of_field(depth_of_field);
+        }
+    }
+
+    /// Sets field of view (in radians) of photo camera.
+    pub fn set_fov<M: MessageData, C: Control<M, C>>(
+        &mut self,
+        engine: &mut Engine<M, C>,
+        fov: f32,
+    ) {
+        if let Node::Camera(camera) = &mut engine.scenes[self.scene].graph[self.camera] {
+            camera.set_fov(fov);
+        }
+    }
+
+    /// Renders picture from photo camera at given resolution, it can be bigger than size of
+    /// window. User interface is never included, render passes of photo camera are taken
+    /// from its scene (see `frame_graph`).
+    pub fn capture<M: MessageData, C: Control<M, C>>(
+        &self,
+        engine: &mut Engine<M, C>,
+        width: u32,
+        height: u32,
+    ) -> Result<Texture, RendererError> {
+        let settings = ScreenshotSettings::new(width, height).with_scene(self.scene);
+        engine.renderer.render_screenshot_with_settings(
+            &engine.scenes[self.scene],
+            self.camera,
+            settings,
+        )
+    }
+}
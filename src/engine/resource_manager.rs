@@ -1,12 +1,52 @@
 //! Resource manager controls loading and lifetime of resource in the engine.
+//!
+//! # Load hooks
+//!
+//! Resource manager allows user code to intercept resource requests with load hooks,
+//! this is the main extension point for modding: a hook can redirect request to another
+//! file (e.g. check a mods folder before the base assets) or patch resource right after
+//! it was loaded.
+//!
+//! # Virtual file system
+//!
+//! Every resource is read through [virtual file system](../vfs/index.html) of resource
+//! manager, mount points of VFS allow to layer base, DLC and mod content. Paths of loaded
+//! resources are always virtual, so saved scenes does not depend on layering.
+//!
+//! ```no_run
+//! use rg3d::engine::resource_manager::{ResourceKind, ResourceLoadHook};
+//! use std::path::{Path, PathBuf};
+//!
+//! struct ModsFolder;
+//!
+//! impl ResourceLoadHook for ModsFolder {
+//!     fn pre_load(&self, path: &Path, _kind: ResourceKind) -> Option<PathBuf> {
+//!         let modded = Path::new("mods").join(path);
+//!         if modded.exists() {
+//!             Some(modded)
+//!         } else {
+//!             None
+//!         }
+//!     }
+//! }
+//! # fn register(resource_manager: &mut rg3d::engine::resource_manager::ResourceManager) {
+//! resource_manager.add_load_hook(ModsFolder);
+//! # }
+//! ```
 
 use crate::{
     core::visitor::{Visit, VisitResult, Visitor},
-    resource::{model::Model, texture::Texture, texture::TextureKind},
+    engine::vfs::{self, Vfs},
+    resource::{
+        channel_packing, import_cache::ImportCache, model::Model, texture::Texture,
+        texture::TextureKind,
+    },
     sound::buffer::{DataSource, SoundBuffer},
     utils::log::Log,
 };
+use image::ImageError;
 use std::{
+    collections::HashMap,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
@@ -82,14 +122,55 @@ pub type SharedModel = Arc<Mutex<Model>>;
 /// Type alias for Arc<Mutex<SoundBuffer>> to make code less noisy.
 pub type SharedSoundBuffer = Arc<Mutex<SoundBuffer>>;
 
+/// Kind of requested resource, passed to load hooks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    /// Texture of any kind.
+    Texture,
+    /// Model (FBX or RGS).
+    Model,
+    /// Sound buffer (WAV or OGG).
+    SoundBuffer,
+}
+
+/// Load hook allows to redirect or patch resource requests, see module docs. Hooks are
+/// called in order of registration, every method has default empty implementation so
+/// only required ones have to be implemented. Hooks can be called from worker threads
+/// (async texture loading), so they must be thread-safe.
+pub trait ResourceLoadHook: Send + Sync {
+    /// Called before resource is read. Returns new path from which resource should be
+    /// read instead of given one, or None to keep the path. Next hooks receive path which
+    /// was returned by previous hook. Resource keeps requested path, redirection is done
+    /// on every read (including reload), so hooks can redirect it elsewhere later.
+    fn pre_load(&self, _path: &Path, _kind: ResourceKind) -> Option<PathBuf> {
+        None
+    }
+
+    /// Called right after texture was loaded from given path, before it is shared.
+    fn post_load_texture(&self, _path: &Path, _texture: &mut Texture) {}
+
+    /// Called right after model was loaded from given path, before it is shared.
+    fn post_load_model(&self, _path: &Path, _model: &mut Model) {}
+
+    /// Called right after sound buffer was loaded from given path, before it is shared.
+    fn post_load_sound_buffer(&self, _path: &Path, _sound_buffer: &mut SoundBuffer) {}
+}
+
 /// See module docs.
 pub struct ResourceManager {
     textures: Vec<TimedEntry<SharedTexture>>,
     models: Vec<TimedEntry<SharedModel>>,
     sound_buffers: Vec<TimedEntry<SharedSoundBuffer>>,
+    /// Virtual paths of sound buffers keyed by real paths of their files. Sound buffers
+    /// know only real path of their data, but they are requested by virtual paths.
+    sound_buffer_paths: HashMap<PathBuf, PathBuf>,
     /// Path to textures, extensively used for resource files which stores path in weird
     /// format (either relative or absolute) which is obviously not good for engine.
     textures_path: PathBuf,
+    load_hooks: Vec<Arc<dyn ResourceLoadHook>>,
+    vfs: Vfs,
+    import_cache: Option<ImportCache>,
+    lenient_loading: bool,
 }
 
 impl ResourceManager {
@@ -101,7 +182,113 @@ impl ResourceManager {
             textures: Vec::new(),
             models: Vec::new(),
             sound_buffers: Vec::new(),
+            sound_buffer_paths: Default::default(),
             textures_path: PathBuf::from("data/textures/"),
+            load_hooks: Default::default(),
+            vfs: Vfs::new(),
+            import_cache: None,
+            lenient_loading: false,
+        }
+    }
+
+    /// Sets import cache which will be used to store processed results of import of
+    /// non-native resources (currently FBX models only), pass None to disable caching. See
+    /// [import_cache](../../resource/import_cache/index.html) module docs for more info.
+    pub fn set_import_cache(&mut self, import_cache: Option<ImportCache>) {
+        self.import_cache = import_cache;
+    }
+
+    /// Returns current import cache, if any.
+    pub fn import_cache(&self) -> Option<&ImportCache> {
+        self.import_cache.as_ref()
+    }
+
+    /// Enables or disables lenient loading of native scenes used as model resources. In
+    /// lenient mode unknown node kinds and missing fields are skipped with a warning in log
+    /// instead of failing whole load. See [lenient](../../scene/lenient/index.html) module
+    /// docs for more info. Disabled by default.
+    pub fn set_lenient_loading(&mut self, enabled: bool) {
+        self.lenient_loading = enabled;
+    }
+
+    /// Returns true if lenient loading of native scenes is enabled.
+    pub fn is_lenient_loading(&self) -> bool {
+        self.lenient_loading
+    }
+
+    /// Returns shared reference to virtual file system which is used to read resources.
+    pub fn vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+
+    /// Returns mutable reference to virtual file system which is used to read resources,
+    /// use it to mount or unmount sources. Resources which were already loaded are not
+    /// affected, use [reload_resources](struct.ResourceManager.html#method.reload_resources)
+    /// to apply new layering to them.
+    pub fn vfs_mut(&mut self) -> &mut Vfs {
+        &mut self.vfs
+    }
+
+    fn load_texture(
+        vfs: &Vfs,
+        hooks: &[Arc<dyn ResourceLoadHook>],
+        path: &Path,
+        kind: TextureKind,
+    ) -> Result<Texture, ImageError> {
+        let resolved_path = Self::resolve(hooks, path, ResourceKind::Texture);
+        let data = vfs.read(&resolved_path).map_err(ImageError::IoError)?;
+        let mut texture = Texture::load_from_memory(&resolved_path, &data, kind)?;
+        texture.set_path(&path);
+        Ok(texture)
+    }
+
+    /// Registers new load hook, it will be called after every previously registered
+    /// hook. See module docs for more info.
+    pub fn add_load_hook<H: ResourceLoadHook + 'static>(&mut self, hook: H) {
+        self.load_hooks.push(Arc::new(hook));
+    }
+
+    /// Removes every registered load hook. Already loaded resources are not affected.
+    pub fn clear_load_hooks(&mut self) {
+        self.load_hooks.clear();
+    }
+
+    /// Passes given path through `pre_load` of every load hook and returns resulting path.
+    /// Resources are requested and stored by their original paths, this method gives the
+    /// path from which resource is actually read.
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P, kind: ResourceKind) -> PathBuf {
+        Self::resolve(&self.load_hooks, path.as_ref(), kind)
+    }
+
+    fn resolve(hooks: &[Arc<dyn ResourceLoadHook>], path: &Path, kind: ResourceKind) -> PathBuf {
+        let mut path = path.to_owned();
+        for hook in hooks.iter() {
+            if let Some(redirected) = hook.pre_load(&path, kind) {
+                Log::writeln(format!(
+                    "{:?} request {:?} was redirected to {:?} by load hook.",
+                    kind, path, redirected
+                ));
+                path = redirected;
+            }
+        }
+        path
+    }
+
+    fn post_load_texture(hooks: &[Arc<dyn ResourceLoadHook>], path: &Path, texture: &mut Texture) {
+        for hook in hooks.iter() {
+            hook.post_load_texture(path, texture);
+        }
+    }
+
+    fn post_load_model(&self, path: &Path, model: &mut Model) {
+        for hook in self.load_hooks.iter() {
+            hook.post_load_model(path, model);
+        }
+    }
+
+    fn post_load_sound_buffer(&self, path: &Path, sound_buffer: &mut SoundBuffer) {
+        for hook in self.load_hooks.iter() {
+            hook.post_load_sound_buffer(path, sound_buffer);
         }
     }
 
@@ -114,7 +301,9 @@ impl ResourceManager {
         path: P,
         kind: TextureKind,
     ) -> SharedTexture {
-        if let Some(texture) = self.find_texture(path.as_ref()) {
+        let path = path.as_ref().to_owned();
+
+        if let Some(texture) = self.find_texture(&path) {
             return texture;
         }
 
@@ -125,12 +314,14 @@ impl ResourceManager {
         });
         let result = texture.clone();
 
-        let path = PathBuf::from(path.as_ref());
+        let hooks = self.load_hooks.clone();
+        let vfs = self.vfs.clone();
         std::thread::spawn(move || {
             if let Ok(mut texture) = texture.lock() {
                 let time = time::Instant::now();
-                match Texture::load_from_file(&path, kind) {
-                    Ok(raw_texture) => {
+                match Self::load_texture(&vfs, &hooks, &path, kind) {
+                    Ok(mut raw_texture) => {
+                        Self::post_load_texture(&hooks, &path, &mut raw_texture);
                         *texture = raw_texture;
                         Log::writeln(format!(
                             "Texture {:?} is loaded in {:?}!",
@@ -161,24 +352,27 @@ impl ResourceManager {
         path: P,
         kind: TextureKind,
     ) -> Option<SharedTexture> {
-        if let Some(texture) = self.find_texture(path.as_ref()) {
+        let path = path.as_ref();
+
+        if let Some(texture) = self.find_texture(path) {
             return Some(texture);
         }
 
-        match Texture::load_from_file(path.as_ref(), kind) {
-            Ok(texture) => {
+        match Self::load_texture(&self.vfs, &self.load_hooks, path, kind) {
+            Ok(mut texture) => {
+                Self::post_load_texture(&self.load_hooks, path, &mut texture);
                 let shared_texture = Arc::new(Mutex::new(texture));
                 self.textures.push(TimedEntry {
                     value: shared_texture.clone(),
                     time_to_live: Self::MAX_RESOURCE_TTL,
                 });
-                Log::writeln(format!("Texture {} is loaded!", path.as_ref().display()));
+                Log::writeln(format!("Texture {} is loaded!", path.display()));
                 Some(shared_texture)
             }
             Err(e) => {
                 Log::writeln(format!(
                     "Unable to load texture {}! Reason {}",
-                    path.as_ref().display(),
+                    path.display(),
                     e
                 ));
                 None
@@ -186,6 +380,89 @@ impl ResourceManager {
         }
     }
 
+    /// Returns packed occlusion-roughness-metallic texture for given separate maps. Packed
+    /// texture is loaded from `packed_path` if it exists, otherwise source maps are packed
+    /// and result is saved to `packed_path` (to a directory mount point which covers it,
+    /// see `Vfs::write_path`), so packing is done only once at import. Missing
+    /// maps are replaced with defaults, see
+    /// [channel_packing](../../resource/channel_packing/index.html) module docs. This method
+    /// is **blocking**. On failure it returns None and prints failure reason to log.
+    pub fn request_orm_texture<P: AsRef<Path>>(
+        &mut self,
+        packed_path: P,
+        occlusion: Option<&Path>,
+        roughness: Option<&Path>,
+        metallic: Option<&Path>,
+    ) -> Option<SharedTexture> {
+        let packed_path = packed_path.as_ref();
+        let resolved_packed_path = self.resolve_path(packed_path, ResourceKind::Texture);
+
+        if self.find_texture(packed_path).is_some() || self.vfs.exists(&resolved_packed_path) {
+            return self.request_texture(packed_path, TextureKind::RGB8);
+        }
+
+        let mut sources = Vec::new();
+        for path in [occlusion, roughness, metallic].iter() {
+            sources.push(match path {
+                Some(path) => {
+                    match Self::load_texture(&self.vfs, &self.load_hooks, path, TextureKind::R8) {
+                        Ok(texture) => Some(texture),
+                        Err(e) => {
+                            Log::writeln(format!(
+                                "Unable to load texture {:?} for packing! Reason {}",
+                                path, e
+                            ));
+                            return None;
+                        }
+                    }
+                }
+                None => None,
+            });
+        }
+
+        let mut texture = match channel_packing::pack_orm(
+            sources[0].as_ref(),
+            sources[1].as_ref(),
+            sources[2].as_ref(),
+        ) {
+            Ok(texture) => texture,
+            Err(e) => {
+                Log::writeln(format!(
+                    "Unable to pack texture {:?}! Reason {}",
+                    packed_path, e
+                ));
+                return None;
+            }
+        };
+
+        // Save to real location where VFS will find it on next request, but keep requested
+        // virtual path in resource.
+        let write_path = self.vfs.write_path(&resolved_packed_path);
+        texture.set_path(&write_path);
+        let saved = write_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(ImageError::IoError)
+            .and_then(|_| texture.save());
+        if let Err(e) = saved {
+            Log::writeln(format!(
+                "Unable to save packed texture {:?}, it will be packed again on next \
+                 request! Reason {}",
+                write_path, e
+            ));
+        }
+        texture.set_path(&packed_path);
+
+        Self::post_load_texture(&self.load_hooks, packed_path, &mut texture);
+        let shared_texture = Arc::new(Mutex::new(texture));
+        self.textures.push(TimedEntry {
+            value: shared_texture.clone(),
+            time_to_live: Self::MAX_RESOURCE_TTL,
+        });
+        Log::writeln(format!("Texture {} is packed!", packed_path.display()));
+        Some(shared_texture)
+    }
+
     /// Tries to load new model resource from given path or get instance of existing, if any.
     /// This method is **blocking**, so it will block current thread until model is loading
     /// On failure it returns None and prints failure reason to log.
@@ -195,26 +472,28 @@ impl ResourceManager {
     /// Currently only FBX (common format in game industry for storing complex 3d models)
     /// and RGS (native rusty-editor format) formats are supported.
     pub fn request_model<P: AsRef<Path>>(&mut self, path: P) -> Option<SharedModel> {
-        if let Some(model) = self.find_model(path.as_ref()) {
+        let path = path.as_ref();
+
+        if let Some(model) = self.find_model(path) {
             return Some(model);
         }
 
-        match Model::load(path.as_ref(), self) {
-            Ok(model) => {
+        match Model::load(path, self) {
+            Ok(mut model) => {
+                self.post_load_model(path, &mut model);
                 let model = Arc::new(Mutex::new(model));
                 model.lock().unwrap().self_weak_ref = Some(Arc::downgrade(&model));
                 self.models.push(TimedEntry {
                     value: model.clone(),
                     time_to_live: Self::MAX_RESOURCE_TTL,
                 });
-                Log::writeln(format!("Model {} is loaded!", path.as_ref().display()));
+                Log::writeln(format!("Model {} is loaded!", path.display()));
                 Some(model)
             }
             Err(e) => {
                 Log::writeln(format!(
                     "Unable to load model from {:?}! Reason {:?}",
-                    path.as_ref(),
-                    e
+                    path, e
                 ));
                 None
             }
@@ -225,6 +504,9 @@ impl ResourceManager {
     /// This method is **blocking**, so it will block current thread until sound buffer is
     /// loading. On failure it returns None and prints failure reason to log.
     ///
+    /// Sound buffers can be loaded only from directory mount points of VFS or from real
+    /// file system, but not from archives or memory.
+    ///
     /// # Supported formats
     ///
     /// Currently only WAV (uncompressed) and OGG are supported.
@@ -233,11 +515,27 @@ impl ResourceManager {
         path: P,
         stream: bool,
     ) -> Option<SharedSoundBuffer> {
-        if let Some(sound_buffer) = self.find_sound_buffer(path.as_ref()) {
+        let path = path.as_ref();
+
+        if let Some(sound_buffer) = self.find_sound_buffer(path) {
             return Some(sound_buffer);
         }
 
-        match DataSource::from_file(path.as_ref()) {
+        // Sound buffers can be streamed, so they're always loaded from real files.
+        let resolved_path = self.resolve_path(path, ResourceKind::SoundBuffer);
+        let real_path = match self.vfs.real_path(&resolved_path) {
+            Some(real_path) => real_path,
+            None => {
+                Log::writeln(format!(
+                    "Unable to load sound buffer {:?}, sound buffers can be loaded only \
+                     from a directory mount point!",
+                    resolved_path
+                ));
+                return None;
+            }
+        };
+
+        match DataSource::from_file(&real_path) {
             Ok(source) => {
                 let buffer = if stream {
                     SoundBuffer::new_streaming(source)
@@ -246,20 +544,20 @@ impl ResourceManager {
                 };
                 match buffer {
                     Ok(sound_buffer) => {
+                        self.post_load_sound_buffer(path, &mut sound_buffer.lock().unwrap());
+                        self.sound_buffer_paths
+                            .insert(real_path, vfs::normalize_path(path));
                         self.sound_buffers.push(TimedEntry {
                             value: sound_buffer.clone(),
                             time_to_live: Self::MAX_RESOURCE_TTL,
                         });
-                        Log::writeln(format!(
-                            "Sound buffer {} is loaded!",
-                            path.as_ref().display()
-                        ));
+                        Log::writeln(format!("Sound buffer {} is loaded!", path.display()));
                         Some(sound_buffer)
                     }
                     Err(_) => {
                         Log::writeln(format!(
                             "Unable to load sound buffer from {}!",
-                            path.as_ref().display()
+                            path.display()
                         ));
                         None
                     }
@@ -278,6 +576,22 @@ impl ResourceManager {
         &self.textures
     }
 
+    /// Restores texture loaded from a save file. Save files contain only paths and kinds of
+    /// textures, this method loads texture by them (or gets instance of existing). Returns
+    /// None if texture has no path (procedural texture) or it cannot be loaded.
+    pub fn restore_texture(&mut self, shallow_texture: &SharedTexture) -> Option<SharedTexture> {
+        let (path, kind) = {
+            let texture = shallow_texture.lock().unwrap();
+            (texture.path.clone(), texture.kind)
+        };
+        if path.as_os_str().is_empty() {
+            Log::writeln("Unable to restore texture without path!".to_owned());
+            None
+        } else {
+            self.request_texture(path, kind)
+        }
+    }
+
     /// Tries to find texture by its path. Returns None if no such texture was found.
     pub fn find_texture<P: AsRef<Path>>(&self, path: P) -> Option<SharedTexture> {
         for texture_entry in self.textures.iter() {
@@ -310,11 +624,13 @@ impl ResourceManager {
         &self.sound_buffers
     }
 
-    /// Tries to find sound buffer by its path. Returns None if no such sound buffer was found.
+    /// Tries to find sound buffer by its virtual path. Returns None if no such sound buffer
+    /// was found.
     pub fn find_sound_buffer<P: AsRef<Path>>(&self, path: P) -> Option<SharedSoundBuffer> {
+        let path = vfs::normalize_path(path);
         for sound_buffer in self.sound_buffers.iter() {
             if let Some(ext_path) = sound_buffer.lock().unwrap().external_data_path() {
-                if ext_path == path.as_ref() {
+                if self.sound_buffer_paths.get(&ext_path) == Some(&path) {
                     return Some(sound_buffer.value.clone());
                 }
             }
@@ -396,6 +712,12 @@ impl ResourceManager {
             }
             retain
         });
+        let sound_buffers = &self.sound_buffers;
+        self.sound_buffer_paths.retain(|real_path, _| {
+            sound_buffers.iter().any(|buffer| {
+                buffer.lock().unwrap().external_data_path().as_ref() == Some(real_path)
+            })
+        });
     }
 
     pub(in crate) fn update(&mut self, dt: f32) {
@@ -407,17 +729,22 @@ impl ResourceManager {
     fn reload_textures(&mut self) {
         for old_texture in self.textures.iter() {
             let mut old_texture = old_texture.lock().unwrap();
-            let new_texture =
-                match Texture::load_from_file(old_texture.path.as_path(), old_texture.kind) {
-                    Ok(texture) => texture,
-                    Err(e) => {
-                        Log::writeln(format!(
-                            "Unable to reload {:?} texture! Reason: {}",
-                            old_texture.path, e
-                        ));
-                        continue;
-                    }
-                };
+            let mut new_texture = match Self::load_texture(
+                &self.vfs,
+                &self.load_hooks,
+                old_texture.path.as_path(),
+                old_texture.kind,
+            ) {
+                Ok(texture) => texture,
+                Err(e) => {
+                    Log::writeln(format!(
+                        "Unable to reload {:?} texture! Reason: {}",
+                        old_texture.path, e
+                    ));
+                    continue;
+                }
+            };
+            Self::post_load_texture(&self.load_hooks, &old_texture.path, &mut new_texture);
             old_texture.path = Default::default();
             *old_texture = new_texture;
         }
@@ -437,6 +764,7 @@ impl ResourceManager {
                     continue;
                 }
             };
+            self.post_load_model(&old_model.path, &mut new_model);
             new_model.self_weak_ref = Some(Arc::downgrade(&old_model_arc));
             old_model.path = Default::default();
             *old_model = new_model;
@@ -444,25 +772,41 @@ impl ResourceManager {
     }
 
     fn reload_sound_buffers(&mut self) {
+        let mut reloaded_paths = HashMap::new();
         for old_sound_buffer in self.sound_buffers() {
             let mut old_sound_buffer = old_sound_buffer.lock().unwrap();
             if let Some(ext_path) = old_sound_buffer.external_data_path() {
+                // Resolve virtual path again, mount points and load hooks could be changed
+                // since the buffer was loaded.
+                let virtual_path = self.sound_buffer_paths.get(&ext_path).cloned();
+                let ext_path = virtual_path
+                    .as_ref()
+                    .and_then(|virtual_path| {
+                        self.vfs
+                            .real_path(self.resolve_path(virtual_path, ResourceKind::SoundBuffer))
+                    })
+                    .unwrap_or(ext_path);
                 if let Ok(data_source) = DataSource::from_file(ext_path.as_path()) {
                     let new_sound_buffer = match *old_sound_buffer {
                         SoundBuffer::Generic(_) => SoundBuffer::raw_generic(data_source),
                         SoundBuffer::Streaming(_) => SoundBuffer::raw_streaming(data_source),
                     };
-                    let new_sound_buffer = match new_sound_buffer {
+                    let mut new_sound_buffer = match new_sound_buffer {
                         Ok(new_sound_buffer) => new_sound_buffer,
                         Err(_) => {
                             Log::writeln(format!("Unable to reload {:?} sound buffer!", ext_path));
                             continue;
                         }
                     };
+                    self.post_load_sound_buffer(&ext_path, &mut new_sound_buffer);
                     *old_sound_buffer = new_sound_buffer;
+                    if let Some(virtual_path) = virtual_path {
+                        reloaded_paths.insert(ext_path, virtual_path);
+                    }
                 }
             }
         }
+        self.sound_buffer_paths.extend(reloaded_paths);
     }
 
     /// Reloads all loaded resources. Normally it should never be called, because it is **very** heavy
@@ -481,6 +825,7 @@ impl Visit for ResourceManager {
         self.textures.visit("Textures", visitor)?;
         self.models.visit("Models", visitor)?;
         self.sound_buffers.visit("SoundBuffers", visitor)?;
+        let _ = self.sound_buffer_paths.visit("SoundBufferPaths", visitor);
 
         visitor.leave_region()
     }
@@ -0,0 +1,121 @@
+//! Safe area of the screen for user interface.
+//!
+//! TVs may hide a few percent of the image at the edges (overscan), some phones have
+//! rounded corners and notches. Safe area defines insets from each edge of the frame
+//! where important HUD elements must not be placed. Insets are defined as fractions of
+//! frame size, so they're resolution-independent. Engine keeps current safe area and
+//! can apply it as margin to root widget of HUD.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{core::math::vec2::Vec2, engine::safe_area::SafeArea};
+//!
+//! // Title-safe area for TVs, 5% from each edge.
+//! let safe_area = SafeArea::title_safe();
+//! let insets = safe_area.insets(Vec2::new(1920.0, 1080.0));
+//! assert_eq!(insets.left, 96.0);
+//! assert_eq!(insets.top, 54.0);
+//! ```
+
+use crate::{
+    core::math::{vec2::Vec2, Rect},
+    gui::Thickness,
+};
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SafeArea {
+    /// Inset from left edge as fraction of frame width.
+    pub left: f32,
+    /// Inset from top edge as fraction of frame height.
+    pub top: f32,
+    /// Inset from right edge as fraction of frame width.
+    pub right: f32,
+    /// Inset from bottom edge as fraction of frame height.
+    pub bottom: f32,
+}
+
+impl SafeArea {
+    /// Largest inset for a single edge, insets are clamped so safe area never collapses.
+    pub const MAX_INSET: f32 = 0.45;
+
+    /// Creates safe area with given insets, see fields docs.
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        let clamp = |v: f32| v.max(0.0).min(Self::MAX_INSET);
+        Self {
+            left: clamp(left),
+            top: clamp(top),
+            right: clamp(right),
+            bottom: clamp(bottom),
+        }
+    }
+
+    /// Creates safe area with same inset on every edge.
+    pub fn uniform(inset: f32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+
+    /// Safe area for essential information (text, HUD) on TVs - 90% of the frame.
+    pub fn title_safe() -> Self {
+        Self::uniform(0.05)
+    }
+
+    /// Safe area for important action on TVs - 93% of the frame.
+    pub fn action_safe() -> Self {
+        Self::uniform(0.035)
+    }
+
+    /// Returns insets in units of given frame size, it can be in pixels or in logical
+    /// UI units.
+    pub fn insets(&self, frame_size: Vec2) -> Thickness {
+        Thickness {
+            left: self.left * frame_size.x,
+            top: self.top * frame_size.y,
+            right: self.right * frame_size.x,
+            bottom: self.bottom * frame_size.y,
+        }
+    }
+
+    /// Returns safe rectangle of frame with given size, origin is at top left corner.
+    pub fn rect(&self, frame_size: Vec2) -> Rect<f32> {
+        let insets = self.insets(frame_size);
+        Rect::new(
+            insets.left,
+            insets.top,
+            frame_size.x - insets.left - insets.right,
+            frame_size.y - insets.top - insets.bottom,
+        )
+    }
+
+    /// Returns true if given point is inside safe rectangle of frame with given size.
+    pub fn contains(&self, frame_size: Vec2, point: Vec2) -> bool {
+        let rect = self.rect(frame_size);
+        point.x >= rect.x
+            && point.y >= rect.y
+            && point.x <= rect.x + rect.w
+            && point.y <= rect.y + rect.h
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{core::math::vec2::Vec2, engine::safe_area::SafeArea};
+
+    #[test]
+    fn test_safe_rect() {
+        let size = Vec2::new(1000.0, 500.0);
+        let safe_area = SafeArea::new(0.1, 0.0, 0.2, 0.9);
+        // Bottom inset is clamped.
+        assert_eq!(safe_area.bottom, SafeArea::MAX_INSET);
+
+        let rect = safe_area.rect(size);
+        assert_eq!(rect.x, 100.0);
+        assert_eq!(rect.w, 700.0);
+        assert!((rect.h - 275.0).abs() < 0.001);
+
+        assert!(safe_area.contains(size, Vec2::new(500.0, 100.0)));
+        assert!(!safe_area.contains(size, Vec2::new(50.0, 100.0)));
+        assert!(SafeArea::default().contains(size, Vec2::new(0.0, 0.0)));
+    }
+}
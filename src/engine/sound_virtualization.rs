@@ -0,0 +1,333 @@
+//! Distance-based virtualization of sound sources.
+//!
+//! Every playing source costs mixer time even when it is too far to be heard. Levels with
+//! hundreds of ambient emitters (torches, rivers, machinery) waste most of mixing on such
+//! sources. Virtualizer pauses spatial sources which are farther from listener than
+//! virtualization distance, so they are not decoded and mixed anymore, but keeps tracking
+//! their playback position. When listener comes back in range, source is resumed from the
+//! position where it would be if it was playing all this time, so virtualization can't be
+//! heard. Non-looping source which ended while being virtual is stopped.
+//!
+//! Number of real sources can also be limited, in this case only the closest sources are
+//! played and the rest are virtualized.
+//!
+//! Virtualizer is disabled by default and it touches only spatial sources, generic sources
+//! (music, UI sounds) are never virtualized.
+//!
+//! # Notes
+//!
+//! Virtual source has `Paused` status in sound context. Stopping it as usual is fine, but
+//! to pause virtual source explicitly it must be released from virtualizer first by
+//! [`SoundVirtualizer::forget`], otherwise it will be resumed when listener comes close.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::engine::sound_virtualization::VirtualizationSettings;
+//! # use rg3d::engine::sound_virtualization::SoundVirtualizer;
+//! # let mut virtualizer = SoundVirtualizer::default();
+//!
+//! // Usually it is `engine.sound_virtualizer`.
+//! virtualizer.set_settings(VirtualizationSettings {
+//!     enabled: true,
+//!     distance: 40.0,
+//!     max_real_sources: Some(32),
+//!     ..Default::default()
+//! });
+//! ```
+
+use crate::{
+    core::pool::Handle,
+    sound::{
+        context::Context,
+        source::{SoundSource, Status},
+    },
+};
+use std::{cmp::Ordering, collections::HashMap, time::Duration};
+
+/// Parameters of sound virtualization.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VirtualizationSettings {
+    /// Virtualization is done only if this flag is set.
+    pub enabled: bool,
+    /// Spatial sources farther than this distance from listener become virtual.
+    pub distance: f32,
+    /// Fraction of distance that listener must come closer to virtual source to make it
+    /// real again. Prevents sources from flickering between states at the border.
+    pub hysteresis: f32,
+    /// Maximum number of real spatial sources, farthest sources above the limit become
+    /// virtual. `None` - no limit.
+    pub max_real_sources: Option<usize>,
+}
+
+impl Default for VirtualizationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: 50.0,
+            hysteresis: 0.1,
+            max_real_sources: None,
+        }
+    }
+}
+
+/// Returns new playback position of virtual source after `dt` seconds or None if
+/// non-looping source reached its end.
+fn advance_playback(time: f32, dt: f32, pitch: f32, duration: f32, looping: bool) -> Option<f32> {
+    let time = time + dt * pitch;
+    if time < duration {
+        Some(time)
+    } else if looping && duration > 0.0 {
+        Some(time % duration)
+    } else {
+        None
+    }
+}
+
+/// Virtualizes far spatial sources, see module docs.
+#[derive(Clone, Debug, Default)]
+pub struct SoundVirtualizer {
+    settings: VirtualizationSettings,
+    // Virtual sources with their tracked playback positions in seconds.
+    virtual_sources: HashMap<Handle<SoundSource>, f32>,
+}
+
+impl SoundVirtualizer {
+    /// Returns parameters of virtualization.
+    pub fn settings(&self) -> &VirtualizationSettings {
+        &self.settings
+    }
+
+    /// Sets parameters of virtualization. Virtual sources are resumed on next update if
+    /// virtualization was disabled.
+    pub fn set_settings(&mut self, settings: VirtualizationSettings) {
+        self.settings = settings;
+    }
+
+    /// Returns true if given source is virtual at the moment.
+    pub fn is_virtual(&self, source: Handle<SoundSource>) -> bool {
+        self.virtual_sources.contains_key(&source)
+    }
+
+    /// Returns amount of virtual sources.
+    pub fn virtual_source_count(&self) -> usize {
+        self.virtual_sources.len()
+    }
+
+    /// Stops tracking of given virtual source, it stays paused at position where it was
+    /// virtualized.
+    pub fn forget(&mut self, source: Handle<SoundSource>) {
+        self.virtual_sources.remove(&source);
+    }
+
+    /// Advances playback of virtual sources and moves sources between real and virtual
+    /// states. Engine calls it automatically, unless sound is paused.
+    pub fn update(&mut self, context: &mut Context, dt: f32) {
+        let listener = context.listener().position();
+
+        // Advance virtual sources, forget the ones that were stopped, removed or played by
+        // user, and stop the ones that ended.
+        let mut ended = Vec::new();
+        self.virtual_sources.retain(|&handle, time| {
+            if !context.sources().is_valid_handle(handle) {
+                return false;
+            }
+            let source = context.source(handle).generic();
+            if source.status() != Status::Paused {
+                return false;
+            }
+            let duration = source.buffer().map_or(0.0, |buffer| {
+                buffer.lock().unwrap().generic().duration().as_secs_f32()
+            });
+            match advance_playback(
+                *time,
+                dt,
+                source.pitch() as f32,
+                duration,
+                source.is_looping(),
+            ) {
+                Some(new_time) => {
+                    *time = new_time;
+                    true
+                }
+                None => {
+                    ended.push(handle);
+                    false
+                }
+            }
+        });
+        for handle in ended {
+            let _ = context.source_mut(handle).generic_mut().stop();
+        }
+
+        // Collect candidates - playing or virtual spatial sources with distances.
+        let mut candidates = context
+            .sources()
+            .pair_iter()
+            .filter_map(|(handle, source)| match source {
+                SoundSource::Spatial(spatial)
+                    if spatial.generic().status() == Status::Playing
+                        || self.virtual_sources.contains_key(&handle) =>
+                {
+                    Some((handle, spatial.position().distance(&listener)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+
+        let settings = self.settings;
+        let mut real_count = 0;
+        for (handle, distance) in candidates {
+            let is_virtual = self.virtual_sources.contains_key(&handle);
+            let in_range = !settings.enabled
+                || if is_virtual {
+                    distance < settings.distance * (1.0 - settings.hysteresis)
+                } else {
+                    distance <= settings.distance
+                };
+            let has_slot = !settings.enabled
+                || settings
+                    .max_real_sources
+                    .map_or(true, |max| real_count < max);
+
+            let source = context.source_mut(handle).generic_mut();
+            if in_range && has_slot {
+                real_count += 1;
+                if let Some(time) = self.virtual_sources.remove(&handle) {
+                    source.set_playback_time(Duration::from_secs_f32(time));
+                    source.play();
+                }
+            } else if !is_virtual {
+                self.virtual_sources
+                    .insert(handle, source.playback_time().as_secs_f32());
+                source.pause();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::{math::vec3::Vec3, pool::Handle},
+        engine::sound_virtualization::{
+            advance_playback, SoundVirtualizer, VirtualizationSettings,
+        },
+        sound::{
+            buffer::{DataSource, SoundBuffer},
+            context::Context,
+            source::{
+                generic::GenericSourceBuilder, spatial::SpatialSourceBuilder, SoundSource, Status,
+            },
+        },
+    };
+    use std::sync::{Arc, Mutex};
+
+    // One second of silence, 8 kHz mono 16-bit.
+    fn silent_wav() -> Vec<u8> {
+        let sample_rate = 8000u32;
+        let data_size = sample_rate * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        wav.resize(wav.len() + data_size as usize, 0);
+        wav
+    }
+
+    fn add_source(context: &mut Context, buffer: Arc<Mutex<SoundBuffer>>) -> Handle<SoundSource> {
+        let generic = GenericSourceBuilder::new(buffer)
+            .with_looping(true)
+            .with_status(Status::Playing)
+            .build()
+            .unwrap();
+        context.add_source(SpatialSourceBuilder::new(generic).build_source())
+    }
+
+    fn set_distance(context: &mut Context, source: Handle<SoundSource>, distance: f32) {
+        if let SoundSource::Spatial(spatial) = context.source_mut(source) {
+            spatial.set_position(&Vec3::new(distance, 0.0, 0.0));
+        }
+    }
+
+    fn status(context: &Context, source: Handle<SoundSource>) -> Status {
+        context.source(source).generic().status()
+    }
+
+    #[test]
+    fn test_virtualize_and_resume() {
+        // Sound context needs an output device, skip on machines without one.
+        let context = match Context::new() {
+            Ok(context) => context,
+            Err(_) => return,
+        };
+        let mut context = context.lock().unwrap();
+        let buffer = SoundBuffer::new_generic(DataSource::from_memory(silent_wav())).unwrap();
+
+        let a = add_source(&mut context, buffer.clone());
+        let b = add_source(&mut context, buffer);
+        set_distance(&mut context, a, 20.0);
+        set_distance(&mut context, b, 5.0);
+
+        let mut virtualizer = SoundVirtualizer::default();
+        virtualizer.set_settings(VirtualizationSettings {
+            enabled: true,
+            distance: 10.0,
+            hysteresis: 0.2,
+            max_real_sources: None,
+        });
+
+        virtualizer.update(&mut context, 0.1);
+        assert!(virtualizer.is_virtual(a));
+        assert_eq!(status(&context, a), Status::Paused);
+        assert!(!virtualizer.is_virtual(b));
+        assert_eq!(status(&context, b), Status::Playing);
+
+        // Inside of virtualization distance, but not closer than hysteresis allows.
+        set_distance(&mut context, a, 9.0);
+        virtualizer.update(&mut context, 0.1);
+        assert!(virtualizer.is_virtual(a));
+
+        set_distance(&mut context, a, 7.0);
+        virtualizer.update(&mut context, 0.1);
+        assert!(!virtualizer.is_virtual(a));
+        assert_eq!(status(&context, a), Status::Playing);
+
+        // Only the closest source is real when limit is reached.
+        virtualizer.set_settings(VirtualizationSettings {
+            max_real_sources: Some(1),
+            ..*virtualizer.settings()
+        });
+        virtualizer.update(&mut context, 0.1);
+        assert!(virtualizer.is_virtual(a));
+        assert!(!virtualizer.is_virtual(b));
+
+        set_distance(&mut context, a, 1.0);
+        virtualizer.update(&mut context, 0.1);
+        assert!(!virtualizer.is_virtual(a));
+        assert_eq!(status(&context, a), Status::Playing);
+        assert!(virtualizer.is_virtual(b));
+        assert_eq!(status(&context, b), Status::Paused);
+        assert_eq!(virtualizer.virtual_source_count(), 1);
+    }
+
+    #[test]
+    fn test_advance_playback() {
+        assert_eq!(advance_playback(1.0, 0.5, 1.0, 2.0, false), Some(1.5));
+        assert_eq!(advance_playback(1.0, 0.5, 2.0, 2.0, true), Some(0.0));
+        assert_eq!(advance_playback(1.5, 1.0, 1.0, 2.0, true), Some(0.5));
+        assert_eq!(advance_playback(1.5, 1.0, 1.0, 2.0, false), None);
+        // Source without buffer can't loop.
+        assert_eq!(advance_playback(0.0, 1.0, 1.0, 0.0, true), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Scale-independent coordinate system for user interface.
+//!
+//! By default one UI unit is one physical pixel of the window, so HUD which was designed
+//! at 1080p becomes twice smaller on 4K monitor. UI scaling decouples *logical* UI units
+//! from framebuffer resolution: user interface is laid out in logical units and then
+//! stretched to the whole framebuffer by the renderer.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::engine::ui_scaling::{UiScaleMode, UiScaling};
+//! use rg3d::core::math::vec2::Vec2;
+//!
+//! // HUD is designed at 1920x1080.
+//! let scaling = UiScaling::new(UiScaleMode::Reference {
+//!     width: 1920.0,
+//!     height: 1080.0,
+//! });
+//! // At 4K it has the same layout, but every unit is two pixels.
+//! assert_eq!(scaling.scale(Vec2::new(3840.0, 2160.0), 1.0), 2.0);
+//! ```
+
+use crate::core::math::vec2::Vec2;
+
+/// Defines how many physical pixels are in a single logical UI unit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UiScaleMode {
+    /// One UI unit is exactly one physical pixel, UI shrinks on high resolutions.
+    /// This is default mode.
+    Physical,
+    /// One UI unit is one pixel multiplied by DPI scale factor of the window, this is
+    /// how most desktop applications are scaled.
+    Dpi,
+    /// UI is designed at given reference resolution. Scale is chosen so reference
+    /// resolution fits into frame, so on wider (or taller) screens UI gets extra space
+    /// at the sides instead of being stretched. DPI scale factor is ignored in this mode.
+    Reference {
+        /// Width of reference resolution in pixels.
+        width: f32,
+        /// Height of reference resolution in pixels.
+        height: f32,
+    },
+}
+
+impl Default for UiScaleMode {
+    fn default() -> Self {
+        UiScaleMode::Physical
+    }
+}
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UiScaling {
+    mode: UiScaleMode,
+    user_scale: f32,
+}
+
+impl Default for UiScaling {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl UiScaling {
+    /// Smallest possible scale, prevents division by zero on degenerated frame sizes.
+    pub const MIN_SCALE: f32 = 0.01;
+
+    /// Creates new scaling with given mode and user scale of 1.0.
+    pub fn new(mode: UiScaleMode) -> Self {
+        Self {
+            mode,
+            user_scale: 1.0,
+        }
+    }
+
+    /// Sets additional scale multiplier which is applied on top of scale mode. Useful
+    /// for "UI size" option in game settings.
+    pub fn with_user_scale(mut self, user_scale: f32) -> Self {
+        self.set_user_scale(user_scale);
+        self
+    }
+
+    /// Sets new scale mode.
+    pub fn set_mode(&mut self, mode: UiScaleMode) {
+        self.mode = mode;
+    }
+
+    /// Returns current scale mode.
+    pub fn mode(&self) -> UiScaleMode {
+        self.mode
+    }
+
+    /// Sets additional scale multiplier, see [with_user_scale](struct.UiScaling.html#method.with_user_scale).
+    pub fn set_user_scale(&mut self, user_scale: f32) {
+        self.user_scale = user_scale.max(Self::MIN_SCALE);
+    }
+
+    /// Returns additional scale multiplier.
+    pub fn user_scale(&self) -> f32 {
+        self.user_scale
+    }
+
+    /// Returns amount of physical pixels in a single UI unit for given frame size (in
+    /// pixels) and DPI scale factor of the window.
+    pub fn scale(&self, frame_size: Vec2, dpi_factor: f32) -> f32 {
+        let base = match self.mode {
+            UiScaleMode::Physical => 1.0,
+            UiScaleMode::Dpi => dpi_factor,
+            UiScaleMode::Reference { width, height } => {
+                if width > 0.0 && height > 0.0 {
+                    (frame_size.x / width).min(frame_size.y / height)
+                } else {
+                    1.0
+                }
+            }
+        };
+        (base * self.user_scale).max(Self::MIN_SCALE)
+    }
+
+    /// Returns size of frame in logical UI units.
+    pub fn logical_size(&self, frame_size: Vec2, dpi_factor: f32) -> Vec2 {
+        frame_size.scale(1.0 / self.scale(frame_size, dpi_factor))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec2::Vec2,
+        engine::ui_scaling::{UiScaleMode, UiScaling},
+    };
+
+    #[test]
+    fn test_physical_ignores_dpi() {
+        let scaling = UiScaling::default();
+        assert_eq!(scaling.scale(Vec2::new(3840.0, 2160.0), 2.0), 1.0);
+    }
+
+    #[test]
+    fn test_dpi() {
+        let scaling = UiScaling::new(UiScaleMode::Dpi).with_user_scale(1.5);
+        assert_eq!(scaling.scale(Vec2::new(1920.0, 1080.0), 2.0), 3.0);
+    }
+
+    #[test]
+    fn test_reference_resolution() {
+        let scaling = UiScaling::new(UiScaleMode::Reference {
+            width: 1920.0,
+            height: 1080.0,
+        });
+        // Same layout at 4K.
+        assert_eq!(
+            scaling.logical_size(Vec2::new(3840.0, 2160.0), 1.0),
+            Vec2::new(1920.0, 1080.0)
+        );
+        // Ultrawide keeps height, gets extra width.
+        let size = scaling.logical_size(Vec2::new(3440.0, 1440.0), 1.0);
+        assert!((size.y - 1080.0).abs() < 0.001);
+        assert!(size.x > 1920.0);
+    }
+}
@@ -0,0 +1,533 @@
+//! Virtual file system (VFS) is used by resource manager to read resources.
+//!
+//! VFS consists of mount points, each mount point maps some virtual path prefix to a source
+//! of files: directory, archive or in-memory blobs. When resource is requested, mount points
+//! are checked in order of their priorities (highest first, on equal priorities latest
+//! mounted goes first) and first source which contains the file is used. This allows to
+//! layer content: base game assets can be overridden by DLC which in its turn can be
+//! overridden by mods, without any changes in paths which are stored in scenes and models.
+//!
+//! If no mount point contains requested file, VFS falls back to real file system, so VFS
+//! without mount points behaves exactly as plain file system.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::engine::vfs::{ArchiveSource, DirectorySource, MemorySource, Vfs};
+//!
+//! let mut vfs = Vfs::new();
+//! vfs.mount("data", DirectorySource::new("base/data"), 0);
+//! vfs.mount("data", ArchiveSource::open("dlc1.pak").unwrap(), 10);
+//! vfs.mount("data", DirectorySource::new("mods/my_mod/data"), 100);
+//! vfs.mount(
+//!     "data/textures",
+//!     MemorySource::new().with_file("debug.png", vec![/* png bytes */]),
+//!     1000,
+//! );
+//! let bytes = vfs.read("data/textures/crate.png").unwrap();
+//! ```
+//!
+//! # Archives
+//!
+//! Archives use simple uncompressed format: `RG3DPACK` magic, version, amount of entries,
+//! table of entries (path, offset, size) and then contents of files. Archives can be made
+//! by [ArchiveSource::pack_directory](struct.ArchiveSource.html#method.pack_directory).
+
+use crate::utils::log::Log;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::File,
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+/// Normalizes virtual path: removes `.` components, resolves `..` and converts it to
+/// relative path, so `./data/../data/a.png` and `data/a.png` are the same file.
+pub fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    // Paths from some model formats use Windows separators.
+    let path = path.as_ref().to_string_lossy().replace('\\', "/");
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => (),
+        }
+    }
+    normalized
+}
+
+/// Source of files for mount point. Paths passed to source methods are normalized and
+/// relative to mount point.
+pub trait MountSource: Debug + Send + Sync {
+    /// Reads whole file. Returns None if source does not contain such file.
+    fn read(&self, path: &Path) -> Option<io::Result<Vec<u8>>>;
+
+    /// Returns true if source contains given file.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Returns path to the file in real file system, if any. Some resources (streaming
+    /// sounds and native scenes) can be loaded only from real files.
+    fn real_path(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Returns path in real file system where new file with given path should be written,
+    /// None if source is read-only.
+    fn write_path(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Source which takes files from a directory of real file system.
+#[derive(Debug)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    /// Creates new source which takes files from given directory.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_owned(),
+        }
+    }
+}
+
+impl MountSource for DirectorySource {
+    fn read(&self, path: &Path) -> Option<io::Result<Vec<u8>>> {
+        let path = self.root.join(path);
+        if path.is_file() {
+            Some(std::fs::read(path))
+        } else {
+            None
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn real_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = self.root.join(path);
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    fn write_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(self.root.join(path))
+    }
+}
+
+/// Source which holds files in memory. Useful for tests and procedurally generated content.
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    files: HashMap<PathBuf, Arc<Vec<u8>>>,
+}
+
+impl MemorySource {
+    /// Creates new empty source.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds new file to source, replaces existing file with same path.
+    pub fn with_file<P: AsRef<Path>>(mut self, path: P, data: Vec<u8>) -> Self {
+        self.add_file(path, data);
+        self
+    }
+
+    /// Adds new file to source, replaces existing file with same path.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P, data: Vec<u8>) {
+        self.files.insert(normalize_path(path), Arc::new(data));
+    }
+}
+
+impl MountSource for MemorySource {
+    fn read(&self, path: &Path) -> Option<io::Result<Vec<u8>>> {
+        self.files.get(path).map(|data| Ok(data.as_ref().clone()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+}
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"RG3DPACK";
+const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Copy, Clone)]
+struct ArchiveEntry {
+    offset: u64,
+    size: u64,
+}
+
+impl ArchiveEntry {
+    /// Checks that entry lies within archive of given length, so its size can be trusted
+    /// when allocating memory for its contents.
+    fn check(&self, archive_length: u64) -> io::Result<()> {
+        match self.offset.checked_add(self.size) {
+            Some(end) if end <= archive_length => Ok(()),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Entry at {} with size {} is out of archive bounds ({})",
+                    self.offset, self.size, archive_length
+                ),
+            )),
+        }
+    }
+}
+
+/// Source which takes files from an archive, see module docs. Only table of entries is
+/// kept in memory, contents of files are read on request.
+#[derive(Debug)]
+pub struct ArchiveSource {
+    path: PathBuf,
+    entries: HashMap<PathBuf, ArchiveEntry>,
+}
+
+impl ArchiveSource {
+    /// Opens archive and reads its table of entries.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path.as_ref())?;
+        let length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not an archive"));
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != ARCHIVE_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported archive version {}", version),
+            ));
+        }
+
+        let count = reader.read_u32::<LittleEndian>()?;
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let name_len = reader.read_u32::<LittleEndian>()?;
+            if u64::from(name_len) > length {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Invalid entry name length",
+                ));
+            }
+            let mut name = vec![0; name_len as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid entry name"))?;
+            let offset = reader.read_u64::<LittleEndian>()?;
+            let size = reader.read_u64::<LittleEndian>()?;
+            let entry = ArchiveEntry { offset, size };
+            entry.check(length)?;
+            entries.insert(normalize_path(name), entry);
+        }
+
+        Log::writeln(format!(
+            "Archive {:?} with {} entries was opened.",
+            path.as_ref(),
+            entries.len()
+        ));
+
+        Ok(Self {
+            path: path.as_ref().to_owned(),
+            entries,
+        })
+    }
+
+    /// Writes given files into new archive.
+    pub fn write<P: AsRef<Path>>(path: P, files: &[(PathBuf, Vec<u8>)]) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let names = files
+            .iter()
+            .map(|(path, _)| normalize_path(path).to_string_lossy().replace('\\', "/"))
+            .collect::<Vec<_>>();
+
+        let header_size = ARCHIVE_MAGIC.len()
+            + 2 * 4
+            + names
+                .iter()
+                .map(|name| 4 + name.len() + 2 * 8)
+                .sum::<usize>();
+
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_u32::<LittleEndian>(ARCHIVE_VERSION)?;
+        writer.write_u32::<LittleEndian>(files.len() as u32)?;
+        let mut offset = header_size as u64;
+        for (name, (_, data)) in names.iter().zip(files.iter()) {
+            writer.write_u32::<LittleEndian>(name.len() as u32)?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_u64::<LittleEndian>(offset)?;
+            writer.write_u64::<LittleEndian>(data.len() as u64)?;
+            offset += data.len() as u64;
+        }
+        for (_, data) in files.iter() {
+            writer.write_all(data)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Packs every file of given directory (including sub-directories) into new archive.
+    /// Paths of entries are relative to the directory.
+    pub fn pack_directory<P: AsRef<Path>, A: AsRef<Path>>(
+        directory: P,
+        archive: A,
+    ) -> io::Result<()> {
+        fn collect(root: &Path, dir: &Path, files: &mut Vec<(PathBuf, Vec<u8>)>) -> io::Result<()> {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    collect(root, &path, files)?;
+                } else if let Ok(relative) = path.strip_prefix(root) {
+                    files.push((relative.to_owned(), std::fs::read(&path)?));
+                }
+            }
+            Ok(())
+        }
+
+        let mut files = Vec::new();
+        collect(directory.as_ref(), directory.as_ref(), &mut files)?;
+        Self::write(archive, &files)
+    }
+
+    fn read_entry(&self, entry: ArchiveEntry) -> io::Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        // Archive could be replaced since it was opened.
+        entry.check(file.metadata()?.len())?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = vec![0; entry.size as usize];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl MountSource for ArchiveSource {
+    fn read(&self, path: &Path) -> Option<io::Result<Vec<u8>>> {
+        self.entries.get(path).map(|entry| self.read_entry(*entry))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.entries.contains_key(path)
+    }
+}
+
+/// Unique id of a mount point, used to unmount it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MountId(u64);
+
+#[derive(Clone, Debug)]
+struct MountPoint {
+    id: MountId,
+    point: PathBuf,
+    priority: i32,
+    source: Arc<dyn MountSource>,
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct Vfs {
+    // Sorted in order of lookup.
+    mounts: Vec<MountPoint>,
+    next_id: u64,
+}
+
+impl Vfs {
+    /// Creates new VFS without mount points.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Mounts given source at given virtual path with given priority. Mount points with
+    /// higher priority are checked first.
+    pub fn mount<P: AsRef<Path>, S: MountSource + 'static>(
+        &mut self,
+        point: P,
+        source: S,
+        priority: i32,
+    ) -> MountId {
+        let id = MountId(self.next_id);
+        self.next_id += 1;
+        let mount = MountPoint {
+            id,
+            point: normalize_path(point),
+            priority,
+            source: Arc::new(source),
+        };
+        // Insert before every mount point with same or lower priority, so latest mounted
+        // source wins among equal priorities.
+        let index = self
+            .mounts
+            .iter()
+            .position(|m| m.priority <= priority)
+            .unwrap_or_else(|| self.mounts.len());
+        self.mounts.insert(index, mount);
+        id
+    }
+
+    /// Removes mount point. Returns false if there is no such mount point. Resources
+    /// which were already loaded are not affected.
+    pub fn unmount(&mut self, id: MountId) -> bool {
+        let len = self.mounts.len();
+        self.mounts.retain(|m| m.id != id);
+        self.mounts.len() != len
+    }
+
+    /// Removes every mount point.
+    pub fn unmount_all(&mut self) {
+        self.mounts.clear();
+    }
+
+    /// Returns true if there is at least one mount point.
+    pub fn has_mounts(&self) -> bool {
+        !self.mounts.is_empty()
+    }
+
+    fn find(&self, path: &Path) -> Option<(&MountPoint, PathBuf)> {
+        let path = normalize_path(path);
+        for mount in self.mounts.iter() {
+            if let Ok(relative) = path.strip_prefix(&mount.point) {
+                if mount.source.exists(relative) {
+                    return Some((mount, relative.to_owned()));
+                }
+            }
+        }
+        None
+    }
+
+    /// Reads whole file from first mount point which has it, or from real file system if
+    /// none of mount points contains the file.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<u8>> {
+        if let Some((mount, relative)) = self.find(path.as_ref()) {
+            if let Some(result) = mount.source.read(&relative) {
+                return result;
+            }
+        }
+        std::fs::read(path)
+    }
+
+    /// Returns true if file exists in any mount point or in real file system.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.find(path.as_ref()).is_some() || path.as_ref().is_file()
+    }
+
+    /// Returns path in real file system where file is located. Returns None if file is
+    /// located in archive or memory source.
+    pub fn real_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        match self.find(path.as_ref()) {
+            Some((mount, relative)) => mount.source.real_path(&relative),
+            None => Some(path.as_ref().to_owned()),
+        }
+    }
+
+    /// Returns path in real file system where file with given virtual path should be
+    /// written, so it will be found by VFS later: directory of first writable mount point
+    /// which covers the path or real file system if there is no such mount point.
+    pub fn write_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = normalize_path(path);
+        for mount in self.mounts.iter() {
+            if let Ok(relative) = path.strip_prefix(&mount.point) {
+                if let Some(write_path) = mount.source.write_path(relative) {
+                    return write_path;
+                }
+            }
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::vfs::{normalize_path, ArchiveSource, DirectorySource, MemorySource, Vfs};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("./data/../data\\textures/a.png"),
+            Path::new("data/textures/a.png")
+        );
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut vfs = Vfs::new();
+        vfs.mount("data", MemorySource::new().with_file("a", vec![0]), 0);
+        let dlc = vfs.mount(
+            "data",
+            MemorySource::new()
+                .with_file("a", vec![1])
+                .with_file("b", vec![1]),
+            10,
+        );
+        // Same priority as base, but mounted later.
+        vfs.mount("data", MemorySource::new().with_file("b", vec![2]), 0);
+
+        assert_eq!(vfs.read("data/a").unwrap(), vec![1]);
+        assert_eq!(vfs.read("./data/b").unwrap(), vec![1]);
+        assert!(vfs.unmount(dlc));
+        assert_eq!(vfs.read("data/a").unwrap(), vec![0]);
+        assert_eq!(vfs.read("data/b").unwrap(), vec![2]);
+        assert!(vfs.read("data/c").is_err());
+        assert!(vfs.real_path("data/a").is_none());
+    }
+
+    #[test]
+    fn test_write_path() {
+        let mut vfs = Vfs::new();
+        vfs.mount("data", DirectorySource::new("base/data"), 0);
+        vfs.mount("data", MemorySource::new(), 10);
+        assert_eq!(
+            vfs.write_path("data/textures/a.png"),
+            Path::new("base/data/textures/a.png")
+        );
+        assert_eq!(vfs.write_path("other/a.png"), Path::new("other/a.png"));
+    }
+
+    #[test]
+    fn test_archive() {
+        let path = std::env::temp_dir().join(format!("rg3d_vfs_test_{}.pak", std::process::id()));
+        ArchiveSource::write(
+            &path,
+            &[
+                (PathBuf::from("textures/a.png"), vec![1, 2, 3]),
+                (PathBuf::from("b.wav"), vec![4]),
+            ],
+        )
+        .unwrap();
+        let mut vfs = Vfs::new();
+        vfs.mount("data", ArchiveSource::open(&path).unwrap(), 0);
+        assert_eq!(vfs.read("data/textures/a.png").unwrap(), vec![1, 2, 3]);
+        assert_eq!(vfs.read("data/b.wav").unwrap(), vec![4]);
+        assert!(!vfs.exists("data/c.wav"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_archive_entry_out_of_bounds() {
+        let path =
+            std::env::temp_dir().join(format!("rg3d_vfs_test_bounds_{}.pak", std::process::id()));
+        ArchiveSource::write(&path, &[(PathBuf::from("a.png"), vec![1, 2, 3])]).unwrap();
+        // Corrupt size of the entry, it is the last field of the table.
+        let mut data = std::fs::read(&path).unwrap();
+        let size_offset = data.len() - 3 - 8;
+        data[size_offset..size_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, data).unwrap();
+        assert!(ArchiveSource::open(&path).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,202 @@
+//! Capabilities of GPU and its driver.
+//!
+//! Capabilities are detected once when renderer is created and can be accessed using
+//! [`Renderer::capabilities`](../struct.Renderer.html#method.capabilities). They can be used
+//! to pick sensible defaults (texture sizes, amount of samples, quality settings) and to
+//! gracefully disable features which are not supported by weak hardware.
+
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+};
+
+/// Compressed texture format family which can be supported by GPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CompressedTextureFormat {
+    /// S3TC DXT1 (BC1).
+    Dxt1,
+    /// S3TC DXT3 (BC2).
+    Dxt3,
+    /// S3TC DXT5 (BC3).
+    Dxt5,
+    /// RGTC (BC4, BC5), usually used for normal maps.
+    Rgtc,
+    /// BPTC (BC6H, BC7).
+    Bptc,
+    /// ETC2 and EAC, common on mobile GPUs.
+    Etc2,
+    /// ASTC, common on mobile GPUs.
+    Astc,
+}
+
+/// Estimate of video memory. Drivers are not required to report it, only NVIDIA
+/// (`GL_NVX_gpu_memory_info`) and AMD (`GL_ATI_meminfo`) drivers do that.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct VideoMemory {
+    /// Total amount of dedicated video memory in kilobytes, if known.
+    pub total_kb: Option<u32>,
+    /// Amount of video memory that was available at the moment of detection in kilobytes,
+    /// if known.
+    pub available_kb: Option<u32>,
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct GpuCapabilities {
+    /// Name of vendor of GPU, for example "NVIDIA Corporation".
+    pub vendor: String,
+    /// Name of GPU, for example "GeForce GTX 1060/PCIe/SSE2".
+    pub renderer: String,
+    /// Version string of OpenGL driver.
+    pub version: String,
+    /// Version string of shading language.
+    pub shading_language_version: String,
+    /// Max width and height of 2D texture in pixels.
+    pub max_texture_size: u32,
+    /// Max width and height of a cube map face in pixels.
+    pub max_cube_map_texture_size: u32,
+    /// Max width, height and depth of volume texture in pixels.
+    pub max_3d_texture_size: u32,
+    /// Max amount of samples for multisampled render targets.
+    pub max_samples: u32,
+    /// Max amount of color attachments of a framebuffer.
+    pub max_color_attachments: u32,
+    /// Max amount of textures that can be used in fragment shader at once.
+    pub max_texture_image_units: u32,
+    /// Max level of anisotropic filtering, 1.0 if anisotropic filtering is not supported.
+    pub max_anisotropy: f32,
+    /// Video memory estimate.
+    pub video_memory: VideoMemory,
+    /// Supported compressed texture formats.
+    pub compressed_formats: Vec<CompressedTextureFormat>,
+    /// Names of supported extensions, for example "GL_ARB_buffer_storage".
+    pub extensions: HashSet<String>,
+}
+
+impl GpuCapabilities {
+    /// Returns true if driver supports extension with given name.
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+
+    /// Returns true if given compressed texture format is supported.
+    pub fn supports_compressed_format(&self, format: CompressedTextureFormat) -> bool {
+        self.compressed_formats.contains(&format)
+    }
+
+    /// Returns total amount of video memory in bytes, if driver reports it.
+    pub fn video_memory_bytes(&self) -> Option<u64> {
+        self.video_memory.total_kb.map(|kb| kb as u64 * 1024)
+    }
+
+    /// Returns true if GPU is made by given vendor, check is case-insensitive and only
+    /// looks for given substring in vendor string, for example `is_vendor("nvidia")`.
+    pub fn is_vendor(&self, vendor: &str) -> bool {
+        self.vendor.to_lowercase().contains(&vendor.to_lowercase())
+    }
+}
+
+impl Display for GpuCapabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "GPU: {} ({})", self.renderer, self.vendor)?;
+        writeln!(
+            f,
+            "OpenGL: {}, GLSL: {}",
+            self.version, self.shading_language_version
+        )?;
+        writeln!(
+            f,
+            "Max texture size: {}, max samples: {}, max anisotropy: {}",
+            self.max_texture_size, self.max_samples, self.max_anisotropy
+        )?;
+        match self.video_memory.total_kb {
+            Some(kb) => writeln!(f, "Video memory: {} Mb", kb / 1024)?,
+            None => writeln!(f, "Video memory: unknown")?,
+        }
+        write!(
+            f,
+            "Compressed formats: {:?}, extensions: {}",
+            self.compressed_formats,
+            self.extensions.len()
+        )
+    }
+}
+
+/// Adds compressed formats that are exposed only through extensions, some drivers do not
+/// list them in `GL_COMPRESSED_TEXTURE_FORMATS`. Keeps order and avoids duplicates.
+pub(in crate) fn merge_extension_formats(
+    formats: &mut Vec<CompressedTextureFormat>,
+    extensions: &HashSet<String>,
+) {
+    let pairs = [
+        (
+            "GL_EXT_texture_compression_s3tc",
+            CompressedTextureFormat::Dxt1,
+        ),
+        (
+            "GL_EXT_texture_compression_s3tc",
+            CompressedTextureFormat::Dxt3,
+        ),
+        (
+            "GL_EXT_texture_compression_s3tc",
+            CompressedTextureFormat::Dxt5,
+        ),
+        (
+            "GL_ARB_texture_compression_rgtc",
+            CompressedTextureFormat::Rgtc,
+        ),
+        (
+            "GL_ARB_texture_compression_bptc",
+            CompressedTextureFormat::Bptc,
+        ),
+        ("GL_ARB_ES3_compatibility", CompressedTextureFormat::Etc2),
+        (
+            "GL_KHR_texture_compression_astc_ldr",
+            CompressedTextureFormat::Astc,
+        ),
+    ];
+    for &(extension, format) in pairs.iter() {
+        if extensions.contains(extension) && !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renderer::capabilities::{
+        merge_extension_formats, CompressedTextureFormat, GpuCapabilities, VideoMemory,
+    };
+
+    #[test]
+    fn test_capabilities() {
+        let mut caps = GpuCapabilities {
+            vendor: "NVIDIA Corporation".to_owned(),
+            video_memory: VideoMemory {
+                total_kb: Some(6 * 1024 * 1024),
+                available_kb: None,
+            },
+            compressed_formats: vec![CompressedTextureFormat::Dxt1],
+            extensions: ["GL_EXT_texture_compression_s3tc", "GL_ARB_buffer_storage"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        merge_extension_formats(&mut caps.compressed_formats, &caps.extensions);
+
+        assert!(caps.is_vendor("nvidia"));
+        assert!(!caps.is_vendor("amd"));
+        assert!(caps.has_extension("GL_ARB_buffer_storage"));
+        assert_eq!(caps.video_memory_bytes(), Some(6 * 1024 * 1024 * 1024));
+        assert_eq!(
+            caps.compressed_formats,
+            vec![
+                CompressedTextureFormat::Dxt1,
+                CompressedTextureFormat::Dxt3,
+                CompressedTextureFormat::Dxt5
+            ]
+        );
+        assert!(!caps.supports_compressed_format(CompressedTextureFormat::Bptc));
+    }
+}
@@ -23,7 +23,12 @@ use crate::{
         surface::SurfaceSharedData,
         GeometryCache, QualitySettings, RenderPassStatistics, TextureCache,
     },
-    scene::{camera::Camera, light::Light, node::Node, Scene},
+    scene::{
+        camera::Camera,
+        light::{Light, LightFalloff},
+        node::Node,
+        Scene,
+    },
 };
 use std::{cell::RefCell, rc::Rc};
 
@@ -59,6 +64,7 @@ struct SpotLightShader {
     depth_sampler: UniformLocation,
     color_sampler: UniformLocation,
     normal_sampler: UniformLocation,
+    ambient_sampler: UniformLocation,
     spot_shadow_texture: UniformLocation,
     light_view_proj_matrix: UniformLocation,
     shadows_enabled: UniformLocation,
@@ -67,6 +73,8 @@ struct SpotLightShader {
     light_position: UniformLocation,
     light_radius: UniformLocation,
     light_color: UniformLocation,
+    light_intensity: UniformLocation,
+    inverse_square_falloff: UniformLocation,
     light_direction: UniformLocation,
     half_hotspot_cone_angle_cos: UniformLocation,
     half_cone_angle_cos: UniformLocation,
@@ -85,6 +93,7 @@ impl SpotLightShader {
             depth_sampler: program.uniform_location("depthTexture")?,
             color_sampler: program.uniform_location("colorTexture")?,
             normal_sampler: program.uniform_location("normalTexture")?,
+            ambient_sampler: program.uniform_location("ambientTexture")?,
             spot_shadow_texture: program.uniform_location("spotShadowTexture")?,
             light_view_proj_matrix: program.uniform_location("lightViewProjMatrix")?,
             shadows_enabled: program.uniform_location("shadowsEnabled")?,
@@ -93,6 +102,8 @@ impl SpotLightShader {
             light_position: program.uniform_location("lightPos")?,
             light_radius: program.uniform_location("lightRadius")?,
             light_color: program.uniform_location("lightColor")?,
+            light_intensity: program.uniform_location("lightIntensity")?,
+            inverse_square_falloff: program.uniform_location("inverseSquareFalloff")?,
             light_direction: program.uniform_location("lightDirection")?,
             half_hotspot_cone_angle_cos: program.uniform_location("halfHotspotConeAngleCos")?,
             half_cone_angle_cos: program.uniform_location("halfConeAngleCos")?,
@@ -110,12 +121,15 @@ struct PointLightShader {
     depth_sampler: UniformLocation,
     color_sampler: UniformLocation,
     normal_sampler: UniformLocation,
+    ambient_sampler: UniformLocation,
     point_shadow_texture: UniformLocation,
     shadows_enabled: UniformLocation,
     soft_shadows: UniformLocation,
     light_position: UniformLocation,
     light_radius: UniformLocation,
     light_color: UniformLocation,
+    light_intensity: UniformLocation,
+    inverse_square_falloff: UniformLocation,
     inv_view_proj_matrix: UniformLocation,
     camera_position: UniformLocation,
 }
@@ -131,12 +145,15 @@ impl PointLightShader {
             depth_sampler: program.uniform_location("depthTexture")?,
             color_sampler: program.uniform_location("colorTexture")?,
             normal_sampler: program.uniform_location("normalTexture")?,
+            ambient_sampler: program.uniform_location("ambientTexture")?,
             point_shadow_texture: program.uniform_location("pointShadowTexture")?,
             shadows_enabled: program.uniform_location("shadowsEnabled")?,
             soft_shadows: program.uniform_location("softShadows")?,
             light_position: program.uniform_location("lightPos")?,
             light_radius: program.uniform_location("lightRadius")?,
             light_color: program.uniform_location("lightColor")?,
+            light_intensity: program.uniform_location("lightIntensity")?,
+            inverse_square_falloff: program.uniform_location("inverseSquareFalloff")?,
             inv_view_proj_matrix: program.uniform_location("invViewProj")?,
             camera_position: program.uniform_location("cameraPosition")?,
 
@@ -151,8 +168,10 @@ struct DirectionalLightShader {
     depth_sampler: UniformLocation,
     color_sampler: UniformLocation,
     normal_sampler: UniformLocation,
+    ambient_sampler: UniformLocation,
     light_direction: UniformLocation,
     light_color: UniformLocation,
+    light_intensity: UniformLocation,
     inv_view_proj_matrix: UniformLocation,
     camera_position: UniformLocation,
 }
@@ -168,8 +187,10 @@ impl DirectionalLightShader {
             depth_sampler: program.uniform_location("depthTexture")?,
             color_sampler: program.uniform_location("colorTexture")?,
             normal_sampler: program.uniform_location("normalTexture")?,
+            ambient_sampler: program.uniform_location("ambientTexture")?,
             light_direction: program.uniform_location("lightDirection")?,
             light_color: program.uniform_location("lightColor")?,
+            light_intensity: program.uniform_location("lightIntensity")?,
             inv_view_proj_matrix: program.uniform_location("invViewProj")?,
             camera_position: program.uniform_location("cameraPosition")?,
             program,
@@ -373,7 +394,7 @@ impl DeferredLightRenderer {
                 None
             }
         }) {
-            if !light.global_visibility() {
+            if !light.global_visibility() || !light.is_switched_on() {
                 continue;
             }
 
@@ -383,6 +404,9 @@ impl DeferredLightRenderer {
                 Light::Directional(_) => std::f32::MAX,
             };
 
+            let light_intensity = light.shading_intensity();
+            let inverse_square_falloff = light.falloff() == LightFalloff::InverseSquare;
+
             let light_position = light.global_position();
             let light_radius_scale = light.local_transform().scale().max_value();
             let light_radius = light_radius_scale * raw_radius;
@@ -564,7 +588,15 @@ impl DeferredLightRenderer {
                             shader.inv_view_proj_matrix,
                             UniformValue::Mat4(inv_view_projection),
                         ),
-                        (shader.light_color, UniformValue::Color(light.color())),
+                        (
+                            shader.light_color,
+                            UniformValue::Color(light.shading_color()),
+                        ),
+                        (shader.light_intensity, UniformValue::Float(light_intensity)),
+                        (
+                            shader.inverse_square_falloff,
+                            UniformValue::Bool(inverse_square_falloff),
+                        ),
                         (
                             shader.half_hotspot_cone_angle_cos,
                             UniformValue::Float((spot_light.hotspot_cone_angle() * 0.5).cos()),
@@ -603,6 +635,13 @@ impl DeferredLightRenderer {
                                 texture: gbuffer.normal_texture(),
                             },
                         ),
+                        (
+                            shader.ambient_sampler,
+                            UniformValue::Sampler {
+                                index: 4,
+                                texture: gbuffer.ambient_texture(),
+                            },
+                        ),
                         (
                             shader.spot_shadow_texture,
                             UniformValue::Sampler {
@@ -636,7 +675,15 @@ impl DeferredLightRenderer {
                             shader.inv_view_proj_matrix,
                             UniformValue::Mat4(inv_view_projection),
                         ),
-                        (shader.light_color, UniformValue::Color(light.color())),
+                        (
+                            shader.light_color,
+                            UniformValue::Color(light.shading_color()),
+                        ),
+                        (shader.light_intensity, UniformValue::Float(light_intensity)),
+                        (
+                            shader.inverse_square_falloff,
+                            UniformValue::Bool(inverse_square_falloff),
+                        ),
                         (shader.wvp_matrix, UniformValue::Mat4(frame_matrix)),
                         (
                             shader.camera_position,
@@ -663,6 +710,13 @@ impl DeferredLightRenderer {
                                 texture: gbuffer.normal_texture(),
                             },
                         ),
+                        (
+                            shader.ambient_sampler,
+                            UniformValue::Sampler {
+                                index: 4,
+                                texture: gbuffer.ambient_texture(),
+                            },
+                        ),
                         (
                             shader.point_shadow_texture,
                             UniformValue::Sampler {
@@ -690,7 +744,11 @@ impl DeferredLightRenderer {
                             shader.inv_view_proj_matrix,
                             UniformValue::Mat4(inv_view_projection),
                         ),
-                        (shader.light_color, UniformValue::Color(light.color())),
+                        (
+                            shader.light_color,
+                            UniformValue::Color(light.shading_color()),
+                        ),
+                        (shader.light_intensity, UniformValue::Float(light_intensity)),
                         (shader.wvp_matrix, UniformValue::Mat4(frame_matrix)),
                         (
                             shader.camera_position,
@@ -717,6 +775,13 @@ impl DeferredLightRenderer {
                                 texture: gbuffer.normal_texture(),
                             },
                         ),
+                        (
+                            shader.ambient_sampler,
+                            UniformValue::Sampler {
+                                index: 3,
+                                texture: gbuffer.ambient_texture(),
+                            },
+                        ),
                     ];
 
                     gbuffer.final_frame.draw(
@@ -52,6 +52,15 @@ pub enum RendererError {
     InvalidFrameBuffer,
     /// OpenGL failed to construct framebuffer.
     FailedToConstructFBO,
+    /// Handle does not point to a camera node.
+    InvalidCamera,
+    /// Data written to GPU job target has wrong amount of values.
+    InvalidGpuJobData {
+        /// Amount of values needed to fill the target.
+        expected: usize,
+        /// Actual amount of values.
+        actual: usize,
+    },
     /// Internal context error.
     Context(ContextError),
 }
@@ -1,7 +1,17 @@
-use crate::renderer::{
-    error::RendererError,
-    framework::gpu_program::{GpuProgram, UniformLocation},
+use crate::{
+    core::math::{mat4::Mat4, vec3::Vec3, Rect},
+    renderer::{
+        error::RendererError,
+        framework::{
+            framebuffer::{CullFace, DrawParameters, FrameBufferTrait},
+            geometry_buffer::{DrawCallStatistics, GeometryBuffer},
+            gpu_program::{GpuProgram, UniformLocation, UniformValue},
+            gpu_texture::GpuTexture,
+            state::State,
+        },
+    },
 };
+use std::{cell::RefCell, rc::Rc};
 
 pub struct FlatShader {
     pub program: GpuProgram,
@@ -21,4 +31,44 @@ impl FlatShader {
             program,
         })
     }
+
+    /// Stretches texture over given viewport of framebuffer, depth is not affected.
+    pub fn blit<F: FrameBufferTrait, T>(
+        &self,
+        framebuffer: &mut F,
+        quad: &GeometryBuffer<T>,
+        state: &mut State,
+        viewport: Rect<i32>,
+        texture: Rc<RefCell<GpuTexture>>,
+    ) -> DrawCallStatistics {
+        let (width, height) = (viewport.w as f32, viewport.h as f32);
+        framebuffer.draw(
+            quad,
+            state,
+            viewport,
+            &self.program,
+            DrawParameters {
+                cull_face: CullFace::Back,
+                culling: false,
+                color_write: Default::default(),
+                depth_write: false,
+                stencil_test: false,
+                depth_test: false,
+                blend: false,
+            },
+            &[
+                (
+                    self.wvp_matrix,
+                    UniformValue::Mat4(
+                        Mat4::ortho(0.0, width, height, 0.0, -1.0, 1.0)
+                            * Mat4::scale(Vec3::new(width, height, 0.0)),
+                    ),
+                ),
+                (
+                    self.diffuse_texture,
+                    UniformValue::Sampler { index: 0, texture },
+                ),
+            ],
+        )
+    }
 }
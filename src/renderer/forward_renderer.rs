@@ -0,0 +1,476 @@
+//! Forward renderer is a lightweight alternative to deferred pipeline for simple scenes.
+//!
+//! Each mesh is drawn directly into final frame with every light that affects it
+//! evaluated in a single shader pass. There is no G-Buffer filling, no full-screen
+//! lighting passes and no SSAO, so fixed cost of a frame is minimal. On the other hand
+//! each mesh is lit by at most `MAX_FORWARD_LIGHTS` lights and shadows are not supported,
+//! so this path is suitable only for small scenes: menu backgrounds, model previews,
+//! simple levels on weak GPUs. Path is selected per scene, see `Scene::render_path`.
+
+use crate::{
+    core::{
+        color::Color,
+        math::{frustum::Frustum, mat4::Mat4, vec2::Vec2, vec3::Vec3, vec4::Vec4, Rect},
+        scope_profile,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            framebuffer::{CullFace, DrawParameters, FrameBuffer, FrameBufferTrait},
+            gpu_program::{GpuProgram, UniformLocation, UniformValue},
+            gpu_texture::GpuTexture,
+            state::State,
+            uniform_ring::{
+                InstanceData, UniformRing, BONE_MATRICES_BINDING, INSTANCE_DATA_BINDING,
+            },
+        },
+        GeometryCache, RenderPassStatistics, TextureCache,
+    },
+    scene::{
+        camera::Camera,
+        graph::Graph,
+        light::{Light, LightFalloff},
+        node::Node,
+    },
+    utils::light_probe::LightProbeGrid,
+};
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+/// Maximum amount of lights that can affect single mesh. Must be in sync with
+/// MAX_LIGHTS in forward_fs.glsl
+const MAX_FORWARD_LIGHTS: usize = 8;
+
+/// Size of bone matrices array of the shader.
+const MAX_BONE_MATRICES: usize = 60;
+
+struct ForwardShader {
+    program: GpuProgram,
+    use_skeletal_animation: UniformLocation,
+    diffuse_texture: UniformLocation,
+    normal_texture: UniformLocation,
+    lightmap_texture: UniformLocation,
+    orm_texture: UniformLocation,
+    use_orm_texture: UniformLocation,
+    diffuse_color: UniformLocation,
+    ambient_color: UniformLocation,
+    use_light_probe: UniformLocation,
+    light_probe: UniformLocation,
+    camera_position: UniformLocation,
+    light_count: UniformLocation,
+    light_kind: UniformLocation,
+    light_position: UniformLocation,
+    light_direction: UniformLocation,
+    light_color: UniformLocation,
+    light_radius: UniformLocation,
+    light_inverse_square_falloff: UniformLocation,
+    light_cone_angle_cos: UniformLocation,
+}
+
+impl ForwardShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/forward_fs.glsl");
+        let vertex_source = include_str!("shaders/forward_vs.glsl");
+        let program = GpuProgram::from_source("ForwardShader", vertex_source, fragment_source)?;
+        program.bind_uniform_block("BoneMatrices", BONE_MATRICES_BINDING)?;
+        program.bind_uniform_block("InstanceData", INSTANCE_DATA_BINDING)?;
+        Ok(Self {
+            use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
+            diffuse_texture: program.uniform_location("diffuseTexture")?,
+            normal_texture: program.uniform_location("normalTexture")?,
+            lightmap_texture: program.uniform_location("lightmapTexture")?,
+            orm_texture: program.uniform_location("ormTexture")?,
+            use_orm_texture: program.uniform_location("useOrmTexture")?,
+            diffuse_color: program.uniform_location("diffuseColor")?,
+            ambient_color: program.uniform_location("ambientColor")?,
+            use_light_probe: program.uniform_location("useLightProbe")?,
+            light_probe: program.uniform_location("lightProbe")?,
+            camera_position: program.uniform_location("cameraPosition")?,
+            light_count: program.uniform_location("lightCount")?,
+            light_kind: program.uniform_location("lightKind")?,
+            light_position: program.uniform_location("lightPosition")?,
+            light_direction: program.uniform_location("lightDirection")?,
+            light_color: program.uniform_location("lightColor")?,
+            light_radius: program.uniform_location("lightRadius")?,
+            light_inverse_square_falloff: program.uniform_location("lightInverseSquareFalloff")?,
+            light_cone_angle_cos: program.uniform_location("lightConeAngleCos")?,
+            program,
+        })
+    }
+}
+
+/// Light source data in form suitable for the shader.
+#[derive(Copy, Clone)]
+struct ForwardLight {
+    kind: i32,
+    position: Vec3,
+    direction: Vec3,
+    color: Vec4,
+    radius: f32,
+    inverse_square_falloff: i32,
+    cone_angle_cos: Vec2,
+}
+
+/// Uniform arrays of lights of a mesh.
+#[derive(Default)]
+struct LightArrays {
+    kind: Vec<i32>,
+    position: Vec<Vec3>,
+    direction: Vec<Vec3>,
+    color: Vec<Vec4>,
+    radius: Vec<f32>,
+    inverse_square_falloff: Vec<i32>,
+    cone_angle_cos: Vec<Vec2>,
+}
+
+impl LightArrays {
+    fn clear(&mut self) {
+        self.kind.clear();
+        self.position.clear();
+        self.direction.clear();
+        self.color.clear();
+        self.radius.clear();
+        self.inverse_square_falloff.clear();
+        self.cone_angle_cos.clear();
+    }
+
+    fn push(&mut self, light: &ForwardLight) {
+        self.kind.push(light.kind);
+        self.position.push(light.position);
+        self.direction.push(light.direction);
+        self.color.push(light.color);
+        self.radius.push(light.radius);
+        self.inverse_square_falloff
+            .push(light.inverse_square_falloff);
+        self.cone_angle_cos.push(light.cone_angle_cos);
+    }
+}
+
+pub(in crate) struct ForwardRenderer {
+    shader: ForwardShader,
+    bone_matrices: Vec<Mat4>,
+    uniform_ring: UniformRing,
+    lights: Vec<ForwardLight>,
+    light_arrays: LightArrays,
+}
+
+pub(in crate) struct ForwardRenderContext<'a, 'b> {
+    pub state: &'a mut State,
+    pub graph: &'b Graph,
+    pub camera: &'b Camera,
+    pub framebuffer: &'a mut FrameBuffer,
+    pub viewport: Rect<i32>,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub normal_dummy: Rc<RefCell<GpuTexture>>,
+    pub ambient_color: Color,
+    pub texture_cache: &'a mut TextureCache,
+    pub geom_cache: &'a mut GeometryCache,
+    pub light_probes: Option<&'b LightProbeGrid>,
+}
+
+fn collect_lights(graph: &Graph, lights: &mut Vec<ForwardLight>) {
+    lights.clear();
+    for light in graph.linear_iter().filter_map(|node| {
+        if let Node::Light(light) = node {
+            Some(light)
+        } else {
+            None
+        }
+    }) {
+        if !light.global_visibility() || !light.is_switched_on() {
+            continue;
+        }
+
+        let (kind, raw_radius, cone_angle_cos) = match light {
+            Light::Directional(_) => (0, std::f32::MAX, Vec2::ZERO),
+            Light::Point(point) => (1, point.radius(), Vec2::ZERO),
+            Light::Spot(spot) => (
+                2,
+                spot.distance(),
+                Vec2::new(
+                    (spot.hotspot_cone_angle() * 0.5).cos(),
+                    (spot.full_cone_angle() * 0.5).cos(),
+                ),
+            ),
+        };
+
+        let intensity = light.shading_intensity();
+        let color = light.shading_color().as_frgba();
+
+        lights.push(ForwardLight {
+            kind,
+            position: light.global_position(),
+            direction: light.up_vector().normalized().unwrap_or(Vec3::LOOK),
+            color: Vec4 {
+                x: color.x * intensity,
+                y: color.y * intensity,
+                z: color.z * intensity,
+                w: color.w,
+            },
+            radius: if kind == 0 {
+                raw_radius
+            } else {
+                // Radius is scaled by global scale, so lights attached to scaled
+                // parents cover same volume as their geometry.
+                let scale = Vec3::new(
+                    light.side_vector().len(),
+                    light.up_vector().len(),
+                    light.look_vector().len(),
+                );
+                raw_radius * scale.max_value()
+            },
+            inverse_square_falloff: (light.falloff() == LightFalloff::InverseSquare) as i32,
+            cone_angle_cos,
+        });
+    }
+}
+
+impl ForwardRenderer {
+    pub fn new() -> Result<Self, RendererError> {
+        Ok(Self {
+            shader: ForwardShader::new()?,
+            bone_matrices: Vec::new(),
+            uniform_ring: UniformRing::new(),
+            lights: Vec::new(),
+            light_arrays: Default::default(),
+        })
+    }
+
+    /// Selects lights which affects sphere with given center and radius, directional
+    /// lights go first, then point and spot lights closest to the sphere.
+    fn select_lights(&mut self, center: Vec3, radius: f32) {
+        let mut candidates = self
+            .lights
+            .iter()
+            .filter_map(|light| {
+                if light.kind == 0 {
+                    Some((std::f32::MIN, light))
+                } else {
+                    let distance = light.position.distance(&center) - light.radius - radius;
+                    if distance <= 0.0 {
+                        Some((distance, light))
+                    } else {
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        self.light_arrays.clear();
+        for (_, light) in candidates.into_iter().take(MAX_FORWARD_LIGHTS) {
+            self.light_arrays.push(light);
+        }
+    }
+
+    #[must_use]
+    pub(in crate) fn render(&mut self, args: ForwardRenderContext) -> RenderPassStatistics {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let ForwardRenderContext {
+            state,
+            graph,
+            camera,
+            framebuffer,
+            viewport,
+            white_dummy,
+            normal_dummy,
+            ambient_color,
+            texture_cache,
+            geom_cache,
+            light_probes,
+        } = args;
+
+        framebuffer.clear(
+            state,
+            viewport,
+            Some(Color::from_rgba(0, 0, 0, 0)),
+            Some(1.0),
+            Some(0),
+        );
+
+        collect_lights(graph, &mut self.lights);
+
+        let frustum = Frustum::from(camera.view_projection_matrix()).unwrap();
+        let view_projection = camera.view_projection_matrix();
+        let camera_position = camera.global_position();
+
+        for mesh in graph.linear_iter().filter_map(|node| {
+            if let Node::Mesh(mesh) = node {
+                Some(mesh)
+            } else {
+                None
+            }
+        }) {
+            if !mesh.global_visibility()
+                || !camera.sees_layer(mesh.layer())
+                || !mesh.is_intersect_frustum(graph, &frustum)
+            {
+                continue;
+            }
+
+            let bounding_box = mesh.bounding_box();
+            let global_transform = mesh.instance_transform();
+            let variation = *mesh.instance_variation();
+            let min = global_transform.transform_vector(bounding_box.min);
+            let max = global_transform.transform_vector(bounding_box.max);
+            self.select_lights((min + max).scale(0.5), (max - min).len() * 0.5);
+
+            let light_probe = light_probes
+                .and_then(|grid| grid.interpolate(mesh.global_position()))
+                .unwrap_or_default();
+
+            for surface in mesh.surfaces().iter() {
+                let is_skinned = !surface.bones.is_empty();
+
+                let world = if is_skinned {
+                    Mat4::IDENTITY
+                } else {
+                    global_transform
+                };
+                let mvp = view_projection * world;
+
+                let diffuse_texture = surface
+                    .diffuse_texture()
+                    .and_then(|texture| texture_cache.get(state, texture))
+                    .unwrap_or_else(|| white_dummy.clone());
+                let normal_texture = surface
+                    .normal_texture()
+                    .and_then(|texture| texture_cache.get(state, texture))
+                    .unwrap_or_else(|| normal_dummy.clone());
+                let lightmap_texture = surface
+                    .lightmap_texture()
+                    .and_then(|texture| texture_cache.get(state, texture))
+                    .unwrap_or_else(|| white_dummy.clone());
+                let orm_texture = surface
+                    .orm_texture()
+                    .and_then(|texture| texture_cache.get(state, texture));
+
+                let use_light_probe =
+                    light_probes.is_some() && surface.lightmap_texture().is_none();
+
+                surface.calculate_bone_matrices(graph, &mut self.bone_matrices);
+                self.uniform_ring
+                    .bind_bone_matrices(&self.bone_matrices, MAX_BONE_MATRICES);
+                self.uniform_ring.bind_instance_data(&InstanceData {
+                    world,
+                    world_view_projection: mvp,
+                    tint: variation.tint(),
+                    seed: variation.shader_seed(),
+                    brightness_jitter: variation.brightness_jitter(),
+                });
+
+                let lights = &self.light_arrays;
+
+                statistics += framebuffer.draw(
+                    geom_cache.get(state, &surface.data().lock().unwrap()),
+                    state,
+                    viewport,
+                    &self.shader.program,
+                    DrawParameters {
+                        cull_face: CullFace::Back,
+                        culling: true,
+                        color_write: Default::default(),
+                        depth_write: true,
+                        stencil_test: false,
+                        depth_test: true,
+                        blend: false,
+                    },
+                    &[
+                        (
+                            self.shader.diffuse_texture,
+                            UniformValue::Sampler {
+                                index: 0,
+                                texture: diffuse_texture,
+                            },
+                        ),
+                        (
+                            self.shader.normal_texture,
+                            UniformValue::Sampler {
+                                index: 1,
+                                texture: normal_texture,
+                            },
+                        ),
+                        (
+                            self.shader.lightmap_texture,
+                            UniformValue::Sampler {
+                                index: 2,
+                                texture: lightmap_texture,
+                            },
+                        ),
+                        (
+                            self.shader.use_orm_texture,
+                            UniformValue::Bool(orm_texture.is_some()),
+                        ),
+                        (
+                            self.shader.orm_texture,
+                            UniformValue::Sampler {
+                                index: 3,
+                                texture: orm_texture.unwrap_or_else(|| white_dummy.clone()),
+                            },
+                        ),
+                        (
+                            self.shader.use_skeletal_animation,
+                            UniformValue::Bool(is_skinned),
+                        ),
+                        (
+                            self.shader.diffuse_color,
+                            UniformValue::Color(surface.color()),
+                        ),
+                        (
+                            self.shader.ambient_color,
+                            UniformValue::Color(ambient_color),
+                        ),
+                        (
+                            self.shader.use_light_probe,
+                            UniformValue::Bool(use_light_probe),
+                        ),
+                        (
+                            self.shader.light_probe,
+                            UniformValue::Vec3Array(&light_probe.ambient_cube),
+                        ),
+                        (
+                            self.shader.camera_position,
+                            UniformValue::Vec3(camera_position),
+                        ),
+                        (
+                            self.shader.light_count,
+                            UniformValue::Integer(lights.kind.len() as i32),
+                        ),
+                        (
+                            self.shader.light_kind,
+                            UniformValue::IntegerArray(&lights.kind),
+                        ),
+                        (
+                            self.shader.light_position,
+                            UniformValue::Vec3Array(&lights.position),
+                        ),
+                        (
+                            self.shader.light_direction,
+                            UniformValue::Vec3Array(&lights.direction),
+                        ),
+                        (
+                            self.shader.light_color,
+                            UniformValue::Vec4Array(&lights.color),
+                        ),
+                        (
+                            self.shader.light_radius,
+                            UniformValue::FloatArray(&lights.radius),
+                        ),
+                        (
+                            self.shader.light_inverse_square_falloff,
+                            UniformValue::IntegerArray(&lights.inverse_square_falloff),
+                        ),
+                        (
+                            self.shader.light_cone_angle_cos,
+                            UniformValue::Vec2Array(&lights.cone_angle_cos),
+                        ),
+                    ],
+                );
+            }
+        }
+
+        statistics
+    }
+}
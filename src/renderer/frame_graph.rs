@@ -0,0 +1,738 @@
+//! Frame graph - scheduling of render passes of cameras.
+//!
+//! Every camera is rendered by a sequence of render passes: G-buffer fill, lighting,
+//! particles, sprites, post effects, etc. Instead of a fixed order of calls, each pass
+//! declares which frame resources it reads and writes, and passes are put into a frame
+//! graph. Before camera is rendered, graph is compiled: passes whose results never reach
+//! output of camera (back buffer or render target) are culled, the rest are executed in
+//! order of declaration. So passes can be disabled, added or reordered per camera and
+//! renderer will skip work that became useless, for example disabling [`PRESENT_PASS`]
+//! skips every pass of camera except user passes that do not write frame (and passes they
+//! read from). Note that built-in passes that draw over frame read depth of camera, so
+//! [`GBUFFER_PASS`] is kept even if [`LIGHTING_PASS`] is disabled.
+//!
+//! Built-in passes have names from constants of this module. Deferred cameras have
+//! [`GBUFFER_PASS`] and [`LIGHTING_PASS`], forward cameras have [`FORWARD_PASS`] instead,
+//! then every camera has [`PARTICLES_PASS`], [`SPRITES_PASS`], [`POST_EFFECTS_PASS`],
+//! [`IMMEDIATE_PASS`], [`DEBUG_PASS`] and [`PRESENT_PASS`] (only if scene has no render
+//! target).
+//!
+//! Pass configuration is stored per camera of a scene and dropped when camera is removed.
+//! Screenshots use same graph (without debug geometry and present), see
+//! `ScreenshotSettings::with_scene`.
+//!
+//! # User passes
+//!
+//! User pass is a GPU job (see `gpu_job` module) run between built-in passes of a camera.
+//! Job of user pass should have [`GpuJobMode::UserPass`] mode so it is not run before
+//! scenes, it can read frame and depth of camera by [`GpuJobInput::CameraFrame`] and
+//! [`GpuJobInput::CameraDepth`] inputs. If pass writes frame, result of job is stretched
+//! over frame of camera, so target of job should have size of camera viewport. Pass that
+//! does not write frame is never culled, because its target can be used anywhere.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::pool::Handle,
+//!     renderer::{
+//!         frame_graph::{UserPass, DEBUG_PASS, POST_EFFECTS_PASS},
+//!         gpu_job::GpuJob,
+//!         Renderer,
+//!     },
+//!     scene::{node::Node, Scene},
+//! };
+//!
+//! fn setup(
+//!     renderer: &mut Renderer,
+//!     scene: Handle<Scene>,
+//!     camera: Handle<Node>,
+//!     job: Handle<GpuJob>,
+//! ) {
+//!     // Invert colors of frame after post effects.
+//!     renderer
+//!         .add_user_pass(
+//!             scene,
+//!             camera,
+//!             "Invert",
+//!             UserPass::new(job)
+//!                 .after(POST_EFFECTS_PASS)
+//!                 .with_frame_write(true),
+//!         )
+//!         .unwrap();
+//!     // Debug geometry is not needed for this camera.
+//!     renderer.set_render_pass_enabled(scene, camera, DEBUG_PASS, false);
+//! }
+//! ```
+
+use crate::{
+    core::pool::Handle,
+    renderer::{
+        gpu_job::{GpuJob, GpuJobInput, GpuJobMode, GpuJobTarget, GpuJobs},
+        Renderer,
+    },
+    scene::{node::Node, RenderPath, Scene},
+    utils::log::Log,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+};
+
+/// Name of pass that fills G-buffer of deferred camera.
+pub const GBUFFER_PASS: &str = "GBuffer";
+/// Name of pass that lights G-buffer of deferred camera.
+pub const LIGHTING_PASS: &str = "Lighting";
+/// Name of pass that draws forward camera.
+pub const FORWARD_PASS: &str = "Forward";
+/// Name of pass that draws particle systems.
+pub const PARTICLES_PASS: &str = "Particles";
+/// Name of pass that draws sprites.
+pub const SPRITES_PASS: &str = "Sprites";
+/// Name of pass that applies post effects of camera.
+pub const POST_EFFECTS_PASS: &str = "PostEffects";
+/// Name of pass that draws immediate geometry.
+pub const IMMEDIATE_PASS: &str = "Immediate";
+/// Name of pass that draws debug geometry.
+pub const DEBUG_PASS: &str = "Debug";
+/// Name of pass that copies frame of camera to back buffer.
+pub const PRESENT_PASS: &str = "Present";
+
+/// Resource which can be read or written by render pass.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FrameResource {
+    /// Color attachments of G-buffer (albedo, normals, etc.).
+    GBuffer,
+    /// Depth buffer of camera.
+    Depth,
+    /// Frame of camera.
+    Frame,
+    /// Back buffer of window.
+    Backbuffer,
+    /// Target of GPU job.
+    Target(Handle<GpuJobTarget>),
+}
+
+/// Description of render pass in frame graph.
+#[derive(Clone, Debug)]
+pub struct RenderPassDesc<P> {
+    name: String,
+    reads: Vec<FrameResource>,
+    writes: Vec<FrameResource>,
+    enabled: bool,
+    side_effects: bool,
+    payload: P,
+}
+
+impl<P> RenderPassDesc<P> {
+    /// Creates new enabled pass with given name and payload which tells how to execute
+    /// the pass.
+    pub fn new<N: AsRef<str>>(name: N, payload: P) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            reads: Default::default(),
+            writes: Default::default(),
+            enabled: true,
+            side_effects: false,
+            payload,
+        }
+    }
+
+    /// Sets resources read by pass.
+    pub fn with_reads(mut self, reads: &[FrameResource]) -> Self {
+        self.reads = reads.to_vec();
+        self
+    }
+
+    /// Sets resources written by pass.
+    pub fn with_writes(mut self, writes: &[FrameResource]) -> Self {
+        self.writes = writes.to_vec();
+        self
+    }
+
+    /// Marks pass as having effects outside of frame graph, such pass is never culled.
+    pub fn with_side_effects(mut self, side_effects: bool) -> Self {
+        self.side_effects = side_effects;
+        self
+    }
+
+    /// Returns name of pass.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns resources read by pass.
+    pub fn reads(&self) -> &[FrameResource] {
+        &self.reads
+    }
+
+    /// Returns resources written by pass.
+    pub fn writes(&self) -> &[FrameResource] {
+        &self.writes
+    }
+
+    /// Returns true if pass is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns payload of pass.
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+}
+
+/// Errors of frame graph modification.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameGraphError {
+    /// Pass with such name already exists.
+    DuplicatePass(String),
+    /// There is no pass with such name.
+    UnknownPass(String),
+}
+
+impl Display for FrameGraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameGraphError::DuplicatePass(name) => write!(f, "Pass {} already exists", name),
+            FrameGraphError::UnknownPass(name) => write!(f, "There is no pass {}", name),
+        }
+    }
+}
+
+/// Ordered set of render passes, see module docs.
+#[derive(Clone, Debug)]
+pub struct FrameGraph<P> {
+    passes: Vec<RenderPassDesc<P>>,
+}
+
+impl<P> Default for FrameGraph<P> {
+    fn default() -> Self {
+        Self {
+            passes: Default::default(),
+        }
+    }
+}
+
+impl<P> FrameGraph<P> {
+    /// Creates new empty graph.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn position(&self, name: &str) -> Result<usize, FrameGraphError> {
+        self.passes
+            .iter()
+            .position(|pass| pass.name == name)
+            .ok_or_else(|| FrameGraphError::UnknownPass(name.to_owned()))
+    }
+
+    fn insert(&mut self, index: usize, pass: RenderPassDesc<P>) -> Result<(), FrameGraphError> {
+        if self.passes.iter().any(|p| p.name == pass.name) {
+            Err(FrameGraphError::DuplicatePass(pass.name))
+        } else {
+            self.passes.insert(index, pass);
+            Ok(())
+        }
+    }
+
+    /// Adds pass to the end of graph.
+    pub fn add_pass(&mut self, pass: RenderPassDesc<P>) -> Result<(), FrameGraphError> {
+        self.insert(self.passes.len(), pass)
+    }
+
+    /// Inserts pass right before pass with given name.
+    pub fn insert_before(
+        &mut self,
+        anchor: &str,
+        pass: RenderPassDesc<P>,
+    ) -> Result<(), FrameGraphError> {
+        let index = self.position(anchor)?;
+        self.insert(index, pass)
+    }
+
+    /// Inserts pass right after pass with given name.
+    pub fn insert_after(
+        &mut self,
+        anchor: &str,
+        pass: RenderPassDesc<P>,
+    ) -> Result<(), FrameGraphError> {
+        let index = self.position(anchor)?;
+        self.insert(index + 1, pass)
+    }
+
+    /// Removes pass with given name.
+    pub fn remove_pass(&mut self, name: &str) -> Option<RenderPassDesc<P>> {
+        self.position(name)
+            .ok()
+            .map(|index| self.passes.remove(index))
+    }
+
+    /// Enables or disables pass, returns false if there is no such pass. Disabled pass
+    /// is never executed, as if it was removed.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.passes.iter_mut().find(|pass| pass.name == name) {
+            Some(pass) => {
+                pass.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns pass with given name.
+    pub fn pass(&self, name: &str) -> Option<&RenderPassDesc<P>> {
+        self.passes.iter().find(|pass| pass.name == name)
+    }
+
+    /// Returns every pass in order of declaration.
+    pub fn passes(&self) -> &[RenderPassDesc<P>] {
+        &self.passes
+    }
+
+    /// Returns passes that must be executed to produce given resources, in order of
+    /// declaration. Pass reading a resource depends on the last pass that wrote it before,
+    /// passes that are not needed by outputs (directly or through dependencies) and have no
+    /// side effects are culled.
+    pub fn compile(&self, outputs: &[FrameResource]) -> Vec<&RenderPassDesc<P>> {
+        let passes = self
+            .passes
+            .iter()
+            .filter(|pass| pass.enabled)
+            .collect::<Vec<_>>();
+
+        let mut last_writers = HashMap::new();
+        let mut dependencies = vec![Vec::new(); passes.len()];
+        for (index, pass) in passes.iter().enumerate() {
+            for resource in pass.reads.iter() {
+                if let Some(&writer) = last_writers.get(resource) {
+                    dependencies[index].push(writer);
+                }
+            }
+            for resource in pass.writes.iter() {
+                last_writers.insert(*resource, index);
+            }
+        }
+
+        let mut stack = outputs
+            .iter()
+            .filter_map(|resource| last_writers.get(resource).cloned())
+            .chain(
+                passes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pass)| pass.side_effects)
+                    .map(|(index, _)| index),
+            )
+            .collect::<Vec<_>>();
+        let mut alive = vec![false; passes.len()];
+        while let Some(index) = stack.pop() {
+            if !alive[index] {
+                alive[index] = true;
+                stack.extend_from_slice(&dependencies[index]);
+            }
+        }
+
+        passes
+            .into_iter()
+            .zip(alive)
+            .filter(|(_, alive)| *alive)
+            .map(|(pass, _)| pass)
+            .collect()
+    }
+}
+
+/// Position of user pass relative to other pass.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PassAnchor {
+    /// Pass is run right before pass with given name.
+    Before(String),
+    /// Pass is run right after pass with given name.
+    After(String),
+}
+
+/// User render pass of camera, see module docs.
+#[derive(Clone, Debug)]
+pub struct UserPass {
+    job: Handle<GpuJob>,
+    anchor: PassAnchor,
+    writes_frame: bool,
+}
+
+impl UserPass {
+    /// Creates new pass which runs given job after post effects and does not write frame.
+    pub fn new(job: Handle<GpuJob>) -> Self {
+        Self {
+            job,
+            anchor: PassAnchor::After(POST_EFFECTS_PASS.to_owned()),
+            writes_frame: false,
+        }
+    }
+
+    /// Runs pass right before pass with given name, it can be other user pass.
+    pub fn before<N: AsRef<str>>(mut self, pass: N) -> Self {
+        self.anchor = PassAnchor::Before(pass.as_ref().to_owned());
+        self
+    }
+
+    /// Runs pass right after pass with given name, it can be other user pass.
+    pub fn after<N: AsRef<str>>(mut self, pass: N) -> Self {
+        self.anchor = PassAnchor::After(pass.as_ref().to_owned());
+        self
+    }
+
+    /// Sets whether result of job replaces frame of camera.
+    pub fn with_frame_write(mut self, writes_frame: bool) -> Self {
+        self.writes_frame = writes_frame;
+        self
+    }
+
+    /// Returns job of pass.
+    pub fn job(&self) -> Handle<GpuJob> {
+        self.job
+    }
+
+    /// Returns position of pass.
+    pub fn anchor(&self) -> &PassAnchor {
+        &self.anchor
+    }
+
+    /// Returns true if result of job replaces frame of camera.
+    pub fn writes_frame(&self) -> bool {
+        self.writes_frame
+    }
+}
+
+/// Pass configuration of a camera.
+#[derive(Default)]
+pub(in crate) struct CameraPasses {
+    disabled: HashSet<String>,
+    // User passes in order of addition, passes are inserted in this order.
+    user_passes: Vec<(String, UserPass)>,
+}
+
+/// Tells renderer how to execute pass.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(in crate) enum PassKind {
+    GBuffer,
+    Lighting,
+    Forward,
+    Particles,
+    Sprites,
+    PostEffects,
+    Immediate,
+    Debug,
+    Present,
+    User {
+        job: Handle<GpuJob>,
+        writes_frame: bool,
+    },
+}
+
+fn is_builtin_pass(name: &str) -> bool {
+    [
+        GBUFFER_PASS,
+        LIGHTING_PASS,
+        FORWARD_PASS,
+        PARTICLES_PASS,
+        SPRITES_PASS,
+        POST_EFFECTS_PASS,
+        IMMEDIATE_PASS,
+        DEBUG_PASS,
+        PRESENT_PASS,
+    ]
+    .contains(&name)
+}
+
+/// Builds frame graph of camera and returns it with resource that is output of camera.
+pub(in crate) fn camera_frame_graph(
+    render_path: RenderPath,
+    has_render_target: bool,
+    passes: Option<&CameraPasses>,
+    jobs: &GpuJobs,
+) -> (FrameGraph<PassKind>, FrameResource) {
+    use FrameResource::*;
+
+    let mut graph = FrameGraph::new();
+    let mut builtin = |name: &str, kind, reads: &[FrameResource], writes: &[FrameResource]| {
+        // Names of built-in passes are unique.
+        let _ = graph.add_pass(
+            RenderPassDesc::new(name, kind)
+                .with_reads(reads)
+                .with_writes(writes),
+        );
+    };
+    match render_path {
+        RenderPath::Deferred => {
+            builtin(GBUFFER_PASS, PassKind::GBuffer, &[], &[GBuffer, Depth]);
+            builtin(
+                LIGHTING_PASS,
+                PassKind::Lighting,
+                &[GBuffer, Depth],
+                &[Frame],
+            );
+        }
+        RenderPath::Forward => builtin(FORWARD_PASS, PassKind::Forward, &[], &[Frame, Depth]),
+    }
+    for &(name, kind) in [
+        (PARTICLES_PASS, PassKind::Particles),
+        (SPRITES_PASS, PassKind::Sprites),
+        (POST_EFFECTS_PASS, PassKind::PostEffects),
+        (IMMEDIATE_PASS, PassKind::Immediate),
+        (DEBUG_PASS, PassKind::Debug),
+    ]
+    .iter()
+    {
+        // These passes draw over frame and use depth of camera.
+        builtin(name, kind, &[Frame, Depth], &[Frame]);
+    }
+    let output = if has_render_target {
+        Frame
+    } else {
+        builtin(PRESENT_PASS, PassKind::Present, &[Frame], &[Backbuffer]);
+        Backbuffer
+    };
+
+    if let Some(passes) = passes {
+        for (name, user_pass) in passes.user_passes.iter() {
+            let job = match jobs.job(user_pass.job) {
+                Some(job) => job,
+                None => continue,
+            };
+            let mut reads = Vec::new();
+            for (_, input) in job.inputs() {
+                match input {
+                    GpuJobInput::CameraFrame => reads.push(Frame),
+                    GpuJobInput::CameraDepth => reads.push(Depth),
+                    GpuJobInput::Target(target) => reads.push(Target(*target)),
+                    GpuJobInput::Texture(_) => (),
+                }
+            }
+            let mut writes = vec![Target(job.target())];
+            if user_pass.writes_frame {
+                writes.push(Frame);
+            }
+            let pass = RenderPassDesc::new(
+                name,
+                PassKind::User {
+                    job: user_pass.job,
+                    writes_frame: user_pass.writes_frame,
+                },
+            )
+            .with_reads(&reads)
+            .with_writes(&writes)
+            .with_side_effects(!user_pass.writes_frame);
+            let result = match &user_pass.anchor {
+                PassAnchor::Before(anchor) => graph.insert_before(anchor, pass),
+                PassAnchor::After(anchor) => graph.insert_after(anchor, pass),
+            };
+            if let Err(e) = result {
+                Log::writeln(format!("Unable to add user pass {}: {}", name, e));
+            }
+        }
+
+        for name in passes.disabled.iter() {
+            graph.set_enabled(name, false);
+        }
+    }
+
+    (graph, output)
+}
+
+/// Builds frame graph of camera rendered to a texture (screenshot, quality calibration)
+/// and returns passes to execute. Debug geometry is not drawn.
+pub(in crate) fn offscreen_passes(
+    render_path: RenderPath,
+    passes: Option<&CameraPasses>,
+    jobs: &GpuJobs,
+) -> Vec<PassKind> {
+    let (mut graph, output) = camera_frame_graph(render_path, true, passes, jobs);
+    graph.set_enabled(DEBUG_PASS, false);
+    graph
+        .compile(&[output])
+        .into_iter()
+        .map(|pass| *pass.payload())
+        .collect()
+}
+
+impl Renderer {
+    /// Enables or disables pass of given camera of given scene, see module docs for names
+    /// of built-in passes. Disabled pass is not executed, and passes that produce data only
+    /// for it are culled.
+    pub fn set_render_pass_enabled<N: AsRef<str>>(
+        &mut self,
+        scene: Handle<Scene>,
+        camera: Handle<Node>,
+        pass: N,
+        enabled: bool,
+    ) {
+        let passes = self.camera_passes.entry((scene, camera)).or_default();
+        if enabled {
+            passes.disabled.remove(pass.as_ref());
+        } else {
+            passes.disabled.insert(pass.as_ref().to_owned());
+        }
+    }
+
+    /// Returns true if pass of given camera is not disabled.
+    pub fn is_render_pass_enabled(
+        &self,
+        scene: Handle<Scene>,
+        camera: Handle<Node>,
+        pass: &str,
+    ) -> bool {
+        self.camera_passes
+            .get(&(scene, camera))
+            .map_or(true, |passes| !passes.disabled.contains(pass))
+    }
+
+    /// Adds user pass to given camera. Anchor of pass is checked when camera is rendered,
+    /// pass with unknown anchor is skipped with a message in log.
+    pub fn add_user_pass<N: AsRef<str>>(
+        &mut self,
+        scene: Handle<Scene>,
+        camera: Handle<Node>,
+        name: N,
+        pass: UserPass,
+    ) -> Result<(), FrameGraphError> {
+        let name = name.as_ref();
+        let passes = self.camera_passes.entry((scene, camera)).or_default();
+        if is_builtin_pass(name) || passes.user_passes.iter().any(|(n, _)| n == name) {
+            return Err(FrameGraphError::DuplicatePass(name.to_owned()));
+        }
+        if self
+            .gpu_jobs
+            .job(pass.job)
+            .map_or(false, |job| job.mode() != GpuJobMode::UserPass)
+        {
+            Log::writeln(format!(
+                "Job of user pass {} is not in UserPass mode, it will be run twice per frame",
+                name
+            ));
+        }
+        passes.user_passes.push((name.to_owned(), pass));
+        Ok(())
+    }
+
+    /// Removes user pass of given camera.
+    pub fn remove_user_pass(
+        &mut self,
+        scene: Handle<Scene>,
+        camera: Handle<Node>,
+        name: &str,
+    ) -> Option<UserPass> {
+        let passes = self.camera_passes.get_mut(&(scene, camera))?;
+        let index = passes.user_passes.iter().position(|(n, _)| n == name)?;
+        Some(passes.user_passes.remove(index).1)
+    }
+
+    /// Removes every user pass and enables every pass of given camera.
+    pub fn reset_render_passes(&mut self, scene: Handle<Scene>, camera: Handle<Node>) {
+        self.camera_passes.remove(&(scene, camera));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        renderer::{
+            frame_graph::{
+                camera_frame_graph, FrameGraph, FrameGraphError, FrameResource::*, PassKind,
+                RenderPassDesc, GBUFFER_PASS, LIGHTING_PASS, PRESENT_PASS,
+            },
+            gpu_job::GpuJobs,
+        },
+        scene::RenderPath,
+    };
+
+    #[test]
+    fn test_frame_graph_culling() {
+        let mut graph = FrameGraph::new();
+        graph
+            .add_pass(RenderPassDesc::new("A", ()).with_writes(&[GBuffer]))
+            .unwrap();
+        graph
+            .add_pass(
+                RenderPassDesc::new("B", ())
+                    .with_reads(&[GBuffer])
+                    .with_writes(&[Frame]),
+            )
+            .unwrap();
+        graph
+            .add_pass(RenderPassDesc::new("C", ()).with_writes(&[Depth]))
+            .unwrap();
+        graph
+            .add_pass(
+                RenderPassDesc::new("E", ())
+                    .with_reads(&[Frame])
+                    .with_writes(&[Frame]),
+            )
+            .unwrap();
+        graph
+            .insert_before(
+                "E",
+                RenderPassDesc::new("D", ())
+                    .with_reads(&[Frame])
+                    .with_writes(&[Frame]),
+            )
+            .unwrap();
+        assert_eq!(
+            graph.add_pass(RenderPassDesc::new("A", ())),
+            Err(FrameGraphError::DuplicatePass("A".to_owned()))
+        );
+        assert!(graph
+            .insert_after("F", RenderPassDesc::new("G", ()))
+            .is_err());
+
+        let names = |graph: &FrameGraph<()>| {
+            graph
+                .compile(&[Frame])
+                .iter()
+                .map(|pass| pass.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+        // C does not contribute to output.
+        assert_eq!(names(&graph), vec!["A", "B", "D", "E"]);
+
+        graph.set_enabled("E", false);
+        assert_eq!(names(&graph), vec!["A", "B", "D"]);
+
+        // Nothing reads G-buffer anymore.
+        graph.set_enabled("B", false);
+        assert_eq!(names(&graph), vec!["D"]);
+
+        graph
+            .add_pass(RenderPassDesc::new("H", ()).with_side_effects(true))
+            .unwrap();
+        assert_eq!(names(&graph), vec!["D", "H"]);
+    }
+
+    #[test]
+    fn test_camera_frame_graph() {
+        let jobs = GpuJobs::default();
+        let kinds = |graph: &FrameGraph<PassKind>| {
+            graph
+                .compile(&[Backbuffer])
+                .iter()
+                .map(|pass| *pass.payload())
+                .collect::<Vec<_>>()
+        };
+
+        let (mut graph, output) = camera_frame_graph(RenderPath::Deferred, false, None, &jobs);
+        assert_eq!(output, Backbuffer);
+        assert_eq!(kinds(&graph).len(), graph.passes().len());
+
+        // Passes drawing over frame read depth, so G-buffer is still filled.
+        graph.set_enabled(LIGHTING_PASS, false);
+        assert!(kinds(&graph).contains(&PassKind::GBuffer));
+        assert!(!kinds(&graph).contains(&PassKind::Lighting));
+
+        graph.set_enabled(PRESENT_PASS, false);
+        assert!(kinds(&graph).is_empty());
+
+        let (graph, output) = camera_frame_graph(RenderPath::Forward, true, None, &jobs);
+        assert_eq!(output, Frame);
+        assert!(graph.pass(GBUFFER_PASS).is_none());
+        assert!(graph.pass(PRESENT_PASS).is_none());
+    }
+}
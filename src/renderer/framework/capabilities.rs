@@ -0,0 +1,156 @@
+use crate::renderer::{
+    capabilities::{
+        merge_extension_formats, CompressedTextureFormat, GpuCapabilities, VideoMemory,
+    },
+    framework::gl::{
+        self,
+        types::{GLenum, GLfloat, GLint, GLuint},
+    },
+};
+use std::{collections::HashSet, ffi::CStr};
+
+// Constants of extensions which are not part of generated bindings.
+const COMPRESSED_RGB_S3TC_DXT1_EXT: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1_EXT: GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3_EXT: GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5_EXT: GLenum = 0x83F3;
+const COMPRESSED_RGBA_ASTC_4X4_KHR: GLenum = 0x93B0;
+const COMPRESSED_SRGB8_ALPHA8_ASTC_12X12_KHR: GLenum = 0x93DD;
+const GPU_MEMORY_INFO_DEDICATED_VIDMEM_NVX: GLenum = 0x9047;
+const GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX: GLenum = 0x9049;
+const TEXTURE_FREE_MEMORY_ATI: GLenum = 0x87FC;
+
+// Max amount of error flags to clear, OpenGL has only a few kinds of errors.
+const MAX_ERROR_FLAGS: usize = 16;
+
+fn compressed_format(format: GLenum) -> Option<CompressedTextureFormat> {
+    match format {
+        COMPRESSED_RGB_S3TC_DXT1_EXT | COMPRESSED_RGBA_S3TC_DXT1_EXT => {
+            Some(CompressedTextureFormat::Dxt1)
+        }
+        COMPRESSED_RGBA_S3TC_DXT3_EXT => Some(CompressedTextureFormat::Dxt3),
+        COMPRESSED_RGBA_S3TC_DXT5_EXT => Some(CompressedTextureFormat::Dxt5),
+        gl::COMPRESSED_RED_RGTC1
+        | gl::COMPRESSED_SIGNED_RED_RGTC1
+        | gl::COMPRESSED_RG_RGTC2
+        | gl::COMPRESSED_SIGNED_RG_RGTC2 => Some(CompressedTextureFormat::Rgtc),
+        gl::COMPRESSED_RGBA_BPTC_UNORM
+        | gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM
+        | gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT
+        | gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT => Some(CompressedTextureFormat::Bptc),
+        gl::COMPRESSED_RGB8_ETC2
+        | gl::COMPRESSED_SRGB8_ETC2
+        | gl::COMPRESSED_RGBA8_ETC2_EAC
+        | gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC
+        | gl::COMPRESSED_R11_EAC
+        | gl::COMPRESSED_RG11_EAC => Some(CompressedTextureFormat::Etc2),
+        COMPRESSED_RGBA_ASTC_4X4_KHR..=COMPRESSED_SRGB8_ALPHA8_ASTC_12X12_KHR => {
+            Some(CompressedTextureFormat::Astc)
+        }
+        _ => None,
+    }
+}
+
+unsafe fn get_string(name: GLenum) -> String {
+    let string = gl::GetString(name);
+    if string.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(string as *const _)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+unsafe fn get_integer(name: GLenum) -> u32 {
+    let mut value: GLint = 0;
+    gl::GetIntegerv(name, &mut value);
+    value.max(0) as u32
+}
+
+/// Queries capabilities of current OpenGL context. Must be called after OpenGL functions
+/// were loaded.
+pub fn query() -> GpuCapabilities {
+    unsafe {
+        let mut extensions = HashSet::new();
+        for i in 0..get_integer(gl::NUM_EXTENSIONS) {
+            let name = gl::GetStringi(gl::EXTENSIONS, i as GLuint);
+            if !name.is_null() {
+                extensions.insert(
+                    CStr::from_ptr(name as *const _)
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+        }
+
+        let mut gl_formats = vec![0; get_integer(gl::NUM_COMPRESSED_TEXTURE_FORMATS) as usize];
+        if !gl_formats.is_empty() {
+            gl::GetIntegerv(gl::COMPRESSED_TEXTURE_FORMATS, gl_formats.as_mut_ptr());
+        }
+        let mut compressed_formats = Vec::new();
+        for format in gl_formats
+            .into_iter()
+            .filter_map(|f| compressed_format(f as GLenum))
+        {
+            if !compressed_formats.contains(&format) {
+                compressed_formats.push(format);
+            }
+        }
+        merge_extension_formats(&mut compressed_formats, &extensions);
+
+        let max_anisotropy = if extensions.contains("GL_EXT_texture_filter_anisotropic")
+            || extensions.contains("GL_ARB_texture_filter_anisotropic")
+        {
+            let mut value: GLfloat = 1.0;
+            gl::GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut value);
+            value.max(1.0)
+        } else {
+            1.0
+        };
+
+        let video_memory = if extensions.contains("GL_NVX_gpu_memory_info") {
+            VideoMemory {
+                total_kb: Some(get_integer(GPU_MEMORY_INFO_DEDICATED_VIDMEM_NVX)),
+                available_kb: Some(get_integer(GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX)),
+            }
+        } else if extensions.contains("GL_ATI_meminfo") {
+            // First value is total amount of free memory in pool, total size of memory
+            // is not reported.
+            let mut values: [GLint; 4] = [0; 4];
+            gl::GetIntegerv(TEXTURE_FREE_MEMORY_ATI, values.as_mut_ptr());
+            VideoMemory {
+                total_kb: None,
+                available_kb: Some(values[0].max(0) as u32),
+            }
+        } else {
+            VideoMemory::default()
+        };
+
+        // Some queries above are legal only with extensions, make sure error flag is not
+        // left set for someone else. There is one flag per error kind, but lost context
+        // may report error forever, so amount of attempts is limited.
+        for _ in 0..MAX_ERROR_FLAGS {
+            if gl::GetError() == gl::NO_ERROR {
+                break;
+            }
+        }
+
+        GpuCapabilities {
+            vendor: get_string(gl::VENDOR),
+            renderer: get_string(gl::RENDERER),
+            version: get_string(gl::VERSION),
+            shading_language_version: get_string(gl::SHADING_LANGUAGE_VERSION),
+            max_texture_size: get_integer(gl::MAX_TEXTURE_SIZE),
+            max_cube_map_texture_size: get_integer(gl::MAX_CUBE_MAP_TEXTURE_SIZE),
+            max_3d_texture_size: get_integer(gl::MAX_3D_TEXTURE_SIZE),
+            max_samples: get_integer(gl::MAX_SAMPLES),
+            max_color_attachments: get_integer(gl::MAX_COLOR_ATTACHMENTS),
+            max_texture_image_units: get_integer(gl::MAX_TEXTURE_IMAGE_UNITS),
+            max_anisotropy,
+            video_memory,
+            compressed_formats,
+            extensions,
+        }
+    }
+}
@@ -263,6 +263,58 @@ pub trait FrameBufferTrait {
         }
     }
 
+    /// Reads RGBA8 pixels of first color attachment in given viewport. Rows are returned
+    /// from top to bottom.
+    fn read_pixels(&self, state: &mut State, viewport: Rect<i32>) -> Vec<u8> {
+        scope_profile!();
+
+        state.set_framebuffer(self.id());
+
+        let row_size = viewport.w.max(0) as usize * 4;
+        let mut pixels = vec![0u8; row_size * viewport.h.max(0) as usize];
+        unsafe {
+            gl::ReadPixels(
+                viewport.x,
+                viewport.y,
+                viewport.w,
+                viewport.h,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut _,
+            );
+        }
+
+        // OpenGL has origin at left bottom corner, flip rows to get usual image layout.
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for row in pixels.chunks(row_size.max(1)).rev() {
+            flipped.extend_from_slice(row);
+        }
+        flipped
+    }
+
+    /// Reads pixels of first color attachment in given viewport as four floats per pixel,
+    /// this is lossless for float textures. Rows are returned in OpenGL order - from bottom
+    /// to top, which is the same order in which texture data is uploaded.
+    fn read_pixels_f32(&self, state: &mut State, viewport: Rect<i32>) -> Vec<f32> {
+        scope_profile!();
+
+        state.set_framebuffer(self.id());
+
+        let mut pixels = vec![0.0f32; viewport.w.max(0) as usize * viewport.h.max(0) as usize * 4];
+        unsafe {
+            gl::ReadPixels(
+                viewport.x,
+                viewport.y,
+                viewport.w,
+                viewport.h,
+                gl::RGBA,
+                gl::FLOAT,
+                pixels.as_mut_ptr() as *mut _,
+            );
+        }
+        pixels
+    }
+
     fn draw<T>(
         &mut self,
         geometry: &GeometryBuffer<T>,
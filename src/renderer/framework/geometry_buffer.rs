@@ -8,20 +8,35 @@ use crate::{
                 types::{GLint, GLuint},
             },
             state::State,
+            streaming_buffer::StreamingBuffer,
         },
         TriangleDefinition,
     },
     utils::log::Log,
 };
-use std::{cell::Cell, ffi::c_void, marker::PhantomData, mem::size_of};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+    marker::PhantomData,
+    mem::size_of,
+};
 
 /// Safe wrapper over OpenGL's Vertex Array Objects for interleaved vertices (where
 /// position, normal, etc. stored together, not in separate arrays)
 /// WARNING: T must have repr(C) attribute!
 pub struct GeometryBuffer<T> {
     vertex_array_object: GLuint,
-    vertex_buffer_object: GLuint,
-    element_buffer_object: GLuint,
+    vertex_buffer_object: Cell<GLuint>,
+    element_buffer_object: Cell<GLuint>,
+    /// Ring buffers for vertices and elements, dynamic buffers only.
+    streams: Option<RefCell<Streams>>,
+    /// Attributes are kept to be able to re-describe them when streaming vertex buffer
+    /// was re-created.
+    attributes: RefCell<Vec<AttributeDefinition>>,
+    /// Offset of first vertex in vertex buffer, non-zero for dynamic buffers only.
+    base_vertex: Cell<usize>,
+    /// Offset of first index in element buffer, non-zero for dynamic buffers only.
+    element_offset: Cell<usize>,
     meta: PhantomData<T>,
     kind: GeometryBufferKind,
     element_count: Cell<usize>,
@@ -30,6 +45,11 @@ pub struct GeometryBuffer<T> {
     thread_mark: PhantomData<*const u8>,
 }
 
+struct Streams {
+    vertices: StreamingBuffer,
+    elements: StreamingBuffer,
+}
+
 #[derive(Copy, Clone)]
 #[allow(dead_code)]
 pub enum AttributeKind {
@@ -54,6 +74,7 @@ pub enum AttributeKind {
     UnsignedInt4,
 }
 
+#[derive(Copy, Clone)]
 pub struct AttributeDefinition {
     pub kind: AttributeKind,
     pub normalized: bool,
@@ -135,6 +156,8 @@ impl AttributeKind {
 
 pub enum GeometryBufferKind {
     StaticDraw,
+    /// Data is re-uploaded every frame. Such buffers use ring buffers to not stall
+    /// pipeline, see `StreamingBuffer` docs.
     DynamicDraw,
 }
 
@@ -153,8 +176,33 @@ impl ElementKind {
     }
 }
 
+/// Sets attribute pointers of currently bound vertex array object to currently bound
+/// vertex buffer.
+unsafe fn set_attribute_pointers<T>(definitions: &[AttributeDefinition]) {
+    let vertex_size = size_of::<T>();
+    let mut offset = 0;
+    for (index, definition) in definitions.iter().enumerate() {
+        let index = index as u32;
+        let size = definition.kind.length();
+        let type_ = definition.kind.get_type();
+        let normalized = if definition.normalized {
+            gl::TRUE
+        } else {
+            gl::FALSE
+        };
+        let stride = vertex_size as i32;
+        let pointer = offset as *const c_void;
+
+        gl::VertexAttribPointer(index, size, type_, normalized, stride, pointer);
+        gl::EnableVertexAttribArray(index);
+
+        offset += definition.kind.size_bytes();
+    }
+}
+
 pub struct GeometryBufferBinding<'a, T> {
     buffer: &'a GeometryBuffer<T>,
+    state: &'a mut State,
 }
 
 #[derive(Copy, Clone)]
@@ -170,15 +218,52 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
         }
     }
 
-    pub fn set_vertices(self, vertices: &[T]) -> Self {
+    pub fn set_vertices(mut self, vertices: &[T]) -> Self {
         scope_profile!();
 
+        let size = vertices.len() * size_of::<T>();
+        let data = vertices.as_ptr() as *const c_void;
+
+        if let Some(streams) = self.buffer.streams.as_ref() {
+            let mut streams = streams.borrow_mut();
+            let bytes = unsafe { std::slice::from_raw_parts(data as *const u8, size) };
+            let offset = streams.vertices.write(bytes, size_of::<T>());
+            self.buffer.base_vertex.set(offset / size_of::<T>());
+
+            let id = streams.vertices.id();
+            if id != self.buffer.vertex_buffer_object.get() {
+                // Storage was re-created, attributes must point to new buffer.
+                self.buffer.vertex_buffer_object.set(id);
+                self.state.set_vertex_buffer_object(id);
+                unsafe {
+                    set_attribute_pointers::<T>(&self.buffer.attributes.borrow());
+                }
+            }
+        } else {
+            let usage = self.get_usage();
+
+            unsafe {
+                gl::BufferData(gl::ARRAY_BUFFER, size as isize, data, usage);
+            }
+        }
+
+        self
+    }
+
+    /// Uploads given vertices into existing vertex buffer starting from `offset` vertex.
+    /// Vertex buffer must be large enough to fit new data. Static buffers only, dynamic
+    /// buffers must be re-uploaded entirely.
+    pub fn set_vertices_range(self, offset: usize, vertices: &[T]) -> Self {
+        scope_profile!();
+
+        debug_assert!(self.buffer.streams.is_none());
+
+        let offset = (offset * size_of::<T>()) as isize;
         let size = (vertices.len() * size_of::<T>()) as isize;
         let data = vertices.as_ptr() as *const c_void;
-        let usage = self.get_usage();
 
         unsafe {
-            gl::BufferData(gl::ARRAY_BUFFER, size, data, usage);
+            gl::BufferSubData(gl::ARRAY_BUFFER, offset, size, data);
         }
 
         self
@@ -191,31 +276,20 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
         scope_profile!();
 
         let vertex_size = size_of::<T>();
-        let mut offset = 0;
-        for (index, definition) in definitions.iter().enumerate() {
-            let index = index as u32;
-            let size = definition.kind.length();
-            let type_ = definition.kind.get_type();
-            let normalized = if definition.normalized {
-                gl::TRUE
-            } else {
-                gl::FALSE
-            };
-            let stride = vertex_size as i32;
-            let pointer = offset as *const c_void;
-
-            unsafe {
-                gl::VertexAttribPointer(index, size, type_, normalized, stride, pointer);
-                gl::EnableVertexAttribArray(index);
-            }
-
-            offset += definition.kind.size_bytes();
+        let total_size = definitions
+            .iter()
+            .map(|definition| definition.kind.size_bytes())
+            .sum::<usize>();
+        if total_size > vertex_size {
+            return Err(RendererError::InvalidAttributeDescriptor);
+        }
 
-            if offset > vertex_size {
-                return Err(RendererError::InvalidAttributeDescriptor);
-            }
+        unsafe {
+            set_attribute_pointers::<T>(&definitions);
         }
 
+        *self.buffer.attributes.borrow_mut() = definitions;
+
         Ok(self)
     }
 
@@ -252,8 +326,18 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
     unsafe fn set_elements(&self, elements: *const c_void, size: isize) {
         scope_profile!();
 
-        let usage = self.get_usage();
-        gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, elements, usage);
+        if let Some(streams) = self.buffer.streams.as_ref() {
+            let mut streams = streams.borrow_mut();
+            let bytes = std::slice::from_raw_parts(elements as *const u8, size as usize);
+            let offset = streams.elements.write(bytes, size_of::<u32>());
+            self.buffer.element_offset.set(offset / size_of::<u32>());
+            // Element buffer binding is stored in vertex array object, so new buffer
+            // is already bound to it.
+            self.buffer.element_buffer_object.set(streams.elements.id());
+        } else {
+            let usage = self.get_usage();
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, elements, usage);
+        }
     }
 
     pub fn draw_part(
@@ -309,8 +393,20 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
         scope_profile!();
 
         if index_count > 0 {
+            let start_index = self.buffer.element_offset.get() + start_index;
             let indices = (start_index * size_of::<u32>()) as *const c_void;
-            gl::DrawElements(self.mode(), index_count as i32, gl::UNSIGNED_INT, indices);
+            let base_vertex = self.buffer.base_vertex.get();
+            if base_vertex == 0 {
+                gl::DrawElements(self.mode(), index_count as i32, gl::UNSIGNED_INT, indices);
+            } else {
+                gl::DrawElementsBaseVertex(
+                    self.mode(),
+                    index_count as i32,
+                    gl::UNSIGNED_INT,
+                    indices,
+                    base_vertex as i32,
+                );
+            }
         }
     }
 }
@@ -326,11 +422,28 @@ where
             let mut vao = 0;
             gl::GenVertexArrays(1, &mut vao);
 
-            let mut vbo = 0;
-            gl::GenBuffers(1, &mut vbo);
-
-            let mut ebo = 0;
-            gl::GenBuffers(1, &mut ebo);
+            let (vbo, ebo, streams) = match kind {
+                GeometryBufferKind::StaticDraw => {
+                    let mut vbo = 0;
+                    gl::GenBuffers(1, &mut vbo);
+
+                    let mut ebo = 0;
+                    gl::GenBuffers(1, &mut ebo);
+
+                    (vbo, ebo, None)
+                }
+                GeometryBufferKind::DynamicDraw => {
+                    let streams = Streams {
+                        vertices: StreamingBuffer::new(gl::ARRAY_BUFFER),
+                        elements: StreamingBuffer::new(gl::ELEMENT_ARRAY_BUFFER),
+                    };
+                    (
+                        streams.vertices.id(),
+                        streams.elements.id(),
+                        Some(RefCell::new(streams)),
+                    )
+                }
+            };
 
             Log::writeln(format!(
                 "GL geometry buffer was created - VBO: {}, EBO: {}, VAO: {}!",
@@ -339,8 +452,12 @@ where
 
             Self {
                 vertex_array_object: vao,
-                vertex_buffer_object: vbo,
-                element_buffer_object: ebo,
+                vertex_buffer_object: Cell::new(vbo),
+                element_buffer_object: Cell::new(ebo),
+                streams,
+                attributes: Default::default(),
+                base_vertex: Cell::new(0),
+                element_offset: Cell::new(0),
                 meta: PhantomData,
                 kind,
                 element_count: Cell::new(0),
@@ -350,19 +467,22 @@ where
         }
     }
 
-    pub fn bind(&self, state: &mut State) -> GeometryBufferBinding<'_, T> {
+    pub fn bind<'a>(&'a self, state: &'a mut State) -> GeometryBufferBinding<'a, T> {
         scope_profile!();
 
         state.set_vertex_array_object(self.vertex_array_object);
-        state.set_vertex_buffer_object(self.vertex_buffer_object);
+        state.set_vertex_buffer_object(self.vertex_buffer_object.get());
 
         // Element buffer object binding is stored inside vertex array object, so
         // it does not modified state.
         unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.element_buffer_object);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.element_buffer_object.get());
         }
 
-        GeometryBufferBinding { buffer: self }
+        GeometryBufferBinding {
+            buffer: self,
+            state,
+        }
     }
 }
 
@@ -371,11 +491,16 @@ impl<T> Drop for GeometryBuffer<T> {
         unsafe {
             Log::writeln(format!(
                 "GL geometry buffer was destroyed - VBO: {}, EBO: {}, VAO: {}!",
-                self.vertex_buffer_object, self.element_buffer_object, self.vertex_array_object
+                self.vertex_buffer_object.get(),
+                self.element_buffer_object.get(),
+                self.vertex_array_object
             ));
 
-            gl::DeleteBuffers(1, &self.vertex_buffer_object);
-            gl::DeleteBuffers(1, &self.element_buffer_object);
+            // Streaming buffers are destroyed by themselves.
+            if self.streams.is_none() {
+                gl::DeleteBuffers(1, &self.vertex_buffer_object.get());
+                gl::DeleteBuffers(1, &self.element_buffer_object.get());
+            }
             gl::DeleteVertexArrays(1, &self.vertex_array_object);
         }
     }
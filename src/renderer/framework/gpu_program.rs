@@ -178,6 +178,24 @@ impl GpuProgram {
         }
     }
 
+    /// Assigns given binding point to uniform block with specified name, data of the
+    /// block is taken from buffer range bound to that point (see `uniform_ring` module).
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) -> Result<(), RendererError> {
+        let buf = &mut self.name_buf.borrow_mut();
+        buf.clear();
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(0);
+        unsafe {
+            let index = gl::GetUniformBlockIndex(self.id, buf.as_ptr() as *const i8);
+            if index == gl::INVALID_INDEX {
+                Err(RendererError::UnableToFindShaderUniform(name.to_owned()))
+            } else {
+                gl::UniformBlockBinding(self.id, index, binding);
+                Ok(())
+            }
+        }
+    }
+
     pub fn bind(&self, state: &mut State) {
         state.set_program(self.id);
     }
@@ -46,6 +46,7 @@ impl GpuTextureKind {
 #[derive(Copy, Clone)]
 pub enum PixelKind {
     F32,
+    RGBA32F,
     D32,
     D24S8,
     RGBA8,
@@ -60,6 +61,8 @@ impl From<TextureKind> for PixelKind {
             TextureKind::R8 => PixelKind::R8,
             TextureKind::RGB8 => PixelKind::RGB8,
             TextureKind::RGBA8 => PixelKind::RGBA8,
+            TextureKind::R32F => PixelKind::F32,
+            TextureKind::RGBA32F => PixelKind::RGBA32F,
         }
     }
 }
@@ -74,6 +77,7 @@ pub struct GpuTexture {
 impl PixelKind {
     fn size_bytes(self) -> usize {
         match self {
+            PixelKind::RGBA32F => 16,
             PixelKind::RGBA8 | PixelKind::D24S8 | PixelKind::D32 | PixelKind::F32 => 4,
             PixelKind::RGB8 => 3,
             PixelKind::RG8 => 2,
@@ -87,11 +91,30 @@ impl PixelKind {
             | PixelKind::RGB8
             | PixelKind::D24S8
             | PixelKind::D32
-            | PixelKind::F32 => 4,
+            | PixelKind::F32
+            | PixelKind::RGBA32F => 4,
             PixelKind::RG8 => 2,
             PixelKind::R8 => 1,
         }
     }
+
+    /// Returns type, format and internal format of pixels for GL.
+    fn gl_formats(self) -> (GLuint, GLuint, GLuint) {
+        match self {
+            PixelKind::F32 => (gl::FLOAT, gl::RED, gl::R32F),
+            PixelKind::RGBA32F => (gl::FLOAT, gl::RGBA, gl::RGBA32F),
+            PixelKind::D32 => (gl::FLOAT, gl::DEPTH_COMPONENT, gl::DEPTH_COMPONENT),
+            PixelKind::D24S8 => (
+                gl::UNSIGNED_INT_24_8,
+                gl::DEPTH_STENCIL,
+                gl::DEPTH24_STENCIL8,
+            ),
+            PixelKind::RGBA8 => (gl::UNSIGNED_BYTE, gl::RGBA, gl::RGBA8),
+            PixelKind::RGB8 => (gl::UNSIGNED_BYTE, gl::RGB, gl::RGB8),
+            PixelKind::RG8 => (gl::UNSIGNED_BYTE, gl::RG, gl::RG8),
+            PixelKind::R8 => (gl::UNSIGNED_BYTE, gl::RED, gl::R8),
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -247,6 +270,49 @@ impl<'a> TextureBinding<'a> {
         }
         self
     }
+
+    /// Sets index of the most detailed mip level that can be sampled, levels above it
+    /// are ignored and can be empty.
+    pub fn set_base_level(self, level: u32) -> Self {
+        unsafe {
+            gl::TexParameteri(
+                self.texture.kind.to_texture_target(),
+                gl::TEXTURE_BASE_LEVEL,
+                level as i32,
+            );
+        }
+        self
+    }
+
+    /// Replaces contents of given mip level of rectangle texture. Zero size frees memory
+    /// of the level.
+    pub fn set_mip_level(
+        self,
+        level: u32,
+        width: usize,
+        height: usize,
+        pixel_kind: PixelKind,
+        data: Option<&[u8]>,
+    ) -> Self {
+        if let GpuTextureKind::Rectangle { .. } = self.texture.kind {
+            let (type_, format, internal_format) = pixel_kind.gl_formats();
+            unsafe {
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, pixel_kind.unpack_alignment());
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    level as i32,
+                    internal_format as i32,
+                    width as i32,
+                    height as i32,
+                    0,
+                    format,
+                    type_,
+                    data.map_or(std::ptr::null(), |data| data.as_ptr() as *const c_void),
+                );
+            }
+        }
+        self
+    }
 }
 
 impl GpuTexture {
@@ -295,19 +361,7 @@ impl GpuTexture {
 
             state.set_texture(0, target, texture);
 
-            let (type_, format, internal_format) = match pixel_kind {
-                PixelKind::F32 => (gl::FLOAT, gl::RED, gl::R32F),
-                PixelKind::D32 => (gl::FLOAT, gl::DEPTH_COMPONENT, gl::DEPTH_COMPONENT),
-                PixelKind::D24S8 => (
-                    gl::UNSIGNED_INT_24_8,
-                    gl::DEPTH_STENCIL,
-                    gl::DEPTH24_STENCIL8,
-                ),
-                PixelKind::RGBA8 => (gl::UNSIGNED_BYTE, gl::RGBA, gl::RGBA8),
-                PixelKind::RGB8 => (gl::UNSIGNED_BYTE, gl::RGB, gl::RGB8),
-                PixelKind::RG8 => (gl::UNSIGNED_BYTE, gl::RG, gl::RG8),
-                PixelKind::R8 => (gl::UNSIGNED_BYTE, gl::RED, gl::R8),
-            };
+            let (type_, format, internal_format) = pixel_kind.gl_formats();
 
             gl::PixelStorei(gl::UNPACK_ALIGNMENT, pixel_kind.unpack_alignment());
 
@@ -13,11 +13,14 @@ macro_rules! check_gl_error {
     };
 }
 
+pub mod capabilities;
 pub mod framebuffer;
 pub mod geometry_buffer;
 pub mod gpu_program;
 pub mod gpu_texture;
 pub mod state;
+pub mod streaming_buffer;
+pub mod uniform_ring;
 
 pub fn check_gl_error_internal(line: u32, file: &str) {
     unsafe {
@@ -36,6 +36,9 @@ pub struct State {
 
     vao: GLuint,
     vbo: GLuint,
+
+    /// Amount of actual texture binding changes since last `take_texture_binds` call.
+    texture_binds: usize,
 }
 
 #[derive(Copy, Clone)]
@@ -154,6 +157,7 @@ impl State {
             stencil_op: Default::default(),
             vao: 0,
             vbo: 0,
+            texture_binds: 0,
         }
     }
 
@@ -337,6 +341,7 @@ impl State {
         if unit.target != target || unit.texture != texture {
             unit.texture = texture;
             unit.target = target;
+            self.texture_binds += 1;
 
             unsafe {
                 gl::ActiveTexture(gl::TEXTURE0 + sampler_index as u32);
@@ -393,6 +398,10 @@ impl State {
         }
     }
 
+    pub fn take_texture_binds(&mut self) -> usize {
+        std::mem::replace(&mut self.texture_binds, 0)
+    }
+
     pub fn invalidate_resource_bindings_cache(&mut self) {
         self.texture_units = Default::default();
         self.program = 0;
@@ -0,0 +1,266 @@
+//! Streaming buffer is a ring buffer for data which is re-uploaded every frame, like
+//! vertices of user interface, particles, debug lines or per-draw uniform blocks (see
+//! `uniform_ring` module).
+//!
+//! Re-specification of whole buffer on each upload forces driver to wait until GPU
+//! finishes previous draw calls that use the buffer. Instead, every upload is written to
+//! the next free region of the ring, so GPU can still read old data while new data is
+//! written. Two strategies are used depending on what is supported by driver:
+//!
+//! - Persistent mapping (OpenGL 4.4 or GL_ARB_buffer_storage) - buffer is mapped once and
+//! stays mapped for its whole lifetime. Ring is split into segments, each segment is
+//! protected by a fence, so CPU will wait only if it outran GPU by whole ring.
+//! - Orphaning - each upload is written to ring using unsynchronized mapping, when ring
+//! is full its storage is orphaned and driver gives new storage while old one is still
+//! used by GPU.
+
+use crate::{
+    renderer::framework::gl::{
+        self,
+        types::{GLenum, GLint, GLsync, GLuint},
+    },
+    utils::log::Log,
+};
+use std::{
+    ffi::{c_void, CStr},
+    ptr,
+};
+
+/// Amount of segments of persistently mapped ring. Three segments allow CPU to write
+/// one frame while GPU reads another and driver holds one more frame in queue.
+const SEGMENT_COUNT: usize = 3;
+
+/// Minimal capacity of a ring in bytes.
+const MIN_CAPACITY: usize = 64 * 1024;
+
+fn is_persistent_mapping_supported() -> bool {
+    if !gl::BufferStorage::is_loaded() {
+        return false;
+    }
+
+    unsafe {
+        let mut major: GLint = 0;
+        let mut minor: GLint = 0;
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        if major > 4 || (major == 4 && minor >= 4) {
+            return true;
+        }
+
+        let mut extension_count: GLint = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+        for i in 0..extension_count {
+            let name = gl::GetStringi(gl::EXTENSIONS, i as GLuint);
+            if !name.is_null()
+                && CStr::from_ptr(name as *const _).to_bytes() == b"GL_ARB_buffer_storage"
+            {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    let alignment = alignment.max(1);
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// See module docs.
+pub struct StreamingBuffer {
+    id: GLuint,
+    target: GLenum,
+    capacity: usize,
+    head: usize,
+    persistent: bool,
+    /// Pointer to persistently mapped memory, null if buffer is not mapped.
+    mapped: *mut u8,
+    segment: usize,
+    fences: [GLsync; SEGMENT_COUNT],
+}
+
+impl StreamingBuffer {
+    /// Creates new empty streaming buffer for given target (`gl::ARRAY_BUFFER`,
+    /// `gl::ELEMENT_ARRAY_BUFFER` or `gl::UNIFORM_BUFFER`). Storage is allocated on first
+    /// write.
+    pub fn new(target: GLenum) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+        let persistent = is_persistent_mapping_supported();
+        Log::writeln(format!(
+            "GL streaming buffer {} was created, persistent mapping: {}",
+            id, persistent
+        ));
+        Self {
+            id,
+            target,
+            capacity: 0,
+            head: 0,
+            persistent,
+            mapped: ptr::null_mut(),
+            segment: 0,
+            fences: [ptr::null(); SEGMENT_COUNT],
+        }
+    }
+
+    /// Returns OpenGL name of buffer. Name can be changed after write, because persistent
+    /// storage cannot be resized.
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    fn delete_fences(&mut self) {
+        for fence in self.fences.iter_mut() {
+            if !fence.is_null() {
+                unsafe {
+                    gl::DeleteSync(*fence);
+                }
+                *fence = ptr::null();
+            }
+        }
+    }
+
+    unsafe fn allocate(&mut self, capacity: usize) {
+        if self.persistent {
+            // Storage of persistent buffer is immutable, so buffer has to be re-created.
+            if self.capacity != 0 {
+                self.delete_fences();
+                // Deletion of mapped buffer unmaps it.
+                gl::DeleteBuffers(1, &self.id);
+                gl::GenBuffers(1, &mut self.id);
+            }
+            gl::BindBuffer(self.target, self.id);
+            let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+            gl::BufferStorage(self.target, capacity as isize, ptr::null(), flags);
+            self.mapped = gl::MapBufferRange(self.target, 0, capacity as isize, flags) as *mut u8;
+            if self.mapped.is_null() {
+                Log::writeln(format!(
+                    "Unable to map streaming buffer {}, falling back to orphaning.",
+                    self.id
+                ));
+                gl::DeleteBuffers(1, &self.id);
+                gl::GenBuffers(1, &mut self.id);
+                gl::BindBuffer(self.target, self.id);
+                self.persistent = false;
+            }
+        }
+        if !self.persistent {
+            gl::BufferData(self.target, capacity as isize, ptr::null(), gl::STREAM_DRAW);
+        }
+        self.capacity = capacity;
+        self.head = 0;
+        self.segment = 0;
+    }
+
+    /// Waits until GPU finishes to use given segment and marks it as current.
+    unsafe fn enter_segment(&mut self, segment: usize) {
+        let current = &mut self.fences[self.segment];
+        if !current.is_null() {
+            gl::DeleteSync(*current);
+        }
+        *current = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+
+        let fence = self.fences[segment];
+        if !fence.is_null() {
+            loop {
+                let result = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000);
+                if result != gl::TIMEOUT_EXPIRED {
+                    break;
+                }
+            }
+            gl::DeleteSync(fence);
+            self.fences[segment] = ptr::null();
+        }
+
+        self.segment = segment;
+    }
+
+    /// Makes sure that ring has at least given capacity in bytes. Buffer must be bound to
+    /// its target.
+    pub fn reserve(&mut self, capacity: usize) {
+        if capacity > self.capacity {
+            unsafe {
+                self.allocate(capacity);
+            }
+        }
+    }
+
+    /// Writes given data to the buffer and returns offset in bytes at which data was
+    /// written. Offset will be multiple of `alignment`. Buffer must be bound to its
+    /// target.
+    pub fn write(&mut self, data: &[u8], alignment: usize) -> usize {
+        let size = data.len();
+        if size == 0 {
+            return 0;
+        }
+
+        unsafe {
+            // Single upload must fit in a segment, otherwise ring is not a ring anymore.
+            if size > self.capacity / SEGMENT_COUNT {
+                let capacity = (align_up(size, alignment).next_power_of_two() * SEGMENT_COUNT)
+                    .max(MIN_CAPACITY);
+                self.allocate(capacity);
+            }
+
+            let mut offset = align_up(self.head, alignment);
+            if offset + size > self.capacity {
+                offset = 0;
+                if !self.persistent {
+                    // Orphan old storage, it is still used by GPU, but driver will give
+                    // new one without waiting.
+                    gl::BufferData(
+                        self.target,
+                        self.capacity as isize,
+                        ptr::null(),
+                        gl::STREAM_DRAW,
+                    );
+                }
+            }
+
+            if self.persistent {
+                let segment_size = self.capacity / SEGMENT_COUNT;
+                let first = offset / segment_size;
+                let last = ((offset + size - 1) / segment_size).min(SEGMENT_COUNT - 1);
+                for segment in first..=last {
+                    if segment != self.segment {
+                        self.enter_segment(segment);
+                    }
+                }
+                ptr::copy_nonoverlapping(data.as_ptr(), self.mapped.add(offset), size);
+            } else {
+                let access =
+                    gl::MAP_WRITE_BIT | gl::MAP_UNSYNCHRONIZED_BIT | gl::MAP_INVALIDATE_RANGE_BIT;
+                let mapped =
+                    gl::MapBufferRange(self.target, offset as isize, size as isize, access);
+                if mapped.is_null() {
+                    gl::BufferSubData(
+                        self.target,
+                        offset as isize,
+                        size as isize,
+                        data.as_ptr() as *const c_void,
+                    );
+                } else {
+                    ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut u8, size);
+                    gl::UnmapBuffer(self.target);
+                }
+            }
+
+            self.head = offset + size;
+
+            offset
+        }
+    }
+}
+
+impl Drop for StreamingBuffer {
+    fn drop(&mut self) {
+        self.delete_fences();
+        unsafe {
+            Log::writeln(format!("GL streaming buffer {} was destroyed!", self.id));
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Uniform ring is used to pass per-draw data (bone matrices, per-instance data) to
+//! shaders through uniform blocks instead of plain uniforms. Data of each draw is
+//! written to next free region of a streaming buffer (see `streaming_buffer` module) and
+//! written range is bound to binding point of uniform block, so uploads do not force
+//! driver to wait for previous draw calls.
+//!
+//! Shaders must declare blocks with `std140` layout and names listed below.
+
+use crate::{
+    core::{color::Color, math::mat4::Mat4},
+    renderer::framework::{gl, streaming_buffer::StreamingBuffer},
+};
+
+/// Binding point of `BoneMatrices` uniform block.
+pub const BONE_MATRICES_BINDING: u32 = 0;
+
+/// Binding point of `InstanceData` uniform block.
+pub const INSTANCE_DATA_BINDING: u32 = 1;
+
+/// Initial capacity of a ring in bytes. It is large enough to hold bone matrices of
+/// hundreds of skinned surfaces per frame, so CPU does not wait for GPU within a frame.
+const CAPACITY: usize = 4 * 1024 * 1024;
+
+/// Per-instance data of a draw, layout matches `InstanceData` block:
+///
+/// ```glsl
+/// layout(std140) uniform InstanceData
+/// {
+///     mat4 worldMatrix;
+///     mat4 worldViewProjection;
+///     vec4 instanceTint;
+///     float instanceSeed;
+///     float instanceBrightnessJitter;
+/// };
+/// ```
+pub struct InstanceData {
+    pub world: Mat4,
+    pub world_view_projection: Mat4,
+    pub tint: Color,
+    pub seed: f32,
+    pub brightness_jitter: f32,
+}
+
+/// See module docs.
+pub struct UniformRing {
+    buffer: StreamingBuffer,
+    alignment: usize,
+    scratch: Vec<f32>,
+    /// Whether some range of this ring is bound to bone matrices binding point.
+    bones_bound: bool,
+}
+
+impl Default for UniformRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UniformRing {
+    pub fn new() -> Self {
+        let mut alignment = 0;
+        let mut buffer = StreamingBuffer::new(gl::UNIFORM_BUFFER);
+        unsafe {
+            gl::GetIntegerv(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT, &mut alignment);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer.id());
+        }
+        buffer.reserve(CAPACITY);
+        Self {
+            buffer,
+            alignment: alignment.max(1) as usize,
+            scratch: Vec::new(),
+            bones_bound: false,
+        }
+    }
+
+    fn bind_scratch(&mut self, binding: u32) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                self.scratch.as_ptr() as *const u8,
+                self.scratch.len() * std::mem::size_of::<f32>(),
+            )
+        };
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer.id());
+            let offset = self.buffer.write(bytes, self.alignment);
+            // Name of the buffer could be changed by write.
+            gl::BindBufferRange(
+                gl::UNIFORM_BUFFER,
+                binding,
+                self.buffer.id(),
+                offset as isize,
+                bytes.len() as isize,
+            );
+        }
+    }
+
+    /// Binds array of `max_count` bone matrices to `BoneMatrices` block. Shader does not
+    /// read matrices of surfaces without skin, so if `matrices` is empty and some range is
+    /// already bound, nothing is uploaded.
+    pub fn bind_bone_matrices(&mut self, matrices: &[Mat4], max_count: usize) {
+        if matrices.is_empty() && self.bones_bound {
+            return;
+        }
+        self.scratch.clear();
+        for matrix in matrices.iter().take(max_count) {
+            self.scratch.extend_from_slice(&matrix.f);
+        }
+        // Range must cover whole block.
+        self.scratch.resize(max_count * 16, 0.0);
+        self.bind_scratch(BONE_MATRICES_BINDING);
+        self.bones_bound = true;
+    }
+
+    /// Binds given data to `InstanceData` block.
+    pub fn bind_instance_data(&mut self, data: &InstanceData) {
+        let tint = data.tint.as_frgba();
+        self.scratch.clear();
+        self.scratch.extend_from_slice(&data.world.f);
+        self.scratch
+            .extend_from_slice(&data.world_view_projection.f);
+        self.scratch
+            .extend_from_slice(&[tint.x, tint.y, tint.z, tint.w]);
+        // Block size is rounded up to size of vec4 by std140 rules.
+        self.scratch
+            .extend_from_slice(&[data.seed, data.brightness_jitter, 0.0, 0.0]);
+        self.bind_scratch(INSTANCE_DATA_BINDING);
+    }
+}
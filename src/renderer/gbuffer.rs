@@ -1,7 +1,7 @@
 use crate::{
     core::{
         color::Color,
-        math::{frustum::Frustum, mat4::Mat4, Rect},
+        math::{aabb::AxisAlignedBoundingBox, frustum::Frustum, mat4::Mat4, vec3::Vec3, Rect},
         scope_profile,
     },
     renderer::{
@@ -13,23 +13,35 @@ use crate::{
             gpu_program::{GpuProgram, UniformLocation, UniformValue},
             gpu_texture::{Coordinate, GpuTexture, GpuTextureKind, PixelKind, WrapMode},
             state::State,
+            uniform_ring::{
+                InstanceData, UniformRing, BONE_MATRICES_BINDING, INSTANCE_DATA_BINDING,
+            },
         },
+        surface::{Surface, SurfaceSharedData},
         GeometryCache, RenderPassStatistics, TextureCache,
     },
-    scene::{camera::Camera, graph::Graph, node::Node},
+    scene::{camera::Camera, graph::Graph, instance_variation::InstanceVariation, node::Node},
+    utils::light_probe::{LightProbe, LightProbeGrid},
+};
+use rayon::prelude::*;
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time,
 };
-use std::{cell::RefCell, rc::Rc};
 
 struct GBufferShader {
     program: GpuProgram,
-    world_matrix: UniformLocation,
-    wvp_matrix: UniformLocation,
     use_skeletal_animation: UniformLocation,
-    bone_matrices: UniformLocation,
     diffuse_texture: UniformLocation,
     normal_texture: UniformLocation,
     lightmap_texture: UniformLocation,
+    orm_texture: UniformLocation,
+    use_orm_texture: UniformLocation,
     diffuse_color: UniformLocation,
+    use_light_probe: UniformLocation,
+    light_probe: UniformLocation,
 }
 
 impl GBufferShader {
@@ -37,15 +49,18 @@ impl GBufferShader {
         let fragment_source = include_str!("shaders/gbuffer_fs.glsl");
         let vertex_source = include_str!("shaders/gbuffer_vs.glsl");
         let program = GpuProgram::from_source("GBufferShader", vertex_source, fragment_source)?;
+        program.bind_uniform_block("BoneMatrices", BONE_MATRICES_BINDING)?;
+        program.bind_uniform_block("InstanceData", INSTANCE_DATA_BINDING)?;
         Ok(Self {
-            world_matrix: program.uniform_location("worldMatrix")?,
-            wvp_matrix: program.uniform_location("worldViewProjection")?,
             use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
-            bone_matrices: program.uniform_location("boneMatrices")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
             normal_texture: program.uniform_location("normalTexture")?,
             lightmap_texture: program.uniform_location("lightmapTexture")?,
+            orm_texture: program.uniform_location("ormTexture")?,
+            use_orm_texture: program.uniform_location("useOrmTexture")?,
             diffuse_color: program.uniform_location("diffuseColor")?,
+            use_light_probe: program.uniform_location("useLightProbe")?,
+            light_probe: program.uniform_location("lightProbe")?,
             program,
         })
     }
@@ -56,10 +71,70 @@ pub struct GBuffer {
     pub final_frame: FrameBuffer,
     shader: GBufferShader,
     bone_matrices: Vec<Mat4>,
+    uniform_ring: UniformRing,
+    /// Measured time (in seconds) of serial preparation of a single mesh, smoothed over
+    /// frames. Used to decide whether preparation is worth to be done in parallel.
+    prepare_time_per_mesh: f32,
     pub width: i32,
     pub height: i32,
 }
 
+/// Size of bone matrices array of the shader.
+const MAX_BONE_MATRICES: usize = 60;
+
+/// Minimal estimated time (in seconds) of serial preparation of meshes at which it will
+/// be done in parallel. Per-mesh work is small, so overhead of spreading it across worker
+/// threads (tens of microseconds) is paid back only by large amount of meshes.
+const PARALLEL_PREPARATION_MIN_TIME: f32 = 0.0005;
+
+// Plain copy of mesh's data required for visibility test and preparation of surfaces,
+// unlike meshes it can be safely sent to other threads.
+struct FlatMesh<'a> {
+    surfaces: &'a [Surface],
+    bounding_box: AxisAlignedBoundingBox,
+    global_transform: Mat4,
+    bone_positions: Vec<Vec3>,
+    depth_offset: f32,
+    variation: InstanceVariation,
+}
+
+/// Surface of visible mesh with everything required for its draw command except GPU
+/// resources, which can be used only on rendering thread.
+struct PreparedSurface<'a> {
+    surface: &'a Surface,
+    /// Identities of textures and geometry of surface, surfaces with same resources
+    /// have same key.
+    sort_key: (usize, usize, usize, usize, usize),
+    world: Mat4,
+    mvp: Mat4,
+    is_skinned: bool,
+    use_light_probe: bool,
+    light_probe: LightProbe,
+    coverage: f32,
+    variation: InstanceVariation,
+}
+
+/// Returns identity of a resource, surfaces which share a resource get same identity.
+fn resource_key<T>(resource: Option<Arc<Mutex<T>>>) -> usize {
+    resource.map_or(0, |resource| (&*resource as *const Mutex<T>) as usize)
+}
+
+/// Single surface prepared for rendering.
+struct GBufferDrawItem<'a> {
+    surface: &'a Surface,
+    data: Arc<Mutex<SurfaceSharedData>>,
+    diffuse_texture: Rc<RefCell<GpuTexture>>,
+    normal_texture: Rc<RefCell<GpuTexture>>,
+    lightmap_texture: Rc<RefCell<GpuTexture>>,
+    orm_texture: Option<Rc<RefCell<GpuTexture>>>,
+    world: Mat4,
+    mvp: Mat4,
+    is_skinned: bool,
+    use_light_probe: bool,
+    light_probe: LightProbe,
+    variation: InstanceVariation,
+}
+
 pub(in crate) struct GBufferRenderContext<'a, 'b> {
     pub state: &'a mut State,
     pub graph: &'b Graph,
@@ -68,6 +143,7 @@ pub(in crate) struct GBufferRenderContext<'a, 'b> {
     pub normal_dummy: Rc<RefCell<GpuTexture>>,
     pub texture_cache: &'a mut TextureCache,
     pub geom_cache: &'a mut GeometryCache,
+    pub light_probes: Option<&'b LightProbeGrid>,
 }
 
 impl GBuffer {
@@ -163,6 +239,8 @@ impl GBuffer {
             framebuffer,
             shader: GBufferShader::new()?,
             bone_matrices: Vec::new(),
+            uniform_ring: UniformRing::new(),
+            prepare_time_per_mesh: 0.0,
             width: width as i32,
             height: height as i32,
             final_frame: opt_framebuffer,
@@ -190,7 +268,10 @@ impl GBuffer {
     }
 
     #[must_use]
-    pub(in crate) fn fill(&mut self, args: GBufferRenderContext) -> RenderPassStatistics {
+    pub(in crate) fn fill<'b>(
+        &mut self,
+        args: GBufferRenderContext<'_, 'b>,
+    ) -> RenderPassStatistics {
         scope_profile!();
 
         let mut statistics = RenderPassStatistics::default();
@@ -203,6 +284,7 @@ impl GBuffer {
             normal_dummy,
             texture_cache,
             geom_cache,
+            light_probes,
         } = args;
 
         let frustum = Frustum::from(camera.view_projection_matrix()).unwrap();
@@ -217,133 +299,283 @@ impl GBuffer {
         );
 
         let initial_view_projection = camera.view_projection_matrix();
-
-        'mesh_loop: for mesh in graph.linear_iter().filter_map(|node| {
+        let camera_position = camera.global_position();
+        // Height of frustum slice at distance of 1 unit, used to estimate how much pixels
+        // object will take on screen.
+        let frustum_height = 2.0 * (camera.fov() * 0.5).tan();
+
+        // Flatten meshes into thread-safe data. This part is serial, because nodes are not
+        // thread-safe, but it is cheap - bounding boxes and transforms are cached.
+        let mut flat_meshes = Vec::new();
+        for mesh in graph.linear_iter().filter_map(|node| {
             if let Node::Mesh(mesh) = node {
                 Some(mesh)
             } else {
                 None
             }
         }) {
-            if !mesh.is_intersect_frustum(graph, &frustum) {
-                continue 'mesh_loop;
+            if !mesh.global_visibility() || !camera.sees_layer(mesh.layer()) {
+                continue;
             }
 
-            if !mesh.global_visibility() {
-                continue 'mesh_loop;
+            flat_meshes.push(FlatMesh {
+                surfaces: mesh.surfaces(),
+                bounding_box: mesh.bounding_box(),
+                global_transform: mesh.instance_transform(),
+                bone_positions: mesh
+                    .surfaces()
+                    .iter()
+                    .flat_map(|surface| surface.bones.iter())
+                    .map(|&bone| graph[bone].global_position())
+                    .collect(),
+                depth_offset: mesh.depth_offset_factor(),
+                variation: *mesh.instance_variation(),
+            });
+        }
+
+        let projection = camera.projection_matrix();
+        let view = camera.view_matrix();
+        let frame_height = self.height as f32;
+        let prepare = |flat: &FlatMesh<'b>| -> Vec<PreparedSurface<'b>> {
+            // Mesh is visible if its bounding box is visible or any of its bones.
+            if !frustum.is_intersects_aabb_transform(&flat.bounding_box, &flat.global_transform)
+                && !flat
+                    .bone_positions
+                    .iter()
+                    .any(|&position| frustum.is_contains_point(position))
+            {
+                return Vec::new();
             }
 
-            let view_projection = if mesh.depth_offset_factor() != 0.0 {
-                let mut projection = camera.projection_matrix();
-                projection.f[14] -= mesh.depth_offset_factor();
-                projection * camera.view_matrix()
+            let view_projection = if flat.depth_offset != 0.0 {
+                let mut projection = projection;
+                projection.f[14] -= flat.depth_offset;
+                projection * view
             } else {
                 initial_view_projection
             };
 
-            for surface in mesh.surfaces().iter() {
-                let is_skinned = !surface.bones.is_empty();
+            // Dynamic objects (without lightmap) are lit by interpolated light probe
+            // at position of mesh.
+            let light_probe = light_probes
+                .and_then(|grid| grid.interpolate(flat.global_transform.position()))
+                .unwrap_or_default();
+
+            // Screen coverage of the mesh is estimated by its bounding sphere, it defines
+            // which mip levels of streamed textures are needed.
+            let min = flat
+                .global_transform
+                .transform_vector(flat.bounding_box.min);
+            let max = flat
+                .global_transform
+                .transform_vector(flat.bounding_box.max);
+            let radius = (max - min).len() * 0.5;
+            let distance = (min + max).scale(0.5).distance(&camera_position);
+            let coverage = if distance <= radius {
+                std::f32::INFINITY
+            } else {
+                2.0 * radius / (distance * frustum_height) * frame_height
+            };
 
-                let world = if is_skinned {
-                    Mat4::IDENTITY
-                } else {
-                    mesh.global_transform()
-                };
-                let mvp = view_projection * world;
+            flat.surfaces
+                .iter()
+                .map(|surface| {
+                    let is_skinned = !surface.bones.is_empty();
 
-                let diffuse_texture = if let Some(texture) = surface.diffuse_texture() {
-                    if let Some(texture) = texture_cache.get(state, texture) {
-                        texture
+                    let world = if is_skinned {
+                        Mat4::IDENTITY
                     } else {
-                        white_dummy.clone()
+                        flat.global_transform
+                    };
+
+                    PreparedSurface {
+                        surface,
+                        sort_key: (
+                            resource_key(surface.diffuse_texture()),
+                            resource_key(surface.normal_texture()),
+                            resource_key(surface.lightmap_texture()),
+                            resource_key(surface.orm_texture()),
+                            resource_key(Some(surface.data())),
+                        ),
+                        world,
+                        mvp: view_projection * world,
+                        is_skinned,
+                        use_light_probe: light_probes.is_some()
+                            && surface.lightmap_texture().is_none(),
+                        light_probe,
+                        coverage,
+                        variation: flat.variation,
                     }
+                })
+                .collect()
+        };
+
+        // Surfaces are drawn in order of their textures and geometry, so surfaces with
+        // same material will go one after another and state won't be changed between
+        // them. Opaque geometry is drawn into G-Buffer with depth test, so order does
+        // not affect result.
+        //
+        // Visibility test, preparation of per-surface data and sorting are done on worker
+        // threads only when measured cost of serial preparation is high enough. Cost is
+        // measured on serial runs only, parallel timings do not reflect per-mesh cost.
+        let estimated_time = self.prepare_time_per_mesh * flat_meshes.len() as f32;
+        let prepared = if estimated_time >= PARALLEL_PREPARATION_MIN_TIME {
+            let mut prepared = flat_meshes.par_iter().flat_map(prepare).collect::<Vec<_>>();
+            prepared.par_sort_unstable_by_key(|item| item.sort_key);
+            prepared
+        } else {
+            let start = time::Instant::now();
+            let mut prepared = flat_meshes.iter().flat_map(prepare).collect::<Vec<_>>();
+            prepared.sort_unstable_by_key(|item| item.sort_key);
+            if !flat_meshes.is_empty() {
+                let time_per_mesh = start.elapsed().as_secs_f32() / flat_meshes.len() as f32;
+                self.prepare_time_per_mesh = if self.prepare_time_per_mesh == 0.0 {
+                    time_per_mesh
                 } else {
-                    white_dummy.clone()
+                    self.prepare_time_per_mesh * 0.9 + time_per_mesh * 0.1
                 };
+            }
+            prepared
+        };
+
+        // GPU textures and draw commands can be used on this thread only, so this is
+        // the only serial part of the pass besides flattening.
+        let mut batch = Vec::with_capacity(prepared.len());
+        for prepared in prepared {
+            let surface = prepared.surface;
+            let coverage = prepared.coverage;
+
+            let diffuse_texture = if let Some(texture) = surface.diffuse_texture() {
+                if let Some(texture) = texture_cache.get_streamed(state, texture, coverage) {
+                    texture
+                } else {
+                    white_dummy.clone()
+                }
+            } else {
+                white_dummy.clone()
+            };
 
-                let normal_texture = if let Some(texture) = surface.normal_texture() {
-                    if let Some(texture) = texture_cache.get(state, texture) {
-                        texture
-                    } else {
-                        normal_dummy.clone()
-                    }
+            let normal_texture = if let Some(texture) = surface.normal_texture() {
+                if let Some(texture) = texture_cache.get_streamed(state, texture, coverage) {
+                    texture
                 } else {
                     normal_dummy.clone()
-                };
+                }
+            } else {
+                normal_dummy.clone()
+            };
 
-                let lightmap_texture = if let Some(texture) = surface.lightmap_texture() {
-                    if let Some(texture) = texture_cache.get(state, texture) {
-                        texture
-                    } else {
-                        white_dummy.clone()
-                    }
+            let lightmap_texture = if let Some(texture) = surface.lightmap_texture() {
+                if let Some(texture) = texture_cache.get(state, texture) {
+                    texture
                 } else {
                     white_dummy.clone()
-                };
+                }
+            } else {
+                white_dummy.clone()
+            };
 
-                statistics += self.framebuffer.draw(
-                    geom_cache.get(state, &surface.data().lock().unwrap()),
-                    state,
-                    viewport,
-                    &self.shader.program,
-                    DrawParameters {
-                        cull_face: CullFace::Back,
-                        culling: true,
-                        color_write: Default::default(),
-                        depth_write: true,
-                        stencil_test: false,
-                        depth_test: true,
-                        blend: false,
-                    },
-                    &[
-                        (
-                            self.shader.diffuse_texture,
-                            UniformValue::Sampler {
-                                index: 0,
-                                texture: diffuse_texture,
-                            },
-                        ),
-                        (
-                            self.shader.normal_texture,
-                            UniformValue::Sampler {
-                                index: 1,
-                                texture: normal_texture,
-                            },
-                        ),
-                        (
-                            self.shader.lightmap_texture,
-                            UniformValue::Sampler {
-                                index: 2,
-                                texture: lightmap_texture,
-                            },
-                        ),
-                        (self.shader.wvp_matrix, UniformValue::Mat4(mvp)),
-                        (self.shader.world_matrix, UniformValue::Mat4(world)),
-                        (
-                            self.shader.use_skeletal_animation,
-                            UniformValue::Bool(is_skinned),
-                        ),
-                        (
-                            self.shader.diffuse_color,
-                            UniformValue::Color(surface.color()),
-                        ),
-                        (
-                            self.shader.bone_matrices,
-                            UniformValue::Mat4Array({
-                                self.bone_matrices.clear();
-                                for &bone_handle in surface.bones.iter() {
-                                    let bone_node = &graph[bone_handle];
-                                    self.bone_matrices.push(
-                                        bone_node.global_transform()
-                                            * bone_node.inv_bind_pose_transform(),
-                                    );
-                                }
-                                &self.bone_matrices
-                            }),
-                        ),
-                    ],
-                );
-            }
+            let orm_texture = surface
+                .orm_texture()
+                .and_then(|texture| texture_cache.get_streamed(state, texture, coverage));
+
+            batch.push(GBufferDrawItem {
+                surface,
+                data: surface.data(),
+                diffuse_texture,
+                normal_texture,
+                lightmap_texture,
+                orm_texture,
+                world: prepared.world,
+                mvp: prepared.mvp,
+                is_skinned: prepared.is_skinned,
+                use_light_probe: prepared.use_light_probe,
+                light_probe: prepared.light_probe,
+                variation: prepared.variation,
+            });
+        }
+
+        for item in batch.iter() {
+            item.surface
+                .calculate_bone_matrices(graph, &mut self.bone_matrices);
+            self.uniform_ring
+                .bind_bone_matrices(&self.bone_matrices, MAX_BONE_MATRICES);
+            self.uniform_ring.bind_instance_data(&InstanceData {
+                world: item.world,
+                world_view_projection: item.mvp,
+                tint: item.variation.tint(),
+                seed: item.variation.shader_seed(),
+                brightness_jitter: item.variation.brightness_jitter(),
+            });
+
+            statistics += self.framebuffer.draw(
+                geom_cache.get(state, &item.data.lock().unwrap()),
+                state,
+                viewport,
+                &self.shader.program,
+                DrawParameters {
+                    cull_face: CullFace::Back,
+                    culling: true,
+                    color_write: Default::default(),
+                    depth_write: true,
+                    stencil_test: false,
+                    depth_test: true,
+                    blend: false,
+                },
+                &[
+                    (
+                        self.shader.diffuse_texture,
+                        UniformValue::Sampler {
+                            index: 0,
+                            texture: item.diffuse_texture.clone(),
+                        },
+                    ),
+                    (
+                        self.shader.normal_texture,
+                        UniformValue::Sampler {
+                            index: 1,
+                            texture: item.normal_texture.clone(),
+                        },
+                    ),
+                    (
+                        self.shader.lightmap_texture,
+                        UniformValue::Sampler {
+                            index: 2,
+                            texture: item.lightmap_texture.clone(),
+                        },
+                    ),
+                    (
+                        self.shader.orm_texture,
+                        UniformValue::Sampler {
+                            index: 3,
+                            texture: item
+                                .orm_texture
+                                .clone()
+                                .unwrap_or_else(|| white_dummy.clone()),
+                        },
+                    ),
+                    (
+                        self.shader.use_orm_texture,
+                        UniformValue::Bool(item.orm_texture.is_some()),
+                    ),
+                    (
+                        self.shader.use_skeletal_animation,
+                        UniformValue::Bool(item.is_skinned),
+                    ),
+                    (
+                        self.shader.diffuse_color,
+                        UniformValue::Color(item.surface.color()),
+                    ),
+                    (
+                        self.shader.use_light_probe,
+                        UniformValue::Bool(item.use_light_probe),
+                    ),
+                    (
+                        self.shader.light_probe,
+                        UniformValue::Vec3Array(&item.light_probe.ambient_cube),
+                    ),
+                ],
+            );
         }
 
         statistics
@@ -0,0 +1,761 @@
+//! User GPU jobs.
+//!
+//! GPU job is a user fragment shader which is run for every texel of a job target once per
+//! frame (or once on request) before scenes are rendered. This allows to compute things
+//! on GPU without modifications of renderer: GPU particle forces, fluid simulations,
+//! procedural textures, etc.
+//!
+//! Job target is a pair of textures: job writes to one of them while the other one keeps
+//! result of previous run, then they're swapped. So a job can read its own target to get
+//! previous state of a simulation. Results of a target can be:
+//!
+//! - read by other jobs, see [`GpuJobInput::Target`];
+//! - used by materials of scene like any other texture, see
+//!   `Renderer::gpu_job_target_texture`;
+//! - read back to CPU, see `Renderer::read_gpu_job_target`.
+//!
+//! Job in [`GpuJobMode::UserPass`] mode is not run before scenes, instead it is run as a
+//! render pass of cameras and can read frame and depth of camera, see `frame_graph` module.
+//!
+//! # Buffers
+//!
+//! OpenGL 3.3 has no compute shaders and transform feedback is not exposed by renderer, so
+//! buffers are stored in float targets one element per texel, see
+//! `Renderer::add_gpu_job_buffer`. Element `i` of buffer with width `w` is stored in texel
+//! `(i % w, i / w)`, so it can be read by `texelFetch`. Buffers are padded to whole rows,
+//! so data written to a buffer must be padded too.
+//!
+//! # Shaders
+//!
+//! Fragment shader of a job gets `texCoord` input and must write `FragColor` output. Uniforms
+//! `targetSize` (size of target in texels) and `deltaTime` (time from last frame in seconds)
+//! are set if they're declared. Every input and uniform of a job must be used by shader,
+//! otherwise it is optimized out and job can't be added.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::renderer::{
+//!     gpu_job::{GpuJob, GpuJobFormat, GpuJobInput, GpuJobUniform},
+//!     Renderer,
+//! };
+//!
+//! const SHADER: &str = r#"
+//!     #version 330 core
+//!
+//!     uniform sampler2D positions;
+//!     uniform float deltaTime;
+//!     uniform float gravity;
+//!
+//!     in vec2 texCoord;
+//!     out vec4 FragColor;
+//!
+//!     void main()
+//!     {
+//!         vec4 position = texture(positions, texCoord);
+//!         FragColor = vec4(position.xyz - vec3(0.0, gravity * deltaTime, 0.0), position.w);
+//!     }
+//! "#;
+//!
+//! fn add_particles(renderer: &mut Renderer, initial_positions: &[f32]) {
+//!     let buffer = renderer
+//!         .add_gpu_job_buffer(initial_positions.len() / 4, GpuJobFormat::Rgba32F)
+//!         .unwrap();
+//!     // Buffer is padded to whole rows, so is its data.
+//!     let mut data = initial_positions.to_vec();
+//!     data.resize(renderer.gpu_job_target(buffer).capacity() * 4, 0.0);
+//!     renderer.write_gpu_job_target(buffer, &data).unwrap();
+//!     // Job reads previous positions from its own target.
+//!     let job = GpuJob::new("Gravity", SHADER, buffer)
+//!         .with_input("positions", GpuJobInput::Target(buffer))
+//!         .with_uniform("gravity", GpuJobUniform::Float(9.81));
+//!     renderer.add_gpu_job(job).unwrap();
+//! }
+//! ```
+
+use crate::{
+    core::{
+        color::Color,
+        math::{mat4::Mat4, vec2::Vec2, vec3::Vec3, vec4::Vec4, Rect},
+        pool::{Handle, Pool},
+        scope_profile,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            framebuffer::{
+                Attachment, AttachmentKind, CullFace, DrawParameters, FrameBuffer, FrameBufferTrait,
+            },
+            geometry_buffer::DrawCallStatistics,
+            gpu_program::{GpuProgram, UniformLocation, UniformValue},
+            gpu_texture::{
+                Coordinate, GpuTexture, GpuTextureKind, MagnificationFilter, MininificationFilter,
+                PixelKind, WrapMode,
+            },
+            state::State,
+        },
+        surface::SurfaceSharedData,
+        GeometryCache, RenderPassStatistics, Renderer, TextureCache,
+    },
+    resource::texture::{Texture, TextureKind},
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+/// Maximum width of buffer targets in texels.
+pub const MAX_BUFFER_WIDTH: usize = 1024;
+
+/// Format of texels of job target.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GpuJobFormat {
+    /// Four 8-bit normalized channels, good for procedural textures. Target is sampled
+    /// with linear filtering.
+    Rgba8,
+    /// Single 32-bit float channel.
+    R32F,
+    /// Four 32-bit float channels, good for positions and velocities.
+    Rgba32F,
+}
+
+impl GpuJobFormat {
+    fn texture_kind(self) -> TextureKind {
+        match self {
+            GpuJobFormat::Rgba8 => TextureKind::RGBA8,
+            GpuJobFormat::R32F => TextureKind::R32F,
+            GpuJobFormat::Rgba32F => TextureKind::RGBA32F,
+        }
+    }
+
+    /// Returns amount of values per texel.
+    pub fn components(self) -> usize {
+        match self {
+            GpuJobFormat::Rgba8 | GpuJobFormat::Rgba32F => 4,
+            GpuJobFormat::R32F => 1,
+        }
+    }
+
+    /// Converts values to bytes of texture data, 8-bit channels are mapped from [0; 1].
+    fn to_bytes(self, data: &[f32]) -> Vec<u8> {
+        match self {
+            GpuJobFormat::Rgba8 => data
+                .iter()
+                .map(|v| (v.max(0.0).min(1.0) * 255.0).round() as u8)
+                .collect(),
+            GpuJobFormat::R32F | GpuJobFormat::Rgba32F => {
+                data.iter().flat_map(|v| v.to_ne_bytes().to_vec()).collect()
+            }
+        }
+    }
+
+    /// Drops channels which are not stored in target from pixels read back as RGBA.
+    fn keep_components(self, pixels: Vec<f32>) -> Vec<f32> {
+        match self {
+            GpuJobFormat::Rgba8 | GpuJobFormat::Rgba32F => pixels,
+            GpuJobFormat::R32F => pixels.chunks(4).map(|texel| texel[0]).collect(),
+        }
+    }
+}
+
+/// Returns size of buffer target which can hold given amount of elements.
+fn buffer_size(len: usize) -> (usize, usize) {
+    let width = len.max(1).min(MAX_BUFFER_WIDTH);
+    let height = (len + width - 1) / width;
+    (width, height.max(1))
+}
+
+/// Output of jobs, see module docs.
+pub struct GpuJobTarget {
+    width: usize,
+    height: usize,
+    format: GpuJobFormat,
+    // Result of last run.
+    front: FrameBuffer,
+    // Written by next run.
+    back: FrameBuffer,
+    texture: Arc<Mutex<Texture>>,
+}
+
+impl GpuJobTarget {
+    fn new(
+        state: &mut State,
+        width: usize,
+        height: usize,
+        format: GpuJobFormat,
+        data: Option<&[u8]>,
+    ) -> Result<Self, RendererError> {
+        let mut make_framebuffer = || -> Result<FrameBuffer, RendererError> {
+            let kind = GpuTextureKind::Rectangle { width, height };
+            let pixel_kind = PixelKind::from(format.texture_kind());
+            let mut texture = GpuTexture::new(state, kind, pixel_kind, data)?;
+            // Filtering of float textures is not supported everywhere.
+            let (min, mag) = match format {
+                GpuJobFormat::Rgba8 => (MininificationFilter::Linear, MagnificationFilter::Linear),
+                _ => (MininificationFilter::Nearest, MagnificationFilter::Nearest),
+            };
+            texture
+                .bind_mut(state, 0)
+                .set_minification_filter(min)
+                .set_magnification_filter(mag)
+                .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
+                .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
+            FrameBuffer::new(
+                state,
+                None,
+                vec![Attachment {
+                    kind: AttachmentKind::Color,
+                    texture: Rc::new(RefCell::new(texture)),
+                }],
+            )
+        };
+        let front = make_framebuffer()?;
+        let back = make_framebuffer()?;
+
+        let mut texture = Texture::default();
+        texture.width = width as u32;
+        texture.height = height as u32;
+        texture.kind = format.texture_kind();
+
+        Ok(Self {
+            width,
+            height,
+            format,
+            front,
+            back,
+            texture: Arc::new(Mutex::new(texture)),
+        })
+    }
+
+    fn front_texture(&self) -> Rc<RefCell<GpuTexture>> {
+        self.front.color_attachments()[0].texture.clone()
+    }
+
+    fn viewport(&self) -> Rect<i32> {
+        Rect::new(0, 0, self.width as i32, self.height as i32)
+    }
+
+    /// Returns width of target in texels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns height of target in texels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns format of target.
+    pub fn format(&self) -> GpuJobFormat {
+        self.format
+    }
+
+    /// Returns amount of texels of target, buffer targets may be larger than requested
+    /// amount of elements because they're padded to whole rows.
+    pub fn capacity(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// Texture bound to a sampler of job shader.
+#[derive(Clone, Debug)]
+pub enum GpuJobInput {
+    /// Usual texture, white texture is used until it is loaded.
+    Texture(Arc<Mutex<Texture>>),
+    /// Last result of job target. If job reads its own target, it gets result of its
+    /// previous run.
+    Target(Handle<GpuJobTarget>),
+    /// Frame of camera rendered by passes scheduled before job, available only if job is
+    /// run as user render pass. White texture is used otherwise.
+    CameraFrame,
+    /// Depth buffer of camera, available only if job is run as user render pass. White
+    /// texture is used otherwise.
+    CameraDepth,
+}
+
+/// Value of uniform of job shader.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GpuJobUniform {
+    /// `int` uniform.
+    Integer(i32),
+    /// `float` uniform.
+    Float(f32),
+    /// `vec2` uniform.
+    Vec2(Vec2),
+    /// `vec3` uniform.
+    Vec3(Vec3),
+    /// `vec4` uniform.
+    Vec4(Vec4),
+    /// `vec4` uniform with components in [0; 1] range.
+    Color(Color),
+    /// `mat4` uniform.
+    Mat4(Mat4),
+}
+
+impl GpuJobUniform {
+    fn value(&self) -> UniformValue<'static> {
+        match *self {
+            GpuJobUniform::Integer(v) => UniformValue::Integer(v),
+            GpuJobUniform::Float(v) => UniformValue::Float(v),
+            GpuJobUniform::Vec2(v) => UniformValue::Vec2(v),
+            GpuJobUniform::Vec3(v) => UniformValue::Vec3(v),
+            GpuJobUniform::Vec4(v) => UniformValue::Vec4(v),
+            GpuJobUniform::Color(v) => UniformValue::Color(v),
+            GpuJobUniform::Mat4(v) => UniformValue::Mat4(v),
+        }
+    }
+}
+
+/// Defines when job is run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GpuJobMode {
+    /// Job is run every frame, this is default mode.
+    EveryFrame,
+    /// Job is run once on next frame after it was added or after `GpuJob::run_once` call.
+    Once,
+    /// Job is run only as user render pass of cameras, see `frame_graph` module.
+    UserPass,
+}
+
+struct JobProgram {
+    program: GpuProgram,
+    wvp_matrix: UniformLocation,
+    target_size: Option<UniformLocation>,
+    delta_time: Option<UniformLocation>,
+    inputs: Vec<UniformLocation>,
+    uniforms: Vec<UniformLocation>,
+}
+
+impl JobProgram {
+    const VERTEX_SHADER: &'static str = include_str!("shaders/blur_vs.glsl");
+
+    fn new(job: &GpuJob) -> Result<Self, RendererError> {
+        let program = GpuProgram::from_source(&job.name, Self::VERTEX_SHADER, &job.shader)?;
+        Ok(Self {
+            wvp_matrix: program.uniform_location("worldViewProjection")?,
+            target_size: program.uniform_location("targetSize").ok(),
+            delta_time: program.uniform_location("deltaTime").ok(),
+            inputs: job
+                .inputs
+                .iter()
+                .map(|(name, _)| program.uniform_location(name))
+                .collect::<Result<_, _>>()?,
+            uniforms: job
+                .uniforms
+                .iter()
+                .map(|(name, _)| program.uniform_location(name))
+                .collect::<Result<_, _>>()?,
+            program,
+        })
+    }
+}
+
+/// User GPU job, see module docs.
+pub struct GpuJob {
+    name: String,
+    shader: String,
+    target: Handle<GpuJobTarget>,
+    inputs: Vec<(String, GpuJobInput)>,
+    uniforms: Vec<(String, GpuJobUniform)>,
+    mode: GpuJobMode,
+    pending: bool,
+    program: Option<JobProgram>,
+}
+
+impl GpuJob {
+    /// Creates new job with given name (used in error messages), fragment shader and
+    /// target.
+    pub fn new<N: AsRef<str>, S: AsRef<str>>(
+        name: N,
+        fragment_shader: S,
+        target: Handle<GpuJobTarget>,
+    ) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            shader: fragment_shader.as_ref().to_owned(),
+            target,
+            inputs: Default::default(),
+            uniforms: Default::default(),
+            mode: GpuJobMode::EveryFrame,
+            pending: true,
+            program: None,
+        }
+    }
+
+    /// Binds input to a sampler with given name.
+    pub fn with_input<N: AsRef<str>>(mut self, sampler: N, input: GpuJobInput) -> Self {
+        self.inputs.push((sampler.as_ref().to_owned(), input));
+        self
+    }
+
+    /// Sets initial value of uniform with given name.
+    pub fn with_uniform<N: AsRef<str>>(mut self, uniform: N, value: GpuJobUniform) -> Self {
+        self.uniforms.push((uniform.as_ref().to_owned(), value));
+        self
+    }
+
+    /// Sets when job is run.
+    pub fn with_mode(mut self, mode: GpuJobMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns name of job.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns target of job.
+    pub fn target(&self) -> Handle<GpuJobTarget> {
+        self.target
+    }
+
+    /// Returns inputs of job with names of their samplers.
+    pub fn inputs(&self) -> &[(String, GpuJobInput)] {
+        &self.inputs
+    }
+
+    /// Returns mode of job.
+    pub fn mode(&self) -> GpuJobMode {
+        self.mode
+    }
+
+    /// Sets new value of uniform. Returns false if job has no such uniform, uniforms can
+    /// be added only before job is added to renderer.
+    pub fn set_uniform(&mut self, uniform: &str, value: GpuJobUniform) -> bool {
+        match self.uniforms.iter_mut().find(|(name, _)| name == uniform) {
+            Some((_, current)) => {
+                *current = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets new input for sampler. Returns false if job has no such sampler.
+    pub fn set_input(&mut self, sampler: &str, input: GpuJobInput) -> bool {
+        match self.inputs.iter_mut().find(|(name, _)| name == sampler) {
+            Some((_, current)) => {
+                *current = input;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Requests one more run of a job in [`GpuJobMode::Once`] mode.
+    pub fn run_once(&mut self) {
+        self.pending = true;
+    }
+
+    /// Returns true if job will be run on next frame.
+    pub fn is_pending(&self) -> bool {
+        self.mode == GpuJobMode::EveryFrame || self.pending
+    }
+}
+
+/// Jobs and targets of renderer.
+#[derive(Default)]
+pub(in crate) struct GpuJobs {
+    targets: Pool<GpuJobTarget>,
+    jobs: Pool<GpuJob>,
+    // Jobs are run in order of addition.
+    order: Vec<Handle<GpuJob>>,
+}
+
+/// Everything that is needed to run a job.
+pub(in crate) struct GpuJobContext<'a> {
+    pub state: &'a mut State,
+    pub texture_cache: &'a mut TextureCache,
+    pub geometry_cache: &'a mut GeometryCache,
+    pub white_dummy: &'a Rc<RefCell<GpuTexture>>,
+    pub quad: &'a SurfaceSharedData,
+    pub dt: f32,
+    /// Frame and depth textures of camera if job is run as user render pass.
+    pub camera: Option<(Rc<RefCell<GpuTexture>>, Rc<RefCell<GpuTexture>>)>,
+}
+
+impl GpuJobs {
+    /// Returns job if handle is valid.
+    pub(in crate) fn job(&self, handle: Handle<GpuJob>) -> Option<&GpuJob> {
+        if self.jobs.is_valid_handle(handle) {
+            Some(self.jobs.borrow(handle))
+        } else {
+            None
+        }
+    }
+
+    /// Returns last result of target of given job.
+    pub(in crate) fn result_of(&self, handle: Handle<GpuJob>) -> Option<Rc<RefCell<GpuTexture>>> {
+        let target = self.job(handle)?.target;
+        if self.targets.is_valid_handle(target) {
+            Some(self.targets.borrow(target).front_texture())
+        } else {
+            None
+        }
+    }
+
+    /// Runs single job regardless of its mode. Returns None if job or its target is
+    /// invalid.
+    pub(in crate) fn run(
+        &mut self,
+        handle: Handle<GpuJob>,
+        context: &mut GpuJobContext,
+    ) -> Option<DrawCallStatistics> {
+        let GpuJobs { targets, jobs, .. } = self;
+
+        if !jobs.is_valid_handle(handle) {
+            return None;
+        }
+        let job = jobs.borrow_mut(handle);
+        if !targets.is_valid_handle(job.target) {
+            return None;
+        }
+        job.pending = false;
+        let program = job.program.as_ref()?;
+
+        let mut uniforms = Vec::with_capacity(job.inputs.len() + job.uniforms.len() + 3);
+        for (index, ((_, input), &location)) in
+            job.inputs.iter().zip(program.inputs.iter()).enumerate()
+        {
+            let texture = match input {
+                GpuJobInput::Texture(texture) => context
+                    .texture_cache
+                    .get(context.state, texture.clone())
+                    .unwrap_or_else(|| context.white_dummy.clone()),
+                GpuJobInput::Target(target) if targets.is_valid_handle(*target) => {
+                    targets.borrow(*target).front_texture()
+                }
+                GpuJobInput::Target(_) => context.white_dummy.clone(),
+                GpuJobInput::CameraFrame => context
+                    .camera
+                    .as_ref()
+                    .map_or_else(|| context.white_dummy.clone(), |(frame, _)| frame.clone()),
+                GpuJobInput::CameraDepth => context
+                    .camera
+                    .as_ref()
+                    .map_or_else(|| context.white_dummy.clone(), |(_, depth)| depth.clone()),
+            };
+            uniforms.push((location, UniformValue::Sampler { index, texture }));
+        }
+        for ((_, value), &location) in job.uniforms.iter().zip(program.uniforms.iter()) {
+            uniforms.push((location, value.value()));
+        }
+
+        let target = targets.borrow_mut(job.target);
+        let viewport = target.viewport();
+        let size = Vec2::new(viewport.w as f32, viewport.h as f32);
+        uniforms.push((
+            program.wvp_matrix,
+            UniformValue::Mat4(
+                Mat4::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0)
+                    * Mat4::scale(Vec3::new(size.x, size.y, 0.0)),
+            ),
+        ));
+        if let Some(location) = program.target_size {
+            uniforms.push((location, UniformValue::Vec2(size)));
+        }
+        if let Some(location) = program.delta_time {
+            uniforms.push((location, UniformValue::Float(context.dt)));
+        }
+
+        let statistics = target.back.draw(
+            context.geometry_cache.get(context.state, context.quad),
+            context.state,
+            viewport,
+            &program.program,
+            DrawParameters {
+                cull_face: CullFace::Back,
+                culling: false,
+                color_write: Default::default(),
+                depth_write: false,
+                stencil_test: false,
+                depth_test: false,
+                blend: false,
+            },
+            &uniforms,
+        );
+        std::mem::swap(&mut target.front, &mut target.back);
+
+        // Front texture was swapped, so texture cache must be updated.
+        context
+            .texture_cache
+            .pin(&target.texture, target.front_texture());
+
+        Some(statistics)
+    }
+}
+
+impl Renderer {
+    /// Adds new job target of given size in texels.
+    pub fn add_gpu_job_target(
+        &mut self,
+        width: usize,
+        height: usize,
+        format: GpuJobFormat,
+    ) -> Result<Handle<GpuJobTarget>, RendererError> {
+        let target = GpuJobTarget::new(&mut self.state, width, height, format, None)?;
+        let handle = self.gpu_jobs.targets.spawn(target);
+        self.expose_gpu_job_target(handle);
+        Ok(handle)
+    }
+
+    /// Adds new job target which can hold given amount of elements, see module docs.
+    pub fn add_gpu_job_buffer(
+        &mut self,
+        len: usize,
+        format: GpuJobFormat,
+    ) -> Result<Handle<GpuJobTarget>, RendererError> {
+        let (width, height) = buffer_size(len);
+        self.add_gpu_job_target(width, height, format)
+    }
+
+    /// Removes job target. Jobs writing to the target are not run anymore.
+    pub fn remove_gpu_job_target(&mut self, handle: Handle<GpuJobTarget>) {
+        let target = self.gpu_jobs.targets.free(handle);
+        self.texture_cache.unpin(&target.texture);
+    }
+
+    /// Returns reference to job target.
+    pub fn gpu_job_target(&self, handle: Handle<GpuJobTarget>) -> &GpuJobTarget {
+        self.gpu_jobs.targets.borrow(handle)
+    }
+
+    /// Replaces content of job target, `data` must contain values of every texel row by
+    /// row from bottom to top (i.e. elements of buffer in order), see
+    /// [`GpuJobFormat::components`]. Returns [`RendererError::InvalidGpuJobData`] if amount
+    /// of values does not match size of target, note that buffers are padded to whole rows,
+    /// see [`GpuJobTarget::capacity`].
+    pub fn write_gpu_job_target(
+        &mut self,
+        handle: Handle<GpuJobTarget>,
+        data: &[f32],
+    ) -> Result<(), RendererError> {
+        let target = self.gpu_jobs.targets.borrow_mut(handle);
+        let expected = target.capacity() * target.format.components();
+        if data.len() != expected {
+            return Err(RendererError::InvalidGpuJobData {
+                expected,
+                actual: data.len(),
+            });
+        }
+        let bytes = target.format.to_bytes(data);
+        let new_target = GpuJobTarget::new(
+            &mut self.state,
+            target.width,
+            target.height,
+            target.format,
+            Some(&bytes),
+        )?;
+        // Texture is kept so materials that use it are not affected.
+        target.front = new_target.front;
+        target.back = new_target.back;
+        self.expose_gpu_job_target(handle);
+        Ok(())
+    }
+
+    /// Reads last result of job target back to CPU in the same layout as
+    /// [`Renderer::write_gpu_job_target`] takes - [`GpuJobFormat::components`] values per
+    /// texel row by row from bottom to top, 8-bit channels are mapped to [0; 1]. This method
+    /// stalls until GPU has finished every submitted command, so it should not be called
+    /// every frame.
+    pub fn read_gpu_job_target(&mut self, handle: Handle<GpuJobTarget>) -> Vec<f32> {
+        let target = self.gpu_jobs.targets.borrow(handle);
+        let pixels = target
+            .front
+            .read_pixels_f32(&mut self.state, target.viewport());
+        target.format.keep_components(pixels)
+    }
+
+    /// Returns texture which can be used by materials to sample last result of job target.
+    pub fn gpu_job_target_texture(&self, handle: Handle<GpuJobTarget>) -> Arc<Mutex<Texture>> {
+        self.gpu_jobs.targets.borrow(handle).texture.clone()
+    }
+
+    /// Compiles shader of job and adds it to renderer. Jobs are run in order of addition.
+    pub fn add_gpu_job(&mut self, mut job: GpuJob) -> Result<Handle<GpuJob>, RendererError> {
+        job.program = Some(JobProgram::new(&job)?);
+        let handle = self.gpu_jobs.jobs.spawn(job);
+        self.gpu_jobs.order.push(handle);
+        Ok(handle)
+    }
+
+    /// Removes job.
+    pub fn remove_gpu_job(&mut self, handle: Handle<GpuJob>) -> GpuJob {
+        self.gpu_jobs.order.retain(|&h| h != handle);
+        let mut job = self.gpu_jobs.jobs.free(handle);
+        job.program = None;
+        job
+    }
+
+    /// Returns reference to job.
+    pub fn gpu_job(&self, handle: Handle<GpuJob>) -> &GpuJob {
+        self.gpu_jobs.jobs.borrow(handle)
+    }
+
+    /// Returns mutable reference to job, for example to change its uniforms.
+    pub fn gpu_job_mut(&mut self, handle: Handle<GpuJob>) -> &mut GpuJob {
+        self.gpu_jobs.jobs.borrow_mut(handle)
+    }
+
+    /// Registers last result of target in texture cache so its texture can be used as
+    /// usual texture.
+    fn expose_gpu_job_target(&mut self, handle: Handle<GpuJobTarget>) {
+        let target = self.gpu_jobs.targets.borrow(handle);
+        self.texture_cache
+            .pin(&target.texture, target.front_texture());
+    }
+
+    /// Runs every pending job, except jobs in [`GpuJobMode::UserPass`] mode.
+    pub(in crate) fn run_gpu_jobs(&mut self, dt: f32) -> RenderPassStatistics {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+        let mut context = GpuJobContext {
+            state: &mut self.state,
+            texture_cache: &mut self.texture_cache,
+            geometry_cache: &mut self.geometry_cache,
+            white_dummy: &self.white_dummy,
+            quad: &self.quad,
+            dt,
+            camera: None,
+        };
+        for i in 0..self.gpu_jobs.order.len() {
+            let handle = self.gpu_jobs.order[i];
+            let job = self.gpu_jobs.jobs.borrow(handle);
+            if job.mode == GpuJobMode::UserPass || !job.is_pending() {
+                continue;
+            }
+            if let Some(job_statistics) = self.gpu_jobs.run(handle, &mut context) {
+                statistics += job_statistics;
+            }
+        }
+
+        statistics
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renderer::gpu_job::{buffer_size, GpuJobFormat, MAX_BUFFER_WIDTH};
+
+    #[test]
+    fn test_buffer_layout() {
+        assert_eq!(buffer_size(0), (1, 1));
+        assert_eq!(buffer_size(10), (10, 1));
+        assert_eq!(buffer_size(MAX_BUFFER_WIDTH + 1), (MAX_BUFFER_WIDTH, 2));
+        assert_eq!(
+            GpuJobFormat::Rgba8.to_bytes(&[0.0, 0.5, 1.0, 2.0]),
+            vec![0, 128, 255, 255]
+        );
+        assert_eq!(
+            GpuJobFormat::R32F.to_bytes(&[1.0]),
+            1.0f32.to_ne_bytes().to_vec()
+        );
+        assert_eq!(
+            GpuJobFormat::R32F.keep_components(vec![1.0, 0.0, 0.0, 1.0, 2.0, 0.0, 0.0, 1.0]),
+            vec![1.0, 2.0]
+        );
+    }
+}
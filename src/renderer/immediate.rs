@@ -0,0 +1,451 @@
+//! Immediate-mode geometry supplied by nodes every frame.
+//!
+//! Any scene node can have a render callback, which is called by renderer every frame for
+//! every camera that sees the node. Callback fills a draw list with transient geometry in
+//! world space, this is useful for effects which change their shape every frame: lightning
+//! bolts, ropes, trails, etc. Geometry is drawn unlit after lighting, particles and sprites,
+//! with depth test against scene geometry.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{
+//!     core::{color::Color, math::vec3::Vec3},
+//!     renderer::immediate::{ImmediateBlend, RenderCallback},
+//!     scene::base::BaseBuilder,
+//! };
+//!
+//! let rope = BaseBuilder::new()
+//!     .with_render_callback(RenderCallback::new(|context, draw_list| {
+//!         let points = [Vec3::ZERO, Vec3::new(0.0, -1.0, 0.2), Vec3::new(0.0, -2.0, 0.0)];
+//!         draw_list.begin(None, ImmediateBlend::Opaque);
+//!         draw_list.push_ribbon(&points, 0.05, context.camera.global_position(), Color::WHITE);
+//!     }))
+//!     .build_node();
+//! ```
+
+use crate::{
+    core::{
+        color::Color,
+        math::{mat4::Mat4, vec2::Vec2, vec3::Vec3, Rect, TriangleDefinition},
+        pool::Handle,
+        scope_profile,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            framebuffer::{
+                CullFace, DrawParameters, DrawPartContext, FrameBuffer, FrameBufferTrait,
+            },
+            geometry_buffer::{
+                AttributeDefinition, AttributeKind, ElementKind, GeometryBuffer, GeometryBufferKind,
+            },
+            gl,
+            gpu_program::{GpuProgram, UniformLocation, UniformValue},
+            gpu_texture::GpuTexture,
+            state::State,
+        },
+        RenderPassStatistics, TextureCache,
+    },
+    resource::texture::Texture,
+    scene::{camera::Camera, graph::Graph, node::Node},
+};
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Formatter},
+    ops::Range,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+/// Vertex of immediate-mode geometry, position is in world space.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct ImmediateVertex {
+    /// Position in world space.
+    pub position: Vec3,
+    /// Texture coordinates.
+    pub tex_coord: Vec2,
+    /// Color, multiplied with texture.
+    pub color: Color,
+}
+
+/// Defines how immediate-mode geometry is blended with frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImmediateBlend {
+    /// No blending, geometry writes depth. Opaque geometry is drawn before blended.
+    Opaque,
+    /// Alpha blending, geometry does not write depth.
+    Alpha,
+    /// Additive blending, geometry does not write depth. Good for glowing effects.
+    Additive,
+}
+
+impl Default for ImmediateBlend {
+    fn default() -> Self {
+        ImmediateBlend::Opaque
+    }
+}
+
+/// Range of triangles which share texture and blending.
+#[derive(Clone, Debug)]
+pub struct ImmediateCommand {
+    /// Texture of command, None means white texture.
+    pub texture: Option<Arc<Mutex<Texture>>>,
+    /// Blending of command.
+    pub blend: ImmediateBlend,
+    /// Range of triangles of command.
+    pub triangles: Range<usize>,
+}
+
+/// Draw list is filled by render callbacks. Every triangle belongs to a command, command
+/// is started by [begin](struct.ImmediateDrawList.html#method.begin). If triangle is
+/// added without `begin` call, it goes to opaque untextured command.
+#[derive(Default, Debug)]
+pub struct ImmediateDrawList {
+    vertices: Vec<ImmediateVertex>,
+    triangles: Vec<TriangleDefinition>,
+    commands: Vec<ImmediateCommand>,
+    // Whether last command can accept new triangles.
+    open: bool,
+}
+
+impl ImmediateDrawList {
+    /// Removes every vertex, triangle and command.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+        self.commands.clear();
+        self.open = false;
+    }
+
+    /// Starts new command with given texture and blending, all triangles added after this
+    /// call will be drawn with them.
+    pub fn begin(&mut self, texture: Option<Arc<Mutex<Texture>>>, blend: ImmediateBlend) {
+        let start = self.triangles.len();
+        self.commands.push(ImmediateCommand {
+            texture,
+            blend,
+            triangles: start..start,
+        });
+        self.open = true;
+    }
+
+    /// Finishes current command, next triangles will go to new command.
+    pub fn end(&mut self) {
+        self.open = false;
+    }
+
+    /// Adds new vertex and returns its index.
+    pub fn push_vertex(&mut self, vertex: ImmediateVertex) -> u32 {
+        self.vertices.push(vertex);
+        (self.vertices.len() - 1) as u32
+    }
+
+    /// Adds new triangle from indices of vertices returned by `push_vertex`.
+    pub fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        if !self.open {
+            self.begin(None, Default::default());
+        }
+        self.triangles.push(TriangleDefinition([a, b, c]));
+        if let Some(command) = self.commands.last_mut() {
+            command.triangles.end = self.triangles.len();
+        }
+    }
+
+    /// Adds new quad, vertices must go in clockwise or counter-clockwise order.
+    pub fn push_quad(&mut self, vertices: [ImmediateVertex; 4]) {
+        let a = self.push_vertex(vertices[0]);
+        let b = self.push_vertex(vertices[1]);
+        let c = self.push_vertex(vertices[2]);
+        let d = self.push_vertex(vertices[3]);
+        self.push_triangle(a, b, c);
+        self.push_triangle(a, c, d);
+    }
+
+    /// Adds camera-facing strip of given width through given points. Texture is stretched
+    /// along the strip. Useful for ropes, lightning bolts and trails.
+    pub fn push_ribbon(&mut self, points: &[Vec3], width: f32, eye: Vec3, color: Color) {
+        if points.len() < 2 {
+            return;
+        }
+        let half_width = width * 0.5;
+        let last = points.len() - 1;
+        let mut prev = None;
+        for (i, point) in points.iter().enumerate() {
+            let tangent = points[(i + 1).min(last)] - points[i.saturating_sub(1)];
+            let side = tangent
+                .cross(&(eye - *point))
+                .normalized()
+                .unwrap_or_else(|| Vec3::new(1.0, 0.0, 0.0))
+                .scale(half_width);
+            let v = i as f32 / last as f32;
+            let left = self.push_vertex(ImmediateVertex {
+                position: *point - side,
+                tex_coord: Vec2::new(0.0, v),
+                color,
+            });
+            let right = self.push_vertex(ImmediateVertex {
+                position: *point + side,
+                tex_coord: Vec2::new(1.0, v),
+                color,
+            });
+            if let Some((prev_left, prev_right)) = prev {
+                self.push_triangle(prev_left, prev_right, right);
+                self.push_triangle(prev_left, right, left);
+            }
+            prev = Some((left, right));
+        }
+    }
+
+    /// Returns vertices of draw list.
+    pub fn vertices(&self) -> &[ImmediateVertex] {
+        &self.vertices
+    }
+
+    /// Returns triangles of draw list.
+    pub fn triangles(&self) -> &[TriangleDefinition] {
+        &self.triangles
+    }
+
+    /// Returns commands of draw list.
+    pub fn commands(&self) -> &[ImmediateCommand] {
+        &self.commands
+    }
+}
+
+/// Information which is passed to render callback.
+pub struct RenderCallbackContext<'a> {
+    /// Graph of the node.
+    pub graph: &'a Graph,
+    /// Handle of the node which owns the callback.
+    pub node: Handle<Node>,
+    /// Camera from which the frame is rendered.
+    pub camera: &'a Camera,
+}
+
+type RenderCallbackFn =
+    dyn Fn(&RenderCallbackContext, &mut ImmediateDrawList) + Send + Sync + 'static;
+
+/// Render callback of a node, see module docs. Callback is shared between copies of a
+/// node and it is not serialized, so it must be set again after loading a scene.
+#[derive(Clone)]
+pub struct RenderCallback(Arc<RenderCallbackFn>);
+
+impl RenderCallback {
+    /// Creates new render callback from given closure.
+    pub fn new<F>(func: F) -> Self
+    where
+        F: Fn(&RenderCallbackContext, &mut ImmediateDrawList) + Send + Sync + 'static,
+    {
+        Self(Arc::new(func))
+    }
+
+    /// Calls the callback.
+    pub fn call(&self, context: &RenderCallbackContext, draw_list: &mut ImmediateDrawList) {
+        (self.0)(context, draw_list)
+    }
+}
+
+impl Debug for RenderCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RenderCallback")
+    }
+}
+
+struct ImmediateShader {
+    program: GpuProgram,
+    wvp_matrix: UniformLocation,
+    diffuse_texture: UniformLocation,
+}
+
+impl ImmediateShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/immediate_fs.glsl");
+        let vertex_source = include_str!("shaders/immediate_vs.glsl");
+        let program = GpuProgram::from_source("ImmediateShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            wvp_matrix: program.uniform_location("worldViewProjection")?,
+            diffuse_texture: program.uniform_location("diffuseTexture")?,
+            program,
+        })
+    }
+}
+
+pub(in crate) struct ImmediateRenderer {
+    shader: ImmediateShader,
+    geometry: GeometryBuffer<ImmediateVertex>,
+    draw_list: ImmediateDrawList,
+}
+
+pub(in crate) struct ImmediateRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub framebuffer: &'b mut FrameBuffer,
+    pub graph: &'c Graph,
+    pub camera: &'c Camera,
+    pub viewport: Rect<i32>,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub textures: &'a mut TextureCache,
+}
+
+impl ImmediateRenderer {
+    pub(in crate) fn new(state: &mut State) -> Result<Self, RendererError> {
+        let geometry = GeometryBuffer::new(GeometryBufferKind::DynamicDraw, ElementKind::Triangle);
+
+        geometry.bind(state).describe_attributes(vec![
+            AttributeDefinition {
+                kind: AttributeKind::Float3,
+                normalized: false,
+            },
+            AttributeDefinition {
+                kind: AttributeKind::Float2,
+                normalized: false,
+            },
+            AttributeDefinition {
+                kind: AttributeKind::UnsignedByte4,
+                normalized: true,
+            },
+        ])?;
+
+        Ok(Self {
+            shader: ImmediateShader::new()?,
+            geometry,
+            draw_list: Default::default(),
+        })
+    }
+
+    #[must_use]
+    pub(in crate) fn render(
+        &mut self,
+        args: ImmediateRenderContext,
+    ) -> Result<RenderPassStatistics, RendererError> {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let ImmediateRenderContext {
+            state,
+            framebuffer,
+            graph,
+            camera,
+            viewport,
+            white_dummy,
+            textures,
+        } = args;
+
+        self.draw_list.clear();
+        for (handle, node) in graph.pair_iter() {
+            if let Some(callback) = node.render_callback() {
+                if node.global_visibility() && camera.sees_layer(node.layer()) {
+                    callback.call(
+                        &RenderCallbackContext {
+                            graph,
+                            node: handle,
+                            camera,
+                        },
+                        &mut self.draw_list,
+                    );
+                    // Every node starts with its own commands.
+                    self.draw_list.end();
+                }
+            }
+        }
+
+        if self.draw_list.triangles.is_empty() {
+            return Ok(statistics);
+        }
+
+        self.geometry
+            .bind(state)
+            .set_vertices(&self.draw_list.vertices)
+            .set_triangles(&self.draw_list.triangles);
+
+        let view_projection: Mat4 = camera.view_projection_matrix();
+
+        // Opaque geometry goes first, so blended geometry will be correctly blended with it.
+        for opaque_pass in [true, false].iter() {
+            for command in self.draw_list.commands.iter() {
+                if (command.blend == ImmediateBlend::Opaque) != *opaque_pass
+                    || command.triangles.start == command.triangles.end
+                {
+                    continue;
+                }
+
+                match command.blend {
+                    ImmediateBlend::Opaque => (),
+                    ImmediateBlend::Alpha => {
+                        state.set_blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)
+                    }
+                    ImmediateBlend::Additive => state.set_blend_func(gl::SRC_ALPHA, gl::ONE),
+                }
+
+                let diffuse_texture = command
+                    .texture
+                    .clone()
+                    .and_then(|texture| textures.get(state, texture))
+                    .unwrap_or_else(|| white_dummy.clone());
+
+                statistics += framebuffer.draw_part(DrawPartContext {
+                    state,
+                    viewport,
+                    geometry: &mut self.geometry,
+                    program: &mut self.shader.program,
+                    params: DrawParameters {
+                        cull_face: CullFace::Back,
+                        culling: false,
+                        color_write: Default::default(),
+                        depth_write: *opaque_pass,
+                        stencil_test: false,
+                        depth_test: true,
+                        blend: !*opaque_pass,
+                    },
+                    uniforms: &[
+                        (
+                            self.shader.diffuse_texture,
+                            UniformValue::Sampler {
+                                index: 0,
+                                texture: diffuse_texture,
+                            },
+                        ),
+                        (self.shader.wvp_matrix, UniformValue::Mat4(view_projection)),
+                    ],
+                    offset: command.triangles.start,
+                    count: command.triangles.end - command.triangles.start,
+                })?;
+            }
+        }
+
+        Ok(statistics)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::{color::Color, math::vec3::Vec3},
+        renderer::immediate::{ImmediateBlend, ImmediateDrawList},
+    };
+
+    #[test]
+    fn test_draw_list_commands() {
+        let mut draw_list = ImmediateDrawList::default();
+        // Implicit opaque command.
+        let points = [
+            Vec3::ZERO,
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        ];
+        draw_list.push_ribbon(&points, 0.1, Vec3::new(0.0, 0.0, -5.0), Color::WHITE);
+        draw_list.begin(None, ImmediateBlend::Additive);
+        draw_list.push_ribbon(&points[..2], 0.1, Vec3::new(0.0, 0.0, -5.0), Color::WHITE);
+
+        assert_eq!(draw_list.vertices().len(), 10);
+        assert_eq!(draw_list.triangles().len(), 6);
+        let commands = draw_list.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].blend, ImmediateBlend::Opaque);
+        assert_eq!(commands[0].triangles, 0..4);
+        assert_eq!(commands[1].blend, ImmediateBlend::Additive);
+        assert_eq!(commands[1].triangles, 4..6);
+    }
+}
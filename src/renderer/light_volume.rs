@@ -229,7 +229,7 @@ impl LightVolumeRenderer {
                         ),
                         (
                             self.spot_light_shader.light_color,
-                            UniformValue::Vec3(light.color().as_frgba().xyz()),
+                            UniformValue::Vec3(light.shading_color().as_frgba().xyz()),
                         ),
                         (
                             self.spot_light_shader.scatter_factor,
@@ -322,7 +322,7 @@ impl LightVolumeRenderer {
                         ),
                         (
                             self.point_light_shader.light_color,
-                            UniformValue::Vec3(light.color().as_frgba().xyz()),
+                            UniformValue::Vec3(light.shading_color().as_frgba().xyz()),
                         ),
                         (
                             self.point_light_shader.scatter_factor,
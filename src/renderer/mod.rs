@@ -1,15 +1,21 @@
 //! Renderer is a "workhorse" of the engine, it draws scenes and user interface.
-//! For now there is almost no possibility to change pipeline of renderer, you
-//! can only modify quality settings. This will change in future to make renderer
-//! more flexible.
+//! Each camera is drawn by a set of render passes which are scheduled by a frame
+//! graph, so passes can be disabled per camera and user passes can be added, see
+//! `frame_graph` module.
 //!
 //! Renderer based on OpenGL 3.3+ Core.
 
 #![warn(missing_docs)]
 #![deny(unsafe_code)]
 
+pub mod capabilities;
 pub mod debug_renderer;
 pub mod error;
+pub mod frame_graph;
+pub mod gpu_job;
+pub mod immediate;
+pub mod quality_detection;
+pub mod screenshot;
 pub mod surface;
 
 // Framework wraps all OpenGL calls so it has to be unsafe. Rest of renderer
@@ -21,56 +27,68 @@ mod framework;
 mod blur;
 mod deferred_light_renderer;
 mod flat_shader;
+mod forward_renderer;
 mod gbuffer;
 mod light_volume;
 mod particle_system_renderer;
+mod post_effects;
 mod shadow_map_renderer;
 mod sprite_renderer;
 mod ssao;
+mod thumbnail;
 mod ui_renderer;
 
 use crate::{
     core::{
         color::Color,
-        math::{mat4::Mat4, vec2::Vec2, vec3::Vec3, Rect, TriangleDefinition},
+        math::{vec2::Vec2, Rect, TriangleDefinition},
         pool::Handle,
         scope_profile,
     },
     engine::resource_manager::TimedEntry,
     gui::draw::DrawingContext,
     renderer::{
+        capabilities::GpuCapabilities,
         debug_renderer::DebugRenderer,
         deferred_light_renderer::{DeferredLightRenderer, DeferredRendererContext},
         error::RendererError,
         flat_shader::FlatShader,
+        forward_renderer::{ForwardRenderContext, ForwardRenderer},
+        frame_graph::{camera_frame_graph, CameraPasses, PassKind},
         framework::{
-            framebuffer::{BackBuffer, CullFace, DrawParameters, FrameBufferTrait},
+            framebuffer::{BackBuffer, FrameBufferTrait},
             geometry_buffer::{
                 AttributeDefinition, AttributeKind, DrawCallStatistics, ElementKind,
                 GeometryBuffer, GeometryBufferKind,
             },
             gl,
-            gpu_program::UniformValue,
             gpu_texture::{
                 GpuTexture, GpuTextureKind, MagnificationFilter, MininificationFilter, PixelKind,
             },
             state::State,
         },
         gbuffer::{GBuffer, GBufferRenderContext},
+        gpu_job::{GpuJobContext, GpuJobs},
+        immediate::{ImmediateRenderContext, ImmediateRenderer},
         particle_system_renderer::{ParticleSystemRenderContext, ParticleSystemRenderer},
+        post_effects::PostEffectsRenderer,
         sprite_renderer::{SpriteRenderContext, SpriteRenderer},
         surface::SurfaceSharedData,
         ui_renderer::{UiRenderContext, UiRenderer},
     },
     resource::texture::{Texture, TextureKind},
-    scene::{node::Node, SceneContainer},
+    scene::{camera::Camera, node::Node, Scene, SceneContainer},
+    utils::{light_probe::LightProbeGrid, log::Log},
 };
 use glutin::PossiblyCurrent;
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
     time,
 };
 
@@ -87,6 +105,9 @@ pub struct Statistics {
     pub capped_frame_time: f32,
     /// Total amount of frames been rendered in one second.
     pub frames_per_second: usize,
+    /// Amount of texture binding changes per frame - lower the better. Redundant
+    /// bindings are skipped and not counted.
+    pub texture_binds: usize,
     frame_counter: usize,
     frame_start_time: time::Instant,
     last_fps_commit_time: time::Instant,
@@ -163,6 +184,22 @@ pub struct QualitySettings {
     /// Global switch to enable or disable light scattering. Each light can have
     /// its own scatter switch, but this one is able to globally disable scatter.
     pub light_scatter_enabled: bool,
+
+    /// Texture streaming
+    /// Whether to stream high mip levels of large textures in and out depending on
+    /// distance to camera and size of objects on screen or not. When enabled, only
+    /// levels which are really visible are kept in video memory. Mip levels of streamed
+    /// textures are prepared on worker threads and kept in memory, so streaming trades
+    /// video memory for system memory. Disabled by default.
+    pub texture_streaming: bool,
+    /// Textures with width or height less than this value are always resident at
+    /// full resolution.
+    pub texture_streaming_threshold: u32,
+    /// Size of the least detailed level of streamed texture which is always resident.
+    pub texture_streaming_base_size: u32,
+    /// Maximum amount of textures that can be re-uploaded per frame. Larger values
+    /// makes streaming more responsive, but may cause stutters.
+    pub texture_streaming_uploads_per_frame: usize,
 }
 
 impl Default for QualitySettings {
@@ -182,6 +219,11 @@ impl Default for QualitySettings {
             ssao_radius: 0.5,
 
             light_scatter_enabled: true,
+
+            texture_streaming: false,
+            texture_streaming_threshold: 2048,
+            texture_streaming_base_size: 256,
+            texture_streaming_uploads_per_frame: 2,
         }
     }
 }
@@ -228,6 +270,7 @@ impl Default for Statistics {
             pure_frame_time: 0.0,
             capped_frame_time: 0.0,
             frames_per_second: 0,
+            texture_binds: 0,
             frame_counter: 0,
             frame_start_time: time::Instant::now(),
             last_fps_commit_time: time::Instant::now(),
@@ -241,8 +284,10 @@ pub struct Renderer {
     backbuffer: BackBuffer,
     deferred_light_renderer: DeferredLightRenderer,
     flat_shader: FlatShader,
+    forward_renderer: ForwardRenderer,
     sprite_renderer: SpriteRenderer,
     particle_system_renderer: ParticleSystemRenderer,
+    post_effects_renderer: PostEffectsRenderer,
     /// Dummy white one pixel texture which will be used as stub when rendering
     /// something without texture specified.
     white_dummy: Rc<RefCell<GpuTexture>>,
@@ -257,11 +302,29 @@ pub struct Renderer {
     quality_settings: QualitySettings,
     /// Debug renderer instance can be used for debugging purposes
     pub debug_renderer: DebugRenderer,
+    immediate_renderer: ImmediateRenderer,
     /// Camera to G-buffer mapping.
     gbuffers: HashMap<Handle<Node>, GBuffer>,
     backbuffer_clear_color: Color,
     texture_cache: TextureCache,
     geometry_cache: GeometryCache,
+    capabilities: GpuCapabilities,
+    gpu_jobs: GpuJobs,
+    /// (Scene, camera) to pass configuration mapping.
+    camera_passes: HashMap<(Handle<Scene>, Handle<Node>), CameraPasses>,
+}
+
+/// Parameters of rendering of a camera by `Renderer::render_camera_passes`.
+pub(in crate) struct CameraRenderContext<'a> {
+    pub scene: &'a Scene,
+    pub camera: &'a Camera,
+    /// Viewport of camera in back buffer.
+    pub viewport: Rect<i32>,
+    pub frame_size: Vec2,
+    pub ambient_color: Color,
+    pub light_probes: Option<&'a LightProbeGrid>,
+    /// Time passed since last frame, used by user passes.
+    pub dt: f32,
 }
 
 #[derive(Default)]
@@ -314,6 +377,10 @@ impl GeometryCache {
                         kind: AttributeKind::UnsignedByte4,
                         normalized: false,
                     },
+                    AttributeDefinition {
+                        kind: AttributeKind::UnsignedByte4,
+                        normalized: true,
+                    },
                 ])
                 .unwrap()
                 .set_vertices(data.vertices.as_slice())
@@ -325,6 +392,13 @@ impl GeometryCache {
             }
         });
 
+        // Upload only modified region of vertices (for example after vertex painting).
+        if let Some((begin, end)) = data.take_dirty_vertices() {
+            geometry_buffer
+                .bind(state)
+                .set_vertices_range(begin, &data.vertices[begin..end]);
+        }
+
         geometry_buffer.time_to_live = 20.0;
         geometry_buffer
     }
@@ -341,45 +415,265 @@ impl GeometryCache {
     }
 }
 
+/// Level of mip chain prepared on worker thread.
+struct MipLevel {
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+/// Residency state of a streamed texture. Level is an index of top mip level which is
+/// currently resident on GPU, zero means full resolution. Every level is stored in the
+/// same GPU texture, levels above resident one are freed and excluded from sampling
+/// using base level of texture.
+struct StreamingEntry {
+    texture: Arc<Mutex<Texture>>,
+    resident_level: u32,
+    /// Most detailed level requested since last update.
+    wanted_level: u32,
+    /// Amount of time during which less detailed level was enough. Used to not
+    /// re-upload textures back and forth when camera moves around threshold distance.
+    drop_timer: f32,
+    /// Levels starting from first one, they are required to stream details back in.
+    mips: Vec<MipLevel>,
+    /// Receives mip chain from worker thread.
+    pending_mips: Option<Receiver<Vec<MipLevel>>>,
+}
+
+/// Time in seconds during which a texture must be unused at resident level before its
+/// high mips will be dropped.
+const STREAMING_DROP_DELAY: f32 = 2.0;
+
+#[derive(Copy, Clone, Default)]
+struct StreamingSettings {
+    enabled: bool,
+    threshold: u32,
+    base_size: u32,
+    uploads_per_frame: usize,
+}
+
+impl StreamingSettings {
+    /// Returns least detailed level of a texture which can be used without violation of
+    /// base size. Zero for textures which are not streamed. Float textures are not
+    /// streamed, mips are built by averaging of bytes.
+    fn max_level(&self, texture: &Texture) -> u32 {
+        let size = texture.width.max(texture.height);
+        if !self.enabled || size < self.threshold || texture.kind.is_float() {
+            return 0;
+        }
+        let mut level = 0;
+        while (size >> (level + 1)) >= self.base_size {
+            level += 1;
+        }
+        level
+    }
+}
+
+/// Downsamples image with box filter, each call halves width and height.
+fn downsample(bytes: &[u8], width: u32, height: u32, bpp: usize) -> (Vec<u8>, u32, u32) {
+    let new_width = (width / 2).max(1);
+    let new_height = (height / 2).max(1);
+    let mut result = Vec::with_capacity(new_width as usize * new_height as usize * bpp);
+    for y in 0..new_height {
+        for x in 0..new_width {
+            for c in 0..bpp {
+                let mut sum = 0u32;
+                for (dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(width - 1) as usize;
+                    let sy = (y * 2 + dy).min(height - 1) as usize;
+                    sum += u32::from(bytes[(sy * width as usize + sx) * bpp + c]);
+                }
+                result.push((sum / 4) as u8);
+            }
+        }
+    }
+    (result, new_width, new_height)
+}
+
+/// Starts building of mip levels in `1..level_count` range on worker thread.
+fn build_mips_async(texture: &Texture, level_count: u32) -> Receiver<Vec<MipLevel>> {
+    let (sender, receiver) = mpsc::channel();
+    let bytes = texture.bytes.clone();
+    let (width, height) = (texture.width, texture.height);
+    let bpp = texture.kind.bytes_per_pixel();
+    rayon::spawn(move || {
+        let mut mips: Vec<MipLevel> = Vec::new();
+        for _ in 1..level_count {
+            let (bytes, width, height) = match mips.last() {
+                Some(prev) => downsample(&prev.bytes, prev.width, prev.height, bpp),
+                None => downsample(&bytes, width, height, bpp),
+            };
+            mips.push(MipLevel {
+                width,
+                height,
+                bytes,
+            });
+        }
+        // Cache could be cleared while mips were built.
+        let _ = sender.send(mips);
+    });
+    receiver
+}
+
+fn upload_texture(state: &mut State, texture: &Texture) -> GpuTexture {
+    let kind = GpuTextureKind::Rectangle {
+        width: texture.width as usize,
+        height: texture.height as usize,
+    };
+    let mut gpu_texture = GpuTexture::new(
+        state,
+        kind,
+        PixelKind::from(texture.kind),
+        Some(texture.bytes.as_slice()),
+    )
+    .unwrap();
+    gpu_texture
+        .bind_mut(state, 0)
+        .generate_mip_maps()
+        .set_minification_filter(MininificationFilter::LinearMip)
+        .set_magnification_filter(MagnificationFilter::Linear)
+        .set_max_anisotropy();
+    gpu_texture
+}
+
+/// Makes `new_level` top resident level of streamed texture. Dropped levels are freed,
+/// restored levels are uploaded from prepared mip chain, texture object is kept.
+fn set_resident_level(
+    state: &mut State,
+    gpu_texture: &mut GpuTexture,
+    texture: &Texture,
+    mips: &[MipLevel],
+    resident_level: u32,
+    new_level: u32,
+) {
+    let pixel_kind = PixelKind::from(texture.kind);
+    let mut binding = gpu_texture.bind_mut(state, 0);
+    if new_level > resident_level {
+        binding = binding.set_base_level(new_level);
+        for level in resident_level..new_level {
+            binding = binding.set_mip_level(level, 0, 0, pixel_kind, None);
+        }
+    } else {
+        for level in new_level..resident_level {
+            binding = if level == 0 {
+                binding.set_mip_level(
+                    0,
+                    texture.width as usize,
+                    texture.height as usize,
+                    pixel_kind,
+                    Some(&texture.bytes),
+                )
+            } else {
+                let mip = &mips[level as usize - 1];
+                binding.set_mip_level(
+                    level,
+                    mip.width as usize,
+                    mip.height as usize,
+                    pixel_kind,
+                    Some(&mip.bytes),
+                )
+            };
+        }
+        binding.set_base_level(new_level);
+    }
+}
+
 #[derive(Default)]
 pub(in crate) struct TextureCache {
     map: HashMap<usize, TimedEntry<Rc<RefCell<GpuTexture>>>>,
+    // GPU textures which are owned by renderer (i.e. results of GPU jobs), they're never
+    // uploaded or evicted, even by `clear`.
+    pinned: HashMap<usize, Rc<RefCell<GpuTexture>>>,
+    streaming: HashMap<usize, StreamingEntry>,
+    streaming_settings: StreamingSettings,
 }
 
 impl TextureCache {
+    fn new(settings: &QualitySettings) -> Self {
+        let mut cache = Self::default();
+        cache.set_streaming_settings(settings);
+        cache
+    }
+
+    fn set_streaming_settings(&mut self, settings: &QualitySettings) {
+        self.streaming_settings = StreamingSettings {
+            enabled: settings.texture_streaming,
+            threshold: settings.texture_streaming_threshold,
+            base_size: settings.texture_streaming_base_size.max(1),
+            uploads_per_frame: settings.texture_streaming_uploads_per_frame,
+        };
+    }
+
     fn get(
         &mut self,
         state: &mut State,
         texture: Arc<Mutex<Texture>>,
+    ) -> Option<Rc<RefCell<GpuTexture>>> {
+        // Unknown coverage - full resolution is required.
+        self.get_streamed(state, texture, std::f32::INFINITY)
+    }
+
+    /// Returns GPU texture for given texture, `coverage` is approximate size in pixels of
+    /// the object on screen, it is used to decide which mip levels must be resident.
+    /// Requested levels are uploaded during `update`, so this method returns currently
+    /// resident version of texture.
+    fn get_streamed(
+        &mut self,
+        state: &mut State,
+        texture: Arc<Mutex<Texture>>,
+        coverage: f32,
     ) -> Option<Rc<RefCell<GpuTexture>>> {
         scope_profile!();
 
-        if texture.lock().unwrap().loaded {
+        if let Some(pinned) = self.pinned.get(&((&*texture as *const _) as usize)) {
+            return Some(pinned.clone());
+        }
+
+        let texture_ref = texture.lock().unwrap();
+        if texture_ref.loaded {
             let key = (&*texture as *const _) as usize;
-            let gpu_texture = self.map.entry(key).or_insert_with(move || {
-                let texture = texture.lock().unwrap();
-                let kind = GpuTextureKind::Rectangle {
-                    width: texture.width as usize,
-                    height: texture.height as usize,
-                };
-                let mut gpu_texture = GpuTexture::new(
-                    state,
-                    kind,
-                    PixelKind::from(texture.kind),
-                    Some(texture.bytes.as_slice()),
-                )
-                .unwrap();
-                gpu_texture
-                    .bind_mut(state, 0)
-                    .generate_mip_maps()
-                    .set_minification_filter(MininificationFilter::LinearMip)
-                    .set_magnification_filter(MagnificationFilter::Linear)
-                    .set_max_anisotropy();
-                TimedEntry {
-                    value: Rc::new(RefCell::new(gpu_texture)),
-                    time_to_live: 20.0,
+
+            let max_level = self.streaming_settings.max_level(&texture_ref);
+            let wanted_level = if max_level > 0 && coverage.is_finite() {
+                let size = texture_ref.width.max(texture_ref.height) as f32;
+                let ratio = size / coverage.max(1.0);
+                if ratio > 1.0 {
+                    (ratio.log2().floor() as u32).min(max_level)
+                } else {
+                    0
                 }
-            });
+            } else {
+                0
+            };
+
+            let gpu_texture = match self.map.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    // First upload is done at full resolution with mips generated by GPU,
+                    // unneeded details will be dropped by `update` later on.
+                    let gpu_texture = upload_texture(state, &texture_ref);
+                    if max_level > 0 {
+                        self.streaming.insert(
+                            key,
+                            StreamingEntry {
+                                texture: texture.clone(),
+                                resident_level: 0,
+                                wanted_level,
+                                drop_timer: 0.0,
+                                mips: Vec::new(),
+                                pending_mips: Some(build_mips_async(&texture_ref, max_level)),
+                            },
+                        );
+                    }
+                    entry.insert(TimedEntry {
+                        value: Rc::new(RefCell::new(gpu_texture)),
+                        time_to_live: 20.0,
+                    })
+                }
+            };
+            if let Some(streaming) = self.streaming.get_mut(&key) {
+                streaming.wanted_level = streaming.wanted_level.min(wanted_level);
+            }
             // Texture won't be destroyed while it used.
             gpu_texture.time_to_live = 20.0;
             Some(gpu_texture.value.clone())
@@ -388,15 +682,87 @@ impl TextureCache {
         }
     }
 
-    fn update(&mut self, dt: f32) {
+    fn update(&mut self, state: &mut State, dt: f32) {
         for entry in self.map.values_mut() {
             entry.time_to_live -= dt;
         }
         self.map.retain(|_, v| v.time_to_live > 0.0);
+        let map = &self.map;
+        self.streaming.retain(|key, _| map.contains_key(key));
+
+        let settings = self.streaming_settings;
+        let mut uploads = 0;
+        for (key, streaming) in self.streaming.iter_mut() {
+            if let Some(pending_mips) = streaming.pending_mips.as_ref() {
+                if let Ok(mips) = pending_mips.try_recv() {
+                    streaming.mips = mips;
+                    streaming.pending_mips = None;
+                }
+            }
+
+            let texture = streaming.texture.lock().unwrap();
+            // Settings could be changed since texture was uploaded. Details can be
+            // dropped only when they can be streamed back in, so texture stays at full
+            // resolution until its mips are prepared.
+            let prepared_level = if streaming.pending_mips.is_some() {
+                0
+            } else {
+                streaming.mips.len() as u32 + 1
+            };
+            let max_level = settings.max_level(&texture).min(prepared_level);
+            let wanted_level = streaming.wanted_level.min(max_level);
+
+            let new_level = if wanted_level < streaming.resident_level {
+                // More details needed - stream in as soon as possible.
+                streaming.drop_timer = 0.0;
+                Some(wanted_level)
+            } else if wanted_level > streaming.resident_level {
+                // Details are not needed anymore, but drop them only after a while.
+                streaming.drop_timer += dt;
+                if streaming.drop_timer >= STREAMING_DROP_DELAY {
+                    Some(wanted_level)
+                } else {
+                    None
+                }
+            } else {
+                streaming.drop_timer = 0.0;
+                None
+            };
+
+            if let Some(new_level) = new_level {
+                if uploads < settings.uploads_per_frame {
+                    uploads += 1;
+                    set_resident_level(
+                        state,
+                        &mut self.map[key].value.borrow_mut(),
+                        &texture,
+                        &streaming.mips,
+                        streaming.resident_level,
+                        new_level,
+                    );
+                    streaming.resident_level = new_level;
+                    streaming.drop_timer = 0.0;
+                }
+            }
+
+            // Texture that was not requested during frame needs only its base level.
+            streaming.wanted_level = max_level;
+        }
     }
 
     fn clear(&mut self) {
         self.map.clear();
+        self.streaming.clear();
+    }
+
+    /// Makes given texture an alias of GPU texture owned by renderer until `unpin` is called.
+    fn pin(&mut self, texture: &Arc<Mutex<Texture>>, gpu_texture: Rc<RefCell<GpuTexture>>) {
+        self.pinned
+            .insert((&**texture as *const _) as usize, gpu_texture);
+    }
+
+    fn unpin(&mut self, texture: &Arc<Mutex<Texture>>) {
+        self.pinned.remove(&((&**texture as *const _) as usize));
     }
 }
 
@@ -410,11 +776,15 @@ impl Renderer {
         let settings = QualitySettings::default();
         let mut state = State::new();
 
+        let capabilities = framework::capabilities::query();
+        Log::writeln(format!("{}", capabilities));
+
         Ok(Self {
             backbuffer: BackBuffer,
             frame_size,
             deferred_light_renderer: DeferredLightRenderer::new(&mut state, frame_size, &settings)?,
             flat_shader: FlatShader::new()?,
+            forward_renderer: ForwardRenderer::new()?,
             statistics: Statistics::default(),
             sprite_renderer: SpriteRenderer::new()?,
             white_dummy: Rc::new(RefCell::new(GpuTexture::new(
@@ -438,13 +808,18 @@ impl Renderer {
             quad: SurfaceSharedData::make_unit_xy_quad(),
             ui_renderer: UiRenderer::new(&mut state)?,
             particle_system_renderer: ParticleSystemRenderer::new(&mut state)?,
+            post_effects_renderer: PostEffectsRenderer::new()?,
             ambient_color: Color::opaque(100, 100, 100),
             quality_settings: settings,
             debug_renderer: DebugRenderer::new(&mut state)?,
+            immediate_renderer: ImmediateRenderer::new(&mut state)?,
             gbuffers: Default::default(),
             backbuffer_clear_color: Color::from_rgba(0, 0, 0, 0),
-            texture_cache: Default::default(),
+            texture_cache: TextureCache::new(&settings),
             geometry_cache: Default::default(),
+            capabilities,
+            gpu_jobs: Default::default(),
+            camera_passes: Default::default(),
             state,
         })
     }
@@ -490,6 +865,12 @@ impl Renderer {
         self.frame_size
     }
 
+    /// Returns capabilities of GPU and its driver, they're detected once on creation of
+    /// renderer.
+    pub fn capabilities(&self) -> &GpuCapabilities {
+        &self.capabilities
+    }
+
     /// Sets new quality settings for renderer. Never call this method in a loop, otherwise
     /// you may get **significant** lags. Always check if current quality setting differs
     /// from new!
@@ -498,6 +879,7 @@ impl Renderer {
         settings: &QualitySettings,
     ) -> Result<(), RendererError> {
         self.quality_settings = *settings;
+        self.texture_cache.set_streaming_settings(settings);
         self.deferred_light_renderer
             .set_quality_settings(&mut self.state, settings)
     }
@@ -518,7 +900,8 @@ impl Renderer {
     fn render_frame(
         &mut self,
         scenes: &SceneContainer,
-        drawing_context: &DrawingContext,
+        drawing_context: Option<&DrawingContext>,
+        ui_scale: f32,
         dt: f32,
     ) -> Result<(), RendererError> {
         scope_profile!();
@@ -531,10 +914,13 @@ impl Renderer {
 
         // Update caches - this will remove timed out resources.
         self.geometry_cache.update(dt);
-        self.texture_cache.update(dt);
+        self.texture_cache.update(&mut self.state, dt);
 
         self.statistics.begin_frame();
 
+        // Results of user jobs can be used by scenes, so jobs must be run first.
+        self.statistics += self.run_gpu_jobs(dt);
+
         let window_viewport = Rect::new(0, 0, self.frame_size.0 as i32, self.frame_size.1 as i32);
         self.backbuffer.clear(
             &mut self.state,
@@ -547,7 +933,14 @@ impl Renderer {
         let frame_width = self.frame_size.0 as f32;
         let frame_height = self.frame_size.1 as f32;
 
-        for scene in scenes.iter() {
+        // Forget pass configuration of removed cameras.
+        self.camera_passes.retain(|(scene, camera), _| {
+            scenes.is_valid_handle(*scene)
+                && scenes[*scene].graph.is_valid_handle(*camera)
+                && scenes[*scene].graph[*camera].is_camera()
+        });
+
+        for (scene_handle, scene) in scenes.pair_iter() {
             let graph = &scene.graph;
 
             for (camera_handle, camera) in graph.pair_iter().filter_map(|(handle, node)| {
@@ -563,20 +956,18 @@ impl Renderer {
 
                 let viewport = camera.viewport_pixels(Vec2::new(frame_width, frame_height));
 
-                let state = &mut self.state;
-                let gbuffer = self
-                    .gbuffers
-                    .entry(camera_handle)
-                    .and_modify(|buf| {
-                        if buf.width != viewport.w || buf.height != viewport.h {
-                            let width = (viewport.w as usize).max(1);
-                            let height = (viewport.h as usize).max(1);
-                            *buf = GBuffer::new(state, width, height).unwrap();
-                        }
-                    })
-                    .or_insert_with(|| {
-                        GBuffer::new(state, viewport.w as usize, viewport.h as usize).unwrap()
-                    });
+                let mut gbuffer = match self.gbuffers.remove(&camera_handle) {
+                    Some(gbuffer)
+                        if gbuffer.width == viewport.w && gbuffer.height == viewport.h =>
+                    {
+                        gbuffer
+                    }
+                    _ => GBuffer::new(
+                        &mut self.state,
+                        (viewport.w as usize).max(1),
+                        (viewport.h as usize).max(1),
+                    )?,
+                };
 
                 // If we specified a texture to draw to, we have to register it in texture cache
                 // so it can be used in later on as texture. This is useful in case if you need
@@ -602,135 +993,228 @@ impl Renderer {
                     }
                 }
 
-                self.statistics += gbuffer.fill(GBufferRenderContext {
-                    state,
-                    graph,
-                    camera,
-                    white_dummy: self.white_dummy.clone(),
-                    normal_dummy: self.normal_dummy.clone(),
-                    texture_cache: &mut self.texture_cache,
-                    geom_cache: &mut self.geometry_cache,
-                });
-
-                self.statistics += self
-                    .deferred_light_renderer
-                    .render(DeferredRendererContext {
-                        state,
+                let (pass_graph, output) = camera_frame_graph(
+                    scene.render_path,
+                    scene.render_target.is_some(),
+                    self.camera_passes.get(&(scene_handle, camera_handle)),
+                    &self.gpu_jobs,
+                );
+                let passes = pass_graph
+                    .compile(&[output])
+                    .into_iter()
+                    .map(|pass| *pass.payload())
+                    .collect::<Vec<_>>();
+
+                let result = self.render_camera_passes(
+                    &passes,
+                    &mut gbuffer,
+                    CameraRenderContext {
                         scene,
                         camera,
-                        gbuffer,
-                        white_dummy: self.white_dummy.clone(),
+                        viewport,
+                        frame_size: Vec2::new(frame_width, frame_height),
                         ambient_color: self.ambient_color,
-                        settings: &self.quality_settings,
-                        textures: &mut self.texture_cache,
-                        geometry_cache: &mut self.geometry_cache,
-                    });
+                        light_probes: scene.light_probes(),
+                        dt,
+                    },
+                );
+                self.gbuffers.insert(camera_handle, gbuffer);
+                self.statistics += result?;
+            }
+        }
+
+        // Render UI on top of everything.
+        if let Some(drawing_context) = drawing_context {
+            self.statistics += self.ui_renderer.render(UiRenderContext {
+                state: &mut self.state,
+                viewport: window_viewport,
+                backbuffer: &mut self.backbuffer,
+                frame_width,
+                frame_height,
+                ui_scale,
+                drawing_context,
+                white_dummy: self.white_dummy.clone(),
+                texture_cache: &mut self.texture_cache,
+            })?;
+        }
 
-                let depth = gbuffer.depth();
+        Ok(())
+    }
 
-                self.statistics +=
-                    self.particle_system_renderer
-                        .render(ParticleSystemRenderContext {
+    /// Executes given passes of a camera, see `frame_graph` module.
+    pub(in crate) fn render_camera_passes(
+        &mut self,
+        passes: &[PassKind],
+        gbuffer: &mut GBuffer,
+        context: CameraRenderContext,
+    ) -> Result<RenderPassStatistics, RendererError> {
+        let CameraRenderContext {
+            scene,
+            camera,
+            viewport,
+            frame_size,
+            ambient_color,
+            light_probes,
+            dt,
+        } = context;
+        let graph = &scene.graph;
+        let state = &mut self.state;
+        let mut statistics = RenderPassStatistics::default();
+
+        for &pass in passes {
+            match pass {
+                PassKind::GBuffer => {
+                    statistics += gbuffer.fill(GBufferRenderContext {
+                        state,
+                        graph,
+                        camera,
+                        white_dummy: self.white_dummy.clone(),
+                        normal_dummy: self.normal_dummy.clone(),
+                        texture_cache: &mut self.texture_cache,
+                        geom_cache: &mut self.geometry_cache,
+                        light_probes,
+                    });
+                }
+                PassKind::Lighting => {
+                    statistics += self
+                        .deferred_light_renderer
+                        .render(DeferredRendererContext {
                             state,
-                            framebuffer: &mut gbuffer.final_frame,
-                            graph,
+                            scene,
                             camera,
+                            gbuffer,
                             white_dummy: self.white_dummy.clone(),
-                            depth,
-                            frame_width,
-                            frame_height,
-                            viewport,
-                            texture_cache: &mut self.texture_cache,
+                            ambient_color,
+                            settings: &self.quality_settings,
+                            textures: &mut self.texture_cache,
+                            geometry_cache: &mut self.geometry_cache,
                         });
-
-                self.statistics += self.sprite_renderer.render(SpriteRenderContext {
-                    state,
-                    framebuffer: &mut gbuffer.final_frame,
-                    graph,
-                    camera,
-                    white_dummy: self.white_dummy.clone(),
-                    viewport,
-                    textures: &mut self.texture_cache,
-                    geom_map: &mut self.geometry_cache,
-                });
-
-                self.statistics +=
-                    self.debug_renderer
-                        .render(state, viewport, &mut gbuffer.final_frame, camera);
-
+                }
+                PassKind::Forward => {
+                    statistics += self.forward_renderer.render(ForwardRenderContext {
+                        state,
+                        graph,
+                        camera,
+                        framebuffer: &mut gbuffer.final_frame,
+                        viewport,
+                        white_dummy: self.white_dummy.clone(),
+                        normal_dummy: self.normal_dummy.clone(),
+                        ambient_color,
+                        texture_cache: &mut self.texture_cache,
+                        geom_cache: &mut self.geometry_cache,
+                        light_probes,
+                    });
+                }
+                PassKind::Particles => {
+                    statistics +=
+                        self.particle_system_renderer
+                            .render(ParticleSystemRenderContext {
+                                state,
+                                framebuffer: &mut gbuffer.final_frame,
+                                graph,
+                                camera,
+                                white_dummy: self.white_dummy.clone(),
+                                depth: gbuffer.depth(),
+                                frame_width: frame_size.x,
+                                frame_height: frame_size.y,
+                                viewport,
+                                texture_cache: &mut self.texture_cache,
+                            });
+                }
+                PassKind::Sprites => {
+                    statistics += self.sprite_renderer.render(SpriteRenderContext {
+                        state,
+                        framebuffer: &mut gbuffer.final_frame,
+                        graph,
+                        camera,
+                        white_dummy: self.white_dummy.clone(),
+                        viewport,
+                        textures: &mut self.texture_cache,
+                        geom_map: &mut self.geometry_cache,
+                    });
+                }
+                PassKind::PostEffects => {
+                    statistics += self.post_effects_renderer.render(
+                        state,
+                        &mut self.geometry_cache,
+                        &self.quad,
+                        gbuffer,
+                        camera,
+                    )?;
+                }
+                PassKind::Immediate => {
+                    statistics += self.immediate_renderer.render(ImmediateRenderContext {
+                        state,
+                        framebuffer: &mut gbuffer.final_frame,
+                        graph,
+                        camera,
+                        viewport,
+                        white_dummy: self.white_dummy.clone(),
+                        textures: &mut self.texture_cache,
+                    })?;
+                }
+                PassKind::Debug => {
+                    statistics += self.debug_renderer.render(
+                        state,
+                        viewport,
+                        &mut gbuffer.final_frame,
+                        camera,
+                    );
+                }
+                PassKind::User { job, writes_frame } => {
+                    let mut context = GpuJobContext {
+                        state,
+                        texture_cache: &mut self.texture_cache,
+                        geometry_cache: &mut self.geometry_cache,
+                        white_dummy: &self.white_dummy,
+                        quad: &self.quad,
+                        dt,
+                        camera: Some((gbuffer.frame_texture(), gbuffer.depth())),
+                    };
+                    if let Some(job_statistics) = self.gpu_jobs.run(job, &mut context) {
+                        statistics += job_statistics;
+                    }
+                    if writes_frame {
+                        if let Some(result) = self.gpu_jobs.result_of(job) {
+                            statistics += self.flat_shader.blit(
+                                &mut gbuffer.final_frame,
+                                self.geometry_cache.get(state, &self.quad),
+                                state,
+                                Rect::new(0, 0, gbuffer.width, gbuffer.height),
+                                result,
+                            );
+                        }
+                    }
+                }
                 // Finally render everything into back buffer.
-                if scene.render_target.is_none() {
-                    self.statistics.geometry += self.backbuffer.draw(
+                PassKind::Present => {
+                    statistics += self.flat_shader.blit(
+                        &mut self.backbuffer,
                         self.geometry_cache.get(state, &self.quad),
                         state,
                         viewport,
-                        &self.flat_shader.program,
-                        DrawParameters {
-                            cull_face: CullFace::Back,
-                            culling: false,
-                            color_write: Default::default(),
-                            depth_write: true,
-                            stencil_test: false,
-                            depth_test: false,
-                            blend: false,
-                        },
-                        &[
-                            (
-                                self.flat_shader.wvp_matrix,
-                                UniformValue::Mat4({
-                                    Mat4::ortho(
-                                        0.0,
-                                        viewport.w as f32,
-                                        viewport.h as f32,
-                                        0.0,
-                                        -1.0,
-                                        1.0,
-                                    ) * Mat4::scale(Vec3::new(
-                                        viewport.w as f32,
-                                        viewport.h as f32,
-                                        0.0,
-                                    ))
-                                }),
-                            ),
-                            (
-                                self.flat_shader.diffuse_texture,
-                                UniformValue::Sampler {
-                                    index: 0,
-                                    texture: gbuffer.frame_texture(),
-                                },
-                            ),
-                        ],
+                        gbuffer.frame_texture(),
                     );
                 }
             }
         }
 
-        // Render UI on top of everything.
-        self.statistics += self.ui_renderer.render(UiRenderContext {
-            state: &mut self.state,
-            viewport: window_viewport,
-            backbuffer: &mut self.backbuffer,
-            frame_width,
-            frame_height,
-            drawing_context,
-            white_dummy: self.white_dummy.clone(),
-            texture_cache: &mut self.texture_cache,
-        })?;
-
-        Ok(())
+        Ok(statistics)
     }
 
     pub(in crate) fn render_and_swap_buffers(
         &mut self,
         scenes: &SceneContainer,
-        drawing_context: &DrawingContext,
+        drawing_context: Option<&DrawingContext>,
+        ui_scale: f32,
         context: &glutin::WindowedContext<PossiblyCurrent>,
         dt: f32,
     ) -> Result<(), RendererError> {
         scope_profile!();
 
-        self.render_frame(scenes, drawing_context, dt)?;
+        self.render_frame(scenes, drawing_context, ui_scale, dt)?;
 
+        self.statistics.texture_binds = self.state.take_texture_binds();
         self.statistics.end_frame();
         context.swap_buffers()?;
         check_gl_error!();
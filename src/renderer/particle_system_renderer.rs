@@ -142,6 +142,10 @@ impl ParticleSystemRenderer {
                 continue;
             };
 
+            if !camera.sees_layer(particle_system.layer()) {
+                continue;
+            }
+
             particle_system.generate_draw_data(
                 &mut self.sorted_particles,
                 &mut self.draw_data,
@@ -0,0 +1,248 @@
+use crate::{
+    core::{
+        math::{mat4::Mat4, vec3::Vec3, Rect},
+        scope_profile,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            framebuffer::{
+                Attachment, AttachmentKind, CullFace, DrawParameters, FrameBuffer, FrameBufferTrait,
+            },
+            gpu_program::{GpuProgram, UniformLocation, UniformValue},
+            gpu_texture::{Coordinate, GpuTexture, GpuTextureKind, PixelKind, WrapMode},
+            state::State,
+        },
+        gbuffer::GBuffer,
+        surface::SurfaceSharedData,
+        GeometryCache, RenderPassStatistics,
+    },
+    scene::{camera::Camera, color_blindness::IDENTITY},
+};
+use std::{cell::RefCell, rc::Rc};
+
+struct Shader {
+    program: GpuProgram,
+    world_view_projection_matrix: UniformLocation,
+    frame_texture: UniformLocation,
+    depth_texture: UniformLocation,
+    inv_projection_matrix: UniformLocation,
+    exposure: UniformLocation,
+    focus_distance: UniformLocation,
+    focus_range: UniformLocation,
+    max_blur: UniformLocation,
+    color_matrix: UniformLocation,
+}
+
+impl Shader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/post_effects_fs.glsl");
+        let vertex_source = include_str!("shaders/post_effects_vs.glsl");
+
+        let program = GpuProgram::from_source("PostEffectsShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            world_view_projection_matrix: program.uniform_location("worldViewProjection")?,
+            frame_texture: program.uniform_location("frameTexture")?,
+            depth_texture: program.uniform_location("depthTexture")?,
+            inv_projection_matrix: program.uniform_location("inverseProjectionMatrix")?,
+            exposure: program.uniform_location("exposure")?,
+            focus_distance: program.uniform_location("focusDistance")?,
+            focus_range: program.uniform_location("focusRange")?,
+            max_blur: program.uniform_location("maxBlur")?,
+            color_matrix: program.uniform_location("colorMatrix")?,
+            program,
+        })
+    }
+}
+
+fn draw_parameters() -> DrawParameters {
+    DrawParameters {
+        cull_face: CullFace::Back,
+        culling: false,
+        color_write: Default::default(),
+        depth_write: false,
+        stencil_test: false,
+        depth_test: false,
+        blend: false,
+    }
+}
+
+/// Applies exposure, depth of field and color blindness filter of camera to final frame
+/// of a G-Buffer.
+pub struct PostEffectsRenderer {
+    shader: Shader,
+    // Intermediate frame, it is re-created when size of G-Buffer changes.
+    framebuffer: Option<FrameBuffer>,
+    width: i32,
+    height: i32,
+}
+
+impl PostEffectsRenderer {
+    pub fn new() -> Result<Self, RendererError> {
+        Ok(Self {
+            shader: Shader::new()?,
+            framebuffer: None,
+            width: 0,
+            height: 0,
+        })
+    }
+
+    fn create_framebuffer(
+        state: &mut State,
+        width: usize,
+        height: usize,
+    ) -> Result<FrameBuffer, RendererError> {
+        let kind = GpuTextureKind::Rectangle { width, height };
+        let mut texture = GpuTexture::new(state, kind, PixelKind::RGBA8, None)?;
+        texture
+            .bind_mut(state, 0)
+            .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
+            .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
+
+        Ok(FrameBuffer::new(
+            state,
+            None,
+            vec![Attachment {
+                kind: AttachmentKind::Color,
+                texture: Rc::new(RefCell::new(texture)),
+            }],
+        )?)
+    }
+
+    pub(in crate) fn render(
+        &mut self,
+        state: &mut State,
+        geom_cache: &mut GeometryCache,
+        quad: &SurfaceSharedData,
+        gbuffer: &mut GBuffer,
+        camera: &Camera,
+    ) -> Result<RenderPassStatistics, RendererError> {
+        scope_profile!();
+
+        let mut stats = RenderPassStatistics::default();
+
+        if !camera.has_post_effects() {
+            return Ok(stats);
+        }
+
+        if self.framebuffer.is_none()
+            || self.width != gbuffer.width
+            || self.height != gbuffer.height
+        {
+            self.framebuffer = Some(Self::create_framebuffer(
+                state,
+                gbuffer.width.max(1) as usize,
+                gbuffer.height.max(1) as usize,
+            )?);
+            self.width = gbuffer.width;
+            self.height = gbuffer.height;
+        }
+        let framebuffer = self.framebuffer.as_mut().unwrap();
+
+        let viewport = Rect::new(0, 0, gbuffer.width, gbuffer.height);
+        let frame_matrix = Mat4::ortho(0.0, viewport.w as f32, viewport.h as f32, 0.0, -1.0, 1.0)
+            * Mat4::scale(Vec3::new(viewport.w as f32, viewport.h as f32, 0.0));
+        let inv_projection = camera.projection_matrix().inverse().unwrap_or_default();
+        let dof = camera.depth_of_field().unwrap_or_default();
+        let max_blur = camera.depth_of_field().map_or(0.0, |dof| dof.max_blur);
+        let color_matrix = camera
+            .color_blind_filter()
+            .map_or(IDENTITY, |filter| filter.matrix());
+
+        // Apply effects to intermediate frame.
+        stats += framebuffer.draw(
+            geom_cache.get(state, quad),
+            state,
+            viewport,
+            &self.shader.program,
+            draw_parameters(),
+            &[
+                (
+                    self.shader.world_view_projection_matrix,
+                    UniformValue::Mat4(frame_matrix),
+                ),
+                (
+                    self.shader.frame_texture,
+                    UniformValue::Sampler {
+                        index: 0,
+                        texture: gbuffer.frame_texture(),
+                    },
+                ),
+                (
+                    self.shader.depth_texture,
+                    UniformValue::Sampler {
+                        index: 1,
+                        texture: gbuffer.depth(),
+                    },
+                ),
+                (
+                    self.shader.inv_projection_matrix,
+                    UniformValue::Mat4(inv_projection),
+                ),
+                (self.shader.exposure, UniformValue::Float(camera.exposure())),
+                (
+                    self.shader.focus_distance,
+                    UniformValue::Float(dof.focus_distance),
+                ),
+                (
+                    self.shader.focus_range,
+                    UniformValue::Float(dof.focus_range),
+                ),
+                (self.shader.max_blur, UniformValue::Float(max_blur)),
+                (
+                    self.shader.color_matrix,
+                    UniformValue::Vec3Array(&color_matrix),
+                ),
+            ],
+        );
+
+        // Copy result back, so render targets and screenshots see processed frame too.
+        let result = framebuffer.color_attachments()[0].texture.clone();
+        stats += gbuffer.final_frame.draw(
+            geom_cache.get(state, quad),
+            state,
+            viewport,
+            &self.shader.program,
+            draw_parameters(),
+            &[
+                (
+                    self.shader.world_view_projection_matrix,
+                    UniformValue::Mat4(frame_matrix),
+                ),
+                (
+                    self.shader.frame_texture,
+                    UniformValue::Sampler {
+                        index: 0,
+                        texture: result.clone(),
+                    },
+                ),
+                (
+                    // Depth is attached to final frame, so it must not be sampled here.
+                    // Blur is disabled in this pass, so any texture will do.
+                    self.shader.depth_texture,
+                    UniformValue::Sampler {
+                        index: 1,
+                        texture: result,
+                    },
+                ),
+                (
+                    self.shader.inv_projection_matrix,
+                    UniformValue::Mat4(inv_projection),
+                ),
+                (self.shader.exposure, UniformValue::Float(1.0)),
+                (
+                    self.shader.focus_distance,
+                    UniformValue::Float(dof.focus_distance),
+                ),
+                (
+                    self.shader.focus_range,
+                    UniformValue::Float(dof.focus_range),
+                ),
+                (self.shader.max_blur, UniformValue::Float(0.0)),
+                (self.shader.color_matrix, UniformValue::Vec3Array(&IDENTITY)),
+            ],
+        );
+
+        Ok(stats)
+    }
+}
@@ -0,0 +1,366 @@
+//! Automatic detection of quality preset.
+//!
+//! Detection combines two estimates: heuristic one which is based on capabilities of GPU
+//! (its name, amount of video memory, texture limits) and benchmark one which renders a
+//! small calibration scene with shadows for a few frames and measures average frame time.
+//! Lowest of both estimates wins, so neither a fast GPU with tiny amount of memory nor
+//! a well-known GPU stuck with a slow driver ends up at unplayable settings. Detection
+//! takes a fraction of a second and is meant to be done once on first run, result should
+//! be saved together with other game settings.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::renderer::Renderer;
+//!
+//! fn first_run(renderer: &mut Renderer) {
+//!     let detection = renderer.detect_quality_preset().unwrap();
+//!     renderer
+//!         .set_quality_settings(&detection.preset.settings())
+//!         .unwrap();
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::{mat4::Mat4, quat::Quat, vec2::Vec2, vec3::Vec3, Rect},
+        pool::Handle,
+    },
+    renderer::{
+        capabilities::GpuCapabilities,
+        error::RendererError,
+        frame_graph::offscreen_passes,
+        framework::framebuffer::FrameBufferTrait,
+        gbuffer::GBuffer,
+        surface::{Surface, SurfaceSharedData},
+        QualitySettings, Renderer,
+    },
+    scene::{
+        base::BaseBuilder,
+        camera::CameraBuilder,
+        light::{BaseLightBuilder, PointLightBuilder, SpotLightBuilder},
+        mesh::MeshBuilder,
+        node::Node,
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Predefined set of quality settings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QualityPreset {
+    /// No shadows and no SSAO, for integrated and software renderers.
+    Low,
+    /// Hard shadows of small resolution at short distance.
+    Medium,
+    /// Soft shadows, SSAO, light scattering. Same as default settings.
+    High,
+    /// Same as high, but with larger shadow maps and shadow distance.
+    Ultra,
+}
+
+impl QualityPreset {
+    /// Returns quality settings that correspond to preset.
+    pub fn settings(self) -> QualitySettings {
+        let default = QualitySettings::default();
+        match self {
+            QualityPreset::Low => QualitySettings {
+                point_shadows_enabled: false,
+                point_soft_shadows: false,
+                spot_shadows_enabled: false,
+                spot_soft_shadows: false,
+                use_ssao: false,
+                light_scatter_enabled: false,
+                texture_streaming_threshold: 1024,
+                texture_streaming_base_size: 128,
+                ..default
+            },
+            QualityPreset::Medium => QualitySettings {
+                point_shadow_map_size: 512,
+                point_soft_shadows: false,
+                point_shadows_distance: 10.0,
+                spot_shadow_map_size: 512,
+                spot_soft_shadows: false,
+                spot_shadows_distance: 10.0,
+                use_ssao: false,
+                light_scatter_enabled: false,
+                ..default
+            },
+            QualityPreset::High => default,
+            QualityPreset::Ultra => QualitySettings {
+                point_shadow_map_size: 2048,
+                point_shadows_distance: 25.0,
+                spot_shadow_map_size: 2048,
+                spot_shadows_distance: 25.0,
+                texture_streaming_threshold: 4096,
+                texture_streaming_uploads_per_frame: 4,
+                ..default
+            },
+        }
+    }
+}
+
+/// Result of quality detection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QualityDetection {
+    /// Preset that should be used, lowest of heuristic and benchmark presets.
+    pub preset: QualityPreset,
+    /// Preset guessed from capabilities of GPU.
+    pub heuristic_preset: QualityPreset,
+    /// Preset chosen by timed render of calibration scene.
+    pub benchmark_preset: QualityPreset,
+    /// Average time of a calibration frame in seconds.
+    pub average_frame_time: f32,
+}
+
+/// Guesses quality preset from capabilities of GPU.
+pub fn preset_from_capabilities(capabilities: &GpuCapabilities) -> QualityPreset {
+    let renderer = capabilities.renderer.to_lowercase();
+
+    let software = [
+        "llvmpipe",
+        "softpipe",
+        "swiftshader",
+        "software",
+        "gdi generic",
+    ];
+    if software.iter().any(|name| renderer.contains(name)) {
+        return QualityPreset::Low;
+    }
+
+    let mut preset = match capabilities.video_memory.total_kb {
+        Some(kb) if kb < 1024 * 1024 => QualityPreset::Low,
+        Some(kb) if kb < 2 * 1024 * 1024 => QualityPreset::Medium,
+        Some(kb) if kb < 6 * 1024 * 1024 => QualityPreset::High,
+        Some(_) => QualityPreset::Ultra,
+        // Video memory is not reported by Intel and Mesa drivers, rely on other limits.
+        None if capabilities.max_texture_size < 8192 => QualityPreset::Low,
+        None => QualityPreset::High,
+    };
+
+    // Integrated GPUs share memory with CPU and are usually limited by bandwidth.
+    if capabilities.is_vendor("intel") && !renderer.contains("arc") {
+        preset = preset.min(if renderer.contains("iris") {
+            QualityPreset::Medium
+        } else {
+            QualityPreset::Low
+        });
+    }
+
+    preset
+}
+
+/// Chooses quality preset by average frame time (in seconds) of calibration scene
+/// rendered with high settings.
+pub fn preset_from_frame_time(frame_time: f32) -> QualityPreset {
+    // Calibration scene is much simpler than a real game level, so thresholds leave
+    // a lot of room for actual content to fit in 60 FPS.
+    if frame_time < 0.004 {
+        QualityPreset::Ultra
+    } else if frame_time < 0.008 {
+        QualityPreset::High
+    } else if frame_time < 0.016 {
+        QualityPreset::Medium
+    } else {
+        QualityPreset::Low
+    }
+}
+
+/// Amount of frames which are rendered but not measured, first frames include time to
+/// upload geometry and to create shadow maps.
+const WARMUP_FRAMES: usize = 3;
+/// Amount of measured frames.
+const MEASURED_FRAMES: usize = 10;
+/// Size of calibration frame, it does not depend on size of window, so result is the
+/// same for minimized window too.
+const CALIBRATION_FRAME_SIZE: (u32, u32) = (1280, 720);
+
+/// Creates small scene with a field of spheres lit by lights that cast shadows.
+fn make_calibration_scene() -> (Scene, Handle<Node>) {
+    let mut scene = Scene::new();
+    let graph = &mut scene.graph;
+
+    let camera = graph.add_node(
+        CameraBuilder::new(
+            BaseBuilder::new().with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(Vec3::new(0.0, 4.0, -8.0))
+                    .with_local_rotation(Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.4))
+                    .build(),
+            ),
+        )
+        .build_node(),
+    );
+
+    let floor = Arc::new(Mutex::new(SurfaceSharedData::make_cube(Mat4::scale(
+        Vec3::new(20.0, 0.2, 20.0),
+    ))));
+    graph.add_node(
+        MeshBuilder::new(
+            BaseBuilder::new().with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(Vec3::new(0.0, -0.1, 0.0))
+                    .build(),
+            ),
+        )
+        .with_surfaces(vec![Surface::new(floor)])
+        .build_node(),
+    );
+
+    let sphere = Arc::new(Mutex::new(SurfaceSharedData::make_sphere(32, 32, 0.5)));
+    for z in -3..=3 {
+        for x in -3..=3 {
+            graph.add_node(
+                MeshBuilder::new(
+                    BaseBuilder::new().with_local_transform(
+                        TransformBuilder::new()
+                            .with_local_position(Vec3::new(x as f32 * 1.5, 0.5, z as f32 * 1.5))
+                            .build(),
+                    ),
+                )
+                .with_surfaces(vec![Surface::new(sphere.clone())])
+                .build_node(),
+            );
+        }
+    }
+
+    for &(x, z) in [(-3.0, -3.0), (3.0, -3.0), (-3.0, 3.0), (3.0, 3.0)].iter() {
+        graph.add_node(
+            PointLightBuilder::new(BaseLightBuilder::new(
+                BaseBuilder::new().with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_position(Vec3::new(x, 3.0, z))
+                        .build(),
+                ),
+            ))
+            .with_radius(8.0)
+            .build_node(),
+        );
+    }
+
+    graph.add_node(
+        SpotLightBuilder::new(BaseLightBuilder::new(
+            BaseBuilder::new().with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(Vec3::new(0.0, 8.0, 0.0))
+                    .build(),
+            ),
+        ))
+        .with_distance(15.0)
+        .build_node(),
+    );
+
+    (scene, camera)
+}
+
+impl Renderer {
+    /// Detects quality preset which suits current GPU. Current quality settings are left
+    /// untouched, apply result using [`Renderer::set_quality_settings`]. See module docs
+    /// for more info.
+    pub fn detect_quality_preset(&mut self) -> Result<QualityDetection, RendererError> {
+        let heuristic_preset = preset_from_capabilities(&self.capabilities);
+
+        let (mut scene, camera) = make_calibration_scene();
+        let (width, height) = CALIBRATION_FRAME_SIZE;
+        scene.update(Vec2::new(width as f32, height as f32), 0.0);
+        let camera = if let Node::Camera(camera) = &scene.graph[camera] {
+            camera.clone()
+        } else {
+            unreachable!()
+        };
+
+        let previous_settings = self.quality_settings;
+        self.set_quality_settings(&QualityPreset::High.settings())?;
+
+        let passes = offscreen_passes(scene.render_path, None, &self.gpu_jobs);
+        let mut gbuffer = GBuffer::new(&mut self.state, width as usize, height as usize)?;
+        let mut measured_time = 0.0;
+        let mut result = Ok(());
+        for i in 0..(WARMUP_FRAMES + MEASURED_FRAMES) {
+            let start = Instant::now();
+            if let Err(e) = self.render_tile(&scene, &camera, &passes, &mut gbuffer) {
+                result = Err(e);
+                break;
+            }
+            // Reading a single pixel back waits until GPU has finished the frame, so wall
+            // clock time is a fair estimate of frame time.
+            gbuffer
+                .final_frame
+                .read_pixels(&mut self.state, Rect::new(0, 0, 1, 1));
+            if i >= WARMUP_FRAMES {
+                measured_time += start.elapsed().as_secs_f32();
+            }
+        }
+
+        self.state.invalidate_resource_bindings_cache();
+        self.set_quality_settings(&previous_settings)?;
+        result?;
+
+        let average_frame_time = measured_time / MEASURED_FRAMES as f32;
+        let benchmark_preset = preset_from_frame_time(average_frame_time);
+
+        Ok(QualityDetection {
+            preset: heuristic_preset.min(benchmark_preset),
+            heuristic_preset,
+            benchmark_preset,
+            average_frame_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renderer::{
+        capabilities::{GpuCapabilities, VideoMemory},
+        quality_detection::{preset_from_capabilities, preset_from_frame_time, QualityPreset},
+    };
+
+    fn capabilities(vendor: &str, renderer: &str, vram_mb: Option<u32>) -> GpuCapabilities {
+        GpuCapabilities {
+            vendor: vendor.to_owned(),
+            renderer: renderer.to_owned(),
+            max_texture_size: 16384,
+            video_memory: VideoMemory {
+                total_kb: vram_mb.map(|mb| mb * 1024),
+                available_kb: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_preset_from_capabilities() {
+        let gtx = capabilities(
+            "NVIDIA Corporation",
+            "GeForce GTX 1060/PCIe/SSE2",
+            Some(6144),
+        );
+        assert_eq!(preset_from_capabilities(&gtx), QualityPreset::Ultra);
+
+        let old = capabilities("ATI Technologies Inc.", "Radeon HD 5450", Some(512));
+        assert_eq!(preset_from_capabilities(&old), QualityPreset::Low);
+
+        let intel = capabilities("Intel", "Mesa Intel(R) UHD Graphics 620", None);
+        assert_eq!(preset_from_capabilities(&intel), QualityPreset::Low);
+
+        let iris = capabilities("Intel", "Mesa Intel(R) Iris(R) Xe Graphics", None);
+        assert_eq!(preset_from_capabilities(&iris), QualityPreset::Medium);
+
+        let software = capabilities("Mesa/X.org", "llvmpipe (LLVM 10.0.0, 256 bits)", None);
+        assert_eq!(preset_from_capabilities(&software), QualityPreset::Low);
+    }
+
+    #[test]
+    fn test_preset_from_frame_time() {
+        assert_eq!(preset_from_frame_time(0.002), QualityPreset::Ultra);
+        assert_eq!(preset_from_frame_time(0.006), QualityPreset::High);
+        assert_eq!(preset_from_frame_time(0.012), QualityPreset::Medium);
+        assert_eq!(preset_from_frame_time(0.1), QualityPreset::Low);
+        assert!(QualityPreset::Low < QualityPreset::Ultra);
+        assert!(!QualityPreset::Low.settings().use_ssao);
+    }
+}
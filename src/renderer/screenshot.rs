@@ -0,0 +1,313 @@
+//! Contains methods to render screenshots of any resolution.
+//!
+//! Size of a single framebuffer is limited by GPU, so big screenshots are rendered in
+//! tiles. Every tile is rendered with a part of camera's projection (off-center frustum)
+//! and then tiles are stitched together. Size of tile is limited by size of current frame.
+//!
+//! Geometry and lighting of stitched image match whole image rendered at once, but
+//! screen-space effects (depth of field, SSAO) see only their own tile, so they may have
+//! visible seams on tile borders. Render screenshot in a single tile (no bigger than
+//! current frame) if such effects are enabled.
+//!
+//! Screenshots can also be supersampled: every tile is rendered multiple times with
+//! projection jittered by a fraction of a pixel and results are averaged, which gives
+//! smooth edges without any post-processing. See [`ScreenshotSettings`].
+
+use crate::{
+    core::{
+        math::{mat4::Mat4, vec2::Vec2, vec3::Vec3, Rect},
+        pool::Handle,
+    },
+    renderer::{
+        error::RendererError,
+        frame_graph::{offscreen_passes, PassKind},
+        framework::framebuffer::FrameBufferTrait,
+        gbuffer::GBuffer,
+        CameraRenderContext, Renderer,
+    },
+    resource::texture::{Texture, TextureKind},
+    scene::{camera::Camera, node::Node, Scene},
+};
+
+/// Returns projection matrix which maps given part of image (in normalized device
+/// coordinates) to whole viewport.
+fn crop_projection(projection: Mat4, min: Vec2, max: Vec2) -> Mat4 {
+    let size = max - min;
+    let center = (min + max).scale(0.5);
+    Mat4::scale(Vec3::new(2.0 / size.x, 2.0 / size.y, 1.0))
+        * Mat4::translate(Vec3::new(-center.x, -center.y, 0.0))
+        * projection
+}
+
+/// Settings of screenshot rendering.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScreenshotSettings {
+    /// Width of screenshot in pixels.
+    pub width: u32,
+    /// Height of screenshot in pixels.
+    pub height: u32,
+    /// Amount of jittered samples per pixel, 1 means no supersampling. Every sample is a
+    /// separate render of a tile, so rendering time grows linearly with this value.
+    pub samples: u32,
+    /// Scene whose render pass configuration of camera is used (see `frame_graph`),
+    /// built-in passes are used if handle is none.
+    pub scene: Handle<Scene>,
+}
+
+impl ScreenshotSettings {
+    /// Creates settings for screenshot of given size without supersampling.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            samples: 1,
+            scene: Handle::NONE,
+        }
+    }
+
+    /// Creates settings for screenshot which is `scale` times bigger than given frame
+    /// size, for example scale 4 for 1920x1080 frame gives 7680x4320 screenshot.
+    pub fn scaled(frame_size: (u32, u32), scale: u32) -> Self {
+        Self::new(frame_size.0 * scale.max(1), frame_size.1 * scale.max(1))
+    }
+
+    /// Sets amount of jittered samples per pixel.
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Sets scene whose render pass configuration of camera is used, so disabled and user
+    /// passes of camera affect screenshot too.
+    pub fn with_scene(mut self, scene: Handle<Scene>) -> Self {
+        self.scene = scene;
+        self
+    }
+}
+
+/// Returns n-th element of Halton sequence with given base, it is in [0; 1) range.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Returns offset of sample with given index in pixels, it is in [-0.5; 0.5) range.
+/// First sample is always at center of pixel.
+fn jitter(sample: u32) -> Vec2 {
+    if sample == 0 {
+        Vec2::ZERO
+    } else {
+        Vec2::new(halton(sample, 2) - 0.5, halton(sample, 3) - 0.5)
+    }
+}
+
+impl Renderer {
+    /// Renders scene by given passes (see `frame_graph::offscreen_passes`) to final frame
+    /// of given G-Buffer, result is not read back.
+    pub(in crate) fn render_tile(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        passes: &[PassKind],
+        gbuffer: &mut GBuffer,
+    ) -> Result<(), RendererError> {
+        let viewport = Rect::new(0, 0, gbuffer.width, gbuffer.height);
+        self.render_camera_passes(
+            passes,
+            gbuffer,
+            CameraRenderContext {
+                scene,
+                camera,
+                viewport,
+                frame_size: Vec2::new(gbuffer.width as f32, gbuffer.height as f32),
+                ambient_color: self.ambient_color,
+                light_probes: scene.light_probes(),
+                dt: 0.0,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Renders given scene from given camera to a RGBA8 texture of given size, size is not
+    /// limited by size of framebuffer. Rows of the texture are stored from top to bottom,
+    /// so it can be saved to a file directly using [`Texture::save`]. Debug drawings and
+    /// user interface are not included. Returns [`RendererError::InvalidCamera`] if given
+    /// handle does not point to a camera. See module docs for more info.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rg3d::{core::pool::Handle, renderer::Renderer, scene::{node::Node, Scene}};
+    /// # fn capture(renderer: &mut Renderer, scene: &Scene, camera: Handle<Node>) {
+    /// // 8K screenshot.
+    /// let mut texture = renderer.render_screenshot(scene, camera, 7680, 4320).unwrap();
+    /// texture.set_path(&"screenshot.png");
+    /// texture.save().unwrap();
+    /// # }
+    /// ```
+    pub fn render_screenshot(
+        &mut self,
+        scene: &Scene,
+        camera: Handle<Node>,
+        width: u32,
+        height: u32,
+    ) -> Result<Texture, RendererError> {
+        self.render_screenshot_with_settings(scene, camera, ScreenshotSettings::new(width, height))
+    }
+
+    /// Same as [`Self::render_screenshot`], but allows to supersample screenshot.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rg3d::{core::pool::Handle, renderer::{Renderer, screenshot::ScreenshotSettings},
+    /// #   scene::{node::Node, Scene}};
+    /// # fn capture(renderer: &mut Renderer, scene: &Scene, camera: Handle<Node>) {
+    /// // Print-quality capture: 4x of current frame size with 8 samples per pixel.
+    /// let settings = ScreenshotSettings::scaled(renderer.get_frame_size(), 4).with_samples(8);
+    /// let mut texture = renderer
+    ///     .render_screenshot_with_settings(scene, camera, settings)
+    ///     .unwrap();
+    /// texture.set_path(&"poster.png");
+    /// texture.save().unwrap();
+    /// # }
+    /// ```
+    pub fn render_screenshot_with_settings(
+        &mut self,
+        scene: &Scene,
+        camera: Handle<Node>,
+        settings: ScreenshotSettings,
+    ) -> Result<Texture, RendererError> {
+        let width = settings.width.max(1);
+        let height = settings.height.max(1);
+        let samples = settings.samples.max(1);
+
+        if !scene.graph.is_valid_handle(camera) {
+            return Err(RendererError::InvalidCamera);
+        }
+        let passes = offscreen_passes(
+            scene.render_path,
+            self.camera_passes.get(&(settings.scene, camera)),
+            &self.gpu_jobs,
+        );
+        let mut camera = if let Node::Camera(camera) = &scene.graph[camera] {
+            camera.clone()
+        } else {
+            return Err(RendererError::InvalidCamera);
+        };
+        // Projection must have aspect ratio of screenshot, not of current frame.
+        let aspect = width as f32 / height as f32;
+        let projection = Mat4::perspective(
+            camera.aspect_mode().vertical_fov(camera.fov(), aspect),
+            aspect,
+            camera.z_near(),
+            camera.z_far(),
+        );
+
+        let tile_width = width.min(self.frame_size.0.max(1));
+        let tile_height = height.min(self.frame_size.1.max(1));
+        let columns = (width + tile_width - 1) / tile_width;
+        let rows = (height + tile_height - 1) / tile_height;
+
+        let mut gbuffer = GBuffer::new(&mut self.state, tile_width as usize, tile_height as usize)?;
+        // Size of big screenshots does not fit into u32.
+        let (width_px, tile_width_px) = (width as usize, tile_width as usize);
+        let mut pixels = vec![0; width_px * height as usize * 4];
+        let mut accumulator = vec![0u32; tile_width_px * tile_height as usize * 4];
+
+        for row in 0..rows {
+            for column in 0..columns {
+                // Tile in pixels, origin is at bottom left corner as in OpenGL. Last tiles
+                // may stick out of the image, their excess is cropped.
+                let x = column * tile_width;
+                let y = row * tile_height;
+                let min = Vec2::new(
+                    x as f32 / width as f32 * 2.0 - 1.0,
+                    y as f32 / height as f32 * 2.0 - 1.0,
+                );
+                let max = Vec2::new(
+                    (x + tile_width) as f32 / width as f32 * 2.0 - 1.0,
+                    (y + tile_height) as f32 / height as f32 * 2.0 - 1.0,
+                );
+                let tile_projection = crop_projection(projection, min, max);
+
+                for value in accumulator.iter_mut() {
+                    *value = 0;
+                }
+                for sample in 0..samples {
+                    // Shift whole tile by a fraction of a pixel, size of a pixel in normalized
+                    // device coordinates is 2 / size of tile.
+                    let offset = jitter(sample);
+                    let shift = Mat4::translate(Vec3::new(
+                        offset.x * 2.0 / tile_width as f32,
+                        offset.y * 2.0 / tile_height as f32,
+                        0.0,
+                    ));
+                    camera.set_projection_matrix(shift * tile_projection);
+
+                    self.render_tile(scene, &camera, &passes, &mut gbuffer)?;
+                    let tile = gbuffer.final_frame.read_pixels(
+                        &mut self.state,
+                        Rect::new(0, 0, gbuffer.width, gbuffer.height),
+                    );
+                    for (value, &component) in accumulator.iter_mut().zip(tile.iter()) {
+                        *value += component as u32;
+                    }
+                }
+
+                let copy_width = tile_width.min(width - x) as usize;
+                for tile_y in 0..tile_height.min(height - y) {
+                    let src = tile_y as usize * tile_width_px * 4;
+                    // Flip rows, so image goes from top to bottom.
+                    let dest_y = (height - 1 - (y + tile_y)) as usize;
+                    let dest = (dest_y * width_px + x as usize) * 4;
+                    for (dest, &sum) in pixels[dest..(dest + copy_width * 4)]
+                        .iter_mut()
+                        .zip(accumulator[src..(src + copy_width * 4)].iter())
+                    {
+                        *dest = ((sum + samples / 2) / samples) as u8;
+                    }
+                }
+            }
+        }
+
+        // Rendering of tiles changes bindings, so cache must be invalidated to not break
+        // rendering of next frame.
+        self.state.invalidate_resource_bindings_cache();
+
+        Ok(Texture::from_bytes(width, height, TextureKind::RGBA8, pixels).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renderer::screenshot::{halton, jitter, ScreenshotSettings};
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(2, 3) - 2.0 / 3.0).abs() < 1.0e-6);
+
+        for sample in 0..64 {
+            let offset = jitter(sample);
+            assert!(offset.x >= -0.5 && offset.x < 0.5);
+            assert!(offset.y >= -0.5 && offset.y < 0.5);
+        }
+        assert_eq!(jitter(0).x, 0.0);
+    }
+
+    #[test]
+    fn test_scaled_settings() {
+        let settings = ScreenshotSettings::scaled((1920, 1080), 4).with_samples(0);
+        assert_eq!(settings.width, 7680);
+        assert_eq!(settings.height, 4320);
+        assert_eq!(settings.samples, 1);
+    }
+}
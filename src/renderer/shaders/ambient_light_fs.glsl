@@ -11,7 +11,10 @@ in vec2 texCoord;
 void main()
 {
     float ambientOcclusion =  texture(aoSampler, texCoord).r;
-    FragColor = ambientColor * texture(diffuseTexture, texCoord) ;
-    FragColor.rgb *= texture(ambientTexture, texCoord).rgb;
+    // Alpha of ambient texture is metalness of material.
+    vec4 ambient = texture(ambientTexture, texCoord);
+    vec4 albedo = texture(diffuseTexture, texCoord);
+    FragColor = ambientColor * vec4(S_AmbientReflectance(albedo.rgb, ambient.a), albedo.a);
+    FragColor.rgb *= ambient.rgb;
     FragColor.rgb *= ambientOcclusion;
 }
\ No newline at end of file
@@ -3,10 +3,12 @@
 uniform sampler2D depthTexture;
 uniform sampler2D colorTexture;
 uniform sampler2D normalTexture;
+uniform sampler2D ambientTexture;
 uniform samplerCube pointShadowTexture;
 
 uniform vec3 lightDirection;
 uniform vec4 lightColor;
+uniform float lightIntensity;
 uniform mat4 invViewProj;
 uniform vec3 cameraPosition;
 
@@ -15,16 +17,18 @@ out vec4 FragColor;
 
 void main()
 {
-    vec3 fragmentNormal = normalize(texture(normalTexture, texCoord).xyz * 2.0 - 1.0);
+    vec4 normalGlossiness = texture(normalTexture, texCoord);
+    vec3 fragmentNormal = normalize(normalGlossiness.xyz * 2.0 - 1.0);
     vec3 fragmentPosition = S_UnProject(vec3(texCoord, texture(depthTexture, texCoord).r), invViewProj);
-    const float specularPower = 80.0;
+    float specularPower = S_SpecularPower(normalGlossiness.w);
 
     vec3 h = normalize(lightDirection + (cameraPosition - fragmentPosition));
     float specular = pow(clamp(dot(fragmentNormal, h), 0.0, 1.0), specularPower);
 
     float lambertian = max(dot(fragmentNormal, lightDirection), 0);
 
+    float metallic = texture(ambientTexture, texCoord).a;
     FragColor = texture(colorTexture, texCoord);
-    FragColor.xyz += 0.4 * specular;
-    FragColor *= lambertian * lightColor;
+    FragColor.rgb = S_ShadeMaterial(FragColor.rgb, specular, normalGlossiness.w, metallic);
+    FragColor *= lambertian * lightIntensity * lightColor;
 }
@@ -3,11 +3,14 @@
 uniform sampler2D depthTexture;
 uniform sampler2D colorTexture;
 uniform sampler2D normalTexture;
+uniform sampler2D ambientTexture;
 uniform samplerCube pointShadowTexture;
 
 uniform vec3 lightPos;
 uniform float lightRadius;
 uniform vec4 lightColor;
+uniform float lightIntensity;
+uniform bool inverseSquareFalloff;
 uniform mat4 invViewProj;
 uniform vec3 cameraPosition;
 uniform bool softShadows;
@@ -18,13 +21,16 @@ out vec4 FragColor;
 
 void main()
 {
+    vec4 normalGlossiness = texture(normalTexture, texCoord);
+
     TBlinnPhongContext ctx;
     ctx.lightPosition = lightPos;
     ctx.lightRadius = lightRadius;
-    ctx.fragmentNormal = normalize(texture(normalTexture, texCoord).xyz * 2.0 - 1.0);
+    ctx.fragmentNormal = normalize(normalGlossiness.xyz * 2.0 - 1.0);
     ctx.fragmentPosition = S_UnProject(vec3(texCoord, texture(depthTexture, texCoord).r), invViewProj);
     ctx.cameraPosition = cameraPosition;
-    ctx.specularPower = 80.0;
+    ctx.specularPower = S_SpecularPower(normalGlossiness.w);
+    ctx.inverseSquareFalloff = inverseSquareFalloff;
     TBlinnPhong lighting = S_BlinnPhong(ctx);
 
     float shadow = 1.0;
@@ -69,7 +75,8 @@ void main()
         }
     }
 
+    float metallic = texture(ambientTexture, texCoord).a;
     FragColor = texture(colorTexture, texCoord);
-    FragColor.xyz += 0.4 * lighting.specular;
-    FragColor *= lighting.attenuation * shadow * lightColor;
+    FragColor.rgb = S_ShadeMaterial(FragColor.rgb, lighting.specular, normalGlossiness.w, metallic);
+    FragColor *= lighting.attenuation * shadow * lightIntensity * lightColor;
 }
@@ -0,0 +1,117 @@
+#version 330 core
+
+// Must be in sync with MAX_FORWARD_LIGHTS in forward_renderer.rs
+#define MAX_LIGHTS 8
+
+uniform sampler2D diffuseTexture;
+uniform sampler2D normalTexture;
+uniform sampler2D lightmapTexture;
+uniform sampler2D ormTexture;
+uniform bool useOrmTexture;
+uniform vec4 diffuseColor;
+uniform vec4 ambientColor;
+uniform bool useLightProbe;
+uniform vec3 lightProbe[6];
+uniform vec3 cameraPosition;
+// Must be in sync with InstanceData in uniform_ring.rs
+layout(std140) uniform InstanceData
+{
+    mat4 worldMatrix;
+    mat4 worldViewProjection;
+    vec4 instanceTint;
+    float instanceSeed;
+    float instanceBrightnessJitter;
+};
+
+uniform int lightCount;
+// 0 - directional, 1 - point, 2 - spot.
+uniform int lightKind[MAX_LIGHTS];
+uniform vec3 lightPosition[MAX_LIGHTS];
+uniform vec3 lightDirection[MAX_LIGHTS];
+// Color of light multiplied by its intensity.
+uniform vec4 lightColor[MAX_LIGHTS];
+uniform float lightRadius[MAX_LIGHTS];
+uniform int lightInverseSquareFalloff[MAX_LIGHTS];
+// Cosines of half of hotspot angle (x) and half of full cone angle (y) of spot lights.
+uniform vec2 lightConeAngleCos[MAX_LIGHTS];
+
+in vec3 normal;
+in vec2 texCoord;
+in vec3 tangent;
+in vec3 binormal;
+in vec2 secondTexCoord;
+in vec4 color;
+in vec3 worldPosition;
+
+out vec4 FragColor;
+
+void main()
+{
+    vec4 diffuse = diffuseColor * instanceTint * color * texture(diffuseTexture, texCoord);
+    if (diffuse.a < 0.5) discard;
+    diffuse.rgb *= 1.0 + (instanceSeed * 2.0 - 1.0) * instanceBrightnessJitter;
+    diffuse.a = 1.0;
+
+    vec3 n = normalize(texture(normalTexture, texCoord).xyz * 2.0 - 1.0);
+    mat3 tangentSpace = mat3(tangent, binormal, normal);
+    vec3 fragmentNormal = normalize(tangentSpace * n);
+
+    // Packed occlusion-roughness-metallic, same as in G-Buffer.
+    vec3 orm = useOrmTexture ? texture(ormTexture, texCoord).rgb : vec3(1.0, 0.0, 0.0);
+    float glossiness = 1.0 - orm.g;
+    float metallic = orm.b;
+    float specularPower = S_SpecularPower(glossiness);
+
+    vec3 ambient;
+    if (useLightProbe)
+    {
+        vec3 nSquared = normal * normal;
+        ambient = nSquared.x * lightProbe[normal.x >= 0.0 ? 0 : 1] +
+                  nSquared.y * lightProbe[normal.y >= 0.0 ? 2 : 3] +
+                  nSquared.z * lightProbe[normal.z >= 0.0 ? 4 : 5];
+    }
+    else
+    {
+        ambient = texture(lightmapTexture, secondTexCoord).rgb;
+    }
+
+    ambient *= orm.r;
+
+    FragColor = vec4(ambientColor.rgb * ambient * S_AmbientReflectance(diffuse.rgb, metallic), 1.0);
+
+    for (int i = 0; i < lightCount; ++i)
+    {
+        float attenuation;
+        float specular;
+        if (lightKind[i] == 0)
+        {
+            vec3 v = normalize(cameraPosition - worldPosition);
+            vec3 h = normalize(lightDirection[i] + v);
+            specular = pow(clamp(dot(fragmentNormal, h), 0.0, 1.0), specularPower);
+            attenuation = max(dot(fragmentNormal, lightDirection[i]), 0.0);
+        }
+        else
+        {
+            TBlinnPhongContext ctx;
+            ctx.lightPosition = lightPosition[i];
+            ctx.lightRadius = lightRadius[i];
+            ctx.fragmentNormal = fragmentNormal;
+            ctx.fragmentPosition = worldPosition;
+            ctx.cameraPosition = cameraPosition;
+            ctx.specularPower = specularPower;
+            ctx.inverseSquareFalloff = lightInverseSquareFalloff[i] != 0;
+            TBlinnPhong lighting = S_BlinnPhong(ctx);
+
+            specular = lighting.specular;
+            attenuation = lighting.attenuation;
+
+            if (lightKind[i] == 2)
+            {
+                float spotAngleCos = dot(lightDirection[i], lighting.direction);
+                attenuation *= smoothstep(lightConeAngleCos[i].y, lightConeAngleCos[i].x, spotAngleCos);
+            }
+        }
+
+        FragColor.rgb += attenuation * lightColor[i].rgb * S_ShadeMaterial(diffuse.rgb, specular, glossiness, metallic);
+    }
+}
@@ -6,24 +6,55 @@ layout(location = 2) out vec4 outAmbient;
 
 uniform sampler2D diffuseTexture;
 uniform sampler2D normalTexture;
-uniform sampler2D specularTexture;
 uniform sampler2D lightmapTexture;
+uniform sampler2D ormTexture;
+uniform bool useOrmTexture;
 uniform vec4 diffuseColor;
+uniform bool useLightProbe;
+uniform vec3 lightProbe[6];
+// Must be in sync with InstanceData in uniform_ring.rs
+layout(std140) uniform InstanceData
+{
+    mat4 worldMatrix;
+    mat4 worldViewProjection;
+    vec4 instanceTint;
+    float instanceSeed;
+    float instanceBrightnessJitter;
+};
 
 in vec3 normal;
 in vec2 texCoord;
 in vec3 tangent;
 in vec3 binormal;
 in vec2 secondTexCoord;
+in vec4 color;
 
 void main()
 {
-    outColor = diffuseColor * texture(diffuseTexture, texCoord);
+    outColor = diffuseColor * instanceTint * color * texture(diffuseTexture, texCoord);
     if (outColor.a < 0.5) discard;
+    outColor.rgb *= 1.0 + (instanceSeed * 2.0 - 1.0) * instanceBrightnessJitter;
     outColor.a = 1;
     vec4 n = normalize(texture(normalTexture, texCoord) * 2.0 - 1.0);
     mat3 tangentSpace = mat3(tangent, binormal, normal);
     outNormal.xyz = normalize(tangentSpace * n.xyz) * 0.5 + 0.5;
-    outNormal.w = texture(specularTexture, texCoord).r;
-    outAmbient = vec4(texture(lightmapTexture, secondTexCoord).rgb, 1.0);
+    // Packed occlusion-roughness-metallic. Glossiness goes to normal's w and metalness
+    // to ambient's alpha, occlusion is baked into ambient light right here.
+    vec3 orm = useOrmTexture ? texture(ormTexture, texCoord).rgb : vec3(1.0, 0.0, 0.0);
+    outNormal.w = 1.0 - orm.g;
+    if (useLightProbe)
+    {
+        // Evaluate ambient cube using world space normal.
+        vec3 nSquared = normal * normal;
+        vec3 probe = nSquared.x * lightProbe[normal.x >= 0.0 ? 0 : 1] +
+                     nSquared.y * lightProbe[normal.y >= 0.0 ? 2 : 3] +
+                     nSquared.z * lightProbe[normal.z >= 0.0 ? 4 : 5];
+        outAmbient = vec4(probe, 1.0);
+    }
+    else
+    {
+        outAmbient = vec4(texture(lightmapTexture, secondTexCoord).rgb, 1.0);
+    }
+    outAmbient.rgb *= orm.r;
+    outAmbient.a = orm.b;
 }
@@ -7,17 +7,29 @@ layout(location = 3) in vec3 vertexNormal;
 layout(location = 4) in vec4 vertexTangent;
 layout(location = 5) in vec4 boneWeights;
 layout(location = 6) in vec4 boneIndices;
+layout(location = 7) in vec4 vertexColor;
 
-uniform mat4 worldMatrix;
-uniform mat4 worldViewProjection;
+// Must be in sync with InstanceData in uniform_ring.rs
+layout(std140) uniform InstanceData
+{
+    mat4 worldMatrix;
+    mat4 worldViewProjection;
+    vec4 instanceTint;
+    float instanceSeed;
+    float instanceBrightnessJitter;
+};
 uniform bool useSkeletalAnimation;
-uniform mat4 boneMatrices[60];
+layout(std140) uniform BoneMatrices
+{
+    mat4 boneMatrices[60];
+};
 
 out vec3 normal;
 out vec2 texCoord;
 out vec3 tangent;
 out vec3 binormal;
 out vec2 secondTexCoord;
+out vec4 color;
 
 void main()
 {
@@ -60,4 +72,5 @@ void main()
     binormal = normalize(vertexTangent.w * cross(tangent, normal));
     texCoord = vertexTexCoord;
     secondTexCoord = vertexSecondTexCoord;
+    color = vertexColor;
 }
\ No newline at end of file
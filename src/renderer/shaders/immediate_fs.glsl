@@ -0,0 +1,13 @@
+#version 330 core
+
+uniform sampler2D diffuseTexture;
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+in vec4 color;
+
+void main()
+{
+    FragColor = color * texture(diffuseTexture, texCoord);
+}
\ No newline at end of file
@@ -0,0 +1,68 @@
+// Exposure, depth of field and color filter.
+
+#version 330 core
+
+#define SAMPLE_COUNT 12
+
+uniform sampler2D frameTexture;
+uniform sampler2D depthTexture;
+uniform mat4 inverseProjectionMatrix;
+uniform float exposure;
+uniform float focusDistance;
+uniform float focusRange;
+uniform float maxBlur;
+// Rows of color transformation matrix, it is applied to linear colors.
+uniform vec3 colorMatrix[3];
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+// Poisson disk with unit radius.
+const vec2 disk[SAMPLE_COUNT] = vec2[](
+    vec2(-0.326, -0.406), vec2(-0.840, -0.074), vec2(-0.696, 0.457),
+    vec2(-0.203, 0.621), vec2(0.962, -0.195), vec2(0.473, -0.480),
+    vec2(0.519, 0.767), vec2(0.185, -0.893), vec2(0.507, 0.064),
+    vec2(0.896, 0.412), vec2(-0.322, -0.933), vec2(-0.792, -0.598)
+);
+
+float BlurRadius(vec2 coord)
+{
+    vec3 position = S_UnProject(vec3(coord, texture(depthTexture, coord).r), inverseProjectionMatrix);
+    float distance = -position.z;
+    float outOfFocus = abs(distance - focusDistance) - focusRange * 0.5;
+    return clamp(outOfFocus / max(focusRange, 0.0001), 0.0, 1.0) * maxBlur;
+}
+
+void main()
+{
+    vec4 color = texture(frameTexture, texCoord);
+
+    if (maxBlur > 0.0)
+    {
+        vec2 texelSize = 1.0 / vec2(textureSize(frameTexture, 0));
+        float radius = BlurRadius(texCoord);
+        if (radius > 0.5)
+        {
+            vec4 sum = color;
+            float weight = 1.0;
+            for (int i = 0; i < SAMPLE_COUNT; ++i)
+            {
+                vec2 coord = texCoord + disk[i] * radius * texelSize;
+                // Sharp foreground must not bleed into blurred background.
+                float w = clamp(BlurRadius(coord) / radius, 0.0, 1.0);
+                sum += texture(frameTexture, coord) * w;
+                weight += w;
+            }
+            color = sum / weight;
+        }
+    }
+
+    vec3 rgb = color.rgb * exposure;
+    // Frame is gamma-encoded, but color matrices are defined for linear colors.
+    vec3 linear = S_SRGBToLinear(clamp(rgb, 0.0, 1.0));
+    linear = vec3(dot(colorMatrix[0], linear), dot(colorMatrix[1], linear), dot(colorMatrix[2], linear));
+    rgb = S_LinearToSRGB(clamp(linear, 0.0, 1.0));
+
+    FragColor = vec4(rgb, color.a);
+}
@@ -33,6 +33,15 @@ float S_LightDistanceAttenuation(float distance, float radius)
     return attenuation * attenuation;
 }
 
+// Returns physically correct inverse square attenuation. To keep light volume bounded,
+// it is smoothly windowed to zero at given radius.
+float S_InverseSquareAttenuation(float distance, float radius)
+{
+    float ratio = distance / radius;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / max(distance * distance, 0.0001);
+}
+
 // Projects world space position (typical use case) by given matrix.
 vec3 S_Project(vec3 worldPosition, mat4 matrix)
 {
@@ -75,6 +84,8 @@ struct TBlinnPhongContext {
     vec3 fragmentPosition;
     vec3 cameraPosition;
     float specularPower;
+    // Use physically correct inverse square falloff instead of range-based one.
+    bool inverseSquareFalloff;
 };
 
 // Blinn-Phong lighting output parameters.
@@ -91,6 +102,28 @@ struct TBlinnPhong {
     vec3 direction;
 };
 
+// Returns Blinn-Phong specular power for given glossiness (1 - roughness) of material.
+float S_SpecularPower(float glossiness)
+{
+    return mix(2.0, 80.0, glossiness * glossiness);
+}
+
+// Combines albedo of fragment and specular factor of light for material with given
+// glossiness and metalness. Metals have no diffuse reflection and tint specular with albedo.
+vec3 S_ShadeMaterial(vec3 albedo, float specular, float glossiness, float metallic)
+{
+    vec3 specularColor = mix(vec3(1.0), albedo, metallic);
+    return albedo * (1.0 - metallic) + 0.4 * glossiness * specular * specularColor;
+}
+
+// Returns reflectance of material with given metalness for uniform ambient light. Metals
+// reflect it only by specular part (with same scale as in S_ShadeMaterial), glossiness
+// does not matter since uniform light is reflected equally in every direction.
+vec3 S_AmbientReflectance(vec3 albedo, float metallic)
+{
+    return mix(albedo, 0.4 * albedo, metallic);
+}
+
 // Calculates lighting parameters for point light using Blinn-Phong model.
 // This function also suitable for calculations of spot lighting, because
 // spot light is a point light but with defined lighting cone.
@@ -104,7 +137,9 @@ TBlinnPhong S_BlinnPhong(TBlinnPhongContext ctx)
 
     float lambertian = max(dot(ctx.fragmentNormal, lightVector), 0);
 
-    float distance_attenuation = S_LightDistanceAttenuation(distance, ctx.lightRadius);
+    float distance_attenuation = ctx.inverseSquareFalloff
+        ? S_InverseSquareAttenuation(distance, ctx.lightRadius)
+        : S_LightDistanceAttenuation(distance, ctx.lightRadius);
 
     float attenuation = lambertian * distance_attenuation;
 
@@ -149,4 +184,19 @@ bool S_RaySphereIntersection(vec3 origin, vec3 dir, vec3 center, float radius, o
     float b = 2.0 * dot(dir, d);
     float c = dot(d, d) - radius * radius;
     return S_SolveQuadraticEq(a, b, c, minT, maxT);
-}
\ No newline at end of file
+}
+// Converts gamma-encoded (sRGB) color to linear color.
+vec3 S_SRGBToLinear(vec3 color)
+{
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, vec3(lessThanEqual(color, vec3(0.04045))));
+}
+
+// Converts linear color to gamma-encoded (sRGB) color.
+vec3 S_LinearToSRGB(vec3 color)
+{
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(color, vec3(0.0031308))));
+}
@@ -7,7 +7,10 @@ layout(location = 6) in vec4 boneIndices;
 
 uniform mat4 worldViewProjection;
 uniform bool useSkeletalAnimation;
-uniform mat4 boneMatrices[60];
+layout(std140) uniform BoneMatrices
+{
+    mat4 boneMatrices[60];
+};
 
 out vec2 texCoord;
 
@@ -16,6 +16,7 @@ use crate::{
                 MininificationFilter, PixelKind, WrapMode,
             },
             state::{ColorMask, State},
+            uniform_ring::{UniformRing, BONE_MATRICES_BINDING},
         },
         GeometryCache, RenderPassStatistics, TextureCache,
     },
@@ -23,9 +24,14 @@ use crate::{
 };
 use std::{cell::RefCell, rc::Rc};
 
+/// Size of bone matrices array of spot shadow map shader.
+const MAX_SPOT_BONE_MATRICES: usize = 60;
+
+/// Size of bone matrices array of point shadow map shader.
+const MAX_POINT_BONE_MATRICES: usize = 80;
+
 struct SpotShadowMapShader {
     program: GpuProgram,
-    bone_matrices: UniformLocation,
     world_view_projection_matrix: UniformLocation,
     use_skeletal_animation: UniformLocation,
     diffuse_texture: UniformLocation,
@@ -37,8 +43,8 @@ impl SpotShadowMapShader {
         let vertex_source = include_str!("shaders/spot_shadow_map_vs.glsl");
         let program =
             GpuProgram::from_source("SpotShadowMapShader", vertex_source, fragment_source)?;
+        program.bind_uniform_block("BoneMatrices", BONE_MATRICES_BINDING)?;
         Ok(Self {
-            bone_matrices: program.uniform_location("boneMatrices")?,
             world_view_projection_matrix: program.uniform_location("worldViewProjection")?,
             use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
@@ -52,6 +58,7 @@ pub struct SpotShadowMapRenderer {
     shader: SpotShadowMapShader,
     framebuffer: FrameBuffer,
     bone_matrices: Vec<Mat4>,
+    uniform_ring: UniformRing,
     pub size: usize,
 }
 
@@ -87,6 +94,7 @@ impl SpotShadowMapRenderer {
             framebuffer,
             shader: SpotShadowMapShader::new()?,
             bone_matrices: Vec::new(),
+            uniform_ring: UniformRing::new(),
         })
     }
 
@@ -119,7 +127,7 @@ impl SpotShadowMapRenderer {
                     continue;
                 }
 
-                let global_transform = node.global_transform();
+                let global_transform = mesh.instance_transform();
 
                 if !mesh.is_intersect_frustum(graph, &frustum) {
                     continue;
@@ -145,6 +153,10 @@ impl SpotShadowMapRenderer {
                         white_dummy.clone()
                     };
 
+                    surface.calculate_bone_matrices(graph, &mut self.bone_matrices);
+                    self.uniform_ring
+                        .bind_bone_matrices(&self.bone_matrices, MAX_SPOT_BONE_MATRICES);
+
                     statistics += self.framebuffer.draw(
                         geom_map.get(state, &surface.data().lock().unwrap()),
                         state,
@@ -168,22 +180,6 @@ impl SpotShadowMapRenderer {
                                 self.shader.use_skeletal_animation,
                                 UniformValue::Bool(is_skinned),
                             ),
-                            (
-                                self.shader.bone_matrices,
-                                UniformValue::Mat4Array({
-                                    self.bone_matrices.clear();
-
-                                    for &bone_handle in surface.bones.iter() {
-                                        let bone = &graph[bone_handle];
-                                        self.bone_matrices.push(
-                                            bone.global_transform()
-                                                * bone.inv_bind_pose_transform(),
-                                        );
-                                    }
-
-                                    &self.bone_matrices
-                                }),
-                            ),
                             (
                                 self.shader.diffuse_texture,
                                 UniformValue::Sampler {
@@ -204,7 +200,6 @@ impl SpotShadowMapRenderer {
 struct PointShadowMapShader {
     program: GpuProgram,
     world_matrix: UniformLocation,
-    bone_matrices: UniformLocation,
     world_view_projection_matrix: UniformLocation,
     use_skeletal_animation: UniformLocation,
     diffuse_texture: UniformLocation,
@@ -217,9 +212,9 @@ impl PointShadowMapShader {
         let vertex_source = include_str!("shaders/point_shadow_map_vs.glsl");
         let program =
             GpuProgram::from_source("PointShadowMapShader", vertex_source, fragment_source)?;
+        program.bind_uniform_block("BoneMatrices", BONE_MATRICES_BINDING)?;
         Ok(Self {
             world_matrix: program.uniform_location("worldMatrix")?,
-            bone_matrices: program.uniform_location("boneMatrices")?,
             world_view_projection_matrix: program.uniform_location("worldViewProjection")?,
             use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
@@ -231,6 +226,7 @@ impl PointShadowMapShader {
 
 pub struct PointShadowMapRenderer {
     bone_matrices: Vec<Mat4>,
+    uniform_ring: UniformRing,
     shader: PointShadowMapShader,
     framebuffer: FrameBuffer,
     pub size: usize,
@@ -382,6 +378,7 @@ impl PointShadowMapRenderer {
             framebuffer,
             size,
             bone_matrices: Vec::new(),
+            uniform_ring: UniformRing::new(),
             shader: PointShadowMapShader::new()?,
         })
     }
@@ -428,7 +425,7 @@ impl PointShadowMapRenderer {
                         continue;
                     }
 
-                    let global_transform = node.global_transform();
+                    let global_transform = mesh.instance_transform();
 
                     if !mesh.is_intersect_frustum(graph, &frustum) {
                         continue;
@@ -454,6 +451,10 @@ impl PointShadowMapRenderer {
                             white_dummy.clone()
                         };
 
+                        surface.calculate_bone_matrices(graph, &mut self.bone_matrices);
+                        self.uniform_ring
+                            .bind_bone_matrices(&self.bone_matrices, MAX_POINT_BONE_MATRICES);
+
                         statistics += self.framebuffer.draw(
                             geom_cache.get(state, &surface.data().lock().unwrap()),
                             state,
@@ -479,22 +480,6 @@ impl PointShadowMapRenderer {
                                     self.shader.use_skeletal_animation,
                                     UniformValue::Bool(is_skinned),
                                 ),
-                                (
-                                    self.shader.bone_matrices,
-                                    UniformValue::Mat4Array({
-                                        self.bone_matrices.clear();
-
-                                        for &bone_handle in surface.bones.iter() {
-                                            let bone = &graph[bone_handle];
-                                            self.bone_matrices.push(
-                                                bone.global_transform()
-                                                    * bone.inv_bind_pose_transform(),
-                                            );
-                                        }
-
-                                        &self.bone_matrices
-                                    }),
-                                ),
                                 (
                                     self.shader.diffuse_texture,
                                     UniformValue::Sampler {
@@ -104,6 +104,10 @@ impl SpriteRenderer {
                 continue;
             };
 
+            if !camera.sees_layer(sprite.layer()) {
+                continue;
+            }
+
             let diffuse_texture = if let Some(texture) = sprite.texture() {
                 if let Some(texture) = textures.get(state, texture) {
                     texture
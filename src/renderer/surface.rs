@@ -11,11 +11,13 @@ use crate::{
         pool::{ErasedHandle, Handle},
         visitor::{Visit, VisitResult, Visitor},
     },
+    engine::resource_manager::ResourceManager,
     resource::texture::Texture,
-    scene::node::Node,
+    scene::{graph::Graph, lenient::has_region, node::Node},
     utils::raw_mesh::{RawMesh, RawMeshBuilder},
 };
 use std::{
+    cell::Cell,
     hash::{Hash, Hasher},
     sync::{Arc, Mutex},
 };
@@ -46,6 +48,9 @@ pub struct Vertex {
     /// Array of bone indices. It has indices of bones in array of bones of a
     /// surface.
     pub bone_indices: [u8; 4],
+    /// Color of vertex. It is multiplied with diffuse color of a surface, so white
+    /// vertices will have no effect. Can be modified at runtime by vertex painting.
+    pub color: Color,
 }
 
 impl Visit for Vertex {
@@ -68,6 +73,11 @@ impl Visit for Vertex {
         self.bone_indices[2].visit("BoneIndex2", visitor)?;
         self.bone_indices[3].visit("BoneIndex3", visitor)?;
 
+        // Vertices saved before vertex colors were added have no color.
+        if !visitor.is_reading() || has_region("Color", visitor) {
+            self.color.visit("Color", visitor)?;
+        }
+
         visitor.leave_region()
     }
 }
@@ -88,6 +98,7 @@ impl Vertex {
             },
             bone_weights: [0.0, 0.0, 0.0, 0.0],
             bone_indices: Default::default(),
+            color: Color::WHITE,
         }
     }
 }
@@ -100,6 +111,7 @@ impl PartialEq for Vertex {
             && self.tangent == other.tangent
             && self.bone_weights == other.bone_weights
             && self.bone_indices == other.bone_indices
+            && self.color == other.color
     }
 }
 
@@ -129,6 +141,8 @@ pub struct SurfaceSharedData {
     // If true - indicates that surface was generated and does not have reference
     // resource. Procedural data will be serialized.
     is_procedural: bool,
+    // Range of vertices that were modified since last upload to GPU.
+    dirty_vertices: Cell<Option<(usize, usize)>>,
 }
 
 impl Default for SurfaceSharedData {
@@ -137,6 +151,7 @@ impl Default for SurfaceSharedData {
             vertices: Default::default(),
             triangles: Default::default(),
             is_procedural: false,
+            dirty_vertices: Cell::new(None),
         }
     }
 }
@@ -152,9 +167,20 @@ impl SurfaceSharedData {
             vertices,
             triangles,
             is_procedural,
+            dirty_vertices: Cell::new(None),
         }
     }
 
+    /// Marks data as procedural, geometry of procedural data is serialized.
+    pub(in crate) fn set_procedural(&mut self, procedural: bool) {
+        self.is_procedural = procedural;
+    }
+
+    /// Returns true if data is procedural, see `set_procedural`.
+    pub fn is_procedural(&self) -> bool {
+        self.is_procedural
+    }
+
     /// Converts raw mesh into "renderable" mesh. It is useful to build procedural
     /// meshes.
     pub fn from_raw_mesh(raw: RawMesh<Vertex>, is_procedural: bool) -> Self {
@@ -162,6 +188,7 @@ impl SurfaceSharedData {
             vertices: raw.vertices,
             triangles: raw.triangles,
             is_procedural,
+            dirty_vertices: Cell::new(None),
         }
     }
 
@@ -182,6 +209,60 @@ impl SurfaceSharedData {
         self.triangles.as_slice()
     }
 
+    /// Paints vertices that are inside of sphere of given brush. `transform` is used to
+    /// transform vertices into coordinate system of brush, usually it is global transform
+    /// of a mesh. Only modified range of vertices will be uploaded to GPU. Returns amount
+    /// of painted vertices.
+    ///
+    /// # Notes
+    ///
+    /// Painting is performed over bind pose of skinned surfaces, not over their actual
+    /// animated shape.
+    pub fn paint(&mut self, transform: Mat4, brush: &VertexPaintBrush) -> usize {
+        let mut count = 0;
+        let mut begin = std::usize::MAX;
+        let mut end = 0;
+
+        for (i, vertex) in self.vertices.iter_mut().enumerate() {
+            let position = transform.transform_vector(vertex.position);
+            let k = brush.influence(position.distance(&brush.position));
+            if k > 0.0 {
+                vertex.color = blend_color(vertex.color, brush.color, k);
+                begin = begin.min(i);
+                end = end.max(i + 1);
+                count += 1;
+            }
+        }
+
+        if count > 0 {
+            self.mark_vertices_dirty(begin, end);
+        }
+
+        count
+    }
+
+    /// Sets color of every vertex of surface.
+    pub fn fill_color(&mut self, color: Color) {
+        for vertex in self.vertices.iter_mut() {
+            vertex.color = color;
+        }
+        let count = self.vertices.len();
+        self.mark_vertices_dirty(0, count);
+    }
+
+    fn mark_vertices_dirty(&self, begin: usize, end: usize) {
+        let range = match self.dirty_vertices.get() {
+            Some((prev_begin, prev_end)) => (prev_begin.min(begin), prev_end.max(end)),
+            None => (begin, end),
+        };
+        self.dirty_vertices.set(Some(range));
+    }
+
+    /// Returns range of vertices that were modified since last call and resets it.
+    pub(in crate) fn take_dirty_vertices(&self) -> Option<(usize, usize)> {
+        self.dirty_vertices.take()
+    }
+
     /// Calculates tangents of surface. Tangents are needed for correct lighting, you will
     /// get incorrect lighting if tangents of your surface are invalid! When engine loads
     /// a mesh from "untrusted" source, it automatically calculates tangents for you, so
@@ -271,6 +352,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -293,6 +375,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -315,6 +398,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -337,6 +421,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
         ];
@@ -371,6 +456,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -393,6 +479,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -415,6 +502,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -437,6 +525,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
         ];
@@ -469,6 +558,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -491,6 +581,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -513,6 +604,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -535,6 +627,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
         ];
@@ -798,6 +891,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -820,6 +914,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -842,6 +937,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -864,6 +960,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             // Back
@@ -887,6 +984,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -909,6 +1007,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -931,6 +1030,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -953,6 +1053,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             // Left
@@ -976,6 +1077,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -998,6 +1100,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1020,6 +1123,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1042,6 +1146,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             // Right
@@ -1065,6 +1170,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1087,6 +1193,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1109,6 +1216,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1131,6 +1239,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             // Top
@@ -1154,6 +1263,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1176,6 +1286,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1198,6 +1309,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1220,6 +1332,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             // Bottom
@@ -1243,6 +1356,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1265,6 +1379,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1287,6 +1402,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
             Vertex {
@@ -1309,6 +1425,7 @@ impl SurfaceSharedData {
                 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                color: Color::WHITE,
                 second_tex_coord: Default::default(),
             },
         ];
@@ -1358,6 +1475,62 @@ impl Visit for SurfaceSharedData {
     }
 }
 
+/// Brush for runtime vertex painting. Brush is a sphere in world space, every vertex
+/// inside it will be tinted with brush color. See `Mesh::paint` for more info.
+#[derive(Copy, Clone, Debug)]
+pub struct VertexPaintBrush {
+    /// Center of brush.
+    pub position: Vec3,
+    /// Radius of brush.
+    pub radius: f32,
+    /// Portion of radius (in 0..1 range) in which intensity of brush smoothly fades out
+    /// to zero. 0.0 - means hard brush, 1.0 - fading starts right from center.
+    pub falloff: f32,
+    /// Color of brush.
+    pub color: Color,
+    /// Intensity of brush in 0..1 range.
+    pub opacity: f32,
+}
+
+impl Default for VertexPaintBrush {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            radius: 1.0,
+            falloff: 0.5,
+            color: Color::WHITE,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl VertexPaintBrush {
+    /// Returns intensity of brush at given distance from its center.
+    pub fn influence(&self, distance: f32) -> f32 {
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let falloff = self.falloff.max(0.0).min(1.0);
+        let inner_radius = self.radius * (1.0 - falloff);
+        let k = if distance <= inner_radius {
+            1.0
+        } else {
+            1.0 - (distance - inner_radius) / (self.radius - inner_radius)
+        };
+        k * self.opacity.max(0.0).min(1.0)
+    }
+}
+
+fn blend_color(a: Color, b: Color, k: f32) -> Color {
+    let blend = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * k) as u8;
+    Color::from_rgba(
+        blend(a.r, b.r),
+        blend(a.g, b.g),
+        blend(a.b, b.b),
+        blend(a.a, b.a),
+    )
+}
+
 /// Vertex weight is a pair of (bone; weight) that affects vertex.
 #[derive(Copy, Clone, Debug)]
 pub struct VertexWeight {
@@ -1454,6 +1627,7 @@ pub struct Surface {
     diffuse_texture: Option<Arc<Mutex<Texture>>>,
     normal_texture: Option<Arc<Mutex<Texture>>>,
     lightmap_texture: Option<Arc<Mutex<Texture>>>,
+    orm_texture: Option<Arc<Mutex<Texture>>>,
     /// Temporal array for FBX conversion needs, it holds skinning data (weight + bone handle)
     /// and will be used to fill actual bone indices and weight in vertices that will be
     /// sent to GPU. The idea is very simple: GPU needs to know only indices of matrices of
@@ -1464,6 +1638,7 @@ pub struct Surface {
     /// Array of handle to scene nodes which are used as bones.
     pub bones: Vec<Handle<Node>>,
     color: Color,
+    material: Option<String>,
 }
 
 /// Shallow copy of surface.
@@ -1482,6 +1657,8 @@ impl Clone for Surface {
             vertex_weights: Vec::new(), // Intentionally not copied.
             color: self.color,
             lightmap_texture: self.lightmap_texture.clone(),
+            orm_texture: self.orm_texture.clone(),
+            material: self.material.clone(),
         }
     }
 }
@@ -1498,6 +1675,8 @@ impl Surface {
             vertex_weights: Vec::new(),
             color: Color::WHITE,
             lightmap_texture: None,
+            orm_texture: None,
+            material: None,
         }
     }
 
@@ -1507,6 +1686,12 @@ impl Surface {
         self.data.as_ref().unwrap().clone()
     }
 
+    /// Sets new data for surface, textures and other properties are kept.
+    #[inline]
+    pub fn set_data(&mut self, data: Arc<Mutex<SurfaceSharedData>>) {
+        self.data = Some(data);
+    }
+
     /// Sets new diffuse texture.
     #[inline]
     pub fn set_diffuse_texture(&mut self, tex: Arc<Mutex<Texture>>) {
@@ -1543,6 +1728,21 @@ impl Surface {
         self.lightmap_texture.clone()
     }
 
+    /// Sets packed occlusion-roughness-metallic texture: red channel is ambient occlusion,
+    /// green - roughness, blue - metalness. Separate maps can be packed by
+    /// [channel_packing](../../resource/channel_packing/index.html) utilities. Surface
+    /// without the texture is glossy dielectric without occlusion.
+    #[inline]
+    pub fn set_orm_texture(&mut self, tex: Arc<Mutex<Texture>>) {
+        self.orm_texture = Some(tex);
+    }
+
+    /// Returns packed occlusion-roughness-metallic texture.
+    #[inline]
+    pub fn orm_texture(&self) -> Option<Arc<Mutex<Texture>>> {
+        self.orm_texture.clone()
+    }
+
     /// Sets color of surface.
     #[inline]
     pub fn set_color(&mut self, color: Color) {
@@ -1555,11 +1755,64 @@ impl Surface {
         self.color
     }
 
+    /// Tags surface with a name of surface material, i.e. "wood" or "metal". See
+    /// `surface_material` module docs for more info.
+    #[inline]
+    pub fn set_material<N: AsRef<str>>(&mut self, material: Option<N>) {
+        self.material = material.map(|m| m.as_ref().to_owned());
+    }
+
+    /// Returns name of surface material of the surface.
+    #[inline]
+    pub fn material(&self) -> Option<&str> {
+        self.material.as_deref()
+    }
+
     /// Returns list of bones that affects the surface.
     #[inline]
     pub fn bones(&self) -> &[Handle<Node>] {
         &self.bones
     }
+
+    /// Calculates skinning matrices of bones that affects the surface, each matrix is a
+    /// product of global transform of a bone and its inverse bind pose transform. Order of
+    /// matrices matches order of bones. These are exactly the same matrices that will be
+    /// used by renderer to do skinning. Given array will be cleared before calculation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any handle of a bone is invalid for given graph.
+    pub fn calculate_bone_matrices(&self, graph: &Graph, matrices: &mut Vec<Mat4>) {
+        matrices.clear();
+        for &bone_handle in self.bones.iter() {
+            let bone = &graph[bone_handle];
+            matrices.push(bone.global_transform() * bone.inv_bind_pose_transform());
+        }
+    }
+
+    /// Returns skinning matrices of bones that affects the surface. See
+    /// [`calculate_bone_matrices`] for more info.
+    pub fn bone_matrices(&self, graph: &Graph) -> Vec<Mat4> {
+        let mut matrices = Vec::with_capacity(self.bones.len());
+        self.calculate_bone_matrices(graph, &mut matrices);
+        matrices
+    }
+}
+
+impl Surface {
+    /// Replaces shallow textures of surface (loaded from a save file) with real ones.
+    pub(in crate) fn restore_resources(&mut self, resource_manager: &mut ResourceManager) {
+        for texture in &mut [
+            &mut self.diffuse_texture,
+            &mut self.normal_texture,
+            &mut self.lightmap_texture,
+            &mut self.orm_texture,
+        ] {
+            if let Some(shallow_texture) = texture.clone() {
+                **texture = resource_manager.restore_texture(&shallow_texture);
+            }
+        }
+    }
 }
 
 impl Visit for Surface {
@@ -1576,6 +1829,8 @@ impl Visit for Surface {
         // Try to get lightmap texture but don't care if it is missing, it can
         // be missing on previous versions.
         let _ = self.lightmap_texture.visit("LightmapTexture", visitor);
+        let _ = self.material.visit("Material", visitor);
+        let _ = self.orm_texture.visit("OrmTexture", visitor);
 
         visitor.leave_region()
     }
@@ -1587,8 +1842,10 @@ pub struct SurfaceBuilder {
     diffuse_texture: Option<Arc<Mutex<Texture>>>,
     normal_texture: Option<Arc<Mutex<Texture>>>,
     lightmap_texture: Option<Arc<Mutex<Texture>>>,
+    orm_texture: Option<Arc<Mutex<Texture>>>,
     bones: Vec<Handle<Node>>,
     color: Color,
+    material: Option<String>,
 }
 
 impl SurfaceBuilder {
@@ -1599,8 +1856,10 @@ impl SurfaceBuilder {
             diffuse_texture: None,
             normal_texture: None,
             lightmap_texture: None,
+            orm_texture: None,
             bones: Default::default(),
             color: Color::WHITE,
+            material: None,
         }
     }
 
@@ -1622,6 +1881,12 @@ impl SurfaceBuilder {
         self
     }
 
+    /// Sets desired packed occlusion-roughness-metallic texture.
+    pub fn with_orm_texture(mut self, tex: Arc<Mutex<Texture>>) -> Self {
+        self.orm_texture = Some(tex);
+        self
+    }
+
     /// Sets desired color of surface.
     pub fn with_color(mut self, color: Color) -> Self {
         self.color = color;
@@ -1634,6 +1899,12 @@ impl SurfaceBuilder {
         self
     }
 
+    /// Sets desired name of surface material.
+    pub fn with_material<N: AsRef<str>>(mut self, material: N) -> Self {
+        self.material = Some(material.as_ref().to_owned());
+        self
+    }
+
     /// Creates new instance of surface.
     pub fn build(self) -> Surface {
         Surface {
@@ -1641,9 +1912,11 @@ impl SurfaceBuilder {
             diffuse_texture: self.diffuse_texture,
             normal_texture: self.normal_texture,
             lightmap_texture: self.lightmap_texture,
+            orm_texture: self.orm_texture,
             vertex_weights: Default::default(),
             bones: self.bones,
             color: self.color,
+            material: self.material,
         }
     }
 }
@@ -0,0 +1,179 @@
+//! Contains methods to render small previews of models and scenes.
+//!
+//! Thumbnail is rendered off-screen with auto-framed camera and neutral lighting: every
+//! camera and light source of source scene is ignored, instead a camera which sees whole
+//! scene from above at 3/4 angle and single white directional light are used. Result is
+//! a plain texture which can be shown in asset browsers, save slots, etc.
+
+use crate::{
+    core::{
+        color::Color,
+        math::{quat::Quat, vec2::Vec2, vec3::Vec3, Rect},
+    },
+    renderer::{
+        error::RendererError,
+        frame_graph::{camera_frame_graph, DEBUG_PASS, IMMEDIATE_PASS, POST_EFFECTS_PASS},
+        framework::framebuffer::FrameBufferTrait,
+        gbuffer::GBuffer,
+        CameraRenderContext, Renderer,
+    },
+    resource::{
+        model::Model,
+        texture::{Texture, TextureKind},
+    },
+    scene::{
+        base::BaseBuilder,
+        camera::CameraBuilder,
+        light::{BaseLightBuilder, DirectionalLightBuilder},
+        node::Node,
+        transform::TransformBuilder,
+        RenderPath, Scene,
+    },
+};
+
+/// Returns center and radius of bounding sphere of every mesh in the scene.
+fn bounding_sphere(scene: &Scene) -> (Vec3, f32) {
+    let mut min = Vec3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+    let mut max = Vec3::new(-std::f32::MAX, -std::f32::MAX, -std::f32::MAX);
+    let mut empty = true;
+    for node in scene.graph.linear_iter() {
+        if let Node::Mesh(mesh) = node {
+            let bounding_box = mesh.world_bounding_box();
+            if bounding_box.min.x <= bounding_box.max.x {
+                empty = false;
+                min = Vec3::new(
+                    min.x.min(bounding_box.min.x),
+                    min.y.min(bounding_box.min.y),
+                    min.z.min(bounding_box.min.z),
+                );
+                max = Vec3::new(
+                    max.x.max(bounding_box.max.x),
+                    max.y.max(bounding_box.max.y),
+                    max.z.max(bounding_box.max.z),
+                );
+            }
+        }
+    }
+    if empty {
+        (Vec3::ZERO, 1.0)
+    } else {
+        let center = (min + max).scale(0.5);
+        (center, (max - center).len().max(0.001))
+    }
+}
+
+impl Renderer {
+    /// Renders given scene to a square texture of given size. Scene is not modified, it is
+    /// copied internally. See module docs for more info.
+    pub fn render_scene_thumbnail(
+        &mut self,
+        scene: &Scene,
+        size: u32,
+    ) -> Result<Texture, RendererError> {
+        let size = size.max(1);
+        let mut thumbnail_scene = scene.clone(&mut |_, node| !node.is_camera() && !node.is_light());
+        let frame_size = Vec2::new(size as f32, size as f32);
+        thumbnail_scene.graph.update_hierachical_data();
+
+        let (center, radius) = bounding_sphere(&thumbnail_scene);
+
+        let fov = 60.0f32.to_radians();
+        // Look at the scene from above at 3/4 angle.
+        let rotation = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 225.0f32.to_radians())
+            * Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 30.0f32.to_radians());
+        let distance = radius / (fov * 0.5).sin();
+
+        let camera = thumbnail_scene.graph.add_node(
+            CameraBuilder::new(
+                BaseBuilder::new().with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_rotation(rotation)
+                        .build(),
+                ),
+            )
+            .with_fov(fov)
+            .with_z_near(distance * 0.01)
+            .with_z_far(distance * 4.0)
+            .build_node(),
+        );
+        thumbnail_scene.graph.update_hierachical_data();
+        let look = thumbnail_scene.graph[camera].look_vector();
+        thumbnail_scene.graph[camera]
+            .local_transform_mut()
+            .set_position(center - look.scale(distance));
+
+        // Light goes from the camera with small offset to make shapes readable.
+        let light_rotation =
+            rotation * Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 20.0f32.to_radians());
+        thumbnail_scene.graph.add_node(
+            DirectionalLightBuilder::new(
+                BaseLightBuilder::new(
+                    BaseBuilder::new().with_local_transform(
+                        TransformBuilder::new()
+                            .with_local_rotation(light_rotation)
+                            .build(),
+                    ),
+                )
+                .with_color(Color::WHITE),
+            )
+            .build_node(),
+        );
+
+        thumbnail_scene.graph.update_nodes(frame_size, 0.0);
+
+        let camera = if let Node::Camera(camera) = &thumbnail_scene.graph[camera] {
+            camera
+        } else {
+            unreachable!()
+        };
+
+        // Thumbnail is always lit the same way and shows only the scene itself.
+        let (mut pass_graph, output) =
+            camera_frame_graph(RenderPath::Deferred, true, None, &self.gpu_jobs);
+        for pass in &[POST_EFFECTS_PASS, IMMEDIATE_PASS, DEBUG_PASS] {
+            pass_graph.set_enabled(pass, false);
+        }
+        let passes = pass_graph
+            .compile(&[output])
+            .into_iter()
+            .map(|pass| *pass.payload())
+            .collect::<Vec<_>>();
+
+        let viewport = Rect::new(0, 0, size as i32, size as i32);
+        let mut gbuffer = GBuffer::new(&mut self.state, size as usize, size as usize)?;
+        self.render_camera_passes(
+            &passes,
+            &mut gbuffer,
+            CameraRenderContext {
+                scene: &thumbnail_scene,
+                camera,
+                viewport,
+                frame_size,
+                // Brighter than usual ambient to make dark sides of objects visible.
+                ambient_color: Color::opaque(110, 110, 110),
+                light_probes: None,
+                dt: 0.0,
+            },
+        )?;
+
+        let pixels = gbuffer.final_frame.read_pixels(&mut self.state, viewport);
+
+        // Rendering of thumbnail changes bindings, so cache must be invalidated to not
+        // break rendering of next frame.
+        self.state.invalidate_resource_bindings_cache();
+
+        Ok(Texture::from_bytes(size, size, TextureKind::RGBA8, pixels).unwrap())
+    }
+
+    /// Renders given model resource to a square texture of given size. See module docs
+    /// for more info.
+    pub fn render_model_thumbnail(
+        &mut self,
+        model: &Model,
+        size: u32,
+    ) -> Result<Texture, RendererError> {
+        let mut scene = Scene::new();
+        model.instantiate_geometry(&mut scene);
+        self.render_scene_thumbnail(&scene, size)
+    }
+}
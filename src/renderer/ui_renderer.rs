@@ -86,6 +86,7 @@ pub(in crate) struct UiRenderContext<'a, 'b, 'c> {
     pub backbuffer: &'b mut BackBuffer,
     pub frame_width: f32,
     pub frame_height: f32,
+    pub ui_scale: f32,
     pub drawing_context: &'c DrawingContext,
     pub white_dummy: Rc<RefCell<GpuTexture>>,
     pub texture_cache: &'a mut TextureCache,
@@ -125,6 +126,7 @@ impl UiRenderer {
             backbuffer,
             frame_width,
             frame_height,
+            ui_scale,
             drawing_context,
             white_dummy,
             texture_cache,
@@ -140,7 +142,15 @@ impl UiRenderer {
             .set_triangles(drawing_context.get_triangles())
             .set_vertices(drawing_context.get_vertices());
 
-        let ortho = Mat4::ortho(0.0, frame_width, frame_height, 0.0, -1.0, 1.0);
+        // UI is laid out in logical units, projection stretches it to the whole frame.
+        let ortho = Mat4::ortho(
+            0.0,
+            frame_width / ui_scale,
+            frame_height / ui_scale,
+            0.0,
+            -1.0,
+            1.0,
+        );
 
         for cmd in drawing_context.get_commands() {
             let mut diffuse_texture = white_dummy.clone();
@@ -232,8 +242,15 @@ impl UiRenderer {
                     self.shader.resolution,
                     UniformValue::Vec2(Vec2::new(frame_width, frame_height)),
                 ),
-                (self.shader.bounds_min, UniformValue::Vec2(cmd.bounds.min)),
-                (self.shader.bounds_max, UniformValue::Vec2(cmd.bounds.max)),
+                // Bounds are compared with fragment coordinates, which are in pixels.
+                (
+                    self.shader.bounds_min,
+                    UniformValue::Vec2(cmd.bounds.min.scale(ui_scale)),
+                ),
+                (
+                    self.shader.bounds_max,
+                    UniformValue::Vec2(cmd.bounds.max.scale(ui_scale)),
+                ),
                 (self.shader.is_font, UniformValue::Bool(is_font_texture)),
                 (
                     self.shader.brush_type,
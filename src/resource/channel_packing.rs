@@ -0,0 +1,257 @@
+//! Packing and unpacking of texture channels.
+//!
+//! Most PBR asset packs ship occlusion, roughness and metallic maps either as separate
+//! grayscale images or as a single packed RGB image (ORM) where red channel is ambient
+//! occlusion, green is roughness and blue is metallic. Engine uses packed form, see
+//! [`Surface::set_orm_texture`](../../renderer/surface/struct.Surface.html#method.set_orm_texture),
+//! this module allows to convert separate maps to it and back. Packed texture can be saved
+//! next to source maps at import, so packing is done only once, see
+//! `ResourceManager::request_orm_texture`.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::resource::{
+//!     channel_packing::{pack_orm, unpack_channel, Channel},
+//!     texture::{Texture, TextureKind},
+//! };
+//!
+//! let roughness = Texture::from_bytes(2, 1, TextureKind::R8, vec![10, 20]).unwrap();
+//! // Missing maps are filled with defaults: no occlusion and no metalness.
+//! let orm = pack_orm(None, Some(&roughness), None).unwrap();
+//! let unpacked = unpack_channel(&orm, Channel::Green);
+//! assert_eq!(unpacked.data(), &[10, 20]);
+//! ```
+
+use crate::resource::texture::{Texture, TextureKind};
+use std::fmt;
+
+/// Single channel of a texture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Red channel, for grayscale textures it is luminance.
+    Red,
+    /// Green channel.
+    Green,
+    /// Blue channel.
+    Blue,
+    /// Alpha channel, textures without alpha are treated as opaque.
+    Alpha,
+}
+
+/// Defines where to take data of a single channel of packed texture.
+#[derive(Copy, Clone, Debug)]
+pub enum ChannelSource<'a> {
+    /// Channel of existing texture.
+    Texture {
+        /// Source texture.
+        texture: &'a Texture,
+        /// Channel of source texture to take data from.
+        channel: Channel,
+    },
+    /// Every pixel has same value.
+    Constant(u8),
+}
+
+/// Errors that may occur while packing channels.
+#[derive(Debug, PartialEq)]
+pub enum ChannelPackingError {
+    /// All source textures must have same size.
+    SizeMismatch {
+        /// Size of first source texture.
+        expected: (u32, u32),
+        /// Size of mismatching texture.
+        actual: (u32, u32),
+    },
+    /// Size of packed texture can't be deduced because every source is constant.
+    NoTextures,
+}
+
+impl fmt::Display for ChannelPackingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelPackingError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Source texture has size {}x{}, but {}x{} was expected",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            ChannelPackingError::NoTextures => {
+                write!(f, "At least one source must be a texture")
+            }
+        }
+    }
+}
+
+/// Occlusion of packed ORM texture when there is no occlusion map - fully lit.
+pub const DEFAULT_OCCLUSION: u8 = 255;
+/// Roughness of packed ORM texture when there is no roughness map - fully glossy, same as
+/// shading of surfaces without ORM texture.
+pub const DEFAULT_ROUGHNESS: u8 = 0;
+/// Metalness of packed ORM texture when there is no metallic map - dielectric.
+pub const DEFAULT_METALLIC: u8 = 0;
+
+fn read(texture: &Texture, pixel: usize, channel: Channel) -> u8 {
+    let offset = pixel * texture.kind.bytes_per_pixel();
+    let component = match (texture.kind, channel) {
+        (TextureKind::R8, Channel::Alpha)
+        | (TextureKind::RGB8, Channel::Alpha)
+        | (TextureKind::R32F, Channel::Alpha) => return 255,
+        // Grayscale texture has same value in every color channel.
+        (TextureKind::R8, _) | (TextureKind::R32F, _) => 0,
+        (_, Channel::Red) => 0,
+        (_, Channel::Green) => 1,
+        (_, Channel::Blue) => 2,
+        (_, Channel::Alpha) => 3,
+    };
+    if texture.kind.is_float() {
+        // Float components are mapped from [0; 1].
+        let b = &texture.bytes[offset + component * 4..offset + component * 4 + 4];
+        let v = f32::from_ne_bytes([b[0], b[1], b[2], b[3]]);
+        (v.max(0.0).min(1.0) * 255.0).round() as u8
+    } else {
+        texture.bytes[offset + component]
+    }
+}
+
+/// Packs given sources into channels of new texture. Three sources give RGB8 texture,
+/// four - RGBA8. Every source texture must have same size.
+pub fn pack_channels(sources: &[ChannelSource]) -> Result<Texture, ChannelPackingError> {
+    let kind = if sources.len() >= 4 {
+        TextureKind::RGBA8
+    } else {
+        TextureKind::RGB8
+    };
+    let channel_count = kind.bytes_per_pixel();
+
+    let mut size = None;
+    for source in sources.iter().take(channel_count) {
+        if let ChannelSource::Texture { texture, .. } = source {
+            let actual = (texture.width, texture.height);
+            match size {
+                None => size = Some(actual),
+                Some(expected) if expected != actual => {
+                    return Err(ChannelPackingError::SizeMismatch { expected, actual })
+                }
+                _ => (),
+            }
+        }
+    }
+    let (width, height) = size.ok_or(ChannelPackingError::NoTextures)?;
+
+    let pixel_count = (width * height) as usize;
+    let mut bytes = Vec::with_capacity(pixel_count * channel_count);
+    for pixel in 0..pixel_count {
+        for i in 0..channel_count {
+            bytes.push(match sources.get(i) {
+                Some(ChannelSource::Texture { texture, channel }) => read(texture, pixel, *channel),
+                Some(ChannelSource::Constant(value)) => *value,
+                // Channels without source are black.
+                None => 0,
+            });
+        }
+    }
+
+    Ok(Texture::from_bytes(width, height, kind, bytes).unwrap())
+}
+
+/// Extracts single channel of texture into new grayscale texture.
+pub fn unpack_channel(texture: &Texture, channel: Channel) -> Texture {
+    let pixel_count = (texture.width * texture.height) as usize;
+    let bytes = (0..pixel_count)
+        .map(|pixel| read(texture, pixel, channel))
+        .collect();
+    Texture::from_bytes(texture.width, texture.height, TextureKind::R8, bytes).unwrap()
+}
+
+/// Packs separate occlusion, roughness and metallic maps into single ORM texture. Red
+/// channel (luminance for grayscale images) of every map is used, missing maps are
+/// replaced with defaults.
+pub fn pack_orm(
+    occlusion: Option<&Texture>,
+    roughness: Option<&Texture>,
+    metallic: Option<&Texture>,
+) -> Result<Texture, ChannelPackingError> {
+    fn source(texture: Option<&Texture>, default: u8) -> ChannelSource {
+        match texture {
+            Some(texture) => ChannelSource::Texture {
+                texture,
+                channel: Channel::Red,
+            },
+            None => ChannelSource::Constant(default),
+        }
+    }
+
+    pack_channels(&[
+        source(occlusion, DEFAULT_OCCLUSION),
+        source(roughness, DEFAULT_ROUGHNESS),
+        source(metallic, DEFAULT_METALLIC),
+    ])
+}
+
+/// Splits ORM texture into separate occlusion, roughness and metallic maps.
+pub fn unpack_orm(orm: &Texture) -> (Texture, Texture, Texture) {
+    (
+        unpack_channel(orm, Channel::Red),
+        unpack_channel(orm, Channel::Green),
+        unpack_channel(orm, Channel::Blue),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::resource::{
+        channel_packing::{
+            pack_channels, pack_orm, unpack_orm, Channel, ChannelPackingError, ChannelSource,
+        },
+        texture::{Texture, TextureKind},
+    };
+
+    #[test]
+    fn test_pack_unpack() {
+        let occlusion = Texture::from_bytes(2, 1, TextureKind::R8, vec![1, 2]).unwrap();
+        let metallic =
+            Texture::from_bytes(2, 1, TextureKind::RGB8, vec![3, 0, 0, 4, 0, 0]).unwrap();
+        let orm = pack_orm(Some(&occlusion), None, Some(&metallic)).unwrap();
+        assert_eq!(orm.kind(), TextureKind::RGB8);
+        assert_eq!(orm.data(), &[1, 0, 3, 2, 0, 4]);
+
+        let (o, r, m) = unpack_orm(&orm);
+        assert_eq!(o.data(), occlusion.data());
+        assert_eq!(r.data(), &[0, 0]);
+        assert_eq!(m.data(), &[3, 4]);
+
+        let rgba = pack_channels(&[
+            ChannelSource::Constant(7),
+            ChannelSource::Constant(8),
+            ChannelSource::Constant(9),
+            ChannelSource::Texture {
+                texture: &metallic,
+                channel: Channel::Alpha,
+            },
+        ])
+        .unwrap();
+        assert_eq!(rgba.data(), &[7, 8, 9, 255, 7, 8, 9, 255]);
+
+        // Float components are mapped from [0; 1].
+        let float_bytes = [1.0f32, 0.5]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes().to_vec())
+            .collect();
+        let roughness = Texture::from_bytes(2, 1, TextureKind::R32F, float_bytes).unwrap();
+        let orm = pack_orm(None, Some(&roughness), None).unwrap();
+        assert_eq!(orm.data(), &[255, 255, 0, 255, 128, 0]);
+
+        let small = Texture::from_bytes(1, 1, TextureKind::R8, vec![0]).unwrap();
+        assert_eq!(
+            pack_orm(Some(&occlusion), Some(&small), None).err(),
+            Some(ChannelPackingError::SizeMismatch {
+                expected: (2, 1),
+                actual: (1, 1)
+            })
+        );
+        assert_eq!(
+            pack_orm(None, None, None).err(),
+            Some(ChannelPackingError::NoTextures)
+        );
+    }
+}
@@ -11,7 +11,7 @@ pub mod error;
 mod scene;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     path::Path,
     sync::{Arc, Mutex},
     time::Instant,
@@ -20,6 +20,7 @@ use std::{
 use crate::{
     animation::{Animation, AnimationContainer, KeyFrame, Track},
     core::{
+        color::Color,
         math::{
             mat4::Mat4,
             quat::{Quat, RotationOrder},
@@ -154,6 +155,7 @@ impl Into<Vertex> for UnpackedVertex {
             // when all nodes will be converted.
             bone_weights: Default::default(),
             bone_indices: Default::default(),
+            color: Color::WHITE,
         }
     }
 }
@@ -456,6 +458,10 @@ fn convert(
 ) -> Result<Handle<Node>, FbxError> {
     let root = scene.graph.add_node(Node::Base(Base::default()));
     let animation_handle = scene.animations.add(Animation::default());
+    // Keep order of models as in FBX scene, so conversion produce exactly the same graph
+    // (and handles) across runs. Map is used only for lookups, iteration is performed over
+    // ordered list of pairs.
+    let mut fbx_models = Vec::new();
     let mut fbx_model_to_node_map = HashMap::new();
     for (component_handle, component) in fbx_scene.pair_iter() {
         if let FbxComponent::Model(model) = component {
@@ -468,12 +474,13 @@ fn convert(
                 animation_handle,
             )?;
             scene.graph.link_nodes(node, root);
+            fbx_models.push((component_handle, node));
             fbx_model_to_node_map.insert(component_handle, node);
         }
     }
     // Link according to hierarchy
-    for (&fbx_model_handle, node_handle) in fbx_model_to_node_map.iter() {
-        if let FbxComponent::Model(fbx_model) = fbx_scene.get(fbx_model_handle) {
+    for (fbx_model_handle, node_handle) in fbx_models.iter() {
+        if let FbxComponent::Model(fbx_model) = fbx_scene.get(*fbx_model_handle) {
             for fbx_child_handle in fbx_model.children.iter() {
                 if let Some(child_handle) = fbx_model_to_node_map.get(fbx_child_handle) {
                     scene.graph.link_nodes(*child_handle, *node_handle);
@@ -485,9 +492,10 @@ fn convert(
 
     // Remap handles from fbx model to handles of instantiated nodes
     // on each surface of each mesh.
-    for &handle in fbx_model_to_node_map.values() {
+    for &(_, handle) in fbx_models.iter() {
         if let Node::Mesh(mesh) = &mut scene.graph[handle] {
-            let mut surface_bones = HashSet::new();
+            // Use ordered set of bones to get same bone indices on every load.
+            let mut surface_bones = Vec::new();
             for surface in mesh.surfaces_mut() {
                 for weight_set in surface.vertex_weights.iter_mut() {
                     for weight in weight_set.iter_mut() {
@@ -495,11 +503,13 @@ fn convert(
                         let bone_handle = fbx_model_to_node_map
                             .get(&fbx_model)
                             .ok_or(FbxError::UnableToRemapModelToNode)?;
-                        surface_bones.insert(*bone_handle);
+                        if !surface_bones.contains(bone_handle) {
+                            surface_bones.push(*bone_handle);
+                        }
                         weight.effector = (*bone_handle).into();
                     }
                 }
-                surface.bones = surface_bones.iter().copied().collect();
+                surface.bones = surface_bones.clone();
 
                 let data_rc = surface.data();
                 let mut data = data_rc.lock().unwrap();
@@ -527,20 +537,34 @@ fn convert(
     Ok(root)
 }
 
-/// Tries to load and convert FBX from given path.
+/// Tries to load and convert FBX from given path, file is read through virtual file system
+/// of resource manager.
 ///
 /// Normally you should never use this method, use resource manager to load models.
 pub fn load_to_scene<P: AsRef<Path>>(
     scene: &mut Scene,
     resource_manager: &mut ResourceManager,
     path: P,
+) -> Result<Handle<Node>, FbxError> {
+    let data = resource_manager.vfs().read(path.as_ref())?;
+    load_bytes_to_scene(scene, resource_manager, path, &data)
+}
+
+/// Tries to convert FBX from given file content, path is used only for logging.
+///
+/// Normally you should never use this method, use resource manager to load models.
+pub fn load_bytes_to_scene<P: AsRef<Path>>(
+    scene: &mut Scene,
+    resource_manager: &mut ResourceManager,
+    path: P,
+    data: &[u8],
 ) -> Result<Handle<Node>, FbxError> {
     let start_time = Instant::now();
 
     Log::writeln(format!("Trying to load {:?}", path.as_ref()));
 
     let now = Instant::now();
-    let fbx = FbxDocument::new(path.as_ref())?;
+    let fbx = FbxDocument::from_bytes(data)?;
     let parsing_time = now.elapsed().as_millis();
 
     let now = Instant::now();
@@ -0,0 +1,168 @@
+//! Import cache stores post-processed results of import of non-native resources.
+//!
+//! Import of FBX model includes parsing, triangulation, generation of tangents and
+//! conversion to engine's scene, this can take seconds for big models. Import cache saves
+//! converted scene in native format to cache directory, entry is keyed by hash of source
+//! file content, so on next launch model is loaded from cache if source was not changed.
+//! Stale entries are never used, because changed source gives different hash.
+//!
+//! Currently only FBX models go through the cache. Native scenes (RGS) are already in the
+//! format cache would store them in, and textures and sound buffers are decoded directly
+//! from their sources, so caching them would not save any work.
+//!
+//! Import cache is disabled by default, use `ResourceManager::set_import_cache` to enable it.
+//!
+//! ```no_run
+//! # fn enable(resource_manager: &mut rg3d::engine::resource_manager::ResourceManager) {
+//! use rg3d::resource::import_cache::ImportCache;
+//!
+//! resource_manager.set_import_cache(Some(ImportCache::new("data/.import_cache")));
+//! # }
+//! ```
+
+use crate::{
+    core::visitor::{Visit, Visitor},
+    engine::resource_manager::ResourceManager,
+    scene::{node::Node, Scene},
+    utils::log::Log,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Version of cached data, must be bumped every time when import process is changed, so
+/// old entries won't be used.
+pub const IMPORT_CACHE_VERSION: u32 = 1;
+
+/// Calculates stable (FNV-1a) hash of given source data. Hash includes version of cache and
+/// path to textures, because converted models store paths to textures.
+pub fn source_hash(data: &[u8], textures_path: &Path) -> u64 {
+    const PRIME: u64 = 0x0100_0000_01b3;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let version = IMPORT_CACHE_VERSION.to_le_bytes();
+    let textures_path = textures_path.to_string_lossy();
+    for byte in version
+        .iter()
+        .chain(textures_path.as_bytes().iter())
+        .chain(data.iter())
+    {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
+
+fn set_surfaces_procedural(scene: &Scene, procedural: bool) {
+    for node in scene.graph.linear_iter() {
+        if let Node::Mesh(mesh) = node {
+            for surface in mesh.surfaces() {
+                surface.data().lock().unwrap().set_procedural(procedural);
+            }
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct ImportCache {
+    directory: PathBuf,
+}
+
+impl ImportCache {
+    /// Creates new import cache which stores entries in given directory. Directory will be
+    /// created on first write.
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_owned(),
+        }
+    }
+
+    /// Returns directory where entries are stored.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns path of cache entry with given hash.
+    pub fn entry_path(&self, hash: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.rgs", hash))
+    }
+
+    /// Removes every cache entry.
+    pub fn clear(&self) -> io::Result<()> {
+        if self.directory.exists() {
+            std::fs::remove_dir_all(&self.directory)?;
+        }
+        Ok(())
+    }
+
+    pub(in crate) fn load_scene(
+        &self,
+        hash: u64,
+        resource_manager: &mut ResourceManager,
+    ) -> Option<Scene> {
+        let path = self.entry_path(hash);
+        if !path.exists() {
+            return None;
+        }
+        match Scene::from_file(&path, resource_manager) {
+            Ok(scene) => {
+                // Geometry was stored as procedural only to be written to cache.
+                set_surfaces_procedural(&scene, false);
+                Log::writeln(format!("Import cache entry {:?} was used.", path));
+                Some(scene)
+            }
+            Err(e) => {
+                Log::writeln(format!(
+                    "Unable to load import cache entry {:?}, it will be rebuilt. Reason: {:?}",
+                    path, e
+                ));
+                None
+            }
+        }
+    }
+
+    pub(in crate) fn store_scene(&self, hash: u64, scene: &mut Scene) {
+        if let Err(e) = std::fs::create_dir_all(&self.directory) {
+            Log::writeln(format!(
+                "Unable to create import cache directory {:?}. Reason: {:?}",
+                self.directory, e
+            ));
+            return;
+        }
+
+        // Geometry of non-procedural surfaces is not serialized, because it is taken
+        // from resource, but here scene *is* the resource.
+        set_surfaces_procedural(scene, true);
+        let mut visitor = Visitor::new();
+        let path = self.entry_path(hash);
+        let result = scene
+            .visit("Scene", &mut visitor)
+            .and_then(|_| visitor.save_binary(&path));
+        set_surfaces_procedural(scene, false);
+
+        if let Err(e) = result {
+            Log::writeln(format!(
+                "Unable to write import cache entry {:?}. Reason: {:?}",
+                path, e
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::resource::import_cache::source_hash;
+    use std::path::Path;
+
+    #[test]
+    fn test_source_hash() {
+        let textures = Path::new("data/textures");
+        assert_eq!(source_hash(b"fbx", textures), source_hash(b"fbx", textures));
+        assert_ne!(source_hash(b"fbx", textures), source_hash(b"fbX", textures));
+        assert_ne!(
+            source_hash(b"fbx", textures),
+            source_hash(b"fbx", Path::new("other"))
+        );
+    }
+}
@@ -2,6 +2,8 @@
 
 //!
 
+pub mod channel_packing;
 pub mod fbx;
+pub mod import_cache;
 pub mod model;
 pub mod texture;
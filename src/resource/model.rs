@@ -23,8 +23,8 @@ use crate::{
         pool::Handle,
         visitor::{Visit, VisitError, VisitResult, Visitor},
     },
-    engine::resource_manager::ResourceManager,
-    resource::{fbx, fbx::error::FbxError},
+    engine::resource_manager::{ResourceKind, ResourceManager},
+    resource::{fbx, fbx::error::FbxError, import_cache},
     scene::{node::Node, Scene},
     utils::log::Log,
 };
@@ -116,8 +116,10 @@ impl Model {
         path: P,
         resource_manager: &mut ResourceManager,
     ) -> Result<Model, ModelLoadError> {
-        let extension = path
-            .as_ref()
+        // Resource keeps requested path, but data is read from the path given by load
+        // hooks, so hooks are applied again when the model is reloaded.
+        let resolved_path = resource_manager.resolve_path(path.as_ref(), ResourceKind::Model);
+        let extension = resolved_path
             .extension()
             .unwrap_or_default()
             .to_string_lossy()
@@ -125,13 +127,53 @@ impl Model {
             .to_lowercase();
         let scene = match extension.as_ref() {
             "fbx" => {
-                let mut scene = Scene::new();
-                fbx::load_to_scene(&mut scene, resource_manager, path.as_ref())?;
-                scene
+                let data = resource_manager
+                    .vfs()
+                    .read(&resolved_path)
+                    .map_err(FbxError::Io)?;
+                // Hash whole source only if there is a cache to look it up in.
+                let cache = resource_manager.import_cache().cloned().map(|cache| {
+                    let hash = import_cache::source_hash(&data, resource_manager.textures_path());
+                    (cache, hash)
+                });
+                match cache
+                    .as_ref()
+                    .and_then(|(cache, hash)| cache.load_scene(*hash, resource_manager))
+                {
+                    Some(scene) => scene,
+                    None => {
+                        let mut scene = Scene::new();
+                        fbx::load_bytes_to_scene(
+                            &mut scene,
+                            resource_manager,
+                            &resolved_path,
+                            &data,
+                        )?;
+                        if let Some((cache, hash)) = cache {
+                            cache.store_scene(hash, &mut scene);
+                        }
+                        scene
+                    }
+                }
             }
             // Scene can be used directly as model resource. Such scenes can be created from
             // rusty-editor (https://github.com/mrDIMAS/rusty-editor) for example.
-            "rgs" => Scene::from_file(path.as_ref(), resource_manager)?,
+            "rgs" => match resource_manager.vfs().real_path(&resolved_path) {
+                Some(real_path) => {
+                    if resource_manager.is_lenient_loading() {
+                        // Recovered errors are already written to log.
+                        Scene::from_file_lenient(real_path, resource_manager)?.0
+                    } else {
+                        Scene::from_file(real_path, resource_manager)?
+                    }
+                }
+                None => {
+                    return Err(ModelLoadError::NotSupported(format!(
+                        "Native scene {:?} can be loaded only from a directory mount point",
+                        resolved_path
+                    )))
+                }
+            },
             // TODO: Add more formats.
             _ => {
                 return Err(ModelLoadError::NotSupported(format!(
@@ -69,6 +69,10 @@ pub enum TextureKind {
     RGB8,
     /// Red, green, blue, and alpha components, each by 1 byte.
     RGBA8,
+    /// Only red component as 32-bit float.
+    R32F,
+    /// Red, green, blue, and alpha components, each as 32-bit float.
+    RGBA32F,
 }
 
 impl TextureKind {
@@ -77,6 +81,8 @@ impl TextureKind {
             0 => Ok(TextureKind::R8),
             1 => Ok(TextureKind::RGB8),
             2 => Ok(TextureKind::RGBA8),
+            3 => Ok(TextureKind::R32F),
+            4 => Ok(TextureKind::RGBA32F),
             _ => Err(format!("Invalid texture kind {}!", id)),
         }
     }
@@ -86,16 +92,60 @@ impl TextureKind {
             TextureKind::R8 => 0,
             TextureKind::RGB8 => 1,
             TextureKind::RGBA8 => 2,
+            TextureKind::R32F => 3,
+            TextureKind::RGBA32F => 4,
         }
     }
+
+    /// Returns true if components are 32-bit floats.
+    pub fn is_float(self) -> bool {
+        match self {
+            TextureKind::R32F | TextureKind::RGBA32F => true,
+            TextureKind::R8 | TextureKind::RGB8 | TextureKind::RGBA8 => false,
+        }
+    }
+
+    /// Returns size of one pixel in bytes.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            TextureKind::R8 => 1,
+            TextureKind::RGB8 => 3,
+            TextureKind::RGBA8 | TextureKind::R32F => 4,
+            TextureKind::RGBA32F => 16,
+        }
+    }
+}
+
+/// Maps 8-bit channels to [0; 1] floats.
+fn to_float_bytes(bytes: Vec<u8>) -> Vec<u8> {
+    bytes
+        .into_iter()
+        .flat_map(|v| (f32::from(v) / 255.0).to_ne_bytes().to_vec())
+        .collect()
+}
+
+/// Maps floats to 8-bit channels, values are clamped to [0; 1].
+fn to_byte_channels(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .chunks_exact(4)
+        .map(|c| {
+            let v = f32::from_ne_bytes([c[0], c[1], c[2], c[3]]);
+            (v.max(0.0).min(1.0) * 255.0).round() as u8
+        })
+        .collect()
 }
 
 impl Texture {
-    pub(in crate) fn load_from_file<P: AsRef<Path>>(
+    /// Decodes texture from given encoded image (png, jpg, etc.) bytes. Path is used to
+    /// identify the texture and its extension defines format of the image (some formats,
+    /// like TGA, have no magic bytes to guess it from), data is not read from it.
+    pub(in crate) fn load_from_memory<P: AsRef<Path>>(
         path: P,
+        data: &[u8],
         kind: TextureKind,
     ) -> Result<Self, image::ImageError> {
-        let dyn_img = image::open(path.as_ref())?;
+        let format = image::ImageFormat::from_path(path.as_ref())?;
+        let dyn_img = image::load_from_memory_with_format(data, format)?;
 
         let width = dyn_img.width();
         let height = dyn_img.height();
@@ -104,6 +154,8 @@ impl Texture {
             TextureKind::R8 => dyn_img.to_luma().into_raw(),
             TextureKind::RGB8 => dyn_img.to_rgb().into_raw(),
             TextureKind::RGBA8 => dyn_img.to_rgba().into_raw(),
+            TextureKind::R32F => to_float_bytes(dyn_img.to_luma().into_raw()),
+            TextureKind::RGBA32F => to_float_bytes(dyn_img.to_rgba().into_raw()),
         };
 
         Ok(Texture {
@@ -123,13 +175,7 @@ impl Texture {
         kind: TextureKind,
         bytes: Vec<u8>,
     ) -> Result<Self, ()> {
-        let bpp = match kind {
-            TextureKind::R8 => 1,
-            TextureKind::RGB8 => 3,
-            TextureKind::RGBA8 => 4,
-        };
-
-        let required_bytes = width * height * bpp;
+        let required_bytes = width * height * kind.bytes_per_pixel() as u32;
         if required_bytes != bytes.len() as u32 {
             Err(())
         } else {
@@ -150,24 +196,44 @@ impl Texture {
         self.loaded
     }
 
+    /// Returns width of texture in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns height of texture in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns pixel format of texture.
+    pub fn kind(&self) -> TextureKind {
+        self.kind
+    }
+
+    /// Returns raw pixels of texture, rows go from top to bottom.
+    pub fn data(&self) -> &[u8] {
+        &self.bytes
+    }
+
     /// Sets new path to source file.
     pub fn set_path<P: AsRef<Path>>(&mut self, path: &P) {
         self.path = path.as_ref().to_owned();
     }
 
-    /// Tries to save internal buffer into source file.
+    /// Tries to save internal buffer into source file. Float textures are saved with 8-bit
+    /// channels, values are clamped to [0; 1].
     pub fn save(&self) -> Result<(), ImageError> {
         let color_type = match self.kind {
-            TextureKind::R8 => ColorType::L8,
+            TextureKind::R8 | TextureKind::R32F => ColorType::L8,
             TextureKind::RGB8 => ColorType::Rgb8,
-            TextureKind::RGBA8 => ColorType::Rgba8,
+            TextureKind::RGBA8 | TextureKind::RGBA32F => ColorType::Rgba8,
+        };
+        let bytes = if self.kind.is_float() {
+            to_byte_channels(&self.bytes)
+        } else {
+            self.bytes.clone()
         };
-        image::save_buffer(
-            &self.path,
-            self.bytes.as_ref(),
-            self.width,
-            self.height,
-            color_type,
-        )
+        image::save_buffer(&self.path, &bytes, self.width, self.height, color_type)
     }
 }
@@ -12,8 +12,9 @@ use crate::{
         pool::Handle,
         visitor::{Visit, VisitResult, Visitor},
     },
+    renderer::immediate::RenderCallback,
     resource::model::Model,
-    scene::{node::Node, transform::Transform},
+    scene::{layer::DEFAULT_LAYER, lenient, node::Node, transform::Transform},
 };
 use std::sync::{Arc, Mutex};
 
@@ -29,6 +30,8 @@ pub struct Base {
     pub(in crate) global_transform: Mat4,
     /// Bone-specific matrix. Non-serializable.
     pub(in crate) inv_bind_pose_transform: Mat4,
+    /// Additional transform applied on top of local transform. Non-serializable.
+    post_transform: Option<Mat4>,
     /// A resource from which this node was instantiated from, can work in pair
     /// with `original` handle to get corresponding node from resource.
     pub(in crate) resource: Option<Arc<Mutex<Model>>>,
@@ -43,6 +46,9 @@ pub struct Base {
     /// if node has undefined lifetime.
     lifetime: Option<f32>,
     depth_offset: f32,
+    layer: u32,
+    /// Callback which supplies immediate-mode geometry every frame. Non-serializable.
+    render_callback: Option<RenderCallback>,
 }
 
 impl Base {
@@ -120,6 +126,29 @@ impl Base {
         self.inv_bind_pose_transform
     }
 
+    /// Sets additional local-space transform which will be applied on top of local
+    /// transform when global transform is calculated, so it will be correctly propagated
+    /// to descendant nodes. Main purpose of this method is to override bone transforms
+    /// after animation was applied but before skinning, this can be used for procedural
+    /// aiming, look-at and other custom constraints. Post transform is not serialized.
+    pub fn set_post_transform(&mut self, transform: Option<Mat4>) -> &mut Self {
+        self.post_transform = transform;
+        self
+    }
+
+    /// Returns current post transform. See [`set_post_transform`] for more info.
+    pub fn post_transform(&self) -> Option<Mat4> {
+        self.post_transform
+    }
+
+    /// Returns local transform matrix combined with post transform (if any).
+    pub(in crate) fn combined_local_transform(&self) -> Mat4 {
+        match self.post_transform {
+            Some(post_transform) => self.local_transform.matrix() * post_transform,
+            None => self.local_transform.matrix(),
+        }
+    }
+
     /// Returns true if this node is model resource instance root node.
     pub fn is_resource_instance(&self) -> bool {
         self.is_resource_instance
@@ -198,6 +227,31 @@ impl Base {
     pub fn depth_offset_factor(&self) -> f32 {
         self.depth_offset
     }
+
+    /// Sets index of render layer of the node. Node will be rendered only by cameras
+    /// which have this layer in their layer mask. See `layer` module docs for more info.
+    pub fn set_layer(&mut self, layer: u32) -> &mut Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Returns index of render layer of the node.
+    pub fn layer(&self) -> u32 {
+        self.layer
+    }
+
+    /// Sets callback which will be called by renderer every frame to get transient geometry
+    /// of the node. See [immediate](../../renderer/immediate/index.html) module docs for more
+    /// info. Render callback is not serialized.
+    pub fn set_render_callback(&mut self, callback: Option<RenderCallback>) -> &mut Self {
+        self.render_callback = callback;
+        self
+    }
+
+    /// Returns current render callback, if any.
+    pub fn render_callback(&self) -> Option<&RenderCallback> {
+        self.render_callback.as_ref()
+    }
 }
 
 impl Clone for Base {
@@ -211,9 +265,12 @@ impl Clone for Base {
             visibility: self.visibility,
             global_visibility: self.global_visibility,
             inv_bind_pose_transform: self.inv_bind_pose_transform,
+            post_transform: self.post_transform,
             resource: self.resource.clone(),
             is_resource_instance: self.is_resource_instance,
             lifetime: self.lifetime,
+            layer: self.layer,
+            render_callback: self.render_callback.clone(),
             // Rest of data is *not* copied!
             ..Default::default()
         }
@@ -230,16 +287,20 @@ impl Visit for Base {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
 
-        self.name.visit("Name", visitor)?;
-        self.local_transform.visit("Transform", visitor)?;
-        self.visibility.visit("Visibility", visitor)?;
-        self.parent.visit("Parent", visitor)?;
-        self.children.visit("Children", visitor)?;
-        self.resource.visit("Resource", visitor)?;
-        self.is_resource_instance
-            .visit("IsResourceInstance", visitor)?;
-        self.lifetime.visit("Lifetime", visitor)?;
-        self.depth_offset.visit("DepthOffset", visitor)?;
+        lenient::field(&mut self.name, "Name", visitor)?;
+        lenient::field(&mut self.local_transform, "Transform", visitor)?;
+        lenient::field(&mut self.visibility, "Visibility", visitor)?;
+        lenient::field(&mut self.parent, "Parent", visitor)?;
+        lenient::field(&mut self.children, "Children", visitor)?;
+        lenient::field(&mut self.resource, "Resource", visitor)?;
+        lenient::field(
+            &mut self.is_resource_instance,
+            "IsResourceInstance",
+            visitor,
+        )?;
+        lenient::field(&mut self.lifetime, "Lifetime", visitor)?;
+        lenient::field(&mut self.depth_offset, "DepthOffset", visitor)?;
+        let _ = self.layer.visit("Layer", visitor);
 
         visitor.leave_region()
     }
@@ -253,6 +314,8 @@ pub struct BaseBuilder {
     children: Option<Vec<Handle<Node>>>,
     lifetime: Option<f32>,
     depth_offset: f32,
+    layer: u32,
+    render_callback: Option<RenderCallback>,
 }
 
 impl Default for BaseBuilder {
@@ -271,6 +334,8 @@ impl BaseBuilder {
             children: None,
             lifetime: None,
             depth_offset: 0.0,
+            layer: DEFAULT_LAYER,
+            render_callback: None,
         }
     }
 
@@ -310,6 +375,18 @@ impl BaseBuilder {
         self
     }
 
+    /// Sets desired render layer.
+    pub fn with_layer(mut self, layer: u32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Sets desired render callback.
+    pub fn with_render_callback(mut self, callback: RenderCallback) -> Self {
+        self.render_callback = Some(callback);
+        self
+    }
+
     /// Creates new instance of base scene node. Do not forget to add
     /// node to scene or pass to other nodes as base.
     pub fn build(self) -> Base {
@@ -323,10 +400,13 @@ impl BaseBuilder {
             parent: Handle::NONE,
             global_transform: Mat4::IDENTITY,
             inv_bind_pose_transform: Mat4::IDENTITY,
+            post_transform: None,
             resource: None,
             original: Handle::NONE,
             is_resource_instance: false,
             depth_offset: self.depth_offset,
+            layer: self.layer,
+            render_callback: self.render_callback,
         }
     }
 
@@ -13,6 +13,13 @@
 //!
 //! Each camera forces engine to re-render same scene one more time, which may cause
 //! almost double load of your GPU.
+//!
+//! # Aspect ratio
+//!
+//! By default vertical field of view is fixed and horizontal one follows aspect ratio of
+//! viewport, so wider screens see more at the sides. Games designed for specific aspect
+//! ratio can use [`AspectMode`] to letterbox, expand or crop the image instead, so
+//! ultrawide and tall screens do not show things they shouldn't.
 
 use crate::scene::node::Node;
 use crate::{
@@ -20,10 +27,158 @@ use crate::{
         math::{mat4::Mat4, ray::Ray, vec2::Vec2, vec3::Vec3, vec4::Vec4, Rect},
         visitor::{Visit, VisitResult, Visitor},
     },
-    scene::base::{Base, BaseBuilder},
+    scene::{
+        base::{Base, BaseBuilder},
+        color_blindness::ColorBlindFilter,
+        layer::{layer_bit, ALL_LAYERS},
+        lenient,
+    },
+    utils::log::Log,
 };
 use std::ops::{Deref, DerefMut};
 
+/// Depth of field settings of a camera. Objects closer or further than focus range
+/// around focus distance are blurred, blur grows with distance from focus range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthOfField {
+    /// Distance from camera to center of sharp area.
+    pub focus_distance: f32,
+    /// Depth of sharp area around focus distance, also distance over which blur goes from
+    /// zero to maximum.
+    pub focus_range: f32,
+    /// Maximum radius of blur in pixels.
+    pub max_blur: f32,
+}
+
+impl Default for DepthOfField {
+    fn default() -> Self {
+        Self {
+            focus_distance: 10.0,
+            focus_range: 5.0,
+            max_blur: 8.0,
+        }
+    }
+}
+
+impl Visit for DepthOfField {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.focus_distance.visit("FocusDistance", visitor)?;
+        self.focus_range.visit("FocusRange", visitor)?;
+        self.max_blur.visit("MaxBlur", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Defines how camera adapts to aspect ratio of its viewport. Every mode except `Free`
+/// has target aspect ratio (width / height), for example 16.0 / 9.0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AspectMode {
+    /// Vertical field of view is fixed, horizontal field of view follows aspect ratio of
+    /// viewport. This is default mode.
+    Free,
+    /// Viewport is shrunk to target aspect ratio and centered, rest of the viewport is
+    /// filled with black bars at the sides or at the top and bottom.
+    Letterbox(f32),
+    /// Whole area visible at target aspect ratio is always visible, wider viewports get
+    /// extra space at the sides, taller ones - at the top and bottom.
+    Expand(f32),
+    /// Viewport is always filled with area visible at target aspect ratio, excess of the
+    /// image is cropped.
+    Crop(f32),
+}
+
+impl Default for AspectMode {
+    fn default() -> Self {
+        AspectMode::Free
+    }
+}
+
+impl AspectMode {
+    fn id(self) -> u32 {
+        match self {
+            AspectMode::Free => 0,
+            AspectMode::Letterbox(_) => 1,
+            AspectMode::Expand(_) => 2,
+            AspectMode::Crop(_) => 3,
+        }
+    }
+
+    /// Returns target aspect ratio of mode, if any.
+    pub fn target_aspect(self) -> Option<f32> {
+        match self {
+            AspectMode::Free => None,
+            AspectMode::Letterbox(aspect)
+            | AspectMode::Expand(aspect)
+            | AspectMode::Crop(aspect) => Some(aspect),
+        }
+    }
+
+    /// Returns vertical field of view which should be used for viewport with given
+    /// aspect ratio, so horizontal field of view of target aspect ratio is preserved
+    /// when needed.
+    pub fn vertical_fov(self, fov: f32, aspect: f32) -> f32 {
+        let preserve_horizontal = match self {
+            AspectMode::Free | AspectMode::Letterbox(_) => None,
+            AspectMode::Expand(target) if aspect < target => Some(target),
+            AspectMode::Crop(target) if aspect > target => Some(target),
+            AspectMode::Expand(_) | AspectMode::Crop(_) => None,
+        };
+        match preserve_horizontal {
+            Some(target) if aspect > 0.0 => 2.0 * ((fov * 0.5).tan() * target / aspect).atan(),
+            _ => fov,
+        }
+    }
+
+    /// Returns part of given viewport (in pixels) which is used for rendering, only
+    /// letterbox mode makes it smaller.
+    pub fn fit_viewport(self, viewport: Rect<i32>) -> Rect<i32> {
+        match self {
+            AspectMode::Letterbox(target) if target > 0.0 && viewport.h > 0 => {
+                let aspect = viewport.w as f32 / viewport.h as f32;
+                if aspect > target {
+                    let w = (viewport.h as f32 * target) as i32;
+                    Rect::new(viewport.x + (viewport.w - w) / 2, viewport.y, w, viewport.h)
+                } else {
+                    let h = (viewport.w as f32 / target) as i32;
+                    Rect::new(viewport.x, viewport.y + (viewport.h - h) / 2, viewport.w, h)
+                }
+            }
+            _ => viewport,
+        }
+    }
+}
+
+impl Visit for AspectMode {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        let mut target = self.target_aspect().unwrap_or(16.0 / 9.0);
+        target.visit("Target", visitor)?;
+        if visitor.is_reading() {
+            *self = match id {
+                0 => AspectMode::Free,
+                1 => AspectMode::Letterbox(target),
+                2 => AspectMode::Expand(target),
+                3 => AspectMode::Crop(target),
+                _ => {
+                    Log::writeln(format!(
+                        "Invalid aspect mode {}, default mode is used instead.",
+                        id
+                    ));
+                    AspectMode::default()
+                }
+            };
+        }
+
+        visitor.leave_region()
+    }
+}
+
 /// See module docs.
 #[derive(Clone, Debug)]
 pub struct Camera {
@@ -35,6 +190,11 @@ pub struct Camera {
     view_matrix: Mat4,
     projection_matrix: Mat4,
     enabled: bool,
+    layer_mask: u32,
+    exposure: f32,
+    depth_of_field: Option<DepthOfField>,
+    aspect_mode: AspectMode,
+    color_blind_filter: Option<ColorBlindFilter>,
 }
 
 impl Deref for Camera {
@@ -60,12 +220,17 @@ impl Default for Camera {
 impl Visit for Camera {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
-        self.fov.visit("Fov", visitor)?;
-        self.z_near.visit("ZNear", visitor)?;
-        self.z_far.visit("ZFar", visitor)?;
-        self.viewport.visit("Viewport", visitor)?;
+        lenient::field(&mut self.fov, "Fov", visitor)?;
+        lenient::field(&mut self.z_near, "ZNear", visitor)?;
+        lenient::field(&mut self.z_far, "ZFar", visitor)?;
+        lenient::field(&mut self.viewport, "Viewport", visitor)?;
         self.base.visit("Base", visitor)?;
-        self.enabled.visit("Enabled", visitor)?;
+        lenient::field(&mut self.enabled, "Enabled", visitor)?;
+        let _ = self.layer_mask.visit("LayerMask", visitor);
+        let _ = self.exposure.visit("Exposure", visitor);
+        let _ = self.depth_of_field.visit("DepthOfField", visitor);
+        let _ = self.aspect_mode.visit("AspectMode", visitor);
+        let _ = self.color_blind_filter.visit("ColorBlindFilter", visitor);
         visitor.leave_region()
     }
 }
@@ -86,7 +251,8 @@ impl Camera {
         }
         let viewport = self.viewport_pixels(frame_size);
         let aspect = viewport.w as f32 / viewport.h as f32;
-        self.projection_matrix = Mat4::perspective(self.fov, aspect, self.z_near, self.z_far);
+        let fov = self.aspect_mode.vertical_fov(self.fov, aspect);
+        self.projection_matrix = Mat4::perspective(fov, aspect, self.z_near, self.z_far);
     }
 
     /// Sets new viewport in resolution-independent format. In other words
@@ -103,14 +269,29 @@ impl Camera {
 
     /// Calculates viewport rectangle in pixels based on internal resolution-independent
     /// viewport. It is useful when you need to get real viewport rectangle in pixels.
+    /// Letterbox aspect mode is taken into account, so returned rectangle can be smaller
+    /// than viewport.
     #[inline]
     pub fn viewport_pixels(&self, frame_size: Vec2) -> Rect<i32> {
-        Rect {
+        self.aspect_mode.fit_viewport(Rect {
             x: (self.viewport.x * frame_size.x) as i32,
             y: (self.viewport.y * frame_size.y) as i32,
             w: (self.viewport.w * frame_size.x) as i32,
             h: (self.viewport.h * frame_size.y) as i32,
-        }
+        })
+    }
+
+    /// Sets new aspect mode, see [`AspectMode`] docs.
+    #[inline]
+    pub fn set_aspect_mode(&mut self, aspect_mode: AspectMode) -> &mut Self {
+        self.aspect_mode = aspect_mode;
+        self
+    }
+
+    /// Returns current aspect mode.
+    #[inline]
+    pub fn aspect_mode(&self) -> AspectMode {
+        self.aspect_mode
     }
 
     /// Returns current view-projection matrix.
@@ -191,13 +372,86 @@ impl Camera {
         self
     }
 
+    /// Sets layer mask of camera. Camera will render only nodes which layers are
+    /// included in the mask. See `layer` module docs for more info.
+    #[inline]
+    pub fn set_layer_mask(&mut self, mask: u32) -> &mut Self {
+        self.layer_mask = mask;
+        self
+    }
+
+    /// Returns current layer mask of camera.
+    #[inline]
+    pub fn layer_mask(&self) -> u32 {
+        self.layer_mask
+    }
+
+    /// Returns true if camera can see nodes in given layer.
+    #[inline]
+    pub fn sees_layer(&self, layer: u32) -> bool {
+        self.layer_mask & layer_bit(layer) != 0
+    }
+
+    /// Sets exposure multiplier of final image of camera, 1.0 means no change.
+    #[inline]
+    pub fn set_exposure(&mut self, exposure: f32) -> &mut Self {
+        self.exposure = exposure.max(0.0);
+        self
+    }
+
+    /// Returns exposure multiplier of final image of camera.
+    #[inline]
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Sets or removes depth of field effect.
+    #[inline]
+    pub fn set_depth_of_field(&mut self, depth_of_field: Option<DepthOfField>) -> &mut Self {
+        self.depth_of_field = depth_of_field;
+        self
+    }
+
+    /// Returns depth of field settings of camera.
+    #[inline]
+    pub fn depth_of_field(&self) -> Option<DepthOfField> {
+        self.depth_of_field
+    }
+
+    /// Sets or removes color blindness filter of final image, see
+    /// [color_blindness](../color_blindness/index.html) module docs.
+    #[inline]
+    pub fn set_color_blind_filter(&mut self, filter: Option<ColorBlindFilter>) -> &mut Self {
+        self.color_blind_filter = filter;
+        self
+    }
+
+    /// Returns color blindness filter of camera.
+    #[inline]
+    pub fn color_blind_filter(&self) -> Option<ColorBlindFilter> {
+        self.color_blind_filter
+    }
+
+    /// Returns true if exposure, depth of field or color filter must be applied to final
+    /// image.
+    pub(in crate) fn has_post_effects(&self) -> bool {
+        self.exposure != 1.0 || self.depth_of_field.is_some() || self.color_blind_filter.is_some()
+    }
+
+    /// Overrides projection matrix which was calculated in `calculate_matrices`, used by
+    /// renderer to render parts of a frame.
+    pub(in crate) fn set_projection_matrix(&mut self, projection_matrix: Mat4) {
+        self.projection_matrix = projection_matrix;
+    }
+
     /// Creates picking ray from given screen coordinates.
     pub fn make_ray(&self, screen_coord: Vec2, screen_size: Vec2) -> Ray {
         let viewport = self.viewport_pixels(screen_size);
-        let nx = screen_coord.x / (viewport.w as f32) * 2.0 - 1.0;
+        let nx = (screen_coord.x - viewport.x as f32) / (viewport.w as f32) * 2.0 - 1.0;
         // Invert y here because OpenGL has origin at left bottom corner,
         // but window coordinates starts from left *upper* corner.
-        let ny = (viewport.h as f32 - screen_coord.y) / (viewport.h as f32) * 2.0 - 1.0;
+        let ny =
+            (screen_size.y - screen_coord.y - viewport.y as f32) / (viewport.h as f32) * 2.0 - 1.0;
         let inv_view_proj = self.view_projection_matrix().inverse().unwrap_or_default();
         let near = inv_view_proj.transform_vector4(Vec4::new(nx, ny, -1.0, 1.0));
         let far = inv_view_proj.transform_vector4(Vec4::new(nx, ny, 1.0, 1.0));
@@ -216,7 +470,7 @@ impl Camera {
             let k = (1.0 / proj.w) * 0.5;
             Some(Vec2::new(
                 viewport.x as f32 + viewport.w as f32 * (proj.x * k + 0.5),
-                viewport.h as f32 - (viewport.y as f32 + viewport.h as f32 * (proj.y * k + 0.5)),
+                screen_size.y - (viewport.y as f32 + viewport.h as f32 * (proj.y * k + 0.5)),
             ))
         } else {
             None
@@ -233,6 +487,11 @@ pub struct CameraBuilder {
     z_far: f32,
     viewport: Rect<f32>,
     enabled: bool,
+    layer_mask: u32,
+    exposure: f32,
+    depth_of_field: Option<DepthOfField>,
+    aspect_mode: AspectMode,
+    color_blind_filter: Option<ColorBlindFilter>,
 }
 
 impl CameraBuilder {
@@ -240,6 +499,11 @@ impl CameraBuilder {
     pub fn new(base_builder: BaseBuilder) -> Self {
         Self {
             enabled: true,
+            layer_mask: ALL_LAYERS,
+            exposure: 1.0,
+            depth_of_field: None,
+            aspect_mode: AspectMode::Free,
+            color_blind_filter: None,
             base_builder,
             fov: 75.0f32.to_radians(),
             z_near: 0.025,
@@ -283,11 +547,46 @@ impl CameraBuilder {
         self
     }
 
+    /// Sets desired layer mask.
+    pub fn with_layer_mask(mut self, mask: u32) -> Self {
+        self.layer_mask = mask;
+        self
+    }
+
+    /// Sets desired exposure multiplier.
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure.max(0.0);
+        self
+    }
+
+    /// Sets desired depth of field settings.
+    pub fn with_depth_of_field(mut self, depth_of_field: DepthOfField) -> Self {
+        self.depth_of_field = Some(depth_of_field);
+        self
+    }
+
+    /// Sets desired aspect mode.
+    pub fn with_aspect_mode(mut self, aspect_mode: AspectMode) -> Self {
+        self.aspect_mode = aspect_mode;
+        self
+    }
+
+    /// Sets desired color blindness filter.
+    pub fn with_color_blind_filter(mut self, filter: ColorBlindFilter) -> Self {
+        self.color_blind_filter = Some(filter);
+        self
+    }
+
     /// Creates new instance of camera node. Do not forget to add node to scene,
     /// otherwise it is useless.
     pub fn build(self) -> Camera {
         Camera {
             enabled: self.enabled,
+            layer_mask: self.layer_mask,
+            exposure: self.exposure,
+            depth_of_field: self.depth_of_field,
+            aspect_mode: self.aspect_mode,
+            color_blind_filter: self.color_blind_filter,
             base: self.base_builder.build(),
             fov: self.fov,
             z_near: self.z_near,
@@ -0,0 +1,316 @@
+//! Color blindness filters.
+//!
+//! Filter is a linear transformation of colors of final image of a camera, see
+//! [`Camera::set_color_blind_filter`](../camera/struct.Camera.html#method.set_color_blind_filter).
+//! It can either simulate how image is seen with given type of color blindness, which is
+//! useful to check readability of a game during development, or compensate it by shifting
+//! colors that can't be distinguished to ones that can (daltonization). Same compensation
+//! can be applied to individual colors, for example to team colors or to colors of UI
+//! elements, using [`shift_palette`].
+//!
+//! Simulation matrices are taken from "A Physiologically-based Model for Simulation of Color
+//! Vision Deficiency" by Machado et al. with severity 1.0. Matrices are defined for linear
+//! colors, so gamma-encoded (sRGB) colors are converted to linear space and back around them.
+
+use crate::{
+    core::{
+        color::Color,
+        math::vec3::Vec3,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    utils::log::Log,
+};
+
+/// Type of color vision deficiency.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorBlindness {
+    /// Missing red cones, red and green are hard to distinguish and red looks dark.
+    Protanopia,
+    /// Missing green cones, red and green are hard to distinguish. Most common type.
+    Deuteranopia,
+    /// Missing blue cones, blue and green, yellow and red are hard to distinguish.
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    fn id(self) -> u32 {
+        match self {
+            ColorBlindness::Protanopia => 0,
+            ColorBlindness::Deuteranopia => 1,
+            ColorBlindness::Tritanopia => 2,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(ColorBlindness::Protanopia),
+            1 => Ok(ColorBlindness::Deuteranopia),
+            2 => Ok(ColorBlindness::Tritanopia),
+            _ => Err(format!("Invalid color blindness {}", id)),
+        }
+    }
+
+    /// Returns rows of matrix which transforms color to how it is seen with this type
+    /// of color blindness.
+    pub fn simulation_matrix(self) -> [Vec3; 3] {
+        match self {
+            ColorBlindness::Protanopia => [
+                Vec3::new(0.152_286, 1.052_583, -0.204_868),
+                Vec3::new(0.114_503, 0.786_281, 0.099_216),
+                Vec3::new(-0.003_882, -0.048_116, 1.051_998),
+            ],
+            ColorBlindness::Deuteranopia => [
+                Vec3::new(0.367_322, 0.860_646, -0.227_968),
+                Vec3::new(0.280_085, 0.672_501, 0.047_413),
+                Vec3::new(-0.011_820, 0.042_940, 0.968_881),
+            ],
+            ColorBlindness::Tritanopia => [
+                Vec3::new(1.255_528, -0.076_749, -0.178_779),
+                Vec3::new(-0.078_411, 0.930_809, 0.147_602),
+                Vec3::new(0.004_733, 0.691_367, 0.303_900),
+            ],
+        }
+    }
+
+    /// Returns rows of matrix which moves error between original and simulated colors to
+    /// channels that are still perceived.
+    fn error_shift_matrix(self) -> [Vec3; 3] {
+        match self {
+            // Red-green error goes to green and blue.
+            ColorBlindness::Protanopia | ColorBlindness::Deuteranopia => [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.7, 1.0, 0.0),
+                Vec3::new(0.7, 0.0, 1.0),
+            ],
+            // Blue-yellow error goes to red and green.
+            ColorBlindness::Tritanopia => [
+                Vec3::new(1.0, 0.0, 0.7),
+                Vec3::new(0.0, 1.0, 0.7),
+                Vec3::new(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    /// Returns rows of matrix which compensates this type of color blindness.
+    pub fn compensation_matrix(self) -> [Vec3; 3] {
+        // C = I + E * (I - S)
+        let error = sub(IDENTITY, self.simulation_matrix());
+        add(IDENTITY, mul(self.error_shift_matrix(), error))
+    }
+}
+
+/// What filter does with colors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorBlindMode {
+    /// Shows how image is seen with color blindness.
+    Simulate,
+    /// Shifts colors so image is easier to read with color blindness.
+    Compensate,
+}
+
+impl ColorBlindMode {
+    fn id(self) -> u32 {
+        match self {
+            ColorBlindMode::Simulate => 0,
+            ColorBlindMode::Compensate => 1,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(ColorBlindMode::Simulate),
+            1 => Ok(ColorBlindMode::Compensate),
+            _ => Err(format!("Invalid color blind mode {}", id)),
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColorBlindFilter {
+    /// Type of color blindness.
+    pub kind: ColorBlindness,
+    /// Simulation or compensation.
+    pub mode: ColorBlindMode,
+    /// Strength of filter in [0; 1] range, 0 - no effect, 1 - full effect.
+    pub strength: f32,
+}
+
+impl Default for ColorBlindFilter {
+    fn default() -> Self {
+        Self::new(ColorBlindness::Deuteranopia, ColorBlindMode::Compensate)
+    }
+}
+
+impl Visit for ColorBlindFilter {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut kind = self.kind.id();
+        kind.visit("Kind", visitor)?;
+        let mut mode = self.mode.id();
+        mode.visit("Mode", visitor)?;
+        if visitor.is_reading() {
+            let default = Self::default();
+            self.kind = ColorBlindness::from_id(kind).unwrap_or_else(|e| {
+                Log::writeln(format!("{}, default kind is used instead.", e));
+                default.kind
+            });
+            self.mode = ColorBlindMode::from_id(mode).unwrap_or_else(|e| {
+                Log::writeln(format!("{}, default mode is used instead.", e));
+                default.mode
+            });
+        }
+        self.strength.visit("Strength", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub(in crate) const IDENTITY: [Vec3; 3] = [
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    },
+];
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn add(a: [Vec3; 3], b: [Vec3; 3]) -> [Vec3; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [Vec3; 3], b: [Vec3; 3]) -> [Vec3; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn mul(a: [Vec3; 3], b: [Vec3; 3]) -> [Vec3; 3] {
+    let column = |i: usize| match i {
+        0 => Vec3::new(b[0].x, b[1].x, b[2].x),
+        1 => Vec3::new(b[0].y, b[1].y, b[2].y),
+        _ => Vec3::new(b[0].z, b[1].z, b[2].z),
+    };
+    let row = |r: Vec3| Vec3::new(r.dot(&column(0)), r.dot(&column(1)), r.dot(&column(2)));
+    [row(a[0]), row(a[1]), row(a[2])]
+}
+
+impl ColorBlindFilter {
+    /// Creates new filter with full strength.
+    pub fn new(kind: ColorBlindness, mode: ColorBlindMode) -> Self {
+        Self {
+            kind,
+            mode,
+            strength: 1.0,
+        }
+    }
+
+    /// Sets strength of filter.
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength.max(0.0).min(1.0);
+        self
+    }
+
+    /// Returns rows of color transformation matrix of filter, strength is taken into
+    /// account.
+    pub fn matrix(&self) -> [Vec3; 3] {
+        let full = match self.mode {
+            ColorBlindMode::Simulate => self.kind.simulation_matrix(),
+            ColorBlindMode::Compensate => self.kind.compensation_matrix(),
+        };
+        let k = self.strength.max(0.0).min(1.0);
+        let lerp = |a: Vec3, b: Vec3| a + (b - a).scale(k);
+        [
+            lerp(IDENTITY[0], full[0]),
+            lerp(IDENTITY[1], full[1]),
+            lerp(IDENTITY[2], full[2]),
+        ]
+    }
+
+    /// Applies filter to a single gamma-encoded (sRGB) color, alpha is left untouched.
+    pub fn apply(&self, color: Color) -> Color {
+        let m = self.matrix();
+        let v = Vec3::new(
+            srgb_to_linear(color.r as f32 / 255.0),
+            srgb_to_linear(color.g as f32 / 255.0),
+            srgb_to_linear(color.b as f32 / 255.0),
+        );
+        let channel =
+            |row: Vec3| (linear_to_srgb(row.dot(&v).max(0.0).min(1.0)) * 255.0).round() as u8;
+        Color::from_rgba(channel(m[0]), channel(m[1]), channel(m[2]), color.a)
+    }
+}
+
+/// Shifts colors of palette so they're easier to distinguish with given type of color
+/// blindness. Useful for colors which are not part of rendered image, like colors of UI
+/// or markers of teams.
+pub fn shift_palette(palette: &[Color], kind: ColorBlindness) -> Vec<Color> {
+    let filter = ColorBlindFilter::new(kind, ColorBlindMode::Compensate);
+    palette.iter().map(|&color| filter.apply(color)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::color::Color,
+        scene::color_blindness::{shift_palette, ColorBlindFilter, ColorBlindMode, ColorBlindness},
+    };
+
+    #[test]
+    fn test_grey_is_preserved() {
+        let kinds = [
+            ColorBlindness::Protanopia,
+            ColorBlindness::Deuteranopia,
+            ColorBlindness::Tritanopia,
+        ];
+        for &kind in kinds.iter() {
+            for &mode in [ColorBlindMode::Simulate, ColorBlindMode::Compensate].iter() {
+                let filter = ColorBlindFilter::new(kind, mode);
+                let grey = Color::from_rgba(128, 128, 128, 200);
+                let result = filter.apply(grey);
+                assert!((result.r as i32 - 128).abs() <= 1);
+                assert!((result.g as i32 - 128).abs() <= 1);
+                assert!((result.b as i32 - 128).abs() <= 1);
+                assert_eq!(result.a, 200);
+            }
+        }
+    }
+
+    #[test]
+    fn test_strength_and_palette() {
+        let red = Color::from_rgba(255, 0, 0, 255);
+        let filter = ColorBlindFilter::new(ColorBlindness::Deuteranopia, ColorBlindMode::Simulate)
+            .with_strength(0.0);
+        assert_eq!(filter.apply(red), red);
+
+        // Red and green look similar to deuteranope, compensation makes them differ in blue.
+        let green = Color::from_rgba(0, 255, 0, 255);
+        let shifted = shift_palette(&[red, green], ColorBlindness::Deuteranopia);
+        assert_ne!(shifted[0], red);
+        assert!(shifted[0].b as i32 - shifted[1].b as i32 > 50);
+    }
+}
@@ -0,0 +1,1045 @@
+//! Contains all structures and methods to edit scenes at runtime with undo/redo support.
+//!
+//! Every modification of a scene is done by a command, which knows how to execute itself
+//! and how to revert changes it made. Commands are executed through command stack, which
+//! keeps history of executed commands and allows to undo and redo them. This is the
+//! foundation for in-game editors and modding tools.
+//!
+//! # Handles
+//!
+//! Commands never invalidate handles: deleted nodes are not destroyed, but extracted from
+//! graph with their handles reserved, so undo of deletion puts nodes back at the same
+//! handles and every other command in the stack stays valid. Reserved handles are
+//! released when command leaves the stack (i.e. when new command is executed after undo).
+//! Animation tracks of deleted nodes are removed together with nodes and are put back on
+//! undo. Note that rigid bodies bound with deleted nodes are unbound on next scene update
+//! and won't be bound back on undo.
+//!
+//! # Serialization
+//!
+//! Command stack implements `Visit`, so undo/redo history can be saved together with the
+//! scene and editing can be continued after load. Every command is saved with its undo
+//! state (extracted nodes, old values), so stack must be loaded together with the same
+//! scene it was saved with. Reservations of handles are not saved, so nodes that were
+//! outside of graph at save time are put back at new handles; the stack replaces old
+//! handles with new ones in every command, but handles stored elsewhere become invalid.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{
+//!     core::math::vec3::Vec3,
+//!     scene::{
+//!         base::BaseBuilder,
+//!         command::{CommandStack, NodeProperty, SceneCommand},
+//!         Scene,
+//!     },
+//! };
+//!
+//! let mut scene = Scene::new();
+//! let mut stack = CommandStack::new(None);
+//!
+//! let parent = scene.graph.get_root();
+//! let node = BaseBuilder::new().with_name("Box").build_node();
+//! stack.do_command(SceneCommand::add_node(node, parent), &mut scene).unwrap();
+//! let handle = scene.graph.find_by_name_from_root("Box");
+//!
+//! stack
+//!     .do_command(
+//!         SceneCommand::set_property(handle, NodeProperty::Position(Vec3::new(1.0, 0.0, 0.0))),
+//!         &mut scene,
+//!     )
+//!     .unwrap();
+//!
+//! stack.undo(&mut scene);
+//! assert_eq!(scene.graph[handle].local_transform().position(), Vec3::ZERO);
+//! ```
+
+use crate::{
+    animation::{Animation, Track},
+    core::{
+        math::{quat::Quat, vec3::Vec3},
+        pool::{Handle, Ticket},
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::{
+        graph::{Graph, SubGraph},
+        node::Node,
+        Scene,
+    },
+};
+use std::collections::{HashMap, HashSet};
+
+/// Old and new handles of nodes that were put back into graph at new handles.
+type HandleMap = HashMap<Handle<Node>, Handle<Node>>;
+
+fn remap_handle(handle: &mut Handle<Node>, map: &HandleMap) {
+    if let Some(new_handle) = map.get(handle) {
+        *handle = *new_handle;
+    }
+}
+
+fn visit_index(index: &mut usize, name: &str, visitor: &mut Visitor) -> VisitResult {
+    let mut value = if *index == std::usize::MAX {
+        std::u32::MAX
+    } else {
+        *index as u32
+    };
+    value.visit(name, visitor)?;
+    if visitor.is_reading() {
+        *index = if value == std::u32::MAX {
+            std::usize::MAX
+        } else {
+            value as usize
+        };
+    }
+    Ok(())
+}
+
+/// Links child with parent and moves child to given position in children list of parent,
+/// so undo restores exact order of nodes.
+fn link_at(graph: &mut Graph, child: Handle<Node>, parent: Handle<Node>, index: usize) {
+    graph.link_nodes(child, parent);
+    let children = &mut graph[parent].children;
+    let child = children.pop().unwrap();
+    children.insert(index.min(children.len()), child);
+}
+
+fn child_index(graph: &Graph, child: Handle<Node>) -> usize {
+    let parent = graph[child].parent();
+    if parent.is_some() {
+        graph[parent]
+            .children()
+            .iter()
+            .position(|c| *c == child)
+            .unwrap_or(0)
+    } else {
+        0
+    }
+}
+
+/// Adds a node to the graph and links it with a parent.
+#[derive(Debug, Default)]
+pub struct AddNodeCommand {
+    parent: Handle<Node>,
+    // Copy of the node is kept all the time, so command can be saved in any state.
+    node: Option<Node>,
+    handle: Handle<Node>,
+    ticket: Option<Ticket<Node>>,
+}
+
+impl AddNodeCommand {
+    fn execute(&mut self, scene: &mut Scene, map: &mut HandleMap) -> Result<(), &'static str> {
+        if !scene.graph.is_valid_handle(self.parent) {
+            return Err("parent node does not exist");
+        }
+        let node = self.node.clone().ok_or("nothing to add")?;
+        let handle = match self.ticket.take() {
+            Some(ticket) => scene.graph.put_back(ticket, node),
+            None => scene.graph.add_node(node),
+        };
+        // Command was loaded after undo, so its handle was not reserved.
+        if self.handle.is_some() && self.handle != handle {
+            map.insert(self.handle, handle);
+        }
+        self.handle = handle;
+        scene.graph.link_nodes(self.handle, self.parent);
+        Ok(())
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        let (ticket, node) = scene.graph.take_reserve(self.handle);
+        self.ticket = Some(ticket);
+        self.node = Some(node);
+    }
+
+    fn finalize(&mut self, scene: &mut Scene) {
+        if let Some(ticket) = self.ticket.take() {
+            scene.graph.forget_ticket(ticket);
+        }
+    }
+
+    fn remap(&mut self, map: &HandleMap) {
+        remap_handle(&mut self.parent, map);
+        remap_handle(&mut self.handle, map);
+    }
+
+    /// Returns handle of added node. It is valid only after command was executed.
+    pub fn handle(&self) -> Handle<Node> {
+        self.handle
+    }
+}
+
+impl Visit for AddNodeCommand {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.parent.visit("Parent", visitor)?;
+        self.node.visit("Node", visitor)?;
+        self.handle.visit("Handle", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Animation track that was removed together with its node.
+#[derive(Debug, Default)]
+struct RemovedTrack {
+    animation: Handle<Animation>,
+    track: Track,
+}
+
+impl Visit for RemovedTrack {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.animation.visit("Animation", visitor)?;
+        self.track.visit("Track", visitor)?;
+        // Track does not save its frames, because they are usually taken from resource,
+        // but there is nothing to take them from here.
+        let mut frames = self.track.get_key_frames().to_vec();
+        frames.visit("Frames", visitor)?;
+        if visitor.is_reading() {
+            self.track.set_key_frames(&frames);
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Node that was outside of graph when command was saved, with its handle at that time.
+#[derive(Debug, Default)]
+struct SavedNode {
+    handle: Handle<Node>,
+    node: Node,
+}
+
+impl Visit for SavedNode {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.handle.visit("Handle", visitor)?;
+        self.node.visit("Node", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Deletes a node with all its descendants.
+#[derive(Debug, Default)]
+pub struct DeleteNodeCommand {
+    handle: Handle<Node>,
+    parent: Handle<Node>,
+    index: usize,
+    sub_graph: Option<SubGraph>,
+    // Deleted nodes of loaded command, root is first. They have no reserved handles.
+    saved_nodes: Vec<SavedNode>,
+    tracks: Vec<RemovedTrack>,
+}
+
+impl DeleteNodeCommand {
+    fn execute(&mut self, scene: &mut Scene) -> Result<(), &'static str> {
+        if !scene.graph.is_valid_handle(self.handle) {
+            return Err("node does not exist");
+        }
+        if self.handle == scene.graph.get_root() {
+            return Err("root node cannot be deleted");
+        }
+        self.parent = scene.graph[self.handle].parent();
+        self.index = child_index(&scene.graph, self.handle);
+
+        // Tracks must be removed too, otherwise animations will try to apply poses to
+        // deleted nodes.
+        let deleted = scene
+            .graph
+            .traverse_handle_iter(self.handle)
+            .collect::<HashSet<_>>();
+        for (animation_handle, animation) in scene.animations.pair_iter_mut() {
+            for track in animation.get_tracks() {
+                if deleted.contains(&track.get_node()) {
+                    self.tracks.push(RemovedTrack {
+                        animation: animation_handle,
+                        track: track.clone(),
+                    });
+                }
+            }
+            animation.retain_tracks(|track| !deleted.contains(&track.get_node()));
+        }
+
+        self.sub_graph = Some(scene.graph.take_reserve_sub_graph(self.handle));
+        Ok(())
+    }
+
+    fn revert(&mut self, scene: &mut Scene, map: &mut HandleMap) {
+        if let Some(sub_graph) = self.sub_graph.take() {
+            let handle = scene.graph.put_sub_graph_back(sub_graph);
+            link_at(&mut scene.graph, handle, self.parent, self.index);
+        } else if !self.saved_nodes.is_empty() {
+            // Command was loaded, handles of nodes were not reserved, so nodes are added
+            // at new handles and links between them are restored by the map.
+            let mut links = Vec::with_capacity(self.saved_nodes.len());
+            for saved in self.saved_nodes.drain(..) {
+                let mut node = saved.node;
+                let children = std::mem::take(&mut node.children);
+                node.parent = Handle::NONE;
+                let handle = scene.graph.add_node(node);
+                map.insert(saved.handle, handle);
+                links.push((handle, children));
+            }
+            for (handle, children) in links.iter() {
+                for child in children {
+                    if let Some(&child) = map.get(child) {
+                        scene.graph.link_nodes(child, *handle);
+                    }
+                }
+            }
+            self.handle = map[&self.handle];
+            link_at(&mut scene.graph, self.handle, self.parent, self.index);
+        }
+
+        for mut removed in self.tracks.drain(..) {
+            if scene.animations.is_valid_handle(removed.animation) {
+                let node = removed.track.get_node();
+                removed
+                    .track
+                    .set_node(map.get(&node).copied().unwrap_or(node));
+                scene
+                    .animations
+                    .get_mut(removed.animation)
+                    .add_track(removed.track);
+            }
+        }
+    }
+
+    fn finalize(&mut self, scene: &mut Scene) {
+        if let Some(sub_graph) = self.sub_graph.take() {
+            scene.graph.forget_sub_graph(sub_graph);
+        }
+        self.saved_nodes.clear();
+        self.tracks.clear();
+    }
+
+    fn remap(&mut self, map: &HandleMap) {
+        remap_handle(&mut self.handle, map);
+        remap_handle(&mut self.parent, map);
+        for saved in self.saved_nodes.iter_mut() {
+            remap_handle(&mut saved.handle, map);
+            remap_handle(&mut saved.node.parent, map);
+            for child in saved.node.children.iter_mut() {
+                remap_handle(child, map);
+            }
+        }
+        for removed in self.tracks.iter_mut() {
+            let mut node = removed.track.get_node();
+            remap_handle(&mut node, map);
+            removed.track.set_node(node);
+        }
+    }
+}
+
+impl Visit for DeleteNodeCommand {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.handle.visit("Handle", visitor)?;
+        self.parent.visit("Parent", visitor)?;
+        visit_index(&mut self.index, "Index", visitor)?;
+
+        // Extracted nodes are saved with handles they had, tickets cannot be saved.
+        if !visitor.is_reading() {
+            if let Some(sub_graph) = self.sub_graph.as_ref() {
+                self.saved_nodes.push(SavedNode {
+                    handle: self.handle,
+                    node: sub_graph.root.1.clone(),
+                });
+                for (handle, (_, node)) in sub_graph
+                    .descendant_handles()
+                    .into_iter()
+                    .zip(sub_graph.descendants.iter())
+                {
+                    self.saved_nodes.push(SavedNode {
+                        handle,
+                        node: node.clone(),
+                    });
+                }
+            }
+        }
+        let result = self.saved_nodes.visit("Nodes", visitor);
+        if !visitor.is_reading() && self.sub_graph.is_some() {
+            self.saved_nodes.clear();
+        }
+        result?;
+
+        self.tracks.visit("Tracks", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Changes parent of a node.
+#[derive(Debug, Default)]
+pub struct LinkNodesCommand {
+    child: Handle<Node>,
+    parent: Handle<Node>,
+    index: usize,
+}
+
+impl LinkNodesCommand {
+    fn execute(&mut self, scene: &mut Scene) -> Result<(), &'static str> {
+        let graph = &mut scene.graph;
+        if !graph.is_valid_handle(self.child) || !graph.is_valid_handle(self.parent) {
+            return Err("node does not exist");
+        }
+        if self.child == graph.get_root() {
+            return Err("root node cannot be re-linked");
+        }
+        // Node cannot be linked with its own descendant, it would create a cycle.
+        let mut ancestor = self.parent;
+        while ancestor.is_some() {
+            if ancestor == self.child {
+                return Err("node cannot be linked with its descendant");
+            }
+            ancestor = graph[ancestor].parent();
+        }
+        self.swap(graph);
+        Ok(())
+    }
+
+    fn swap(&mut self, graph: &mut Graph) {
+        let old_parent = graph[self.child].parent();
+        let old_index = child_index(graph, self.child);
+        if self.index == std::usize::MAX {
+            graph.link_nodes(self.child, self.parent);
+        } else {
+            link_at(graph, self.child, self.parent, self.index);
+        }
+        self.parent = old_parent;
+        self.index = old_index;
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        self.swap(&mut scene.graph)
+    }
+
+    fn remap(&mut self, map: &HandleMap) {
+        remap_handle(&mut self.child, map);
+        remap_handle(&mut self.parent, map);
+    }
+}
+
+impl Visit for LinkNodesCommand {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.child.visit("Child", visitor)?;
+        self.parent.visit("Parent", visitor)?;
+        visit_index(&mut self.index, "Index", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Property of a node which can be changed by `SetPropertyCommand`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeProperty {
+    /// Name of node.
+    Name(String),
+    /// Local position.
+    Position(Vec3),
+    /// Local rotation.
+    Rotation(Quat),
+    /// Local scale.
+    Scale(Vec3),
+    /// Local visibility.
+    Visibility(bool),
+    /// Render layer.
+    Layer(u32),
+}
+
+impl Default for NodeProperty {
+    fn default() -> Self {
+        NodeProperty::Visibility(true)
+    }
+}
+
+impl NodeProperty {
+    fn id(&self) -> u32 {
+        match self {
+            NodeProperty::Name(_) => 0,
+            NodeProperty::Position(_) => 1,
+            NodeProperty::Rotation(_) => 2,
+            NodeProperty::Scale(_) => 3,
+            NodeProperty::Visibility(_) => 4,
+            NodeProperty::Layer(_) => 5,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(NodeProperty::Name(Default::default())),
+            1 => Ok(NodeProperty::Position(Default::default())),
+            2 => Ok(NodeProperty::Rotation(Default::default())),
+            3 => Ok(NodeProperty::Scale(Default::default())),
+            4 => Ok(NodeProperty::Visibility(Default::default())),
+            5 => Ok(NodeProperty::Layer(Default::default())),
+            _ => Err(format!("Invalid node property id {}", id)),
+        }
+    }
+
+    /// Writes value into node and returns previous value.
+    fn apply(self, node: &mut Node) -> Self {
+        match self {
+            NodeProperty::Name(name) => {
+                let old = node.name().to_owned();
+                node.set_name(name);
+                NodeProperty::Name(old)
+            }
+            NodeProperty::Position(position) => {
+                let old = node.local_transform().position();
+                node.local_transform_mut().set_position(position);
+                NodeProperty::Position(old)
+            }
+            NodeProperty::Rotation(rotation) => {
+                let old = node.local_transform().rotation();
+                node.local_transform_mut().set_rotation(rotation);
+                NodeProperty::Rotation(old)
+            }
+            NodeProperty::Scale(scale) => {
+                let old = node.local_transform().scale();
+                node.local_transform_mut().set_scale(scale);
+                NodeProperty::Scale(old)
+            }
+            NodeProperty::Visibility(visibility) => {
+                let old = node.visibility();
+                node.set_visibility(visibility);
+                NodeProperty::Visibility(old)
+            }
+            NodeProperty::Layer(layer) => {
+                let old = node.layer();
+                node.set_layer(layer);
+                NodeProperty::Layer(old)
+            }
+        }
+    }
+}
+
+impl Visit for NodeProperty {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = Self::from_id(id)?;
+        }
+
+        match self {
+            NodeProperty::Name(v) => v.visit("Value", visitor)?,
+            NodeProperty::Position(v) => v.visit("Value", visitor)?,
+            NodeProperty::Rotation(v) => v.visit("Value", visitor)?,
+            NodeProperty::Scale(v) => v.visit("Value", visitor)?,
+            NodeProperty::Visibility(v) => v.visit("Value", visitor)?,
+            NodeProperty::Layer(v) => v.visit("Value", visitor)?,
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Changes single property of a node. Execution and revert are the same operation - swap
+/// of stored value with the value in node.
+#[derive(Debug, Default)]
+pub struct SetPropertyCommand {
+    node: Handle<Node>,
+    value: NodeProperty,
+}
+
+impl SetPropertyCommand {
+    fn swap(&mut self, scene: &mut Scene) -> Result<(), &'static str> {
+        if !scene.graph.is_valid_handle(self.node) {
+            return Err("node does not exist");
+        }
+        let value = std::mem::take(&mut self.value);
+        self.value = value.apply(&mut scene.graph[self.node]);
+        Ok(())
+    }
+}
+
+impl Visit for SetPropertyCommand {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.value.visit("Value", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Debug)]
+pub enum SceneCommand {
+    /// See `AddNodeCommand` docs.
+    AddNode(AddNodeCommand),
+    /// See `DeleteNodeCommand` docs.
+    DeleteNode(DeleteNodeCommand),
+    /// See `LinkNodesCommand` docs.
+    LinkNodes(LinkNodesCommand),
+    /// See `SetPropertyCommand` docs.
+    SetProperty(SetPropertyCommand),
+}
+
+impl Default for SceneCommand {
+    fn default() -> Self {
+        SceneCommand::SetProperty(Default::default())
+    }
+}
+
+impl SceneCommand {
+    /// Creates command that adds given node to the graph and links it with given parent.
+    pub fn add_node(node: Node, parent: Handle<Node>) -> Self {
+        SceneCommand::AddNode(AddNodeCommand {
+            parent,
+            node: Some(node),
+            handle: Handle::NONE,
+            ticket: None,
+        })
+    }
+
+    /// Creates command that deletes given node with all its descendants.
+    pub fn delete_node(node: Handle<Node>) -> Self {
+        SceneCommand::DeleteNode(DeleteNodeCommand {
+            handle: node,
+            ..Default::default()
+        })
+    }
+
+    /// Creates command that links given child with new parent.
+    pub fn link_nodes(child: Handle<Node>, parent: Handle<Node>) -> Self {
+        SceneCommand::LinkNodes(LinkNodesCommand {
+            child,
+            parent,
+            index: std::usize::MAX,
+        })
+    }
+
+    /// Creates command that sets new value of a property of given node.
+    pub fn set_property(node: Handle<Node>, value: NodeProperty) -> Self {
+        SceneCommand::SetProperty(SetPropertyCommand { node, value })
+    }
+
+    /// Returns human-readable name of command, can be used to show history in editor.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SceneCommand::AddNode(_) => "Add Node",
+            SceneCommand::DeleteNode(_) => "Delete Node",
+            SceneCommand::LinkNodes(_) => "Link Nodes",
+            SceneCommand::SetProperty(command) => match command.value {
+                NodeProperty::Name(_) => "Set Name",
+                NodeProperty::Position(_) => "Set Position",
+                NodeProperty::Rotation(_) => "Set Rotation",
+                NodeProperty::Scale(_) => "Set Scale",
+                NodeProperty::Visibility(_) => "Set Visibility",
+                NodeProperty::Layer(_) => "Set Layer",
+            },
+        }
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            SceneCommand::AddNode(_) => 0,
+            SceneCommand::DeleteNode(_) => 1,
+            SceneCommand::LinkNodes(_) => 2,
+            SceneCommand::SetProperty(_) => 3,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(SceneCommand::AddNode(Default::default())),
+            1 => Ok(SceneCommand::DeleteNode(Default::default())),
+            2 => Ok(SceneCommand::LinkNodes(Default::default())),
+            3 => Ok(SceneCommand::SetProperty(Default::default())),
+            _ => Err(format!("Invalid scene command id {}", id)),
+        }
+    }
+
+    /// Executes command. Scene stays unchanged if command has failed.
+    pub fn execute(&mut self, scene: &mut Scene) -> Result<(), &'static str> {
+        self.execute_internal(scene, &mut Default::default())
+    }
+
+    fn execute_internal(
+        &mut self,
+        scene: &mut Scene,
+        map: &mut HandleMap,
+    ) -> Result<(), &'static str> {
+        match self {
+            SceneCommand::AddNode(command) => command.execute(scene, map),
+            SceneCommand::DeleteNode(command) => command.execute(scene),
+            SceneCommand::LinkNodes(command) => command.execute(scene),
+            SceneCommand::SetProperty(command) => command.swap(scene),
+        }
+    }
+
+    /// Reverts changes made by `execute`. Must be called only after successful execution.
+    pub fn revert(&mut self, scene: &mut Scene) {
+        self.revert_internal(scene, &mut Default::default())
+    }
+
+    fn revert_internal(&mut self, scene: &mut Scene, map: &mut HandleMap) {
+        match self {
+            SceneCommand::AddNode(command) => command.revert(scene),
+            SceneCommand::DeleteNode(command) => command.revert(scene, map),
+            SceneCommand::LinkNodes(command) => command.revert(scene),
+            SceneCommand::SetProperty(command) => {
+                let _ = command.swap(scene);
+            }
+        }
+    }
+
+    /// Replaces old handles of nodes with new ones.
+    fn remap(&mut self, map: &HandleMap) {
+        match self {
+            SceneCommand::AddNode(command) => command.remap(map),
+            SceneCommand::DeleteNode(command) => command.remap(map),
+            SceneCommand::LinkNodes(command) => command.remap(map),
+            SceneCommand::SetProperty(command) => remap_handle(&mut command.node, map),
+        }
+    }
+
+    /// Releases handles reserved by command. Must be called when command is no longer
+    /// needed, command cannot be executed or reverted after that.
+    pub fn finalize(&mut self, scene: &mut Scene) {
+        match self {
+            SceneCommand::AddNode(command) => command.finalize(scene),
+            SceneCommand::DeleteNode(command) => command.finalize(scene),
+            SceneCommand::LinkNodes(_) | SceneCommand::SetProperty(_) => (),
+        }
+    }
+}
+
+impl Visit for SceneCommand {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = Self::from_id(id)?;
+        }
+
+        match self {
+            SceneCommand::AddNode(command) => command.visit("Command", visitor)?,
+            SceneCommand::DeleteNode(command) => command.visit("Command", visitor)?,
+            SceneCommand::LinkNodes(command) => command.visit("Command", visitor)?,
+            SceneCommand::SetProperty(command) => command.visit("Command", visitor)?,
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// History of executed commands with undo/redo. Stack must be used with the same scene
+/// all the time.
+#[derive(Debug)]
+pub struct CommandStack {
+    commands: Vec<SceneCommand>,
+    top: usize,
+    max_len: Option<usize>,
+}
+
+impl CommandStack {
+    /// Creates new empty stack. If `max_len` is set, oldest commands are dropped when stack
+    /// becomes longer than `max_len`.
+    pub fn new(max_len: Option<usize>) -> Self {
+        Self {
+            commands: Default::default(),
+            top: 0,
+            max_len,
+        }
+    }
+
+    /// Executes command and puts it on top of stack. Every undone command is dropped, so
+    /// they cannot be redone anymore.
+    pub fn do_command(
+        &mut self,
+        mut command: SceneCommand,
+        scene: &mut Scene,
+    ) -> Result<(), &'static str> {
+        command.execute(scene)?;
+
+        for mut dropped in self.commands.drain(self.top..) {
+            dropped.finalize(scene);
+        }
+        self.commands.push(command);
+        self.top = self.commands.len();
+
+        if let Some(max_len) = self.max_len {
+            if self.commands.len() > max_len {
+                let excess = self.commands.len() - max_len;
+                for mut dropped in self.commands.drain(..excess) {
+                    dropped.finalize(scene);
+                }
+                self.top = self.commands.len();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reverts last executed command. Returns false if there is nothing to undo.
+    pub fn undo(&mut self, scene: &mut Scene) -> bool {
+        if self.top > 0 {
+            self.top -= 1;
+            let mut map = HandleMap::new();
+            self.commands[self.top].revert_internal(scene, &mut map);
+            self.remap(&map);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Executes last undone command again. Returns false if there is nothing to redo.
+    pub fn redo(&mut self, scene: &mut Scene) -> bool {
+        if self.top < self.commands.len() {
+            let mut map = HandleMap::new();
+            if self.commands[self.top]
+                .execute_internal(scene, &mut map)
+                .is_ok()
+            {
+                self.top += 1;
+                self.remap(&map);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Nodes of loaded commands are put back at new handles, every command must use them.
+    fn remap(&mut self, map: &HandleMap) {
+        if !map.is_empty() {
+            for command in self.commands.iter_mut() {
+                command.remap(map);
+            }
+        }
+    }
+
+    /// Returns true if there is a command to undo.
+    pub fn can_undo(&self) -> bool {
+        self.top > 0
+    }
+
+    /// Returns true if there is a command to redo.
+    pub fn can_redo(&self) -> bool {
+        self.top < self.commands.len()
+    }
+
+    /// Returns every command in stack, first `executed_count` of them are executed and
+    /// the rest are undone.
+    pub fn commands(&self) -> &[SceneCommand] {
+        &self.commands
+    }
+
+    /// Returns amount of executed commands.
+    pub fn executed_count(&self) -> usize {
+        self.top
+    }
+
+    /// Drops every command. Changes made by commands are kept.
+    pub fn clear(&mut self, scene: &mut Scene) {
+        for mut command in self.commands.drain(..) {
+            command.finalize(scene);
+        }
+        self.top = 0;
+    }
+}
+
+impl Visit for CommandStack {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.commands.visit("Commands", visitor)?;
+        let mut top = self.top as u32;
+        top.visit("Top", visitor)?;
+        let mut max_len = self.max_len.map(|max_len| max_len as u32);
+        max_len.visit("MaxLen", visitor)?;
+
+        visitor.leave_region()?;
+
+        if visitor.is_reading() {
+            if top as usize > self.commands.len() {
+                return Err(format!(
+                    "Command stack has {} commands, but {} are executed",
+                    self.commands.len(),
+                    top
+                )
+                .into());
+            }
+            self.top = top as usize;
+            self.max_len = max_len.map(|max_len| max_len as usize);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::{Animation, KeyFrame, Track},
+        core::{
+            math::{quat::Quat, vec3::Vec3},
+            visitor::{Visit, Visitor},
+        },
+        scene::{
+            base::BaseBuilder,
+            command::{CommandStack, NodeProperty, SceneCommand},
+            Scene,
+        },
+    };
+
+    #[test]
+    fn command_stack_test() {
+        let mut scene = Scene::new();
+        let root = scene.graph.get_root();
+        let mut stack = CommandStack::new(None);
+
+        let a = BaseBuilder::new().with_name("A").build_node();
+        stack
+            .do_command(SceneCommand::add_node(a, root), &mut scene)
+            .unwrap();
+        let a = scene.graph.find_by_name_from_root("A");
+
+        let b = BaseBuilder::new().with_name("B").build_node();
+        stack
+            .do_command(SceneCommand::add_node(b, root), &mut scene)
+            .unwrap();
+        let b = scene.graph.find_by_name_from_root("B");
+
+        stack
+            .do_command(SceneCommand::link_nodes(b, a), &mut scene)
+            .unwrap();
+        assert_eq!(scene.graph[b].parent(), a);
+
+        // Cycles are not allowed.
+        assert!(stack
+            .do_command(SceneCommand::link_nodes(a, b), &mut scene)
+            .is_err());
+
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        stack
+            .do_command(
+                SceneCommand::set_property(b, NodeProperty::Position(position)),
+                &mut scene,
+            )
+            .unwrap();
+        assert_eq!(scene.graph[b].local_transform().position(), position);
+
+        stack
+            .do_command(SceneCommand::delete_node(a), &mut scene)
+            .unwrap();
+        assert!(!scene.graph.is_valid_handle(a));
+        assert!(!scene.graph.is_valid_handle(b));
+
+        // Undo deletion - handles must be the same.
+        assert!(stack.undo(&mut scene));
+        assert!(scene.graph.is_valid_handle(a));
+        assert_eq!(scene.graph[b].parent(), a);
+
+        assert!(stack.undo(&mut scene));
+        assert_eq!(scene.graph[b].local_transform().position(), Vec3::ZERO);
+
+        assert!(stack.undo(&mut scene));
+        assert_eq!(scene.graph[b].parent(), root);
+
+        assert!(stack.undo(&mut scene));
+        assert!(stack.undo(&mut scene));
+        assert!(!stack.undo(&mut scene));
+        assert!(!scene.graph.is_valid_handle(a));
+
+        // Redo everything back, handles must be the same.
+        while stack.redo(&mut scene) {}
+        assert!(!scene.graph.is_valid_handle(a));
+        assert!(stack.undo(&mut scene));
+        assert_eq!(scene.graph[b].parent(), a);
+        assert_eq!(scene.graph[b].local_transform().position(), position);
+
+        stack.clear(&mut scene);
+        assert!(!stack.can_undo());
+        assert!(!stack.can_redo());
+    }
+
+    #[test]
+    fn command_stack_save_load_test() {
+        let mut scene = Scene::new();
+        let root = scene.graph.get_root();
+        let mut stack = CommandStack::new(Some(10));
+
+        let a = BaseBuilder::new().with_name("A").build_node();
+        stack
+            .do_command(SceneCommand::add_node(a, root), &mut scene)
+            .unwrap();
+        let a = scene.graph.find_by_name_from_root("A");
+        let b = BaseBuilder::new().with_name("B").build_node();
+        stack
+            .do_command(SceneCommand::add_node(b, a), &mut scene)
+            .unwrap();
+        let b = scene.graph.find_by_name_from_root("B");
+
+        let mut track = Track::new();
+        track.set_node(b);
+        track.add_key_frame(KeyFrame::new(1.0, Vec3::UNIT, Vec3::UNIT, Quat::IDENTITY));
+        let mut animation = Animation::default();
+        animation.add_track(track);
+        let animation = scene.animations.add(animation);
+
+        // Tracks of deleted nodes must be removed and put back on undo.
+        stack
+            .do_command(SceneCommand::delete_node(a), &mut scene)
+            .unwrap();
+        assert!(scene.animations.get(animation).get_tracks().is_empty());
+        assert!(stack.undo(&mut scene));
+        assert_eq!(
+            scene.animations.get(animation).get_tracks()[0].get_node(),
+            b
+        );
+        assert!(stack.redo(&mut scene));
+
+        let path = std::env::temp_dir().join(format!(
+            "rg3d_command_stack_save_load_{}.bin",
+            std::process::id()
+        ));
+        let mut visitor = Visitor::new();
+        scene.visit("Scene", &mut visitor).unwrap();
+        stack.visit("CommandStack", &mut visitor).unwrap();
+        visitor.save_binary(&path).unwrap();
+
+        let mut visitor = Visitor::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut scene = Scene::default();
+        scene.visit("Scene", &mut visitor).unwrap();
+        let mut stack = CommandStack::new(None);
+        stack.visit("CommandStack", &mut visitor).unwrap();
+        assert_eq!(stack.executed_count(), 3);
+
+        // Deleted nodes are put back from saved command.
+        assert!(stack.undo(&mut scene));
+        let a = scene.graph.find_by_name_from_root("A");
+        let b = scene.graph.find_by_name_from_root("B");
+        assert_eq!(scene.graph[b].parent(), a);
+        let tracks = scene.animations.get(animation).get_tracks();
+        assert_eq!(tracks[0].get_node(), b);
+        assert_eq!(tracks[0].get_key_frames().len(), 1);
+
+        // Other commands use new handles.
+        assert!(stack.undo(&mut scene));
+        assert!(!scene.graph.is_valid_handle(b));
+        assert!(stack.undo(&mut scene));
+        assert!(!scene.graph.is_valid_handle(a));
+        while stack.redo(&mut scene) {}
+        assert_eq!(stack.executed_count(), 3);
+        assert!(scene.graph.find_by_name_from_root("A").is_none());
+    }
+}
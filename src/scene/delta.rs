@@ -0,0 +1,420 @@
+//! Contains all structures and methods to create and apply scene deltas.
+//!
+//! Scene delta is a set of differences between a runtime scene and its source scene
+//! (usually a level loaded from an asset). Delta contains only new nodes, changed
+//! properties of existing nodes and removed nodes, so save files made of deltas are
+//! tiny compared to full scene saves. To load such save, load source scene first and
+//! then apply the delta to it.
+//!
+//! # Node matching
+//!
+//! Nodes are matched by their paths - names of every ancestor node from root separated
+//! by `/`, siblings with same name are distinguished by their order with `[n]` suffix
+//! (the first one has no suffix). Characters `\`, `/`, `[` and `]` in names are escaped
+//! with `\`, so a suffix never collides with a node that is literally named `x[1]`.
+//! Handles are not used for matching, so delta will still be applicable after source
+//! asset was changed, as long as names of affected nodes were kept. Changes for nodes
+//! which cannot be found are skipped.
+//!
+//! # Limitations
+//!
+//! For existing nodes only local transform (position, rotation, scale) and visibility
+//! are tracked. If some other property of a node was changed, remove the node and add
+//! its copy instead. Animations and physics are not included in delta.
+
+use crate::{
+    core::{
+        math::{quat::Quat, vec3::Vec3},
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    engine::resource_manager::ResourceManager,
+    scene::{node::Node, Scene},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// Changed properties of existing node. Properties which were not changed are None.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeDelta {
+    /// Path of the node.
+    pub path: String,
+    /// New local position.
+    pub position: Option<Vec3>,
+    /// New local rotation.
+    pub rotation: Option<Quat>,
+    /// New local scale.
+    pub scale: Option<Vec3>,
+    /// New local visibility.
+    pub visibility: Option<bool>,
+}
+
+impl NodeDelta {
+    fn is_empty(&self) -> bool {
+        self.position.is_none()
+            && self.rotation.is_none()
+            && self.scale.is_none()
+            && self.visibility.is_none()
+    }
+}
+
+impl Visit for NodeDelta {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.path.visit("Path", visitor)?;
+        self.position.visit("Position", visitor)?;
+        self.rotation.visit("Rotation", visitor)?;
+        self.scale.visit("Scale", visitor)?;
+        self.visibility.visit("Visibility", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Node which is not present in source scene.
+#[derive(Clone, Debug, Default)]
+pub struct NewNode {
+    /// Path of parent node. Parent can be a new node too.
+    pub parent_path: String,
+    /// Copy of the node.
+    pub node: Node,
+}
+
+impl Visit for NewNode {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.parent_path.visit("ParentPath", visitor)?;
+        self.node.visit("Node", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct SceneDelta {
+    changed: Vec<NodeDelta>,
+    added: Vec<NewNode>,
+    removed: Vec<String>,
+}
+
+impl Visit for SceneDelta {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.changed.visit("Changed", visitor)?;
+        self.added.visit("Added", visitor)?;
+        self.removed.visit("Removed", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Escapes characters that have special meaning in paths.
+fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if let '\\' | '/' | '[' | ']' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Makes path of a child with given name, `index` is index of the child among siblings
+/// with the same name.
+fn child_path(parent_path: &str, name: &str, index: usize) -> String {
+    let mut path = if parent_path.is_empty() {
+        escape_name(name)
+    } else {
+        format!("{}/{}", parent_path, escape_name(name))
+    };
+    if index > 0 {
+        path += &format!("[{}]", index);
+    }
+    path
+}
+
+/// Returns paths of every node of scene (except root), parents are always before their
+/// children.
+pub(in crate) fn node_paths(scene: &Scene) -> Vec<(String, Handle<Node>)> {
+    let mut paths = Vec::new();
+    let mut stack = vec![(String::new(), scene.graph.get_root(), 0)];
+    while let Some((path, handle, depth)) = stack.pop() {
+        let mut name_counters = HashMap::new();
+        let mut children = Vec::new();
+        for &child in scene.graph[handle].children() {
+            let name = scene.graph[child].name();
+            let counter = name_counters.entry(name).or_insert(0);
+            children.push((child_path(&path, name, *counter), child));
+            *counter += 1;
+        }
+        // Reverse to keep order of children.
+        for (child_path, child) in children.into_iter().rev() {
+            paths.push((depth, child_path.clone(), child));
+            stack.push((child_path, child, depth + 1));
+        }
+    }
+    // Parents are always before their children, but siblings of different parents
+    // may be interleaved, sort by depth to make order independent of that.
+    paths.sort_by_key(|(depth, _, _)| *depth);
+    paths
+        .into_iter()
+        .map(|(_, path, handle)| (path, handle))
+        .collect()
+}
+
+impl SceneDelta {
+    /// Creates delta between source scene and current (runtime) scene.
+    pub fn new(base: &Scene, current: &Scene) -> Self {
+        let base_paths = node_paths(base);
+        let base_map = base_paths.iter().cloned().collect::<HashMap<_, _>>();
+        let base_handle_map = base_paths
+            .iter()
+            .map(|(path, handle)| (*handle, path.as_str()))
+            .collect::<HashMap<_, _>>();
+        let current_paths = node_paths(current);
+        let current_map = current_paths
+            .iter()
+            .map(|(path, handle)| (*handle, path.clone()))
+            .collect::<HashMap<_, _>>();
+        let current_path_set = current_paths
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<HashSet<_>>();
+
+        let mut delta = Self::default();
+
+        for (path, handle) in current_paths.iter() {
+            let node = &current.graph[*handle];
+            if let Some(base_handle) = base_map.get(path) {
+                let base_node = &base.graph[*base_handle];
+                let transform = node.local_transform();
+                let base_transform = base_node.local_transform();
+                let node_delta = NodeDelta {
+                    path: path.clone(),
+                    position: Some(transform.position())
+                        .filter(|p| *p != base_transform.position()),
+                    rotation: Some(transform.rotation())
+                        .filter(|r| *r != base_transform.rotation()),
+                    scale: Some(transform.scale()).filter(|s| *s != base_transform.scale()),
+                    visibility: Some(node.visibility()).filter(|v| *v != base_node.visibility()),
+                };
+                if !node_delta.is_empty() {
+                    delta.changed.push(node_delta);
+                }
+            } else {
+                delta.added.push(NewNode {
+                    parent_path: current_map.get(&node.parent()).cloned().unwrap_or_default(),
+                    node: node.clone(),
+                });
+            }
+        }
+
+        let mut removed = HashSet::new();
+        for (path, handle) in base_paths.iter() {
+            if !current_path_set.contains(path.as_str()) {
+                removed.insert(path.as_str());
+                // Removal of a node removes its descendants too.
+                let parent = base.graph[*handle].parent();
+                let parent_removed = base_handle_map
+                    .get(&parent)
+                    .map_or(false, |parent_path| removed.contains(parent_path));
+                if !parent_removed {
+                    delta.removed.push(path.clone());
+                }
+            }
+        }
+
+        delta
+    }
+
+    /// Applies delta to given scene, which must be the source scene (or its updated
+    /// version) that was used to create the delta. Returns amount of changes which
+    /// were skipped because corresponding nodes were not found. Resource manager is
+    /// used to restore resources (models, textures) of new nodes.
+    pub fn apply(&self, scene: &mut Scene, resource_manager: &mut ResourceManager) -> usize {
+        // Paths must be resolved before any modification, because removal of a node
+        // changes paths of its siblings with same name.
+        let mut paths = node_paths(scene).into_iter().collect::<HashMap<_, _>>();
+        let mut skipped = 0;
+
+        for node_delta in self.changed.iter() {
+            if let Some(&handle) = paths.get(&node_delta.path) {
+                let node = &mut scene.graph[handle];
+                let transform = node.local_transform_mut();
+                if let Some(position) = node_delta.position {
+                    transform.set_position(position);
+                }
+                if let Some(rotation) = node_delta.rotation {
+                    transform.set_rotation(rotation);
+                }
+                if let Some(scale) = node_delta.scale {
+                    transform.set_scale(scale);
+                }
+                if let Some(visibility) = node_delta.visibility {
+                    node.set_visibility(visibility);
+                }
+            } else {
+                skipped += 1;
+            }
+        }
+
+        let removed = self
+            .removed
+            .iter()
+            .filter_map(|path| {
+                let handle = paths.get(path).copied();
+                if handle.is_none() {
+                    skipped += 1;
+                }
+                handle
+            })
+            .collect::<Vec<_>>();
+
+        for new_node in self.added.iter() {
+            let parent = if new_node.parent_path.is_empty() {
+                scene.graph.get_root()
+            } else if let Some(&parent) = paths.get(&new_node.parent_path) {
+                parent
+            } else {
+                skipped += 1;
+                continue;
+            };
+            let mut node = new_node.node.clone();
+            node.restore_resources(resource_manager);
+            let handle = scene.graph.add_node(node);
+            scene.graph.link_nodes(handle, parent);
+            // Keep the same naming as in `node_paths`, so children of new nodes
+            // will find their parents.
+            let name = new_node.node.name();
+            let mut index = 0;
+            let mut path = child_path(&new_node.parent_path, name, index);
+            while paths.contains_key(&path) {
+                index += 1;
+                path = child_path(&new_node.parent_path, name, index);
+            }
+            paths.insert(path, handle);
+        }
+
+        for handle in removed {
+            if scene.graph.is_valid_handle(handle) {
+                scene.remove_node(handle);
+            }
+        }
+
+        // New nodes may be instances of models, so they must be resolved.
+        scene.resolve();
+
+        skipped
+    }
+
+    /// Returns true if there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Returns changed properties of existing nodes.
+    pub fn changed(&self) -> &[NodeDelta] {
+        &self.changed
+    }
+
+    /// Returns nodes which are not present in source scene.
+    pub fn added(&self) -> &[NewNode] {
+        &self.added
+    }
+
+    /// Returns paths of nodes which were removed from source scene.
+    pub fn removed(&self) -> &[String] {
+        &self.removed
+    }
+
+    /// Saves delta to a file.
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> VisitResult {
+        let mut visitor = Visitor::new();
+        self.visit("SceneDelta", &mut visitor)?;
+        visitor.save_binary(path)
+    }
+
+    /// Loads delta from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, crate::core::visitor::VisitError> {
+        let mut delta = Self::default();
+        let mut visitor = Visitor::load_binary(path.as_ref())?;
+        delta.visit("SceneDelta", &mut visitor)?;
+        Ok(delta)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{
+            base::BaseBuilder,
+            delta::{node_paths, SceneDelta},
+            Scene,
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn scene_delta_test() {
+        let mut base = Scene::new();
+        let a = base
+            .graph
+            .add_node(BaseBuilder::new().with_name("A").build_node());
+        let b = base
+            .graph
+            .add_node(BaseBuilder::new().with_name("B").build_node());
+        base.graph.link_nodes(b, a);
+        base.graph
+            .add_node(BaseBuilder::new().with_name("C").build_node());
+
+        let mut current = base.clone(&mut |_, _| true);
+        let current_b = current.graph.find_by_name_from_root("B");
+        current.graph[current_b]
+            .local_transform_mut()
+            .set_position(Vec3::new(1.0, 2.0, 3.0));
+        let current_c = current.graph.find_by_name_from_root("C");
+        current.remove_node(current_c);
+        let d = current
+            .graph
+            .add_node(BaseBuilder::new().with_name("D").build_node());
+        current.graph.link_nodes(d, current_b);
+
+        let delta = SceneDelta::new(&base, &current);
+        assert_eq!(delta.changed().len(), 1);
+        assert_eq!(delta.changed()[0].path, "A/B");
+        assert_eq!(delta.added().len(), 1);
+        assert_eq!(delta.added()[0].parent_path, "A/B");
+        assert_eq!(delta.removed(), &["C".to_owned()]);
+
+        assert!(SceneDelta::new(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn node_paths_are_unambiguous() {
+        let mut scene = Scene::new();
+        let mut add = |name: &str| {
+            scene
+                .graph
+                .add_node(BaseBuilder::new().with_name(name).build_node())
+        };
+        let first = add("x");
+        let second = add("x");
+        let literal = add("x[1]");
+        let slash = add("a/b");
+
+        let paths = node_paths(&scene).into_iter().collect::<HashMap<_, _>>();
+        assert_eq!(paths.len(), 4);
+        assert_eq!(paths["x"], first);
+        assert_eq!(paths["x[1]"], second);
+        assert_eq!(paths["x\\[1\\]"], literal);
+        assert_eq!(paths["a\\/b"], slash);
+    }
+}
@@ -0,0 +1,535 @@
+//! Structured comparison of two scenes.
+//!
+//! Scene diff lists nodes that were added or removed and properties that were changed
+//! between two versions of a scene or a model (use [`Model::get_scene`] for models). It
+//! is meant for asset pipelines: review of level changes, validation that automated
+//! processing (optimization, lightmap baking, re-export) didn't touch anything it
+//! shouldn't. Nodes are matched by paths in the same way as in
+//! [delta](../delta/index.html) module.
+//!
+//! Unlike [`SceneDelta`](../delta/struct.SceneDelta.html), diff is not applicable to a
+//! scene, it only describes differences and can be printed as a human-readable report.
+//!
+//! # Coverage
+//!
+//! Diff compares transform, visibility, layer and lifetime of every node, and following
+//! properties of specific nodes:
+//!
+//! - lights: color, intensity, shadows, radius and cone angles;
+//! - cameras: projection, layer mask, exposure, aspect mode, depth of field and color
+//! blindness filter;
+//! - sprites: size, color and texture;
+//! - particle systems: acceleration, texture, kinds of emitters and emission parameters
+//! of emitters;
+//! - meshes: topology, geometry, textures and materials of surfaces.
+//!
+//! Everything else is not compared: alive particles, color gradients of particle systems,
+//! custom emitters beyond their base parameters, skins of surfaces, physics bodies and
+//! other scene-level data (navmeshes, lightmaps, sound). Meshes have no own shadow
+//! settings - shadows are defined by lights, which are compared.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::scene::{diff::SceneDiff, Scene};
+//!
+//! fn review(old: &Scene, new: &Scene) {
+//!     let diff = SceneDiff::new(old, new);
+//!     if !diff.is_empty() {
+//!         println!("{}", diff);
+//!     }
+//! }
+//! ```
+//!
+//! [`Model::get_scene`]: ../../resource/model/struct.Model.html#method.get_scene
+
+use crate::{
+    renderer::surface::Surface,
+    resource::texture::Texture,
+    scene::{delta::node_paths, light::Light, node::Node, particle_system::Emitter, Scene},
+};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Change of a single property, values are formatted for display.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyChange {
+    /// Name of property, for example "Position".
+    pub property: String,
+    /// Value in old scene.
+    pub old: String,
+    /// Value in new scene.
+    pub new: String,
+}
+
+/// Change of a surface of mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SurfaceChange {
+    /// Surface with given index exists only in new scene.
+    Added(usize),
+    /// Surface with given index exists only in old scene.
+    Removed(usize),
+    /// Amount of vertices or triangles was changed.
+    Topology {
+        /// Index of surface.
+        index: usize,
+        /// Amount of vertices and triangles in old scene.
+        old: (usize, usize),
+        /// Amount of vertices and triangles in new scene.
+        new: (usize, usize),
+    },
+    /// Topology is the same, but positions, normals or texture coordinates of vertices
+    /// were changed.
+    Geometry(usize),
+    /// Property of surface (texture, material) was changed.
+    Property {
+        /// Index of surface.
+        index: usize,
+        /// Description of change.
+        change: PropertyChange,
+    },
+}
+
+/// Changes of a node which exists in both scenes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeChange {
+    /// Path of node.
+    pub path: String,
+    /// Changed properties.
+    pub properties: Vec<PropertyChange>,
+    /// Changed surfaces, only for meshes.
+    pub surfaces: Vec<SurfaceChange>,
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneDiff {
+    /// Paths of nodes which exist only in new scene.
+    pub added: Vec<String>,
+    /// Paths of nodes which exist only in old scene.
+    pub removed: Vec<String>,
+    /// Nodes which exist in both scenes, but differ.
+    pub changed: Vec<NodeChange>,
+}
+
+fn kind_name(node: &Node) -> &'static str {
+    match node {
+        Node::Base(_) => "Base",
+        Node::Light(Light::Directional(_)) => "DirectionalLight",
+        Node::Light(Light::Spot(_)) => "SpotLight",
+        Node::Light(Light::Point(_)) => "PointLight",
+        Node::Camera(_) => "Camera",
+        Node::Mesh(_) => "Mesh",
+        Node::Sprite(_) => "Sprite",
+        Node::ParticleSystem(_) => "ParticleSystem",
+    }
+}
+
+fn texture_path(texture: Option<Arc<Mutex<Texture>>>) -> Option<PathBuf> {
+    texture.map(|texture| texture.lock().unwrap().path.clone())
+}
+
+struct Comparer<'a> {
+    properties: &'a mut Vec<PropertyChange>,
+}
+
+impl<'a> Comparer<'a> {
+    fn compare<T: PartialEq + std::fmt::Debug>(&mut self, property: &str, old: T, new: T) {
+        if old != new {
+            self.properties.push(PropertyChange {
+                property: property.to_owned(),
+                old: format!("{:?}", old),
+                new: format!("{:?}", new),
+            });
+        }
+    }
+
+    /// Compares values which are not `PartialEq` by their debug representation.
+    fn compare_debug<T: std::fmt::Debug>(&mut self, property: &str, old: T, new: T) {
+        let (old, new) = (format!("{:?}", old), format!("{:?}", new));
+        if old != new {
+            self.properties.push(PropertyChange {
+                property: property.to_owned(),
+                old,
+                new,
+            });
+        }
+    }
+}
+
+fn compare_emitters(old: &[Emitter], new: &[Emitter], comparer: &mut Comparer) {
+    comparer.compare("EmitterCount", old.len(), new.len());
+    for (index, (old, new)) in old.iter().zip(new.iter()).enumerate() {
+        if old.id() != new.id() {
+            comparer.compare(&format!("Emitter{}Kind", index), old.id(), new.id());
+            continue;
+        }
+        if let Emitter::Unknown = old {
+            continue;
+        }
+        let property = |name: &str| format!("Emitter{}{}", index, name);
+        comparer.compare(&property("Position"), old.position(), new.position());
+        comparer.compare(&property("SpawnRate"), old.spawn_rate(), new.spawn_rate());
+        comparer.compare_debug(
+            &property("MaxParticles"),
+            old.max_particles(),
+            new.max_particles(),
+        );
+        comparer.compare_debug(
+            &property("LifeTimeRange"),
+            old.life_time_range(),
+            new.life_time_range(),
+        );
+        comparer.compare_debug(&property("SizeRange"), old.size_range(), new.size_range());
+        comparer.compare_debug(
+            &property("SizeModifierRange"),
+            old.size_modifier_range(),
+            new.size_modifier_range(),
+        );
+        comparer.compare_debug(
+            &property("VelocityRange"),
+            (
+                old.x_velocity_range(),
+                old.y_velocity_range(),
+                old.z_velocity_range(),
+            ),
+            (
+                new.x_velocity_range(),
+                new.y_velocity_range(),
+                new.z_velocity_range(),
+            ),
+        );
+        comparer.compare_debug(
+            &property("RotationSpeedRange"),
+            old.rotation_speed_range(),
+            new.rotation_speed_range(),
+        );
+        comparer.compare_debug(
+            &property("RotationRange"),
+            old.rotation_range(),
+            new.rotation_range(),
+        );
+        comparer.compare(
+            &property("ResurrectParticles"),
+            old.is_particles_resurrects(),
+            new.is_particles_resurrects(),
+        );
+    }
+}
+
+fn compare_surfaces(old: &[Surface], new: &[Surface], changes: &mut Vec<SurfaceChange>) {
+    for index in new.len()..old.len() {
+        changes.push(SurfaceChange::Removed(index));
+    }
+    for index in old.len()..new.len() {
+        changes.push(SurfaceChange::Added(index));
+    }
+
+    for (index, (old, new)) in old.iter().zip(new.iter()).enumerate() {
+        let old_data = old.data();
+        let new_data = new.data();
+        // Shared data can be the same instance, for example when scene was cloned.
+        if !Arc::ptr_eq(&old_data, &new_data) {
+            let old_data = old_data.lock().unwrap();
+            let new_data = new_data.lock().unwrap();
+            let old_topology = (old_data.get_vertices().len(), old_data.triangles().len());
+            let new_topology = (new_data.get_vertices().len(), new_data.triangles().len());
+            if old_topology != new_topology {
+                changes.push(SurfaceChange::Topology {
+                    index,
+                    old: old_topology,
+                    new: new_topology,
+                });
+            } else if old_data
+                .get_vertices()
+                .iter()
+                .zip(new_data.get_vertices())
+                .any(|(a, b)| {
+                    a.position != b.position || a.normal != b.normal || a.tex_coord != b.tex_coord
+                })
+                || old_data
+                    .triangles()
+                    .iter()
+                    .zip(new_data.triangles())
+                    .any(|(a, b)| a.0 != b.0)
+            {
+                changes.push(SurfaceChange::Geometry(index));
+            }
+        }
+
+        let mut properties = Vec::new();
+        let mut comparer = Comparer {
+            properties: &mut properties,
+        };
+        comparer.compare(
+            "DiffuseTexture",
+            texture_path(old.diffuse_texture()),
+            texture_path(new.diffuse_texture()),
+        );
+        comparer.compare(
+            "NormalTexture",
+            texture_path(old.normal_texture()),
+            texture_path(new.normal_texture()),
+        );
+        comparer.compare(
+            "LightmapTexture",
+            texture_path(old.lightmap_texture()),
+            texture_path(new.lightmap_texture()),
+        );
+        comparer.compare(
+            "OrmTexture",
+            texture_path(old.orm_texture()),
+            texture_path(new.orm_texture()),
+        );
+        comparer.compare("Material", old.material(), new.material());
+        changes.extend(
+            properties
+                .into_iter()
+                .map(|change| SurfaceChange::Property { index, change }),
+        );
+    }
+}
+
+fn compare_nodes(path: &str, old: &Node, new: &Node) -> NodeChange {
+    let mut change = NodeChange {
+        path: path.to_owned(),
+        ..Default::default()
+    };
+    let mut comparer = Comparer {
+        properties: &mut change.properties,
+    };
+
+    comparer.compare("Kind", kind_name(old), kind_name(new));
+    let (old_transform, new_transform) = (old.local_transform(), new.local_transform());
+    comparer.compare(
+        "Position",
+        old_transform.position(),
+        new_transform.position(),
+    );
+    comparer.compare(
+        "Rotation",
+        old_transform.rotation(),
+        new_transform.rotation(),
+    );
+    comparer.compare("Scale", old_transform.scale(), new_transform.scale());
+    comparer.compare("Visibility", old.visibility(), new.visibility());
+    comparer.compare("Layer", old.layer(), new.layer());
+    comparer.compare("Lifetime", old.lifetime(), new.lifetime());
+
+    match (old, new) {
+        (Node::Light(old), Node::Light(new)) => {
+            comparer.compare("Color", old.color(), new.color());
+            comparer.compare("Intensity", old.intensity(), new.intensity());
+            comparer.compare("CastShadows", old.is_cast_shadows(), new.is_cast_shadows());
+            match (old, new) {
+                (Light::Point(old), Light::Point(new)) => {
+                    comparer.compare("Radius", old.radius(), new.radius());
+                }
+                (Light::Spot(old), Light::Spot(new)) => {
+                    comparer.compare("Distance", old.distance(), new.distance());
+                    comparer.compare(
+                        "HotspotConeAngle",
+                        old.hotspot_cone_angle(),
+                        new.hotspot_cone_angle(),
+                    );
+                    comparer.compare(
+                        "FalloffAngleDelta",
+                        old.falloff_angle_delta(),
+                        new.falloff_angle_delta(),
+                    );
+                }
+                _ => (),
+            }
+        }
+        (Node::Camera(old), Node::Camera(new)) => {
+            comparer.compare("Fov", old.fov(), new.fov());
+            comparer.compare("ZNear", old.z_near(), new.z_near());
+            comparer.compare("ZFar", old.z_far(), new.z_far());
+            comparer.compare("Enabled", old.is_enabled(), new.is_enabled());
+            comparer.compare("LayerMask", old.layer_mask(), new.layer_mask());
+            comparer.compare("AspectMode", old.aspect_mode(), new.aspect_mode());
+            comparer.compare("Exposure", old.exposure(), new.exposure());
+            comparer.compare("DepthOfField", old.depth_of_field(), new.depth_of_field());
+            comparer.compare(
+                "ColorBlindFilter",
+                old.color_blind_filter(),
+                new.color_blind_filter(),
+            );
+        }
+        (Node::ParticleSystem(old), Node::ParticleSystem(new)) => {
+            comparer.compare("Acceleration", old.acceleration(), new.acceleration());
+            comparer.compare(
+                "Texture",
+                texture_path(old.texture()),
+                texture_path(new.texture()),
+            );
+            compare_emitters(old.emitters(), new.emitters(), &mut comparer);
+        }
+        (Node::Sprite(old), Node::Sprite(new)) => {
+            comparer.compare("Size", old.size(), new.size());
+            comparer.compare("Color", old.color(), new.color());
+            comparer.compare(
+                "Texture",
+                texture_path(old.texture()),
+                texture_path(new.texture()),
+            );
+        }
+        (Node::Mesh(old), Node::Mesh(new)) => {
+            compare_surfaces(old.surfaces(), new.surfaces(), &mut change.surfaces);
+        }
+        _ => (),
+    }
+
+    change
+}
+
+impl SceneDiff {
+    /// Compares two scenes.
+    pub fn new(old: &Scene, new: &Scene) -> Self {
+        let old_paths = node_paths(old);
+        let old_map = old_paths.iter().cloned().collect::<HashMap<_, _>>();
+        let new_paths = node_paths(new);
+        let new_map = new_paths.iter().cloned().collect::<HashMap<_, _>>();
+
+        let mut diff = Self::default();
+
+        for (path, handle) in new_paths.iter() {
+            if let Some(old_handle) = old_map.get(path) {
+                let change = compare_nodes(path, &old.graph[*old_handle], &new.graph[*handle]);
+                if !change.properties.is_empty() || !change.surfaces.is_empty() {
+                    diff.changed.push(change);
+                }
+            } else {
+                diff.added.push(path.clone());
+            }
+        }
+
+        for (path, _) in old_paths.iter() {
+            if !new_map.contains_key(path) {
+                diff.removed.push(path.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Returns true if scenes are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for SurfaceChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SurfaceChange::Added(index) => write!(f, "surface {} added", index),
+            SurfaceChange::Removed(index) => write!(f, "surface {} removed", index),
+            SurfaceChange::Topology { index, old, new } => write!(
+                f,
+                "surface {}: {} vertices, {} triangles -> {} vertices, {} triangles",
+                index, old.0, old.1, new.0, new.1
+            ),
+            SurfaceChange::Geometry(index) => write!(f, "surface {}: geometry changed", index),
+            SurfaceChange::Property { index, change } => write!(
+                f,
+                "surface {}: {}: {} -> {}",
+                index, change.property, change.old, change.new
+            ),
+        }
+    }
+}
+
+impl Display for SceneDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for path in self.added.iter() {
+            writeln!(f, "+ {}", path)?;
+        }
+        for path in self.removed.iter() {
+            writeln!(f, "- {}", path)?;
+        }
+        for change in self.changed.iter() {
+            writeln!(f, "~ {}", change.path)?;
+            for property in change.properties.iter() {
+                writeln!(
+                    f,
+                    "    {}: {} -> {}",
+                    property.property, property.old, property.new
+                )?;
+            }
+            for surface in change.surfaces.iter() {
+                writeln!(f, "    {}", surface)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{base::BaseBuilder, camera::CameraBuilder, diff::SceneDiff, node::Node, Scene},
+    };
+
+    #[test]
+    fn test_scene_diff() {
+        let mut old = Scene::new();
+        let a = old
+            .graph
+            .add_node(BaseBuilder::new().with_name("A").build_node());
+        let b = old
+            .graph
+            .add_node(BaseBuilder::new().with_name("B").build_node());
+        old.graph.link_nodes(b, a);
+        old.graph
+            .add_node(BaseBuilder::new().with_name("C").build_node());
+
+        let mut new = old.clone(&mut |_, _| true);
+        assert!(SceneDiff::new(&old, &new).is_empty());
+
+        let new_b = new.graph.find_by_name_from_root("B");
+        new.graph[new_b]
+            .local_transform_mut()
+            .set_position(Vec3::new(1.0, 0.0, 0.0));
+        new.graph[new_b].set_visibility(false);
+        let new_c = new.graph.find_by_name_from_root("C");
+        new.remove_node(new_c);
+        let d = new
+            .graph
+            .add_node(BaseBuilder::new().with_name("D").build_node());
+        new.graph.link_nodes(d, new_b);
+
+        let diff = SceneDiff::new(&old, &new);
+        assert_eq!(diff.added, vec!["A/B/D".to_owned()]);
+        assert_eq!(diff.removed, vec!["C".to_owned()]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].path, "A/B");
+        let properties = diff.changed[0]
+            .properties
+            .iter()
+            .map(|p| p.property.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(properties, vec!["Position", "Visibility"]);
+
+        let report = diff.to_string();
+        assert!(report.contains("+ A/B/D"));
+        assert!(report.contains("- C"));
+        assert!(report.contains("~ A/B"));
+
+        let mut old = Scene::new();
+        old.graph
+            .add_node(CameraBuilder::new(BaseBuilder::new().with_name("Camera")).build_node());
+        let mut new = old.clone(&mut |_, _| true);
+        let camera = new.graph.find_by_name_from_root("Camera");
+        if let Node::Camera(camera) = &mut new.graph[camera] {
+            camera.set_exposure(2.0);
+        }
+        let diff = SceneDiff::new(&old, &new);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].properties[0].property, "Exposure");
+    }
+}
@@ -21,6 +21,20 @@
 //! is global transform calculation - it allows you to produce complex movements
 //! just by linking nodes to each other. Good example of this is skeleton which
 //! is used in skinning (animating 3d model by set of bones).
+//!
+//! # Determinism
+//!
+//! Handles of nodes and order of iteration over nodes depends only on sequence of
+//! operations performed on a graph, there is no random state involved. So same sequence
+//! of operations (including loading of models) will produce exactly the same handles on
+//! every run. This is important for replays, lockstep networking and reproducible tests.
+//!
+//! By default records of removed nodes are reused by new nodes, so handles also depend on
+//! the order in which nodes were removed. If this order can differ between runs or peers,
+//! handle reuse can be disabled by `Graph::set_handle_reuse`, then new nodes always get
+//! new records and handles depend only on the order of additions. Records of removed nodes
+//! are reserved until `Graph::release_reserved_handles` is called, reservations are not
+//! saved.
 
 use crate::{
     core::{
@@ -29,22 +43,45 @@ use crate::{
             Handle, Pool, PoolIterator, PoolIteratorMut, PoolPairIterator, PoolPairIteratorMut,
             Ticket,
         },
+        scope_profile,
         visitor::{Visit, VisitResult, Visitor},
     },
+    engine::PauseFlags,
     scene::node::Node,
-    utils::log::Log,
+    utils::{
+        handle_debug::{HandleDebug, HandleScope},
+        log::Log,
+    },
 };
+use rayon::prelude::*;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ops::{Index, IndexMut},
 };
 
+/// Minimal amount of nodes in a graph at which global transforms and visibility will be
+/// calculated in parallel. For smaller graphs overhead of parallel processing is higher
+/// than the gain.
+pub const PARALLEL_PROPAGATION_THRESHOLD: usize = 4096;
+
+// Pointer to a node which can be sent to other threads. Used only to update disjoint
+// sub-trees in parallel, so each node is accessed by exactly one thread at a time.
+#[derive(Copy, Clone)]
+struct NodePtr(*mut Node);
+
+unsafe impl Send for NodePtr {}
+unsafe impl Sync for NodePtr {}
+
 /// See module docs.
 #[derive(Debug)]
 pub struct Graph {
     root: Handle<Node>,
     pool: Pool<Node>,
     stack: Vec<Handle<Node>>,
+    handle_reuse: bool,
+    // Records of removed nodes which must not be reused.
+    reserved: Vec<Ticket<Node>>,
+    debug_scope: HandleScope,
 }
 
 impl Default for Graph {
@@ -53,10 +90,19 @@ impl Default for Graph {
             root: Handle::NONE,
             pool: Pool::new(),
             stack: Vec::new(),
+            handle_reuse: true,
+            reserved: Vec::new(),
+            debug_scope: HandleScope::new(),
         }
     }
 }
 
+impl Drop for Graph {
+    fn drop(&mut self) {
+        HandleDebug::release_scope(self.debug_scope);
+    }
+}
+
 /// Sub-graph is a piece of graph that was extracted from a graph. It has ownership
 /// over its nodes. It is used to temporarily take ownership of a sub-graph. This could
 /// be used if you making a scene editor with a command stack - once you reverted a command,
@@ -72,6 +118,22 @@ pub struct SubGraph {
     pub descendants: Vec<(Ticket<Node>, Node)>,
 }
 
+impl SubGraph {
+    /// Returns handles that descendants had in graph, in the same order as in
+    /// `descendants`.
+    pub(in crate) fn descendant_handles(&self) -> Vec<Handle<Node>> {
+        // Repeats traversal of `take_reserve_sub_graph`, extracted nodes keep their
+        // children.
+        let mut handles = Vec::with_capacity(self.descendants.len());
+        let mut stack = self.root.1.children().to_vec();
+        while let Some(handle) = stack.pop() {
+            stack.extend_from_slice(self.descendants[handles.len()].1.children());
+            handles.push(handle);
+        }
+        handles
+    }
+}
+
 impl Graph {
     /// Creates new graph instance with single root node.
     pub fn new() -> Self {
@@ -79,10 +141,44 @@ impl Graph {
         let mut root = Node::Base(Default::default());
         root.set_name("__ROOT__");
         let root = pool.spawn(root);
+        let debug_scope = HandleScope::new();
+        HandleDebug::register(debug_scope, root, pool[root].name());
         Self {
             stack: Vec::new(),
             root,
             pool,
+            handle_reuse: true,
+            reserved: Vec::new(),
+            debug_scope,
+        }
+    }
+
+    /// Returns scope of handles of graph in handle debug registry, see `handle_debug`
+    /// module docs.
+    pub fn debug_scope(&self) -> HandleScope {
+        self.debug_scope
+    }
+
+    /// Enables or disables reuse of records of removed nodes, see module docs. Enabling
+    /// reuse releases every reserved record.
+    pub fn set_handle_reuse(&mut self, reuse: bool) {
+        self.handle_reuse = reuse;
+        if reuse {
+            self.release_reserved_handles();
+        }
+    }
+
+    /// Returns true if records of removed nodes are reused by new nodes.
+    pub fn is_handle_reuse_enabled(&self) -> bool {
+        self.handle_reuse
+    }
+
+    /// Allows records of nodes removed while handle reuse was disabled to be used by new
+    /// nodes. Should be called at points which are the same for every run or peer, for
+    /// example when level is loaded.
+    pub fn release_reserved_handles(&mut self) {
+        for ticket in self.reserved.drain(..) {
+            self.pool.forget_ticket(ticket);
         }
     }
 
@@ -92,6 +188,7 @@ impl Graph {
     #[inline]
     pub fn add_node(&mut self, node: Node) -> Handle<Node> {
         let handle = self.pool.spawn(node);
+        HandleDebug::register(self.debug_scope, handle, self.pool[handle].name());
         if self.root.is_some() {
             self.link_nodes(handle, self.root);
         }
@@ -138,7 +235,13 @@ impl Graph {
             for &child in self.pool[handle].children().iter() {
                 self.stack.push(child);
             }
-            self.pool.free(handle);
+            if self.handle_reuse {
+                self.pool.free(handle);
+            } else {
+                let (ticket, _) = self.pool.take_reserve(handle);
+                self.reserved.push(ticket);
+            }
+            HandleDebug::unregister(self.debug_scope, handle);
         }
     }
 
@@ -391,10 +494,70 @@ impl Graph {
     /// on each frame. However there is one use case - when you setup complex hierarchy and
     /// need to know global transform of nodes before entering update loop, then you can call
     /// this method.
+    ///
+    /// # Performance
+    ///
+    /// Large graphs (see `PARALLEL_PROPAGATION_THRESHOLD`) are processed in parallel, each
+    /// sub-tree of root node is processed independently, so it is better to keep hierarchy
+    /// "wide" near the root.
     pub fn update_hierachical_data(&mut self) {
+        if self.pool.alive_count() >= PARALLEL_PROPAGATION_THRESHOLD {
+            self.update_hierarchical_data_parallel();
+        } else {
+            self.update_hierarchical_data_for_descendants(self.root);
+        }
+    }
+
+    fn update_hierarchical_data_parallel(&mut self) {
+        scope_profile!();
+
+        // Root is processed first, then each of its sub-trees can be processed independently.
+        let root = &mut self.pool[self.root];
+        root.global_transform = root.combined_local_transform();
+        root.global_visibility = root.visibility();
+        let root_transform = root.global_transform;
+        let root_visibility = root.global_visibility;
+        let subtree_roots = root.children().to_vec();
+
+        // Table of nodes by index of handle, so worker threads can walk their sub-trees
+        // without access to the pool.
+        let mut nodes = Vec::new();
+        for (handle, node) in self.pool.pair_iter_mut() {
+            let index = handle.index() as usize;
+            if index >= nodes.len() {
+                nodes.resize(index + 1, NodePtr(std::ptr::null_mut()));
+            }
+            nodes[index] = NodePtr(node);
+        }
+
+        subtree_roots
+            .into_par_iter()
+            .for_each_init(Vec::new, |stack, subtree_root| {
+                stack.push((subtree_root, root_transform, root_visibility));
+                while let Some((handle, parent_transform, parent_visibility)) = stack.pop() {
+                    // SAFETY: Sub-trees are disjoint, so every node is visited by exactly one
+                    // thread, and pool is not modified while table is alive.
+                    let node = unsafe { &mut *nodes[handle.index() as usize].0 };
+                    node.global_transform = parent_transform * node.combined_local_transform();
+                    node.global_visibility = parent_visibility && node.visibility();
+                    for &child in node.children() {
+                        stack.push((child, node.global_transform, node.global_visibility));
+                    }
+                }
+            });
+    }
+
+    /// Calculates global transforms and visibility of given node and all its descendants
+    /// using current global transform of its parent. It can be used to propagate changes
+    /// of a sub-tree (for example after bone overrides) without re-walking whole graph.
+    ///
+    /// # Panics
+    ///
+    /// Panics if handle is invalid.
+    pub fn update_hierarchical_data_for_descendants(&mut self, node_handle: Handle<Node>) {
         // Calculate transforms on nodes
         self.stack.clear();
-        self.stack.push(self.root);
+        self.stack.push(node_handle);
         while let Some(node_handle) = self.stack.pop() {
             // Calculate local transform and get parent handle
             let parent_handle = self.pool[node_handle].parent();
@@ -407,7 +570,7 @@ impl Graph {
             };
 
             let node = &mut self.pool[node_handle];
-            node.global_transform = parent_global_transform * node.local_transform().matrix();
+            node.global_transform = parent_global_transform * node.combined_local_transform();
             node.global_visibility = parent_visibility && node.visibility();
 
             // Queue children and continue traversal on them
@@ -415,6 +578,75 @@ impl Graph {
         }
     }
 
+    /// Recalculates hierarchical data of sub-trees of given (modified) nodes. Every sub-tree
+    /// is processed once, nodes whose ancestor is in the set too are covered by sub-tree of
+    /// that ancestor. Global transforms of nodes outside of the sub-trees are expected to be
+    /// up to date.
+    fn update_hierarchical_data_for_batch(&mut self, dirty: HashSet<Handle<Node>>) {
+        for &handle in dirty.iter() {
+            let mut ancestor = self.pool[handle].parent();
+            let mut covered = false;
+            while ancestor.is_some() {
+                if dirty.contains(&ancestor) {
+                    covered = true;
+                    break;
+                }
+                ancestor = self.pool[ancestor].parent();
+            }
+            if !covered {
+                self.update_hierarchical_data_for_descendants(handle);
+            }
+        }
+    }
+
+    /// Sets local transforms of many nodes at once and recalculates hierarchical data
+    /// only once at the end of the batch and only for sub-trees of the nodes, so global
+    /// transforms are valid immediately after the call. Each matrix is decomposed into
+    /// position, rotation and scale (see `Transform::set_matrix`). Invalid handles are
+    /// ignored.
+    pub fn set_transforms_batch(&mut self, transforms: &[(Handle<Node>, Mat4)]) {
+        let mut dirty = HashSet::new();
+        for &(handle, matrix) in transforms {
+            if self.pool.is_valid_handle(handle) {
+                self.pool[handle].local_transform_mut().set_matrix(matrix);
+                dirty.insert(handle);
+            }
+        }
+        self.update_hierarchical_data_for_batch(dirty);
+    }
+
+    /// Sets local visibility of many nodes at once and recalculates hierarchical data
+    /// only once at the end of the batch and only for sub-trees of the nodes. Invalid
+    /// handles are ignored.
+    pub fn set_visibility_batch(&mut self, visibility: &[(Handle<Node>, bool)]) {
+        let mut dirty = HashSet::new();
+        for &(handle, visible) in visibility {
+            if self.pool.is_valid_handle(handle) {
+                self.pool[handle].set_visibility(visible);
+                dirty.insert(handle);
+            }
+        }
+        self.update_hierarchical_data_for_batch(dirty);
+    }
+
+    /// Applies given function to every node from given set and recalculates hierarchical
+    /// data only once at the end of the batch and only for sub-trees of the nodes. This is
+    /// generic version of other batch methods and can be used to modify any properties of
+    /// nodes, except hierarchy - use `link_nodes` for that. Invalid handles are ignored.
+    pub fn modify_batch<F>(&mut self, handles: &[Handle<Node>], mut func: F)
+    where
+        F: FnMut(Handle<Node>, &mut Node),
+    {
+        let mut dirty = HashSet::new();
+        for &handle in handles {
+            if self.pool.is_valid_handle(handle) {
+                func(handle, &mut self.pool[handle]);
+                dirty.insert(handle);
+            }
+        }
+        self.update_hierarchical_data_for_batch(dirty);
+    }
+
     /// Checks whether given node handle is valid or not.
     pub fn is_valid_handle(&self, node_handle: Handle<Node>) -> bool {
         self.pool.is_valid_handle(node_handle)
@@ -422,16 +654,44 @@ impl Graph {
 
     /// Updates nodes in graph using given delta time. There is no need to call it manually.
     pub fn update_nodes(&mut self, frame_size: Vec2, dt: f32) {
+        self.update_nodes_internal(frame_size, dt, PauseFlags::NONE)
+    }
+
+    pub(in crate) fn update_nodes_internal(
+        &mut self,
+        frame_size: Vec2,
+        dt: f32,
+        paused: PauseFlags,
+    ) {
         self.update_hierachical_data();
 
         for node in self.pool.iter_mut() {
-            if let Some(lifetime) = node.lifetime() {
-                node.set_lifetime(lifetime - dt);
+            // Lifetime is a game time, so it must stop together with the subsystem
+            // that drives the node, otherwise paused effects would vanish.
+            let lifetime_paused = if let Node::ParticleSystem(_) = node {
+                paused.contains(PauseFlags::PARTICLES)
+            } else {
+                paused.contains(PauseFlags::ANIMATION)
+            };
+            if !lifetime_paused {
+                if let Some(lifetime) = node.lifetime() {
+                    node.set_lifetime(lifetime - dt);
+                }
             }
 
             match node {
                 Node::Camera(camera) => camera.calculate_matrices(frame_size),
-                Node::ParticleSystem(particle_system) => particle_system.update(dt),
+                Node::Mesh(mesh) => mesh.update_modifiers(),
+                Node::ParticleSystem(particle_system) => {
+                    if !paused.contains(PauseFlags::PARTICLES) {
+                        particle_system.update(dt)
+                    }
+                }
+                Node::Light(light) => {
+                    if !paused.contains(PauseFlags::ANIMATION) {
+                        light.update_animation(dt)
+                    }
+                }
                 _ => (),
             }
         }
@@ -522,12 +782,14 @@ impl Graph {
     /// detached from its parent!
     pub fn take_reserve(&mut self, handle: Handle<Node>) -> (Ticket<Node>, Node) {
         self.unlink_internal(handle);
+        HandleDebug::unregister(self.debug_scope, handle);
         self.pool.take_reserve(handle)
     }
 
     /// Puts node back by given ticket. Attaches back to root node of graph.
     pub fn put_back(&mut self, ticket: Ticket<Node>, node: Node) -> Handle<Node> {
         let handle = self.pool.put_back(ticket, node);
+        HandleDebug::register(self.debug_scope, handle, self.pool[handle].name());
         self.link_nodes(handle, self.root);
         handle
     }
@@ -542,11 +804,13 @@ impl Graph {
     /// at such handle will result in panic!. Please note that root node will be
     /// detached from its parent!
     pub fn take_reserve_sub_graph(&mut self, root: Handle<Node>) -> SubGraph {
-        // Take out descendants first.
+        // Take out descendants first. Order of traversal must match
+        // `SubGraph::descendant_handles`.
         let mut descendants = Vec::new();
         let mut stack = self[root].children().to_vec();
         while let Some(handle) = stack.pop() {
             stack.extend_from_slice(self[handle].children());
+            HandleDebug::unregister(self.debug_scope, handle);
             descendants.push(self.pool.take_reserve(handle));
         }
 
@@ -562,7 +826,8 @@ impl Graph {
     /// parent.
     pub fn put_sub_graph_back(&mut self, sub_graph: SubGraph) -> Handle<Node> {
         for (ticket, node) in sub_graph.descendants {
-            self.pool.put_back(ticket, node);
+            let handle = self.pool.put_back(ticket, node);
+            HandleDebug::register(self.debug_scope, handle, self.pool[handle].name());
         }
 
         let (ticket, node) = sub_graph.root;
@@ -615,7 +880,9 @@ impl Graph {
     where
         F: FnMut(Handle<Node>, &Node) -> bool,
     {
-        let mut copy = Self::default();
+        // Graph implements Drop, so its fields can't be moved out of default one.
+        let mut copy = Graph::default();
+        copy.handle_reuse = self.handle_reuse;
         let (root, old_new_map) = self.copy_node(self.root, &mut copy, filter);
         copy.root = root;
         (copy, old_new_map)
@@ -653,12 +920,18 @@ impl Index<Handle<Node>> for Graph {
     type Output = Node;
 
     fn index(&self, index: Handle<Node>) -> &Self::Output {
+        if HandleDebug::is_enabled() && !self.pool.is_valid_handle(index) {
+            HandleDebug::report_invalid(self.debug_scope, index);
+        }
         &self.pool[index]
     }
 }
 
 impl IndexMut<Handle<Node>> for Graph {
     fn index_mut(&mut self, index: Handle<Node>) -> &mut Self::Output {
+        if HandleDebug::is_enabled() && !self.pool.is_valid_handle(index) {
+            HandleDebug::report_invalid(self.debug_scope, index);
+        }
         &mut self.pool[index]
     }
 }
@@ -719,6 +992,14 @@ impl Visit for Graph {
 
         self.root.visit("Root", visitor)?;
         self.pool.visit("Pool", visitor)?;
+        let _ = self.handle_reuse.visit("HandleReuse", visitor);
+
+        if visitor.is_reading() && HandleDebug::is_enabled() {
+            HandleDebug::release_scope(self.debug_scope);
+            for (handle, node) in self.pool.pair_iter() {
+                HandleDebug::register(self.debug_scope, handle, node.name());
+            }
+        }
 
         visitor.leave_region()
     }
@@ -727,8 +1008,15 @@ impl Visit for Graph {
 #[cfg(test)]
 mod test {
     use crate::{
-        core::pool::Handle,
-        scene::{base::Base, graph::Graph, node::Node},
+        core::{
+            math::{mat4::Mat4, vec3::Vec3},
+            pool::Handle,
+        },
+        scene::{
+            base::Base,
+            graph::{Graph, PARALLEL_PROPAGATION_THRESHOLD},
+            node::Node,
+        },
     };
 
     #[test]
@@ -746,4 +1034,100 @@ mod test {
         graph.add_node(Node::Base(Base::default()));
         assert_eq!(graph.pool.alive_count(), 4);
     }
+
+    #[test]
+    fn graph_deterministic_handles_test() {
+        let make_graph = || {
+            let mut graph = Graph::new();
+            let mut handles = Vec::new();
+            for _ in 0..10 {
+                handles.push(graph.add_node(Node::Base(Base::default())));
+            }
+            graph.remove_node(handles[3]);
+            graph.remove_node(handles[7]);
+            for _ in 0..3 {
+                handles.push(graph.add_node(Node::Base(Base::default())));
+            }
+            let order = graph.pair_iter().map(|(h, _)| h).collect::<Vec<_>>();
+            (handles, order)
+        };
+
+        assert_eq!(make_graph(), make_graph());
+
+        // Without reuse handles do not depend on order of removal.
+        let make_graph = |removal_order: [usize; 2]| {
+            let mut graph = Graph::new();
+            graph.set_handle_reuse(false);
+            let handles = (0..4)
+                .map(|_| graph.add_node(Node::Base(Base::default())))
+                .collect::<Vec<_>>();
+            for &i in removal_order.iter() {
+                graph.remove_node(handles[i]);
+            }
+            (0..2)
+                .map(|_| graph.add_node(Node::Base(Base::default())))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(make_graph([1, 2]), make_graph([2, 1]));
+    }
+
+    #[test]
+    fn graph_transforms_batch_test() {
+        let mut graph = Graph::new();
+        let parent = graph.add_node(Node::Base(Base::default()));
+        let child = graph.add_node(Node::Base(Base::default()));
+        graph.link_nodes(child, parent);
+
+        graph.set_transforms_batch(&[
+            (parent, Mat4::translate(Vec3::new(1.0, 0.0, 0.0))),
+            (child, Mat4::translate(Vec3::new(0.0, 2.0, 0.0))),
+            (Handle::NONE, Mat4::IDENTITY),
+        ]);
+
+        assert_eq!(graph[parent].global_position(), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(graph[child].global_position(), Vec3::new(1.0, 2.0, 0.0));
+
+        graph.set_visibility_batch(&[(parent, false)]);
+        assert!(!graph[child].global_visibility());
+
+        // Only sub-trees of nodes from batch are recalculated.
+        let other = graph.add_node(Node::Base(Base::default()));
+        graph[other]
+            .local_transform_mut()
+            .set_position(Vec3::new(0.0, 0.0, 3.0));
+        graph.set_transforms_batch(&[(child, Mat4::translate(Vec3::new(0.0, 4.0, 0.0)))]);
+        assert_eq!(graph[child].global_position(), Vec3::new(1.0, 4.0, 0.0));
+        assert_eq!(graph[other].global_position(), Vec3::ZERO);
+    }
+
+    #[test]
+    fn graph_parallel_propagation_test() {
+        let mut graph = Graph::new();
+        let mut handles = vec![graph.get_root()];
+        for i in 0..PARALLEL_PROPAGATION_THRESHOLD {
+            let mut base = Base::default();
+            base.local_transform_mut()
+                .set_position(Vec3::new(i as f32, 1.0, -(i as f32) * 0.5))
+                .set_scale(Vec3::new(1.0, 1.001, 1.0));
+            base.set_visibility(i % 7 != 0);
+            let handle = graph.add_node(Node::Base(base));
+            // Mix of wide and deep sub-trees.
+            graph.link_nodes(handle, handles[(i * 31) % handles.len()]);
+            handles.push(handle);
+        }
+
+        graph.update_hierarchical_data_parallel();
+        let parallel = handles
+            .iter()
+            .map(|&h| (graph[h].global_transform(), graph[h].global_visibility()))
+            .collect::<Vec<_>>();
+
+        graph.update_hierarchical_data_for_descendants(graph.get_root());
+        let serial = handles
+            .iter()
+            .map(|&h| (graph[h].global_transform(), graph[h].global_visibility()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(parallel, serial);
+    }
 }
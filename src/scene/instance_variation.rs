@@ -0,0 +1,193 @@
+//! Per-instance variation of meshes.
+//!
+//! Forests, rocks and crowds are made of a few models which are repeated many times, every
+//! copy shares same geometry and material, so repetition is very noticeable. Instance
+//! variation gives every mesh a random seed, a tint and random scale and brightness
+//! offsets derived from the seed, so copies look different without unique materials.
+//!
+//! Variation is passed to shaders as `instanceTint` (RGBA), `instanceSeed` (pseudo-random
+//! value in (0..1) range derived from seed) and `instanceBrightnessJitter` uniforms,
+//! scale is applied to world matrix of mesh.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{core::color::Color, scene::instance_variation::InstanceVariation};
+//!
+//! // Seed is random, every tree will have its own scale and brightness.
+//! let variation = InstanceVariation::random()
+//!     .with_tint(Color::from_rgba(230, 255, 220, 255))
+//!     .with_scale_jitter(0.2)
+//!     .with_brightness_jitter(0.15);
+//! assert!(variation.scale() >= 0.8 && variation.scale() <= 1.2);
+//! ```
+
+use crate::core::{
+    color::Color,
+    visitor::{Visit, VisitResult, Visitor},
+};
+
+/// Stable hash of seed and channel to (0..1) range.
+fn hash(seed: u32, channel: u32) -> f32 {
+    let mut x = seed ^ channel.wrapping_mul(0x9E37_79B9);
+    x = (x ^ (x >> 16)).wrapping_mul(0x7feb_352d);
+    x = (x ^ (x >> 15)).wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    // Use 24 bits to get uniformly distributed float in [0; 1) range.
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InstanceVariation {
+    seed: u32,
+    tint: Color,
+    scale_jitter: f32,
+    brightness_jitter: f32,
+}
+
+impl Default for InstanceVariation {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            tint: Color::WHITE,
+            scale_jitter: 0.0,
+            brightness_jitter: 0.0,
+        }
+    }
+}
+
+impl InstanceVariation {
+    /// Creates new variation with given seed, white tint and no jitter.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// Creates new variation with random seed, white tint and no jitter.
+    pub fn random() -> Self {
+        Self::new(rand::random())
+    }
+
+    /// Sets tint which is multiplied with diffuse color of every surface.
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    /// Sets maximal relative deviation of scale, for example 0.2 gives random scale in
+    /// (0.8..1.2) range.
+    pub fn with_scale_jitter(mut self, scale_jitter: f32) -> Self {
+        self.set_scale_jitter(scale_jitter);
+        self
+    }
+
+    /// Sets maximal relative deviation of brightness, applied in shader.
+    pub fn with_brightness_jitter(mut self, brightness_jitter: f32) -> Self {
+        self.set_brightness_jitter(brightness_jitter);
+        self
+    }
+
+    /// Sets new seed.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    /// Returns seed.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Sets new tint.
+    pub fn set_tint(&mut self, tint: Color) {
+        self.tint = tint;
+    }
+
+    /// Returns tint.
+    pub fn tint(&self) -> Color {
+        self.tint
+    }
+
+    /// Sets maximal relative deviation of scale, it is clamped to (0..1) range.
+    pub fn set_scale_jitter(&mut self, scale_jitter: f32) {
+        self.scale_jitter = scale_jitter.max(0.0).min(1.0);
+    }
+
+    /// Returns maximal relative deviation of scale.
+    pub fn scale_jitter(&self) -> f32 {
+        self.scale_jitter
+    }
+
+    /// Sets maximal relative deviation of brightness, it is clamped to (0..1) range.
+    pub fn set_brightness_jitter(&mut self, brightness_jitter: f32) {
+        self.brightness_jitter = brightness_jitter.max(0.0).min(1.0);
+    }
+
+    /// Returns maximal relative deviation of brightness.
+    pub fn brightness_jitter(&self) -> f32 {
+        self.brightness_jitter
+    }
+
+    /// Returns pseudo-random value in (0..1) range for given channel, same seed and
+    /// channel always give same value. Channels 0 and 1 are used by engine for shader
+    /// seed and scale, custom code can use any other channel.
+    pub fn random_value(&self, channel: u32) -> f32 {
+        hash(self.seed, channel)
+    }
+
+    /// Returns value of `instanceSeed` shader uniform.
+    pub fn shader_seed(&self) -> f32 {
+        self.random_value(0)
+    }
+
+    /// Returns scale multiplier of this instance.
+    pub fn scale(&self) -> f32 {
+        1.0 + (self.random_value(1) * 2.0 - 1.0) * self.scale_jitter
+    }
+}
+
+impl Visit for InstanceVariation {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.seed.visit("Seed", visitor)?;
+        self.tint.visit("Tint", visitor)?;
+        self.scale_jitter.visit("ScaleJitter", visitor)?;
+        self.brightness_jitter.visit("BrightnessJitter", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::instance_variation::InstanceVariation;
+
+    #[test]
+    fn test_default_has_no_effect() {
+        let variation = InstanceVariation::new(12345);
+        assert_eq!(variation.scale(), 1.0);
+    }
+
+    #[test]
+    fn test_values_are_stable_and_bounded() {
+        let mut min = std::f32::MAX;
+        let mut max = -std::f32::MAX;
+        for seed in 0..1000 {
+            let variation = InstanceVariation::new(seed).with_scale_jitter(0.25);
+            assert_eq!(
+                variation.scale(),
+                InstanceVariation::new(seed).with_scale_jitter(0.25).scale()
+            );
+            let seed_value = variation.shader_seed();
+            assert!(seed_value >= 0.0 && seed_value < 1.0);
+            min = min.min(variation.scale());
+            max = max.max(variation.scale());
+        }
+        assert!(min >= 0.75 && max <= 1.25);
+        // Values are spread over whole range.
+        assert!(min < 0.8 && max > 1.2);
+    }
+}
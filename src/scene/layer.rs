@@ -0,0 +1,112 @@
+//! Contains all structures and methods to manage named render layers.
+//!
+//! Every scene node belongs to exactly one render layer, and every camera has a layer
+//! mask which defines which layers it can see. Layers are identified by index in
+//! `[0; 32)` range, and layer mask is a simple bit mask where each bit is responsible
+//! for a layer with same index.
+//!
+//! Layers affect meshes, sprites and particle systems. Light sources illuminate every
+//! layer and shadows are cast by every layer, so first-person weapon will still be lit
+//! by scene lights.
+//!
+//! # Example
+//!
+//! Classic example of layers usage is first-person weapon rendering: weapon model is
+//! put in a separate layer which is excluded from main camera mask, and rendered by
+//! another camera which sees only weapon layer. This way weapon will never clip
+//! through walls.
+//!
+//! ```no_run
+//! use rg3d::scene::Scene;
+//!
+//! let mut scene = Scene::new();
+//! let weapon_layer = scene.layers.add("FirstPersonWeapon").unwrap();
+//! let weapon_mask = scene.layers.mask(&["FirstPersonWeapon"]);
+//! // Assign `weapon_layer` to weapon nodes using `Base::set_layer`, then use
+//! // `Camera::set_layer_mask(!weapon_mask)` on main camera and
+//! // `Camera::set_layer_mask(weapon_mask)` on weapon camera.
+//! ```
+
+use crate::core::visitor::{Visit, VisitResult, Visitor};
+
+/// Maximum amount of render layers.
+pub const MAX_LAYERS: usize = 32;
+
+/// Index of default layer, every node is in this layer by default.
+pub const DEFAULT_LAYER: u32 = 0;
+
+/// Layer mask which includes every layer.
+pub const ALL_LAYERS: u32 = std::u32::MAX;
+
+/// Returns layer mask which includes only given layer.
+pub fn layer_bit(layer: u32) -> u32 {
+    1u32.checked_shl(layer).unwrap_or(0)
+}
+
+/// Registry of named render layers. See module docs.
+#[derive(Clone, Debug)]
+pub struct RenderLayers {
+    names: Vec<String>,
+}
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        Self {
+            names: vec!["Default".to_owned()],
+        }
+    }
+}
+
+impl Visit for RenderLayers {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.names.visit("Names", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl RenderLayers {
+    /// Registers new named layer and returns its index. If there is already a layer
+    /// with given name, its index will be returned. Returns None if there is no more
+    /// free layers.
+    pub fn add<N: AsRef<str>>(&mut self, name: N) -> Option<u32> {
+        if let Some(index) = self.index_of(name.as_ref()) {
+            return Some(index);
+        }
+        if self.names.len() < MAX_LAYERS {
+            self.names.push(name.as_ref().to_owned());
+            Some((self.names.len() - 1) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Returns index of a layer with given name.
+    pub fn index_of<N: AsRef<str>>(&self, name: N) -> Option<u32> {
+        self.names
+            .iter()
+            .position(|n| n == name.as_ref())
+            .map(|i| i as u32)
+    }
+
+    /// Returns name of a layer with given index.
+    pub fn name(&self, layer: u32) -> Option<&str> {
+        self.names.get(layer as usize).map(|n| n.as_str())
+    }
+
+    /// Returns layer mask which includes every layer with given names. Unknown names
+    /// are ignored.
+    pub fn mask(&self, names: &[&str]) -> u32 {
+        names
+            .iter()
+            .filter_map(|name| self.index_of(name))
+            .fold(0, |mask, layer| mask | layer_bit(layer))
+    }
+
+    /// Returns names of all registered layers, index of name is index of layer.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
@@ -0,0 +1,234 @@
+//! Lenient loading of scenes.
+//!
+//! By default any error while reading a scene fails whole load. This is correct for files
+//! made by same version of engine, but too strict for scenes made by newer version of
+//! engine (or by a newer version of editor) which may contain node kinds and fields that
+//! current version knows nothing about, and for partially corrupted files. In lenient mode
+//! loader recovers from such errors instead:
+//!
+//! - Node of unknown kind is replaced with base node, its name, transform and links to
+//! children are kept if they can be read, so hierarchy of scene stays intact.
+//! - Missing field is skipped and default value is used instead.
+//! - Scene with newer format version is loaded as far as possible.
+//!
+//! Every recovered error is written to log and collected into [`LoadReport`]. Lenient mode
+//! is opt-in, scenes can be loaded by `Scene::from_file_lenient` and model resources are
+//! loaded leniently when `ResourceManager::set_lenient_loading` was called with `true`.
+//!
+//! # Limitations
+//!
+//! Recovery works per field - a field which is present but broken inside (for example a
+//! transform with missing position) is still an error, because its layout can't be guessed
+//! and visitor can't be moved back out of a partially read region.
+
+use crate::{
+    core::visitor::{Visit, VisitResult, Visitor},
+    utils::log::Log,
+};
+use std::{cell::RefCell, fmt};
+
+/// List of errors that were recovered during lenient loading.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadReport {
+    /// Human-readable description of every recovered error in order of occurrence.
+    pub warnings: Vec<String>,
+}
+
+impl LoadReport {
+    /// Returns true if nothing was recovered, i.e. load would succeed in strict mode too.
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "No errors");
+        }
+        for warning in self.warnings.iter() {
+            writeln!(f, "{}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    // Some while lenient load is in progress on current thread.
+    static WARNINGS: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Restores previous state on drop so panic while loading won't leave lenient mode on.
+struct Scope {
+    previous: Option<Vec<String>>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        WARNINGS.with(|w| *w.borrow_mut() = previous);
+    }
+}
+
+/// Runs given function with lenient mode enabled on current thread. Calls can be nested,
+/// every call gets its own report.
+pub(in crate) fn run<R, F: FnOnce() -> R>(func: F) -> (R, LoadReport) {
+    let scope = Scope {
+        previous: WARNINGS.with(|w| w.borrow_mut().replace(Vec::new())),
+    };
+    let result = func();
+    let warnings = WARNINGS.with(|w| w.borrow_mut().take()).unwrap_or_default();
+    drop(scope);
+    (result, LoadReport { warnings })
+}
+
+/// Returns true if lenient load is in progress on current thread.
+pub(in crate) fn is_enabled() -> bool {
+    WARNINGS.with(|w| w.borrow().is_some())
+}
+
+/// Writes recovered error to log and to report of current lenient load.
+pub(in crate) fn warn(message: String) {
+    Log::writeln(format!("Recovered from load error: {}", message));
+    WARNINGS.with(|w| {
+        if let Some(warnings) = w.borrow_mut().as_mut() {
+            warnings.push(message);
+        }
+    });
+}
+
+/// Returns true if current region of visitor has child region with given name. Must be
+/// called only while reading, otherwise region will be created.
+pub(in crate) fn has_region(name: &str, visitor: &mut Visitor) -> bool {
+    visitor.enter_region(name).is_ok() && visitor.leave_region().is_ok()
+}
+
+/// Visits a field which is required in strict mode. In lenient mode missing field is
+/// recovered - value is left as is (objects are created with defaults before reading) and
+/// warning is recorded. Field which is present, but can't be read, is still an error: its
+/// region was entered already, so visitor would be left inside of it.
+pub(in crate) fn field<T: Visit + ?Sized>(
+    value: &mut T,
+    name: &str,
+    visitor: &mut Visitor,
+) -> VisitResult {
+    if !visitor.is_reading() || !is_enabled() || has_region(name, visitor) {
+        return value.visit(name, visitor);
+    }
+
+    // Value is either missing or stored as a field of current region. Reading of a field
+    // does not move visitor, so failure can be safely recovered.
+    match value.visit(name, visitor) {
+        Err(e) if visitor.is_reading() && is_enabled() => {
+            warn(format!(
+                "field {} can't be read ({:?}), default value is used",
+                name, e
+            ));
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::visitor::{Visit, Visitor},
+        scene::{
+            base::BaseBuilder,
+            lenient::{is_enabled, run, warn},
+            node::Node,
+        },
+    };
+
+    #[test]
+    fn test_nested_reports() {
+        assert!(!is_enabled());
+        // Warnings outside of lenient load are only logged.
+        warn("ignored".to_owned());
+
+        let ((inner, after_inner), outer) = run(|| {
+            warn("outer".to_owned());
+            let (_, inner) = run(|| warn("inner".to_owned()));
+            let after_inner = is_enabled();
+            warn("outer again".to_owned());
+            (inner, after_inner)
+        });
+
+        assert!(after_inner);
+        assert!(!is_enabled());
+        assert_eq!(inner.warnings, vec!["inner".to_owned()]);
+        assert_eq!(
+            outer.warnings,
+            vec!["outer".to_owned(), "outer again".to_owned()]
+        );
+        assert!(!outer.is_clean());
+    }
+
+    #[test]
+    fn test_unknown_kind_and_missing_fields() {
+        let mut visitor = Visitor::new();
+
+        // Node of kind from future version of engine.
+        visitor.enter_region("First").unwrap();
+        let mut kind_id = 200u8;
+        kind_id.visit("KindId", &mut visitor).unwrap();
+        visitor.enter_region("Node").unwrap();
+        BaseBuilder::new()
+            .with_name("Unknown")
+            .build()
+            .visit("Base", &mut visitor)
+            .unwrap();
+        visitor.leave_region().unwrap();
+        visitor.leave_region().unwrap();
+
+        // Camera without any of its own fields.
+        visitor.enter_region("Second").unwrap();
+        let mut kind_id = 2u8;
+        kind_id.visit("KindId", &mut visitor).unwrap();
+        visitor.enter_region("Node").unwrap();
+        BaseBuilder::new()
+            .with_name("Camera")
+            .build()
+            .visit("Base", &mut visitor)
+            .unwrap();
+        visitor.leave_region().unwrap();
+        visitor.leave_region().unwrap();
+
+        let mut marker = 123u32;
+        marker.visit("Marker", &mut visitor).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "rg3d_lenient_unknown_kind_{}.bin",
+            std::process::id()
+        ));
+        visitor.save_binary(&path).unwrap();
+        let mut visitor = Visitor::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let ((first, second, marker), report) = run(|| {
+            let mut first = Node::default();
+            visitor.enter_region("First").unwrap();
+            first.visit("Node", &mut visitor).unwrap();
+            visitor.leave_region().unwrap();
+
+            let mut second = Node::default();
+            visitor.enter_region("Second").unwrap();
+            second.visit("Node", &mut visitor).unwrap();
+            visitor.leave_region().unwrap();
+
+            let mut marker = 0u32;
+            marker.visit("Marker", &mut visitor).unwrap();
+            (first, second, marker)
+        });
+
+        assert!(matches!(first, Node::Base(_)));
+        assert_eq!(first.name(), "Unknown");
+        assert!(second.is_camera());
+        assert_eq!(second.name(), "Camera");
+        // Visitor must end up in root region after recovered errors.
+        assert_eq!(marker, 123);
+        // Unknown kind, then Fov, ZNear, ZFar, Viewport and Enabled of camera.
+        assert_eq!(report.warnings.len(), 6);
+    }
+}
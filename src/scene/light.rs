@@ -15,6 +15,14 @@
 //! Most of light sources supports shadows (via shadows maps) and light scattering,
 //! these are common effects for modern games but still can significantly impact
 //! performance.
+//!
+//! # Light units
+//!
+//! By default intensity of light is set in artist-friendly units - it is just a
+//! multiplier for light color, and distance attenuation is defined by range of light.
+//! It is also possible to set intensity in physical units (lumens, candela or lux) and
+//! use physically correct inverse square falloff, this allows you to transfer values
+//! from DCC tools or real-world references. See [`LightUnits`] and [`LightFalloff`].
 
 use crate::{
     core::{
@@ -25,6 +33,9 @@ use crate::{
     },
     scene::{
         base::{Base, BaseBuilder},
+        lenient,
+        light_animation::LightAnimation,
+        light_rule::LightRule,
         node::Node,
     },
 };
@@ -34,6 +45,102 @@ use std::ops::{Deref, DerefMut};
 /// significant value and you'll clearly see light volume with such settings.
 pub const DEFAULT_SCATTER: Vec3 = Vec3::new(0.03, 0.03, 0.03);
 
+/// Scale that converts physical light units (candela for point and spot lights,
+/// lux for directional lights) into intensity used for shading. In other words
+/// shading intensity of 1.0 corresponds to 100 candela or 100 lux.
+pub const PHYSICAL_UNITS_SCALE: f32 = 0.01;
+
+/// Defines units in which intensity of light is measured.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightUnits {
+    /// Intensity is a unitless multiplier for light color. This is default mode.
+    Artist,
+    /// Luminous flux - total amount of light emitted by light source. For spot lights
+    /// it is assumed that whole flux is emitted in the cone. For directional lights
+    /// it is treated as lux.
+    Lumens,
+    /// Luminous intensity - amount of light emitted in a single direction. For
+    /// directional lights it is treated as lux.
+    Candela,
+    /// Illuminance - amount of light falling on a surface. Suitable for directional
+    /// lights (sun is 100000 lux at noon). For point and spot lights it is illuminance
+    /// at one meter from light source, which is equal to candela.
+    Lux,
+}
+
+impl Default for LightUnits {
+    fn default() -> Self {
+        LightUnits::Artist
+    }
+}
+
+impl LightUnits {
+    fn new(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(LightUnits::Artist),
+            1 => Ok(LightUnits::Lumens),
+            2 => Ok(LightUnits::Candela),
+            3 => Ok(LightUnits::Lux),
+            _ => Err(format!("Invalid light units {}", id)),
+        }
+    }
+
+    fn id(self) -> u32 {
+        match self {
+            LightUnits::Artist => 0,
+            LightUnits::Lumens => 1,
+            LightUnits::Candela => 2,
+            LightUnits::Lux => 3,
+        }
+    }
+}
+
+impl Visit for LightUnits {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id = self.id();
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = LightUnits::new(id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Defines how intensity of point and spot lights changes over distance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightFalloff {
+    /// Artist-friendly falloff, intensity smoothly decays to zero at light range. This
+    /// is default mode.
+    Range,
+    /// Physically correct inverse square falloff. To keep light volume bounded, it is
+    /// smoothly faded to zero near light range, so range should be big enough.
+    InverseSquare,
+}
+
+impl Default for LightFalloff {
+    fn default() -> Self {
+        LightFalloff::Range
+    }
+}
+
+impl Visit for LightFalloff {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id: u32 = match self {
+            LightFalloff::Range => 0,
+            LightFalloff::InverseSquare => 1,
+        };
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = match id {
+                0 => LightFalloff::Range,
+                1 => LightFalloff::InverseSquare,
+                _ => return Err(format!("Invalid light falloff {}", id).into()),
+            };
+        }
+        Ok(())
+    }
+}
+
 /// Spot light is can be imagined as flash light - it has direction and cone
 /// shape of light volume. It defined by two angles:
 /// 1) Hot spot inner angle - this is zone where intensity of light is max.
@@ -417,6 +524,34 @@ impl Light {
         }
     }
 
+    /// Returns intensity which is used for shading, intensity in physical units is
+    /// converted using [`PHYSICAL_UNITS_SCALE`]. Includes current factors of light
+    /// animation and light rule.
+    pub fn shading_intensity(&self) -> f32 {
+        self.base_shading_intensity() * self.animation_intensity_factor() * self.rule_factor()
+    }
+
+    /// Returns intensity which is used for shading without factors of light animation
+    /// and light rule, i.e. intensity of light at rest. Lightmaps are baked with it, so
+    /// baked result does not depend on moment of baking.
+    pub fn base_shading_intensity(&self) -> f32 {
+        let intensity = self.intensity();
+        let candela = match self.units() {
+            LightUnits::Artist => return intensity,
+            LightUnits::Candela | LightUnits::Lux => intensity,
+            LightUnits::Lumens => match self {
+                Light::Point(_) => intensity / (4.0 * std::f32::consts::PI),
+                Light::Spot(spot) => {
+                    let solid_angle =
+                        2.0 * std::f32::consts::PI * (1.0 - (spot.full_cone_angle() * 0.5).cos());
+                    intensity / solid_angle.max(std::f32::EPSILON)
+                }
+                Light::Directional(_) => intensity,
+            },
+        };
+        candela * PHYSICAL_UNITS_SCALE
+    }
+
     define_is_as!(Light : Directional -> ref DirectionalLight => fn is_directional, fn as_directional, fn as_directional_mut);
     define_is_as!(Light : Spot -> ref SpotLight => fn is_spot, fn as_spot, fn as_spot_mut);
     define_is_as!(Light : Point -> ref PointLight => fn is_point, fn as_point, fn as_point_mut);
@@ -474,6 +609,11 @@ pub struct BaseLight {
     cast_shadows: bool,
     scatter: Vec3,
     scatter_enabled: bool,
+    intensity: f32,
+    units: LightUnits,
+    falloff: LightFalloff,
+    animation: Option<LightAnimation>,
+    rule: Option<LightRule>,
 }
 
 impl Deref for BaseLight {
@@ -498,6 +638,11 @@ impl Default for BaseLight {
             cast_shadows: true,
             scatter: DEFAULT_SCATTER,
             scatter_enabled: true,
+            intensity: 1.0,
+            units: Default::default(),
+            falloff: Default::default(),
+            animation: None,
+            rule: None,
         }
     }
 }
@@ -506,11 +651,16 @@ impl Visit for BaseLight {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
 
-        self.color.visit("Color", visitor)?;
+        lenient::field(&mut self.color, "Color", visitor)?;
         self.base.visit("Base", visitor)?;
-        self.cast_shadows.visit("CastShadows", visitor)?;
-        self.scatter.visit("ScatterFactor", visitor)?;
-        self.scatter_enabled.visit("ScatterEnabled", visitor)?;
+        lenient::field(&mut self.cast_shadows, "CastShadows", visitor)?;
+        lenient::field(&mut self.scatter, "ScatterFactor", visitor)?;
+        lenient::field(&mut self.scatter_enabled, "ScatterEnabled", visitor)?;
+        let _ = self.intensity.visit("Intensity", visitor);
+        let _ = self.units.visit("Units", visitor);
+        let _ = self.falloff.visit("Falloff", visitor);
+        let _ = self.animation.visit("Animation", visitor);
+        let _ = self.rule.visit("Rule", visitor);
 
         visitor.leave_region()
     }
@@ -571,6 +721,131 @@ impl BaseLight {
     pub fn is_scatter_enabled(&self) -> bool {
         self.scatter_enabled
     }
+
+    /// Sets intensity of light in current units. See [`LightUnits`].
+    #[inline]
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    /// Returns intensity of light in current units.
+    #[inline]
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Sets units of light intensity. Intensity value is not converted.
+    #[inline]
+    pub fn set_units(&mut self, units: LightUnits) {
+        self.units = units;
+    }
+
+    /// Returns units of light intensity.
+    #[inline]
+    pub fn units(&self) -> LightUnits {
+        self.units
+    }
+
+    /// Sets distance falloff mode. It has no effect on directional lights.
+    #[inline]
+    pub fn set_falloff(&mut self, falloff: LightFalloff) {
+        self.falloff = falloff;
+    }
+
+    /// Returns distance falloff mode.
+    #[inline]
+    pub fn falloff(&self) -> LightFalloff {
+        self.falloff
+    }
+
+    /// Sets or removes animation of light. Animation is updated by engine and modulates
+    /// intensity and color of light, see [`LightAnimation`].
+    #[inline]
+    pub fn set_animation(&mut self, animation: Option<LightAnimation>) {
+        self.animation = animation;
+    }
+
+    /// Returns animation of light, if any.
+    #[inline]
+    pub fn animation(&self) -> Option<&LightAnimation> {
+        self.animation.as_ref()
+    }
+
+    /// Returns animation of light, if any.
+    #[inline]
+    pub fn animation_mut(&mut self) -> Option<&mut LightAnimation> {
+        self.animation.as_mut()
+    }
+
+    /// Returns current intensity multiplier produced by animation, 1.0 if there is no
+    /// animation.
+    #[inline]
+    pub fn animation_intensity_factor(&self) -> f32 {
+        self.animation
+            .as_ref()
+            .map_or(1.0, |a| a.intensity_factor())
+    }
+
+    /// Returns color which is used for shading - color of light modulated by animation.
+    #[inline]
+    pub fn shading_color(&self) -> Color {
+        match self.animation.as_ref() {
+            Some(animation) => {
+                let factor = animation.color_factor();
+                Color::from_rgba(
+                    (u32::from(self.color.r) * u32::from(factor.r) / 255) as u8,
+                    (u32::from(self.color.g) * u32::from(factor.g) / 255) as u8,
+                    (u32::from(self.color.b) * u32::from(factor.b) / 255) as u8,
+                    self.color.a,
+                )
+            }
+            None => self.color,
+        }
+    }
+
+    pub(in crate) fn update_animation(&mut self, dt: f32) {
+        if let Some(animation) = self.animation.as_mut() {
+            animation.update(dt);
+        }
+    }
+
+    /// Sets or removes rule which automatically switches light on and off by time of day
+    /// and distance to observer, see [`LightRule`].
+    #[inline]
+    pub fn set_rule(&mut self, rule: Option<LightRule>) {
+        self.rule = rule;
+    }
+
+    /// Returns light rule, if any.
+    #[inline]
+    pub fn rule(&self) -> Option<&LightRule> {
+        self.rule.as_ref()
+    }
+
+    /// Returns light rule, if any.
+    #[inline]
+    pub fn rule_mut(&mut self) -> Option<&mut LightRule> {
+        self.rule.as_mut()
+    }
+
+    /// Returns current intensity multiplier produced by light rule, 1.0 if there is no
+    /// rule.
+    #[inline]
+    pub fn rule_factor(&self) -> f32 {
+        self.rule.as_ref().map_or(1.0, |r| r.factor())
+    }
+
+    /// Returns false if light was switched off by its rule, such lights are not rendered.
+    #[inline]
+    pub fn is_switched_on(&self) -> bool {
+        self.rule_factor() > 0.0
+    }
+
+    pub(in crate) fn update_rule(&mut self, dt: f32, time_of_day: f32, distance: Option<f32>) {
+        if let Some(rule) = self.rule.as_mut() {
+            rule.update(dt, time_of_day, distance);
+        }
+    }
 }
 
 /// Light scene node builder. Provides easy declarative way of creating light scene
@@ -581,6 +856,11 @@ pub struct BaseLightBuilder {
     cast_shadows: bool,
     scatter_factor: Vec3,
     scatter_enabled: bool,
+    intensity: f32,
+    units: LightUnits,
+    falloff: LightFalloff,
+    animation: Option<LightAnimation>,
+    rule: Option<LightRule>,
 }
 
 impl BaseLightBuilder {
@@ -595,6 +875,11 @@ impl BaseLightBuilder {
             cast_shadows: true,
             scatter_factor: DEFAULT_SCATTER,
             scatter_enabled: true,
+            intensity: 1.0,
+            units: Default::default(),
+            falloff: Default::default(),
+            animation: None,
+            rule: None,
         }
     }
 
@@ -622,6 +907,31 @@ impl BaseLightBuilder {
         self
     }
 
+    /// Sets light intensity and its units.
+    pub fn with_intensity(mut self, intensity: f32, units: LightUnits) -> Self {
+        self.intensity = intensity;
+        self.units = units;
+        self
+    }
+
+    /// Sets distance falloff mode.
+    pub fn with_falloff(mut self, falloff: LightFalloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    /// Sets light animation.
+    pub fn with_animation(mut self, animation: LightAnimation) -> Self {
+        self.animation = Some(animation);
+        self
+    }
+
+    /// Sets light rule.
+    pub fn with_rule(mut self, rule: LightRule) -> Self {
+        self.rule = Some(rule);
+        self
+    }
+
     /// Creates new instance of light scene node. Warning: each scene node
     /// must be added to scene, otherwise it won't have any effect and most
     /// likely will be dropped as soon as it go out of scope.
@@ -632,6 +942,11 @@ impl BaseLightBuilder {
             cast_shadows: self.cast_shadows,
             scatter: self.scatter_factor,
             scatter_enabled: self.scatter_enabled,
+            intensity: self.intensity.max(0.0),
+            units: self.units,
+            falloff: self.falloff,
+            animation: self.animation,
+            rule: self.rule,
         }
     }
 }
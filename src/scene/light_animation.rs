@@ -0,0 +1,490 @@
+//! Light animation changes intensity and color of a light over time.
+//!
+//! Every torch, candle or broken fluorescent lamp needs its light to flicker, instead of
+//! writing per-frame code for each of them, assign an animation to a light and engine will
+//! update it. Animation does not change intensity and color which were set to light, it
+//! produces multipliers which are applied on top of them during rendering.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::scene::light_animation::{LightAnimation, LightPattern};
+//!
+//! // Torch.
+//! let torch = LightAnimation::new(LightPattern::Flicker {
+//!     frequency: 8.0,
+//!     min: 0.7,
+//!     max: 1.0,
+//! });
+//! // Broken fluorescent lamp, each letter is brightness from 'a' (off) through 'm' (normal)
+//! // to 'z' (a bit more than double).
+//! let lamp = LightAnimation::new(LightPattern::Sequence {
+//!     pattern: "mmamammmmammamamaaamammma".to_owned(),
+//!     rate: 10.0,
+//! });
+//! ```
+
+use crate::{
+    core::{
+        color::Color,
+        color_gradient::ColorGradient,
+        math::vec2::Vec2,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    utils::log::Log,
+};
+
+/// Defines how intensity of light changes over time, every pattern produces intensity
+/// multiplier.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightPattern {
+    /// Intensity does not change.
+    Constant,
+    /// Smooth random flicker, good for torches and candles.
+    Flicker {
+        /// Amount of random values per second.
+        frequency: f32,
+        /// Minimal multiplier.
+        min: f32,
+        /// Maximal multiplier.
+        max: f32,
+    },
+    /// Smooth sine pulsation.
+    Pulse {
+        /// Amount of pulses per second.
+        frequency: f32,
+        /// Minimal multiplier.
+        min: f32,
+        /// Maximal multiplier.
+        max: f32,
+    },
+    /// Light is switched between two values.
+    Strobe {
+        /// Amount of flashes per second.
+        frequency: f32,
+        /// Portion of period in (0..1) range in which light has maximal value.
+        duty: f32,
+        /// Multiplier when light is "off".
+        min: f32,
+        /// Multiplier when light is "on".
+        max: f32,
+    },
+    /// Sequence of letters, each letter defines multiplier: 'a' is zero, 'm' is one and
+    /// every next letter adds 1/12, so 'z' is 25/12. Letters are stepped with given rate,
+    /// sequence is looped. This is the classic way to describe light styles.
+    Sequence {
+        /// Sequence of letters from 'a' to 'z'.
+        pattern: String,
+        /// Amount of letters per second.
+        rate: f32,
+    },
+    /// Piecewise linear curve, `x` of a point is time in seconds, `y` - multiplier. Curve
+    /// is looped, its period is time of last point.
+    Curve {
+        /// Points of curve sorted by time.
+        points: Vec<Vec2>,
+    },
+}
+
+impl Default for LightPattern {
+    fn default() -> Self {
+        LightPattern::Constant
+    }
+}
+
+/// Stable hash of integer to (0..1) range, used as source of noise for flicker.
+fn hash_noise(seed: u32, n: i64) -> f32 {
+    let mut x = (n as u64 as u32) ^ seed.wrapping_mul(0x9E37_79B9);
+    x = (x ^ (x >> 16)).wrapping_mul(0x045d_9f3b);
+    x = (x ^ (x >> 16)).wrapping_mul(0x045d_9f3b);
+    x ^= x >> 16;
+    x as f32 / std::u32::MAX as f32
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+impl LightPattern {
+    fn id(&self) -> u32 {
+        match self {
+            LightPattern::Constant => 0,
+            LightPattern::Flicker { .. } => 1,
+            LightPattern::Pulse { .. } => 2,
+            LightPattern::Strobe { .. } => 3,
+            LightPattern::Sequence { .. } => 4,
+            LightPattern::Curve { .. } => 5,
+        }
+    }
+
+    fn new(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(LightPattern::Constant),
+            1 => Ok(LightPattern::Flicker {
+                frequency: 0.0,
+                min: 0.0,
+                max: 0.0,
+            }),
+            2 => Ok(LightPattern::Pulse {
+                frequency: 0.0,
+                min: 0.0,
+                max: 0.0,
+            }),
+            3 => Ok(LightPattern::Strobe {
+                frequency: 0.0,
+                duty: 0.0,
+                min: 0.0,
+                max: 0.0,
+            }),
+            4 => Ok(LightPattern::Sequence {
+                pattern: Default::default(),
+                rate: 0.0,
+            }),
+            5 => Ok(LightPattern::Curve {
+                points: Default::default(),
+            }),
+            _ => Err(format!("Invalid light pattern {}", id)),
+        }
+    }
+
+    /// Evaluates intensity multiplier at given time. Seed is used by random patterns.
+    pub fn evaluate(&self, time: f32, seed: u32) -> f32 {
+        match self {
+            LightPattern::Constant => 1.0,
+            LightPattern::Flicker {
+                frequency,
+                min,
+                max,
+            } => {
+                let t = time * frequency;
+                let n = t.floor();
+                let k = t - n;
+                // Smoothstep between two random values.
+                let k = k * k * (3.0 - 2.0 * k);
+                let value = lerp(
+                    hash_noise(seed, n as i64),
+                    hash_noise(seed, n as i64 + 1),
+                    k,
+                );
+                lerp(*min, *max, value)
+            }
+            LightPattern::Pulse {
+                frequency,
+                min,
+                max,
+            } => {
+                let k = 0.5 + 0.5 * (time * frequency * 2.0 * std::f32::consts::PI).sin();
+                lerp(*min, *max, k)
+            }
+            LightPattern::Strobe {
+                frequency,
+                duty,
+                min,
+                max,
+            } => {
+                let phase = (time * frequency).fract();
+                if phase < *duty {
+                    *max
+                } else {
+                    *min
+                }
+            }
+            LightPattern::Sequence { pattern, rate } => {
+                let len = pattern.len();
+                if len == 0 {
+                    return 1.0;
+                }
+                let index = ((time * rate).max(0.0) as usize) % len;
+                let letter = pattern.as_bytes()[index].to_ascii_lowercase();
+                if letter.is_ascii_lowercase() {
+                    f32::from(letter - b'a') / f32::from(b'm' - b'a')
+                } else {
+                    1.0
+                }
+            }
+            LightPattern::Curve { points } => {
+                let (first, last) = match (points.first(), points.last()) {
+                    (Some(first), Some(last)) => (*first, *last),
+                    _ => return 1.0,
+                };
+                if last.x <= 0.0 {
+                    return first.y;
+                }
+                let t = time % last.x;
+                if t <= first.x {
+                    return first.y;
+                }
+                for pair in points.windows(2) {
+                    let (a, b) = (pair[0], pair[1]);
+                    if t <= b.x {
+                        let span = b.x - a.x;
+                        return if span > 0.0 {
+                            lerp(a.y, b.y, (t - a.x) / span)
+                        } else {
+                            b.y
+                        };
+                    }
+                }
+                last.y
+            }
+        }
+    }
+}
+
+impl Visit for LightPattern {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = match LightPattern::new(id) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    Log::writeln(format!("{}, constant pattern is used instead.", e));
+                    LightPattern::Constant
+                }
+            };
+        }
+
+        match self {
+            LightPattern::Constant => (),
+            LightPattern::Flicker {
+                frequency,
+                min,
+                max,
+            }
+            | LightPattern::Pulse {
+                frequency,
+                min,
+                max,
+            } => {
+                frequency.visit("Frequency", visitor)?;
+                min.visit("Min", visitor)?;
+                max.visit("Max", visitor)?;
+            }
+            LightPattern::Strobe {
+                frequency,
+                duty,
+                min,
+                max,
+            } => {
+                frequency.visit("Frequency", visitor)?;
+                duty.visit("Duty", visitor)?;
+                min.visit("Min", visitor)?;
+                max.visit("Max", visitor)?;
+            }
+            LightPattern::Sequence { pattern, rate } => {
+                pattern.visit("Pattern", visitor)?;
+                rate.visit("Rate", visitor)?;
+            }
+            LightPattern::Curve { points } => {
+                points.visit("Points", visitor)?;
+            }
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct LightAnimation {
+    pattern: LightPattern,
+    color_gradient: Option<ColorGradient>,
+    color_period: f32,
+    speed: f32,
+    seed: u32,
+    time: f32,
+    intensity_factor: f32,
+    color_factor: Color,
+}
+
+impl Default for LightAnimation {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl LightAnimation {
+    /// Creates new animation with given intensity pattern. Seed of random patterns is
+    /// random, so copies of a light won't flicker in sync.
+    pub fn new(pattern: LightPattern) -> Self {
+        let mut animation = Self {
+            pattern,
+            color_gradient: None,
+            color_period: 1.0,
+            speed: 1.0,
+            seed: rand::random(),
+            time: 0.0,
+            intensity_factor: 1.0,
+            color_factor: Color::WHITE,
+        };
+        animation.update(0.0);
+        animation
+    }
+
+    /// Sets gradient which is used to cycle color of light, gradient is sampled from 0 to
+    /// 1 over given period in seconds and then repeated.
+    pub fn with_color_cycle(mut self, gradient: ColorGradient, period: f32) -> Self {
+        self.set_color_cycle(Some(gradient), period);
+        self
+    }
+
+    /// Sets seed of random patterns, lights with same seed and pattern are synchronized.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self.update(0.0);
+        self
+    }
+
+    /// Sets speed multiplier of animation.
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Sets new intensity pattern.
+    pub fn set_pattern(&mut self, pattern: LightPattern) {
+        self.pattern = pattern;
+        self.update(0.0);
+    }
+
+    /// Returns current intensity pattern.
+    pub fn pattern(&self) -> &LightPattern {
+        &self.pattern
+    }
+
+    /// Sets or removes color cycling, see [with_color_cycle](struct.LightAnimation.html#method.with_color_cycle).
+    pub fn set_color_cycle(&mut self, gradient: Option<ColorGradient>, period: f32) {
+        self.color_gradient = gradient;
+        self.color_period = period.max(std::f32::EPSILON);
+        self.update(0.0);
+    }
+
+    /// Returns color gradient used for color cycling, if any.
+    pub fn color_gradient(&self) -> Option<&ColorGradient> {
+        self.color_gradient.as_ref()
+    }
+
+    /// Sets speed multiplier of animation.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Returns speed multiplier of animation.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Returns current time of animation.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Sets current time of animation.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+        self.update(0.0);
+    }
+
+    /// Returns current intensity multiplier.
+    pub fn intensity_factor(&self) -> f32 {
+        self.intensity_factor
+    }
+
+    /// Returns current color multiplier.
+    pub fn color_factor(&self) -> Color {
+        self.color_factor
+    }
+
+    /// Advances animation by given time delta. There is no need to call it manually, engine
+    /// does it automatically for every light.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt * self.speed;
+        self.intensity_factor = self.pattern.evaluate(self.time, self.seed).max(0.0);
+        self.color_factor = match self.color_gradient.as_ref() {
+            Some(gradient) => gradient.get_color((self.time / self.color_period).fract().abs()),
+            None => Color::WHITE,
+        };
+    }
+}
+
+impl Visit for LightAnimation {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pattern.visit("Pattern", visitor)?;
+        self.color_gradient.visit("ColorGradient", visitor)?;
+        self.color_period.visit("ColorPeriod", visitor)?;
+        self.speed.visit("Speed", visitor)?;
+        self.seed.visit("Seed", visitor)?;
+        self.time.visit("Time", visitor)?;
+
+        if visitor.is_reading() {
+            self.update(0.0);
+        }
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec2::Vec2,
+        scene::light_animation::{LightAnimation, LightPattern},
+    };
+
+    #[test]
+    fn test_sequence() {
+        let pattern = LightPattern::Sequence {
+            pattern: "amz".to_owned(),
+            rate: 1.0,
+        };
+        assert_eq!(pattern.evaluate(0.5, 0), 0.0);
+        assert_eq!(pattern.evaluate(1.5, 0), 1.0);
+        assert!((pattern.evaluate(2.5, 0) - 25.0 / 12.0).abs() < 0.0001);
+        // Looped.
+        assert_eq!(pattern.evaluate(3.5, 0), 0.0);
+    }
+
+    #[test]
+    fn test_strobe_and_curve() {
+        let strobe = LightPattern::Strobe {
+            frequency: 2.0,
+            duty: 0.25,
+            min: 0.0,
+            max: 3.0,
+        };
+        assert_eq!(strobe.evaluate(0.1, 0), 3.0);
+        assert_eq!(strobe.evaluate(0.2, 0), 0.0);
+
+        let curve = LightPattern::Curve {
+            points: vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(2.0, 0.0),
+            ],
+        };
+        assert!((curve.evaluate(0.5, 0) - 0.5).abs() < 0.0001);
+        assert!((curve.evaluate(2.5, 0) - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_flicker_is_deterministic_and_bounded() {
+        let mut a = LightAnimation::new(LightPattern::Flicker {
+            frequency: 10.0,
+            min: 0.5,
+            max: 1.0,
+        })
+        .with_seed(42);
+        let mut b = a.clone();
+        for _ in 0..100 {
+            a.update(0.016);
+            b.update(0.016);
+            assert_eq!(a.intensity_factor(), b.intensity_factor());
+            assert!(a.intensity_factor() >= 0.5 && a.intensity_factor() <= 1.0);
+        }
+    }
+}
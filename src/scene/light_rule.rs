@@ -0,0 +1,320 @@
+//! Light rules automatically switch lights on and off.
+//!
+//! City scenes can have hundreds of street lamps and windows, but most of them are either
+//! off during the day or too far away from the player to be noticeable. Light rule defines
+//! time-of-day window in which light is on and distance to observer (usually player) at
+//! which light is still active. Switched off lights are skipped by renderer entirely, so
+//! amount of rendered lights stays within budget. To avoid popping, light is faded in
+//! and out near the edges of both ranges.
+//!
+//! Time of day is taken from [`LightRuleSettings`] of a scene, sky or weather systems of a
+//! game should write current time there, so all rules stay in sync with the sky.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::scene::light_rule::LightRule;
+//!
+//! // Street lamp: on from 19:00 till 6:00 with half an hour of dusk, active only in
+//! // 60 meters around the player and smoothly fades in last 10 meters.
+//! let rule = LightRule::default()
+//!     .with_time_window(19.0, 6.0, 0.5)
+//!     .with_max_distance(60.0, 10.0);
+//! ```
+
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::{graph::Graph, node::Node},
+};
+
+/// Amount of hours in a day, time of day wraps at this value.
+pub const HOURS_PER_DAY: f32 = 24.0;
+
+/// Per-scene settings of light rules.
+#[derive(Copy, Clone, Debug)]
+pub struct LightRuleSettings {
+    /// Current time of day in hours in (0..24) range. Should be set by sky or weather
+    /// system of a game. Default is 12.0 (noon).
+    pub time_of_day: f32,
+    /// Node whose position is used as observer position for distance checks. If handle
+    /// is none, position of first enabled camera is used. Default is none.
+    pub observer: Handle<Node>,
+    /// Whether light rules are updated or not. Lights keep their last state while rules
+    /// are disabled. Default is true.
+    pub enabled: bool,
+}
+
+impl Default for LightRuleSettings {
+    fn default() -> Self {
+        Self {
+            time_of_day: 12.0,
+            observer: Handle::NONE,
+            enabled: true,
+        }
+    }
+}
+
+impl Visit for LightRuleSettings {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.time_of_day.visit("TimeOfDay", visitor)?;
+        self.observer.visit("Observer", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Time window in hours, `from` can be greater than `to` - in this case window wraps
+/// around midnight.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct TimeWindow {
+    from: f32,
+    to: f32,
+    fade: f32,
+}
+
+fn wrap_hours(hours: f32) -> f32 {
+    let hours = hours % HOURS_PER_DAY;
+    if hours < 0.0 {
+        hours + HOURS_PER_DAY
+    } else {
+        hours
+    }
+}
+
+impl TimeWindow {
+    fn factor(&self, time: f32) -> f32 {
+        let length = wrap_hours(self.to - self.from);
+        // Time passed since start of window.
+        let elapsed = wrap_hours(time - self.from);
+        if elapsed > length {
+            return 0.0;
+        }
+        if self.fade <= 0.0 {
+            return 1.0;
+        }
+        (elapsed / self.fade)
+            .min((length - elapsed) / self.fade)
+            .min(1.0)
+            .max(0.0)
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct LightRule {
+    time_window: Option<TimeWindow>,
+    max_distance: Option<f32>,
+    distance_fade: f32,
+    transition_time: f32,
+    factor: f32,
+}
+
+impl Default for LightRule {
+    fn default() -> Self {
+        Self {
+            time_window: None,
+            max_distance: None,
+            distance_fade: 0.0,
+            transition_time: 0.0,
+            factor: 1.0,
+        }
+    }
+}
+
+impl LightRule {
+    /// Sets time window (in hours) in which light is on, `from` can be greater than `to`,
+    /// in this case window wraps around midnight. Light fades in and out during `fade`
+    /// hours at the edges of the window.
+    pub fn with_time_window(mut self, from: f32, to: f32, fade: f32) -> Self {
+        self.set_time_window(Some((from, to, fade)));
+        self
+    }
+
+    /// Sets maximum distance to observer at which light is on. Light fades out in last
+    /// `fade` units.
+    pub fn with_max_distance(mut self, distance: f32, fade: f32) -> Self {
+        self.set_max_distance(Some(distance), fade);
+        self
+    }
+
+    /// Sets time (in seconds) which light needs to fully switch on or off. Useful to
+    /// avoid popping when observer is teleported or time of day jumps. Default is zero.
+    pub fn with_transition_time(mut self, time: f32) -> Self {
+        self.transition_time = time.max(0.0);
+        self
+    }
+
+    /// Sets or removes time window as `(from, to, fade)` triple, see
+    /// [with_time_window](struct.LightRule.html#method.with_time_window).
+    pub fn set_time_window(&mut self, window: Option<(f32, f32, f32)>) {
+        self.time_window = window.map(|(from, to, fade)| TimeWindow {
+            from: wrap_hours(from),
+            to: wrap_hours(to),
+            fade: fade.max(0.0),
+        });
+    }
+
+    /// Returns time window as `(from, to, fade)` triple.
+    pub fn time_window(&self) -> Option<(f32, f32, f32)> {
+        self.time_window.map(|w| (w.from, w.to, w.fade))
+    }
+
+    /// Sets or removes maximum distance to observer.
+    pub fn set_max_distance(&mut self, distance: Option<f32>, fade: f32) {
+        self.max_distance = distance.map(|d| d.max(0.0));
+        self.distance_fade = fade.max(0.0);
+    }
+
+    /// Returns maximum distance to observer.
+    pub fn max_distance(&self) -> Option<f32> {
+        self.max_distance
+    }
+
+    /// Returns fade distance.
+    pub fn distance_fade(&self) -> f32 {
+        self.distance_fade
+    }
+
+    /// Returns transition time.
+    pub fn transition_time(&self) -> f32 {
+        self.transition_time
+    }
+
+    /// Returns current intensity multiplier in (0..1) range, zero means that light is
+    /// switched off.
+    pub fn factor(&self) -> f32 {
+        self.factor
+    }
+
+    /// Calculates desired intensity multiplier for given time of day and distance to
+    /// observer. Distance is ignored if it is None.
+    pub fn target_factor(&self, time_of_day: f32, distance: Option<f32>) -> f32 {
+        let time_factor = self
+            .time_window
+            .map_or(1.0, |w| w.factor(wrap_hours(time_of_day)));
+        let distance_factor = match (self.max_distance, distance) {
+            (Some(max_distance), Some(distance)) => {
+                if distance >= max_distance {
+                    0.0
+                } else if self.distance_fade > 0.0 {
+                    ((max_distance - distance) / self.distance_fade).min(1.0)
+                } else {
+                    1.0
+                }
+            }
+            _ => 1.0,
+        };
+        time_factor * distance_factor
+    }
+
+    /// Moves current factor towards target factor taking transition time into account.
+    pub fn update(&mut self, dt: f32, time_of_day: f32, distance: Option<f32>) {
+        let target = self.target_factor(time_of_day, distance);
+        if self.transition_time > 0.0 {
+            let step = dt / self.transition_time;
+            if self.factor < target {
+                self.factor = (self.factor + step).min(target);
+            } else {
+                self.factor = (self.factor - step).max(target);
+            }
+        } else {
+            self.factor = target;
+        }
+    }
+}
+
+impl Visit for LightRule {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut has_time_window = self.time_window.is_some();
+        has_time_window.visit("HasTimeWindow", visitor)?;
+        let mut window = self.time_window.unwrap_or(TimeWindow {
+            from: 0.0,
+            to: 0.0,
+            fade: 0.0,
+        });
+        window.from.visit("From", visitor)?;
+        window.to.visit("To", visitor)?;
+        window.fade.visit("TimeFade", visitor)?;
+        if visitor.is_reading() {
+            self.time_window = if has_time_window { Some(window) } else { None };
+        }
+
+        self.max_distance.visit("MaxDistance", visitor)?;
+        self.distance_fade.visit("DistanceFade", visitor)?;
+        self.transition_time.visit("TransitionTime", visitor)?;
+        self.factor.visit("Factor", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+fn observer_position(graph: &Graph, observer: Handle<Node>) -> Option<Vec3> {
+    if observer.is_some() && graph.is_valid_handle(observer) {
+        return Some(graph[observer].global_position());
+    }
+    graph.linear_iter().find_map(|node| match node {
+        Node::Camera(camera) if camera.is_enabled() => Some(camera.global_position()),
+        _ => None,
+    })
+}
+
+/// Updates light rules of every light in the graph. Global transforms must be calculated
+/// before this call.
+pub(in crate) fn update_light_rules(graph: &mut Graph, settings: &LightRuleSettings, dt: f32) {
+    if !settings.enabled {
+        return;
+    }
+
+    let observer = observer_position(graph, settings.observer);
+    for node in graph.linear_iter_mut() {
+        if let Node::Light(light) = node {
+            let distance = observer.map(|p| p.distance(&light.global_position()));
+            light.update_rule(dt, settings.time_of_day, distance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::light_rule::LightRule;
+
+    #[test]
+    fn test_time_window_wraps_midnight() {
+        let rule = LightRule::default().with_time_window(19.0, 6.0, 1.0);
+        assert_eq!(rule.target_factor(12.0, None), 0.0);
+        assert_eq!(rule.target_factor(23.0, None), 1.0);
+        assert_eq!(rule.target_factor(2.0, None), 1.0);
+        assert!((rule.target_factor(19.5, None) - 0.5).abs() < 0.001);
+        assert!((rule.target_factor(5.5, None) - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_distance_fade() {
+        let rule = LightRule::default().with_max_distance(50.0, 10.0);
+        assert_eq!(rule.target_factor(0.0, Some(10.0)), 1.0);
+        assert!((rule.target_factor(0.0, Some(45.0)) - 0.5).abs() < 0.001);
+        assert_eq!(rule.target_factor(0.0, Some(60.0)), 0.0);
+        // No observer - distance is not checked.
+        assert_eq!(rule.target_factor(0.0, None), 1.0);
+    }
+
+    #[test]
+    fn test_transition() {
+        let mut rule = LightRule::default()
+            .with_max_distance(50.0, 0.0)
+            .with_transition_time(2.0);
+        rule.update(1.0, 0.0, Some(100.0));
+        assert!((rule.factor() - 0.5).abs() < 0.001);
+        rule.update(1.0, 0.0, Some(100.0));
+        assert_eq!(rule.factor(), 0.0);
+    }
+}
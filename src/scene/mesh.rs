@@ -13,13 +13,15 @@ use crate::scene::node::Node;
 use crate::{
     core::{
         color::Color,
-        math::{aabb::AxisAlignedBoundingBox, frustum::Frustum},
+        math::{aabb::AxisAlignedBoundingBox, frustum::Frustum, mat4::Mat4},
         visitor::{Visit, VisitResult, Visitor},
     },
-    renderer::surface::Surface,
-    scene::{base::Base, base::BaseBuilder, graph::Graph},
+    renderer::surface::{Surface, VertexPaintBrush},
+    scene::{
+        base::Base, base::BaseBuilder, graph::Graph, instance_variation::InstanceVariation,
+        lenient, spline::SplineMesh, terrain::Terrain,
+    },
 };
-use rg3d_core::math::mat4::Mat4;
 use std::{
     cell::Cell,
     ops::{Deref, DerefMut},
@@ -32,6 +34,9 @@ pub struct Mesh {
     surfaces: Vec<Surface>,
     bounding_box: Cell<AxisAlignedBoundingBox>,
     bounding_box_dirty: Cell<bool>,
+    spline_mesh: Option<SplineMesh>,
+    terrain: Option<Terrain>,
+    instance_variation: InstanceVariation,
 }
 
 impl Default for Mesh {
@@ -41,6 +46,9 @@ impl Default for Mesh {
             surfaces: Default::default(),
             bounding_box: Default::default(),
             bounding_box_dirty: Cell::new(true),
+            spline_mesh: None,
+            terrain: None,
+            instance_variation: Default::default(),
         }
     }
 }
@@ -67,7 +75,10 @@ impl Visit for Mesh {
 
         // Serialize surfaces, but keep in mind that surfaces from resources will be automatically
         // recreated on resolve stage! Serialization of surfaces needed for procedural surfaces.
-        self.surfaces.visit("Surfaces", visitor)?;
+        lenient::field(&mut self.surfaces, "Surfaces", visitor)?;
+        let _ = self.spline_mesh.visit("SplineMesh", visitor);
+        let _ = self.terrain.visit("Terrain", visitor);
+        let _ = self.instance_variation.visit("InstanceVariation", visitor);
 
         visitor.leave_region()
     }
@@ -108,6 +119,118 @@ impl Mesh {
         }
     }
 
+    /// Paints vertex colors of every surface of mesh using given brush. Position of brush
+    /// is given in world coordinates. Modified vertices will be automatically uploaded to
+    /// GPU on next frame. Returns amount of painted vertices. Can be used to make snow
+    /// trails, blood pools, wet areas and so on.
+    ///
+    /// # Notes
+    ///
+    /// Surface data can be shared across multiple instances of same model, so painting one
+    /// instance will affect all other instances. Also painted data of a surface that was
+    /// loaded from a resource is *not* serialized, only procedural surfaces keep it.
+    pub fn paint(&mut self, brush: &VertexPaintBrush) -> usize {
+        let global_transform = self.instance_transform();
+        let mut count = 0;
+        for surface in self.surfaces.iter() {
+            count += surface
+                .data()
+                .lock()
+                .unwrap()
+                .paint(global_transform, brush);
+        }
+        count
+    }
+
+    /// Sets or removes spline modifier. Surfaces of mesh will be replaced with geometry
+    /// generated by modifier on next update. See `spline` module docs for more info.
+    #[inline]
+    pub fn set_spline_mesh(&mut self, spline_mesh: Option<SplineMesh>) {
+        self.spline_mesh = spline_mesh.map(|mut spline_mesh| {
+            spline_mesh.invalidate();
+            spline_mesh
+        });
+    }
+
+    /// Returns spline modifier, if any.
+    #[inline]
+    pub fn spline_mesh(&self) -> Option<&SplineMesh> {
+        self.spline_mesh.as_ref()
+    }
+
+    /// Returns spline modifier, if any. Changes of spline or its settings will cause
+    /// regeneration of surfaces on next update.
+    #[inline]
+    pub fn spline_mesh_mut(&mut self) -> Option<&mut SplineMesh> {
+        self.spline_mesh.as_mut()
+    }
+
+    /// Sets or removes terrain modifier. Surfaces of mesh will be replaced with terrain
+    /// chunks on next update. See `terrain` module docs for more info.
+    #[inline]
+    pub fn set_terrain(&mut self, terrain: Option<Terrain>) {
+        self.terrain = terrain.map(|mut terrain| {
+            terrain.invalidate();
+            terrain
+        });
+    }
+
+    /// Returns terrain modifier, if any.
+    #[inline]
+    pub fn terrain(&self) -> Option<&Terrain> {
+        self.terrain.as_ref()
+    }
+
+    /// Returns terrain modifier, if any. Changes of heightmap or size will cause
+    /// regeneration of surfaces on next update.
+    #[inline]
+    pub fn terrain_mut(&mut self) -> Option<&mut Terrain> {
+        self.terrain.as_mut()
+    }
+
+    /// Sets per-instance variation of mesh. See `instance_variation` module docs for more
+    /// info.
+    #[inline]
+    pub fn set_instance_variation(&mut self, variation: InstanceVariation) {
+        self.instance_variation = variation;
+    }
+
+    /// Returns per-instance variation of mesh.
+    #[inline]
+    pub fn instance_variation(&self) -> &InstanceVariation {
+        &self.instance_variation
+    }
+
+    /// Returns global transform of mesh with scale of instance variation applied, this
+    /// is the transform which is used for rendering.
+    #[inline]
+    pub fn instance_transform(&self) -> Mat4 {
+        let scale = self.instance_variation.scale();
+        if scale == 1.0 {
+            self.global_transform()
+        } else {
+            self.global_transform() * Mat4::scale(Vec3::new(scale, scale, scale))
+        }
+    }
+
+    /// Regenerates surfaces if spline or terrain modifier was changed.
+    pub(in crate) fn update_modifiers(&mut self) {
+        if let Some(spline_mesh) = self.spline_mesh.as_mut() {
+            if spline_mesh.is_dirty() {
+                self.surfaces = spline_mesh.generate();
+                spline_mesh.reset_dirty();
+                self.bounding_box_dirty.set(true);
+            }
+        }
+        if let Some(terrain) = self.terrain.as_mut() {
+            if terrain.is_dirty() {
+                self.surfaces = terrain.generate();
+                terrain.reset_dirty();
+                self.bounding_box_dirty.set(true);
+            }
+        }
+    }
+
     /// Performs lazy bounding box evaluation. Bounding box presented in *local coordinates*
     /// WARNING: This method does *not* includes bounds of bones!
     pub fn bounding_box(&self) -> AxisAlignedBoundingBox {
@@ -130,11 +253,12 @@ impl Mesh {
     /// intended to use every frame! WARNING: This method does *not* includes bounds of bones!
     pub fn world_bounding_box(&self) -> AxisAlignedBoundingBox {
         let mut bounding_box = AxisAlignedBoundingBox::default();
+        let transform = self.instance_transform();
         for surface in self.surfaces.iter() {
             let data = surface.data();
             let data = data.lock().unwrap();
             for vertex in data.get_vertices() {
-                bounding_box.add_point(self.global_transform().transform_vector(vertex.position));
+                bounding_box.add_point(transform.transform_vector(vertex.position));
             }
         }
         bounding_box
@@ -144,27 +268,20 @@ impl Mesh {
     /// is very heavy and not intended to use every frame!
     pub fn full_world_bounding_box(&self, graph: &Graph) -> AxisAlignedBoundingBox {
         let mut bounding_box = AxisAlignedBoundingBox::default();
+        let transform = self.instance_transform();
         for surface in self.surfaces.iter() {
             let data = surface.data();
             let data = data.lock().unwrap();
             if surface.bones().is_empty() {
                 for vertex in data.get_vertices() {
-                    bounding_box
-                        .add_point(self.global_transform().transform_vector(vertex.position));
+                    bounding_box.add_point(transform.transform_vector(vertex.position));
                 }
             } else {
                 // Special case for skinned surface. Its actual bounds defined only by bones
                 // influence.
 
                 // Precalculate bone matrices first to speed up calculations.
-                let bone_matrices = surface
-                    .bones()
-                    .iter()
-                    .map(|&b| {
-                        let bone_node = &graph[b];
-                        bone_node.global_transform() * bone_node.inv_bind_pose_transform()
-                    })
-                    .collect::<Vec<Mat4>>();
+                let bone_matrices = surface.bone_matrices(graph);
 
                 for vertex in data.get_vertices() {
                     let mut position = Vec3::ZERO;
@@ -187,7 +304,7 @@ impl Mesh {
     /// Mesh is considered visible if its bounding box visible by frustum, or if any bones
     /// position is inside frustum.
     pub fn is_intersect_frustum(&self, graph: &Graph, frustum: &Frustum) -> bool {
-        if frustum.is_intersects_aabb_transform(&self.bounding_box(), &self.global_transform) {
+        if frustum.is_intersects_aabb_transform(&self.bounding_box(), &self.instance_transform()) {
             return true;
         }
 
@@ -207,6 +324,9 @@ impl Mesh {
 pub struct MeshBuilder {
     base_builder: BaseBuilder,
     surfaces: Vec<Surface>,
+    spline_mesh: Option<SplineMesh>,
+    terrain: Option<Terrain>,
+    instance_variation: InstanceVariation,
 }
 
 impl MeshBuilder {
@@ -215,6 +335,9 @@ impl MeshBuilder {
         Self {
             base_builder,
             surfaces: Default::default(),
+            spline_mesh: None,
+            terrain: None,
+            instance_variation: Default::default(),
         }
     }
 
@@ -224,14 +347,38 @@ impl MeshBuilder {
         self
     }
 
+    /// Sets spline modifier, surfaces of mesh will be generated from it on first update.
+    pub fn with_spline_mesh(mut self, spline_mesh: SplineMesh) -> Self {
+        self.spline_mesh = Some(spline_mesh);
+        self
+    }
+
+    /// Sets terrain modifier, surfaces of mesh will be generated from it on first update.
+    pub fn with_terrain(mut self, terrain: Terrain) -> Self {
+        self.terrain = Some(terrain);
+        self
+    }
+
+    /// Sets per-instance variation.
+    pub fn with_instance_variation(mut self, variation: InstanceVariation) -> Self {
+        self.instance_variation = variation;
+        self
+    }
+
     /// Creates new mesh.
     pub fn build(self) -> Mesh {
-        Mesh {
+        let mut mesh = Mesh {
             base: self.base_builder.build(),
             surfaces: self.surfaces,
             bounding_box: Default::default(),
             bounding_box_dirty: Cell::new(true),
-        }
+            spline_mesh: None,
+            terrain: None,
+            instance_variation: self.instance_variation,
+        };
+        mesh.set_spline_mesh(self.spline_mesh);
+        mesh.set_terrain(self.terrain);
+        mesh
     }
 
     /// Creates new node instance.
@@ -6,34 +6,73 @@
 
 pub mod base;
 pub mod camera;
+pub mod color_blindness;
+pub mod command;
+pub mod delta;
+pub mod diff;
 pub mod graph;
+pub mod instance_variation;
+pub mod layer;
+pub mod lenient;
 pub mod light;
+pub mod light_animation;
+pub mod light_rule;
 pub mod mesh;
 pub mod node;
 pub mod particle_system;
+pub mod ragdoll;
+pub mod sleep;
+pub mod spline;
 pub mod sprite;
+pub mod surface_material;
+pub mod terrain;
 pub mod transform;
 
 use crate::{
-    animation::AnimationContainer,
+    animation::{foot_ik::FootIkContainer, hit_reaction::HitReactionContainer, AnimationContainer},
     core::{
-        math::vec2::Vec2,
-        pool::{Handle, Pool, PoolIterator, PoolIteratorMut},
+        math::{ray::Ray, vec2::Vec2, vec3::Vec3, TriangleDefinition},
+        pool::{Handle, Pool, PoolIterator, PoolIteratorMut, PoolPairIterator},
         visitor::{Visit, VisitError, VisitResult, Visitor},
     },
-    engine::resource_manager::ResourceManager,
-    physics::{rigid_body::RigidBody, Physics},
+    engine::{resource_manager::ResourceManager, PauseFlags},
+    physics::{rigid_body::RigidBody, HitKind, Physics, RayCastOptions},
+    renderer::surface::Vertex,
     resource::texture::Texture,
-    scene::{graph::Graph, node::Node},
-    utils::{lightmap::Lightmap, log::Log},
+    scene::{
+        graph::Graph,
+        layer::RenderLayers,
+        light_rule::{self, LightRuleSettings},
+        node::Node,
+        particle_system::Particle,
+        ragdoll::RagdollContainer,
+        sleep::SleepController,
+        surface_material::{self, SurfaceMaterialLibrary, SurfaceQueryResult, SurfaceSource},
+    },
+    utils::{
+        handle_debug::{HandleDebug, HandleScope},
+        light_probe::LightProbeGrid,
+        lightmap::Lightmap,
+        log::Log,
+    },
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ops::{Index, IndexMut},
     path::Path,
     sync::{Arc, Mutex},
 };
 
+/// Version of scene format, it is written in every saved scene. Scenes saved by newer
+/// version of the engine are rejected on load with clear error instead of reading
+/// garbage. Scenes without version are treated as version 0.
+///
+/// Note that binary layout of the data is defined by `Visitor` of rg3d-core, so byte
+/// order, magic header and field name hashing are not handled here: scene version
+/// detects scenes of newer format, but not corrupted files or files written on machines
+/// with different byte order.
+pub const SCENE_FORMAT_VERSION: u32 = 1;
+
 /// Physics binder is used to link graph nodes with rigid bodies. Scene will
 /// sync transform of node with its associated rigid body.
 #[derive(Clone, Debug)]
@@ -112,6 +151,180 @@ impl Visit for PhysicsBinder {
     }
 }
 
+/// Physics settings of a scene. Every scene has its own settings, so scenes with
+/// different physics (i.e. space level and ground level) can coexist.
+#[derive(Copy, Clone, Debug)]
+pub struct PhysicsSettings {
+    /// Gravity which will be applied to every rigid body linked with a scene node.
+    /// If None, bodies will use their own gravity. Default is None.
+    pub gravity: Option<Vec3>,
+    /// Fixed time step (in seconds) of physics simulation. If set, physics will be
+    /// stepped with exactly this time step as many times as needed to catch up with
+    /// frame time, this makes simulation stable and independent of frame rate. If
+    /// None, physics will be stepped once per update with frame time. Default is None.
+    pub timestep: Option<f32>,
+    /// Maximum amount of fixed steps per single update. Prevents "spiral of death"
+    /// when simulation is slower than real time. Default is 8.
+    pub max_steps_per_update: u32,
+    /// Amount of sub-steps per step. Each step is split into this amount of physics
+    /// steps with time step divided by amount of sub-steps, more sub-steps gives more
+    /// stable contacts for the price of performance. Default is 1.
+    pub sub_steps: u32,
+    /// Whether resting bodies linked with scene nodes can fall asleep or not. See
+    /// `sleep` module docs for more info. Default is false.
+    pub sleeping_enabled: bool,
+    /// Speed (in units per second) below which body is considered resting. Default
+    /// is 0.05.
+    pub sleep_velocity_threshold: f32,
+    /// Time (in seconds) that body must be resting to fall asleep. Default is 0.5.
+    pub sleep_time_threshold: f32,
+    /// Angular speed (in radians per second) of node rotation below which body is
+    /// considered resting. Default is 0.1.
+    pub sleep_angular_velocity_threshold: f32,
+    /// Distance at which moving body wakes up sleeping one. Also defines which sleeping
+    /// bodies form an island. Should be bigger than size of bodies plus distance that
+    /// bodies travel per step. Default is 1.0.
+    pub wake_distance: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            gravity: None,
+            timestep: None,
+            max_steps_per_update: 8,
+            sub_steps: 1,
+            sleeping_enabled: false,
+            sleep_velocity_threshold: 0.05,
+            sleep_time_threshold: 0.5,
+            sleep_angular_velocity_threshold: 0.1,
+            wake_distance: 1.0,
+        }
+    }
+}
+
+impl Visit for PhysicsSettings {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.gravity.visit("Gravity", visitor)?;
+        self.timestep.visit("Timestep", visitor)?;
+        self.max_steps_per_update
+            .visit("MaxStepsPerUpdate", visitor)?;
+        self.sub_steps.visit("SubSteps", visitor)?;
+        self.sleep_velocity_threshold
+            .visit("SleepVelocityThreshold", visitor)?;
+        self.sleep_time_threshold
+            .visit("SleepTimeThreshold", visitor)?;
+        let _ = self.sleeping_enabled.visit("SleepingEnabled", visitor);
+        let _ = self
+            .sleep_angular_velocity_threshold
+            .visit("SleepAngularVelocityThreshold", visitor);
+        let _ = self.wake_distance.visit("WakeDistance", visitor);
+
+        visitor.leave_region()
+    }
+}
+
+/// Statistics of a scene. Can be used for runtime budgeting or to show debug info.
+/// See `Scene::statistics` for more info.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SceneStatistics {
+    /// Total amount of nodes in the scene.
+    pub nodes: usize,
+    /// Amount of base nodes (pivots).
+    pub base_nodes: usize,
+    /// Amount of light sources.
+    pub lights: usize,
+    /// Amount of cameras.
+    pub cameras: usize,
+    /// Amount of meshes.
+    pub meshes: usize,
+    /// Amount of sprites.
+    pub sprites: usize,
+    /// Amount of particle systems.
+    pub particle_systems: usize,
+    /// Total amount of surfaces of all meshes.
+    pub surfaces: usize,
+    /// Total amount of triangles of all surfaces. Shared surface data is counted once
+    /// per each surface that uses it.
+    pub triangles: usize,
+    /// Total amount of vertices of all surfaces. Shared surface data is counted once
+    /// per each surface that uses it.
+    pub vertices: usize,
+    /// Total amount of alive particles in all particle systems.
+    pub alive_particles: usize,
+    /// Amount of animations.
+    pub animations: usize,
+    /// Amount of rigid bodies linked with scene nodes, including sleeping ones.
+    pub rigid_bodies: usize,
+    /// Amount of sleeping rigid bodies, they are out of physics world and do not
+    /// consume solver time.
+    pub sleeping_rigid_bodies: usize,
+    /// Approximate amount of memory (in bytes) occupied by nodes.
+    pub node_memory: usize,
+    /// Approximate amount of memory (in bytes) occupied by unique surface data.
+    pub geometry_memory: usize,
+    /// Approximate amount of memory (in bytes) occupied by unique textures used in the
+    /// scene.
+    pub texture_memory: usize,
+    /// Approximate amount of memory (in bytes) occupied by particles.
+    pub particle_memory: usize,
+}
+
+impl SceneStatistics {
+    /// Returns approximate total amount of memory (in bytes) used by the scene.
+    pub fn total_memory(&self) -> usize {
+        self.node_memory + self.geometry_memory + self.texture_memory + self.particle_memory
+    }
+}
+
+/// Defines which rendering pipeline is used to draw a scene.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RenderPath {
+    /// Full deferred pipeline with shadows, SSAO and any amount of lights. This is
+    /// default path.
+    Deferred,
+    /// Lightweight forward pipeline for small and simple scenes. It has much lower fixed
+    /// cost per frame, but each mesh is lit by at most 8 lights, and there are no
+    /// shadows, no SSAO and no volumetric light scattering.
+    Forward,
+}
+
+impl Default for RenderPath {
+    fn default() -> Self {
+        RenderPath::Deferred
+    }
+}
+
+impl RenderPath {
+    fn new(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(RenderPath::Deferred),
+            1 => Ok(RenderPath::Forward),
+            _ => Err(format!("Invalid render path {}", id)),
+        }
+    }
+
+    fn id(self) -> u32 {
+        match self {
+            RenderPath::Deferred => 0,
+            RenderPath::Forward => 1,
+        }
+    }
+}
+
+impl Visit for RenderPath {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id = self.id();
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = RenderPath::new(id)?;
+        }
+        Ok(())
+    }
+}
+
 /// See module docs.
 #[derive(Debug)]
 pub struct Scene {
@@ -141,7 +354,44 @@ pub struct Scene {
     /// in real-time strategies, in other words there are plenty of possible uses.
     pub render_target: Option<Arc<Mutex<Texture>>>,
 
+    /// Rendering pipeline which is used to draw the scene. See [`RenderPath`] docs for
+    /// more info.
+    pub render_path: RenderPath,
+
     lightmap: Option<Lightmap>,
+
+    light_probes: Option<LightProbeGrid>,
+
+    /// Named render layers of the scene. See `layer` module docs for more info.
+    pub layers: RenderLayers,
+
+    /// Physics settings of the scene. See [`PhysicsSettings`] docs for more info.
+    pub physics_settings: PhysicsSettings,
+
+    /// Time which was not yet simulated by fixed time step physics.
+    physics_time_accumulator: f32,
+
+    sleep_controller: SleepController,
+
+    /// Library of surface materials of the scene. See `surface_material` module docs
+    /// for more info.
+    pub surface_materials: SurfaceMaterialLibrary,
+
+    /// Settings of light rules, including current time of day. See `light_rule` module
+    /// docs for more info.
+    pub light_rules: LightRuleSettings,
+
+    /// Ragdoll chains which blend animated and physical poses of bones. See `ragdoll`
+    /// module docs for more info.
+    pub ragdolls: RagdollContainer,
+
+    /// Procedural hit reactions layered over animations. See `hit_reaction` module docs
+    /// for more info.
+    pub hit_reactions: HitReactionContainer,
+
+    /// Foot placement of characters on uneven ground. See `foot_ik` module docs for more
+    /// info.
+    pub foot_ik: FootIkContainer,
 }
 
 impl Default for Scene {
@@ -152,7 +402,18 @@ impl Default for Scene {
             physics: Default::default(),
             physics_binder: Default::default(),
             render_target: None,
+            render_path: Default::default(),
             lightmap: None,
+            light_probes: None,
+            layers: Default::default(),
+            physics_settings: Default::default(),
+            physics_time_accumulator: 0.0,
+            sleep_controller: Default::default(),
+            surface_materials: Default::default(),
+            light_rules: Default::default(),
+            ragdolls: Default::default(),
+            hit_reactions: Default::default(),
+            foot_ik: Default::default(),
         }
     }
 }
@@ -173,7 +434,18 @@ impl Scene {
             animations: Default::default(),
             physics_binder: Default::default(),
             render_target: None,
+            render_path: Default::default(),
             lightmap: None,
+            light_probes: None,
+            layers: Default::default(),
+            physics_settings: Default::default(),
+            physics_time_accumulator: 0.0,
+            sleep_controller: Default::default(),
+            surface_materials: Default::default(),
+            light_rules: Default::default(),
+            ragdolls: Default::default(),
+            hit_reactions: Default::default(),
+            foot_ik: Default::default(),
         }
     }
 
@@ -190,30 +462,98 @@ impl Scene {
         // Restore pointers to resources. Scene saves only paths to resources, here we must
         // find real resources instead.
         for node in scene.graph.linear_iter_mut() {
-            if let Some(shallow_resource) = node.resource.clone() {
-                node.resource =
-                    resource_manager.request_model(&shallow_resource.lock().unwrap().path);
-            }
+            node.restore_resources(resource_manager);
+        }
+        if let Some(lightmap) = scene.lightmap.as_mut() {
+            lightmap.restore_resources(resource_manager);
         }
 
         // And do resolve to extract correct graphical data and so on.
         scene.resolve();
 
+        // Resolve copies surfaces from models, so lightmap textures must be assigned again.
+        if let Some(lightmap) = scene.lightmap.take() {
+            if let Err(e) = scene.set_lightmap(lightmap) {
+                Log::writeln(format!("Unable to restore lightmap! Reason: {}", e));
+            }
+        }
+
         Ok(scene)
     }
 
+    /// Same as [`from_file`](#method.from_file), but recovers from errors caused by unknown
+    /// node kinds and missing fields instead of failing, which allows to load scenes made
+    /// by newer versions of engine or partially corrupted scenes. Returns scene together
+    /// with report about every recovered error, see [lenient](lenient/index.html) module
+    /// docs for more info.
+    pub fn from_file_lenient<P: AsRef<Path>>(
+        path: P,
+        resource_manager: &mut ResourceManager,
+    ) -> Result<(Self, LoadReport), VisitError> {
+        let (result, report) = lenient::run(|| Self::from_file(path, resource_manager));
+        result.map(|scene| (scene, report))
+    }
+
     fn update_physics(&mut self, dt: f32) {
-        self.physics.step(dt);
+        let settings = self.physics_settings;
 
-        // Keep pair when node and body are both alive.
+        // Keep pair when node and body are both alive. Body of alive node must be removed
+        // by unbinding, otherwise it is most likely a bug.
         let graph = &self.graph;
-        let physics = &self.physics;
+        let physics = &mut self.physics;
         self.physics_binder
             .node_rigid_body_map
             .retain(|node, body| {
-                graph.is_valid_handle(*node) && physics.is_valid_body_handle(*body)
+                let valid_body = physics.is_valid_body_handle(*body);
+                if !valid_body && graph.is_valid_handle(*node) {
+                    HandleDebug::report_invalid(graph.debug_scope(), *body);
+                }
+                graph.is_valid_handle(*node) && valid_body
             });
 
+        self.surface_materials.apply(physics);
+
+        if let Some(gravity) = settings.gravity {
+            for body in self.physics_binder.node_rigid_body_map.values() {
+                physics.borrow_body_mut(*body).set_gravity(gravity);
+            }
+        }
+
+        let sub_steps = settings.sub_steps.max(1);
+        let step = |physics: &mut Physics, timestep: f32| {
+            let sub_step = timestep / sub_steps as f32;
+            for _ in 0..sub_steps {
+                physics.step(sub_step);
+            }
+        };
+
+        match settings.timestep {
+            Some(timestep) if timestep > 0.0 => {
+                self.physics_time_accumulator += dt;
+                let mut steps = 0;
+                while self.physics_time_accumulator >= timestep
+                    && steps < settings.max_steps_per_update
+                {
+                    step(physics, timestep);
+                    self.physics_time_accumulator -= timestep;
+                    steps += 1;
+                }
+                // Drop time that cannot be simulated in this update.
+                if steps == settings.max_steps_per_update {
+                    self.physics_time_accumulator = self.physics_time_accumulator.min(timestep);
+                }
+            }
+            _ => step(physics, dt),
+        }
+
+        self.sleep_controller.update(
+            physics,
+            &mut self.physics_binder,
+            &self.graph,
+            &settings,
+            dt,
+        );
+
         // Sync node positions with assigned physics bodies
         for (node, body) in self.physics_binder.node_rigid_body_map.iter() {
             let body = physics.borrow_body(*body);
@@ -223,6 +563,110 @@ impl Scene {
         }
     }
 
+    /// Wakes up sleeping rigid body linked with given node and every body of its island.
+    /// Returns handle of the body, it should be used to apply impulses and so on. See
+    /// `sleep` module docs for more info.
+    pub fn wake_body(&mut self, node: Handle<Node>) -> Handle<RigidBody> {
+        self.sleep_controller.wake(
+            node,
+            &mut self.physics,
+            &mut self.physics_binder,
+            &self.graph,
+            &self.physics_settings,
+        )
+    }
+
+    /// Returns true if rigid body linked with given node is sleeping.
+    pub fn is_body_sleeping(&self, node: Handle<Node>) -> bool {
+        self.sleep_controller.is_sleeping(node)
+    }
+
+    /// Casts given ray and returns information about closest surface hit by the ray,
+    /// including its surface material. Both mesh surfaces and physics colliders (rigid
+    /// bodies and static geometries) are tested. Ray is infinite for meshes, colliders
+    /// are tested along the length of ray direction vector, as physics does. Hits behind
+    /// ray origin are ignored. Invisible meshes are ignored too.
+    ///
+    /// # Performance
+    ///
+    /// This method tests every triangle of every mesh which bounding box is intersected
+    /// by the ray, so it is better to not call it too often.
+    pub fn query_surface(&self, ray: &Ray) -> Option<SurfaceQueryResult> {
+        // (distance, source, normal)
+        let mut closest: Option<(f32, SurfaceSource, Vec3)> = None;
+        let dir_len = ray.dir.len();
+        for (handle, node) in self.graph.pair_iter() {
+            if let Node::Mesh(mesh) = node {
+                if !mesh.global_visibility() {
+                    continue;
+                }
+                if let Some((t, surface, normal)) =
+                    surface_material::ray_mesh_intersection(ray, mesh)
+                {
+                    let distance = dir_len * t;
+                    if closest.map_or(true, |(closest_distance, ..)| distance < closest_distance) {
+                        let source = SurfaceSource::Mesh {
+                            node: handle,
+                            surface,
+                        };
+                        closest = Some((distance, source, normal));
+                    }
+                }
+            }
+        }
+
+        let mut hits = Vec::new();
+        self.physics.ray_cast(
+            ray,
+            RayCastOptions {
+                ignore_bodies: false,
+                ignore_static_geometries: false,
+                sort_results: true,
+            },
+            &mut hits,
+        );
+        if let Some(hit) = hits.first() {
+            let distance = hit.sqr_distance.sqrt();
+            if closest.map_or(true, |(closest_distance, ..)| distance < closest_distance) {
+                let source = match hit.kind {
+                    HitKind::Body(body) => SurfaceSource::Body(body),
+                    HitKind::StaticTriangle {
+                        static_geometry, ..
+                    } => SurfaceSource::StaticGeometry(static_geometry),
+                };
+                closest = Some((distance, source, hit.normal));
+            }
+        }
+
+        closest.map(|(distance, source, normal)| {
+            let material_name = match source {
+                SurfaceSource::Mesh { node, surface } => self.graph[node].as_mesh().surfaces()
+                    [surface]
+                    .material()
+                    .map(|m| m.to_owned()),
+                SurfaceSource::Body(body) => self
+                    .surface_materials
+                    .body_material(body)
+                    .map(|m| m.to_owned()),
+                SurfaceSource::StaticGeometry(static_geometry) => self
+                    .surface_materials
+                    .static_geometry_material(static_geometry)
+                    .map(|m| m.to_owned()),
+            };
+            SurfaceQueryResult {
+                source,
+                position: ray.origin + ray.dir.normalized().unwrap_or_default().scale(distance),
+                normal,
+                distance,
+                material: material_name
+                    .as_ref()
+                    .and_then(|name| self.surface_materials.get(name))
+                    .cloned(),
+                material_name,
+            }
+        })
+    }
+
     /// Removes node from scene with all associated entities, like animations etc.
     ///
     /// # Panics
@@ -261,23 +705,136 @@ impl Scene {
                 }
 
                 for (surface, entry) in mesh.surfaces_mut().iter_mut().zip(lightmaps) {
-                    // This unwrap() call must never panic in normal conditions, because texture wrapped in Option
-                    // only to implement Default trait to be serializable.
-                    let texture = entry.texture.clone().unwrap();
-                    surface.set_lightmap_texture(texture)
+                    // Texture wrapped in Option only to implement Default trait to be serializable,
+                    // but it still can be None if it failed to load.
+                    if let Some(texture) = entry.texture.clone() {
+                        surface.set_lightmap_texture(texture)
+                    }
                 }
             }
         }
         Ok(std::mem::replace(&mut self.lightmap, Some(lightmap)))
     }
 
+    /// Sets new grid of light probes, which will be used to light dynamic objects (objects
+    /// without lightmap) in the scene. Returns previous grid.
+    pub fn set_light_probes(
+        &mut self,
+        light_probes: Option<LightProbeGrid>,
+    ) -> Option<LightProbeGrid> {
+        std::mem::replace(&mut self.light_probes, light_probes)
+    }
+
+    /// Returns shared reference to current grid of light probes.
+    pub fn light_probes(&self) -> Option<&LightProbeGrid> {
+        self.light_probes.as_ref()
+    }
+
+    /// Collects statistics of the scene. Memory values are estimations, they do not
+    /// include memory allocated on GPU and some internal buffers.
+    ///
+    /// # Performance
+    ///
+    /// This method iterates over every node in the scene and locks every surface data
+    /// and texture, so it is better to not call it too often.
+    pub fn statistics(&self) -> SceneStatistics {
+        let mut statistics = SceneStatistics {
+            animations: self.animations.iter().count(),
+            rigid_bodies: self.physics_binder.node_rigid_body_map.len()
+                + self.sleep_controller.sleeping_count(),
+            sleeping_rigid_bodies: self.sleep_controller.sleeping_count(),
+            ..Default::default()
+        };
+
+        // Shared data must be counted only once.
+        let mut unique_data = HashSet::new();
+        let mut unique_textures = HashSet::new();
+        let mut add_texture = |texture: Option<Arc<Mutex<Texture>>>, memory: &mut usize| {
+            if let Some(texture) = texture {
+                if unique_textures.insert(&*texture as *const _ as usize) {
+                    *memory += texture.lock().unwrap().bytes.len();
+                }
+            }
+        };
+
+        for node in self.graph.linear_iter() {
+            statistics.nodes += 1;
+            statistics.node_memory += std::mem::size_of::<Node>();
+            match node {
+                Node::Base(_) => statistics.base_nodes += 1,
+                Node::Light(_) => statistics.lights += 1,
+                Node::Camera(_) => statistics.cameras += 1,
+                Node::Mesh(mesh) => {
+                    statistics.meshes += 1;
+                    for surface in mesh.surfaces() {
+                        statistics.surfaces += 1;
+
+                        let data = surface.data();
+                        let key = &*data as *const _ as usize;
+                        let data = data.lock().unwrap();
+                        statistics.triangles += data.triangles().len();
+                        statistics.vertices += data.get_vertices().len();
+                        if unique_data.insert(key) {
+                            statistics.geometry_memory += data.get_vertices().len()
+                                * std::mem::size_of::<Vertex>()
+                                + data.triangles().len()
+                                    * std::mem::size_of::<TriangleDefinition>();
+                        }
+
+                        add_texture(surface.diffuse_texture(), &mut statistics.texture_memory);
+                        add_texture(surface.normal_texture(), &mut statistics.texture_memory);
+                        add_texture(surface.lightmap_texture(), &mut statistics.texture_memory);
+                        add_texture(surface.orm_texture(), &mut statistics.texture_memory);
+                    }
+                }
+                Node::Sprite(sprite) => {
+                    statistics.sprites += 1;
+                    add_texture(sprite.texture(), &mut statistics.texture_memory);
+                }
+                Node::ParticleSystem(particle_system) => {
+                    statistics.particle_systems += 1;
+                    let alive_particles = particle_system.alive_particle_count();
+                    statistics.alive_particles += alive_particles;
+                    statistics.particle_memory += alive_particles * std::mem::size_of::<Particle>();
+                    add_texture(particle_system.texture(), &mut statistics.texture_memory);
+                }
+            }
+        }
+
+        statistics
+    }
+
     /// Performs single update tick with given delta time from last frame. Internally
     /// it updates physics, animations, and each graph node. In most cases there is
     /// no need to call it directly, engine automatically updates all available scenes.
     pub fn update(&mut self, frame_size: Vec2, dt: f32) {
-        self.update_physics(dt);
-        self.animations.update_animations(dt);
-        self.graph.update_nodes(frame_size, dt);
+        self.update_with_pause(frame_size, dt, PauseFlags::NONE);
+    }
+
+    /// Performs single update tick skipping paused subsystems, see `Engine::set_paused`.
+    pub(in crate) fn update_with_pause(&mut self, frame_size: Vec2, dt: f32, paused: PauseFlags) {
+        if !paused.contains(PauseFlags::PHYSICS) {
+            self.update_physics(dt);
+        }
+        if !paused.contains(PauseFlags::ANIMATION) {
+            self.animations.update_animations(dt);
+            self.foot_ik.reset_post_transforms(&mut self.graph);
+            self.hit_reactions.reset_post_transforms(&mut self.graph);
+        }
+        if !paused.contains(PauseFlags::PHYSICS) {
+            self.ragdolls.reset_post_transforms(&mut self.graph);
+        }
+        self.graph.update_nodes_internal(frame_size, dt, paused);
+        if !paused.contains(PauseFlags::ANIMATION) {
+            self.foot_ik.update(&mut self.graph, &self.physics, dt);
+            self.hit_reactions.update(&mut self.graph, dt);
+        }
+        if !paused.contains(PauseFlags::PHYSICS) {
+            self.ragdolls.update(&mut self.graph, &mut self.physics, dt);
+        }
+        if !paused.contains(PauseFlags::ANIMATION) {
+            light_rule::update_light_rules(&mut self.graph, &self.light_rules, dt);
+        }
     }
 
     /// Creates deep copy of a scene, filter predicate allows you to filter out nodes
@@ -312,7 +869,24 @@ impl Scene {
             physics,
             physics_binder,
             render_target: Default::default(),
+            render_path: self.render_path,
             lightmap: self.lightmap.clone(),
+            light_probes: self.light_probes.clone(),
+            layers: self.layers.clone(),
+            physics_settings: self.physics_settings,
+            physics_time_accumulator: 0.0,
+            sleep_controller: self.sleep_controller.remap(&old_new_map),
+            surface_materials: self.surface_materials.clone(),
+            light_rules: LightRuleSettings {
+                observer: old_new_map
+                    .get(&self.light_rules.observer)
+                    .cloned()
+                    .unwrap_or_default(),
+                ..self.light_rules
+            },
+            ragdolls: self.ragdolls.remap(&old_new_map),
+            hit_reactions: self.hit_reactions.remap(&old_new_map),
+            foot_ik: self.foot_ik.remap(&old_new_map),
         }
     }
 }
@@ -320,11 +894,40 @@ impl Scene {
 impl Visit for Scene {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
-        self.physics_binder.visit("PhysicsBinder", visitor)?;
+
+        let mut version = if visitor.is_reading() {
+            0
+        } else {
+            SCENE_FORMAT_VERSION
+        };
+        let _ = version.visit("FormatVersion", visitor);
+        if version > SCENE_FORMAT_VERSION {
+            let message = format!(
+                "Scene format version {} is not supported, maximum supported version is {}",
+                version, SCENE_FORMAT_VERSION
+            );
+            if lenient::is_enabled() {
+                lenient::warn(message);
+            } else {
+                return Err(VisitError::from(message));
+            }
+        }
+
+        lenient::field(&mut self.physics_binder, "PhysicsBinder", visitor)?;
         self.graph.visit("Graph", visitor)?;
-        self.animations.visit("Animations", visitor)?;
-        self.physics.visit("Physics", visitor)?;
+        lenient::field(&mut self.animations, "Animations", visitor)?;
+        lenient::field(&mut self.physics, "Physics", visitor)?;
         let _ = self.lightmap.visit("Lightmap", visitor);
+        let _ = self.light_probes.visit("LightProbes", visitor);
+        let _ = self.layers.visit("Layers", visitor);
+        let _ = self.physics_settings.visit("PhysicsSettings", visitor);
+        let _ = self.surface_materials.visit("SurfaceMaterials", visitor);
+        let _ = self.render_path.visit("RenderPath", visitor);
+        let _ = self.light_rules.visit("LightRules", visitor);
+        let _ = self.ragdolls.visit("Ragdolls", visitor);
+        let _ = self.hit_reactions.visit("HitReactions", visitor);
+        let _ = self.foot_ik.visit("FootIk", visitor);
+        let _ = self.sleep_controller.visit("SleepController", visitor);
         visitor.leave_region()
     }
 }
@@ -351,15 +954,32 @@ impl SceneContainer {
         self.pool.iter_mut()
     }
 
+    /// Creates new iterator over scenes in container which gives (handle; scene) pairs.
+    #[inline]
+    pub fn pair_iter(&self) -> PoolPairIterator<Scene> {
+        self.pool.pair_iter()
+    }
+
+    /// Returns true if given handle points to a scene in container.
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Scene>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
     /// Adds new scene into container.
     #[inline]
     pub fn add(&mut self, scene: Scene) -> Handle<Scene> {
-        self.pool.spawn(scene)
+        let handle = self.pool.spawn(scene);
+        HandleDebug::register(HandleScope::GLOBAL, handle, "Scene");
+        handle
     }
 
     /// Removes all scenes from container.
     #[inline]
     pub fn clear(&mut self) {
+        for (handle, _) in self.pool.pair_iter() {
+            HandleDebug::unregister(HandleScope::GLOBAL, handle);
+        }
         self.pool.clear()
     }
 
@@ -367,6 +987,7 @@ impl SceneContainer {
     #[inline]
     pub fn remove(&mut self, handle: Handle<Scene>) {
         self.pool.free(handle);
+        HandleDebug::unregister(HandleScope::GLOBAL, handle);
     }
 }
 
@@ -375,6 +996,9 @@ impl Index<Handle<Scene>> for SceneContainer {
 
     #[inline]
     fn index(&self, index: Handle<Scene>) -> &Self::Output {
+        if HandleDebug::is_enabled() && !self.pool.is_valid_handle(index) {
+            HandleDebug::report_invalid(HandleScope::GLOBAL, index);
+        }
         &self.pool[index]
     }
 }
@@ -382,6 +1006,9 @@ impl Index<Handle<Scene>> for SceneContainer {
 impl IndexMut<Handle<Scene>> for SceneContainer {
     #[inline]
     fn index_mut(&mut self, index: Handle<Scene>) -> &mut Self::Output {
+        if HandleDebug::is_enabled() && !self.pool.is_valid_handle(index) {
+            HandleDebug::report_invalid(HandleScope::GLOBAL, index);
+        }
         &mut self.pool[index]
     }
 }
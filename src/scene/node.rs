@@ -4,10 +4,11 @@
 
 use crate::{
     core::define_is_as,
-    core::visitor::{Visit, VisitResult, Visitor},
+    core::visitor::{Visit, VisitError, VisitResult, Visitor},
+    engine::resource_manager::ResourceManager,
     scene::{
-        base::Base, camera::Camera, light::Light, mesh::Mesh, particle_system::ParticleSystem,
-        sprite::Sprite,
+        base::Base, camera::Camera, lenient, light::Light, mesh::Mesh,
+        particle_system::ParticleSystem, sprite::Sprite,
     },
 };
 use std::ops::{Deref, DerefMut};
@@ -32,13 +33,39 @@ impl Visit for Node {
         let mut kind_id = self.id();
         kind_id.visit("KindId", visitor)?;
         if visitor.is_reading() {
-            *self = Node::from_id(kind_id)?;
+            match Node::from_id(kind_id) {
+                Ok(node) => *self = node,
+                Err(e) if lenient::is_enabled() => {
+                    *self = Node::Base(read_unknown_kind(kind_id, name, visitor)?);
+                    lenient::warn(format!("{}, node was replaced with base node", e));
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
 
         static_dispatch!(self, visit, name, visitor)
     }
 }
 
+/// Tries to read common part of node of unknown kind. Every kind of node stores its base
+/// as nested region, so name, transform and hierarchy can be restored.
+fn read_unknown_kind(kind_id: u8, name: &str, visitor: &mut Visitor) -> Result<Base, VisitError> {
+    let mut base = Base::default();
+    if visitor.enter_region(name).is_ok() {
+        // Mesh stores base under different name.
+        if lenient::has_region("Base", visitor) {
+            base.visit("Base", visitor)?;
+        } else if lenient::has_region("Common", visitor) {
+            base.visit("Common", visitor)?;
+        } else {
+            lenient::warn(format!("base part of node of kind {} is missing", kind_id));
+        }
+        visitor.leave_region()?;
+    }
+    Ok(base)
+}
+
 /// See module docs.
 #[derive(Clone, Debug)]
 pub enum Node {
@@ -103,6 +130,52 @@ impl Node {
         }
     }
 
+    /// Replaces shallow resources of node (loaded from a save file) with real ones. Save
+    /// files contain only paths of resources, so they must be requested from resource
+    /// manager again.
+    pub(in crate) fn restore_resources(&mut self, resource_manager: &mut ResourceManager) {
+        if let Some(shallow_resource) = self.resource.clone() {
+            self.resource = resource_manager.request_model(&shallow_resource.lock().unwrap().path);
+        }
+
+        match self {
+            Node::Mesh(mesh) => {
+                for surface in mesh.surfaces_mut() {
+                    surface.restore_resources(resource_manager);
+                }
+                // Geometry of spline source is stored in model resource, like geometry of
+                // surfaces, so it is taken from mesh with same name in the resource.
+                let resource_spline_mesh = mesh.resource().and_then(|model| {
+                    let model = model.lock().unwrap();
+                    let graph = &model.get_scene().graph;
+                    let handle = model.find_node_by_name(mesh.name());
+                    if graph.is_valid_handle(handle) {
+                        if let Node::Mesh(resource_mesh) = &graph[handle] {
+                            return resource_mesh.spline_mesh().cloned();
+                        }
+                    }
+                    None
+                });
+                if let Some(spline_mesh) = mesh.spline_mesh_mut() {
+                    if let Some(resource_spline_mesh) = resource_spline_mesh.as_ref() {
+                        spline_mesh.restore_source(resource_spline_mesh);
+                    }
+                    for surface in spline_mesh.source_mut() {
+                        surface.restore_resources(resource_manager);
+                    }
+                }
+                if let Some(terrain) = mesh.terrain_mut() {
+                    terrain.restore_resources(resource_manager);
+                }
+            }
+            Node::Sprite(sprite) => sprite.restore_resources(resource_manager),
+            Node::ParticleSystem(particle_system) => {
+                particle_system.restore_resources(resource_manager)
+            }
+            Node::Base(_) | Node::Light(_) | Node::Camera(_) => {}
+        }
+    }
+
     /// Returns actual variant id.
     pub fn id(&self) -> u8 {
         match self {
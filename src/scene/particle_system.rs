@@ -77,8 +77,12 @@ use crate::{
         numeric_range::NumericRange,
         visitor::{Visit, VisitResult, Visitor},
     },
+    engine::resource_manager::ResourceManager,
     resource::texture::Texture,
-    scene::base::{Base, BaseBuilder},
+    scene::{
+        base::{Base, BaseBuilder},
+        lenient,
+    },
 };
 use rand::Rng;
 use std::{
@@ -1097,6 +1101,16 @@ impl ParticleSystem {
         self.emitters.push(emitter)
     }
 
+    /// Returns shared reference to array of emitters.
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    /// Returns amount of alive particles in particle system.
+    pub fn alive_particle_count(&self) -> usize {
+        self.particles.len() - self.free_particles.len()
+    }
+
     /// Returns current acceleration for particles in particle system.
     pub fn acceleration(&self) -> Vec3 {
         self.acceleration
@@ -1266,18 +1280,26 @@ impl ParticleSystem {
     pub fn texture(&self) -> Option<Arc<Mutex<Texture>>> {
         self.texture.clone()
     }
+
+    pub(in crate) fn restore_resources(&mut self, resource_manager: &mut ResourceManager) {
+        if let Some(shallow_texture) = self.texture.clone() {
+            self.texture = resource_manager.restore_texture(&shallow_texture);
+        }
+    }
 }
 
 impl Visit for ParticleSystem {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
 
-        self.particles.visit("Particles", visitor)?;
-        self.free_particles.visit("FreeParticles", visitor)?;
-        self.texture.visit("Texture", visitor)?;
-        self.emitters.visit("Emitters", visitor)?;
-        self.acceleration.visit("Acceleration", visitor)?;
-        self.color_over_lifetime.visit("ColorGradient", visitor)?;
+        // Alive particles and state of emitters are serialized, so effect will continue
+        // from where it was saved.
+        lenient::field(&mut self.particles, "Particles", visitor)?;
+        lenient::field(&mut self.free_particles, "FreeParticles", visitor)?;
+        lenient::field(&mut self.texture, "Texture", visitor)?;
+        lenient::field(&mut self.emitters, "Emitters", visitor)?;
+        lenient::field(&mut self.acceleration, "Acceleration", visitor)?;
+        lenient::field(&mut self.color_over_lifetime, "ColorGradient", visitor)?;
         self.base.visit("Base", visitor)?;
 
         visitor.leave_region()
@@ -0,0 +1,374 @@
+//! Ragdoll blending - partial physics takeover of skinned mesh bones.
+//!
+//! Ragdoll chain is a sequence of bones where every next bone is a child of previous one,
+//! for example shoulder - elbow - wrist. Every bone of chain has a small sphere body in
+//! physics world. Chain has blend weight: with weight 0 bones are fully driven by animation
+//! and bodies just follow bones, with weight 1 bones are fully driven by bodies, any weight
+//! in between blends these two poses. Weight moves smoothly towards target weight, so an
+//! arm can go limp after a hit and recover later without popping, while rest of the
+//! skeleton continues playing its animation.
+//!
+//! Root of chain can be pinned to its animated position (default), so limp arm stays
+//! attached to shoulder, or it can be free so whole chain falls (full ragdoll of a
+//! character is a set of chains with free root of spine chain).
+//!
+//! Physics has no joints, so bodies of chain are connected by distance constraints of
+//! animated bone lengths, which are solved by moving bodies directly (position based
+//! dynamics). Physics integrates moved bodies, so chain swings and collides naturally.
+//! Every bone of chain (except last one) is rotated so it points to body of next bone. Last
+//! bone of chain (i.e. wrist) follows its parent.
+//!
+//! Ragdoll chain is a bone controller, it is updated last and composes its rotations with
+//! adjustments of foot IK and hit reactions, see
+//! [bone_controller](../../animation/bone_controller/index.html) module docs.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::pool::Handle,
+//!     scene::{node::Node, ragdoll::RagdollChain, Scene},
+//! };
+//!
+//! fn make_arm_limp(scene: &mut Scene, arm: [Handle<Node>; 3]) {
+//!     // Shoulder, elbow and wrist.
+//!     let chain = RagdollChain::new(arm.to_vec()).with_blend_speed(3.0);
+//!     let chain = scene.ragdolls.add(chain, &mut scene.physics, &scene.graph);
+//!     // Weight will move to 1.0 smoothly.
+//!     scene.ragdolls.get_mut(chain).go_limp();
+//! }
+//! ```
+
+use crate::{
+    animation::bone_controller::{
+        rotate_bone, rotation_between, transform_bone, BoneController, BoneControllerContainer,
+    },
+    core::{
+        math::{mat4::Mat4, quat::Quat, vec3::Vec3},
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    physics::{
+        convex_shape::{ConvexShape, SphereShape},
+        rigid_body::RigidBody,
+        Physics,
+    },
+    scene::{graph::Graph, node::Node},
+    utils::handle_debug::HandleDebug,
+};
+use std::collections::HashMap;
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct RagdollChain {
+    bones: Vec<Handle<Node>>,
+    bodies: Vec<Handle<RigidBody>>,
+    radius: f32,
+    weight: f32,
+    target_weight: f32,
+    blend_speed: f32,
+    pinned_root: bool,
+}
+
+impl Default for RagdollChain {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl Visit for RagdollChain {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.bones.visit("Bones", visitor)?;
+        self.bodies.visit("Bodies", visitor)?;
+        self.radius.visit("Radius", visitor)?;
+        self.weight.visit("Weight", visitor)?;
+        self.target_weight.visit("TargetWeight", visitor)?;
+        self.blend_speed.visit("BlendSpeed", visitor)?;
+        self.pinned_root.visit("PinnedRoot", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl RagdollChain {
+    /// Creates new chain of given bones, every next bone must be a child of previous one.
+    /// Chain is fully driven by animation.
+    pub fn new(bones: Vec<Handle<Node>>) -> Self {
+        Self {
+            bones,
+            bodies: Vec::new(),
+            radius: 0.05,
+            weight: 0.0,
+            target_weight: 0.0,
+            blend_speed: 4.0,
+            pinned_root: true,
+        }
+    }
+
+    /// Sets radius of sphere bodies of bones. Must be set before chain is added to
+    /// container.
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius.max(0.001);
+        self
+    }
+
+    /// Sets speed of blend weight change in units per second.
+    pub fn with_blend_speed(mut self, speed: f32) -> Self {
+        self.set_blend_speed(speed);
+        self
+    }
+
+    /// Sets whether root of chain is pinned to its animated position.
+    pub fn with_pinned_root(mut self, pinned: bool) -> Self {
+        self.pinned_root = pinned;
+        self
+    }
+
+    /// Returns bones of chain from root to tip.
+    pub fn bones(&self) -> &[Handle<Node>] {
+        &self.bones
+    }
+
+    /// Returns bodies of bones, order matches order of bones. Empty until chain is added
+    /// to container.
+    pub fn bodies(&self) -> &[Handle<RigidBody>] {
+        &self.bodies
+    }
+
+    /// Returns current blend weight, 0 - animation, 1 - physics.
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Sets blend weight immediately, without smooth transition.
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight.max(0.0).min(1.0);
+        self.target_weight = self.weight;
+    }
+
+    /// Returns weight to which current weight is moving.
+    pub fn target_weight(&self) -> f32 {
+        self.target_weight
+    }
+
+    /// Sets weight to which current weight will move with blend speed.
+    pub fn set_target_weight(&mut self, weight: f32) {
+        self.target_weight = weight.max(0.0).min(1.0);
+    }
+
+    /// Sets speed of blend weight change in units per second.
+    pub fn set_blend_speed(&mut self, speed: f32) {
+        self.blend_speed = speed.max(0.0);
+    }
+
+    /// Returns speed of blend weight change in units per second.
+    pub fn blend_speed(&self) -> f32 {
+        self.blend_speed
+    }
+
+    /// Smoothly gives chain to physics.
+    pub fn go_limp(&mut self) {
+        self.set_target_weight(1.0);
+    }
+
+    /// Smoothly gives chain back to animation.
+    pub fn recover(&mut self) {
+        self.set_target_weight(0.0);
+    }
+
+    /// Returns true if chain is fully driven by animation.
+    pub fn is_animated(&self) -> bool {
+        self.weight <= 0.0 && self.target_weight <= 0.0
+    }
+
+    fn advance_weight(&mut self, dt: f32) {
+        let max_step = self.blend_speed * dt;
+        let delta = self.target_weight - self.weight;
+        self.weight += delta.max(-max_step).min(max_step);
+    }
+
+    /// Keeps bodies at given bone lengths from each other. Pinned root is immovable, any
+    /// other pair of bodies shares correction equally.
+    fn solve_constraints(&self, physics: &mut Physics, lengths: &[f32]) {
+        for _ in 0..SOLVER_ITERATIONS {
+            for (i, &length) in lengths.iter().enumerate() {
+                let (parent, child) = (self.bodies[i], self.bodies[i + 1]);
+                let offset = physics.borrow_body(child).get_position()
+                    - physics.borrow_body(parent).get_position();
+                let correction = match offset.normalized() {
+                    Some(direction) => direction.scale(length) - offset,
+                    None => continue,
+                };
+                if i == 0 && self.pinned_root {
+                    physics.borrow_body_mut(child).move_by(correction);
+                } else {
+                    physics
+                        .borrow_body_mut(child)
+                        .move_by(correction.scale(0.5));
+                    physics
+                        .borrow_body_mut(parent)
+                        .move_by(correction.scale(-0.5));
+                }
+            }
+        }
+    }
+}
+
+impl BoneController for RagdollChain {
+    fn controlled_bones(&self) -> Vec<Handle<Node>> {
+        self.bones.clone()
+    }
+
+    fn remap(&self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) -> Option<Self> {
+        let bones = self
+            .bones
+            .iter()
+            .map(|bone| old_new_map.get(bone).copied())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            bones,
+            ..self.clone()
+        })
+    }
+}
+
+/// Amount of iterations of distance constraints solver, more iterations give stiffer
+/// chains.
+const SOLVER_ITERATIONS: usize = 4;
+
+/// Container of ragdoll chains of a scene.
+pub type RagdollContainer = BoneControllerContainer<RagdollChain>;
+
+impl RagdollContainer {
+    /// Adds new chain and creates bodies for its bones at current positions of bones.
+    pub fn add(
+        &mut self,
+        mut chain: RagdollChain,
+        physics: &mut Physics,
+        graph: &Graph,
+    ) -> Handle<RagdollChain> {
+        chain.bodies = chain
+            .bones
+            .iter()
+            .map(|&bone| {
+                let mut body = RigidBody::new(ConvexShape::Sphere(SphereShape::new(chain.radius)));
+                body.set_position(graph[bone].global_position());
+                let body = physics.add_body(body);
+                HandleDebug::register(
+                    graph.debug_scope(),
+                    body,
+                    &format!("Ragdoll body of {}", graph[bone].name()),
+                );
+                body
+            })
+            .collect();
+        self.spawn(chain)
+    }
+
+    /// Removes chain, its bodies are removed from physics and bones are given back to
+    /// animation immediately.
+    pub fn remove(
+        &mut self,
+        handle: Handle<RagdollChain>,
+        physics: &mut Physics,
+        graph: &mut Graph,
+    ) -> RagdollChain {
+        let mut chain = self.free(handle, graph);
+        for body in chain.bodies.drain(..) {
+            if physics.is_valid_body_handle(body) {
+                physics.remove_body(body);
+                HandleDebug::unregister(graph.debug_scope(), body);
+            }
+        }
+        chain
+    }
+
+    /// Blends animated and physical poses of every chain, see module docs.
+    pub(in crate) fn update(&mut self, graph: &mut Graph, physics: &mut Physics, dt: f32) {
+        for chain in self.iter_mut() {
+            if chain.bones.iter().any(|&bone| !graph.is_valid_handle(bone))
+                || chain
+                    .bodies
+                    .iter()
+                    .any(|&body| !physics.is_valid_body_handle(body))
+            {
+                continue;
+            }
+
+            chain.advance_weight(dt);
+
+            let root = match chain.bones.first() {
+                Some(&root) => root,
+                None => continue,
+            };
+
+            let animated = chain
+                .bones
+                .iter()
+                .map(|&bone| graph[bone].global_position())
+                .collect::<Vec<_>>();
+
+            if chain.is_animated() {
+                // Bodies follow animation, so physics takes over from current pose.
+                for (&body, &position) in chain.bodies.iter().zip(animated.iter()) {
+                    physics.borrow_body_mut(body).set_position(position);
+                }
+                continue;
+            }
+
+            if chain.pinned_root {
+                physics
+                    .borrow_body_mut(chain.bodies[0])
+                    .set_position(animated[0]);
+            }
+
+            let lengths = animated
+                .windows(2)
+                .map(|pair| pair[0].distance(&pair[1]))
+                .collect::<Vec<_>>();
+            chain.solve_constraints(physics, &lengths);
+
+            if !chain.pinned_root {
+                let offset = physics.borrow_body(chain.bodies[0]).get_position() - animated[0];
+                transform_bone(graph, root, Mat4::translate(offset.scale(chain.weight)));
+            }
+
+            // Rotate every bone to its body of next bone, each rotation moves descendants,
+            // so current positions are used.
+            for i in 0..chain.bones.len().saturating_sub(1) {
+                let bone = chain.bones[i];
+                let pivot = graph[bone].global_position();
+                let current = graph[chain.bones[i + 1]].global_position() - pivot;
+                let target = physics.borrow_body(chain.bodies[i + 1]).get_position()
+                    - physics.borrow_body(chain.bodies[i]).get_position();
+                if let Some(rotation) = rotation_between(current, target) {
+                    rotate_bone(graph, bone, Quat::IDENTITY.nlerp(&rotation, chain.weight));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{core::pool::Handle, scene::ragdoll::RagdollChain};
+
+    #[test]
+    fn test_blend_weight() {
+        let mut chain = RagdollChain::new(vec![Handle::NONE; 2]).with_blend_speed(2.0);
+        assert!(chain.is_animated());
+        chain.go_limp();
+        assert!(!chain.is_animated());
+        chain.advance_weight(0.25);
+        assert!((chain.weight() - 0.5).abs() < 0.001);
+        chain.advance_weight(1.0);
+        assert_eq!(chain.weight(), 1.0);
+        chain.recover();
+        chain.advance_weight(0.1);
+        assert!((chain.weight() - 0.8).abs() < 0.001);
+        chain.set_weight(0.0);
+        assert!(chain.is_animated());
+    }
+}
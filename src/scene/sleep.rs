@@ -0,0 +1,287 @@
+//! Contains sleeping of rigid bodies linked with scene nodes.
+//!
+//! Rigid body which rests (moves and rotates slower than sleep thresholds) long enough
+//! falls asleep - it is temporarily taken out of physics world, so it is not stepped and
+//! does not consume any solver time. Its node keeps the last position of the body.
+//! Sleeping body is woken up when some moving body comes closer than wake distance (so
+//! it is back in the world before the contact happens), or when it is explicitly woken
+//! up by `Scene::wake_body` (i.e. before applying an impulse).
+//!
+//! # Islands
+//!
+//! Sleeping bodies which are closer to each other than wake distance form an island,
+//! waking any body of an island wakes every body of it. This prevents stacks of bodies
+//! from being woken up partially, which would leave "floating" bodies.
+//!
+//! # Handles
+//!
+//! Since sleeping body is removed from physics world, its handle becomes invalid and
+//! node is unlinked from body in physics binder. When body is woken up, it receives new
+//! handle and node is linked with it again. This means that you should not store body
+//! handles of nodes for a long time, use `PhysicsBinder::body_of` or `Scene::wake_body`
+//! instead. For the same reason `Physics::ray_cast` does not hit sleeping bodies, but
+//! `Scene::ray_cast` still hits meshes of their nodes.
+
+use crate::{
+    core::{
+        math::{quat::Quat, vec3::Vec3},
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    physics::{rigid_body::RigidBody, Physics},
+    scene::{graph::Graph, node::Node, PhysicsBinder, PhysicsSettings},
+    utils::handle_debug::HandleDebug,
+};
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug)]
+struct RestState {
+    last_position: Vec3,
+    last_rotation: Quat,
+    rest_time: f32,
+}
+
+/// Returns angle (in radians) between two rotations.
+fn angle_between(a: Quat, b: Quat) -> f32 {
+    let dot = (a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w).abs();
+    2.0 * dot.min(1.0).acos()
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub(in crate) struct SleepController {
+    rest_states: HashMap<Handle<Node>, RestState>,
+    /// Bodies that were taken out of physics world.
+    sleeping: HashMap<Handle<Node>, RigidBody>,
+}
+
+impl Visit for SleepController {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.sleeping.visit("Sleeping", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl SleepController {
+    /// Updates rest states of bodies and puts resting bodies to sleep or wakes up bodies
+    /// approached by moving ones. Must be called after physics step.
+    pub fn update(
+        &mut self,
+        physics: &mut Physics,
+        binder: &mut PhysicsBinder,
+        graph: &Graph,
+        settings: &PhysicsSettings,
+        dt: f32,
+    ) {
+        self.sleeping.retain(|node, _| graph.is_valid_handle(*node));
+        self.rest_states
+            .retain(|node, _| binder.node_rigid_body_map.contains_key(node));
+
+        if !settings.sleeping_enabled {
+            self.wake_all(physics, binder, graph, settings);
+            return;
+        }
+
+        let mut resting = Vec::new();
+        let mut moving = Vec::new();
+        for (&node, &body) in binder.node_rigid_body_map.iter() {
+            let position = physics.borrow_body(body).get_position();
+            let rotation = graph[node].local_transform().rotation();
+            let state = self.rest_states.entry(node).or_insert(RestState {
+                last_position: position,
+                last_rotation: rotation,
+                rest_time: 0.0,
+            });
+            let (speed, angular_speed) = if dt > 0.0 {
+                (
+                    position.distance(&state.last_position) / dt,
+                    angle_between(rotation, state.last_rotation) / dt,
+                )
+            } else {
+                (0.0, 0.0)
+            };
+            state.last_position = position;
+            state.last_rotation = rotation;
+            if speed < settings.sleep_velocity_threshold
+                && angular_speed < settings.sleep_angular_velocity_threshold
+            {
+                state.rest_time += dt;
+                if state.rest_time >= settings.sleep_time_threshold {
+                    resting.push((node, body));
+                }
+            } else {
+                state.rest_time = 0.0;
+                moving.push(position);
+            }
+        }
+
+        // Wake up bodies which are about to be touched by moving ones. Sleeping bodies
+        // are not in physics world, so there are no contacts with them. Resting bodies
+        // do not wake anything, otherwise neighbours in a stack would wake each other
+        // forever.
+        let mut approached = Vec::new();
+        for position in moving {
+            for (&node, sleeping) in self.sleeping.iter() {
+                if position.distance(&sleeping.get_position()) < settings.wake_distance {
+                    approached.push(node);
+                }
+            }
+        }
+        for node in approached {
+            self.wake(node, physics, binder, graph, settings);
+        }
+
+        for (node, body) in resting {
+            let mut sleeping = physics.borrow_body(body).clone();
+            // Teleport body to its own position to drop its velocity.
+            sleeping.set_position(sleeping.get_position());
+            physics.remove_body(body);
+            HandleDebug::unregister(graph.debug_scope(), body);
+            binder.unbind(node);
+            self.sleeping.insert(node, sleeping);
+            self.rest_states.remove(&node);
+        }
+    }
+
+    /// Wakes up body of given node with its whole island. Returns handle of the body,
+    /// or handle of current body of the node if it was not sleeping. New handles are
+    /// registered for handle debugging in scope of the graph.
+    pub fn wake(
+        &mut self,
+        node: Handle<Node>,
+        physics: &mut Physics,
+        binder: &mut PhysicsBinder,
+        graph: &Graph,
+        settings: &PhysicsSettings,
+    ) -> Handle<RigidBody> {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if let Some(body) = self.sleeping.remove(&node) {
+                // Wake up every sleeping body close to this one.
+                let position = body.get_position();
+                for (&other, other_body) in self.sleeping.iter() {
+                    if position.distance(&other_body.get_position()) < settings.wake_distance {
+                        stack.push(other);
+                    }
+                }
+
+                // Node could be linked with another body while it was sleeping.
+                if binder.body_of(node).is_none() {
+                    let body = physics.add_body(body);
+                    if graph.is_valid_handle(node) {
+                        HandleDebug::register(
+                            graph.debug_scope(),
+                            body,
+                            &format!("Body of {}", graph[node].name()),
+                        );
+                    }
+                    binder.bind(node, body);
+                }
+            }
+        }
+        binder.body_of(node)
+    }
+
+    /// Wakes up every sleeping body.
+    pub fn wake_all(
+        &mut self,
+        physics: &mut Physics,
+        binder: &mut PhysicsBinder,
+        graph: &Graph,
+        settings: &PhysicsSettings,
+    ) {
+        let sleeping = self.sleeping.keys().copied().collect::<Vec<_>>();
+        for node in sleeping {
+            self.wake(node, physics, binder, graph, settings);
+        }
+    }
+
+    /// Returns true if body of given node is sleeping.
+    pub fn is_sleeping(&self, node: Handle<Node>) -> bool {
+        self.sleeping.contains_key(&node)
+    }
+
+    /// Returns amount of sleeping bodies.
+    pub fn sleeping_count(&self) -> usize {
+        self.sleeping.len()
+    }
+
+    /// Creates copy of controller with remapped nodes, bodies of nodes which are not
+    /// in the map are dropped.
+    pub fn remap(&self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) -> Self {
+        Self {
+            rest_states: Default::default(),
+            sleeping: self
+                .sleeping
+                .iter()
+                .filter_map(|(node, body)| {
+                    old_new_map
+                        .get(node)
+                        .map(|new_node| (*new_node, body.clone()))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        physics::{
+            convex_shape::{ConvexShape, SphereShape},
+            rigid_body::RigidBody,
+            Physics,
+        },
+        scene::{
+            base::Base, graph::Graph, node::Node, sleep::SleepController, PhysicsBinder,
+            PhysicsSettings,
+        },
+    };
+
+    #[test]
+    fn test_sleeping_bodies_are_not_stepped() {
+        let settings = PhysicsSettings {
+            sleeping_enabled: true,
+            ..Default::default()
+        };
+        let mut graph = Graph::new();
+        let mut physics = Physics::default();
+        let mut binder = PhysicsBinder::default();
+        let mut controller = SleepController::default();
+
+        // Row of resting bodies, each one is close enough to its neighbour to form
+        // one island.
+        let mut nodes = Vec::new();
+        for i in 0..3 {
+            let node = graph.add_node(Node::Base(Base::default()));
+            let mut body = RigidBody::new(ConvexShape::Sphere(SphereShape::new(0.25)));
+            body.set_gravity(Vec3::ZERO);
+            body.set_position(Vec3::new(i as f32 * 0.5, 0.0, 0.0));
+            binder.bind(node, physics.add_body(body));
+            nodes.push(node);
+        }
+
+        for _ in 0..10 {
+            physics.step(0.1);
+            controller.update(&mut physics, &mut binder, &graph, &settings, 0.1);
+        }
+
+        // Every body is out of physics world and out of the step.
+        assert_eq!(controller.sleeping_count(), 3);
+        for &node in nodes.iter() {
+            assert!(binder.body_of(node).is_none());
+        }
+
+        // Waking one body wakes whole island.
+        let body = controller.wake(nodes[0], &mut physics, &mut binder, &graph, &settings);
+        assert!(physics.is_valid_body_handle(body));
+        assert_eq!(controller.sleeping_count(), 0);
+        for &node in nodes.iter() {
+            assert!(physics.is_valid_body_handle(binder.body_of(node)));
+        }
+    }
+}
@@ -0,0 +1,720 @@
+//! Splines and meshes deformed along them.
+//!
+//! Spline is a smooth curve (Catmull-Rom) which passes through every control point.
+//! [`SplineMesh`] takes source geometry laid along positive Z axis and bends it along a
+//! spline, this is the way to make roads, pipes, fences, rails and so on. Source geometry
+//! can be either stretched along whole spline or repeated as many times as needed to cover
+//! it. Geometry is regenerated automatically on next update when spline or settings were
+//! changed.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{
+//!     core::math::vec3::Vec3,
+//!     renderer::surface::Surface,
+//!     scene::{
+//!         base::BaseBuilder,
+//!         mesh::MeshBuilder,
+//!         node::Node,
+//!         spline::{Spline, SplineMesh, SplinePoint, SplineTiling},
+//!     },
+//! };
+//!
+//! fn make_fence(segment: Surface) -> Node {
+//!     let spline = Spline::new(vec![
+//!         SplinePoint::new(Vec3::new(0.0, 0.0, 0.0)),
+//!         SplinePoint::new(Vec3::new(10.0, 0.0, 10.0)),
+//!         SplinePoint::new(Vec3::new(20.0, 0.0, 0.0)),
+//!     ]);
+//!     MeshBuilder::new(BaseBuilder::new())
+//!         .with_spline_mesh(
+//!             SplineMesh::new(spline, vec![segment]).with_tiling(SplineTiling::Repeat),
+//!         )
+//!         .build_node()
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::{vec3::Vec3, vec4::Vec4, TriangleDefinition},
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    renderer::surface::{Surface, SurfaceSharedData, Vertex},
+};
+use std::sync::{Arc, Mutex};
+
+/// Control point of a spline.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct SplinePoint {
+    /// Position of point in local coordinates of owner.
+    pub position: Vec3,
+    /// Additional rotation (in radians) around spline direction at this point, it is
+    /// interpolated between points.
+    pub roll: f32,
+}
+
+impl SplinePoint {
+    /// Creates new control point with zero roll.
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            roll: 0.0,
+        }
+    }
+
+    /// Creates new control point with given roll.
+    pub fn with_roll(position: Vec3, roll: f32) -> Self {
+        Self { position, roll }
+    }
+}
+
+impl Visit for SplinePoint {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.position.visit("Position", visitor)?;
+        self.roll.visit("Roll", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Orthonormal basis on a spline at some distance from its start.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SplineFrame {
+    /// Position on spline.
+    pub position: Vec3,
+    /// Direction of spline.
+    pub forward: Vec3,
+    /// Side vector.
+    pub right: Vec3,
+    /// Up vector, includes roll and banking.
+    pub up: Vec3,
+    /// Distance along spline from its start.
+    pub distance: f32,
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1.scale(2.0)
+        + (p2 - p0).scale(t)
+        + (p0.scale(2.0) - p1.scale(5.0) + p2.scale(4.0) - p3).scale(t2)
+        + (p1.scale(3.0) - p0 - p2.scale(3.0) + p3).scale(t3))
+    .scale(0.5)
+}
+
+fn lerp_vec(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    a + (b - a).scale(t)
+}
+
+/// Smooth curve which passes through every control point.
+#[derive(Clone, Debug, Default)]
+pub struct Spline {
+    points: Vec<SplinePoint>,
+    closed: bool,
+}
+
+impl Spline {
+    /// Creates new open spline with given control points.
+    pub fn new(points: Vec<SplinePoint>) -> Self {
+        Self {
+            points,
+            closed: false,
+        }
+    }
+
+    /// Makes spline closed (looped) or open.
+    pub fn with_closed(mut self, closed: bool) -> Self {
+        self.closed = closed;
+        self
+    }
+
+    /// Returns control points of spline.
+    pub fn points(&self) -> &[SplinePoint] {
+        &self.points
+    }
+
+    /// Returns control points of spline.
+    pub fn points_mut(&mut self) -> &mut Vec<SplinePoint> {
+        &mut self.points
+    }
+
+    /// Makes spline closed (looped) or open.
+    pub fn set_closed(&mut self, closed: bool) {
+        self.closed = closed;
+    }
+
+    /// Returns true if spline is closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Returns amount of segments between control points.
+    pub fn segment_count(&self) -> usize {
+        match self.points.len() {
+            0 | 1 => 0,
+            n if self.closed => n,
+            n => n - 1,
+        }
+    }
+
+    fn point(&self, index: isize) -> SplinePoint {
+        let n = self.points.len() as isize;
+        if self.closed {
+            self.points[index.rem_euclid(n) as usize]
+        } else if index < 0 {
+            // Mirror first point to keep tangent at the start.
+            let a = self.points[0];
+            let b = self.points[1];
+            SplinePoint {
+                position: a.position.scale(2.0) - b.position,
+                roll: a.roll,
+            }
+        } else if index >= n {
+            let a = self.points[n as usize - 1];
+            let b = self.points[n as usize - 2];
+            SplinePoint {
+                position: a.position.scale(2.0) - b.position,
+                roll: a.roll,
+            }
+        } else {
+            self.points[index as usize]
+        }
+    }
+
+    /// Returns position and roll at given segment and local parameter `t` in (0..1) range.
+    /// Segment index must be less than segment count.
+    pub fn evaluate(&self, segment: usize, t: f32) -> (Vec3, f32) {
+        let i = segment as isize;
+        let p0 = self.point(i - 1);
+        let p1 = self.point(i);
+        let p2 = self.point(i + 1);
+        let p3 = self.point(i + 2);
+        (
+            catmull_rom(p0.position, p1.position, p2.position, p3.position, t),
+            p1.roll + (p2.roll - p1.roll) * t,
+        )
+    }
+
+    /// Samples spline with given amount of samples per segment and builds frames with
+    /// distances along spline. Frames are rotation-minimizing, so geometry does not twist
+    /// on curves. `banking` tilts frames into turns, bank angle is `atan(banking * curvature)`.
+    /// Returns empty array if spline has less than two points.
+    pub fn build_frames(&self, samples_per_segment: usize, banking: f32) -> Vec<SplineFrame> {
+        let segment_count = self.segment_count();
+        if segment_count == 0 {
+            return Vec::new();
+        }
+        let samples_per_segment = samples_per_segment.max(1);
+
+        let mut samples = Vec::with_capacity(segment_count * samples_per_segment + 1);
+        for segment in 0..segment_count {
+            for i in 0..samples_per_segment {
+                samples.push(self.evaluate(segment, i as f32 / samples_per_segment as f32));
+            }
+        }
+        samples.push(self.evaluate(segment_count - 1, 1.0));
+
+        let count = samples.len();
+        let forward_at = |i: usize| {
+            let a = samples[i.saturating_sub(1)].0;
+            let b = samples[(i + 1).min(count - 1)].0;
+            (b - a).normalized().unwrap_or(Vec3::LOOK)
+        };
+
+        let mut frames: Vec<SplineFrame> = Vec::with_capacity(count);
+        let mut distance = 0.0;
+        let mut up = Vec3::new(0.0, 1.0, 0.0);
+        let mut prev_forward = forward_at(0);
+        for (i, &(position, roll)) in samples.iter().enumerate() {
+            let forward = forward_at(i);
+            if let Some(prev) = frames.last() {
+                distance += position.distance(&prev.position);
+            }
+
+            // Parallel transport of up vector, it is projected on plane of new forward vector.
+            if i == 0 && up.dot(&forward).abs() > 0.99 {
+                up = Vec3::LOOK;
+            }
+            up = (up - forward.scale(up.dot(&forward)))
+                .normalized()
+                .unwrap_or(up);
+
+            let mut angle = roll;
+            if banking != 0.0 && i > 0 {
+                let step = position.distance(&samples[i - 1].0);
+                if step > std::f32::EPSILON {
+                    // Signed turn around up vector per unit length.
+                    let turn = prev_forward.cross(&forward).dot(&up) / step;
+                    angle += (banking * turn).atan();
+                }
+            }
+            prev_forward = forward;
+
+            let rolled_up = up.scale(angle.cos()) + forward.cross(&up).scale(angle.sin());
+            let right = rolled_up.cross(&forward);
+
+            frames.push(SplineFrame {
+                position,
+                forward,
+                right,
+                up: rolled_up,
+                distance,
+            });
+        }
+
+        frames
+    }
+}
+
+impl Visit for Spline {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.points.visit("Points", visitor)?;
+        self.closed.visit("Closed", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Returns interpolated frame at given distance along spline, distance is clamped to
+/// length of spline. Frames must be built by [`Spline::build_frames`] and must not be empty.
+pub fn frame_at(frames: &[SplineFrame], distance: f32) -> SplineFrame {
+    let index = match frames.binary_search_by(|f| f.distance.partial_cmp(&distance).unwrap()) {
+        Ok(index) => return frames[index],
+        Err(index) => index,
+    };
+    if index == 0 {
+        return frames[0];
+    }
+    if index >= frames.len() {
+        return frames[frames.len() - 1];
+    }
+    let a = &frames[index - 1];
+    let b = &frames[index];
+    let span = b.distance - a.distance;
+    let t = if span > 0.0 {
+        (distance - a.distance) / span
+    } else {
+        0.0
+    };
+    let forward = lerp_vec(a.forward, b.forward, t)
+        .normalized()
+        .unwrap_or(a.forward);
+    let up = lerp_vec(a.up, b.up, t).normalized().unwrap_or(a.up);
+    SplineFrame {
+        position: lerp_vec(a.position, b.position, t),
+        forward,
+        right: up.cross(&forward),
+        up,
+        distance,
+    }
+}
+
+/// Defines how source geometry of spline mesh covers spline.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SplineTiling {
+    /// Single copy of source geometry is stretched along whole spline.
+    Stretch,
+    /// Source geometry is repeated along spline, amount of copies is chosen so copies
+    /// are as close as possible to their original length.
+    Repeat,
+}
+
+impl Default for SplineTiling {
+    fn default() -> Self {
+        SplineTiling::Repeat
+    }
+}
+
+impl SplineTiling {
+    fn id(self) -> u32 {
+        match self {
+            SplineTiling::Stretch => 0,
+            SplineTiling::Repeat => 1,
+        }
+    }
+
+    fn new(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(SplineTiling::Stretch),
+            1 => Ok(SplineTiling::Repeat),
+            _ => Err(format!("Invalid spline tiling {}", id)),
+        }
+    }
+}
+
+impl Visit for SplineTiling {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id = self.id();
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = Self::new(id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Mesh modifier which deforms source geometry along a spline. See module docs.
+#[derive(Clone, Debug)]
+pub struct SplineMesh {
+    spline: Spline,
+    source: Vec<Surface>,
+    tiling: SplineTiling,
+    banking: f32,
+    samples_per_segment: usize,
+    dirty: bool,
+}
+
+impl Default for SplineMesh {
+    fn default() -> Self {
+        Self {
+            spline: Default::default(),
+            source: Default::default(),
+            tiling: Default::default(),
+            banking: 0.0,
+            samples_per_segment: Self::DEFAULT_SAMPLES_PER_SEGMENT,
+            dirty: false,
+        }
+    }
+}
+
+impl SplineMesh {
+    /// Default amount of samples per spline segment.
+    pub const DEFAULT_SAMPLES_PER_SEGMENT: usize = 16;
+
+    /// Creates new spline mesh. Source surfaces must be laid along positive Z axis, X is
+    /// side axis and Y is up axis of the spline.
+    ///
+    /// Like surfaces of meshes, source surfaces with non-procedural data are saved as
+    /// references only, their geometry is taken from model resource of the mesh on load.
+    /// Source geometry made in code must be procedural to be saved.
+    pub fn new(spline: Spline, source: Vec<Surface>) -> Self {
+        Self {
+            spline,
+            source,
+            dirty: true,
+            ..Default::default()
+        }
+    }
+
+    /// Sets tiling mode.
+    pub fn with_tiling(mut self, tiling: SplineTiling) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    /// Sets banking factor, see [`Spline::build_frames`].
+    pub fn with_banking(mut self, banking: f32) -> Self {
+        self.banking = banking;
+        self
+    }
+
+    /// Sets amount of samples per spline segment, more samples gives smoother result.
+    pub fn with_samples_per_segment(mut self, samples: usize) -> Self {
+        self.samples_per_segment = samples.max(1);
+        self
+    }
+
+    /// Returns spline.
+    pub fn spline(&self) -> &Spline {
+        &self.spline
+    }
+
+    /// Returns spline, geometry will be regenerated on next update.
+    pub fn spline_mut(&mut self) -> &mut Spline {
+        self.dirty = true;
+        &mut self.spline
+    }
+
+    /// Sets new source surfaces.
+    pub fn set_source(&mut self, source: Vec<Surface>) {
+        self.source = source;
+        self.dirty = true;
+    }
+
+    /// Returns source surfaces.
+    pub fn source(&self) -> &[Surface] {
+        &self.source
+    }
+
+    pub(in crate) fn source_mut(&mut self) -> &mut [Surface] {
+        &mut self.source
+    }
+
+    /// Takes geometry of non-procedural source surfaces from given spline mesh of a model
+    /// resource, it is not stored in save files.
+    pub(in crate) fn restore_source(&mut self, resource_spline_mesh: &SplineMesh) {
+        for (surface, resource_surface) in self.source.iter_mut().zip(resource_spline_mesh.source())
+        {
+            if !surface.data().lock().unwrap().is_procedural() {
+                surface.set_data(resource_surface.data());
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Sets tiling mode.
+    pub fn set_tiling(&mut self, tiling: SplineTiling) {
+        self.tiling = tiling;
+        self.dirty = true;
+    }
+
+    /// Returns tiling mode.
+    pub fn tiling(&self) -> SplineTiling {
+        self.tiling
+    }
+
+    /// Sets banking factor.
+    pub fn set_banking(&mut self, banking: f32) {
+        self.banking = banking;
+        self.dirty = true;
+    }
+
+    /// Returns banking factor.
+    pub fn banking(&self) -> f32 {
+        self.banking
+    }
+
+    /// Forces regeneration of geometry on next update, for example when geometry of
+    /// source surfaces was changed.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Returns true if geometry must be regenerated.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(in crate) fn reset_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    /// Generates deformed surfaces. Every generated surface has same textures and material
+    /// as its source surface, its geometry is not serialized and is regenerated after load.
+    pub fn generate(&self) -> Vec<Surface> {
+        let frames = self
+            .spline
+            .build_frames(self.samples_per_segment, self.banking);
+        let spline_length = match frames.last() {
+            Some(last) if last.distance > std::f32::EPSILON => last.distance,
+            _ => return Vec::new(),
+        };
+
+        let mut min_z = std::f32::MAX;
+        let mut max_z = -std::f32::MAX;
+        for surface in self.source.iter() {
+            let data = surface.data();
+            let data = data.lock().unwrap();
+            for vertex in data.get_vertices() {
+                min_z = min_z.min(vertex.position.z);
+                max_z = max_z.max(vertex.position.z);
+            }
+        }
+        let source_length = max_z - min_z;
+        if source_length <= std::f32::EPSILON {
+            return Vec::new();
+        }
+
+        let tiles = match self.tiling {
+            SplineTiling::Stretch => 1,
+            SplineTiling::Repeat => ((spline_length / source_length).round() as usize).max(1),
+        };
+        let tile_length = spline_length / tiles as f32;
+        let z_scale = tile_length / source_length;
+
+        self.source
+            .iter()
+            .map(|surface| {
+                let data = surface.data();
+                let data = data.lock().unwrap();
+                let source_vertices = data.get_vertices();
+                let source_triangles = data.triangles();
+
+                let mut vertices = Vec::with_capacity(source_vertices.len() * tiles);
+                let mut triangles = Vec::with_capacity(source_triangles.len() * tiles);
+                for tile in 0..tiles {
+                    let offset = tile as f32 * tile_length;
+                    let base_index = vertices.len() as u32;
+                    for vertex in source_vertices {
+                        let frame =
+                            frame_at(&frames, offset + (vertex.position.z - min_z) * z_scale);
+                        // Geometry is scaled by `z_scale` along spline, so directions are
+                        // scaled with it, but normals must be scaled by inverse of it
+                        // (inverse-transpose of the deformation) to stay perpendicular.
+                        let transform = |v: Vec3, forward_scale: f32| {
+                            let v = frame.right.scale(v.x)
+                                + frame.up.scale(v.y)
+                                + frame.forward.scale(v.z * forward_scale);
+                            v.normalized().unwrap_or(v)
+                        };
+                        let tangent = transform(vertex.tangent.xyz(), z_scale);
+                        vertices.push(Vertex {
+                            position: frame.position
+                                + frame.right.scale(vertex.position.x)
+                                + frame.up.scale(vertex.position.y),
+                            normal: transform(vertex.normal, 1.0 / z_scale),
+                            tangent: Vec4::from_vec3(tangent, vertex.tangent.w),
+                            ..*vertex
+                        });
+                    }
+                    for triangle in source_triangles {
+                        triangles.push(TriangleDefinition([
+                            triangle[0] + base_index,
+                            triangle[1] + base_index,
+                            triangle[2] + base_index,
+                        ]));
+                    }
+                }
+
+                let mut result = surface.clone();
+                // Geometry is regenerated from spline after load, so it is not serialized.
+                result.set_data(Arc::new(Mutex::new(SurfaceSharedData::new(
+                    vertices, triangles, false,
+                ))));
+                result
+            })
+            .collect()
+    }
+}
+
+impl Visit for SplineMesh {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.spline.visit("Spline", visitor)?;
+        self.source.visit("Source", visitor)?;
+        self.tiling.visit("Tiling", visitor)?;
+        self.banking.visit("Banking", visitor)?;
+        let mut samples = self.samples_per_segment as u32;
+        samples.visit("SamplesPerSegment", visitor)?;
+        self.samples_per_segment = (samples as usize).max(1);
+
+        if visitor.is_reading() {
+            // Generated geometry is not serialized, regenerate it on next update.
+            self.dirty = true;
+        }
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::{
+            math::{mat4::Mat4, vec3::Vec3},
+            visitor::{Visit, Visitor},
+        },
+        renderer::surface::{Surface, SurfaceSharedData},
+        scene::spline::{frame_at, Spline, SplineMesh, SplinePoint, SplineTiling},
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn straight_spline(length: f32) -> Spline {
+        Spline::new(vec![
+            SplinePoint::new(Vec3::new(0.0, 0.0, 0.0)),
+            SplinePoint::new(Vec3::new(0.0, 0.0, length)),
+        ])
+    }
+
+    #[test]
+    fn test_straight_spline_frames() {
+        let frames = straight_spline(10.0).build_frames(8, 0.0);
+        let last = frames.last().unwrap();
+        assert!((last.distance - 10.0).abs() < 0.001);
+
+        let frame = frame_at(&frames, 2.5);
+        assert!((frame.position.z - 2.5).abs() < 0.001);
+        assert!((frame.up.y - 1.0).abs() < 0.001);
+        assert!((frame.forward.z - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_spline_passes_through_points() {
+        let spline = Spline::new(vec![
+            SplinePoint::new(Vec3::new(0.0, 0.0, 0.0)),
+            SplinePoint::new(Vec3::new(5.0, 0.0, 5.0)),
+            SplinePoint::new(Vec3::new(10.0, 0.0, 0.0)),
+        ]);
+        assert_eq!(spline.segment_count(), 2);
+        let (position, _) = spline.evaluate(1, 0.0);
+        assert!(position.distance(&Vec3::new(5.0, 0.0, 5.0)) < 0.001);
+        assert_eq!(spline.with_closed(true).segment_count(), 3);
+    }
+
+    #[test]
+    fn test_repeat_tiling() {
+        // Unit cube centered at origin, its length along Z is 1.
+        let cube = SurfaceSharedData::make_cube(Mat4::IDENTITY);
+        let vertex_count = cube.get_vertices().len();
+        let source = Surface::new(Arc::new(Mutex::new(cube)));
+
+        let spline_mesh = SplineMesh::new(straight_spline(4.0), vec![source.clone()])
+            .with_tiling(SplineTiling::Repeat);
+        let surfaces = spline_mesh.generate();
+        assert_eq!(surfaces.len(), 1);
+        let data = surfaces[0].data();
+        let data = data.lock().unwrap();
+        assert_eq!(data.get_vertices().len(), vertex_count * 4);
+        let max_z = data
+            .get_vertices()
+            .iter()
+            .map(|v| v.position.z)
+            .fold(0.0f32, f32::max);
+        assert!((max_z - 4.0).abs() < 0.001);
+
+        let stretched = SplineMesh::new(straight_spline(4.0), vec![source])
+            .with_tiling(SplineTiling::Stretch)
+            .generate();
+        assert_eq!(
+            stretched[0].data().lock().unwrap().get_vertices().len(),
+            vertex_count
+        );
+    }
+
+    #[test]
+    fn test_source_geometry_restored_after_load() {
+        let mut cube = SurfaceSharedData::make_cube(Mat4::IDENTITY);
+        // Same as geometry of a model resource.
+        cube.set_procedural(false);
+        let vertex_count = cube.get_vertices().len();
+        let mut spline_mesh = SplineMesh::new(
+            straight_spline(4.0),
+            vec![Surface::new(Arc::new(Mutex::new(cube)))],
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "rg3d_spline_source_restore_{}.bin",
+            std::process::id()
+        ));
+        let mut visitor = Visitor::new();
+        spline_mesh.visit("SplineMesh", &mut visitor).unwrap();
+        visitor.save_binary(&path).unwrap();
+
+        let mut visitor = Visitor::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut loaded = SplineMesh::default();
+        loaded.visit("SplineMesh", &mut visitor).unwrap();
+
+        // Non-procedural source is saved as a reference only.
+        assert!(loaded.source()[0]
+            .data()
+            .lock()
+            .unwrap()
+            .get_vertices()
+            .is_empty());
+
+        loaded.restore_source(&spline_mesh);
+        assert!(loaded.is_dirty());
+        let surfaces = loaded.generate();
+        assert_eq!(
+            surfaces[0].data().lock().unwrap().get_vertices().len(),
+            vertex_count * 4
+        );
+    }
+}
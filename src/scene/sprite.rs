@@ -14,8 +14,12 @@ use crate::{
         color::Color,
         visitor::{Visit, VisitResult, Visitor},
     },
+    engine::resource_manager::ResourceManager,
     resource::texture::Texture,
-    scene::base::{Base, BaseBuilder},
+    scene::{
+        base::{Base, BaseBuilder},
+        lenient,
+    },
 };
 use std::{
     ops::{Deref, DerefMut},
@@ -94,16 +98,22 @@ impl Sprite {
     pub fn texture(&self) -> Option<Arc<Mutex<Texture>>> {
         self.texture.clone()
     }
+
+    pub(in crate) fn restore_resources(&mut self, resource_manager: &mut ResourceManager) {
+        if let Some(shallow_texture) = self.texture.clone() {
+            self.texture = resource_manager.restore_texture(&shallow_texture);
+        }
+    }
 }
 
 impl Visit for Sprite {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
 
-        self.texture.visit("Texture", visitor)?;
-        self.color.visit("Color", visitor)?;
-        self.size.visit("Size", visitor)?;
-        self.rotation.visit("Rotation", visitor)?;
+        lenient::field(&mut self.texture, "Texture", visitor)?;
+        lenient::field(&mut self.color, "Color", visitor)?;
+        lenient::field(&mut self.size, "Size", visitor)?;
+        lenient::field(&mut self.rotation, "Rotation", visitor)?;
         self.base.visit("Base", visitor)?;
 
         visitor.leave_region()
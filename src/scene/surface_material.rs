@@ -0,0 +1,346 @@
+//! Contains all structures and methods to tag mesh surfaces with materials and query
+//! them by rays.
+//!
+//! Surface material is a named set of physical properties, like "wood" or "metal".
+//! Each surface of a mesh, each rigid body and each static geometry can be tagged with
+//! a name of material, scene keeps a library of materials and allows you to query a
+//! material of surface hit by a ray using `Scene::query_surface`. This is useful to vary
+//! footstep sounds, decals, impact particles and so on by surface without any external
+//! lookup tables.
+//!
+//! Friction of material is applied to tagged rigid bodies on every physics update.
+//!
+//! # Limitations
+//!
+//! Skinned surfaces are tested in bind pose. Physics has no restitution and no friction
+//! of static geometry, so elasticity of material and friction of material of static
+//! geometry are only reported by queries.
+
+use crate::{
+    core::{
+        math::{ray::Ray, vec3::Vec3},
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    physics::{rigid_body::RigidBody, static_geometry::StaticGeometry, Physics},
+    scene::{mesh::Mesh, node::Node},
+};
+use std::collections::HashMap;
+
+/// Named set of physical properties of a surface. See module docs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceMaterial {
+    /// Name of material, i.e. "wood", "metal", "concrete", etc. Surfaces are tagged
+    /// with this name.
+    pub name: String,
+    /// Friction coefficient of surface.
+    pub friction: f32,
+    /// Elasticity (bounciness) of surface in [0; 1] range. It is not applied to physics,
+    /// see module docs.
+    pub elasticity: f32,
+    /// Density of material in kg/m³.
+    pub density: f32,
+}
+
+impl Default for SurfaceMaterial {
+    fn default() -> Self {
+        Self {
+            name: Default::default(),
+            friction: 0.5,
+            elasticity: 0.0,
+            density: 1000.0,
+        }
+    }
+}
+
+impl Visit for SurfaceMaterial {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.name.visit("Name", visitor)?;
+        self.friction.visit("Friction", visitor)?;
+        self.elasticity.visit("Elasticity", visitor)?;
+        self.density.visit("Density", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Library of surface materials of a scene.
+#[derive(Clone, Debug, Default)]
+pub struct SurfaceMaterialLibrary {
+    materials: Vec<SurfaceMaterial>,
+    bodies: HashMap<Handle<RigidBody>, String>,
+    static_geometries: HashMap<Handle<StaticGeometry>, String>,
+}
+
+impl Visit for SurfaceMaterialLibrary {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.materials.visit("Materials", visitor)?;
+        let _ = self.bodies.visit("Bodies", visitor);
+        let _ = self.static_geometries.visit("StaticGeometries", visitor);
+
+        visitor.leave_region()
+    }
+}
+
+impl SurfaceMaterialLibrary {
+    /// Adds new material to the library. If there is a material with same name, it
+    /// will be replaced and returned.
+    pub fn add(&mut self, material: SurfaceMaterial) -> Option<SurfaceMaterial> {
+        if let Some(existing) = self.materials.iter_mut().find(|m| m.name == material.name) {
+            Some(std::mem::replace(existing, material))
+        } else {
+            self.materials.push(material);
+            None
+        }
+    }
+
+    /// Removes material with given name from the library.
+    pub fn remove<N: AsRef<str>>(&mut self, name: N) -> Option<SurfaceMaterial> {
+        let index = self
+            .materials
+            .iter()
+            .position(|m| m.name == name.as_ref())?;
+        Some(self.materials.remove(index))
+    }
+
+    /// Returns material with given name.
+    pub fn get<N: AsRef<str>>(&self, name: N) -> Option<&SurfaceMaterial> {
+        self.materials.iter().find(|m| m.name == name.as_ref())
+    }
+
+    /// Returns shared reference to array of materials.
+    pub fn materials(&self) -> &[SurfaceMaterial] {
+        &self.materials
+    }
+
+    /// Tags given rigid body with a name of material. Returns previous name of material
+    /// of the body.
+    pub fn tag_body<N: AsRef<str>>(
+        &mut self,
+        body: Handle<RigidBody>,
+        material: N,
+    ) -> Option<String> {
+        self.bodies.insert(body, material.as_ref().to_owned())
+    }
+
+    /// Removes tag from given rigid body.
+    pub fn untag_body(&mut self, body: Handle<RigidBody>) -> Option<String> {
+        self.bodies.remove(&body)
+    }
+
+    /// Returns name of material of given rigid body.
+    pub fn body_material(&self, body: Handle<RigidBody>) -> Option<&str> {
+        self.bodies.get(&body).map(|name| name.as_str())
+    }
+
+    /// Tags given static geometry with a name of material. Returns previous name of
+    /// material of the geometry.
+    pub fn tag_static_geometry<N: AsRef<str>>(
+        &mut self,
+        static_geometry: Handle<StaticGeometry>,
+        material: N,
+    ) -> Option<String> {
+        self.static_geometries
+            .insert(static_geometry, material.as_ref().to_owned())
+    }
+
+    /// Removes tag from given static geometry.
+    pub fn untag_static_geometry(
+        &mut self,
+        static_geometry: Handle<StaticGeometry>,
+    ) -> Option<String> {
+        self.static_geometries.remove(&static_geometry)
+    }
+
+    /// Returns name of material of given static geometry.
+    pub fn static_geometry_material(
+        &self,
+        static_geometry: Handle<StaticGeometry>,
+    ) -> Option<&str> {
+        self.static_geometries
+            .get(&static_geometry)
+            .map(|name| name.as_str())
+    }
+
+    /// Drops tags of removed bodies and sets friction of tagged bodies from their
+    /// materials.
+    pub(in crate) fn apply(&mut self, physics: &mut Physics) {
+        self.bodies
+            .retain(|body, _| physics.is_valid_body_handle(*body));
+        for (body, name) in self.bodies.iter() {
+            if let Some(material) = self.get(name) {
+                let friction = material.friction;
+                physics
+                    .borrow_body_mut(*body)
+                    .set_friction(Vec3::new(friction, friction, friction));
+            }
+        }
+    }
+}
+
+/// Entity which surface was hit by a ray. See `Scene::query_surface`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SurfaceSource {
+    /// Surface of a mesh.
+    Mesh {
+        /// Handle of mesh node that was hit.
+        node: Handle<Node>,
+        /// Index of surface of the mesh that was hit.
+        surface: usize,
+    },
+    /// Rigid body.
+    Body(Handle<RigidBody>),
+    /// Triangle of static geometry.
+    StaticGeometry(Handle<StaticGeometry>),
+}
+
+/// Result of surface query. See `Scene::query_surface`.
+#[derive(Clone, Debug)]
+pub struct SurfaceQueryResult {
+    /// Entity that was hit.
+    pub source: SurfaceSource,
+    /// World space position of hit point.
+    pub position: Vec3,
+    /// World space normal of hit triangle.
+    pub normal: Vec3,
+    /// Distance from ray origin to hit point.
+    pub distance: f32,
+    /// Name of material of surface, if surface was tagged.
+    pub material_name: Option<String>,
+    /// Material of surface, if surface was tagged and library contains material with
+    /// such name.
+    pub material: Option<SurfaceMaterial>,
+}
+
+/// Returns ray parameter of intersection of a ray with a triangle (Möller–Trumbore).
+pub(in crate) fn ray_triangle_intersection(
+    origin: Vec3,
+    dir: Vec3,
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+) -> Option<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = dir.cross(&ac);
+    let det = ab.dot(&p);
+    if det.abs() < std::f32::EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(&p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+    let q = s.cross(&ab);
+    let v = dir.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(&q) * inv_det;
+    if t >= 0.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+fn ray_aabb_intersects(origin: Vec3, dir: Vec3, min: Vec3, max: Vec3) -> bool {
+    let mut t_min = 0.0f32;
+    let mut t_max = std::f32::MAX;
+    for &(o, d, min, max) in &[
+        (origin.x, dir.x, min.x, max.x),
+        (origin.y, dir.y, min.y, max.y),
+        (origin.z, dir.z, min.z, max.z),
+    ] {
+        if d.abs() < std::f32::EPSILON {
+            if o < min || o > max {
+                return false;
+            }
+        } else {
+            let t1 = (min - o) / d;
+            let t2 = (max - o) / d;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+            if t_min > t_max {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Returns ray parameter, index of surface and world space normal of closest hit of
+/// a ray with given mesh. Mesh is tested with the same transform which is used to render it.
+pub(in crate) fn ray_mesh_intersection(ray: &Ray, mesh: &Mesh) -> Option<(f32, usize, Vec3)> {
+    let instance_transform = mesh.instance_transform();
+    let inv_transform = instance_transform.inverse().ok()?;
+
+    // Do test in local space of mesh, ray parameter is the same in both spaces.
+    let origin = inv_transform.transform_vector(ray.origin);
+    let dir = inv_transform.transform_vector(ray.origin + ray.dir) - origin;
+
+    let bounding_box = mesh.bounding_box();
+    if !ray_aabb_intersects(origin, dir, bounding_box.min, bounding_box.max) {
+        return None;
+    }
+
+    let mut closest: Option<(f32, usize, [Vec3; 3])> = None;
+    for (surface_index, surface) in mesh.surfaces().iter().enumerate() {
+        let data = surface.data();
+        let data = data.lock().unwrap();
+        let vertices = data.get_vertices();
+        for triangle in data.triangles() {
+            let a = vertices[triangle[0] as usize].position;
+            let b = vertices[triangle[1] as usize].position;
+            let c = vertices[triangle[2] as usize].position;
+            if let Some(t) = ray_triangle_intersection(origin, dir, a, b, c) {
+                if closest.map_or(true, |(closest_t, _, _)| t < closest_t) {
+                    closest = Some((t, surface_index, [a, b, c]));
+                }
+            }
+        }
+    }
+
+    closest.map(|(t, surface_index, [a, b, c])| {
+        let a = instance_transform.transform_vector(a);
+        let b = instance_transform.transform_vector(b);
+        let c = instance_transform.transform_vector(c);
+        let normal = (b - a).cross(&(c - a)).normalized().unwrap_or(Vec3::UP);
+        (t, surface_index, normal)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{core::math::vec3::Vec3, scene::surface_material::ray_triangle_intersection};
+
+    #[test]
+    fn ray_triangle_intersection_test() {
+        let a = Vec3::new(-1.0, 0.0, -1.0);
+        let b = Vec3::new(1.0, 0.0, -1.0);
+        let c = Vec3::new(0.0, 0.0, 1.0);
+
+        assert_eq!(
+            ray_triangle_intersection(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), a, b, c),
+            Some(2.0)
+        );
+
+        // Miss.
+        assert_eq!(
+            ray_triangle_intersection(Vec3::new(5.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), a, b, c),
+            None
+        );
+
+        // Triangle is behind ray origin.
+        assert_eq!(
+            ray_triangle_intersection(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0), a, b, c),
+            None
+        );
+    }
+}
@@ -0,0 +1,805 @@
+//! Heightmap-based terrain.
+//!
+//! Terrain is a mesh modifier which generates regular grid from a [`Heightmap`]. Grid is
+//! split into square chunks, each chunk is a separate surface of the mesh. Changes of
+//! heights regenerate geometry on next update. Visibility is tested for whole mesh, chunks
+//! are not culled separately, so very big terrains should be split into several meshes.
+//!
+//! Terrain can have holes, every chunk has its own optional hole mask with a flag per
+//! quad. Quads marked as holes are not generated at all, so they are skipped both in
+//! rendering and in static geometry for collisions (see `utils::mesh_to_static_geometry`).
+//! Holes are used for cave entrances, basements and tunnels which go under terrain.
+//!
+//! Heightmaps can be imported from and exported to 16-bit grayscale PNG images or RAW
+//! files (unsigned 16-bit little-endian samples without header, `.r16`), these are formats
+//! used by most terrain generation tools.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::math::vec3::Vec3,
+//!     scene::{
+//!         base::BaseBuilder,
+//!         mesh::MeshBuilder,
+//!         node::Node,
+//!         terrain::{Heightmap, Terrain},
+//!     },
+//! };
+//!
+//! fn make_terrain() -> Node {
+//!     let heightmap = Heightmap::from_image_file("data/heightmap.png").unwrap();
+//!     // 1 km x 1 km terrain with 200 m of height difference.
+//!     let terrain = Terrain::new(heightmap, Vec3::new(1000.0, 200.0, 1000.0));
+//!     MeshBuilder::new(BaseBuilder::new())
+//!         .with_terrain(terrain)
+//!         .build_node()
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::{vec2::Vec2, vec3::Vec3, vec4::Vec4, TriangleDefinition},
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    engine::resource_manager::ResourceManager,
+    renderer::surface::{Surface, SurfaceSharedData, Vertex},
+    resource::texture::Texture,
+    scene::lenient::has_region,
+    utils::log::Log,
+};
+use image::{DynamicImage, ImageBuffer, ImageError, Luma};
+use std::{
+    fmt::Formatter,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// An error that can occur during import or export of a heightmap.
+#[derive(Debug)]
+pub enum HeightmapError {
+    /// An input/output error has occurred.
+    Io(std::io::Error),
+    /// Image is corrupted or its format is not supported.
+    Image(ImageError),
+    /// Size of data does not match size of heightmap.
+    InvalidSize,
+}
+
+impl std::fmt::Display for HeightmapError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            HeightmapError::Io(io) => write!(f, "Io error: {}", io),
+            HeightmapError::Image(image) => write!(f, "Image error: {}", image),
+            HeightmapError::InvalidSize => write!(f, "Size of data does not match heightmap"),
+        }
+    }
+}
+
+impl From<std::io::Error> for HeightmapError {
+    fn from(e: std::io::Error) -> Self {
+        HeightmapError::Io(e)
+    }
+}
+
+impl From<ImageError> for HeightmapError {
+    fn from(e: ImageError) -> Self {
+        HeightmapError::Image(e)
+    }
+}
+
+/// Grid of normalized heights in (0..1) range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Heightmap {
+    width: u32,
+    depth: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Creates flat heightmap of given size with every height set to `height`.
+    pub fn new(width: u32, depth: u32, height: f32) -> Self {
+        Self {
+            width,
+            depth,
+            heights: vec![height; (width * depth) as usize],
+        }
+    }
+
+    /// Creates heightmap from normalized heights, returns error if length of heights does
+    /// not match size.
+    pub fn from_heights(width: u32, depth: u32, heights: Vec<f32>) -> Result<Self, HeightmapError> {
+        if heights.len() != (width * depth) as usize {
+            return Err(HeightmapError::InvalidSize);
+        }
+        Ok(Self {
+            width,
+            depth,
+            heights,
+        })
+    }
+
+    /// Creates heightmap from unsigned 16-bit samples.
+    pub fn from_u16(width: u32, depth: u32, samples: &[u16]) -> Result<Self, HeightmapError> {
+        Self::from_heights(
+            width,
+            depth,
+            samples
+                .iter()
+                .map(|&s| f32::from(s) / f32::from(std::u16::MAX))
+                .collect(),
+        )
+    }
+
+    /// Creates heightmap from decoded image. 16-bit grayscale images keep their full
+    /// precision, any other image is converted to 8-bit grayscale.
+    pub fn from_image(image: &DynamicImage) -> Result<Self, HeightmapError> {
+        match image {
+            DynamicImage::ImageLuma16(image) => {
+                Self::from_u16(image.width(), image.height(), image.as_raw())
+            }
+            _ => {
+                let image = image.to_luma();
+                Self::from_heights(
+                    image.width(),
+                    image.height(),
+                    image
+                        .as_raw()
+                        .iter()
+                        .map(|&s| f32::from(s) / f32::from(std::u8::MAX))
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// Loads heightmap from image file, see [from_image](struct.Heightmap.html#method.from_image).
+    pub fn from_image_file<P: AsRef<Path>>(path: P) -> Result<Self, HeightmapError> {
+        Self::from_image(&image::open(path)?)
+    }
+
+    /// Creates heightmap from RAW data - unsigned 16-bit little-endian samples without
+    /// header.
+    pub fn from_raw(width: u32, depth: u32, data: &[u8]) -> Result<Self, HeightmapError> {
+        if data.len() != (width * depth * 2) as usize {
+            return Err(HeightmapError::InvalidSize);
+        }
+        let samples = data
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        Self::from_u16(width, depth, &samples)
+    }
+
+    /// Loads heightmap from RAW file. RAW files have no header, so heightmap is assumed
+    /// to be square.
+    pub fn from_raw_file<P: AsRef<Path>>(path: P) -> Result<Self, HeightmapError> {
+        let data = std::fs::read(path)?;
+        let side = ((data.len() / 2) as f64).sqrt() as u32;
+        Self::from_raw(side, side, &data)
+    }
+
+    /// Returns heights as unsigned 16-bit samples.
+    pub fn to_u16(&self) -> Vec<u16> {
+        self.heights
+            .iter()
+            .map(|&h| (h.max(0.0).min(1.0) * f32::from(std::u16::MAX)).round() as u16)
+            .collect()
+    }
+
+    /// Returns heights as RAW data - unsigned 16-bit little-endian samples without header.
+    pub fn to_raw(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.heights.len() * 2);
+        for sample in self.to_u16() {
+            data.extend_from_slice(&sample.to_le_bytes());
+        }
+        data
+    }
+
+    /// Saves heightmap as RAW file.
+    pub fn save_raw<P: AsRef<Path>>(&self, path: P) -> Result<(), HeightmapError> {
+        std::fs::write(path, self.to_raw())?;
+        Ok(())
+    }
+
+    /// Saves heightmap as 16-bit grayscale image, format is defined by extension of file.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P) -> Result<(), HeightmapError> {
+        let image =
+            ImageBuffer::<Luma<u16>, Vec<u16>>::from_raw(self.width, self.depth, self.to_u16())
+                .ok_or(HeightmapError::InvalidSize)?;
+        image.save(path)?;
+        Ok(())
+    }
+
+    /// Returns amount of samples along X axis.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns amount of samples along Z axis.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns all heights, row by row.
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    /// Returns all heights, row by row.
+    pub fn heights_mut(&mut self) -> &mut [f32] {
+        &mut self.heights
+    }
+
+    /// Returns height at given sample, coordinates are clamped to heightmap bounds.
+    pub fn get(&self, x: i64, z: i64) -> f32 {
+        if self.heights.is_empty() {
+            return 0.0;
+        }
+        let x = x.max(0).min(i64::from(self.width) - 1) as usize;
+        let z = z.max(0).min(i64::from(self.depth) - 1) as usize;
+        self.heights[z * self.width as usize + x]
+    }
+
+    /// Sets height at given sample, does nothing if coordinates are out of bounds.
+    pub fn set(&mut self, x: u32, z: u32, height: f32) {
+        if x < self.width && z < self.depth {
+            self.heights[(z * self.width + x) as usize] = height;
+        }
+    }
+
+    /// Returns bilinearly interpolated height at given normalized coordinates in (0..1)
+    /// range.
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        if self.width == 0 || self.depth == 0 {
+            return 0.0;
+        }
+        let x = u.max(0.0).min(1.0) * (self.width - 1) as f32;
+        let z = v.max(0.0).min(1.0) * (self.depth - 1) as f32;
+        let (x0, z0) = (x.floor() as i64, z.floor() as i64);
+        let (kx, kz) = (x - x0 as f32, z - z0 as f32);
+        let top = self.get(x0, z0) + (self.get(x0 + 1, z0) - self.get(x0, z0)) * kx;
+        let bottom = self.get(x0, z0 + 1) + (self.get(x0 + 1, z0 + 1) - self.get(x0, z0 + 1)) * kx;
+        top + (bottom - top) * kz
+    }
+}
+
+impl Visit for Heightmap {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.width.visit("Width", visitor)?;
+        self.depth.visit("Depth", visitor)?;
+        // Heights are stored as 16-bit samples, it is the precision of source images
+        // and it is twice more compact.
+        let mut samples = if visitor.is_reading() {
+            Vec::new()
+        } else {
+            self.to_u16()
+        };
+        samples.visit("Heights", visitor)?;
+
+        visitor.leave_region()?;
+
+        if visitor.is_reading() {
+            match Self::from_u16(self.width, self.depth, &samples) {
+                Ok(heightmap) => *self = heightmap,
+                Err(e) => {
+                    Log::writeln(format!(
+                        "Invalid heightmap: {}, empty heightmap is used instead.",
+                        e
+                    ));
+                    *self = Default::default();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hole mask of a single chunk, it has a flag for every quad of a chunk.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct HoleMask {
+    chunk_x: u32,
+    chunk_z: u32,
+    quads: Vec<bool>,
+}
+
+impl Visit for HoleMask {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.chunk_x.visit("ChunkX", visitor)?;
+        self.chunk_z.visit("ChunkZ", visitor)?;
+        self.quads.visit("Quads", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Mesh modifier which generates terrain from heightmap, see module docs.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    heightmap: Heightmap,
+    size: Vec3,
+    chunk_size: u32,
+    diffuse_texture: Option<Arc<Mutex<Texture>>>,
+    normal_texture: Option<Arc<Mutex<Texture>>>,
+    // Only chunks with at least one hole have a mask.
+    hole_masks: Vec<HoleMask>,
+    dirty: bool,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            heightmap: Default::default(),
+            size: Vec3::new(1.0, 1.0, 1.0),
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+            diffuse_texture: None,
+            normal_texture: None,
+            hole_masks: Default::default(),
+            dirty: false,
+        }
+    }
+}
+
+impl Terrain {
+    /// Default amount of quads along each side of a chunk.
+    pub const DEFAULT_CHUNK_SIZE: u32 = 32;
+
+    /// Creates new terrain from heightmap. `size` defines size of terrain in local
+    /// coordinates: `x` - width, `y` - height at maximum heightmap value, `z` - depth.
+    /// Terrain starts at origin and spans in positive X and Z directions.
+    pub fn new(heightmap: Heightmap, size: Vec3) -> Self {
+        Self {
+            heightmap,
+            size,
+            dirty: true,
+            ..Default::default()
+        }
+    }
+
+    /// Sets amount of quads along each side of a chunk. Hole masks are defined per chunk,
+    /// so every hole is removed.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self.hole_masks.clear();
+        self
+    }
+
+    /// Sets diffuse texture of every chunk, texture is stretched over whole terrain.
+    pub fn with_diffuse_texture(mut self, texture: Arc<Mutex<Texture>>) -> Self {
+        self.diffuse_texture = Some(texture);
+        self
+    }
+
+    /// Sets normal texture of every chunk.
+    pub fn with_normal_texture(mut self, texture: Arc<Mutex<Texture>>) -> Self {
+        self.normal_texture = Some(texture);
+        self
+    }
+
+    /// Returns heightmap.
+    pub fn heightmap(&self) -> &Heightmap {
+        &self.heightmap
+    }
+
+    /// Returns heightmap, geometry will be regenerated on next update.
+    pub fn heightmap_mut(&mut self) -> &mut Heightmap {
+        self.dirty = true;
+        &mut self.heightmap
+    }
+
+    /// Sets size of terrain, see [new](struct.Terrain.html#method.new).
+    pub fn set_size(&mut self, size: Vec3) {
+        self.size = size;
+        self.dirty = true;
+    }
+
+    /// Returns size of terrain.
+    pub fn size(&self) -> Vec3 {
+        self.size
+    }
+
+    /// Returns amount of quads along each side of a chunk.
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    /// Returns amount of chunks along X and Z axes.
+    pub fn chunk_count(&self) -> (u32, u32) {
+        let quads = |samples: u32| samples.saturating_sub(1);
+        let count = |quads: u32| (quads + self.chunk_size - 1) / self.chunk_size;
+        (
+            count(quads(self.heightmap.width)),
+            count(quads(self.heightmap.depth)),
+        )
+    }
+
+    /// Returns height of terrain at given point in local coordinates, point is clamped
+    /// to terrain bounds.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let u = if self.size.x > 0.0 {
+            x / self.size.x
+        } else {
+            0.0
+        };
+        let v = if self.size.z > 0.0 {
+            z / self.size.z
+        } else {
+            0.0
+        };
+        self.heightmap.sample(u, v) * self.size.y
+    }
+
+    fn hole_mask(&self, chunk_x: u32, chunk_z: u32) -> Option<&HoleMask> {
+        self.hole_masks
+            .iter()
+            .find(|m| m.chunk_x == chunk_x && m.chunk_z == chunk_z)
+    }
+
+    /// Returns true if quad with given coordinates is a hole. Quad `(x, z)` lies between
+    /// samples `x..x + 1` and `z..z + 1` of heightmap.
+    pub fn is_hole(&self, x: u32, z: u32) -> bool {
+        let (chunk_x, chunk_z) = (x / self.chunk_size, z / self.chunk_size);
+        match self.hole_mask(chunk_x, chunk_z) {
+            Some(mask) => {
+                let index = (z % self.chunk_size) * self.chunk_size + x % self.chunk_size;
+                mask.quads[index as usize]
+            }
+            None => false,
+        }
+    }
+
+    /// Marks quad with given coordinates as hole or restores it. Does nothing if quad is
+    /// out of bounds.
+    pub fn set_hole(&mut self, x: u32, z: u32, hole: bool) {
+        if x + 1 >= self.heightmap.width || z + 1 >= self.heightmap.depth {
+            return;
+        }
+        let (chunk_x, chunk_z) = (x / self.chunk_size, z / self.chunk_size);
+        let index = ((z % self.chunk_size) * self.chunk_size + x % self.chunk_size) as usize;
+        let position = self
+            .hole_masks
+            .iter()
+            .position(|m| m.chunk_x == chunk_x && m.chunk_z == chunk_z);
+        match position {
+            Some(position) => {
+                let mask = &mut self.hole_masks[position];
+                if mask.quads[index] == hole {
+                    return;
+                }
+                mask.quads[index] = hole;
+                if !mask.quads.iter().any(|&q| q) {
+                    self.hole_masks.remove(position);
+                }
+            }
+            None => {
+                if !hole {
+                    return;
+                }
+                let mut quads = vec![false; (self.chunk_size * self.chunk_size) as usize];
+                quads[index] = true;
+                self.hole_masks.push(HoleMask {
+                    chunk_x,
+                    chunk_z,
+                    quads,
+                });
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Marks every quad whose center is inside of a circle (in local coordinates on XZ
+    /// plane) as hole or restores it.
+    pub fn paint_holes(&mut self, center: Vec2, radius: f32, hole: bool) {
+        let step = self.step();
+        if step.x <= 0.0 || step.y <= 0.0 {
+            return;
+        }
+        let range = |c: f32, step: f32, samples: u32| {
+            let begin = ((c - radius) / step).floor().max(0.0) as u32;
+            let end = (((c + radius) / step).ceil().max(0.0) as u32).min(samples.saturating_sub(1));
+            begin..end
+        };
+        for z in range(center.y, step.y, self.heightmap.depth) {
+            for x in range(center.x, step.x, self.heightmap.width) {
+                let quad_center = Vec2::new((x as f32 + 0.5) * step.x, (z as f32 + 0.5) * step.y);
+                if (quad_center - center).len() <= radius {
+                    self.set_hole(x, z, hole);
+                }
+            }
+        }
+    }
+
+    /// Returns true if point (in local coordinates on XZ plane) is above or below a hole.
+    pub fn is_hole_at(&self, x: f32, z: f32) -> bool {
+        let step = self.step();
+        if x < 0.0 || z < 0.0 || step.x <= 0.0 || step.y <= 0.0 {
+            return false;
+        }
+        self.is_hole((x / step.x) as u32, (z / step.y) as u32)
+    }
+
+    /// Returns true if chunk with given coordinates has at least one hole.
+    pub fn chunk_has_holes(&self, chunk_x: u32, chunk_z: u32) -> bool {
+        self.hole_mask(chunk_x, chunk_z).is_some()
+    }
+
+    /// Removes every hole.
+    pub fn clear_holes(&mut self) {
+        if !self.hole_masks.is_empty() {
+            self.hole_masks.clear();
+            self.dirty = true;
+        }
+    }
+
+    /// Forces regeneration of geometry on next update.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Returns true if geometry must be regenerated.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(in crate) fn reset_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    pub(in crate) fn restore_resources(&mut self, resource_manager: &mut ResourceManager) {
+        for texture in &mut [&mut self.diffuse_texture, &mut self.normal_texture] {
+            if let Some(shallow_texture) = texture.clone() {
+                **texture = resource_manager.restore_texture(&shallow_texture);
+            }
+        }
+    }
+
+    fn step(&self) -> Vec2 {
+        Vec2::new(
+            self.size.x / (self.heightmap.width.max(2) - 1) as f32,
+            self.size.z / (self.heightmap.depth.max(2) - 1) as f32,
+        )
+    }
+
+    fn make_vertex(&self, x: u32, z: u32) -> Vertex {
+        let step = self.step();
+        let (xi, zi) = (i64::from(x), i64::from(z));
+        let height = |x: i64, z: i64| self.heightmap.get(x, z) * self.size.y;
+        // Central differences.
+        let dx = (height(xi + 1, zi) - height(xi - 1, zi)) / (2.0 * step.x);
+        let dz = (height(xi, zi + 1) - height(xi, zi - 1)) / (2.0 * step.y);
+        let normal = Vec3::new(-dx, 1.0, -dz)
+            .normalized()
+            .unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0));
+        let tex_coord = Vec2::new(
+            x as f32 / (self.heightmap.width.max(2) - 1) as f32,
+            z as f32 / (self.heightmap.depth.max(2) - 1) as f32,
+        );
+        Vertex {
+            position: Vec3::new(x as f32 * step.x, height(xi, zi), z as f32 * step.y),
+            tex_coord,
+            second_tex_coord: tex_coord,
+            normal,
+            tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ..Default::default()
+        }
+    }
+
+    /// Generates geometry of chunk with given coordinates, holes are skipped. Returns None
+    /// if chunk is out of bounds or every quad of it is a hole.
+    pub fn generate_chunk(&self, chunk_x: u32, chunk_z: u32) -> Option<SurfaceSharedData> {
+        let (count_x, count_z) = self.chunk_count();
+        if chunk_x >= count_x || chunk_z >= count_z {
+            return None;
+        }
+
+        let x_begin = chunk_x * self.chunk_size;
+        let z_begin = chunk_z * self.chunk_size;
+        let x_end = (x_begin + self.chunk_size).min(self.heightmap.width - 1);
+        let z_end = (z_begin + self.chunk_size).min(self.heightmap.depth - 1);
+        let row = x_end - x_begin + 1;
+
+        let mut vertices = Vec::with_capacity((row * (z_end - z_begin + 1)) as usize);
+        for z in z_begin..=z_end {
+            for x in x_begin..=x_end {
+                vertices.push(self.make_vertex(x, z));
+            }
+        }
+
+        let mask = self.hole_mask(chunk_x, chunk_z);
+        let mut triangles = Vec::new();
+        for z in 0..(z_end - z_begin) {
+            for x in 0..(x_end - x_begin) {
+                if let Some(mask) = mask {
+                    if mask.quads[(z * self.chunk_size + x) as usize] {
+                        continue;
+                    }
+                }
+                let i0 = z * row + x;
+                let i1 = i0 + 1;
+                let i2 = i0 + row;
+                let i3 = i2 + 1;
+                triangles.push(TriangleDefinition([i0, i2, i1]));
+                triangles.push(TriangleDefinition([i1, i2, i3]));
+            }
+        }
+
+        if triangles.is_empty() {
+            return None;
+        }
+
+        // Chunks are regenerated from heightmap after load, so their geometry is not
+        // serialized.
+        let mut data = SurfaceSharedData::new(vertices, triangles, false);
+        data.calculate_tangents();
+        Some(data)
+    }
+
+    /// Generates surfaces of every chunk, chunks are ordered row by row.
+    pub fn generate(&self) -> Vec<Surface> {
+        let (count_x, count_z) = self.chunk_count();
+        let mut surfaces = Vec::with_capacity((count_x * count_z) as usize);
+        for chunk_z in 0..count_z {
+            for chunk_x in 0..count_x {
+                if let Some(data) = self.generate_chunk(chunk_x, chunk_z) {
+                    let mut surface = Surface::new(Arc::new(Mutex::new(data)));
+                    if let Some(texture) = self.diffuse_texture.clone() {
+                        surface.set_diffuse_texture(texture);
+                    }
+                    if let Some(texture) = self.normal_texture.clone() {
+                        surface.set_normal_texture(texture);
+                    }
+                    surfaces.push(surface);
+                }
+            }
+        }
+        surfaces
+    }
+}
+
+impl Visit for Terrain {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.heightmap.visit("Heightmap", visitor)?;
+        self.size.visit("Size", visitor)?;
+        self.chunk_size.visit("ChunkSize", visitor)?;
+        self.diffuse_texture.visit("DiffuseTexture", visitor)?;
+        self.normal_texture.visit("NormalTexture", visitor)?;
+        if !visitor.is_reading() || has_region("HoleMasks", visitor) {
+            self.hole_masks.visit("HoleMasks", visitor)?;
+        }
+
+        visitor.leave_region()?;
+
+        if visitor.is_reading() {
+            if self.chunk_size == 0 {
+                Log::writeln(
+                    "Terrain chunk size must not be zero, default size is used instead.".to_owned(),
+                );
+                self.chunk_size = Self::DEFAULT_CHUNK_SIZE;
+            }
+            let (count_x, count_z) = self.chunk_count();
+            let quad_count = (self.chunk_size * self.chunk_size) as usize;
+            self.hole_masks.retain(|mask| {
+                let valid = mask.chunk_x < count_x
+                    && mask.chunk_z < count_z
+                    && mask.quads.len() == quad_count;
+                if !valid {
+                    Log::writeln(format!(
+                        "Hole mask of chunk {}x{} with {} quads does not match terrain \
+                         with {}x{} chunks of {} quads, it is removed.",
+                        mask.chunk_x,
+                        mask.chunk_z,
+                        mask.quads.len(),
+                        count_x,
+                        count_z,
+                        quad_count
+                    ));
+                }
+                valid
+            });
+            // Geometry of chunks is not serialized, regenerate it on next update.
+            self.dirty = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::{
+            math::{vec2::Vec2, vec3::Vec3},
+            visitor::{Visit, Visitor},
+        },
+        scene::terrain::{Heightmap, HoleMask, Terrain},
+    };
+
+    #[test]
+    fn test_raw_round_trip() {
+        let heightmap = Heightmap::from_u16(2, 2, &[0, 1000, 40000, 65535]).unwrap();
+        let raw = heightmap.to_raw();
+        assert_eq!(raw.len(), 8);
+        let restored = Heightmap::from_raw(2, 2, &raw).unwrap();
+        assert_eq!(restored.to_u16(), vec![0, 1000, 40000, 65535]);
+        assert!(Heightmap::from_raw(3, 3, &raw).is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let heightmap = Heightmap::from_heights(2, 2, vec![0.0, 1.0, 0.0, 1.0]).unwrap();
+        assert!((heightmap.sample(0.5, 0.5) - 0.5).abs() < 0.0001);
+        assert_eq!(heightmap.sample(1.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn test_chunks() {
+        // 65 samples gives 64 quads, which is two chunks of 32 quads.
+        let terrain = Terrain::new(Heightmap::new(65, 33, 0.5), Vec3::new(64.0, 10.0, 32.0));
+        assert_eq!(terrain.chunk_count(), (2, 1));
+        let chunk = terrain.generate_chunk(1, 0).unwrap();
+        assert_eq!(chunk.get_vertices().len(), 33 * 33);
+        assert_eq!(chunk.triangles().len(), 32 * 32 * 2);
+        assert_eq!(chunk.get_vertices()[0].position, Vec3::new(32.0, 5.0, 0.0));
+        assert_eq!(terrain.generate().len(), 2);
+        assert!((terrain.height_at(10.0, 10.0) - 5.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_holes() {
+        let mut terrain = Terrain::new(Heightmap::new(65, 33, 0.5), Vec3::new(64.0, 10.0, 32.0));
+        terrain.set_hole(40, 3, true);
+        assert!(terrain.is_hole(40, 3));
+        assert!(terrain.is_hole_at(40.5, 3.5));
+        assert!(!terrain.chunk_has_holes(0, 0));
+        assert!(terrain.chunk_has_holes(1, 0));
+        let chunk = terrain.generate_chunk(1, 0).unwrap();
+        assert_eq!(chunk.triangles().len(), 32 * 32 * 2 - 2);
+
+        // Mask is removed when last hole is filled.
+        terrain.set_hole(40, 3, false);
+        assert!(!terrain.chunk_has_holes(1, 0));
+
+        // Fully holed chunk is not generated.
+        terrain.paint_holes(Vec2::new(16.0, 16.0), 100.0, true);
+        assert!(terrain.generate_chunk(0, 0).is_none());
+        assert!(terrain.generate().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_terrain_is_fixed_on_load() {
+        let mut terrain = Terrain::new(Heightmap::new(5, 5, 0.0), Vec3::new(4.0, 1.0, 4.0));
+        terrain.chunk_size = 0;
+        terrain.hole_masks.push(HoleMask {
+            chunk_x: 10,
+            chunk_z: 0,
+            quads: vec![true],
+        });
+        let mut next = 42u32;
+
+        let path =
+            std::env::temp_dir().join(format!("rg3d_invalid_terrain_{}.bin", std::process::id()));
+        let mut visitor = Visitor::new();
+        terrain.visit("Terrain", &mut visitor).unwrap();
+        next.visit("Next", &mut visitor).unwrap();
+        visitor.save_binary(&path).unwrap();
+
+        let mut visitor = Visitor::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut loaded = Terrain::default();
+        loaded.visit("Terrain", &mut visitor).unwrap();
+        let mut loaded_next = 0u32;
+        // Visitor must be out of terrain region.
+        loaded_next.visit("Next", &mut visitor).unwrap();
+
+        assert_eq!(loaded.chunk_size, Terrain::DEFAULT_CHUNK_SIZE);
+        assert!(loaded.hole_masks.is_empty());
+        assert_eq!(loaded_next, 42);
+    }
+}
@@ -264,6 +264,33 @@ impl Transform {
         self
     }
 
+    /// Sets transform from given matrix. Matrix is decomposed into position, rotation
+    /// and scale, pre- and post-rotations, offsets and pivots will be reset to default
+    /// values. Matrix must not contain shear, otherwise result will be incorrect.
+    pub fn set_matrix(&mut self, matrix: Mat4) -> &mut Self {
+        let scale = Vec3::new(matrix.side().len(), matrix.up().len(), matrix.look().len());
+        let inv_scale = |s: f32| if s != 0.0 { 1.0 / s } else { 1.0 };
+        let rotation = (matrix
+            * Mat4::scale(Vec3::new(
+                inv_scale(scale.x),
+                inv_scale(scale.y),
+                inv_scale(scale.z),
+            )))
+        .basis();
+
+        self.local_position = matrix.position();
+        self.local_rotation = Quat::from(rotation);
+        self.local_scale = scale;
+        self.pre_rotation = Quat::IDENTITY;
+        self.post_rotation = Quat::IDENTITY;
+        self.rotation_offset = Vec3::ZERO;
+        self.rotation_pivot = Vec3::ZERO;
+        self.scaling_offset = Vec3::ZERO;
+        self.scaling_pivot = Vec3::ZERO;
+        self.dirty.set(true);
+        self
+    }
+
     fn calculate_local_transform(&self) -> Mat4 {
         let pre_rotation = Mat4::from_quat(self.pre_rotation);
         let post_rotation = Mat4::from_quat(self.post_rotation)
@@ -0,0 +1,608 @@
+//! Crowd simulation - coordinated movement of many agents on a navmesh.
+//!
+//! Path finding for every agent individually does not scale to hundreds of agents, and
+//! agents that know nothing about each other tend to walk through each other or jam in
+//! doorways. [`Crowd`] owns a set of agents and coordinates them:
+//!
+//! - Paths are cached by pair of start and end navmesh vertices, so agents going from the
+//!   same area to the same destination share one path. Amount of path searches per update
+//!   is limited, agents that did not get a path wait for next update. Least recently used
+//!   paths are evicted when cache is full.
+//! - Agents never leave navmesh to reach their targets: if target is unreachable, agent
+//!   walks to the closest reachable point and stops there.
+//! - Agents can be moved as a group in formation - path is searched once for the whole
+//!   group and every agent keeps its own offset from the destination.
+//! - Agents separate from their neighbours and slow down in dense areas, which avoids
+//!   congestion in narrow places.
+//!
+//! Crowd does not move scene nodes itself, positions of agents should be copied to nodes
+//! after update.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{
+//!     core::math::vec3::Vec3,
+//!     utils::{crowd::{Agent, Crowd}, navmesh::Navmesh},
+//! };
+//!
+//! fn move_squad(crowd: &mut Crowd, navmesh: &mut Navmesh, target: Vec3) {
+//!     let squad = (0..4)
+//!         .map(|i| crowd.add_agent(Agent::new(Vec3::new(i as f32, 0.0, 0.0))))
+//!         .collect::<Vec<_>>();
+//!     crowd.move_group(&squad, target, 1.5);
+//!     crowd.update(navmesh, 1.0 / 60.0);
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        pool::{Handle, Pool},
+    },
+    utils::{astar::PathKind, navmesh::Navmesh},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// State of path following of an agent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AgentState {
+    /// Agent has no target.
+    Idle,
+    /// Agent waits for a path, path search budget was exhausted.
+    WaitingForPath,
+    /// Agent follows its path.
+    Moving,
+    /// Agent has reached its target.
+    Arrived,
+    /// Target of agent is unreachable, agent has reached the closest reachable point or
+    /// there is no path at all.
+    PathNotFound,
+}
+
+/// Single member of a crowd.
+#[derive(Clone, Debug)]
+pub struct Agent {
+    position: Vec3,
+    velocity: Vec3,
+    radius: f32,
+    max_speed: f32,
+    target: Option<Vec3>,
+    offset: Vec3,
+    path: Arc<Vec<Vec3>>,
+    // False if path is partial and ends at the closest reachable point.
+    reaches_target: bool,
+    // Path is stored in reversed order (last point is the next one), so this is index
+    // of next point plus one.
+    waypoint: usize,
+    state: AgentState,
+}
+
+impl Agent {
+    /// Creates new idle agent at given position with radius 0.4 and max speed 1.5.
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            velocity: Vec3::ZERO,
+            radius: 0.4,
+            max_speed: 1.5,
+            target: None,
+            offset: Vec3::ZERO,
+            path: Default::default(),
+            reaches_target: false,
+            waypoint: 0,
+            state: AgentState::Idle,
+        }
+    }
+
+    /// Sets radius of agent.
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    /// Sets maximum speed of agent.
+    pub fn with_max_speed(mut self, max_speed: f32) -> Self {
+        self.max_speed = max_speed.max(0.0);
+        self
+    }
+
+    /// Returns current position of agent.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Teleports agent to given position. Agent will search new path on next update.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+        self.request_path();
+    }
+
+    /// Returns current velocity of agent.
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    /// Returns radius of agent.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Returns max speed of agent.
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    /// Returns current target of agent (including formation offset).
+    pub fn target(&self) -> Option<Vec3> {
+        self.target.map(|t| t + self.offset)
+    }
+
+    /// Returns current state of agent.
+    pub fn state(&self) -> AgentState {
+        self.state
+    }
+
+    fn request_path(&mut self) {
+        if self.target.is_some() {
+            self.state = AgentState::WaitingForPath;
+        }
+    }
+
+    fn next_point(&self) -> Option<Vec3> {
+        if self.waypoint > 0 {
+            // Agents in formation walk shared path, but finish at their own spot.
+            if self.waypoint == 1 && self.reaches_target {
+                self.target()
+            } else {
+                Some(self.path[self.waypoint - 1])
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// Settings of crowd behaviour.
+#[derive(Copy, Clone, Debug)]
+pub struct CrowdSettings {
+    /// Maximum amount of path searches per update. Default is 8.
+    pub max_path_queries: usize,
+    /// Strength of separation from neighbours. Default is 2.0.
+    pub separation: f32,
+    /// Additional distance between agents which they try to keep. Default is 0.2.
+    pub separation_margin: f32,
+    /// Amount of neighbours at which agent starts to slow down. Default is 3.
+    pub congestion_threshold: usize,
+    /// How much every neighbour above threshold reduces speed of agent. Default is 0.15.
+    pub congestion_slowdown: f32,
+    /// Distance to waypoint at which it is considered reached. Default is 0.3.
+    pub arrival_distance: f32,
+    /// Max distance between starts of agents which go to the same target at which they
+    /// share one path. Default is 4.0.
+    pub path_sharing_distance: f32,
+    /// Maximum amount of cached paths. Default is 256.
+    pub max_cached_paths: usize,
+}
+
+impl Default for CrowdSettings {
+    fn default() -> Self {
+        Self {
+            max_path_queries: 8,
+            separation: 2.0,
+            separation_margin: 0.2,
+            congestion_threshold: 3,
+            congestion_slowdown: 0.15,
+            arrival_distance: 0.3,
+            path_sharing_distance: 4.0,
+            max_cached_paths: 256,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CachedPath {
+    path: Arc<Vec<Vec3>>,
+    reaches_target: bool,
+}
+
+/// See module docs.
+pub struct Crowd {
+    agents: Pool<Agent>,
+    path_cache: HashMap<(usize, usize), (CachedPath, u64)>,
+    settings: CrowdSettings,
+    path_buffer: Vec<Vec3>,
+    query_count: usize,
+    update_count: u64,
+}
+
+impl Default for Crowd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Cell = (i32, i32);
+
+fn cell_of(position: Vec3, size: f32) -> Cell {
+    (
+        (position.x / size).floor() as i32,
+        (position.z / size).floor() as i32,
+    )
+}
+
+/// Calculates offsets of grid formation for given amount of members, rows are
+/// perpendicular to Z axis and formation is centered at origin.
+pub fn grid_formation(count: usize, spacing: f32) -> Vec<Vec3> {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as usize;
+    let rows = (count + columns - 1) / columns.max(1);
+    (0..count)
+        .map(|i| {
+            let column = (i % columns) as f32 - (columns - 1) as f32 * 0.5;
+            let row = (i / columns) as f32 - (rows.max(1) - 1) as f32 * 0.5;
+            Vec3::new(column * spacing, 0.0, row * spacing)
+        })
+        .collect()
+}
+
+impl Crowd {
+    /// Creates new empty crowd with default settings.
+    pub fn new() -> Self {
+        Self {
+            agents: Pool::new(),
+            path_cache: Default::default(),
+            settings: Default::default(),
+            path_buffer: Default::default(),
+            query_count: 0,
+            update_count: 0,
+        }
+    }
+
+    /// Sets new settings of crowd.
+    pub fn set_settings(&mut self, settings: CrowdSettings) {
+        self.settings = settings;
+    }
+
+    /// Returns current settings of crowd.
+    pub fn settings(&self) -> &CrowdSettings {
+        &self.settings
+    }
+
+    /// Adds new agent to crowd.
+    pub fn add_agent(&mut self, agent: Agent) -> Handle<Agent> {
+        self.agents.spawn(agent)
+    }
+
+    /// Removes agent from crowd.
+    pub fn remove_agent(&mut self, handle: Handle<Agent>) {
+        self.agents.free(handle);
+    }
+
+    /// Returns true if handle points to existing agent.
+    pub fn is_valid_handle(&self, handle: Handle<Agent>) -> bool {
+        self.agents.is_valid_handle(handle)
+    }
+
+    /// Returns reference to agent.
+    pub fn agent(&self, handle: Handle<Agent>) -> &Agent {
+        &self.agents[handle]
+    }
+
+    /// Returns reference to agent.
+    pub fn agent_mut(&mut self, handle: Handle<Agent>) -> &mut Agent {
+        &mut self.agents[handle]
+    }
+
+    /// Returns iterator over every agent with its handle.
+    pub fn pair_iter(&self) -> impl Iterator<Item = (Handle<Agent>, &Agent)> {
+        self.agents.pair_iter()
+    }
+
+    /// Sends single agent to given target.
+    pub fn move_agent(&mut self, handle: Handle<Agent>, target: Vec3) {
+        let agent = &mut self.agents[handle];
+        agent.target = Some(target);
+        agent.offset = Vec3::ZERO;
+        agent.request_path();
+    }
+
+    /// Sends group of agents to given target in grid formation with given spacing.
+    /// Group shares one path, so it costs as much as moving a single agent.
+    pub fn move_group(&mut self, handles: &[Handle<Agent>], target: Vec3, spacing: f32) {
+        let offsets = grid_formation(handles.len(), spacing);
+        for (handle, offset) in handles.iter().zip(offsets) {
+            let agent = &mut self.agents[*handle];
+            agent.target = Some(target);
+            agent.offset = offset;
+            agent.request_path();
+        }
+    }
+
+    /// Stops agent.
+    pub fn stop_agent(&mut self, handle: Handle<Agent>) {
+        let agent = &mut self.agents[handle];
+        agent.target = None;
+        agent.velocity = Vec3::ZERO;
+        agent.waypoint = 0;
+        agent.state = AgentState::Idle;
+    }
+
+    /// Clears path cache and forces every moving agent to search new path. Must be
+    /// called when navmesh has changed, for example when
+    /// [version](../tiled_navmesh/struct.TiledNavmesh.html#method.version) of tiled
+    /// navmesh has changed.
+    pub fn invalidate_paths(&mut self) {
+        self.path_cache.clear();
+        for agent in self.agents.iter_mut() {
+            if agent.state == AgentState::Moving || agent.state == AgentState::PathNotFound {
+                agent.request_path();
+            }
+        }
+    }
+
+    /// Returns amount of path searches performed during last update.
+    pub fn path_query_count(&self) -> usize {
+        self.query_count
+    }
+
+    fn find_path(&mut self, navmesh: &mut Navmesh, from: Vec3, to: Vec3) -> CachedPath {
+        let (begin, end) = match (navmesh.query_closest(from), navmesh.query_closest(to)) {
+            (Some(begin), Some(end)) => (begin, end),
+            _ => {
+                return CachedPath {
+                    path: Default::default(),
+                    reaches_target: false,
+                }
+            }
+        };
+        if let Some((path, last_used)) = self.path_cache.get_mut(&(begin, end)) {
+            *last_used = self.update_count;
+            return path.clone();
+        }
+        self.query_count += 1;
+        let path = match navmesh.build_path(begin, end, &mut self.path_buffer) {
+            Ok(kind) => CachedPath {
+                path: Arc::new(self.path_buffer.clone()),
+                reaches_target: kind == PathKind::Full,
+            },
+            Err(_) => CachedPath {
+                path: Default::default(),
+                reaches_target: false,
+            },
+        };
+        if self.path_cache.len() >= self.settings.max_cached_paths {
+            let least_recently_used = self
+                .path_cache
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| *key);
+            if let Some(key) = least_recently_used {
+                self.path_cache.remove(&key);
+            }
+        }
+        if self.settings.max_cached_paths > 0 {
+            self.path_cache
+                .insert((begin, end), (path.clone(), self.update_count));
+        }
+        path
+    }
+
+    fn assign_paths(&mut self, navmesh: &mut Navmesh) {
+        self.query_count = 0;
+        // Agents in a group have same target, so key of requests is a target.
+        let waiting = self
+            .agents
+            .pair_iter()
+            .filter(|(_, a)| a.state == AgentState::WaitingForPath)
+            .map(|(h, a)| (h, a.position, a.target.unwrap_or(a.position)))
+            .collect::<Vec<_>>();
+        let mut group_paths: Vec<(Vec3, Vec3, CachedPath)> = Vec::new();
+        for (handle, position, target) in waiting {
+            let shared = group_paths
+                .iter()
+                .find(|(t, start, _)| {
+                    // Share path only if agent starts near the beginning of it.
+                    *t == target && start.distance(&position) < self.settings.path_sharing_distance
+                })
+                .map(|(_, _, p)| p.clone());
+            let path = if let Some(path) = shared {
+                path
+            } else if self.query_count < self.settings.max_path_queries {
+                let path = self.find_path(navmesh, position, target);
+                let start = path.path.last().cloned().unwrap_or(position);
+                group_paths.push((target, start, path.clone()));
+                path
+            } else {
+                // Out of budget, wait for next update.
+                continue;
+            };
+            let agent = &mut self.agents[handle];
+            if path.path.is_empty() {
+                // Do not walk straight to the target, it would go through walls.
+                agent.waypoint = 0;
+                agent.path = path.path;
+                agent.reaches_target = false;
+                agent.velocity = Vec3::ZERO;
+                agent.state = AgentState::PathNotFound;
+                continue;
+            }
+            // Point closest to agent is its own position, skip it.
+            agent.waypoint = path.path.len().saturating_sub(1).max(1);
+            agent.path = path.path;
+            agent.reaches_target = path.reaches_target;
+            agent.state = AgentState::Moving;
+        }
+    }
+
+    /// Assigns paths to waiting agents and moves agents along their paths, avoiding each
+    /// other.
+    pub fn update(&mut self, navmesh: &mut Navmesh, dt: f32) {
+        self.update_count += 1;
+        self.assign_paths(navmesh);
+
+        // Spatial hash to find neighbours quickly.
+        let cell_size = self
+            .agents
+            .iter()
+            .map(|a| a.radius)
+            .fold(0.0, f32::max)
+            .max(0.1)
+            * 2.0
+            + self.settings.separation_margin;
+        let mut grid: HashMap<Cell, Vec<(Vec3, f32)>> = HashMap::new();
+        for agent in self.agents.iter() {
+            grid.entry(cell_of(agent.position, cell_size))
+                .or_default()
+                .push((agent.position, agent.radius));
+        }
+
+        let settings = self.settings;
+        for agent in self.agents.iter_mut() {
+            // Follow path.
+            let mut desired = Vec3::ZERO;
+            if agent.state == AgentState::Moving {
+                while let Some(point) = agent.next_point() {
+                    let mut to_point = point - agent.position;
+                    to_point.y = 0.0;
+                    if to_point.len() > settings.arrival_distance {
+                        desired = to_point.normalized().unwrap_or(Vec3::ZERO);
+                        break;
+                    }
+                    agent.waypoint -= 1;
+                }
+                if agent.waypoint == 0 {
+                    agent.state = if agent.reaches_target {
+                        AgentState::Arrived
+                    } else {
+                        AgentState::PathNotFound
+                    };
+                }
+            }
+
+            // Separation and congestion.
+            let mut separation = Vec3::ZERO;
+            let mut neighbours = 0;
+            let (cx, cz) = cell_of(agent.position, cell_size);
+            for z in (cz - 1)..=(cz + 1) {
+                for x in (cx - 1)..=(cx + 1) {
+                    for (position, radius) in grid.get(&(x, z)).into_iter().flatten() {
+                        let mut delta = agent.position - *position;
+                        delta.y = 0.0;
+                        let distance = delta.len();
+                        let min_distance = agent.radius + radius + settings.separation_margin;
+                        if distance > std::f32::EPSILON && distance < min_distance {
+                            neighbours += 1;
+                            separation = separation
+                                + delta
+                                    .scale((min_distance - distance) / (min_distance * distance));
+                        }
+                    }
+                }
+            }
+
+            let congestion = neighbours.saturating_sub(settings.congestion_threshold) as f32;
+            let speed = agent.max_speed / (1.0 + congestion * settings.congestion_slowdown);
+            let mut velocity = desired.scale(speed) + separation.scale(settings.separation);
+            if velocity.len() > agent.max_speed {
+                velocity = velocity
+                    .normalized()
+                    .unwrap_or(Vec3::ZERO)
+                    .scale(agent.max_speed);
+            }
+            agent.velocity = velocity;
+            agent.position = agent.position + velocity.scale(dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::{vec3::Vec3, TriangleDefinition},
+        utils::{
+            crowd::{grid_formation, Agent, AgentState, Crowd},
+            navmesh::Navmesh,
+        },
+    };
+
+    fn strip_navmesh() -> Navmesh {
+        let vertices = (0..=10)
+            .flat_map(|i| {
+                let x = i as f32 * 2.0;
+                vec![Vec3::new(x, 0.0, -2.0), Vec3::new(x, 0.0, 2.0)]
+            })
+            .collect::<Vec<_>>();
+        let triangles = (0..10u32)
+            .flat_map(|i| {
+                let a = i * 2;
+                vec![
+                    TriangleDefinition([a, a + 2, a + 1]),
+                    TriangleDefinition([a + 1, a + 2, a + 3]),
+                ]
+            })
+            .collect::<Vec<_>>();
+        Navmesh::new(&triangles, &vertices)
+    }
+
+    #[test]
+    fn test_grid_formation() {
+        let offsets = grid_formation(4, 2.0);
+        assert_eq!(offsets.len(), 4);
+        assert_eq!(offsets[0], Vec3::new(-1.0, 0.0, -1.0));
+        assert_eq!(offsets[3], Vec3::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_group_shares_path() {
+        let mut navmesh = strip_navmesh();
+        let mut crowd = Crowd::new();
+        let group = (0..10)
+            .map(|i| crowd.add_agent(Agent::new(Vec3::new(0.0, 0.0, i as f32 * 0.1))))
+            .collect::<Vec<_>>();
+        crowd.move_group(&group, Vec3::new(18.0, 0.0, 0.0), 1.0);
+        crowd.update(&mut navmesh, 0.1);
+        assert_eq!(crowd.path_query_count(), 1);
+        for handle in group {
+            assert_eq!(crowd.agent(handle).state(), AgentState::Moving);
+        }
+    }
+
+    #[test]
+    fn test_agent_arrives_and_agents_separate() {
+        let mut navmesh = strip_navmesh();
+        let mut crowd = Crowd::new();
+        let a = crowd.add_agent(Agent::new(Vec3::new(0.0, 0.0, 0.0)));
+        let b = crowd.add_agent(Agent::new(Vec3::new(0.0, 0.0, -0.1)));
+        crowd.move_agent(a, Vec3::new(20.0, 0.0, 2.0));
+        for _ in 0..400 {
+            crowd.update(&mut navmesh, 0.1);
+        }
+        assert_eq!(crowd.agent(a).state(), AgentState::Arrived);
+        // Idle agent was pushed away.
+        assert!(crowd.agent(b).position().z < -0.1);
+    }
+
+    #[test]
+    fn test_unreachable_target() {
+        // Two islands with a gap between them.
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(12.0, 0.0, 0.0),
+            Vec3::new(10.0, 0.0, 2.0),
+        ];
+        let triangles = vec![TriangleDefinition([0, 1, 2]), TriangleDefinition([3, 4, 5])];
+        let mut navmesh = Navmesh::new(&triangles, &vertices);
+        let mut crowd = Crowd::new();
+        let agent = crowd.add_agent(Agent::new(Vec3::new(0.0, 0.0, 0.0)));
+        crowd.move_agent(agent, Vec3::new(12.0, 0.0, 0.0));
+        for _ in 0..200 {
+            crowd.update(&mut navmesh, 0.1);
+        }
+        assert_eq!(crowd.agent(agent).state(), AgentState::PathNotFound);
+        // Agent stayed on its island.
+        assert!(crowd.agent(agent).position().x <= 2.0 + 0.3);
+    }
+}
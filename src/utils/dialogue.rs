@@ -0,0 +1,418 @@
+//! Dialogue and subtitle playback.
+//!
+//! Dialogue is a sequence of timed lines, every line has speaker and text which are
+//! localization keys resolved through [`StringTable`]. Dialogue can be bound to a sound
+//! source with recorded speech, in this case lines are synchronized with playback time of
+//! the source, so subtitles never drift from voice even if sound was paused or stalled.
+//! Dialogues without sound are advanced by time passed to update.
+//!
+//! [`SubtitleView`] is a default UI widget that shows current line at the bottom of
+//! screen, games with custom UI can read current line from [`DialoguePlayer`] directly.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::utils::dialogue::{Dialogue, DialogueLine, DialoguePlayer, StringTable};
+//!
+//! let table = StringTable::parse(
+//!     "guard = Guard\n\
+//!      guard_halt = Halt! Who goes there?\n\
+//!      guard_pass = You may pass.",
+//! );
+//! let dialogue = Dialogue::new()
+//!     .with_line(DialogueLine::new("guard", "guard_halt", 0.0, 2.0))
+//!     .with_line(DialogueLine::new("guard", "guard_pass", 2.5, 1.5));
+//!
+//! let mut player = DialoguePlayer::new();
+//! player.play(dialogue);
+//! player.update(1.0, None);
+//! let subtitle = player.subtitle(&table).unwrap();
+//! assert_eq!(subtitle.speaker, "Guard");
+//! assert_eq!(subtitle.text, "Halt! Who goes there?");
+//! ```
+
+use crate::{
+    core::{color::Color, pool::Handle},
+    gui::{
+        border::BorderBuilder,
+        brush::Brush,
+        message::{MessageData, MessageDirection, TextMessage, WidgetMessage},
+        node::UINode,
+        text::TextBuilder,
+        widget::WidgetBuilder,
+        BuildContext, Control, HorizontalAlignment, Thickness, UserInterface, VerticalAlignment,
+    },
+    sound::{
+        context::Context,
+        source::{SoundSource, Status},
+    },
+    utils::handle_debug::{HandleDebug, HandleScope},
+};
+use std::collections::{HashMap, VecDeque};
+
+/// Simple localization table which maps keys to localized strings.
+#[derive(Clone, Debug, Default)]
+pub struct StringTable {
+    entries: HashMap<String, String>,
+}
+
+impl StringTable {
+    /// Creates new empty table.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parses table from text where every line has `key = value` form. Empty lines and
+    /// lines starting with `#` are ignored, `\n` in values is replaced with line break.
+    pub fn parse(text: &str) -> Self {
+        let mut table = Self::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(separator) = line.find('=') {
+                let key = line[..separator].trim();
+                let value = line[(separator + 1)..].trim().replace("\\n", "\n");
+                table.insert(key, value);
+            }
+        }
+        table
+    }
+
+    /// Adds or replaces entry.
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.entries.insert(key.into(), value.into());
+    }
+
+    /// Returns localized string for given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|s| s.as_str())
+    }
+
+    /// Returns localized string for given key, or key itself if there is no such entry, so
+    /// missing translations are visible but do not break dialogues.
+    pub fn resolve<'a>(&'a self, key: &'a str) -> &'a str {
+        self.get(key).unwrap_or(key)
+    }
+}
+
+/// Single timed line of a dialogue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueLine {
+    /// Localization key of speaker name, can be empty.
+    pub speaker: String,
+    /// Localization key of text.
+    pub text: String,
+    /// Time (in seconds) from beginning of dialogue at which line appears.
+    pub start: f32,
+    /// Time (in seconds) during which line is shown.
+    pub duration: f32,
+}
+
+impl DialogueLine {
+    /// Creates new line.
+    pub fn new<S: Into<String>, T: Into<String>>(
+        speaker: S,
+        text: T,
+        start: f32,
+        duration: f32,
+    ) -> Self {
+        Self {
+            speaker: speaker.into(),
+            text: text.into(),
+            start,
+            duration: duration.max(0.0),
+        }
+    }
+
+    /// Returns true if line is shown at given time.
+    pub fn is_active(&self, time: f32) -> bool {
+        time >= self.start && time < self.start + self.duration
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct Dialogue {
+    lines: Vec<DialogueLine>,
+    sound: Handle<SoundSource>,
+}
+
+impl Dialogue {
+    /// Creates new empty dialogue.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds new line to dialogue.
+    pub fn with_line(mut self, line: DialogueLine) -> Self {
+        self.add_line(line);
+        self
+    }
+
+    /// Binds dialogue to a sound source, timing of lines will follow playback time of the
+    /// source. Sound is registered for handle debugging if it has no name yet, so an
+    /// invalid handle is reported as a dialogue sound.
+    pub fn with_sound(mut self, sound: Handle<SoundSource>) -> Self {
+        if HandleDebug::name_of(HandleScope::GLOBAL, sound).is_none() {
+            HandleDebug::register(HandleScope::GLOBAL, sound, "Dialogue sound");
+        }
+        self.sound = sound;
+        self
+    }
+
+    /// Adds new line to dialogue, lines are kept sorted by start time.
+    pub fn add_line(&mut self, line: DialogueLine) {
+        let index = self
+            .lines
+            .iter()
+            .position(|l| l.start > line.start)
+            .unwrap_or_else(|| self.lines.len());
+        self.lines.insert(index, line);
+    }
+
+    /// Returns lines of dialogue.
+    pub fn lines(&self) -> &[DialogueLine] {
+        &self.lines
+    }
+
+    /// Returns sound source bound to dialogue.
+    pub fn sound(&self) -> Handle<SoundSource> {
+        self.sound
+    }
+
+    /// Returns total duration of dialogue.
+    pub fn duration(&self) -> f32 {
+        self.lines
+            .iter()
+            .map(|l| l.start + l.duration)
+            .fold(0.0, f32::max)
+    }
+
+    /// Returns line that is shown at given time.
+    pub fn line_at(&self, time: f32) -> Option<&DialogueLine> {
+        // Later line wins if lines overlap.
+        self.lines.iter().rev().find(|l| l.is_active(time))
+    }
+}
+
+/// Localized line ready to be shown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subtitle {
+    /// Localized speaker name.
+    pub speaker: String,
+    /// Localized text.
+    pub text: String,
+}
+
+/// Plays queue of dialogues.
+#[derive(Clone, Debug, Default)]
+pub struct DialoguePlayer {
+    queue: VecDeque<Dialogue>,
+    time: f32,
+}
+
+impl DialoguePlayer {
+    /// Creates new player with empty queue.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds dialogue to the end of queue, it will be played when previous dialogues are
+    /// finished.
+    pub fn play(&mut self, dialogue: Dialogue) {
+        if self.queue.is_empty() {
+            self.time = 0.0;
+        }
+        self.queue.push_back(dialogue);
+    }
+
+    /// Stops current dialogue and clears queue. Sound sources are not stopped.
+    pub fn stop(&mut self) {
+        self.queue.clear();
+        self.time = 0.0;
+    }
+
+    /// Skips current dialogue and starts next one.
+    pub fn skip(&mut self) {
+        self.queue.pop_front();
+        self.time = 0.0;
+    }
+
+    /// Returns true if some dialogue is playing.
+    pub fn is_playing(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Returns currently playing dialogue.
+    pub fn current(&self) -> Option<&Dialogue> {
+        self.queue.front()
+    }
+
+    /// Returns time from beginning of current dialogue.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Advances current dialogue. If dialogue is bound to a sound source and sound context
+    /// is given, time is taken from the source while it is playing and is frozen while it
+    /// is paused, otherwise time is advanced by `dt`.
+    pub fn update(&mut self, dt: f32, sound_context: Option<&Context>) {
+        let dialogue = match self.queue.front() {
+            Some(dialogue) => dialogue,
+            None => return,
+        };
+
+        match sound_context {
+            Some(context) if dialogue.sound.is_some() => {
+                if HandleDebug::is_enabled() && !context.sources().is_valid_handle(dialogue.sound) {
+                    HandleDebug::report_invalid(HandleScope::GLOBAL, dialogue.sound);
+                }
+                let source = context.source(dialogue.sound).generic();
+                match source.status() {
+                    Status::Playing => self.time = source.playback_time().as_secs_f32(),
+                    // Sound is not started yet or is already finished.
+                    Status::Stopped => self.time += dt,
+                    // Hold current line while sound is paused.
+                    Status::Paused => (),
+                }
+            }
+            _ => self.time += dt,
+        }
+
+        let finished = self.time >= dialogue.duration();
+        if finished {
+            self.skip();
+        }
+    }
+
+    /// Returns current line of current dialogue.
+    pub fn current_line(&self) -> Option<&DialogueLine> {
+        self.queue.front().and_then(|d| d.line_at(self.time))
+    }
+
+    /// Returns current line localized using given table.
+    pub fn subtitle(&self, table: &StringTable) -> Option<Subtitle> {
+        self.current_line().map(|line| Subtitle {
+            speaker: table.resolve(&line.speaker).to_owned(),
+            text: table.resolve(&line.text).to_owned(),
+        })
+    }
+}
+
+/// Default subtitle widget - semi-transparent panel at the bottom of screen with speaker
+/// name and text.
+pub struct SubtitleView<M: 'static + MessageData, C: 'static + Control<M, C>> {
+    root: Handle<UINode<M, C>>,
+    text: Handle<UINode<M, C>>,
+    shown: Option<Subtitle>,
+}
+
+impl<M: 'static + MessageData, C: 'static + Control<M, C>> SubtitleView<M, C> {
+    /// Creates new subtitle widget, it is hidden until first line is shown.
+    pub fn new(ctx: &mut BuildContext<M, C>) -> Self {
+        let text;
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_margin(Thickness::bottom(40.0))
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 160)))
+                .with_child({
+                    text = TextBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(8.0))
+                            .with_foreground(Brush::Solid(Color::WHITE)),
+                    )
+                    .with_wrap(true)
+                    .with_horizontal_text_alignment(HorizontalAlignment::Center)
+                    .build(ctx);
+                    text
+                }),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            text,
+            shown: None,
+        }
+    }
+
+    /// Returns handle of root widget, it can be used to reposition widget.
+    pub fn root(&self) -> Handle<UINode<M, C>> {
+        self.root
+    }
+
+    /// Synchronizes widget with current line of player. Messages are sent only when line
+    /// changes.
+    pub fn update(
+        &mut self,
+        ui: &mut UserInterface<M, C>,
+        player: &DialoguePlayer,
+        table: &StringTable,
+    ) {
+        let subtitle = player.subtitle(table);
+        if subtitle == self.shown {
+            return;
+        }
+
+        if let Some(subtitle) = subtitle.as_ref() {
+            let text = if subtitle.speaker.is_empty() {
+                subtitle.text.clone()
+            } else {
+                format!("{}: {}", subtitle.speaker, subtitle.text)
+            };
+            ui.send_message(TextMessage::text(
+                self.text,
+                MessageDirection::ToWidget,
+                text,
+            ));
+        }
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            subtitle.is_some(),
+        ));
+
+        self.shown = subtitle;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::dialogue::{Dialogue, DialogueLine, DialoguePlayer, StringTable};
+
+    #[test]
+    fn test_string_table() {
+        let table = StringTable::parse("# Comment\n\nhello = Hello\\nworld\nbroken line");
+        assert_eq!(table.get("hello"), Some("Hello\nworld"));
+        assert_eq!(table.resolve("missing_key"), "missing_key");
+    }
+
+    #[test]
+    fn test_dialogue_playback() {
+        let mut player = DialoguePlayer::new();
+        player.play(
+            Dialogue::new()
+                .with_line(DialogueLine::new("", "second", 2.0, 1.0))
+                .with_line(DialogueLine::new("", "first", 0.0, 1.5)),
+        );
+        player.play(Dialogue::new().with_line(DialogueLine::new("", "next", 0.0, 1.0)));
+
+        player.update(1.0, None);
+        assert_eq!(player.current_line().unwrap().text, "first");
+        // Gap between lines.
+        player.update(0.7, None);
+        assert!(player.current_line().is_none());
+        player.update(0.5, None);
+        assert_eq!(player.current_line().unwrap().text, "second");
+        // First dialogue is finished, next one starts from beginning.
+        player.update(1.0, None);
+        assert_eq!(player.current_line().unwrap().text, "next");
+        player.update(1.0, None);
+        assert!(!player.is_playing());
+    }
+}
@@ -0,0 +1,454 @@
+//! Contains reusable manipulators (gizmos) to translate, rotate and scale objects in
+//! 3D space.
+//!
+//! Gizmo is drawn using debug renderer and interacts with user through picking rays
+//! (see `Camera::make_ray`). It does not modify any node by itself, instead it outputs
+//! transform deltas, so it can be used to edit anything that has a transform: scene
+//! nodes, light probes, navmesh vertices and so on.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::math::ray::Ray,
+//!     renderer::debug_renderer::DebugRenderer,
+//!     scene::transform::Transform,
+//!     utils::gizmo::{Gizmo, GizmoDelta, GizmoMode},
+//! };
+//!
+//! fn on_mouse_move(gizmo: &mut Gizmo, ray: &Ray, transform: &mut Transform) {
+//!     gizmo.set_position(transform.position());
+//!     if gizmo.is_dragging() {
+//!         match gizmo.drag(ray) {
+//!             Some(GizmoDelta::Translation(offset)) => {
+//!                 transform.offset(offset);
+//!             }
+//!             Some(GizmoDelta::Rotation(rotation)) => {
+//!                 let new_rotation = rotation * transform.rotation();
+//!                 transform.set_rotation(new_rotation);
+//!             }
+//!             Some(GizmoDelta::Scale(scale)) => {
+//!                 let new_scale = transform.scale() + scale;
+//!                 transform.set_scale(new_scale);
+//!             }
+//!             None => (),
+//!         }
+//!     } else {
+//!         gizmo.update_hover(ray);
+//!     }
+//! }
+//!
+//! fn on_mouse_down(gizmo: &mut Gizmo, ray: &Ray) {
+//!     // If this returns false, nothing was picked and click can be used for something else.
+//!     gizmo.begin_drag(ray);
+//! }
+//!
+//! fn on_mouse_up(gizmo: &mut Gizmo) {
+//!     gizmo.end_drag();
+//! }
+//!
+//! fn draw(gizmo: &Gizmo, debug_renderer: &mut DebugRenderer) {
+//!     gizmo.draw(debug_renderer);
+//! }
+//! ```
+
+use crate::{
+    core::{
+        color::Color,
+        math::{quat::Quat, ray::Ray, vec3::Vec3},
+    },
+    renderer::debug_renderer::{DebugRenderer, Line},
+};
+
+/// Defines which kind of transform gizmo produces.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    /// Gizmo is drawn as three arrows, and produces translation deltas.
+    Translate,
+    /// Gizmo is drawn as three circles, and produces rotation deltas.
+    Rotate,
+    /// Gizmo is drawn as three axes with square handles, and produces scale deltas.
+    Scale,
+}
+
+/// World space axis of gizmo.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoAxis {
+    /// X axis, drawn red.
+    X,
+    /// Y axis, drawn green.
+    Y,
+    /// Z axis, drawn blue.
+    Z,
+}
+
+impl GizmoAxis {
+    /// Returns unit direction vector of axis.
+    pub fn direction(self) -> Vec3 {
+        match self {
+            GizmoAxis::X => Vec3::new(1.0, 0.0, 0.0),
+            GizmoAxis::Y => Vec3::new(0.0, 1.0, 0.0),
+            GizmoAxis::Z => Vec3::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    /// Returns two unit vectors which are perpendicular to axis and to each other.
+    fn tangents(self) -> (Vec3, Vec3) {
+        match self {
+            GizmoAxis::X => (GizmoAxis::Y.direction(), GizmoAxis::Z.direction()),
+            GizmoAxis::Y => (GizmoAxis::Z.direction(), GizmoAxis::X.direction()),
+            GizmoAxis::Z => (GizmoAxis::X.direction(), GizmoAxis::Y.direction()),
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            GizmoAxis::X => Color::opaque(255, 0, 0),
+            GizmoAxis::Y => Color::opaque(0, 255, 0),
+            GizmoAxis::Z => Color::opaque(0, 0, 255),
+        }
+    }
+}
+
+const AXES: [GizmoAxis; 3] = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+
+/// Transform delta produced by gizmo while dragging. Deltas are relative to previous
+/// `drag` call, so they can be applied directly on each mouse move.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GizmoDelta {
+    /// World space offset.
+    Translation(Vec3),
+    /// World space rotation around gizmo position. Should be pre-multiplied with current
+    /// rotation.
+    Rotation(Quat),
+    /// Additive change of scale along each axis.
+    Scale(Vec3),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum DragState {
+    /// Position of last picked point along axis.
+    Linear(f32),
+    /// Last picked point on rotation plane.
+    Angular(Vec3),
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct Gizmo {
+    mode: GizmoMode,
+    position: Vec3,
+    size: f32,
+    hovered: Option<GizmoAxis>,
+    drag_axis: Option<GizmoAxis>,
+    drag_state: Option<DragState>,
+}
+
+impl Gizmo {
+    /// Creates new gizmo of given mode at origin.
+    pub fn new(mode: GizmoMode) -> Self {
+        Self {
+            mode,
+            position: Vec3::ZERO,
+            size: 1.0,
+            hovered: None,
+            drag_axis: None,
+            drag_state: None,
+        }
+    }
+
+    /// Sets new mode of gizmo. Cancels current drag, if any.
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        self.mode = mode;
+        self.end_drag();
+    }
+
+    /// Returns current mode.
+    pub fn mode(&self) -> GizmoMode {
+        self.mode
+    }
+
+    /// Sets world space position of gizmo, usually it is position of edited object.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+
+    /// Returns world space position of gizmo.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Sets size of gizmo in world units - length of arrows or radius of circles. To keep
+    /// gizmo constant size on screen, set size proportional to distance to camera.
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size.max(std::f32::EPSILON);
+    }
+
+    /// Returns size of gizmo in world units.
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Returns axis under cursor, it is drawn highlighted.
+    pub fn hovered(&self) -> Option<GizmoAxis> {
+        self.hovered
+    }
+
+    /// Returns true if gizmo is being dragged.
+    pub fn is_dragging(&self) -> bool {
+        self.drag_axis.is_some()
+    }
+
+    fn pick_tolerance(&self) -> f32 {
+        self.size * 0.1
+    }
+
+    /// Returns parameters of closest points of axis line and ray: (axis, ray).
+    fn closest_on_axis(&self, axis: GizmoAxis, ray: &Ray) -> Option<(f32, f32)> {
+        let d = axis.direction();
+        let r = ray.dir;
+        let w = self.position - ray.origin;
+        let b = d.dot(&r);
+        let c = r.dot(&r);
+        let dd = d.dot(&w);
+        let e = r.dot(&w);
+        let denom = c - b * b;
+        if denom.abs() < std::f32::EPSILON {
+            // Ray is parallel to axis.
+            return None;
+        }
+        Some(((b * e - c * dd) / denom, (e - b * dd) / denom))
+    }
+
+    /// Returns intersection point of ray with plane that goes through gizmo position and
+    /// perpendicular to given axis.
+    fn intersect_plane(&self, axis: GizmoAxis, ray: &Ray) -> Option<Vec3> {
+        let n = axis.direction();
+        let denom = n.dot(&ray.dir);
+        if denom.abs() < std::f32::EPSILON {
+            return None;
+        }
+        let s = n.dot(&(self.position - ray.origin)) / denom;
+        if s < 0.0 {
+            None
+        } else {
+            Some(ray.origin + ray.dir.scale(s))
+        }
+    }
+
+    /// Returns closest axis of gizmo picked by given ray, if any.
+    pub fn pick(&self, ray: &Ray) -> Option<GizmoAxis> {
+        let mut closest: Option<(GizmoAxis, f32)> = None;
+        for &axis in AXES.iter() {
+            let distance = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    if let Some((t, s)) = self.closest_on_axis(axis, ray) {
+                        if t < 0.0 || t > self.size || s < 0.0 {
+                            continue;
+                        }
+                        let on_axis = self.position + axis.direction().scale(t);
+                        let on_ray = ray.origin + ray.dir.scale(s);
+                        let distance = (on_axis - on_ray).len();
+                        if distance > self.pick_tolerance() {
+                            continue;
+                        }
+                        // Prefer axis which is closer to the ray, not to the camera.
+                        distance
+                    } else {
+                        continue;
+                    }
+                }
+                GizmoMode::Rotate => {
+                    if let Some(point) = self.intersect_plane(axis, ray) {
+                        let distance = ((point - self.position).len() - self.size).abs();
+                        if distance > self.pick_tolerance() {
+                            continue;
+                        }
+                        distance
+                    } else {
+                        continue;
+                    }
+                }
+            };
+            if closest.map_or(true, |(_, d)| distance < d) {
+                closest = Some((axis, distance));
+            }
+        }
+        closest.map(|(axis, _)| axis)
+    }
+
+    /// Updates hovered axis using given ray, should be called on mouse move when gizmo is
+    /// not dragged.
+    pub fn update_hover(&mut self, ray: &Ray) -> Option<GizmoAxis> {
+        self.hovered = self.pick(ray);
+        self.hovered
+    }
+
+    /// Tries to start dragging of an axis picked by given ray. Returns true if an axis was
+    /// picked.
+    pub fn begin_drag(&mut self, ray: &Ray) -> bool {
+        self.end_drag();
+        if let Some(axis) = self.pick(ray) {
+            let state = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => self
+                    .closest_on_axis(axis, ray)
+                    .map(|(t, _)| DragState::Linear(t)),
+                GizmoMode::Rotate => self.intersect_plane(axis, ray).map(DragState::Angular),
+            };
+            if state.is_some() {
+                self.drag_axis = Some(axis);
+                self.drag_state = state;
+                self.hovered = Some(axis);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns transform delta since previous call (or since `begin_drag`). Returns None
+    /// if gizmo is not dragged or ray does not produce valid point (i.e. it is parallel
+    /// to dragged axis).
+    pub fn drag(&mut self, ray: &Ray) -> Option<GizmoDelta> {
+        let axis = self.drag_axis?;
+        match (self.mode, self.drag_state?) {
+            (GizmoMode::Translate, DragState::Linear(last)) => {
+                let (t, _) = self.closest_on_axis(axis, ray)?;
+                self.drag_state = Some(DragState::Linear(t));
+                Some(GizmoDelta::Translation(axis.direction().scale(t - last)))
+            }
+            (GizmoMode::Scale, DragState::Linear(last)) => {
+                let (t, _) = self.closest_on_axis(axis, ray)?;
+                self.drag_state = Some(DragState::Linear(t));
+                Some(GizmoDelta::Scale(
+                    axis.direction().scale((t - last) / self.size),
+                ))
+            }
+            (GizmoMode::Rotate, DragState::Angular(last)) => {
+                let point = self.intersect_plane(axis, ray)?;
+                self.drag_state = Some(DragState::Angular(point));
+                let from = last - self.position;
+                let to = point - self.position;
+                let angle = from.cross(&to).dot(&axis.direction()).atan2(from.dot(&to));
+                Some(GizmoDelta::Rotation(Quat::from_axis_angle(
+                    axis.direction(),
+                    angle,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    /// Stops dragging.
+    pub fn end_drag(&mut self) {
+        self.drag_axis = None;
+        self.drag_state = None;
+    }
+
+    /// Pushes lines of gizmo into debug renderer.
+    pub fn draw(&self, debug_renderer: &mut DebugRenderer) {
+        let highlight = Color::opaque(255, 255, 0);
+        for &axis in AXES.iter() {
+            let color = if self.drag_axis.or(self.hovered) == Some(axis) {
+                highlight
+            } else {
+                axis.color()
+            };
+            let dir = axis.direction();
+            let (u, v) = axis.tangents();
+            let tip = self.position + dir.scale(self.size);
+            match self.mode {
+                GizmoMode::Translate => {
+                    debug_renderer.add_line(Line {
+                        begin: self.position,
+                        end: tip,
+                        color,
+                    });
+                    let head_base = self.position + dir.scale(self.size * 0.85);
+                    let head_radius = self.size * 0.05;
+                    for &side in &[u, v, u.scale(-1.0), v.scale(-1.0)] {
+                        debug_renderer.add_line(Line {
+                            begin: tip,
+                            end: head_base + side.scale(head_radius),
+                            color,
+                        });
+                    }
+                }
+                GizmoMode::Scale => {
+                    debug_renderer.add_line(Line {
+                        begin: self.position,
+                        end: tip,
+                        color,
+                    });
+                    let half = self.size * 0.05;
+                    let corners = [
+                        tip + u.scale(half) + v.scale(half),
+                        tip + u.scale(half) - v.scale(half),
+                        tip - u.scale(half) - v.scale(half),
+                        tip - u.scale(half) + v.scale(half),
+                    ];
+                    for i in 0..corners.len() {
+                        debug_renderer.add_line(Line {
+                            begin: corners[i],
+                            end: corners[(i + 1) % corners.len()],
+                            color,
+                        });
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let segments = 48;
+                    let point = |i: usize| {
+                        let angle = i as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+                        self.position
+                            + u.scale(angle.cos() * self.size)
+                            + v.scale(angle.sin() * self.size)
+                    };
+                    for i in 0..segments {
+                        debug_renderer.add_line(Line {
+                            begin: point(i),
+                            end: point(i + 1),
+                            color,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::{ray::Ray, vec3::Vec3},
+        utils::gizmo::{Gizmo, GizmoAxis, GizmoDelta, GizmoMode},
+    };
+
+    #[test]
+    fn gizmo_translate_test() {
+        let mut gizmo = Gizmo::new(GizmoMode::Translate);
+
+        // Looking down at X axis.
+        let ray =
+            Ray::from_two_points(&Vec3::new(0.5, 5.0, 0.0), &Vec3::new(0.5, 0.0, 0.0)).unwrap();
+        assert_eq!(gizmo.pick(&ray), Some(GizmoAxis::X));
+        assert!(gizmo.begin_drag(&ray));
+
+        let ray =
+            Ray::from_two_points(&Vec3::new(0.75, 5.0, 0.0), &Vec3::new(0.75, 0.0, 0.0)).unwrap();
+        match gizmo.drag(&ray) {
+            Some(GizmoDelta::Translation(offset)) => {
+                assert!((offset.x - 0.25).abs() < 0.001);
+                assert!(offset.y.abs() < 0.001);
+                assert!(offset.z.abs() < 0.001);
+            }
+            _ => panic!("expected translation"),
+        }
+
+        gizmo.end_drag();
+        assert!(!gizmo.is_dragging());
+
+        // Miss.
+        let ray =
+            Ray::from_two_points(&Vec3::new(5.0, 5.0, 5.0), &Vec3::new(5.0, 0.0, 5.0)).unwrap();
+        assert_eq!(gizmo.pick(&ray), None);
+    }
+}
@@ -0,0 +1,400 @@
+//! Debugging of handles.
+//!
+//! Handle is just an index and a generation of pool record, so when a handle of freed
+//! object is used, pool can only panic with "access of freed pool record" without any clue
+//! what the object was. Handle debug registry keeps names and types of live handles and a
+//! history of freed ones, so such errors can be explained:
+//!
+//! - Access of graph node or scene by invalid handle writes to log what object it was and
+//! which object reuses its pool record now, right before the panic. Same is done for stale
+//! rigid bodies bound to nodes and for sound sources of dialogues.
+//! - [`HandleDebug::dump`] lists every live handle grouped by type.
+//! - [`HandleDebug::mark`] and [`HandleDebug::alive_since`] find leaks - handles that were
+//! created after some point (for example before level was loaded) and are still alive
+//! (after level was unloaded).
+//!
+//! Handles are registered in scopes - every pool has its own records, so handles with same
+//! index from different pools (i.e. nodes of two scenes) do not collide. Every graph has
+//! its own [`HandleScope`] (see `Graph::debug_scope`), its nodes are registered
+//! automatically and released when graph is dropped. Rigid bodies of a scene may be
+//! registered in scope of its graph, bodies created by the engine (ragdolls, bodies woken
+//! up after sleep) are registered there automatically, invalid handles of bodies bound to
+//! nodes are reported by scene. Scenes and other engine-wide handles (sound sources, handles of game entities)
+//! use [`HandleScope::GLOBAL`]. Handles can be registered manually by
+//! [`HandleDebug::register`] and [`HandleDebug::unregister`]. Resources are not handles -
+//! they're reference counted and can't be freed while in use, see `ResourceManager` to list
+//! them.
+//!
+//! Registry is disabled by default and costs nothing in this state, it should be enabled
+//! before objects of interest are created.
+//!
+//! # Example
+//!
+//! ```
+//! use rg3d::{
+//!     scene::{base::BaseBuilder, graph::Graph},
+//!     utils::handle_debug::HandleDebug,
+//! };
+//!
+//! HandleDebug::set_enabled(true);
+//! let mark = HandleDebug::mark();
+//!
+//! let mut graph = Graph::new();
+//! let pivot = graph.add_node(BaseBuilder::new().with_name("Pivot").build_node());
+//! assert_eq!(
+//!     HandleDebug::name_of(graph.debug_scope(), pivot).as_deref(),
+//!     Some("Pivot")
+//! );
+//!
+//! // Pivot is never removed, so it is reported as leak.
+//! assert!(HandleDebug::alive_since(mark)
+//!     .iter()
+//!     .any(|record| record.name == "Pivot"));
+//! println!("{}", HandleDebug::dump());
+//! ```
+
+use crate::{core::pool::Handle, utils::log::Log};
+use std::{
+    any::{self, TypeId},
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Write,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Mutex,
+    },
+};
+
+/// Set of handles of a single pool, see module docs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HandleScope(u64);
+
+impl HandleScope {
+    /// Scope of engine-wide handles.
+    pub const GLOBAL: HandleScope = HandleScope(0);
+
+    /// Creates new unique scope.
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+}
+
+impl Default for HandleScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Information about a registered handle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandleRecord {
+    /// Scope of handle.
+    pub scope: HandleScope,
+    /// Name of type of object, i.e. `rg3d::scene::node::Node`.
+    pub type_name: &'static str,
+    /// Index of pool record.
+    pub index: u32,
+    /// Generation of pool record.
+    pub generation: u32,
+    /// Name of object given at registration.
+    pub name: String,
+    /// Sequence number of registration, see [`HandleDebug::mark`].
+    pub serial: u64,
+}
+
+/// Maximum amount of freed handles to remember.
+const FREED_HISTORY: usize = 4096;
+
+type Key = (HandleScope, TypeId, u32);
+
+#[derive(Default)]
+struct Registry {
+    live: HashMap<Key, HandleRecord>,
+    freed: VecDeque<(TypeId, HandleRecord)>,
+    serial: u64,
+}
+
+impl Registry {
+    fn register(&mut self, type_id: TypeId, mut record: HandleRecord) {
+        self.serial += 1;
+        record.serial = self.serial;
+        self.live
+            .insert((record.scope, type_id, record.index), record);
+    }
+
+    fn push_freed(&mut self, type_id: TypeId, record: HandleRecord) {
+        if self.freed.len() == FREED_HISTORY {
+            self.freed.pop_front();
+        }
+        self.freed.push_back((type_id, record));
+    }
+
+    fn unregister(&mut self, scope: HandleScope, type_id: TypeId, index: u32, generation: u32) {
+        let key = (scope, type_id, index);
+        if self
+            .live
+            .get(&key)
+            .map_or(false, |r| r.generation == generation)
+        {
+            let record = self.live.remove(&key).unwrap();
+            self.push_freed(type_id, record);
+        }
+    }
+
+    fn release_scope(&mut self, scope: HandleScope) {
+        let mut keys = self
+            .live
+            .keys()
+            .filter(|(s, _, _)| *s == scope)
+            .copied()
+            .collect::<Vec<_>>();
+        keys.sort_by_key(|key| self.live[key].serial);
+        for key in keys {
+            let record = self.live.remove(&key).unwrap();
+            self.push_freed(key.1, record);
+        }
+    }
+
+    fn find(&self, key: Key, generation: u32) -> Option<&HandleRecord> {
+        let (scope, type_id, index) = key;
+        self.live
+            .get(&key)
+            .filter(|record| record.generation == generation)
+            .or_else(|| {
+                self.freed
+                    .iter()
+                    .rev()
+                    .find(|(t, r)| {
+                        *t == type_id
+                            && r.scope == scope
+                            && r.index == index
+                            && r.generation == generation
+                    })
+                    .map(|(_, record)| record)
+            })
+    }
+
+    /// Explains why given handle may be invalid.
+    fn describe(&self, key: Key, type_name: &str, generation: u32) -> String {
+        let index = key.2;
+        let live = self.live.get(&key);
+        match self.find(key, generation) {
+            Some(record) if live.map_or(false, |r| r.generation == generation) => format!(
+                "handle {}:{} of {} is registered as live object \"{}\"",
+                index, generation, type_name, record.name
+            ),
+            Some(record) => {
+                let reused = match live {
+                    Some(new) => format!(", its record is now used by \"{}\"", new.name),
+                    None => String::new(),
+                };
+                format!(
+                    "handle {}:{} of {} was object \"{}\" which was freed{}",
+                    index, generation, type_name, record.name, reused
+                )
+            }
+            None => format!(
+                "handle {}:{} of {} was never registered or was freed too long ago",
+                index, generation, type_name
+            ),
+        }
+    }
+
+    fn alive_since(&self, mark: u64) -> Vec<HandleRecord> {
+        let mut records = self
+            .live
+            .values()
+            .filter(|record| record.serial > mark)
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by_key(|record| record.serial);
+        records
+    }
+
+    fn dump(&self) -> String {
+        let mut by_type = BTreeMap::<&str, Vec<&HandleRecord>>::new();
+        for record in self.live.values() {
+            by_type.entry(record.type_name).or_default().push(record);
+        }
+        let mut dump = String::new();
+        for (type_name, mut records) in by_type {
+            records.sort_by_key(|record| record.index);
+            let _ = writeln!(dump, "{} - {} live handle(s):", type_name, records.len());
+            for record in records {
+                let _ = writeln!(
+                    dump,
+                    "    {}:{} \"{}\"",
+                    record.index, record.generation, record.name
+                );
+            }
+        }
+        dump
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Default::default());
+}
+
+/// Global handle debug registry, see module docs.
+pub struct HandleDebug {}
+
+impl HandleDebug {
+    /// Enables or disables registry. Disabling clears registry.
+    pub fn set_enabled(enabled: bool) {
+        ENABLED.store(enabled, atomic::Ordering::SeqCst);
+        if !enabled {
+            *REGISTRY.lock().unwrap() = Default::default();
+        }
+    }
+
+    /// Returns true if registry is enabled.
+    pub fn is_enabled() -> bool {
+        ENABLED.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Registers live handle with given name in given scope, previous record with same
+    /// index is replaced.
+    pub fn register<T: 'static>(scope: HandleScope, handle: Handle<T>, name: &str) {
+        if Self::is_enabled() && handle.is_some() {
+            REGISTRY.lock().unwrap().register(
+                TypeId::of::<T>(),
+                HandleRecord {
+                    scope,
+                    type_name: any::type_name::<T>(),
+                    index: handle.index(),
+                    generation: handle.generation(),
+                    name: name.to_owned(),
+                    serial: 0,
+                },
+            );
+        }
+    }
+
+    /// Moves handle to history of freed handles, must be called when object is freed.
+    pub fn unregister<T: 'static>(scope: HandleScope, handle: Handle<T>) {
+        if Self::is_enabled() {
+            REGISTRY.lock().unwrap().unregister(
+                scope,
+                TypeId::of::<T>(),
+                handle.index(),
+                handle.generation(),
+            );
+        }
+    }
+
+    /// Moves every live handle of given scope to history of freed handles, must be called
+    /// when pool of the scope is destroyed.
+    pub fn release_scope(scope: HandleScope) {
+        if Self::is_enabled() {
+            REGISTRY.lock().unwrap().release_scope(scope);
+        }
+    }
+
+    /// Returns name of given handle, live or freed.
+    pub fn name_of<T: 'static>(scope: HandleScope, handle: Handle<T>) -> Option<String> {
+        REGISTRY
+            .lock()
+            .unwrap()
+            .find(
+                (scope, TypeId::of::<T>(), handle.index()),
+                handle.generation(),
+            )
+            .map(|record| record.name.clone())
+    }
+
+    /// Returns description of given handle which explains why it may be invalid.
+    pub fn describe<T: 'static>(scope: HandleScope, handle: Handle<T>) -> String {
+        REGISTRY.lock().unwrap().describe(
+            (scope, TypeId::of::<T>(), handle.index()),
+            any::type_name::<T>(),
+            handle.generation(),
+        )
+    }
+
+    /// Writes warning about use of invalid handle to log. Must be called right before
+    /// invalid handle is used, because use most likely panics.
+    pub fn report_invalid<T: 'static>(scope: HandleScope, handle: Handle<T>) {
+        if Self::is_enabled() {
+            Log::writeln(format!(
+                "Use of invalid handle: {}",
+                Self::describe(scope, handle)
+            ));
+        }
+    }
+
+    /// Returns current sequence number of registrations, handles registered after this
+    /// call can be found by [`HandleDebug::alive_since`].
+    pub fn mark() -> u64 {
+        REGISTRY.lock().unwrap().serial
+    }
+
+    /// Returns every live handle registered after given mark, in order of registration.
+    pub fn alive_since(mark: u64) -> Vec<HandleRecord> {
+        REGISTRY.lock().unwrap().alive_since(mark)
+    }
+
+    /// Returns human-readable list of every live handle grouped by type.
+    pub fn dump() -> String {
+        REGISTRY.lock().unwrap().dump()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::handle_debug::{HandleRecord, HandleScope, Registry};
+    use std::any::TypeId;
+
+    #[test]
+    fn test_registry() {
+        let type_id = TypeId::of::<u32>();
+        let scope = HandleScope::new();
+        let record = |index, generation, name: &str| HandleRecord {
+            scope,
+            type_name: "u32",
+            index,
+            generation,
+            name: name.to_owned(),
+            serial: 0,
+        };
+        let mut registry = Registry::default();
+        registry.register(type_id, record(0, 1, "Foo"));
+        let mark = registry.serial;
+        registry.register(type_id, record(1, 1, "Bar"));
+
+        // Stale generation is ignored.
+        registry.unregister(scope, type_id, 0, 2);
+        assert!(registry.find((scope, type_id, 0), 1).is_some());
+
+        registry.unregister(scope, type_id, 0, 1);
+        registry.register(type_id, record(0, 2, "Baz"));
+        assert_eq!(registry.find((scope, type_id, 0), 1).unwrap().name, "Foo");
+        assert!(registry
+            .describe((scope, type_id, 0), "u32", 1)
+            .contains("\"Foo\" which was freed, its record is now used by \"Baz\""));
+        assert!(registry.find((scope, TypeId::of::<i32>(), 0), 2).is_none());
+
+        // Same index in other scope does not collide.
+        let other = HandleScope::new();
+        registry.register(
+            type_id,
+            HandleRecord {
+                scope: other,
+                ..record(0, 2, "Other")
+            },
+        );
+        assert_eq!(registry.find((scope, type_id, 0), 2).unwrap().name, "Baz");
+        registry.release_scope(other);
+        assert_eq!(registry.find((other, type_id, 0), 2).unwrap().name, "Other");
+        assert!(registry.live.get(&(other, type_id, 0)).is_none());
+
+        let leaks = registry.alive_since(mark);
+        assert_eq!(
+            leaks.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+            vec!["Bar", "Baz"]
+        );
+        assert!(registry.dump().contains("u32 - 2 live handle(s):"));
+    }
+}
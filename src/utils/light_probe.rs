@@ -0,0 +1,320 @@
+//! Module to generate and sample grids of light probes.
+//!
+//! Light probe is a point in space which stores amount of light coming to it from
+//! six principal directions (so called "ambient cube"). Light probes are organized
+//! in a regular grid, which is used to get interpolated ambient lighting for dynamic
+//! objects, so they will match lightmapped environment.
+//!
+//! # Limitations
+//!
+//! Probes are baked with direct lighting only, shadows and bounced light are not taken
+//! into account (same as in lightmapper).
+
+use crate::{
+    core::{
+        math::{aabb::AxisAlignedBoundingBox, vec3::Vec3},
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::Scene,
+    utils::{
+        lightmap::{self, LightDefinition},
+        log::Log,
+    },
+};
+
+/// Directions of faces of ambient cube, in order: +X, -X, +Y, -Y, +Z, -Z.
+const FACE_DIRECTIONS: [Vec3; 6] = [
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: -1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: -1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: -1.0,
+    },
+];
+
+/// Single light probe, see module docs.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LightProbe {
+    /// Linear color of light coming from each principal direction in order:
+    /// +X, -X, +Y, -Y, +Z, -Z.
+    pub ambient_cube: [Vec3; 6],
+}
+
+impl Visit for LightProbe {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.ambient_cube[0].visit("PositiveX", visitor)?;
+        self.ambient_cube[1].visit("NegativeX", visitor)?;
+        self.ambient_cube[2].visit("PositiveY", visitor)?;
+        self.ambient_cube[3].visit("NegativeY", visitor)?;
+        self.ambient_cube[4].visit("PositiveZ", visitor)?;
+        self.ambient_cube[5].visit("NegativeZ", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl LightProbe {
+    /// Bakes light probe at given position using specified set of lights.
+    pub fn bake<'a, I: IntoIterator<Item = &'a LightDefinition>>(
+        position: Vec3,
+        lights: I,
+    ) -> Self {
+        let mut probe = Self::default();
+        for light in lights {
+            for (face, direction) in probe.ambient_cube.iter_mut().zip(FACE_DIRECTIONS.iter()) {
+                let (color, attenuation) = lightmap::evaluate_light(light, position, *direction);
+                *face += Vec3::new(color.r as f32, color.g as f32, color.b as f32)
+                    .scale(attenuation / 255.0);
+            }
+        }
+        probe
+    }
+
+    /// Returns light coming to a surface with given normal.
+    pub fn evaluate(&self, normal: Vec3) -> Vec3 {
+        let sqr = Vec3::new(
+            normal.x * normal.x,
+            normal.y * normal.y,
+            normal.z * normal.z,
+        );
+        let x = if normal.x >= 0.0 { 0 } else { 1 };
+        let y = if normal.y >= 0.0 { 2 } else { 3 };
+        let z = if normal.z >= 0.0 { 4 } else { 5 };
+        self.ambient_cube[x].scale(sqr.x)
+            + self.ambient_cube[y].scale(sqr.y)
+            + self.ambient_cube[z].scale(sqr.z)
+    }
+
+    fn add_weighted(&mut self, other: &LightProbe, weight: f32) {
+        for (face, other_face) in self.ambient_cube.iter_mut().zip(other.ambient_cube.iter()) {
+            *face += other_face.scale(weight);
+        }
+    }
+}
+
+/// Regular grid of light probes. See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct LightProbeGrid {
+    origin: Vec3,
+    spacing: f32,
+    size_x: usize,
+    size_y: usize,
+    size_z: usize,
+    probes: Vec<LightProbe>,
+}
+
+impl Visit for LightProbeGrid {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.origin.visit("Origin", visitor)?;
+        self.spacing.visit("Spacing", visitor)?;
+
+        let mut size_x = self.size_x as u32;
+        size_x.visit("SizeX", visitor)?;
+        self.size_x = size_x as usize;
+
+        let mut size_y = self.size_y as u32;
+        size_y.visit("SizeY", visitor)?;
+        self.size_y = size_y as usize;
+
+        let mut size_z = self.size_z as u32;
+        size_z.visit("SizeZ", visitor)?;
+        self.size_z = size_z as usize;
+
+        self.probes.visit("Probes", visitor)?;
+
+        visitor.leave_region()?;
+
+        // Validate only after region was left, otherwise visitor would stay inside of it
+        // and everything that is read after the grid would be read at wrong depth.
+        if visitor.is_reading() && self.size_x * self.size_y * self.size_z != self.probes.len() {
+            Log::writeln(format!(
+                "Light probe grid of {}x{}x{} has {} probes, falling back to empty grid.",
+                self.size_x,
+                self.size_y,
+                self.size_z,
+                self.probes.len()
+            ));
+            *self = Default::default();
+        }
+
+        Ok(())
+    }
+}
+
+impl LightProbeGrid {
+    /// Bakes new grid of light probes which covers given bounds. `spacing` defines distance
+    /// between adjacent probes. Every light in the scene is used to bake probes.
+    pub fn new(scene: &Scene, bounds: AxisAlignedBoundingBox, spacing: f32) -> Self {
+        let spacing = spacing.max(std::f32::EPSILON);
+        let extent = bounds.max - bounds.min;
+        let size_x = (extent.x / spacing).ceil().max(0.0) as usize + 1;
+        let size_y = (extent.y / spacing).ceil().max(0.0) as usize + 1;
+        let size_z = (extent.z / spacing).ceil().max(0.0) as usize + 1;
+
+        let lights = lightmap::extract_lights(scene);
+
+        let mut probes = Vec::with_capacity(size_x * size_y * size_z);
+        for z in 0..size_z {
+            for y in 0..size_y {
+                for x in 0..size_x {
+                    let position =
+                        bounds.min + Vec3::new(x as f32, y as f32, z as f32).scale(spacing);
+                    probes.push(LightProbe::bake(
+                        position,
+                        lights.iter().map(|(_, definition)| definition),
+                    ));
+                }
+            }
+        }
+
+        Self {
+            origin: bounds.min,
+            spacing,
+            size_x,
+            size_y,
+            size_z,
+            probes,
+        }
+    }
+
+    /// Returns world space position of the first probe of grid.
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    /// Returns distance between adjacent probes.
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    /// Returns shared reference to array of probes.
+    pub fn probes(&self) -> &[LightProbe] {
+        &self.probes
+    }
+
+    /// Returns mutable reference to array of probes. Can be used to fill probes
+    /// with captured lighting.
+    pub fn probes_mut(&mut self) -> &mut [LightProbe] {
+        &mut self.probes
+    }
+
+    /// Returns probe at given grid coordinates.
+    pub fn probe(&self, x: usize, y: usize, z: usize) -> Option<&LightProbe> {
+        if x < self.size_x && y < self.size_y && z < self.size_z {
+            self.probes
+                .get(z * self.size_x * self.size_y + y * self.size_x + x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns trilinearly interpolated probe at given world position. Positions outside
+    /// of grid will be clamped to its bounds. Returns None if grid is empty.
+    pub fn interpolate(&self, position: Vec3) -> Option<LightProbe> {
+        if self.probes.is_empty() || self.size_x == 0 || self.size_y == 0 || self.size_z == 0 {
+            return None;
+        }
+
+        let local = (position - self.origin).scale(1.0 / self.spacing);
+
+        let axis = |v: f32, size: usize| {
+            let v = v.max(0.0).min((size - 1) as f32);
+            let i0 = v.floor() as usize;
+            let i1 = (i0 + 1).min(size - 1);
+            (i0, i1, v - i0 as f32)
+        };
+
+        let (x0, x1, tx) = axis(local.x, self.size_x);
+        let (y0, y1, ty) = axis(local.y, self.size_y);
+        let (z0, z1, tz) = axis(local.z, self.size_z);
+
+        let mut result = LightProbe::default();
+        for &(z, wz) in &[(z0, 1.0 - tz), (z1, tz)] {
+            for &(y, wy) in &[(y0, 1.0 - ty), (y1, ty)] {
+                for &(x, wx) in &[(x0, 1.0 - tx), (x1, tx)] {
+                    let weight = wx * wy * wz;
+                    if weight > 0.0 {
+                        result.add_weighted(self.probe(x, y, z)?, weight);
+                    }
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Returns interpolated light at given world position coming to a surface with
+    /// specified normal.
+    pub fn sample(&self, position: Vec3, normal: Vec3) -> Vec3 {
+        self.interpolate(position)
+            .map(|probe| probe.evaluate(normal))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        utils::light_probe::{LightProbe, LightProbeGrid},
+    };
+
+    #[test]
+    fn light_probe_grid_interpolation() {
+        let dark = LightProbe::default();
+        let bright = LightProbe {
+            ambient_cube: [Vec3::new(1.0, 1.0, 1.0); 6],
+        };
+
+        let grid = LightProbeGrid {
+            origin: Vec3::ZERO,
+            spacing: 2.0,
+            size_x: 2,
+            size_y: 1,
+            size_z: 1,
+            probes: vec![dark, bright],
+        };
+
+        assert_eq!(grid.sample(Vec3::new(-5.0, 0.0, 0.0), Vec3::UP), Vec3::ZERO);
+        assert_eq!(
+            grid.sample(Vec3::new(5.0, 0.0, 0.0), Vec3::UP),
+            Vec3::new(1.0, 1.0, 1.0)
+        );
+        assert_eq!(
+            grid.sample(Vec3::new(1.0, 0.0, 0.0), Vec3::UP),
+            Vec3::new(0.5, 0.5, 0.5)
+        );
+
+        // Corrupt grid must not panic.
+        let corrupt = LightProbeGrid { size_x: 0, ..grid };
+        assert_eq!(corrupt.interpolate(Vec3::ZERO), None);
+    }
+}
@@ -14,9 +14,14 @@ use crate::{
         pool::Handle,
         visitor::{Visit, VisitResult, Visitor},
     },
+    engine::resource_manager::ResourceManager,
     renderer::{surface::SurfaceSharedData, surface::Vertex},
     resource::texture::{Texture, TextureKind},
-    scene::{light::Light, node::Node, Scene},
+    scene::{
+        light::{Light, LightFalloff},
+        node::Node,
+        Scene,
+    },
 };
 use image::ImageError;
 use std::{
@@ -71,55 +76,29 @@ impl Visit for Lightmap {
 }
 
 impl Lightmap {
+    /// Replaces shallow textures of lightmap (loaded from a save file) with real ones.
+    pub(in crate) fn restore_resources(&mut self, resource_manager: &mut ResourceManager) {
+        for entry in self.map.values_mut().flatten() {
+            if let Some(shallow_texture) = entry.texture.clone() {
+                entry.texture = resource_manager.restore_texture(&shallow_texture);
+            }
+        }
+    }
+
     /// Generates lightmap for given scene.
     /// Each mesh *must* have generated UVs for lightmap, otherwise result will be incorrect!    
     pub fn new(scene: &Scene, texels_per_unit: u32) -> Self {
         // Extract info about lights first. We need it to be in separate array because
         // it won't be possible to store immutable references to light sources and at the
         // same time modify meshes.
-        let mut lights = Vec::new();
-        for (handle, node) in scene.graph.pair_iter() {
-            if let Node::Light(light) = node {
-                match light {
-                    Light::Directional(_) => lights.push((
-                        handle,
-                        LightDefinition::Directional(DirectionalLightDefinition {
-                            intensity: 1.0,
-                            direction: light.up_vector().normalized().unwrap_or(Vec3::UP),
-                            color: light.color(),
-                        }),
-                    )),
-                    Light::Spot(spot) => lights.push((
-                        handle,
-                        LightDefinition::Spot(SpotLightDefinition {
-                            intensity: 1.0,
-                            hotspot_cone_angle: spot.hotspot_cone_angle(),
-                            falloff_angle_delta: spot.falloff_angle_delta(),
-                            color: light.color(),
-                            direction: light.up_vector().normalized().unwrap_or(Vec3::UP),
-                            position: light.global_position(),
-                            distance: spot.distance(),
-                        }),
-                    )),
-                    Light::Point(point) => lights.push((
-                        handle,
-                        LightDefinition::Point(PointLightDefinition {
-                            intensity: 1.0,
-                            position: light.global_position(),
-                            color: light.color(),
-                            radius: point.radius(),
-                        }),
-                    )),
-                }
-            }
-        }
+        let lights = extract_lights(scene);
         let mut map = HashMap::new();
         for (handle, node) in scene.graph.pair_iter() {
             if let Node::Mesh(mesh) = node {
                 if !mesh.global_visibility() {
                     continue;
                 }
-                let global_transform = mesh.global_transform;
+                let global_transform = mesh.instance_transform();
                 let mut surface_lightmaps = Vec::new();
                 for surface in mesh.surfaces() {
                     let data = surface.data();
@@ -160,6 +139,49 @@ impl Lightmap {
     }
 }
 
+/// Collects definitions of every light source in the scene.
+pub(in crate) fn extract_lights(scene: &Scene) -> Vec<(Handle<Node>, LightDefinition)> {
+    let mut lights = Vec::new();
+    for (handle, node) in scene.graph.pair_iter() {
+        if let Node::Light(light) = node {
+            match light {
+                Light::Directional(_) => lights.push((
+                    handle,
+                    LightDefinition::Directional(DirectionalLightDefinition {
+                        intensity: light.base_shading_intensity(),
+                        direction: light.up_vector().normalized().unwrap_or(Vec3::UP),
+                        color: light.color(),
+                    }),
+                )),
+                Light::Spot(spot) => lights.push((
+                    handle,
+                    LightDefinition::Spot(SpotLightDefinition {
+                        intensity: light.base_shading_intensity(),
+                        hotspot_cone_angle: spot.hotspot_cone_angle(),
+                        falloff_angle_delta: spot.falloff_angle_delta(),
+                        color: light.color(),
+                        direction: light.up_vector().normalized().unwrap_or(Vec3::UP),
+                        position: light.global_position(),
+                        distance: spot.distance(),
+                        falloff: light.falloff(),
+                    }),
+                )),
+                Light::Point(point) => lights.push((
+                    handle,
+                    LightDefinition::Point(PointLightDefinition {
+                        intensity: light.base_shading_intensity(),
+                        position: light.global_position(),
+                        color: light.color(),
+                        radius: point.radius(),
+                        falloff: light.falloff(),
+                    }),
+                )),
+            }
+        }
+    }
+    lights
+}
+
 /// Directional light is a light source with parallel rays. Example: Sun.
 pub struct DirectionalLightDefinition {
     /// Intensity is how bright light is. Default is 1.0.
@@ -187,6 +209,8 @@ pub struct SpotLightDefinition {
     pub position: Vec3,
     /// Distance at which light intensity decays to zero.
     pub distance: f32,
+    /// How intensity of light changes over distance.
+    pub falloff: LightFalloff,
 }
 
 /// Point light is a spherical light source. Example: light bulb.
@@ -199,6 +223,8 @@ pub struct PointLightDefinition {
     pub color: Color,
     /// Radius of sphere at which light intensity decays to zero.
     pub radius: f32,
+    /// How intensity of light changes over distance.
+    pub falloff: LightFalloff,
 }
 
 /// Light definition for lightmap rendering.
@@ -226,11 +252,21 @@ fn estimate_size(vertices: &[Vec3], triangles: &[TriangleDefinition], texels_per
 
 /// Calculates distance attenuation for a point using given distance to the point and
 /// radius of a light.
-fn distance_attenuation(distance: f32, radius: f32) -> f32 {
-    let attenuation = (1.0 - distance * distance / (radius * radius))
-        .max(0.0)
-        .min(1.0);
-    attenuation * attenuation
+fn distance_attenuation(distance: f32, radius: f32, falloff: LightFalloff) -> f32 {
+    match falloff {
+        LightFalloff::Range => {
+            let attenuation = (1.0 - distance * distance / (radius * radius))
+                .max(0.0)
+                .min(1.0);
+            attenuation * attenuation
+        }
+        LightFalloff::InverseSquare => {
+            // Must match S_InverseSquareAttenuation from shared.glsl.
+            let ratio = distance / radius;
+            let window = (1.0 - ratio * ratio * ratio * ratio).max(0.0).min(1.0);
+            window * window / (distance * distance).max(0.0001)
+        }
+    }
 }
 
 /// Transforms vertices of surface data into set of world space positions.
@@ -368,6 +404,45 @@ fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     k * k * (3.0 - 2.0 * k)
 }
 
+/// Calculates color and attenuation of given light at a point with specified normal.
+pub(in crate) fn evaluate_light(
+    light: &LightDefinition,
+    position: Vec3,
+    normal: Vec3,
+) -> (Color, f32) {
+    match light {
+        LightDefinition::Directional(directional) => {
+            let attenuation = directional.intensity * lambertian(directional.direction, normal);
+            (directional.color, attenuation)
+        }
+        LightDefinition::Spot(spot) => {
+            let d = spot.position - position;
+            let distance = d.len();
+            let light_vec = d.scale(1.0 / distance);
+            let spot_angle_cos = light_vec.dot(&spot.direction);
+            let cone_factor = smoothstep(
+                ((spot.hotspot_cone_angle + spot.falloff_angle_delta) * 0.5).cos(),
+                (spot.hotspot_cone_angle * 0.5).cos(),
+                spot_angle_cos,
+            );
+            let attenuation = cone_factor
+                * spot.intensity
+                * lambertian(light_vec, normal)
+                * distance_attenuation(distance, spot.distance, spot.falloff);
+            (spot.color, attenuation)
+        }
+        LightDefinition::Point(point) => {
+            let d = point.position - position;
+            let distance = d.len();
+            let light_vec = d.scale(1.0 / distance);
+            let attenuation = point.intensity
+                * lambertian(light_vec, normal)
+                * distance_attenuation(distance, point.radius, point.falloff);
+            (point.color, attenuation)
+        }
+    }
+}
+
 /// Generates lightmap for given surface data with specified transform.
 ///
 /// # Performance
@@ -438,38 +513,7 @@ fn generate_lightmap<'a, I: IntoIterator<Item = &'a LightDefinition>>(
         } = pixel
         {
             for light in &lights {
-                let (light_color, attenuation) = match light {
-                    LightDefinition::Directional(directional) => {
-                        let attenuation =
-                            directional.intensity * lambertian(directional.direction, *normal);
-                        (directional.color, attenuation)
-                    }
-                    LightDefinition::Spot(spot) => {
-                        let d = spot.position - *position;
-                        let distance = d.len();
-                        let light_vec = d.scale(1.0 / distance);
-                        let spot_angle_cos = light_vec.dot(&spot.direction);
-                        let cone_factor = smoothstep(
-                            ((spot.hotspot_cone_angle + spot.falloff_angle_delta) * 0.5).cos(),
-                            (spot.hotspot_cone_angle * 0.5).cos(),
-                            spot_angle_cos,
-                        );
-                        let attenuation = cone_factor
-                            * spot.intensity
-                            * lambertian(light_vec, *normal)
-                            * distance_attenuation(distance, spot.distance);
-                        (spot.color, attenuation)
-                    }
-                    LightDefinition::Point(point) => {
-                        let d = point.position - *position;
-                        let distance = d.len();
-                        let light_vec = d.scale(1.0 / distance);
-                        let attenuation = point.intensity
-                            * lambertian(light_vec, *normal)
-                            * distance_attenuation(distance, point.radius);
-                        (point.color, attenuation)
-                    }
-                };
+                let (light_color, attenuation) = evaluate_light(light, *position, *normal);
                 color.r =
                     (color.r as f32 + ((light_color.r as f32) * attenuation)).min(255.0) as u8;
                 color.g =
@@ -501,8 +545,11 @@ mod test {
     use crate::{
         core::{color::Color, math::vec3::Vec3},
         renderer::surface::SurfaceSharedData,
+        scene::light::LightFalloff,
         utils::{
-            lightmap::{generate_lightmap, LightDefinition, PointLightDefinition},
+            lightmap::{
+                distance_attenuation, generate_lightmap, LightDefinition, PointLightDefinition,
+            },
             uvgen::generate_uvs,
         },
     };
@@ -519,10 +566,24 @@ mod test {
             position: Vec3::new(0.0, 2.0, 0.0),
             color: Color::WHITE,
             radius: 4.0,
+            falloff: LightFalloff::Range,
         })];
         let lightmap = generate_lightmap(&data, &Default::default(), &lights, 128);
 
         let image = RgbaImage::from_raw(lightmap.width, lightmap.height, lightmap.bytes).unwrap();
         image.save("lightmap.png").unwrap();
     }
+
+    #[test]
+    fn test_distance_attenuation_falloff() {
+        for &falloff in &[LightFalloff::Range, LightFalloff::InverseSquare] {
+            assert_eq!(distance_attenuation(4.0, 4.0, falloff), 0.0);
+        }
+        assert_eq!(distance_attenuation(0.0, 4.0, LightFalloff::Range), 1.0);
+        // Inverse square falloff is not clamped near light source.
+        let near = distance_attenuation(0.5, 4.0, LightFalloff::InverseSquare);
+        let far = distance_attenuation(1.0, 4.0, LightFalloff::InverseSquare);
+        assert!(near > 1.0);
+        assert!((near / far - 4.0).abs() < 0.1);
+    }
 }
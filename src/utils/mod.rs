@@ -3,11 +3,18 @@
 //! Utilities module provides set of commonly used algorithms.
 
 pub mod astar;
+pub mod crowd;
+pub mod dialogue;
+pub mod gizmo;
+pub mod handle_debug;
+pub mod light_probe;
 pub mod lightmap;
 pub mod log;
 pub mod navmesh;
 pub mod raw_mesh;
+pub mod tiled_navmesh;
 pub mod uvgen;
+pub mod visit;
 
 use crate::gui::draw;
 use crate::resource::texture::Texture;
@@ -31,7 +38,7 @@ use std::{any::Any, sync::Arc};
 /// mesh.
 pub fn mesh_to_static_geometry(mesh: &Mesh) -> StaticGeometry {
     let mut triangles = Vec::new();
-    let global_transform = mesh.global_transform();
+    let global_transform = mesh.instance_transform();
     for surface in mesh.surfaces() {
         let shared_data = surface.data();
         let shared_data = shared_data.lock().unwrap();
@@ -311,6 +318,18 @@ pub fn translate_event(event: &WindowEvent) -> Option<OsEvent> {
     }
 }
 
+/// Translates window event to rg3d-ui event, converting cursor position from physical
+/// pixels to logical UI units. `ui_scale` is amount of physical pixels in a single UI
+/// unit, usually it is taken from `Engine::ui_scale`.
+pub fn translate_event_scaled(event: &WindowEvent, ui_scale: f32) -> Option<OsEvent> {
+    match translate_event(event) {
+        Some(OsEvent::CursorMoved { position }) => Some(OsEvent::CursorMoved {
+            position: position.scale(1.0 / ui_scale),
+        }),
+        other => other,
+    }
+}
+
 /// Translates keyboard modifiers to rg3d-ui keyboard modifiers.
 pub fn translate_keyboard_modifiers(modifiers: ModifiersState) -> KeyboardModifiers {
     KeyboardModifiers {
@@ -0,0 +1,557 @@
+//! Tiled navigation mesh that is re-baked in background when its geometry changes.
+//!
+//! Dynamic levels change walkable area at runtime - doors are opened and closed, walls are
+//! destroyed, bridges collapse. Baking navmesh for whole level again on every such change
+//! is too slow to do in a frame, so [`TiledNavmesh`] splits level into square tiles on XZ
+//! plane and tracks which mesh nodes (sources) contribute to navmesh. When a source is
+//! moved, hidden, removed or explicitly invalidated, only tiles it overlaps are gathered
+//! again. Rebuild is performed on a separate thread, current navmesh stays usable until
+//! new one is ready and swapped in during [`TiledNavmesh::update`], so AI keeps moving
+//! without frame hitches.
+//!
+//! Every tile keeps its own welded geometry, so re-bake welds only triangles of dirty
+//! tiles. Tiles are then joined into one navmesh and only vertices near tile borders are
+//! welded across tiles, so paths can go from one tile to another.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::pool::Handle,
+//!     scene::{node::Node, Scene},
+//!     utils::tiled_navmesh::TiledNavmesh,
+//! };
+//!
+//! fn create_navmesh(scene: &Scene, floor: Handle<Node>, door: Handle<Node>) -> TiledNavmesh {
+//!     let mut navmesh = TiledNavmesh::new(16.0);
+//!     navmesh.add_source(floor);
+//!     // Door will be removed from navmesh when it is hidden (opened) and added back
+//!     // when it is shown again.
+//!     navmesh.add_source(door);
+//!     // Initial bake can be blocking, usually it is done while level is loading.
+//!     navmesh.update(&scene.graph);
+//!     navmesh.wait(&scene.graph);
+//!     navmesh
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::{mat4::Mat4, vec3::Vec3, TriangleDefinition},
+        pool::Handle,
+    },
+    scene::{graph::Graph, node::Node},
+    utils::{navmesh::Navmesh, raw_mesh::RawMeshBuilder},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+};
+
+/// Integer coordinates of a tile on XZ plane.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TileCoord {
+    /// Index of tile along X axis.
+    pub x: i32,
+    /// Index of tile along Z axis.
+    pub z: i32,
+}
+
+type Triangles = Arc<Vec<[Vec3; 3]>>;
+
+struct Source {
+    transform: Mat4,
+    visible: bool,
+    triangles: Triangles,
+    min: Vec3,
+    max: Vec3,
+}
+
+/// Geometry of a single tile, vertices are welded only within the tile.
+struct TileMesh {
+    vertices: Vec<Vec3>,
+    triangles: Vec<TriangleDefinition>,
+    /// Max length of edges of triangles which cross borders of the tile. Vertices which
+    /// are closer to borders than this value can be shared with other tiles.
+    border_margin: f32,
+}
+
+impl TileMesh {
+    fn new(coord: TileCoord, tile_size: f32, triangles: &[[Vec3; 3]]) -> Self {
+        let mut builder = RawMeshBuilder::<Vec3>::default();
+        let mut border_margin = 0.0f32;
+        for triangle in triangles {
+            if triangle
+                .iter()
+                .any(|v| TiledNavmesh::tile_coord_of(tile_size, *v) != coord)
+            {
+                for &(a, b) in &[(0, 1), (1, 2), (2, 0)] {
+                    border_margin = border_margin.max(triangle[a].distance(&triangle[b]));
+                }
+            }
+            for vertex in triangle.iter() {
+                builder.insert(*vertex);
+            }
+        }
+        let mesh = builder.build();
+        Self {
+            vertices: mesh.vertices,
+            triangles: mesh.triangles,
+            border_margin,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+}
+
+struct BakeResult {
+    tiles: Vec<(TileCoord, Arc<TileMesh>)>,
+    navmesh: Navmesh,
+}
+
+/// See module docs.
+pub struct TiledNavmesh {
+    tile_size: f32,
+    tagged: HashSet<Handle<Node>>,
+    sources: HashMap<Handle<Node>, Source>,
+    tiles: HashMap<TileCoord, Arc<TileMesh>>,
+    dirty: HashSet<TileCoord>,
+    forced: HashSet<Handle<Node>>,
+    navmesh: Navmesh,
+    version: u32,
+    pending: Option<Receiver<BakeResult>>,
+}
+
+fn bounds(triangles: &[[Vec3; 3]]) -> (Vec3, Vec3) {
+    let mut min = Vec3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+    let mut max = Vec3::new(-std::f32::MAX, -std::f32::MAX, -std::f32::MAX);
+    for vertex in triangles.iter().flat_map(|t| t.iter()) {
+        min = Vec3::new(
+            min.x.min(vertex.x),
+            min.y.min(vertex.y),
+            min.z.min(vertex.z),
+        );
+        max = Vec3::new(
+            max.x.max(vertex.x),
+            max.y.max(vertex.y),
+            max.z.max(vertex.z),
+        );
+    }
+    (min, max)
+}
+
+fn mark_dirty(dirty: &mut HashSet<TileCoord>, tile_size: f32, min: Vec3, max: Vec3) {
+    let from = TiledNavmesh::tile_coord_of(tile_size, min);
+    let to = TiledNavmesh::tile_coord_of(tile_size, max);
+    for z in from.z..=to.z {
+        for x in from.x..=to.x {
+            dirty.insert(TileCoord { x, z });
+        }
+    }
+}
+
+fn gather_triangles(graph: &Graph, handle: Handle<Node>) -> Vec<[Vec3; 3]> {
+    let mut triangles = Vec::new();
+    if let Node::Mesh(mesh) = &graph[handle] {
+        let global_transform = mesh.global_transform();
+        for surface in mesh.surfaces() {
+            let shared_data = surface.data();
+            let shared_data = shared_data.lock().unwrap();
+            let vertices = shared_data.get_vertices();
+            for triangle in shared_data.triangles() {
+                let position = |i: usize| {
+                    global_transform.transform_vector(vertices[triangle[i] as usize].position)
+                };
+                triangles.push([position(0), position(1), position(2)]);
+            }
+        }
+    }
+    triangles
+}
+
+/// Joins tiles into one navmesh. Vertices of different tiles are welded only if they are
+/// near tile borders, every other vertex belongs to its tile only.
+fn join(tile_size: f32, tiles: &[(TileCoord, Arc<TileMesh>)]) -> Navmesh {
+    let margin = tiles
+        .iter()
+        .fold(0.0f32, |margin, (_, tile)| margin.max(tile.border_margin));
+
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    let mut border_vertices = HashMap::new();
+    let mut remap = Vec::new();
+    for (coord, tile) in tiles {
+        let min_x = coord.x as f32 * tile_size;
+        let min_z = coord.z as f32 * tile_size;
+        remap.clear();
+        for vertex in tile.vertices.iter() {
+            let distance = (vertex.x - min_x)
+                .min(min_x + tile_size - vertex.x)
+                .min(vertex.z - min_z)
+                .min(min_z + tile_size - vertex.z);
+            let index = if distance <= margin {
+                let key = [vertex.x.to_bits(), vertex.y.to_bits(), vertex.z.to_bits()];
+                *border_vertices.entry(key).or_insert_with(|| {
+                    vertices.push(*vertex);
+                    vertices.len() as u32 - 1
+                })
+            } else {
+                vertices.push(*vertex);
+                vertices.len() as u32 - 1
+            };
+            remap.push(index);
+        }
+        for triangle in tile.triangles.iter() {
+            triangles.push(TriangleDefinition([
+                remap[triangle[0] as usize],
+                remap[triangle[1] as usize],
+                remap[triangle[2] as usize],
+            ]));
+        }
+    }
+
+    Navmesh::new(&triangles, &vertices)
+}
+
+fn bake(
+    tile_size: f32,
+    dirty: Vec<TileCoord>,
+    sources: Vec<Triangles>,
+    clean: Vec<(TileCoord, Arc<TileMesh>)>,
+) -> BakeResult {
+    // Gather triangles of dirty tiles, every triangle belongs to the tile that contains
+    // its centroid, so there are no duplicates on tile borders.
+    let dirty_set = dirty.iter().cloned().collect::<HashSet<_>>();
+    let mut tiles = dirty
+        .iter()
+        .map(|coord| (*coord, Vec::new()))
+        .collect::<HashMap<_, _>>();
+    for triangle in sources.iter().flat_map(|s| s.iter()) {
+        let centroid = (triangle[0] + triangle[1] + triangle[2]).scale(1.0 / 3.0);
+        let coord = TiledNavmesh::tile_coord_of(tile_size, centroid);
+        if dirty_set.contains(&coord) {
+            tiles.get_mut(&coord).unwrap().push(*triangle);
+        }
+    }
+    let tiles = tiles
+        .into_iter()
+        .map(|(coord, triangles)| (coord, Arc::new(TileMesh::new(coord, tile_size, &triangles))))
+        .collect::<Vec<_>>();
+
+    // Join tiles into one mesh, so paths can go across tile borders.
+    let mut all = clean;
+    all.extend(tiles.iter().filter(|(_, tile)| !tile.is_empty()).cloned());
+    let navmesh = join(tile_size, &all);
+
+    BakeResult { tiles, navmesh }
+}
+
+impl TiledNavmesh {
+    /// Creates new empty tiled navmesh with given size of tile.
+    pub fn new(tile_size: f32) -> Self {
+        Self {
+            tile_size: tile_size.max(std::f32::EPSILON),
+            tagged: Default::default(),
+            sources: Default::default(),
+            tiles: Default::default(),
+            dirty: Default::default(),
+            forced: Default::default(),
+            navmesh: Default::default(),
+            version: 0,
+            pending: None,
+        }
+    }
+
+    fn tile_coord_of(tile_size: f32, point: Vec3) -> TileCoord {
+        TileCoord {
+            x: (point.x / tile_size).floor() as i32,
+            z: (point.z / tile_size).floor() as i32,
+        }
+    }
+
+    /// Returns coordinates of tile which contains given point.
+    pub fn tile_coord(&self, point: Vec3) -> TileCoord {
+        Self::tile_coord_of(self.tile_size, point)
+    }
+
+    /// Returns size of tile.
+    pub fn tile_size(&self) -> f32 {
+        self.tile_size
+    }
+
+    /// Tags given mesh node as navmesh source. Tiles it overlaps will be baked on next
+    /// update.
+    pub fn add_source(&mut self, handle: Handle<Node>) {
+        self.tagged.insert(handle);
+    }
+
+    /// Removes given node from set of sources. Tiles it overlapped will be re-baked on
+    /// next update.
+    pub fn remove_source(&mut self, handle: Handle<Node>) {
+        self.tagged.remove(&handle);
+    }
+
+    /// Returns true if given node is a source of the navmesh.
+    pub fn is_source(&self, handle: Handle<Node>) -> bool {
+        self.tagged.contains(&handle)
+    }
+
+    /// Forces re-bake of tiles of given source on next update. Moving and hiding sources
+    /// is detected automatically, but changes of geometry itself (for example when surface
+    /// data of a mesh was replaced) must be reported using this method.
+    pub fn invalidate_source(&mut self, handle: Handle<Node>) {
+        self.forced.insert(handle);
+    }
+
+    /// Marks every tile that overlaps given bounds on XZ plane as dirty.
+    pub fn invalidate_bounds(&mut self, min: Vec3, max: Vec3) {
+        mark_dirty(&mut self.dirty, self.tile_size, min, max);
+    }
+
+    /// Marks given tile as dirty.
+    pub fn invalidate_tile(&mut self, coord: TileCoord) {
+        self.dirty.insert(coord);
+    }
+
+    /// Marks every tile as dirty, so whole navmesh will be re-baked.
+    pub fn invalidate_all(&mut self) {
+        self.forced.extend(self.tagged.iter().cloned());
+        self.dirty.extend(self.tiles.keys().cloned());
+    }
+
+    /// Returns true if there are dirty tiles which are not yet baked.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Returns true if navmesh is being baked in background.
+    pub fn is_baking(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns version of navmesh, it is increased every time new navmesh is swapped in.
+    /// Can be used to detect that paths built on previous version should be rebuilt.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns reference to current navmesh.
+    pub fn navmesh(&self) -> &Navmesh {
+        &self.navmesh
+    }
+
+    /// Returns reference to current navmesh.
+    pub fn navmesh_mut(&mut self) -> &mut Navmesh {
+        &mut self.navmesh
+    }
+
+    fn sync_sources(&mut self, graph: &Graph) {
+        // Sources which were untagged or whose nodes were destroyed.
+        let removed = self
+            .sources
+            .keys()
+            .filter(|h| !self.tagged.contains(h) || !graph.is_valid_handle(**h))
+            .cloned()
+            .collect::<Vec<_>>();
+        for handle in removed {
+            let source = self.sources.remove(&handle).unwrap();
+            self.invalidate_bounds(source.min, source.max);
+        }
+
+        for &handle in self.tagged.iter() {
+            if !graph.is_valid_handle(handle) {
+                continue;
+            }
+            let node = &graph[handle];
+            let transform = node.global_transform();
+            let visible = node.global_visibility();
+            let forced = self.forced.contains(&handle);
+
+            if let Some(source) = self.sources.get(&handle) {
+                if !forced && source.visible == visible && source.transform.f == transform.f {
+                    continue;
+                }
+                mark_dirty(&mut self.dirty, self.tile_size, source.min, source.max);
+            }
+
+            let triangles = if visible {
+                gather_triangles(graph, handle)
+            } else {
+                Vec::new()
+            };
+            let (min, max) = bounds(&triangles);
+            if !triangles.is_empty() {
+                mark_dirty(&mut self.dirty, self.tile_size, min, max);
+            }
+            self.sources.insert(
+                handle,
+                Source {
+                    transform,
+                    visible,
+                    triangles: Arc::new(triangles),
+                    min,
+                    max,
+                },
+            );
+        }
+        self.forced.clear();
+    }
+
+    fn start_bake(&mut self) {
+        let dirty = self.dirty.drain().collect::<Vec<_>>();
+        let clean = self
+            .tiles
+            .iter()
+            .filter(|(coord, _)| !dirty.contains(coord))
+            .map(|(coord, tile)| (*coord, tile.clone()))
+            .collect::<Vec<_>>();
+        // Only sources that overlap dirty tiles are needed.
+        let tile_size = self.tile_size;
+        let sources = self
+            .sources
+            .values()
+            .filter(|s| {
+                !s.triangles.is_empty() && {
+                    let from = Self::tile_coord_of(tile_size, s.min);
+                    let to = Self::tile_coord_of(tile_size, s.max);
+                    dirty
+                        .iter()
+                        .any(|c| c.x >= from.x && c.x <= to.x && c.z >= from.z && c.z <= to.z)
+                }
+            })
+            .map(|s| s.triangles.clone())
+            .collect::<Vec<_>>();
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // Receiver may be dropped if navmesh was destroyed during bake.
+            let _ = sender.send(bake(tile_size, dirty, sources, clean));
+        });
+        self.pending = Some(receiver);
+    }
+
+    fn apply(&mut self, result: BakeResult) {
+        for (coord, tile) in result.tiles {
+            if tile.is_empty() {
+                self.tiles.remove(&coord);
+            } else {
+                self.tiles.insert(coord, tile);
+            }
+        }
+        self.navmesh = result.navmesh;
+        self.version = self.version.wrapping_add(1);
+    }
+
+    fn poll(&mut self, block: bool) {
+        if let Some(receiver) = self.pending.as_ref() {
+            let result = if block {
+                receiver.recv().ok()
+            } else {
+                match receiver.try_recv() {
+                    Ok(result) => Some(result),
+                    Err(TryRecvError::Empty) => return,
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            self.pending = None;
+            if let Some(result) = result {
+                self.apply(result);
+            }
+        }
+    }
+
+    /// Detects changes of sources, swaps in navmesh if background bake is finished and
+    /// starts new bake if there are dirty tiles. Should be called once per frame after
+    /// scene update. Only one bake is running at a time, changes made while bake is in
+    /// progress are collected and baked right after.
+    pub fn update(&mut self, graph: &Graph) {
+        self.sync_sources(graph);
+        self.poll(false);
+        if self.pending.is_none() && !self.dirty.is_empty() {
+            self.start_bake();
+        }
+    }
+
+    /// Blocks current thread until every pending change is baked and swapped in. Useful
+    /// for initial bake during level loading.
+    pub fn wait(&mut self, graph: &Graph) {
+        self.sync_sources(graph);
+        loop {
+            self.poll(true);
+            if self.dirty.is_empty() {
+                break;
+            }
+            self.start_bake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        utils::tiled_navmesh::{bake, TileCoord, TiledNavmesh},
+    };
+    use std::sync::Arc;
+
+    fn quad(x: f32, z: f32) -> Vec<[Vec3; 3]> {
+        let a = Vec3::new(x, 0.0, z);
+        let b = Vec3::new(x + 1.0, 0.0, z);
+        let c = Vec3::new(x + 1.0, 0.0, z + 1.0);
+        let d = Vec3::new(x, 0.0, z + 1.0);
+        vec![[a, b, c], [a, c, d]]
+    }
+
+    #[test]
+    fn test_tile_coord() {
+        let navmesh = TiledNavmesh::new(4.0);
+        assert_eq!(
+            navmesh.tile_coord(Vec3::new(1.0, 10.0, 5.0)),
+            TileCoord { x: 0, z: 1 }
+        );
+        assert_eq!(
+            navmesh.tile_coord(Vec3::new(-0.5, 0.0, -4.5)),
+            TileCoord { x: -1, z: -2 }
+        );
+    }
+
+    #[test]
+    fn test_partial_bake_keeps_clean_tiles() {
+        let mut floor = quad(0.0, 0.0);
+        floor.extend(quad(1.0, 0.0));
+        let sources = vec![Arc::new(floor)];
+
+        // Bake both tiles.
+        let dirty = vec![TileCoord { x: 0, z: 0 }, TileCoord { x: 1, z: 0 }];
+        let result = bake(1.0, dirty, sources.clone(), Vec::new());
+        assert_eq!(result.tiles.len(), 2);
+        assert_eq!(result.navmesh.triangles().len(), 4);
+        // Shared edge is welded.
+        assert_eq!(result.navmesh.vertices().len(), 6);
+
+        // Re-bake only second tile, first one is passed as clean.
+        let first = result
+            .tiles
+            .iter()
+            .find(|(c, _)| *c == TileCoord { x: 0, z: 0 })
+            .cloned()
+            .unwrap();
+        let result = bake(1.0, vec![TileCoord { x: 1, z: 0 }], Vec::new(), vec![first]);
+        assert_eq!(result.tiles.len(), 1);
+        assert!(result.tiles[0].1.is_empty());
+        assert_eq!(result.navmesh.triangles().len(), 2);
+    }
+
+    #[test]
+    fn test_invalidate_bounds() {
+        let mut navmesh = TiledNavmesh::new(2.0);
+        navmesh.invalidate_bounds(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 1.0));
+        assert!(navmesh.is_dirty());
+        assert_eq!(navmesh.dirty.len(), 3);
+    }
+}
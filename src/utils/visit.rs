@@ -0,0 +1,142 @@
+//! Contains helpers to serialize user types which are not covered by `Visit`
+//! implementations of core.
+//!
+//! Core already implements `Visit` for primitives, `String`, `Vec<T>`, `Option<T>`,
+//! `HashMap<K, V>`, `Arc<Mutex<T>>` and math types, so most of game
+//! state can be saved by simply visiting its fields. This module covers the rest:
+//!
+//! - enums with data - use `impl_visit_for_enum!` macro instead of writing id-based
+//! impl by hand.
+//! - fixed-size arrays - use `visit_array` function.
+
+use crate::core::visitor::{Visit, VisitResult, Visitor};
+
+/// Implements `Visit` for an enum, including enums with data. Every field of every
+/// variant must be named (tuple variants too, names are used only for serialization)
+/// and must implement `Visit` and `Default`. Variants are identified by their order,
+/// so new variants must be added at the end to keep old saves loadable.
+///
+/// # Example
+///
+/// ```
+/// use rg3d::impl_visit_for_enum;
+///
+/// #[derive(Debug)]
+/// enum Weapon {
+///     Unarmed,
+///     Gun(u32, f32),
+///     Sword { damage: f32 },
+/// }
+///
+/// impl Default for Weapon {
+///     fn default() -> Self {
+///         Weapon::Unarmed
+///     }
+/// }
+///
+/// impl_visit_for_enum!(Weapon {
+///     Unarmed,
+///     Gun(ammo: u32, spread: f32),
+///     Sword { damage: f32 },
+/// });
+/// ```
+#[macro_export]
+macro_rules! impl_visit_for_enum {
+    ($enum:ident {
+        $($variant:ident
+            $(( $($tuple_field:ident : $tuple_type:ty),* $(,)? ))?
+            $({ $($struct_field:ident : $struct_type:ty),* $(,)? })?
+        ),* $(,)?
+    }) => {
+        impl $crate::core::visitor::Visit for $enum {
+            #[allow(unused_assignments)]
+            fn visit(
+                &mut self,
+                name: &str,
+                visitor: &mut $crate::core::visitor::Visitor,
+            ) -> $crate::core::visitor::VisitResult {
+                visitor.enter_region(name)?;
+
+                let mut id = 0u32;
+                let mut index = 0u32;
+                $(
+                    if let $enum::$variant { .. } = self {
+                        id = index;
+                    }
+                    index += 1;
+                )*
+                $crate::core::visitor::Visit::visit(&mut id, "Id", visitor)?;
+
+                if visitor.is_reading() {
+                    let mut index = 0u32;
+                    let mut found = false;
+                    $(
+                        if index == id {
+                            *self = $enum::$variant
+                                $(( $(<$tuple_type as Default>::default()),* ))?
+                                $({ $($struct_field: <$struct_type as Default>::default()),* })?;
+                            found = true;
+                        }
+                        index += 1;
+                    )*
+                    if !found {
+                        return Err(format!(
+                            "Invalid {} id {}",
+                            stringify!($enum),
+                            id
+                        ).into());
+                    }
+                }
+
+                match self {
+                    $(
+                        $enum::$variant
+                            $(( $($tuple_field),* ))?
+                            $({ $($struct_field),* })? => {
+                            $($(
+                                $crate::core::visitor::Visit::visit(
+                                    $tuple_field,
+                                    stringify!($tuple_field),
+                                    visitor,
+                                )?;
+                            )*)?
+                            $($(
+                                $crate::core::visitor::Visit::visit(
+                                    $struct_field,
+                                    stringify!($struct_field),
+                                    visitor,
+                                )?;
+                            )*)?
+                        }
+                    )*
+                }
+
+                visitor.leave_region()
+            }
+        }
+    };
+}
+
+/// Visits every element of a fixed-size array (or any other slice). Length of array is
+/// saved too, and loading fails if length of saved array does not match length of
+/// given one.
+pub fn visit_array<T: Visit>(array: &mut [T], name: &str, visitor: &mut Visitor) -> VisitResult {
+    visitor.enter_region(name)?;
+
+    let mut length = array.len() as u32;
+    length.visit("Length", visitor)?;
+    if length as usize != array.len() {
+        return Err(format!(
+            "Array length mismatch: {} saved, {} expected",
+            length,
+            array.len()
+        )
+        .into());
+    }
+
+    for (i, item) in array.iter_mut().enumerate() {
+        item.visit(&format!("Item{}", i), visitor)?;
+    }
+
+    visitor.leave_region()
+}